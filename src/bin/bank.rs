@@ -77,6 +77,14 @@ async fn main() -> Result<()> {
                 .required(true)
                 .default_value("1000"),
         )
+        .arg(
+            clap::Arg::with_name("password")
+                .short("P")
+                .long("password")
+                .help("Superuser password, required to create the bank tables")
+                .takes_value(true)
+                .required(true),
+        )
         .get_matches();
 
     Bank::new(
@@ -84,6 +92,7 @@ async fn main() -> Result<()> {
         opts.value_of("concurrency").unwrap().parse()?,
         opts.value_of("customers").unwrap().parse()?,
         opts.value_of("accounts").unwrap().parse()?,
+        opts.value_of("password").unwrap(),
     )
     .await?
     .run(opts.value_of("transactions").unwrap().parse()?)
@@ -105,12 +114,11 @@ impl Bank {
         concurrency: u64,
         customers: i64,
         accounts: i64,
+        password: &str,
     ) -> Result<Self> {
-        Ok(Self {
-            clients: Pool::new(addrs, concurrency).await?,
-            customers,
-            customer_accounts: accounts,
-        })
+        let clients = Pool::new(addrs, concurrency).await?;
+        clients.authenticate(password).await?;
+        Ok(Self { clients, customers, customer_accounts: accounts })
     }
 
     // Runs the bank simulation, making transfers between customer accounts.
@@ -231,7 +239,7 @@ impl Bank {
         let start = std::time::Instant::now();
 
         let (from_account, to_account, amount) = client
-            .with_txn(|txn| {
+            .with_retry(|txn| {
                 let attempts = attempts.clone();
                 async move {
                     attempts.set(attempts.get() + 1);
@@ -10,6 +10,8 @@ use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_v
 use serde_derive::Deserialize;
 use std::collections::HashMap;
 use toydb::error::{Error, Result};
+use toydb::raft;
+use toydb::sql::engine::{MAX_RECURSIVE_ITERATIONS, MAX_VALUE_SIZE};
 use toydb::storage;
 use toydb::Server;
 
@@ -33,10 +35,28 @@ async fn main() -> Result<()> {
         logconfig.add_filter_allow_str("toydb");
     }
     simplelog::SimpleLogger::init(loglevel, logconfig.build())?;
+    raft::set_log_format(match cfg.log_format.as_str() {
+        "text" | "" => raft::LogFormat::Text,
+        "json" => raft::LogFormat::Json,
+        format => return Err(Error::Config(format!("Unknown log format {}", format))),
+    });
+
+    let durability = match cfg.sync.as_str() {
+        "sync" | "true" | "" => storage::log::Durability::Sync,
+        "periodic" => storage::log::Durability::Periodic {
+            interval: std::time::Duration::from_millis(cfg.sync_interval),
+            writes: cfg.sync_writes,
+        },
+        "none" | "false" => storage::log::Durability::None,
+        mode => return Err(Error::Config(format!("Unknown sync mode {}", mode))),
+    };
+    if cfg.sync_interval == 0 {
+        return Err(Error::Config("Sync interval must be positive".into()));
+    }
 
     let path = std::path::Path::new(&cfg.data_dir);
     let raft_store: Box<dyn storage::log::Store> = match cfg.storage_raft.as_str() {
-        "hybrid" | "" => Box::new(storage::log::Hybrid::new(&path, cfg.sync)?),
+        "hybrid" | "" => Box::new(storage::log::Hybrid::new(&path, durability)?),
         "memory" => Box::new(storage::log::Memory::new()),
         name => return Err(Error::Config(format!("Unknown Raft storage engine {}", name))),
     };
@@ -46,12 +66,68 @@ async fn main() -> Result<()> {
         name => return Err(Error::Config(format!("Unknown SQL storage engine {}", name))),
     };
 
-    Server::new(&cfg.id, cfg.peers, raft_store, sql_store)
-        .await?
-        .listen(&cfg.listen_sql, &cfg.listen_raft)
-        .await?
-        .serve()
-        .await
+    let statement_timeout = match cfg.statement_timeout {
+        t if t < 0.0 || !t.is_finite() => {
+            return Err(Error::Config(format!("Invalid statement timeout {}", t)))
+        }
+        t if t > 0.0 => Some(std::time::Duration::from_secs_f64(t)),
+        _ => None,
+    };
+
+    Server::new(
+        &cfg.id,
+        cfg.peers,
+        raft_store,
+        sql_store,
+        cfg.storage_sql_cache,
+        cfg.max_value_size,
+        match cfg.max_stored_value_size {
+            0 => None,
+            max => Some(max),
+        },
+    )
+    .await?
+    .with_statement_timeout(statement_timeout)
+    .with_max_aggregate_values(match cfg.max_aggregate_values {
+        0 => None,
+        max => Some(max),
+    })
+    .with_max_recursive_iterations(match cfg.max_recursive_iterations {
+        0 => None,
+        max => Some(max),
+    })
+    .with_max_sort_memory(match cfg.max_sort_memory {
+        0 => None,
+        max => Some(max),
+    })
+    .with_max_statement_memory(match cfg.max_statement_memory {
+        0 => None,
+        max => Some(max),
+    })
+    .with_max_inflight_entries(match cfg.max_inflight_entries {
+        0 => None,
+        max => Some(max),
+    })
+    .with_max_entry_size(match cfg.max_entry_size {
+        0 => None,
+        max => Some(max),
+    })
+    .with_max_apply_batch(match cfg.max_apply_batch {
+        0 => None,
+        max => Some(max),
+    })
+    .with_vacuum_interval(match cfg.vacuum_interval {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs)),
+    })
+    .with_superuser_password(match cfg.superuser_password.as_str() {
+        "" => None,
+        password => Some(password.to_string()),
+    })
+    .listen(&cfg.listen_sql, &cfg.listen_raft)
+    .await?
+    .serve()
+    .await
 }
 
 #[derive(Debug, Deserialize)]
@@ -61,10 +137,26 @@ struct Config {
     listen_sql: String,
     listen_raft: String,
     log_level: String,
+    log_format: String,
     data_dir: String,
-    sync: bool,
+    sync: String,
+    sync_interval: u64,
+    sync_writes: u64,
     storage_raft: String,
     storage_sql: String,
+    storage_sql_cache: usize,
+    max_value_size: usize,
+    max_stored_value_size: usize,
+    max_entry_size: usize,
+    statement_timeout: f64,
+    max_aggregate_values: usize,
+    max_recursive_iterations: u64,
+    max_sort_memory: usize,
+    max_statement_memory: usize,
+    max_inflight_entries: u64,
+    max_apply_batch: usize,
+    vacuum_interval: u64,
+    superuser_password: String,
 }
 
 impl Config {
@@ -74,10 +166,26 @@ impl Config {
         c.set_default("listen_sql", "0.0.0.0:9605")?;
         c.set_default("listen_raft", "0.0.0.0:9705")?;
         c.set_default("log_level", "info")?;
+        c.set_default("log_format", "text")?;
         c.set_default("data_dir", "/var/lib/toydb")?;
-        c.set_default("sync", true)?;
+        c.set_default("sync", "sync")?;
+        c.set_default("sync_interval", 100)?;
+        c.set_default("sync_writes", 0)?;
         c.set_default("storage_raft", "hybrid")?;
         c.set_default("storage_sql", "memory")?;
+        c.set_default("storage_sql_cache", 0)?;
+        c.set_default("max_value_size", MAX_VALUE_SIZE as i64)?;
+        c.set_default("max_stored_value_size", 0)?;
+        c.set_default("max_entry_size", 8 * 1024 * 1024)?;
+        c.set_default("statement_timeout", 0.0)?;
+        c.set_default("max_aggregate_values", 1_000_000)?;
+        c.set_default("max_recursive_iterations", MAX_RECURSIVE_ITERATIONS as i64)?;
+        c.set_default("max_sort_memory", 64 * 1024 * 1024)?;
+        c.set_default("max_statement_memory", 256 * 1024 * 1024)?;
+        c.set_default("max_inflight_entries", 1000)?;
+        c.set_default("max_apply_batch", 100)?;
+        c.set_default("vacuum_interval", 0)?;
+        c.set_default("superuser_password", "")?;
 
         c.merge(config::File::with_name(file))?;
         c.merge(config::Environment::with_prefix("TOYDB"))?;
@@ -13,8 +13,11 @@ use toydb::error::{Error, Result};
 use toydb::sql::engine::Mode;
 use toydb::sql::execution::ResultSet;
 use toydb::sql::parser::{Lexer, Token};
+use toydb::sql::types::{Columns, Rows};
 use toydb::Client;
 
+use std::io::Write as _;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let opts = app_from_crate!()
@@ -38,6 +41,20 @@ async fn main() -> Result<()> {
                 .required(true)
                 .default_value("9605"),
         )
+        .arg(
+            clap::Arg::with_name("user")
+                .short("u")
+                .long("user")
+                .help("User to execute statements as, which requires a superuser password")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("password")
+                .short("P")
+                .long("password")
+                .help("Superuser password, to execute statements as the built-in superuser")
+                .takes_value(true),
+        )
         .get_matches();
 
     let mut toysql =
@@ -46,6 +63,12 @@ async fn main() -> Result<()> {
     if opts.is_present("headers") {
         toysql.show_headers = true
     }
+    if let Some(password) = opts.value_of("password") {
+        toysql.client.authenticate(password).await?
+    }
+    if let Some(user) = opts.value_of("user") {
+        toysql.client.set_user(Some(user)).await?
+    }
 
     if let Some(command) = opts.value_of("command") {
         toysql.execute(&command).await
@@ -57,6 +80,9 @@ async fn main() -> Result<()> {
 /// The ToySQL REPL
 struct ToySQL {
     client: Client,
+    host: String,
+    port: u16,
+    session_id: u64,
     editor: Editor<InputValidator>,
     history_path: Option<std::path::PathBuf>,
     show_headers: bool,
@@ -65,8 +91,12 @@ struct ToySQL {
 impl ToySQL {
     /// Creates a new ToySQL REPL for the given server host and port
     async fn new(host: &str, port: u16) -> Result<Self> {
+        let client = Client::new((host, port)).await?;
         Ok(Self {
-            client: Client::new((host, port)).await?,
+            session_id: client.session_id().await?,
+            client,
+            host: host.to_string(),
+            port,
             editor: Editor::new(),
             history_path: std::env::var_os("HOME")
                 .map(|home| std::path::Path::new(&home).join(".toysql.history")),
@@ -90,16 +120,36 @@ impl ToySQL {
         let mut input = input.split_ascii_whitespace();
         let command = input.next().ok_or_else(|| Error::Parse("Expected command.".to_string()))?;
 
+        let args: Vec<&str> = input.collect();
         let getargs = |n| {
-            let args: Vec<&str> = input.collect();
             if args.len() != n {
                 Err(Error::Parse(format!("{}: expected {} args, got {}", command, n, args.len())))
             } else {
-                Ok(args)
+                Ok(args.clone())
             }
         };
 
         match command {
+            "!export" => {
+                let prefix = match args.as_slice() {
+                    [_] => Vec::new(),
+                    [_, prefix] => decode_hex(prefix)?,
+                    _ => {
+                        return Err(Error::Parse(format!(
+                            "{}: expected 1 or 2 args, got {}",
+                            command,
+                            args.len()
+                        )))
+                    }
+                };
+                let pairs = self.client.export(&prefix).await?;
+                let mut file = std::io::BufWriter::new(std::fs::File::create(args[0])?);
+                for pair in &pairs {
+                    bincode::serialize_into(&mut file, pair)?;
+                }
+                file.flush()?;
+                println!("Exported {} keys", pairs.len());
+            }
             "!headers" => match getargs(1)?[0] {
                 "on" => {
                     self.show_headers = true;
@@ -114,15 +164,64 @@ impl ToySQL {
             "!help" => println!(
                 r#"
 Enter a SQL statement terminated by a semicolon (;) to execute it and display the result.
-The following commands are also available:
+Press Ctrl-C while a statement is running to cancel it. The following commands are also
+available:
 
-    !headers <on|off>  Enable or disable column headers
-    !help              This help message
-    !status            Display server status
-    !table [table]     Display table schema, if it exists
-    !tables            List tables
+    !export <file> [prefix]  Export raw key/value pairs to a file, optionally by hex key prefix
+    !headers <on|off>        Enable or disable column headers
+    !health                  Display cluster health as seen by the leader
+    !help                    This help message
+    !import <file>           Import raw key/value pairs from an exported file
+    !status                  Display server status
+    !table [table]           Display table schema, if it exists
+    !tables                  List tables
+    !view [view]             Display view definition, if it exists
+    !views                   List views
 "#
             ),
+            "!health" => {
+                let health = self.client.cluster_health().await?;
+                let mut peers = health
+                    .peer_contact_tick
+                    .iter()
+                    .map(|(id, tick)| match tick {
+                        Some(tick) => format!("{}:{}", id, tick),
+                        None => format!("{}:never", id),
+                    })
+                    .collect::<Vec<_>>();
+                peers.sort();
+                println!(
+                    r#"
+Leader:    {leader} in term {term} at tick {tick}
+Quorum:    {quorum}
+Contacts:  {peers}
+"#,
+                    leader = health.leader,
+                    term = health.term,
+                    tick = health.tick,
+                    quorum = if health.quorum { "reachable" } else { "lost" },
+                    peers = peers.join(" "),
+                )
+            }
+            "!import" => {
+                let args = getargs(1)?;
+                let mut file = std::io::BufReader::new(std::fs::File::open(args[0])?);
+                let mut pairs = Vec::new();
+                loop {
+                    match bincode::deserialize_from(&mut file) {
+                        Ok(pair) => pairs.push(pair),
+                        Err(err) => match *err {
+                            bincode::ErrorKind::Io(err)
+                                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                            {
+                                break
+                            }
+                            err => return Err(Box::new(err).into()),
+                        },
+                    }
+                }
+                println!("Imported {} keys", self.client.import(pairs).await?);
+            }
             "!status" => {
                 let status = self.client.status().await?;
                 let mut node_logs = status
@@ -163,48 +262,93 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
                     println!("{}", table)
                 }
             }
+            "!view" => {
+                let args = getargs(1)?;
+                println!("{}", self.client.get_view(args[0]).await?);
+            }
+            "!views" => {
+                getargs(0)?;
+                for view in self.client.list_views().await? {
+                    println!("{}", view)
+                }
+            }
             c => return Err(Error::Parse(format!("Unknown command {}", c))),
         }
         Ok(())
     }
 
-    /// Runs a query and displays the results
+    /// Runs a query and displays the results. Ctrl-C cancels the query.
     async fn execute_query(&mut self, query: &str) -> Result<()> {
-        match self.client.execute(query).await? {
+        let execute = self.client.execute(query);
+        tokio::pin!(execute);
+        let resultset = loop {
+            tokio::select! {
+                result = &mut execute => break result?,
+                // The client waits for the query, so the cancel is sent via a new connection.
+                _ = tokio::signal::ctrl_c() => {
+                    Client::new((self.host.as_str(), self.port))
+                        .await?
+                        .cancel(self.session_id)
+                        .await?
+                }
+            }
+        };
+        match resultset {
             ResultSet::Begin { id, mode } => match mode {
                 Mode::ReadWrite => println!("Began transaction {}", id),
                 Mode::ReadOnly => println!("Began read-only transaction {}", id),
+                Mode::Serializable => println!("Began serializable transaction {}", id),
                 Mode::Snapshot { version, .. } => println!(
                     "Began read-only transaction {} in snapshot at version {}",
                     id, version
                 ),
             },
             ResultSet::Commit { id } => println!("Committed transaction {}", id),
+            ResultSet::Set { variable, value } => println!("Set {} to {}", variable, value),
             ResultSet::Rollback { id } => println!("Rolled back transaction {}", id),
             ResultSet::Create { count } => println!("Created {} rows", count),
             ResultSet::Delete { count } => println!("Deleted {} rows", count),
             ResultSet::Update { count } => println!("Updated {} rows", count),
+            ResultSet::Copy { count } => println!("Copied {} rows", count),
             ResultSet::CreateTable { name } => println!("Created table {}", name),
             ResultSet::DropTable { name } => println!("Dropped table {}", name),
-            ResultSet::Explain(plan) => println!("{}", plan.to_string()),
-            ResultSet::Query { columns, mut rows } => {
-                if self.show_headers {
-                    println!(
-                        "{}",
-                        columns
-                            .iter()
-                            .map(|c| c.name.as_deref().unwrap_or("?"))
-                            .collect::<Vec<_>>()
-                            .join("|")
-                    );
-                }
-                while let Some(row) = rows.next().transpose()? {
-                    println!(
-                        "{}",
-                        row.into_iter().map(|v| format!("{}", v)).collect::<Vec<_>>().join("|")
-                    );
+            ResultSet::CreateView { name } => println!("Created view {}", name),
+            ResultSet::DropView { name } => println!("Dropped view {}", name),
+            ResultSet::CreateUser { name } => println!("Created user {}", name),
+            ResultSet::DropUser { name } => println!("Dropped user {}", name),
+            ResultSet::Grant { user } => println!("Granted privileges to user {}", user),
+            ResultSet::Revoke { user } => println!("Revoked privileges from user {}", user),
+            ResultSet::AlterTable { name } => println!("Altered table {}", name),
+            ResultSet::Analyze { tables } => {
+                for table in tables {
+                    println!("Analyzed table {}", table)
                 }
             }
+            ResultSet::Vacuum { versions, bytes } => {
+                println!("Vacuumed {} versions ({} bytes)", versions, bytes)
+            }
+            ResultSet::Returning { count: _, columns, rows } => {
+                self.print_rows(columns, Rows::new(rows.into_iter().map(Ok)))?
+            }
+            ResultSet::Query { columns, rows } => self.print_rows(columns, rows)?,
+        }
+        Ok(())
+    }
+
+    /// Displays result rows, with a header if enabled
+    fn print_rows(&self, columns: Columns, mut rows: Rows) -> Result<()> {
+        if self.show_headers {
+            println!(
+                "{}",
+                columns
+                    .iter()
+                    .map(|c| c.name.as_deref().unwrap_or("?"))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            );
+        }
+        while let Some(row) = rows.next().transpose()? {
+            println!("{}", row.into_iter().map(|v| format!("{}", v)).collect::<Vec<_>>().join("|"));
         }
         Ok(())
     }
@@ -214,6 +358,7 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
         let prompt = match self.client.txn() {
             Some((id, Mode::ReadWrite)) => format!("toydb:{}> ", id),
             Some((id, Mode::ReadOnly)) => format!("toydb:{}> ", id),
+            Some((id, Mode::Serializable)) => format!("toydb:{}> ", id),
             Some((_, Mode::Snapshot { version })) => format!("toydb@{}> ", version),
             None => "toydb> ".into(),
         };
@@ -261,6 +406,21 @@ SQL txns:  {txns_active} active, {txns} total ({sql_storage} storage)
     }
 }
 
+/// Decodes a hexadecimal string, e.g. a key prefix.
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    let invalid = || Error::Parse(format!("Invalid hex string {}", s));
+    s.as_bytes()
+        .chunks(2)
+        .map(|digits| match digits {
+            [_, _] => std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        })
+        .collect()
+}
+
 /// A Rustyline helper for multiline editing. It parses input lines and determines if they make up a
 /// complete command or not.
 #[derive(Completer, Helper, Highlighter, Hinter)]
@@ -1,8 +1,11 @@
 use crate::error::{Error, Result};
+use crate::raft::ClusterHealth;
 use crate::server::{Request, Response};
-use crate::sql::engine::{Mode, Status};
+use crate::sql::engine::{Change, Mode, Status};
 use crate::sql::execution::ResultSet;
-use crate::sql::schema::Table;
+use crate::sql::parser::{ast, Parser};
+use crate::sql::schema::{Table, View};
+use crate::sql::types::{Columns, Rows, Value};
 
 use futures::future::FutureExt as _;
 use futures::sink::SinkExt as _;
@@ -23,14 +26,15 @@ type Connection = tokio_serde::Framed<
     tokio_serde::formats::Bincode<Result<Response>, Request>,
 >;
 
-/// Number of serialization retries in with_txn()
-const WITH_TXN_RETRIES: u8 = 8;
+/// Default number of transaction attempts in with_retry(), including the first
+const RETRY_ATTEMPTS: u8 = 8;
 
 /// A toyDB client
 #[derive(Clone)]
 pub struct Client {
     conn: Arc<Mutex<Connection>>,
     txn: Cell<Option<(u64, Mode)>>,
+    retry_attempts: u8,
 }
 
 impl Client {
@@ -42,9 +46,17 @@ impl Client {
                 tokio_serde::formats::Bincode::default(),
             ))),
             txn: Cell::new(None),
+            retry_attempts: RETRY_ATTEMPTS,
         })
     }
 
+    /// Sets the maximum number of times with_retry() attempts a transaction, including the
+    /// first attempt.
+    pub fn with_retry_attempts(mut self, attempts: u8) -> Self {
+        self.retry_attempts = attempts;
+        self
+    }
+
     /// Call a server method
     async fn call(&self, request: Request) -> Result<Response> {
         let mut conn = self.conn.lock().await;
@@ -66,12 +78,56 @@ impl Client {
 
     /// Executes a query
     pub async fn execute(&self, query: &str) -> Result<ResultSet> {
+        let result = self.execute_request(Request::Execute(query.into())).await;
+        // A commit that fails with a serialization error rolls back the session's transaction.
+        if let Err(Error::Serialization) = result {
+            if let Ok(ast::Statement::Commit) = Parser::new(query).parse() {
+                self.txn.set(None);
+            }
+        }
+        result
+    }
+
+    /// Prepares a query with parameter placeholders ($1, $2, ... or ?), returning a statement ID
+    /// for use with execute_prepared(). Prepared statements are specific to the connection.
+    pub async fn prepare(&self, query: &str) -> Result<u64> {
+        match self.call(Request::Prepare(query.into())).await? {
+            Response::Prepare(id) => Ok(id),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Executes a prepared statement with the given parameter values
+    pub async fn execute_prepared(&self, id: u64, params: Vec<Value>) -> Result<ResultSet> {
+        self.execute_request(Request::ExecutePrepared(id, params)).await
+    }
+
+    /// Validates a query by parsing and planning it without executing it, returning the columns
+    /// of its result rows (if any).
+    pub async fn validate(&self, query: &str) -> Result<Columns> {
+        match self.call(Request::Validate(query.into())).await? {
+            Response::Validate(columns) => Ok(columns),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Executes a query request, buffering any result rows
+    async fn execute_request(&self, request: Request) -> Result<ResultSet> {
+        let result = self.execute_request_rows(request).await;
+        // Timed out and cancelled statements roll back the session's transaction.
+        if let Err(Error::Timeout) | Err(Error::Cancelled) = result {
+            self.txn.set(None);
+        }
+        result
+    }
+
+    /// Executes a query request like execute_request(), without handling errors.
+    async fn execute_request_rows(&self, request: Request) -> Result<ResultSet> {
         let mut conn = self.conn.lock().await;
-        let mut resultset =
-            match self.call_locked(&mut conn, Request::Execute(query.into())).await? {
-                Response::Execute(rs) => rs,
-                resp => return Err(Error::Internal(format!("Unexpected response {:?}", resp))),
-            };
+        let mut resultset = match self.call_locked(&mut conn, request).await? {
+            Response::Execute(rs) => rs,
+            resp => return Err(Error::Internal(format!("Unexpected response {:?}", resp))),
+        };
         if let ResultSet::Query { columns, .. } = resultset {
             // FIXME We buffer rows for now to avoid lifetime hassles
             let mut rows = Vec::new();
@@ -84,7 +140,7 @@ impl Client {
                     }
                 }
             }
-            resultset = ResultSet::Query { columns, rows: Box::new(rows.into_iter().map(Ok)) }
+            resultset = ResultSet::Query { columns, rows: Rows::new(rows.into_iter().map(Ok)) }
         };
         match &resultset {
             ResultSet::Begin { id, mode } => self.txn.set(Some((*id, *mode))),
@@ -111,6 +167,22 @@ impl Client {
         }
     }
 
+    /// Fetches the view definition as SQL
+    pub async fn get_view(&self, view: &str) -> Result<View> {
+        match self.call(Request::GetView(view.into())).await? {
+            Response::GetView(v) => Ok(v),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Lists database views
+    pub async fn list_views(&self) -> Result<Vec<String>> {
+        match self.call(Request::ListViews).await? {
+            Response::ListViews(v) => Ok(v),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
     /// Checks server status
     pub async fn status(&self) -> Result<Status> {
         match self.call(Request::Status).await? {
@@ -119,41 +191,169 @@ impl Client {
         }
     }
 
+    /// Checks whether the Raft leader currently reaches a quorum, and when it last heard from
+    /// each peer
+    pub async fn cluster_health(&self) -> Result<ClusterHealth> {
+        match self.call(Request::ClusterHealth).await? {
+            Response::ClusterHealth(h) => Ok(h),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Watches a table for committed row changes, returned by the watch in commit order. Only
+    /// changes made by transactions that begin after this call are returned. The connection is
+    /// dedicated to the watch, so the client (and its clones) can't make any other requests, and
+    /// the watch ends when they are dropped.
+    pub async fn watch(&self, table: &str) -> Result<Watch> {
+        match self.call(Request::Watch(table.into())).await? {
+            Response::Watch => Ok(Watch { conn: self.conn.clone() }),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Exports the raw key/value pairs of the SQL key/value store with the given key prefix (all
+    /// pairs if empty), in key order, as seen by a single read-only transaction.
+    pub async fn export(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut conn = self.conn.lock().await;
+        match self.call_locked(&mut conn, Request::Export(prefix.to_vec())).await? {
+            Response::Export => {}
+            resp => return Err(Error::Internal(format!("Unexpected response {:?}", resp))),
+        }
+        let mut pairs = Vec::new();
+        while let Some(result) = conn.try_next().await? {
+            match result? {
+                Response::KeyValue(Some(pair)) => pairs.push(pair),
+                Response::KeyValue(None) => break,
+                resp => return Err(Error::Internal(format!("Unexpected response {:?}", resp))),
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Imports raw key/value pairs (e.g. from export()) into the SQL key/value store in a single
+    /// transaction, overwriting existing keys. If a key is given several times, the last value
+    /// wins. The server splits large imports across several Raft log entries. Returns the number
+    /// of keys imported.
+    pub async fn import(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<u64> {
+        match self.call(Request::Import(pairs)).await? {
+            Response::Import(count) => Ok(count),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Returns the ID of the client's server session, which can be used to cancel its statements
+    /// from another client via cancel().
+    pub async fn session_id(&self) -> Result<u64> {
+        match self.call(Request::SessionId).await? {
+            Response::SessionId(id) => Ok(id),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Cancels the currently executing statement of the server session with the given ID, if
+    /// any, which errors with Error::Cancelled and has its transaction rolled back. Since a
+    /// client waits for its statements to complete, this must be called via a different client.
+    pub async fn cancel(&self, session_id: u64) -> Result<()> {
+        match self.call(Request::Cancel(session_id)).await? {
+            Response::Cancel => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Authenticates the client's server session as the built-in superuser, using the server's
+    /// configured superuser password. Sessions otherwise execute statements as the unprivileged
+    /// sql::engine::ANONYMOUS_USER.
+    pub async fn authenticate(&self, password: &str) -> Result<()> {
+        match self.call(Request::Authenticate(password.to_string())).await? {
+            Response::Authenticate => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
+    /// Sets the user of the client's server session, or the built-in superuser if None. The
+    /// session's statements are then only executed if the user has the required privileges.
+    /// Only superusers can change the user (see authenticate()), so once set to a regular user
+    /// the session can't regain superuser privileges without the superuser password.
+    pub async fn set_user(&self, user: Option<&str>) -> Result<()> {
+        match self.call(Request::SetUser(user.map(String::from))).await? {
+            Response::SetUser => Ok(()),
+            resp => Err(Error::Value(format!("Unexpected response: {:?}", resp))),
+        }
+    }
+
     /// Returns the transaction status of the client
     pub fn txn(&self) -> Option<(u64, Mode)> {
         self.txn.get()
     }
 
-    /// Runs a query in a transaction, automatically retrying serialization failures with
-    /// exponential backoff.
-    pub async fn with_txn<W, F, R>(&self, mut with: W) -> Result<R>
+    /// Runs a closure in a transaction, which is committed if the closure succeeds and rolled
+    /// back otherwise. Serialization failures (and aborts and timeouts, e.g. due to leader
+    /// changes) are retried with exponential backoff, up to the client's retry attempts (see
+    /// with_retry_attempts()), returning the last error if all attempts fail. Other errors are
+    /// returned immediately.
+    pub async fn with_retry<W, F, R>(&self, mut with: W) -> Result<R>
     where
         W: FnMut(Client) -> F,
         F: Future<Output = Result<R>>,
     {
-        for i in 0..WITH_TXN_RETRIES {
-            if i > 0 {
-                tokio::time::delay_for(std::time::Duration::from_millis(
-                    2_u64.pow(i as u32 - 1) * rand::thread_rng().gen_range(25, 75),
-                ))
+        retry(self.retry_attempts, || {
+            let txn = with(self.clone());
+            async move {
+                let result = async {
+                    self.execute("BEGIN").await?;
+                    let result = txn.await?;
+                    self.execute("COMMIT").await?;
+                    Ok(result)
+                }
                 .await;
-            }
-            let result = async {
-                self.execute("BEGIN").await?;
-                let result = with(self.clone()).await?;
-                self.execute("COMMIT").await?;
-                Ok(result)
-            }
-            .await;
-            if result.is_err() {
-                self.execute("ROLLBACK").await.ok();
-                if matches!(result, Err(Error::Serialization) | Err(Error::Abort)) {
-                    continue;
+                if result.is_err() {
+                    self.execute("ROLLBACK").await.ok();
                 }
+                result
             }
-            return result;
+        })
+        .await
+    }
+}
+
+/// Calls a closure until it succeeds or fails with an error other than a serialization failure,
+/// abort or timeout, up to the given number of attempts (at least one). Retries are delayed with
+/// exponential backoff and jitter. Returns the result of the last attempt.
+async fn retry<W, F, R>(attempts: u8, mut with: W) -> Result<R>
+where
+    W: FnMut() -> F,
+    F: Future<Output = Result<R>>,
+{
+    let mut attempt: u32 = 1;
+    loop {
+        match with().await {
+            Err(Error::Serialization) | Err(Error::Abort) | Err(Error::Timeout)
+                if attempt < attempts as u32 => {}
+            result => return result,
+        }
+        tokio::time::delay_for(std::time::Duration::from_millis(
+            2_u64.pow(attempt - 1) * rand::thread_rng().gen_range(25, 75),
+        ))
+        .await;
+        attempt += 1;
+    }
+}
+
+/// A watch of a table's committed row changes, see Client::watch().
+pub struct Watch {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Watch {
+    /// Waits for the next change.
+    pub async fn next(&mut self) -> Result<Change> {
+        match self.conn.lock().await.try_next().await? {
+            Some(result) => match result? {
+                Response::Change(change) => Ok(change),
+                resp => Err(Error::Internal(format!("Unexpected response {:?}", resp))),
+            },
+            None => Err(Error::Internal("Server disconnected".into())),
         }
-        Err(Error::Serialization)
     }
 }
 
@@ -176,6 +376,14 @@ impl Pool {
         Ok(Self { clients })
     }
 
+    /// Authenticates all clients in the pool as the built-in superuser, see Client::authenticate().
+    pub async fn authenticate(&self, password: &str) -> Result<()> {
+        for client in &self.clients {
+            client.lock().await.authenticate(password).await?;
+        }
+        Ok(())
+    }
+
     /// Fetches a client from the pool. It is reset (i.e. any open txns are rolled back) and
     /// returned when it goes out of scope.
     pub async fn get(&self) -> PoolClient<'_> {
@@ -224,3 +432,56 @@ impl<'a> Drop for PoolClient<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Retries the given sequence of results, returning the final result and number of attempts.
+    async fn retry_results(attempts: u8, results: Vec<Result<u64>>) -> (Result<u64>, usize) {
+        let mut results = results.into_iter();
+        let calls = Cell::new(0);
+        let result = retry(attempts, || {
+            calls.set(calls.get() + 1);
+            let result = results.next().expect("unexpected attempt");
+            async move { result }
+        })
+        .await;
+        (result, calls.get())
+    }
+
+    #[tokio::test]
+    async fn retry_serialization() {
+        assert_eq!(
+            retry_results(8, vec![Err(Error::Serialization), Err(Error::Serialization), Ok(7)])
+                .await,
+            (Ok(7), 3)
+        );
+        assert_eq!(retry_results(8, vec![Err(Error::Abort), Ok(7)]).await, (Ok(7), 2));
+        assert_eq!(retry_results(8, vec![Err(Error::Timeout), Ok(7)]).await, (Ok(7), 2));
+    }
+
+    #[tokio::test]
+    async fn retry_exhausted() {
+        assert_eq!(
+            retry_results(2, vec![Err(Error::Serialization), Err(Error::Abort)]).await,
+            (Err(Error::Abort), 2)
+        );
+        assert_eq!(
+            retry_results(0, vec![Err(Error::Serialization)]).await,
+            (Err(Error::Serialization), 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_non_retryable() {
+        assert_eq!(
+            retry_results(8, vec![Err(Error::Value("boom".into()))]).await,
+            (Err(Error::Value("boom".into())), 1)
+        );
+        assert_eq!(
+            retry_results(8, vec![Err(Error::Serialization), Err(Error::ReadOnly), Ok(7)]).await,
+            (Err(Error::ReadOnly), 2)
+        );
+    }
+}
@@ -8,11 +8,14 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Error {
     Abort,
+    Cancelled,
     Config(String),
     Internal(String),
     Parse(String),
     ReadOnly,
     Serialization,
+    Timeout,
+    Unauthorized(String),
     Value(String),
 }
 
@@ -21,12 +24,16 @@ impl std::error::Error for Error {}
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> fmt::Result {
         match self {
-            Error::Config(s) | Error::Internal(s) | Error::Parse(s) | Error::Value(s) => {
-                write!(f, "{}", s)
-            }
+            Error::Config(s)
+            | Error::Internal(s)
+            | Error::Parse(s)
+            | Error::Unauthorized(s)
+            | Error::Value(s) => write!(f, "{}", s),
             Error::Abort => write!(f, "Operation aborted"),
+            Error::Cancelled => write!(f, "Statement cancelled"),
             Error::Serialization => write!(f, "Serialization failure, retry transaction"),
             Error::ReadOnly => write!(f, "Read-only transaction"),
+            Error::Timeout => write!(f, "Statement timeout exceeded"),
         }
     }
 }
@@ -1,26 +1,41 @@
-use super::{Request, Response, Status};
+use super::{ClusterHealth, Request, Response, Status};
 use crate::error::{Error, Result};
 
+use std::time::Instant;
 use tokio::sync::{mpsc, oneshot};
 
+/// A client request sent to a local Raft server, with an optional deadline and a response sender.
+pub type ClientRequest = (Request, Option<Instant>, oneshot::Sender<Result<Response>>);
+
 /// A client for a local Raft server.
 #[derive(Clone)]
 pub struct Client {
-    request_tx: mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Response>>)>,
+    request_tx: mpsc::UnboundedSender<ClientRequest>,
+    deadline: Option<Instant>,
 }
 
 impl Client {
     /// Creates a new Raft client.
-    pub fn new(
-        request_tx: mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Response>>)>,
-    ) -> Self {
-        Self { request_tx }
+    pub fn new(request_tx: mpsc::UnboundedSender<ClientRequest>) -> Self {
+        Self { request_tx, deadline: None }
+    }
+
+    /// Returns the deadline for requests, if any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Sets a deadline for requests. The server responds with Error::Timeout to requests that
+    /// have not completed by the deadline, e.g. because the cluster has no leader, but a timed out
+    /// mutation may still be applied later.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline
     }
 
     /// Executes a request against the Raft cluster.
     async fn request(&self, request: Request) -> Result<Response> {
         let (response_tx, response_rx) = oneshot::channel();
-        self.request_tx.send((request, response_tx))?;
+        self.request_tx.send((request, self.deadline, response_tx))?;
         response_rx.await?
     }
 
@@ -47,4 +62,12 @@ impl Client {
             resp => Err(Error::Internal(format!("Unexpected Raft status response {:?}", resp))),
         }
     }
+
+    /// Fetches the cluster health as seen by the leader.
+    pub async fn cluster_health(&self) -> Result<ClusterHealth> {
+        match self.request(Request::ClusterHealth).await? {
+            Response::ClusterHealth(health) => Ok(health),
+            resp => Err(Error::Internal(format!("Unexpected Raft health response {:?}", resp))),
+        }
+    }
 }
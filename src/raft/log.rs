@@ -169,9 +169,13 @@ impl Log {
         Ok((term, voted_for))
     }
 
-    /// Saves information about the most recent term.
+    /// Saves information about the most recent term. This is fsynced before returning regardless
+    /// of the store's durability, since a node must never forget a term or vote that it may have
+    /// acted on: losing a vote could allow voting twice in the same term. Callers must therefore
+    /// save the term before sending any message in it, in particular GrantVote and SolicitVote.
     pub fn save_term(&mut self, term: u64, voted_for: Option<&str>) -> Result<()> {
-        self.store.set_metadata(&Key::TermVote.encode(), Self::serialize(&(term, voted_for))?)
+        self.store.set_metadata(&Key::TermVote.encode(), Self::serialize(&(term, voted_for))?)?;
+        self.store.sync()
     }
 
     /// Serializes a value for the log store.
@@ -1,7 +1,8 @@
-use super::{Entry, Status};
+use super::{ClusterHealth, Entry, Status};
 use crate::error::Result;
 
 use serde_derive::{Deserialize, Serialize};
+use std::fmt::{self, Display};
 
 /// A message address.
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -57,6 +58,11 @@ pub enum Event {
     },
     /// Followers may grant votes to candidates.
     GrantVote,
+    /// Nodes may reject votes, such that candidates can abandon hopeless elections early.
+    RejectVote {
+        /// The reason for rejecting the vote.
+        reason: VoteRejection,
+    },
     /// Leaders replicate a set of log entries to followers.
     ReplicateEntries {
         /// The index of the log entry immediately preceding the submitted commands.
@@ -89,12 +95,46 @@ pub enum Event {
     },
 }
 
+/// The reason for rejecting a vote.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VoteRejection {
+    /// The node has already voted for another candidate in this term.
+    AlreadyVoted {
+        /// The candidate that was voted for.
+        voted_for: String,
+    },
+    /// The candidate's log is not as up-to-date as the node's log.
+    LogNotUpToDate {
+        /// The index of the node's last stored log entry.
+        last_index: u64,
+        /// The term of the node's last stored log entry.
+        last_term: u64,
+    },
+    /// The candidate's term is older than the node's current term, which is given in the message.
+    StaleTerm,
+}
+
+impl Display for VoteRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyVoted { voted_for } => write!(f, "already voted for {}", voted_for),
+            Self::LogNotUpToDate { last_index, last_term } => write!(
+                f,
+                "log not up-to-date with last index {} and last term {}",
+                last_index, last_term
+            ),
+            Self::StaleTerm => write!(f, "stale term"),
+        }
+    }
+}
+
 /// A client request.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Request {
     Query(Vec<u8>),
     Mutate(Vec<u8>),
     Status,
+    ClusterHealth,
 }
 
 /// A client response.
@@ -102,4 +142,5 @@ pub enum Request {
 pub enum Response {
     State(Vec<u8>),
     Status(Status),
+    ClusterHealth(ClusterHealth),
 }
@@ -4,10 +4,12 @@ mod message;
 mod node;
 mod server;
 mod state;
+mod transport;
 
 pub use self::log::{Entry, Log, Scan};
-pub use client::Client;
-pub use message::{Address, Event, Message, Request, Response};
-pub use node::{Node, Status};
+pub use client::{Client, ClientRequest};
+pub use message::{Address, Event, Message, Request, Response, VoteRejection};
+pub use node::{set_log_format, ClusterHealth, LogFormat, Node, Status};
 pub use server::Server;
 pub use state::{Driver, Instruction, State};
+pub use transport::{Peers, TcpTransport, Transport};
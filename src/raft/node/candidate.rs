@@ -1,9 +1,9 @@
-use super::super::{Address, Event, Message, Response};
-use super::{Follower, Leader, Node, RoleNode, ELECTION_TIMEOUT_MAX, ELECTION_TIMEOUT_MIN};
+use super::super::{Address, Event, Message, Response, VoteRejection};
+use super::{election_timeout, DropReason, Follower, Leader, Node, RoleNode, Transition};
 use crate::error::Result;
 
-use ::log::{debug, info, warn};
-use rand::Rng as _;
+use ::log::{debug, info};
+use rand::rngs::StdRng;
 
 /// A candidate is campaigning to become a leader.
 #[derive(Debug)]
@@ -14,38 +14,74 @@ pub struct Candidate {
     election_timeout: u64,
     /// Votes received (including ourself).
     votes: u64,
+    /// Vote rejections received.
+    rejections: u64,
 }
 
 impl Candidate {
-    /// Creates a new candidate role.
-    pub fn new() -> Self {
+    /// Creates a new candidate role, with a randomized election timeout drawn from the given
+    /// random number generator.
+    pub fn new(rng: &mut StdRng) -> Self {
         Self {
             votes: 1, // We always start with a vote for ourselves.
+            rejections: 0,
             election_ticks: 0,
-            election_timeout: rand::thread_rng()
-                .gen_range(ELECTION_TIMEOUT_MIN, ELECTION_TIMEOUT_MAX),
+            election_timeout: election_timeout(rng),
         }
     }
+
+    /// Draws a new randomized election timeout.
+    pub(super) fn reset_timeout(&mut self, rng: &mut StdRng) {
+        self.election_timeout = election_timeout(rng);
+    }
 }
 
 impl RoleNode<Candidate> {
     /// Transition to follower role.
     fn become_follower(mut self, term: u64, leader: &str) -> Result<RoleNode<Follower>> {
         info!("Discovered leader {} for term {}, following", leader, term);
+        let new_term = term > self.term;
         self.term = term;
         self.log.save_term(term, None)?;
-        let mut node = self.become_role(Follower::new(Some(leader), None))?;
+        if new_term {
+            self.trace(Transition::Term);
+        }
+        let role = Follower::new(Some(leader), None, &mut self.rng);
+        let mut node = self.become_role(role)?;
+        node.trace(Transition::Role("follower"));
         node.abort_proxied()?;
         node.forward_queued(Address::Peer(leader.to_string()))?;
         Ok(node)
     }
 
+    /// Transition to follower role without a leader, when the election can't be won in this term
+    /// or a vote rejection reveals a later term. We keep our vote for ourself in this term, and
+    /// campaign again if the election timeout elapses without hearing from a leader.
+    fn abandon_election(mut self, term: u64) -> Result<RoleNode<Follower>> {
+        let voted_for = if term > self.term {
+            info!("Discovered new term {}, abandoning election", term);
+            self.term = term;
+            self.log.save_term(term, None)?;
+            self.trace(Transition::Term);
+            None
+        } else {
+            info!("Lost election for term {}, following", self.term);
+            self.log.save_term(self.term, Some(&self.id))?;
+            Some(self.id.clone())
+        };
+        let role = Follower::new(None, voted_for.as_deref(), &mut self.rng);
+        let node = self.become_role(role)?;
+        node.trace(Transition::Role("follower"));
+        Ok(node)
+    }
+
     /// Transition to leader role.
     fn become_leader(self) -> Result<RoleNode<Leader>> {
         info!("Won election for term {}, becoming leader", self.term);
         let peers = self.peers.clone();
         let last_index = self.log.last_index;
         let mut node = self.become_role(Leader::new(peers, last_index))?;
+        node.trace(Transition::Role("leader"));
         node.send(
             Address::Peers,
             Event::Heartbeat {
@@ -60,11 +96,18 @@ impl RoleNode<Candidate> {
 
     /// Processes a message.
     pub fn step(mut self, msg: Message) -> Result<Node> {
-        if let Err(err) = self.validate(&msg) {
-            warn!("Ignoring invalid message: {}", err);
+        if self.reject_stale_vote(&msg)? {
+            return Ok(self.into());
+        }
+        if let Err(reason) = self.validate(&msg) {
+            self.drop_message(&msg, reason);
             return Ok(self.into());
         }
         if msg.term > self.term {
+            // Vote rejections don't come from a leader.
+            if let Event::RejectVote { .. } = msg.event {
+                return Ok(self.abandon_election(msg.term)?.into());
+            }
             if let Address::Peer(from) = &msg.from {
                 return self.become_follower(msg.term, from)?.step(msg);
             }
@@ -90,6 +133,20 @@ impl RoleNode<Candidate> {
                 }
             }
 
+            Event::RejectVote { reason } => {
+                debug!(
+                    "Received term {} vote rejection from {:?}: {}",
+                    self.term, msg.from, reason
+                );
+                self.role.rejections += 1;
+                // Give up once the remaining nodes can't make up a quorum.
+                let nodes = self.peers.len() as u64 + 1;
+                if nodes - self.role.rejections < self.quorum() {
+                    let term = self.term;
+                    return Ok(self.abandon_election(term)?.into());
+                }
+            }
+
             Event::ClientRequest { .. } => self.queued_reqs.push((msg.from, msg.event)),
 
             Event::ClientResponse { id, mut response } => {
@@ -100,13 +157,19 @@ impl RoleNode<Candidate> {
                 self.send(Address::Client, Event::ClientResponse { id, response })?;
             }
 
-            // Ignore other candidates when we're also campaigning
-            Event::SolicitVote { .. } => {}
+            // Reject other candidates when we're also campaigning, since we voted for ourself.
+            Event::SolicitVote { .. } => {
+                if let Address::Peer(from) = msg.from {
+                    let reason = VoteRejection::AlreadyVoted { voted_for: self.id.clone() };
+                    self.send(Address::Peer(from), Event::RejectVote { reason })?;
+                }
+            }
 
             Event::ConfirmLeader { .. }
             | Event::ReplicateEntries { .. }
             | Event::AcceptEntries { .. }
-            | Event::RejectEntries { .. } => warn!("Received unexpected message {:?}", msg),
+            | Event::RejectEntries { .. } => self
+                .drop_message(&msg, DropReason::Malformed("unexpected event for candidate".into())),
         }
         Ok(self.into())
     }
@@ -119,7 +182,7 @@ impl RoleNode<Candidate> {
             info!("Election timed out, starting new election for term {}", self.term + 1);
             self.term += 1;
             self.log.save_term(self.term, None)?;
-            self.role = Candidate::new();
+            self.role = Candidate::new(&mut self.rng);
             self.send(
                 Address::Peers,
                 Event::SolicitVote {
@@ -138,6 +201,7 @@ mod tests {
     use super::super::tests::{assert_messages, assert_node};
     use super::*;
     use crate::storage::log;
+    use rand::SeedableRng as _;
     use std::collections::HashMap;
     use tokio::sync::mpsc;
 
@@ -156,6 +220,7 @@ mod tests {
         log.commit(2)?;
         log.save_term(3, None)?;
 
+        let mut rng = StdRng::seed_from_u64(0);
         let mut node = RoleNode {
             id: "a".into(),
             peers: vec!["b".into(), "c".into(), "d".into(), "e".into()],
@@ -165,7 +230,12 @@ mod tests {
             state_tx,
             queued_reqs: Vec::new(),
             proxied_reqs: HashMap::new(),
-            role: Candidate::new(),
+            max_inflight: None,
+            max_entry_size: None,
+            max_apply_batch: None,
+            dropped: HashMap::new(),
+            role: Candidate::new(&mut rng),
+            rng,
         };
         node = match node.step(Message {
             from: Address::Client,
@@ -347,6 +417,120 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // Enough vote rejections that a quorum is impossible make the candidate abandon the election
+    // before it times out, and return to a leaderless follower which keeps its queued requests.
+    fn step_rejectvote() -> Result<()> {
+        let (candidate, mut node_rx, mut state_rx) = setup()?;
+        let mut node = Node::Candidate(candidate);
+
+        // A grant and two rejections still leave room for a quorum of 3 out of 5 nodes.
+        for (from, event) in vec![
+            ("b", Event::GrantVote),
+            (
+                "c",
+                Event::RejectVote { reason: VoteRejection::AlreadyVoted { voted_for: "d".into() } },
+            ),
+            (
+                "d",
+                Event::RejectVote { reason: VoteRejection::AlreadyVoted { voted_for: "d".into() } },
+            ),
+        ] {
+            node = node.step(Message {
+                from: Address::Peer(from.into()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event,
+            })?;
+            assert_node(&node).is_candidate().term(3);
+        }
+
+        // The third rejection makes a quorum impossible.
+        node = node.step(Message {
+            from: Address::Peer("e".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::RejectVote {
+                reason: VoteRejection::LogNotUpToDate { last_index: 4, last_term: 3 },
+            },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(None).voted_for(Some("a"));
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+
+        // The queued request is forwarded once a leader is discovered.
+        node = node.step(Message {
+            from: Address::Peer("d".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("d"));
+        assert_messages(
+            &mut node_rx,
+            vec![
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("d".into()),
+                    term: 0,
+                    event: Event::ClientRequest {
+                        id: vec![0xaf],
+                        request: Request::Query(vec![0xf0]),
+                    },
+                },
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer("d".into()),
+                    term: 3,
+                    event: Event::ConfirmLeader { commit_index: 2, has_committed: true },
+                },
+            ],
+        );
+        Ok(())
+    }
+
+    #[test]
+    // A vote rejection from a later term abandons the election and adopts the term.
+    fn step_rejectvote_stale_term() -> Result<()> {
+        let (candidate, mut node_rx, mut state_rx) = setup()?;
+        let node = candidate.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 5,
+            event: Event::RejectVote { reason: VoteRejection::StaleTerm },
+        })?;
+        assert_node(&node).is_follower().term(5).leader(None).voted_for(None);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // Other candidates in the same term are rejected, since we voted for ourself.
+    fn step_solicitvote() -> Result<()> {
+        let (candidate, mut node_rx, mut state_rx) = setup()?;
+        let node = candidate.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peers,
+            term: 3,
+            event: Event::SolicitVote { last_index: 3, last_term: 2 },
+        })?;
+        assert_node(&node).is_candidate().term(3);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
+                term: 3,
+                event: Event::RejectVote {
+                    reason: VoteRejection::AlreadyVoted { voted_for: "a".into() },
+                },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
     #[test]
     fn tick() -> Result<()> {
         let (candidate, mut node_rx, mut state_rx) = setup()?;
@@ -1,9 +1,11 @@
-use super::super::{Address, Event, Instruction, Message, Response};
-use super::{Candidate, Node, RoleNode, ELECTION_TIMEOUT_MAX, ELECTION_TIMEOUT_MIN};
+use super::super::{Address, Event, Message, Response, VoteRejection};
+use super::{
+    election_timeout, Candidate, DropReason, Node, RoleNode, Transition, ELECTION_TIMEOUT_MIN,
+};
 use crate::error::Result;
 
-use ::log::{debug, info, warn};
-use rand::Rng as _;
+use ::log::{debug, info};
+use rand::rngs::StdRng;
 
 // A follower replicates state from a leader.
 #[derive(Debug)]
@@ -19,25 +21,41 @@ pub struct Follower {
 }
 
 impl Follower {
-    /// Creates a new follower role.
-    pub fn new(leader: Option<&str>, voted_for: Option<&str>) -> Self {
+    /// Creates a new follower role, with a randomized election timeout drawn from the given
+    /// random number generator.
+    pub fn new(leader: Option<&str>, voted_for: Option<&str>, rng: &mut StdRng) -> Self {
         Self {
             leader: leader.map(String::from),
             voted_for: voted_for.map(String::from),
             leader_seen_ticks: 0,
-            leader_seen_timeout: rand::thread_rng()
-                .gen_range(ELECTION_TIMEOUT_MIN, ELECTION_TIMEOUT_MAX),
+            leader_seen_timeout: election_timeout(rng),
         }
     }
+
+    /// Draws a new randomized election timeout.
+    pub(super) fn reset_timeout(&mut self, rng: &mut StdRng) {
+        self.leader_seen_timeout = election_timeout(rng);
+    }
+
+    /// Marks the leader as unseen for ELECTION_TIMEOUT_MIN ticks, such that votes for later terms
+    /// are granted, without triggering an election.
+    #[cfg(test)]
+    pub(super) fn expire_leader(&mut self) {
+        self.leader_seen_ticks = ELECTION_TIMEOUT_MIN;
+        self.leader_seen_timeout = self.leader_seen_timeout.max(ELECTION_TIMEOUT_MIN + 1);
+    }
 }
 
 impl RoleNode<Follower> {
     /// Transforms the node into a candidate.
-    fn become_candidate(self) -> Result<RoleNode<Candidate>> {
+    fn become_candidate(mut self) -> Result<RoleNode<Candidate>> {
         info!("Starting election for term {}", self.term + 1);
-        let mut node = self.become_role(Candidate::new())?;
+        let role = Candidate::new(&mut self.rng);
+        let mut node = self.become_role(role)?;
         node.term += 1;
         node.log.save_term(node.term, None)?;
+        node.trace(Transition::Term);
+        node.trace(Transition::Role("candidate"));
         node.send(
             Address::Peers,
             Event::SolicitVote { last_index: node.log.last_index, last_term: node.log.last_term },
@@ -52,11 +70,12 @@ impl RoleNode<Follower> {
             info!("Discovered new term {}, following leader {}", term, leader);
             self.term = term;
             self.log.save_term(term, None)?;
+            self.trace(Transition::Term);
         } else {
             info!("Discovered leader {}, following", leader);
             voted_for = self.role.voted_for;
         };
-        self.role = Follower::new(Some(leader), voted_for.as_deref());
+        self.role = Follower::new(Some(leader), voted_for.as_deref(), &mut self.rng);
         self.abort_proxied()?;
         self.forward_queued(Address::Peer(leader.to_string()))?;
         Ok(self)
@@ -72,10 +91,38 @@ impl RoleNode<Follower> {
 
     /// Processes a message.
     pub fn step(mut self, msg: Message) -> Result<Node> {
-        if let Err(err) = self.validate(&msg) {
-            warn!("Ignoring invalid message: {}", err);
+        if self.reject_stale_vote(&msg)? {
+            return Ok(self.into());
+        }
+        if let Err(reason) = self.validate(&msg) {
+            self.drop_message(&msg, reason);
+            return Ok(self.into());
+        }
+        // Votes are usually strays from a previous election that we lost or abandoned. They don't
+        // come from a leader, but may tell us about a new term.
+        if let Event::GrantVote | Event::RejectVote { .. } = msg.event {
+            if msg.term > self.term {
+                info!("Discovered new term {}", msg.term);
+                self.term = msg.term;
+                self.log.save_term(msg.term, None)?;
+                self.trace(Transition::Term);
+                self.role = Follower::new(None, None, &mut self.rng);
+            }
             return Ok(self.into());
         }
+        // Ignore vote requests for later terms while we may still be hearing from a leader, such
+        // that a leader holding a lease can rely on no other leader being elected until
+        // ELECTION_TIMEOUT_MIN ticks after we confirmed its last heartbeat. After startup, or
+        // after moving to a new term without a leader, we may have confirmed a heartbeat just
+        // before, so we also ignore vote requests for our current term until then.
+        if let Event::SolicitVote { .. } = msg.event {
+            if (msg.term > self.term || self.role.leader.is_none())
+                && self.role.leader_seen_ticks < ELECTION_TIMEOUT_MIN
+            {
+                debug!("Ignoring term {} vote request, leader may still be alive", msg.term);
+                return Ok(self.into());
+            }
+        }
         if let Address::Peer(from) = &msg.from {
             if msg.term > self.term || self.role.leader.is_none() {
                 return self.become_follower(from, msg.term)?.step(msg);
@@ -89,35 +136,56 @@ impl RoleNode<Follower> {
             Event::Heartbeat { commit_index, commit_term } => {
                 if self.is_leader(&msg.from) {
                     let has_committed = self.log.has(commit_index, commit_term)?;
-                    if has_committed && commit_index > self.log.commit_index {
+                    // If the leader's commit index is beyond our log tail, we can still commit up
+                    // to our last entry if it was replicated by the current leader, since the
+                    // log matching property then guarantees that our log matches the leader's.
+                    let commit_to = if has_committed {
+                        commit_index
+                    } else if commit_index > self.log.last_index && self.log.last_term == self.term
+                    {
+                        self.log.last_index
+                    } else {
+                        self.log.commit_index
+                    };
+                    // Apply newly committed entries right away, to reduce read-after-write
+                    // latency for reads served by followers.
+                    if commit_to > self.log.commit_index {
                         let old_commit_index = self.log.commit_index;
-                        self.log.commit(commit_index)?;
-                        let mut scan = self.log.scan((old_commit_index + 1)..=commit_index);
-                        while let Some(entry) = scan.next().transpose()? {
-                            self.state_tx.send(Instruction::Apply { entry })?;
-                        }
+                        self.log.commit(commit_to)?;
+                        self.trace(Transition::Commit(commit_to));
+                        self.apply_committed((old_commit_index + 1)..=commit_to)?;
                     }
                     self.send(msg.from, Event::ConfirmLeader { commit_index, has_committed })?;
                 }
             }
 
             Event::SolicitVote { last_index, last_term } => {
-                if let Some(voted_for) = &self.role.voted_for {
-                    if msg.from != Address::Peer(voted_for.clone()) {
+                if let Address::Peer(from) = msg.from {
+                    let reason = match &self.role.voted_for {
+                        Some(voted_for) if voted_for != &from => {
+                            Some(VoteRejection::AlreadyVoted { voted_for: voted_for.clone() })
+                        }
+                        _ if last_term < self.log.last_term
+                            || last_term == self.log.last_term
+                                && last_index < self.log.last_index =>
+                        {
+                            Some(VoteRejection::LogNotUpToDate {
+                                last_index: self.log.last_index,
+                                last_term: self.log.last_term,
+                            })
+                        }
+                        _ => None,
+                    };
+                    if let Some(reason) = reason {
+                        info!("Rejecting {} in term {} election: {}", from, self.term, reason);
+                        self.send(Address::Peer(from), Event::RejectVote { reason })?;
                         return Ok(self.into());
                     }
-                }
-                if last_term < self.log.last_term {
-                    return Ok(self.into());
-                }
-                if last_term == self.log.last_term && last_index < self.log.last_index {
-                    return Ok(self.into());
-                }
-                if let Address::Peer(from) = msg.from {
                     info!("Voting for {} in term {} election", from, self.term);
-                    self.send(Address::Peer(from.clone()), Event::GrantVote)?;
                     self.log.save_term(self.term, Some(&from))?;
-                    self.role.voted_for = Some(from);
+                    self.role.voted_for = Some(from.clone());
+                    self.trace(Transition::Vote(&from));
+                    self.send(Address::Peer(from), Event::GrantVote)?;
                 }
             }
 
@@ -150,12 +218,13 @@ impl RoleNode<Follower> {
                 self.send(Address::Client, Event::ClientResponse { id, response })?;
             }
 
-            // Ignore votes which are usually strays from the previous election that we lost.
-            Event::GrantVote => {}
+            // Votes are handled above.
+            Event::GrantVote | Event::RejectVote { .. } => {}
 
             Event::ConfirmLeader { .. }
             | Event::AcceptEntries { .. }
-            | Event::RejectEntries { .. } => warn!("Received unexpected message {:?}", msg),
+            | Event::RejectEntries { .. } => self
+                .drop_message(&msg, DropReason::Malformed("unexpected event for follower".into())),
         };
         Ok(self.into())
     }
@@ -173,12 +242,14 @@ impl RoleNode<Follower> {
 
 #[cfg(test)]
 pub mod tests {
-    use super::super::super::{Entry, Log, Request};
+    use super::super::super::{Entry, Instruction, Log, Request};
     use super::super::tests::{assert_messages, assert_node};
     use super::*;
     use crate::error::Error;
     use crate::storage::log;
+    use rand::SeedableRng as _;
     use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
     use tokio::sync::mpsc;
 
     pub fn follower_leader(node: &RoleNode<Follower>) -> Option<String> {
@@ -204,6 +275,7 @@ pub mod tests {
         log.commit(2)?;
         log.save_term(3, None)?;
 
+        let mut rng = StdRng::seed_from_u64(0);
         let node = RoleNode {
             id: "a".into(),
             peers: vec!["b".into(), "c".into(), "d".into(), "e".into()],
@@ -212,8 +284,13 @@ pub mod tests {
             node_tx,
             state_tx,
             proxied_reqs: HashMap::new(),
+            max_inflight: None,
+            max_entry_size: None,
+            max_apply_batch: None,
+            dropped: HashMap::new(),
             queued_reqs: Vec::new(),
-            role: Follower::new(Some("b"), None),
+            role: Follower::new(Some("b"), None, &mut rng),
+            rng,
         };
         Ok((node, node_rx, state_rx))
     }
@@ -241,7 +318,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entries: vec![Entry { index: 3, term: 2, command: Some(vec![0x03]) }],
             }],
         );
         Ok(())
@@ -295,6 +372,40 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    // Heartbeat from current leader with a commit_index beyond our last entry, where the last
+    // entry is from the leader's term, should commit and apply up to the last entry
+    fn step_heartbeat_beyond_last() -> Result<()> {
+        let (mut follower, mut node_rx, mut state_rx) = setup()?;
+        follower.log.append(3, Some(vec![0x04]))?;
+        let node = follower.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::Heartbeat { commit_index: 5, commit_term: 3 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(None).committed(4);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
+                term: 3,
+                event: Event::ConfirmLeader { commit_index: 5, has_committed: false },
+            }],
+        );
+        assert_messages(
+            &mut state_rx,
+            vec![Instruction::Apply {
+                entries: vec![
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                ],
+            }],
+        );
+        Ok(())
+    }
+
     #[test]
     // Heartbeat from fake leader
     fn step_heartbeat_fake_leader() -> Result<()> {
@@ -315,7 +426,7 @@ pub mod tests {
     // Heartbeat when no current leader makes us follow the leader
     fn step_heartbeat_no_leader() -> Result<()> {
         let (mut follower, mut node_rx, mut state_rx) = setup()?;
-        follower.role = Follower::new(None, None);
+        follower.role = Follower::new(None, None, &mut follower.rng);
         let node = follower.step(Message {
             from: Address::Peer("c".into()),
             to: Address::Peer("a".into()),
@@ -335,7 +446,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entries: vec![Entry { index: 3, term: 2, command: Some(vec![0x03]) }],
             }],
         );
         Ok(())
@@ -388,7 +499,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entries: vec![Entry { index: 3, term: 2, command: Some(vec![0x03]) }],
             }],
         );
         Ok(())
@@ -404,14 +515,42 @@ pub mod tests {
             term: 2,
             event: Event::Heartbeat { commit_index: 3, commit_term: 2 },
         })?;
-        assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(None).committed(2);
+        assert_node(&node)
+            .is_follower()
+            .term(3)
+            .leader(Some("b"))
+            .voted_for(None)
+            .committed(2)
+            .dropped(vec![("stale_term", 1)]);
         assert_messages(&mut node_rx, vec![]);
         assert_messages(&mut state_rx, vec![]);
         Ok(())
     }
 
     #[test]
-    // SolicitVote is granted for the first solicitor, otherwise ignored.
+    // Heartbeat addressed to another node is dropped, even from a future term
+    fn step_heartbeat_misrouted() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let node = follower.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("c".into()),
+            term: 4,
+            event: Event::Heartbeat { commit_index: 3, commit_term: 2 },
+        })?;
+        assert_node(&node)
+            .is_follower()
+            .term(3)
+            .leader(Some("b"))
+            .voted_for(None)
+            .committed(2)
+            .dropped(vec![("misrouted", 1)]);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // SolicitVote is granted for the first solicitor, otherwise rejected.
     fn step_solicitvote() -> Result<()> {
         let (follower, mut node_rx, mut state_rx) = setup()?;
 
@@ -453,7 +592,7 @@ pub mod tests {
         );
         assert_messages(&mut state_rx, vec![]);
 
-        // But a vote request from a different node is ignored.
+        // But a vote request from a different node is rejected.
         node = node.step(Message {
             from: Address::Peer("d".into()),
             to: Address::Peer("a".into()),
@@ -461,7 +600,104 @@ pub mod tests {
             event: Event::SolicitVote { last_index: 3, last_term: 2 },
         })?;
         assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(Some("c"));
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("d".into()),
+                term: 3,
+                event: Event::RejectVote {
+                    reason: VoteRejection::AlreadyVoted { voted_for: "c".into() },
+                },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // SolicitVote for a later term is ignored while the leader is alive, but granted once the
+    // minimum election timeout has passed.
+    fn step_solicitvote_leader_alive() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let solicit = Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::SolicitVote { last_index: 3, last_term: 2 },
+        };
+
+        let node = follower.step(solicit.clone())?;
+        assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(None);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![]);
+
+        let mut follower = match node {
+            Node::Follower(follower) => follower,
+            _ => panic!("Expected follower"),
+        };
+        follower.role.leader_seen_ticks = ELECTION_TIMEOUT_MIN;
+        let node = follower.step(solicit)?;
+        assert_node(&node).is_follower().term(4).leader(Some("c")).voted_for(Some("c"));
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 4,
+                event: Event::GrantVote,
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // After a restart, or after a vote reveals a later term, we may have confirmed a leader's
+    // heartbeat just before, so SolicitVote is ignored until the minimum election timeout has
+    // passed.
+    fn step_solicitvote_after_restart() -> Result<()> {
+        let (mut follower, mut node_rx, mut state_rx) = setup()?;
+        follower.role = Follower::new(None, None, &mut follower.rng);
+        let solicit = |term| Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term,
+            event: Event::SolicitVote { last_index: 3, last_term: 2 },
+        };
+
+        let node = follower.step(solicit(4))?;
+        assert_node(&node).is_follower().term(3).leader(None).voted_for(None);
         assert_messages(&mut node_rx, vec![]);
+
+        // A stray vote for a later term moves us to the term without a leader.
+        let node = node.step(Message {
+            from: Address::Peer("d".into()),
+            to: Address::Peer("a".into()),
+            term: 5,
+            event: Event::GrantVote,
+        })?;
+        assert_node(&node).is_follower().term(5).leader(None).voted_for(None);
+        let node = node.step(solicit(5))?;
+        assert_node(&node).is_follower().term(5).leader(None).voted_for(None);
+        assert_messages(&mut node_rx, vec![]);
+
+        let mut follower = match node {
+            Node::Follower(follower) => follower,
+            _ => panic!("Expected follower"),
+        };
+        follower.role.leader_seen_ticks = ELECTION_TIMEOUT_MIN;
+        let node = follower.step(solicit(5))?;
+        assert_node(&node).is_follower().term(5).leader(Some("c")).voted_for(Some("c"));
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 5,
+                event: Event::GrantVote,
+            }],
+        );
         assert_messages(&mut state_rx, vec![]);
         Ok(())
     }
@@ -483,7 +719,7 @@ pub mod tests {
     }
 
     #[test]
-    // SolicitVote is rejected if last_term is outdated.
+    // SolicitVote is rejected if last_index is outdated.
     fn step_solicitvote_last_index_outdated() -> Result<()> {
         let (follower, mut node_rx, mut state_rx) = setup()?;
         let node = follower.step(Message {
@@ -493,7 +729,17 @@ pub mod tests {
             event: Event::SolicitVote { last_index: 2, last_term: 2 },
         })?;
         assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(None);
-        assert_messages(&mut node_rx, vec![]);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 3,
+                event: Event::RejectVote {
+                    reason: VoteRejection::LogNotUpToDate { last_index: 3, last_term: 2 },
+                },
+            }],
+        );
         assert_messages(&mut state_rx, vec![]);
         Ok(())
     }
@@ -509,6 +755,64 @@ pub mod tests {
             event: Event::SolicitVote { last_index: 3, last_term: 1 },
         })?;
         assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(None);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 3,
+                event: Event::RejectVote {
+                    reason: VoteRejection::LogNotUpToDate { last_index: 3, last_term: 2 },
+                },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // SolicitVote from a past term is rejected, so the candidate learns of the current term.
+    fn step_solicitvote_stale_term() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 2,
+            event: Event::SolicitVote { last_index: 3, last_term: 2 },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b")).voted_for(None);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("c".into()),
+                term: 3,
+                event: Event::RejectVote { reason: VoteRejection::StaleTerm },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // RejectVote messages are ignored, but a later term is adopted without a leader.
+    fn step_rejectvote() -> Result<()> {
+        let (follower, mut node_rx, mut state_rx) = setup()?;
+        let mut node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::RejectVote { reason: VoteRejection::StaleTerm },
+        })?;
+        assert_node(&node).is_follower().term(3).leader(Some("b"));
+
+        node = node.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::RejectVote { reason: VoteRejection::StaleTerm },
+        })?;
+        assert_node(&node).is_follower().term(4).leader(None).voted_for(None);
         assert_messages(&mut node_rx, vec![]);
         assert_messages(&mut state_rx, vec![]);
         Ok(())
@@ -821,7 +1125,7 @@ pub mod tests {
     // ClientRequest is queued when there is no leader, and forwarded when a leader appears.
     fn step_clientrequest_queued() -> Result<()> {
         let (mut follower, mut node_rx, mut state_rx) = setup()?;
-        follower.role = Follower::new(None, None);
+        follower.role = Follower::new(None, None, &mut follower.rng);
         let mut node = Node::Follower(follower);
 
         node = node.step(Message {
@@ -873,7 +1177,7 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entries: vec![Entry { index: 3, term: 2, command: Some(vec![0x03]) }],
             }],
         );
         Ok(())
@@ -939,12 +1243,46 @@ pub mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                entries: vec![Entry { index: 3, term: 2, command: Some(vec![0x03]) }],
             }],
         );
         Ok(())
     }
 
+    /// Ticks a follower with the given seed until it has started the given number of elections,
+    /// returning the number of ticks before each.
+    fn election_ticks(seed: u64, elections: usize) -> Result<Vec<u64>> {
+        let (follower, _node_rx, _state_rx) = setup()?;
+        let mut node = Node::Follower(follower).with_seed(seed);
+        let (mut term, mut ticks, mut elections_ticks) = (3, 0, Vec::new());
+        while elections_ticks.len() < elections {
+            node = node.tick()?;
+            ticks += 1;
+            let node_term = match &node {
+                Node::Candidate(n) => n.term,
+                Node::Follower(n) => n.term,
+                Node::Leader(n) => n.term,
+            };
+            if node_term > term {
+                elections_ticks.push(ticks);
+                term = node_term;
+                ticks = 0;
+            }
+        }
+        Ok(elections_ticks)
+    }
+
+    #[test]
+    // Election timeouts are drawn from the node's random number generator, so a node with a fixed
+    // seed starts its elections after the same sequence of timeouts on every run.
+    fn tick_seeded() -> Result<()> {
+        let ticks = election_ticks(7, 10)?;
+        assert_eq!(ticks, vec![8, 11, 14, 9, 10, 11, 13, 14, 12, 11]);
+        assert_eq!(election_ticks(7, 10)?, ticks);
+        assert_ne!(election_ticks(8, 10)?, ticks);
+        Ok(())
+    }
+
     #[test]
     fn tick() -> Result<()> {
         let (follower, mut node_rx, mut state_rx) = setup()?;
@@ -991,4 +1329,159 @@ pub mod tests {
         assert_messages(&mut state_rx, vec![]);
         Ok(())
     }
+
+    /// A log store which records syncs and metadata writes, along with the node's outbound
+    /// messages. Messages are recorded by draining the node's outbound channel whenever the store
+    /// is written or synced, such that messages sent before a write are recorded before it.
+    #[derive(Clone)]
+    struct OrderingStore {
+        store: log::Test,
+        node_rx: Arc<Mutex<mpsc::UnboundedReceiver<Message>>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl OrderingStore {
+        fn record(&self, event: Option<&str>) {
+            let mut events = self.events.lock().unwrap();
+            while let Ok(msg) = self.node_rx.lock().unwrap().try_recv() {
+                events.push(format!("send {:?}", msg.event));
+            }
+            if let Some(event) = event {
+                events.push(event.to_string());
+            }
+        }
+
+        /// Returns the recorded events, including any messages sent since the last write.
+        fn events(&self) -> Vec<String> {
+            self.record(None);
+            self.events.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    impl std::fmt::Display for OrderingStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "ordering")
+        }
+    }
+
+    impl log::Store for OrderingStore {
+        fn append(&mut self, entry: Vec<u8>) -> Result<u64> {
+            self.store.append(entry)
+        }
+
+        fn commit(&mut self, index: u64) -> Result<()> {
+            self.store.commit(index)
+        }
+
+        fn committed(&self) -> u64 {
+            self.store.committed()
+        }
+
+        fn get(&self, index: u64) -> Result<Option<Vec<u8>>> {
+            self.store.get(index)
+        }
+
+        fn len(&self) -> u64 {
+            self.store.len()
+        }
+
+        fn scan(&self, range: log::Range) -> log::Scan {
+            self.store.scan(range)
+        }
+
+        fn size(&self) -> u64 {
+            self.store.size()
+        }
+
+        fn truncate(&mut self, index: u64) -> Result<u64> {
+            self.store.truncate(index)
+        }
+
+        fn get_metadata(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            self.store.get_metadata(key)
+        }
+
+        fn set_metadata(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+            let (term, voted_for): (u64, Option<String>) = bincode::deserialize(&value)?;
+            self.record(Some(&format!("set_metadata term={} voted_for={:?}", term, voted_for)));
+            self.store.set_metadata(key, value)
+        }
+
+        fn sync(&mut self) -> Result<()> {
+            self.record(Some("sync"));
+            self.store.sync()
+        }
+    }
+
+    #[test]
+    // The term and vote must be durably saved before a vote is granted or solicited.
+    fn save_term_before_vote() -> Result<()> {
+        let (node_tx, node_rx) = mpsc::unbounded_channel();
+        let (state_tx, _state_rx) = mpsc::unbounded_channel();
+        let store = OrderingStore {
+            store: log::Test::new(),
+            node_rx: Arc::new(Mutex::new(node_rx)),
+            events: Arc::new(Mutex::new(Vec::new())),
+        };
+        let mut log = Log::new(Box::new(store.clone()))?;
+        log.append(1, Some(vec![0x01]))?;
+        log.save_term(3, None)?;
+        store.events();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut follower = RoleNode {
+            id: "a".into(),
+            peers: vec!["b".into(), "c".into()],
+            term: 3,
+            log,
+            node_tx,
+            state_tx,
+            proxied_reqs: HashMap::new(),
+            max_inflight: None,
+            max_entry_size: None,
+            max_apply_batch: None,
+            dropped: HashMap::new(),
+            queued_reqs: Vec::new(),
+            role: Follower::new(None, None, &mut rng),
+            rng,
+        };
+        follower.role.expire_leader();
+
+        let mut node = follower.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::SolicitVote { last_index: 1, last_term: 1 },
+        })?;
+        assert_node(&node).is_follower().term(4).voted_for(Some("c"));
+        assert_eq!(
+            store.events(),
+            vec![
+                "set_metadata term=4 voted_for=None",
+                "sync",
+                "set_metadata term=4 voted_for=Some(\"c\")",
+                "sync",
+                "send GrantVote",
+            ]
+        );
+
+        // The new term's follower has a new randomized election timeout.
+        let timeout = match &node {
+            Node::Follower(follower) => follower.role.leader_seen_timeout,
+            _ => panic!("Expected follower"),
+        };
+        for _ in 0..timeout {
+            node = node.tick()?;
+        }
+        assert_node(&node).is_candidate().term(5);
+        assert_eq!(
+            store.events(),
+            vec![
+                "set_metadata term=5 voted_for=None",
+                "sync",
+                "send SolicitVote { last_index: 1, last_term: 1 }",
+            ]
+        );
+        Ok(())
+    }
 }
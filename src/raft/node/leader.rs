@@ -1,8 +1,13 @@
-use super::super::{Address, Event, Instruction, Message, Request, Response, Status};
-use super::{Follower, Node, RoleNode, HEARTBEAT_INTERVAL};
+use super::super::{
+    Address, ClusterHealth, Event, Instruction, Message, Request, Response, Status, VoteRejection,
+};
+use super::{
+    DropReason, Follower, Node, RoleNode, Transition, ELECTION_TIMEOUT_MIN, HEARTBEAT_INTERVAL,
+    LEASE_DURATION,
+};
 use crate::error::{Error, Result};
 
-use ::log::{debug, info, warn};
+use ::log::{debug, info};
 use std::collections::HashMap;
 
 // A leader serves requests and replicates the log to followers.
@@ -14,6 +19,14 @@ pub struct Leader {
     peer_next_index: HashMap<String, u64>,
     /// The last index known to be replicated on a peer.
     peer_last_index: HashMap<String, u64>,
+    /// The last index sent to a peer.
+    peer_sent_index: HashMap<String, u64>,
+    /// Number of ticks since becoming leader.
+    ticks: u64,
+    /// The tick at which the last heartbeat was sent.
+    heartbeat_tick: u64,
+    /// The tick of the last heartbeat confirmed by a peer.
+    peer_confirm_tick: HashMap<String, u64>,
 }
 
 impl Leader {
@@ -23,34 +36,65 @@ impl Leader {
             heartbeat_ticks: 0,
             peer_next_index: HashMap::new(),
             peer_last_index: HashMap::new(),
+            peer_sent_index: HashMap::new(),
+            ticks: 0,
+            heartbeat_tick: 0,
+            peer_confirm_tick: HashMap::new(),
         };
         for peer in peers {
             leader.peer_next_index.insert(peer.clone(), last_index + 1);
             leader.peer_last_index.insert(peer.clone(), 0);
+            leader.peer_sent_index.insert(peer.clone(), last_index);
         }
         leader
     }
 }
 
 impl RoleNode<Leader> {
-    /// Transforms the leader into a follower
-    fn become_follower(mut self, term: u64, leader: &str) -> Result<RoleNode<Follower>> {
-        info!("Discovered new leader {} for term {}, following", leader, term);
+    /// Transforms the leader into a follower, for a new leader if known.
+    fn become_follower(mut self, term: u64, leader: Option<&str>) -> Result<RoleNode<Follower>> {
+        match leader {
+            Some(leader) => info!("Discovered new leader {} for term {}, following", leader, term),
+            None => info!("Discovered new term {}, following", term),
+        }
         self.term = term;
         self.log.save_term(term, None)?;
+        self.trace(Transition::Term);
         self.state_tx.send(Instruction::Abort)?;
-        self.become_role(Follower::new(Some(leader), None))
+        let role = Follower::new(leader, None, &mut self.rng);
+        let node = self.become_role(role)?;
+        node.trace(Transition::Role("follower"));
+        Ok(node)
     }
 
-    /// Appends an entry to the log and replicates it to peers.
+    /// Appends an entry to the log and replicates it to peers, except peers that have reached
+    /// the inflight limit. These are replicated to once they acknowledge entries.
     pub fn append(&mut self, command: Option<Vec<u8>>) -> Result<u64> {
         let entry = self.log.append(self.term, command)?;
-        for peer in self.peers.iter() {
-            self.replicate(peer)?;
+        for peer in self.peers.clone() {
+            if self.inflight_full(&peer)? {
+                debug!("Not replicating to {}, inflight limit reached", peer);
+                continue;
+            }
+            self.replicate(&peer)?;
         }
         Ok(entry.index)
     }
 
+    /// Checks whether a peer has reached the inflight limit, i.e. whether the maximum number of
+    /// unacknowledged entries have been sent to it.
+    fn inflight_full(&self, peer: &str) -> Result<bool> {
+        let (next, sent) =
+            match (self.role.peer_next_index.get(peer), self.role.peer_sent_index.get(peer)) {
+                (Some(next), Some(sent)) => (*next, *sent),
+                _ => return Err(Error::Internal(format!("Unknown peer {}", peer))),
+            };
+        Ok(match self.max_inflight {
+            Some(max) => sent.saturating_sub(next.saturating_sub(1)) >= max,
+            None => false,
+        })
+    }
+
     /// Commits any pending log entries.
     fn commit(&mut self) -> Result<u64> {
         let mut last_indexes = vec![self.log.last_index];
@@ -65,18 +109,70 @@ impl RoleNode<Leader> {
                 if entry.term == self.term {
                     let old_commit_index = self.log.commit_index;
                     self.log.commit(quorum_index)?;
-                    let mut scan = self.log.scan((old_commit_index + 1)..=self.log.commit_index);
-                    while let Some(entry) = scan.next().transpose()? {
-                        self.state_tx.send(Instruction::Apply { entry })?;
-                    }
+                    self.trace(Transition::Commit(quorum_index));
+                    self.apply_committed((old_commit_index + 1)..=self.log.commit_index)?;
                 }
             }
         }
         Ok(self.log.commit_index)
     }
 
-    /// Replicates the log to a peer.
-    fn replicate(&self, peer: &str) -> Result<()> {
+    /// Sends a heartbeat to all peers, recording the tick it was sent at.
+    fn heartbeat(&mut self) -> Result<()> {
+        self.role.heartbeat_tick = self.role.ticks;
+        self.send(
+            Address::Peers,
+            Event::Heartbeat {
+                commit_index: self.log.commit_index,
+                commit_term: self.log.commit_term,
+            },
+        )
+    }
+
+    /// Checks whether the leader holds a valid lease, i.e. whether a quorum has confirmed a
+    /// heartbeat sent within the last LEASE_DURATION ticks. Followers won't vote for another
+    /// candidate until ELECTION_TIMEOUT_MIN ticks after receiving the heartbeat, so no other
+    /// leader can exist. A confirmation is attributed to the last heartbeat sent before it was
+    /// received, which is why LEASE_MARGIN must also cover message delays. We also require an
+    /// entry from our own term to be committed, such that our commit index is up-to-date.
+    fn has_lease(&self) -> bool {
+        if self.log.commit_term != self.term {
+            return false;
+        }
+        let mut confirm_ticks = vec![self.role.ticks];
+        confirm_ticks.extend(self.role.peer_confirm_tick.values());
+        if (confirm_ticks.len() as u64) < self.quorum() {
+            return false;
+        }
+        confirm_ticks.sort();
+        confirm_ticks.reverse();
+        let lease_tick = confirm_ticks[self.quorum() as usize - 1];
+        self.role.ticks < lease_tick + LEASE_DURATION
+    }
+
+    /// Assesses the cluster health, see ClusterHealth.
+    fn cluster_health(&self) -> ClusterHealth {
+        let peer_contact_tick = self
+            .peers
+            .iter()
+            .map(|peer| (peer.clone(), self.role.peer_confirm_tick.get(peer).copied()))
+            .collect::<HashMap<_, _>>();
+        let reachable = 1 + peer_contact_tick
+            .values()
+            .flatten()
+            .filter(|tick| self.role.ticks < *tick + ELECTION_TIMEOUT_MIN)
+            .count() as u64;
+        ClusterHealth {
+            leader: self.id.clone(),
+            term: self.term,
+            tick: self.role.ticks,
+            peer_contact_tick,
+            quorum: reachable >= self.quorum(),
+        }
+    }
+
+    /// Replicates the log to a peer, sending at most the inflight limit of entries.
+    fn replicate(&mut self, peer: &str) -> Result<()> {
         let peer_next = self
             .role
             .peer_next_index
@@ -89,8 +185,13 @@ impl RoleNode<Leader> {
             None if base_index == 0 => 0,
             None => return Err(Error::Internal(format!("Missing base entry {}", base_index))),
         };
-        let entries = self.log.scan(peer_next..).collect::<Result<Vec<_>>>()?;
+        let entries = match self.max_inflight {
+            Some(max) => self.log.scan(peer_next..=base_index.saturating_add(max)),
+            None => self.log.scan(peer_next..),
+        }
+        .collect::<Result<Vec<_>>>()?;
         debug!("Replicating {} entries at base {} to {}", entries.len(), base_index, peer);
+        self.role.peer_sent_index.insert(peer.to_string(), base_index + entries.len() as u64);
         self.send(
             Address::Peer(peer.to_string()),
             Event::ReplicateEntries { base_index, base_term, entries },
@@ -100,19 +201,27 @@ impl RoleNode<Leader> {
 
     /// Processes a message.
     pub fn step(mut self, msg: Message) -> Result<Node> {
-        if let Err(err) = self.validate(&msg) {
-            warn!("Ignoring invalid message: {}", err);
+        if self.reject_stale_vote(&msg)? {
+            return Ok(self.into());
+        }
+        if let Err(reason) = self.validate(&msg) {
+            self.drop_message(&msg, reason);
             return Ok(self.into());
         }
         if msg.term > self.term {
+            // Vote rejections don't come from a leader.
+            if let Event::RejectVote { .. } = msg.event {
+                return Ok(self.become_follower(msg.term, None)?.into());
+            }
             if let Address::Peer(from) = &msg.from {
-                return self.become_follower(msg.term, from)?.step(msg);
+                return self.become_follower(msg.term, Some(from))?.step(msg);
             }
         }
 
         match msg.event {
             Event::ConfirmLeader { commit_index, has_committed } => {
                 if let Address::Peer(from) = msg.from.clone() {
+                    self.role.peer_confirm_tick.insert(from.clone(), self.role.heartbeat_tick);
                     self.state_tx.send(Instruction::Vote {
                         term: msg.term,
                         index: commit_index,
@@ -127,7 +236,13 @@ impl RoleNode<Leader> {
             Event::AcceptEntries { last_index } => {
                 if let Address::Peer(from) = msg.from {
                     self.role.peer_last_index.insert(from.clone(), last_index);
-                    self.role.peer_next_index.insert(from, last_index + 1);
+                    self.role.peer_next_index.insert(from.clone(), last_index + 1);
+                    // Resume replication to peers that were held back by the inflight limit.
+                    if self.role.peer_sent_index.get(&from).copied().unwrap_or(0)
+                        < self.log.last_index
+                    {
+                        self.replicate(&from)?;
+                    }
                 }
                 self.commit()?;
             }
@@ -143,6 +258,25 @@ impl RoleNode<Leader> {
                 }
             }
 
+            // With a valid lease, the query can be executed locally right away, otherwise it
+            // must be confirmed by a quorum via a heartbeat round (i.e. a ReadIndex read).
+            Event::ClientRequest { id, request: Request::Query(command) } if self.has_lease() => {
+                debug!("Serving query locally under leader lease");
+                self.state_tx.send(Instruction::Query {
+                    id,
+                    address: msg.from,
+                    command,
+                    term: self.term,
+                    index: self.log.commit_index,
+                    quorum: 1,
+                })?;
+                self.state_tx.send(Instruction::Vote {
+                    term: self.term,
+                    index: self.log.commit_index,
+                    address: Address::Local,
+                })?;
+            }
+
             Event::ClientRequest { id, request: Request::Query(command) } => {
                 self.state_tx.send(Instruction::Query {
                     id,
@@ -158,21 +292,28 @@ impl RoleNode<Leader> {
                     address: Address::Local,
                 })?;
                 if !self.peers.is_empty() {
-                    self.send(
-                        Address::Peers,
-                        Event::Heartbeat {
-                            commit_index: self.log.commit_index,
-                            commit_term: self.log.commit_term,
-                        },
-                    )?;
+                    self.heartbeat()?;
                 }
             }
 
             Event::ClientRequest { id, request: Request::Mutate(command) } => {
-                let index = self.append(Some(command))?;
-                self.state_tx.send(Instruction::Notify { id, address: msg.from, index })?;
-                if self.peers.is_empty() {
-                    self.commit()?;
+                match self.max_entry_size {
+                    // Oversized commands are rejected before they are appended to the log.
+                    Some(max) if command.len() > max => {
+                        let response = Err(Error::Value(format!(
+                            "Log entry of {} bytes exceeds maximum size of {} bytes",
+                            command.len(),
+                            max
+                        )));
+                        self.send(msg.from, Event::ClientResponse { id, response })?;
+                    }
+                    _ => {
+                        let index = self.append(Some(command))?;
+                        self.state_tx.send(Instruction::Notify { id, address: msg.from, index })?;
+                        if self.peers.is_empty() {
+                            self.commit()?;
+                        }
+                    }
                 }
             }
 
@@ -186,11 +327,17 @@ impl RoleNode<Leader> {
                     apply_index: 0,
                     storage: self.log.store.to_string(),
                     storage_size: self.log.store.size(),
+                    dropped_messages: self.dropped.clone(),
                 });
                 status.node_last_index.insert(self.id.clone(), self.log.last_index);
                 self.state_tx.send(Instruction::Status { id, address: msg.from, status })?
             }
 
+            Event::ClientRequest { id, request: Request::ClusterHealth } => {
+                let response = Ok(Response::ClusterHealth(self.cluster_health()));
+                self.send(msg.from, Event::ClientResponse { id, response })?;
+            }
+
             Event::ClientResponse { id, mut response } => {
                 if let Ok(Response::Status(ref mut status)) = response {
                     status.server = self.id.clone();
@@ -198,12 +345,20 @@ impl RoleNode<Leader> {
                 self.send(Address::Client, Event::ClientResponse { id, response })?;
             }
 
+            // Reject other candidates in our term, since we voted for ourself.
+            Event::SolicitVote { .. } => {
+                if let Address::Peer(from) = msg.from {
+                    let reason = VoteRejection::AlreadyVoted { voted_for: self.id.clone() };
+                    self.send(Address::Peer(from), Event::RejectVote { reason })?;
+                }
+            }
+
             // We ignore these messages, since they are typically additional votes from the previous
             // election that we won after a quorum.
-            Event::SolicitVote { .. } | Event::GrantVote => {}
+            Event::GrantVote | Event::RejectVote { .. } => {}
 
             Event::Heartbeat { .. } | Event::ReplicateEntries { .. } => {
-                warn!("Received unexpected message {:?}", msg)
+                self.drop_message(&msg, DropReason::Malformed("unexpected event for leader".into()))
             }
         }
 
@@ -212,17 +367,12 @@ impl RoleNode<Leader> {
 
     /// Processes a logical clock tick.
     pub fn tick(mut self) -> Result<Node> {
+        self.role.ticks += 1;
         if !self.peers.is_empty() {
             self.role.heartbeat_ticks += 1;
             if self.role.heartbeat_ticks >= HEARTBEAT_INTERVAL {
                 self.role.heartbeat_ticks = 0;
-                self.send(
-                    Address::Peers,
-                    Event::Heartbeat {
-                        commit_index: self.log.commit_index,
-                        commit_term: self.log.commit_term,
-                    },
-                )?;
+                self.heartbeat()?;
             }
         }
         Ok(self.into())
@@ -236,6 +386,8 @@ mod tests {
     use super::*;
     use crate::storage::log;
     use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng as _;
     use tokio::sync::mpsc;
 
     #[allow(clippy::type_complexity)]
@@ -265,7 +417,12 @@ mod tests {
             node_tx,
             state_tx,
             proxied_reqs: HashMap::new(),
+            max_inflight: None,
+            max_entry_size: None,
+            max_apply_batch: None,
+            dropped: HashMap::new(),
             queued_reqs: Vec::new(),
+            rng: StdRng::seed_from_u64(0),
         };
         Ok((node, node_rx, state_rx))
     }
@@ -332,7 +489,7 @@ mod tests {
             term: 3,
             event: Event::Heartbeat { commit_index: 5, commit_term: 3 },
         })?;
-        assert_node(&node).is_leader().term(3).committed(2);
+        assert_node(&node).is_leader().term(3).committed(2).dropped(vec![("malformed", 1)]);
         assert_messages(&mut node_rx, vec![]);
         assert_messages(&mut state_rx, vec![]);
         Ok(())
@@ -364,6 +521,48 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // SolicitVote in the current term is rejected, since we voted for ourself.
+    fn step_solicitvote() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let node = leader.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peers,
+            term: 3,
+            event: Event::SolicitVote { last_index: 5, last_term: 3 },
+        })?;
+        assert_node(&node).is_leader().term(3);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peer("b".into()),
+                term: 3,
+                event: Event::RejectVote {
+                    reason: VoteRejection::AlreadyVoted { voted_for: "a".into() },
+                },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // A vote rejection from a later term makes us a follower without a leader.
+    fn step_rejectvote_future_term() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let node = leader.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 4,
+            event: Event::RejectVote { reason: VoteRejection::StaleTerm },
+        })?;
+        assert_node(&node).is_follower().term(4).leader(None).voted_for(None);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(&mut state_rx, vec![Instruction::Abort]);
+        Ok(())
+    }
+
     #[test]
     // Heartbeats from other leaders in past terms are ignored.
     fn step_heartbeat_past_term() -> Result<()> {
@@ -376,7 +575,7 @@ mod tests {
             term: 2,
             event: Event::Heartbeat { commit_index: 3, commit_term: 2 },
         })?;
-        assert_node(&node).is_leader().term(3).committed(2);
+        assert_node(&node).is_leader().term(3).committed(2).dropped(vec![("stale_term", 1)]);
         assert_messages(&mut node_rx, vec![]);
         assert_messages(&mut state_rx, vec![]);
         Ok(())
@@ -407,14 +606,12 @@ mod tests {
         assert_messages(&mut node_rx, vec![]);
         assert_messages(
             &mut state_rx,
-            vec![
-                Instruction::Apply {
-                    entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                },
-                Instruction::Apply {
-                    entry: Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-                },
-            ],
+            vec![Instruction::Apply {
+                entries: vec![
+                    Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                    Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                ],
+            }],
         );
 
         node = node.step(Message {
@@ -428,7 +625,7 @@ mod tests {
         assert_messages(
             &mut state_rx,
             vec![Instruction::Apply {
-                entry: Entry { index: 5, term: 3, command: Some(vec![0x05]) },
+                entries: vec![Entry { index: 5, term: 3, command: Some(vec![0x05]) }],
             }],
         );
 
@@ -436,6 +633,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // Committed entries are applied in batches of at most max_apply_batch entries.
+    fn step_acceptentries_max_apply_batch() -> Result<()> {
+        let (mut leader, mut node_rx, mut state_rx) = setup()?;
+        leader.max_apply_batch = Some(2);
+        let mut node: Node = leader.into();
+
+        for peer in ["b", "c"].iter() {
+            node = node.step(Message {
+                from: Address::Peer(peer.to_string()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 5 },
+            })?;
+        }
+        assert_node(&node).committed(5);
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(
+            &mut state_rx,
+            vec![
+                Instruction::Apply {
+                    entries: vec![
+                        Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                        Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                    ],
+                },
+                Instruction::Apply {
+                    entries: vec![Entry { index: 5, term: 3, command: Some(vec![0x05]) }],
+                },
+            ],
+        );
+        Ok(())
+    }
+
     #[test]
     // Duplicate AcceptEntries from single node should not trigger commit.
     fn step_acceptentries_duplicate() -> Result<()> {
@@ -499,17 +730,13 @@ mod tests {
             if i == 1 {
                 assert_messages(
                     &mut state_rx,
-                    vec![
-                        Instruction::Apply {
-                            entry: Entry { index: 3, term: 2, command: Some(vec![0x03]) },
-                        },
-                        Instruction::Apply {
-                            entry: Entry { index: 4, term: 3, command: Some(vec![0x04]) },
-                        },
-                        Instruction::Apply {
-                            entry: Entry { index: 5, term: 3, command: Some(vec![0x05]) },
-                        },
-                    ],
+                    vec![Instruction::Apply {
+                        entries: vec![
+                            Entry { index: 3, term: 2, command: Some(vec![0x03]) },
+                            Entry { index: 4, term: 3, command: Some(vec![0x04]) },
+                            Entry { index: 5, term: 3, command: Some(vec![0x05]) },
+                        ],
+                    }],
                 );
             } else {
                 assert_messages(&mut state_rx, vec![]);
@@ -595,6 +822,122 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // A leader with a valid lease serves queries locally, and falls back to confirming them via
+    // heartbeats once the lease expires.
+    fn step_clientrequest_query_lease() -> Result<()> {
+        let (leader, mut node_rx, mut state_rx) = setup()?;
+        let quorum = leader.quorum();
+        let mut node: Node = leader.into();
+
+        // Commit an entry in the current term, and confirm a heartbeat by a quorum.
+        for peer in &["b", "c"] {
+            node = node.step(Message {
+                from: Address::Peer(peer.to_string()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::AcceptEntries { last_index: 5 },
+            })?;
+            node = node.step(Message {
+                from: Address::Peer(peer.to_string()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::ConfirmLeader { commit_index: 5, has_committed: true },
+            })?;
+        }
+        assert_node(&node).is_leader().term(3).committed(5);
+        while node_rx.try_recv().is_ok() {}
+        while state_rx.try_recv().is_ok() {}
+
+        let query = Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x01], request: Request::Query(vec![0xaf]) },
+        };
+
+        // The lease is valid, so the query is executed locally without a heartbeat.
+        node = node.step(query.clone())?;
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(
+            &mut state_rx,
+            vec![
+                Instruction::Query {
+                    id: vec![0x01],
+                    address: Address::Client,
+                    command: vec![0xaf],
+                    term: 3,
+                    index: 5,
+                    quorum: 1,
+                },
+                Instruction::Vote { term: 3, index: 5, address: Address::Local },
+            ],
+        );
+
+        // Once the lease expires without further confirmations, the query needs a quorum.
+        for _ in 0..LEASE_DURATION {
+            node = node.tick()?;
+        }
+        while node_rx.try_recv().is_ok() {}
+        node = node.step(query)?;
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Peers,
+                term: 3,
+                event: Event::Heartbeat { commit_index: 5, commit_term: 3 },
+            }],
+        );
+        assert_messages(
+            &mut state_rx,
+            vec![
+                Instruction::Query {
+                    id: vec![0x01],
+                    address: Address::Client,
+                    command: vec![0xaf],
+                    term: 3,
+                    index: 5,
+                    quorum,
+                },
+                Instruction::Vote { term: 3, index: 5, address: Address::Local },
+            ],
+        );
+
+        // A new quorum confirmation renews the lease.
+        for peer in &["b", "c"] {
+            node = node.step(Message {
+                from: Address::Peer(peer.to_string()),
+                to: Address::Peer("a".into()),
+                term: 3,
+                event: Event::ConfirmLeader { commit_index: 5, has_committed: true },
+            })?;
+        }
+        while state_rx.try_recv().is_ok() {}
+        node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x02], request: Request::Query(vec![0xaf]) },
+        })?;
+        assert_messages(&mut node_rx, vec![]);
+        assert_messages(
+            &mut state_rx,
+            vec![
+                Instruction::Query {
+                    id: vec![0x02],
+                    address: Address::Client,
+                    command: vec![0xaf],
+                    term: 3,
+                    index: 5,
+                    quorum: 1,
+                },
+                Instruction::Vote { term: 3, index: 5, address: Address::Local },
+            ],
+        );
+        Ok(())
+    }
+
     #[test]
     // Sending a mutate request should append it to log, replicate it to peers, and register notification.
     fn step_clientrequest_mutate() -> Result<()> {
@@ -637,6 +980,147 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // Client commands larger than the maximum entry size are rejected, and never appended to the
+    // log or replicated.
+    fn step_clientrequest_mutate_max_entry_size() -> Result<()> {
+        let (mut leader, mut node_rx, mut state_rx) = setup()?;
+        leader.max_entry_size = Some(4);
+        let peers = leader.peers.clone();
+        let mut node: Node = leader.into();
+
+        // A command at the limit is appended and replicated.
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x01], request: Request::Mutate(vec![0xaf; 4]) },
+        })?;
+        assert_node(&node).is_leader().term(3).committed(2).last(6);
+        for peer in peers.iter().cloned() {
+            assert_eq!(
+                node_rx.try_recv()?,
+                Message {
+                    from: Address::Local,
+                    to: Address::Peer(peer),
+                    term: 3,
+                    event: Event::ReplicateEntries {
+                        base_index: 5,
+                        base_term: 3,
+                        entries: vec![Entry { index: 6, term: 3, command: Some(vec![0xaf; 4]) }],
+                    },
+                }
+            )
+        }
+        assert_messages(
+            &mut state_rx,
+            vec![Instruction::Notify { id: vec![0x01], address: Address::Client, index: 6 }],
+        );
+
+        // A command just over the limit is rejected.
+        node = node.step(Message {
+            from: Address::Client,
+            to: Address::Local,
+            term: 0,
+            event: Event::ClientRequest { id: vec![0x02], request: Request::Mutate(vec![0xaf; 5]) },
+        })?;
+        assert_node(&node).is_leader().term(3).committed(2).last(6);
+        assert_messages(
+            &mut node_rx,
+            vec![Message {
+                from: Address::Local,
+                to: Address::Client,
+                term: 3,
+                event: Event::ClientResponse {
+                    id: vec![0x02],
+                    response: Err(Error::Value(
+                        "Log entry of 5 bytes exceeds maximum size of 4 bytes".into(),
+                    )),
+                },
+            }],
+        );
+        assert_messages(&mut state_rx, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    // Peers that reach the inflight limit of unacknowledged entries aren't sent further entries,
+    // until they accept entries.
+    fn step_clientrequest_mutate_inflight() -> Result<()> {
+        let (mut leader, mut node_rx, mut state_rx) = setup()?;
+        leader.max_inflight = Some(2);
+        let peers = leader.peers.clone();
+        let mut node: Node = leader.into();
+
+        let entry = |index: u64| Entry { index, term: 3, command: Some(vec![index as u8]) };
+        let replicate = |peer: &str, base_index: u64, entries: Vec<Entry>| Message {
+            from: Address::Local,
+            to: Address::Peer(peer.into()),
+            term: 3,
+            event: Event::ReplicateEntries { base_index, base_term: 3, entries },
+        };
+        let mutate = |node: Node, index: u64| -> Result<Node> {
+            node.step(Message {
+                from: Address::Client,
+                to: Address::Local,
+                term: 0,
+                event: Event::ClientRequest {
+                    id: vec![index as u8],
+                    request: Request::Mutate(vec![index as u8]),
+                },
+            })
+        };
+
+        // The first two entries are replicated to all peers, then the peers are at the limit.
+        node = mutate(node, 6)?;
+        assert_messages(
+            &mut node_rx,
+            peers.iter().map(|p| replicate(p, 5, vec![entry(6)])).collect(),
+        );
+        node = mutate(node, 7)?;
+        assert_messages(
+            &mut node_rx,
+            peers.iter().map(|p| replicate(p, 5, vec![entry(6), entry(7)])).collect(),
+        );
+        node = mutate(node, 8)?;
+        assert_node(&node).is_leader().term(3).committed(2).last(8);
+        assert_messages(&mut node_rx, vec![]);
+
+        // Once b accepts the entries, it is sent the next entry, but the others are still held back.
+        node = node.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::AcceptEntries { last_index: 7 },
+        })?;
+        assert_messages(&mut node_rx, vec![replicate("b", 7, vec![entry(8)])]);
+        node = mutate(node, 9)?;
+        assert_node(&node).is_leader().term(3).committed(2).last(9);
+        assert_messages(&mut node_rx, vec![replicate("b", 7, vec![entry(8), entry(9)])]);
+
+        // Rejections still probe the peer, within the limit.
+        node = node.step(Message {
+            from: Address::Peer("c".into()),
+            to: Address::Peer("a".into()),
+            term: 3,
+            event: Event::RejectEntries,
+        })?;
+        assert_node(&node).is_leader().term(3).committed(2).last(9);
+        assert_messages(&mut node_rx, vec![replicate("c", 4, vec![entry(5), entry(6)])]);
+
+        assert_messages(
+            &mut state_rx,
+            (6..=9)
+                .map(|i| Instruction::Notify {
+                    id: vec![i as u8],
+                    address: Address::Client,
+                    index: i,
+                })
+                .collect(),
+        );
+        Ok(())
+    }
+
     #[test]
     // Sending a status request should pass it on to state machine, to add status.
     fn step_clientrequest_status() -> Result<()> {
@@ -673,6 +1157,7 @@ mod tests {
                     apply_index: 0,
                     storage: "test".into(),
                     storage_size: 130,
+                    dropped_messages: HashMap::new(),
                 }),
             }],
         );
@@ -680,6 +1165,101 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // The cluster health reports when each peer last confirmed a heartbeat, and whether a quorum
+    // is reachable, as peers stop confirming heartbeats.
+    fn step_clientrequest_cluster_health() -> Result<()> {
+        let (mut leader, mut node_rx, _state_rx) = setup()?;
+        let peers: Vec<String> = vec!["b".into(), "c".into(), "d".into()];
+        leader.peers = peers.clone();
+        leader.role = Leader::new(peers, leader.log.last_index);
+        let node: Node = leader.into();
+
+        // Ticks the given number of times, with the given peers confirming each heartbeat.
+        let run = |mut node: Node, ticks: u64, acks: &[&str]| -> Result<Node> {
+            for _ in 0..ticks {
+                node = node.tick()?;
+                for peer in acks {
+                    node = node.step(Message {
+                        from: Address::Peer(peer.to_string()),
+                        to: Address::Peer("a".into()),
+                        term: 3,
+                        event: Event::ConfirmLeader { commit_index: 2, has_committed: true },
+                    })?;
+                }
+            }
+            Ok(node)
+        };
+        let health = |node: Node, node_rx: &mut mpsc::UnboundedReceiver<Message>| {
+            while node_rx.try_recv().is_ok() {}
+            let node = node.step(Message {
+                from: Address::Client,
+                to: Address::Local,
+                term: 0,
+                event: Event::ClientRequest { id: vec![0x01], request: Request::ClusterHealth },
+            })?;
+            match node_rx.try_recv()? {
+                Message {
+                    to: Address::Client,
+                    event: Event::ClientResponse { response: Ok(Response::ClusterHealth(h)), .. },
+                    ..
+                } => Ok((node, h)),
+                msg => Err(Error::Internal(format!("Unexpected message {:?}", msg))),
+            }
+        };
+        let contacts = |ticks: &[(&str, Option<u64>)]| -> HashMap<String, Option<u64>> {
+            ticks.iter().map(|(p, t)| (p.to_string(), *t)).collect()
+        };
+
+        // Before any confirmations, the leader can't reach a quorum.
+        let (node, h) = health(node, &mut node_rx)?;
+        assert_eq!(
+            h,
+            ClusterHealth {
+                leader: "a".into(),
+                term: 3,
+                tick: 0,
+                peer_contact_tick: contacts(&[("b", None), ("c", None), ("d", None)]),
+                quorum: false,
+            }
+        );
+
+        // All peers confirm heartbeats.
+        let node = run(node, 3, &["b", "c", "d"])?;
+        let (node, h) = health(node, &mut node_rx)?;
+        assert_eq!(h.tick, 3);
+        assert_eq!(
+            h.peer_contact_tick,
+            contacts(&[("b", Some(3)), ("c", Some(3)), ("d", Some(3))])
+        );
+        assert!(h.quorum);
+
+        // d stops confirming heartbeats. Once it's unreachable, the quorum is still met.
+        let node = run(node, ELECTION_TIMEOUT_MIN, &["b", "c"])?;
+        let (node, h) = health(node, &mut node_rx)?;
+        assert_eq!(h.tick, 3 + ELECTION_TIMEOUT_MIN);
+        assert_eq!(
+            h.peer_contact_tick,
+            contacts(&[("b", Some(h.tick)), ("c", Some(h.tick)), ("d", Some(3))])
+        );
+        assert!(h.quorum);
+
+        // c also stops. The quorum is still met until c's last confirmation expires.
+        let node = run(node, ELECTION_TIMEOUT_MIN - 1, &["b"])?;
+        let (node, h) = health(node, &mut node_rx)?;
+        assert!(h.quorum);
+
+        let node = run(node, 1, &["b"])?;
+        let (_, h) = health(node, &mut node_rx)?;
+        assert_eq!(h.tick, 3 + 2 * ELECTION_TIMEOUT_MIN);
+        assert_eq!(
+            h.peer_contact_tick,
+            contacts(&[("b", Some(h.tick)), ("c", Some(3 + ELECTION_TIMEOUT_MIN)), ("d", Some(3))])
+        );
+        assert!(!h.quorum);
+        Ok(())
+    }
+
     #[test]
     fn tick() -> Result<()> {
         let (leader, mut node_rx, mut state_rx) = setup()?;
@@ -1,16 +1,22 @@
 mod candidate;
 mod follower;
 mod leader;
+mod trace;
 
-use super::{Address, Driver, Event, Instruction, Log, Message, State};
+use super::{Address, Driver, Event, Instruction, Log, Message, State, VoteRejection};
 use crate::error::{Error, Result};
 use candidate::Candidate;
 use follower::Follower;
 use leader::Leader;
+use trace::Transition;
+pub use trace::{set_log_format, LogFormat};
 
-use ::log::{debug, info};
+use ::log::{debug, info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng as _, SeedableRng as _};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use tokio::sync::mpsc;
 
 /// The interval between leader heartbeats, in ticks.
@@ -22,6 +28,22 @@ const ELECTION_TIMEOUT_MIN: u64 = 8 * HEARTBEAT_INTERVAL;
 /// The maximum election timeout, in ticks.
 const ELECTION_TIMEOUT_MAX: u64 = 15 * HEARTBEAT_INTERVAL;
 
+/// Draws a randomized election timeout, in ticks.
+fn election_timeout(rng: &mut StdRng) -> u64 {
+    rng.gen_range(ELECTION_TIMEOUT_MIN, ELECTION_TIMEOUT_MAX)
+}
+
+/// The safety margin subtracted from the minimum election timeout to get the leader lease
+/// duration, in ticks. Ticks are measured by each node's local clock, so the lease is only safe
+/// as long as clock rates differ by less than this margin over an election timeout, and heartbeat
+/// confirmations are delivered within it.
+const LEASE_MARGIN: u64 = 2 * HEARTBEAT_INTERVAL;
+
+/// The leader lease duration, in ticks. A leader which has received heartbeat confirmations
+/// from a quorum within this window may serve reads locally, since followers won't vote for a
+/// new leader until ELECTION_TIMEOUT_MIN ticks after hearing from it.
+const LEASE_DURATION: u64 = ELECTION_TIMEOUT_MIN - LEASE_MARGIN;
+
 /// Node status
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Status {
@@ -33,6 +55,56 @@ pub struct Status {
     pub apply_index: u64,
     pub storage: String,
     pub storage_size: u64,
+    /// The number of messages dropped by the leader without processing them, by DropReason name.
+    pub dropped_messages: HashMap<String, u64>,
+}
+
+/// The reason a received message was dropped without processing it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DropReason {
+    /// The message is addressed to another node or a client, i.e. it's not for this node.
+    Misrouted(Address),
+    /// The message is from a past term, e.g. a delayed message from a previous leader.
+    StaleTerm(u64),
+    /// The message is invalid, e.g. sent from an address that can't send it, or with an event
+    /// that the node's current role doesn't expect.
+    Malformed(String),
+}
+
+impl DropReason {
+    /// Returns the name of the reason, which dropped messages are counted by.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Misrouted(_) => "misrouted",
+            Self::StaleTerm(_) => "stale_term",
+            Self::Malformed(_) => "malformed",
+        }
+    }
+}
+
+impl std::fmt::Display for DropReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Misrouted(to) => write!(f, "addressed to {:?}", to),
+            Self::StaleTerm(term) => write!(f, "from past term {}", term),
+            Self::Malformed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// A point-in-time cluster liveness assessment from the leader's perspective, based on the
+/// heartbeats confirmed by each peer via ConfirmLeader. Peers which haven't confirmed a heartbeat
+/// within ELECTION_TIMEOUT_MIN ticks are considered unreachable.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterHealth {
+    pub leader: String,
+    pub term: u64,
+    /// The leader's current tick, counted from when it became leader.
+    pub tick: u64,
+    /// The tick of the last heartbeat confirmed by each peer, or None if it never confirmed one.
+    pub peer_contact_tick: HashMap<String, Option<u64>>,
+    /// Whether a quorum of nodes, including the leader, is currently reachable.
+    pub quorum: bool,
 }
 
 /// The local Raft node state machine.
@@ -68,6 +140,7 @@ impl Node {
         tokio::spawn(driver.drive(state));
 
         let (term, voted_for) = log.load_term()?;
+        let mut rng = StdRng::from_entropy();
         let node = RoleNode {
             id: id.to_owned(),
             peers,
@@ -77,17 +150,80 @@ impl Node {
             state_tx,
             queued_reqs: Vec::new(),
             proxied_reqs: HashMap::new(),
-            role: Follower::new(None, voted_for.as_deref()),
+            max_inflight: None,
+            max_entry_size: None,
+            max_apply_batch: None,
+            dropped: HashMap::new(),
+            role: Follower::new(None, voted_for.as_deref(), &mut rng),
+            rng,
         };
         if node.peers.is_empty() {
             info!("No peers specified, starting as leader");
             let last_index = node.log.last_index;
-            Ok(node.become_role(Leader::new(vec![], last_index))?.into())
+            let node = node.become_role(Leader::new(vec![], last_index))?;
+            node.trace(Transition::Role("leader"));
+            Ok(node.into())
         } else {
             Ok(node.into())
         }
     }
 
+    /// Limits the number of unacknowledged entries a leader sends to each peer. Once a peer has
+    /// this many entries in flight, the leader stops replicating to it until it acknowledges them.
+    pub fn with_max_inflight(mut self, max_inflight: Option<u64>) -> Self {
+        match &mut self {
+            Node::Candidate(n) => n.max_inflight = max_inflight,
+            Node::Follower(n) => n.max_inflight = max_inflight,
+            Node::Leader(n) => n.max_inflight = max_inflight,
+        }
+        self
+    }
+
+    /// Limits the size of client commands, in bytes. Leaders reject larger commands with
+    /// Error::Value before appending them to the log, so they are never replicated.
+    pub fn with_max_entry_size(mut self, max_entry_size: Option<usize>) -> Self {
+        match &mut self {
+            Node::Candidate(n) => n.max_entry_size = max_entry_size,
+            Node::Follower(n) => n.max_entry_size = max_entry_size,
+            Node::Leader(n) => n.max_entry_size = max_entry_size,
+        }
+        self
+    }
+
+    /// Limits the number of committed log entries applied to the state machine in a single batch,
+    /// which bounds the latency of applying a batch.
+    pub fn with_max_apply_batch(mut self, max_apply_batch: Option<usize>) -> Self {
+        match &mut self {
+            Node::Candidate(n) => n.max_apply_batch = max_apply_batch,
+            Node::Follower(n) => n.max_apply_batch = max_apply_batch,
+            Node::Leader(n) => n.max_apply_batch = max_apply_batch,
+        }
+        self
+    }
+
+    /// Seeds the random number generator used for election timeouts, such that a node's sequence
+    /// of election timeouts is reproducible, e.g. in tests. By default, it's seeded randomly. The
+    /// current election timeout is redrawn from the seeded generator.
+    pub fn with_seed(self, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self {
+            Node::Candidate(mut n) => {
+                n.role.reset_timeout(&mut rng);
+                n.rng = rng;
+                n.into()
+            }
+            Node::Follower(mut n) => {
+                n.role.reset_timeout(&mut rng);
+                n.rng = rng;
+                n.into()
+            }
+            Node::Leader(mut n) => {
+                n.rng = rng;
+                n.into()
+            }
+        }
+    }
+
     /// Returns the node ID.
     pub fn id(&self) -> String {
         match self {
@@ -147,6 +283,16 @@ pub struct RoleNode<R> {
     queued_reqs: Vec<(Address, Event)>,
     /// Keeps track of proxied client requests, to abort on new leader election.
     proxied_reqs: HashMap<Vec<u8>, Address>,
+    /// The maximum number of unacknowledged entries a leader sends to a peer, if limited.
+    max_inflight: Option<u64>,
+    /// The maximum size of a client command appended to the log, in bytes, if limited.
+    max_entry_size: Option<usize>,
+    /// The maximum number of committed entries applied to the state machine in a batch, if limited.
+    max_apply_batch: Option<usize>,
+    /// The number of dropped messages, by DropReason name.
+    dropped: HashMap<String, u64>,
+    /// The random number generator for election timeouts.
+    rng: StdRng,
     role: R,
 }
 
@@ -162,10 +308,38 @@ impl<R> RoleNode<R> {
             state_tx: self.state_tx,
             queued_reqs: self.queued_reqs,
             proxied_reqs: self.proxied_reqs,
+            max_inflight: self.max_inflight,
+            max_entry_size: self.max_entry_size,
+            max_apply_batch: self.max_apply_batch,
+            dropped: self.dropped,
+            rng: self.rng,
             role,
         })
     }
 
+    /// Logs a structured transition of the node, see Transition.
+    fn trace(&self, transition: Transition) {
+        transition.log(&self.id, self.term, self.log.last_index)
+    }
+
+    /// Sends the committed log entries in the given index range to the state machine driver, in
+    /// batches of at most max_apply_batch entries.
+    fn apply_committed(&mut self, range: RangeInclusive<u64>) -> Result<()> {
+        let max = self.max_apply_batch.unwrap_or(usize::MAX).max(1);
+        let mut entries = Vec::new();
+        let mut scan = self.log.scan(range);
+        while let Some(entry) = scan.next().transpose()? {
+            entries.push(entry);
+            if entries.len() >= max {
+                self.state_tx.send(Instruction::Apply { entries: std::mem::take(&mut entries) })?;
+            }
+        }
+        if !entries.is_empty() {
+            self.state_tx.send(Instruction::Apply { entries })?;
+        }
+        Ok(())
+    }
+
     /// Aborts any proxied requests.
     fn abort_proxied(&mut self) -> Result<()> {
         for (id, address) in std::mem::replace(&mut self.proxied_reqs, HashMap::new()) {
@@ -198,6 +372,25 @@ impl<R> RoleNode<R> {
         (self.peers.len() as u64 + 1) / 2 + 1
     }
 
+    /// Rejects votes solicited by candidates from past terms, such that they learn about the
+    /// current term and can abandon their election. Returns true if the vote was rejected.
+    fn reject_stale_vote(&self, msg: &Message) -> Result<bool> {
+        match (&msg.from, &msg.event) {
+            (Address::Peer(from), Event::SolicitVote { .. }) if msg.term < self.term => {
+                debug!(
+                    "Rejecting term {} vote for {} from stale term {}",
+                    self.term, from, msg.term
+                );
+                self.send(
+                    Address::Peer(from.clone()),
+                    Event::RejectVote { reason: VoteRejection::StaleTerm },
+                )?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
     /// Sends an event
     fn send(&self, to: Address, event: Event) -> Result<()> {
         let msg = Message { term: self.term, from: Address::Local, to, event };
@@ -205,40 +398,53 @@ impl<R> RoleNode<R> {
         Ok(self.node_tx.send(msg)?)
     }
 
-    /// Validates a message
-    fn validate(&self, msg: &Message) -> Result<()> {
+    /// Validates a message, returning the reason to drop it if it's invalid: malformed messages
+    /// are checked first, then messages that aren't for this node, then messages from past terms.
+    fn validate(&self, msg: &Message) -> std::result::Result<(), DropReason> {
         match msg.from {
-            Address::Peers => return Err(Error::Internal("Message from broadcast address".into())),
-            Address::Local => return Err(Error::Internal("Message from local node".into())),
-            Address::Client if !matches!(msg.event, Event::ClientRequest{..}) => {
-                return Err(Error::Internal("Non-request message from client".into()));
+            Address::Peers => {
+                return Err(DropReason::Malformed("message from broadcast address".into()))
+            }
+            Address::Local => return Err(DropReason::Malformed("message from local node".into())),
+            Address::Client if !matches!(msg.event, Event::ClientRequest { .. }) => {
+                return Err(DropReason::Malformed("non-request message from client".into()));
             }
             _ => {}
         }
 
+        match &msg.to {
+            Address::Peer(id) if id == &self.id => {}
+            Address::Local | Address::Peers => {}
+            to @ Address::Peer(_) | to @ Address::Client => {
+                return Err(DropReason::Misrouted(to.clone()))
+            }
+        }
+
         // Allowing requests and responses form past terms is fine, since they don't rely on it
         if msg.term < self.term
-            && !matches!(msg.event, Event::ClientRequest{..} | Event::ClientResponse{..})
+            && !matches!(msg.event, Event::ClientRequest { .. } | Event::ClientResponse { .. })
         {
-            return Err(Error::Internal(format!("Message from past term {}", msg.term)));
+            return Err(DropReason::StaleTerm(msg.term));
         }
+        Ok(())
+    }
 
-        match &msg.to {
-            Address::Peer(id) if id == &self.id => Ok(()),
-            Address::Local => Ok(()),
-            Address::Peers => Ok(()),
-            Address::Peer(id) => {
-                Err(Error::Internal(format!("Received message for other node {}", id)))
-            }
-            Address::Client => Err(Error::Internal("Received message for client".into())),
+    /// Drops a message without processing it, logging the reason and counting it by name.
+    /// Messages from past terms are expected after elections, so they're only logged at debug
+    /// level.
+    fn drop_message(&mut self, msg: &Message, reason: DropReason) {
+        match reason {
+            DropReason::StaleTerm(_) => debug!("Dropping message {:?}: {}", msg, reason),
+            _ => warn!("Dropping {} message {:?}: {}", reason.name(), msg, reason),
         }
+        *self.dropped.entry(reason.name().to_string()).or_default() += 1;
     }
 }
 
 #[cfg(test)]
 mod tests {
     pub use super::super::state::tests::TestState;
-    use super::super::Entry;
+    use super::super::{Entry, Request};
     use super::follower::tests::{follower_leader, follower_voted_for};
     use super::*;
     use crate::storage::log;
@@ -334,6 +540,19 @@ mod tests {
             self
         }
 
+        pub fn dropped(self, dropped: Vec<(&str, u64)>) -> Self {
+            assert_eq!(
+                &dropped.into_iter().map(|(r, n)| (r.to_string(), n)).collect::<HashMap<_, _>>(),
+                match self.node {
+                    Node::Candidate(n) => &n.dropped,
+                    Node::Follower(n) => &n.dropped,
+                    Node::Leader(n) => &n.dropped,
+                },
+                "Unexpected dropped messages"
+            );
+            self
+        }
+
         pub fn proxied(self, proxied: Vec<(Vec<u8>, Address)>) -> Self {
             assert_eq!(
                 &proxied.into_iter().collect::<HashMap<Vec<u8>, Address>>(),
@@ -420,7 +639,12 @@ mod tests {
             node_tx,
             state_tx,
             proxied_reqs: HashMap::new(),
+            max_inflight: None,
+            max_entry_size: None,
+            max_apply_batch: None,
+            dropped: HashMap::new(),
             queued_reqs: Vec::new(),
+            rng: StdRng::seed_from_u64(0),
         };
         Ok((node, node_rx))
     }
@@ -569,6 +793,70 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    // Invalid messages are categorized as malformed, misrouted, or stale, in that order.
+    fn validate() -> Result<()> {
+        let (node, _) = setup_rolenode()?;
+        let msg =
+            |from: Address, to: Address, term: u64, event: Event| Message { from, to, term, event };
+        let heartbeat = Event::Heartbeat { commit_index: 1, commit_term: 1 };
+        let request = Event::ClientRequest { id: vec![0x01], request: Request::Status };
+        let b = || Address::Peer("b".into());
+        let a = || Address::Peer("a".into());
+        let cases = vec![
+            (msg(b(), a(), 1, heartbeat.clone()), Ok(())),
+            (msg(b(), Address::Peers, 1, heartbeat.clone()), Ok(())),
+            (msg(Address::Client, Address::Local, 0, request.clone()), Ok(())),
+            (
+                msg(b(), Address::Peer("c".into()), 1, heartbeat.clone()),
+                Err(DropReason::Misrouted(Address::Peer("c".into()))),
+            ),
+            (
+                msg(b(), Address::Client, 1, heartbeat.clone()),
+                Err(DropReason::Misrouted(Address::Client)),
+            ),
+            (msg(b(), a(), 0, heartbeat.clone()), Err(DropReason::StaleTerm(0))),
+            (
+                msg(b(), Address::Peer("c".into()), 0, heartbeat.clone()),
+                Err(DropReason::Misrouted(Address::Peer("c".into()))),
+            ),
+            (
+                msg(Address::Peers, a(), 1, heartbeat.clone()),
+                Err(DropReason::Malformed("message from broadcast address".into())),
+            ),
+            (
+                msg(Address::Local, a(), 1, heartbeat.clone()),
+                Err(DropReason::Malformed("message from local node".into())),
+            ),
+            (
+                msg(Address::Client, Address::Peer("c".into()), 0, heartbeat),
+                Err(DropReason::Malformed("non-request message from client".into())),
+            ),
+        ];
+        for (msg, expect) in cases {
+            assert_eq!(node.validate(&msg), expect, "{:?}", msg);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn drop_message() -> Result<()> {
+        let (node, _) = setup_rolenode()?;
+        let mut node =
+            node.become_role(Follower::new(None, None, &mut StdRng::seed_from_u64(0)))?;
+        let msg = Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("c".into()),
+            term: 0,
+            event: Event::Heartbeat { commit_index: 1, commit_term: 1 },
+        };
+        node.drop_message(&msg, DropReason::Misrouted(Address::Peer("c".into())));
+        node.drop_message(&msg, DropReason::StaleTerm(0));
+        node.drop_message(&msg, DropReason::Misrouted(Address::Peer("c".into())));
+        assert_node(&node.into()).dropped(vec![("misrouted", 2), ("stale_term", 1)]);
+        Ok(())
+    }
+
     #[test]
     fn send() -> Result<()> {
         let (node, mut rx) = setup_rolenode()?;
@@ -0,0 +1,286 @@
+use ::log::{debug, info};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The log target of Raft node transitions, which can be used to filter them.
+pub const TARGET: &str = "toydb::raft::transition";
+
+/// Whether transitions are logged as JSON objects rather than key=value pairs.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// The format of logged Raft node transitions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// Space-separated key=value pairs, e.g. event=role node=a term=2 index=5 role=leader.
+    Text,
+    /// A JSON object per line, e.g. {"event":"role","node":"a","term":2,"index":5,"role":"leader"}.
+    Json,
+}
+
+/// Sets the format of logged Raft node transitions for the process. Defaults to LogFormat::Text.
+pub fn set_log_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed)
+}
+
+/// A Raft node transition, logged with the node ID, its current term, and its last log index.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transition<'a> {
+    /// The node changed role.
+    Role(&'static str),
+    /// The node moved to a new term.
+    Term,
+    /// The node granted its vote to a candidate.
+    Vote(&'a str),
+    /// The node's commit index advanced.
+    Commit(u64),
+}
+
+impl<'a> Transition<'a> {
+    /// The event name, logged as the event field.
+    fn event(&self) -> &'static str {
+        match self {
+            Self::Role(_) => "role",
+            Self::Term => "term",
+            Self::Vote(_) => "vote",
+            Self::Commit(_) => "commit",
+        }
+    }
+
+    /// Formats the transition for the given node, term, and last log index.
+    pub fn format(&self, format: LogFormat, node: &str, term: u64, index: u64) -> String {
+        let mut fields = vec![
+            ("event", Field::String(self.event())),
+            ("node", Field::String(node)),
+            ("term", Field::Integer(term)),
+            ("index", Field::Integer(index)),
+        ];
+        match self {
+            Self::Role(role) => fields.push(("role", Field::String(role))),
+            Self::Term => {}
+            Self::Vote(candidate) => fields.push(("candidate", Field::String(candidate))),
+            Self::Commit(commit_index) => fields.push(("commit", Field::Integer(*commit_index))),
+        }
+
+        let mut s = String::new();
+        match format {
+            LogFormat::Text => {
+                for (i, (key, value)) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        s.push(' ');
+                    }
+                    match value {
+                        Field::String(v) => write!(s, "{}={}", key, v),
+                        Field::Integer(v) => write!(s, "{}={}", key, v),
+                    }
+                    .expect("writing to String can't fail");
+                }
+            }
+            LogFormat::Json => {
+                s.push('{');
+                for (i, (key, value)) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        s.push(',');
+                    }
+                    match value {
+                        Field::String(v) => write!(s, "\"{}\":{}", key, json_string(v)),
+                        Field::Integer(v) => write!(s, "\"{}\":{}", key, v),
+                    }
+                    .expect("writing to String can't fail");
+                }
+                s.push('}');
+            }
+        }
+        s
+    }
+
+    /// Logs the transition for the given node, term, and last log index, in the process' log
+    /// format. Commit advances happen on every write, so they're logged at debug level, and other
+    /// transitions at info level.
+    pub fn log(&self, node: &str, term: u64, index: u64) {
+        let format = if JSON.load(Ordering::Relaxed) { LogFormat::Json } else { LogFormat::Text };
+        match self {
+            Self::Commit(_) => debug!(target: TARGET, "{}", self.format(format, node, term, index)),
+            _ => info!(target: TARGET, "{}", self.format(format, node, term, index)),
+        }
+    }
+}
+
+/// A transition field value.
+enum Field<'a> {
+    String(&'a str),
+    Integer(u64),
+}
+
+/// Encodes a string as a quoted JSON string.
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                write!(json, "\\u{:04x}", c as u32).expect("writing to String can't fail")
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{Address, Event, Log, Message};
+    use super::super::tests::TestState;
+    use super::super::Node;
+    use super::*;
+    use crate::error::Result;
+    use crate::storage::log;
+    use pretty_assertions::assert_eq;
+    use std::cell::RefCell;
+    use std::sync::Once;
+    use tokio::sync::mpsc;
+
+    thread_local! {
+        /// Transitions logged by the current thread.
+        static CAPTURED: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    }
+
+    /// A logger which captures transitions logged by the current thread, such that concurrent
+    /// tests don't see each other's transitions.
+    struct Capture;
+
+    impl ::log::Log for Capture {
+        fn enabled(&self, metadata: &::log::Metadata) -> bool {
+            metadata.target() == TARGET
+        }
+
+        fn log(&self, record: &::log::Record) {
+            if self.enabled(record.metadata()) {
+                let line = format!("{} {}", record.level(), record.args());
+                CAPTURED.with(|c| c.borrow_mut().push(line));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture;
+    static CAPTURE_INIT: Once = Once::new();
+
+    /// Starts capturing transitions logged by the current thread.
+    fn capture() {
+        CAPTURE_INIT.call_once(|| {
+            ::log::set_logger(&CAPTURE).unwrap();
+            ::log::set_max_level(::log::LevelFilter::Debug);
+        });
+        CAPTURED.with(|c| c.borrow_mut().clear());
+    }
+
+    /// Returns and clears the transitions captured for the current thread.
+    fn captured() -> Vec<String> {
+        CAPTURED.with(|c| std::mem::take(&mut *c.borrow_mut()))
+    }
+
+    async fn setup(id: &str, peers: Vec<&str>) -> Result<(Node, mpsc::UnboundedReceiver<Message>)> {
+        let (node_tx, node_rx) = mpsc::unbounded_channel();
+        let node = Node::new(
+            id,
+            peers.into_iter().map(String::from).collect(),
+            Log::new(Box::new(log::Test::new()))?,
+            Box::new(TestState::new(0)),
+            node_tx,
+        )
+        .await?;
+        Ok((node, node_rx))
+    }
+
+    #[tokio::test]
+    // An election logs the term and role changes of the candidate, the vote of the follower, and
+    // the commit of the leader's initial entry, with the node ID, term, and last log index.
+    async fn election() -> Result<()> {
+        capture();
+        let (mut a, _a_rx) = setup("a", vec!["b", "c"]).await?;
+        let (mut b, _b_rx) = setup("b", vec!["a", "c"]).await?;
+        if let Node::Follower(b) = &mut b {
+            b.role.expire_leader(); // b won't vote until the minimum election timeout after startup
+        }
+        assert_eq!(captured(), Vec::<String>::new());
+
+        while let Node::Follower(_) = a {
+            a = a.tick()?;
+        }
+        assert_eq!(
+            captured(),
+            vec![
+                "INFO event=term node=a term=1 index=0",
+                "INFO event=role node=a term=1 index=0 role=candidate",
+            ]
+        );
+
+        b.step(Message {
+            from: Address::Peer("a".into()),
+            to: Address::Peer("b".into()),
+            term: 1,
+            event: Event::SolicitVote { last_index: 0, last_term: 0 },
+        })?;
+        assert_eq!(
+            captured(),
+            vec![
+                "INFO event=term node=b term=1 index=0",
+                "INFO event=vote node=b term=1 index=0 candidate=a",
+            ]
+        );
+
+        let a = a.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 1,
+            event: Event::GrantVote,
+        })?;
+        assert_eq!(captured(), vec!["INFO event=role node=a term=1 index=0 role=leader"]);
+
+        a.step(Message {
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            term: 1,
+            event: Event::AcceptEntries { last_index: 1 },
+        })?;
+        assert_eq!(captured(), vec!["DEBUG event=commit node=a term=1 index=1 commit=1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn format() {
+        let cases = vec![
+            (
+                Transition::Role("leader"),
+                "event=role node=a term=2 index=5 role=leader",
+                r#"{"event":"role","node":"a","term":2,"index":5,"role":"leader"}"#,
+            ),
+            (
+                Transition::Term,
+                "event=term node=a term=2 index=5",
+                r#"{"event":"term","node":"a","term":2,"index":5}"#,
+            ),
+            (
+                Transition::Vote("b\"\n"),
+                "event=vote node=a term=2 index=5 candidate=b\"\n",
+                r#"{"event":"vote","node":"a","term":2,"index":5,"candidate":"b\"\n"}"#,
+            ),
+            (
+                Transition::Commit(4),
+                "event=commit node=a term=2 index=5 commit=4",
+                r#"{"event":"commit","node":"a","term":2,"index":5,"commit":4}"#,
+            ),
+        ];
+        for (transition, text, json) in cases {
+            assert_eq!(transition.format(LogFormat::Text, "a", 2, 5), text);
+            assert_eq!(transition.format(LogFormat::Json, "a", 2, 5), json);
+        }
+    }
+}
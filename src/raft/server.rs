@@ -1,10 +1,12 @@
-use super::{Address, Event, Log, Message, Node, Request, Response, State};
+use super::{
+    Address, ClientRequest, Event, Log, Message, Node, Peers, Response, State, TcpTransport,
+};
 use crate::error::{Error, Result};
 
 use ::log::{debug, error};
-use futures::{sink::SinkExt as _, FutureExt as _};
+use futures::FutureExt as _;
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::stream::StreamExt as _;
 use tokio::sync::{mpsc, oneshot};
@@ -44,11 +46,29 @@ impl Server {
         })
     }
 
+    /// Limits the number of unacknowledged log entries the leader sends to each peer.
+    pub fn with_max_inflight(mut self, max_inflight: Option<u64>) -> Self {
+        self.node = self.node.with_max_inflight(max_inflight);
+        self
+    }
+
+    /// Limits the size of client commands appended to the log, in bytes.
+    pub fn with_max_entry_size(mut self, max_entry_size: Option<usize>) -> Self {
+        self.node = self.node.with_max_entry_size(max_entry_size);
+        self
+    }
+
+    /// Limits the number of committed log entries applied to the state machine in a batch.
+    pub fn with_max_apply_batch(mut self, max_apply_batch: Option<usize>) -> Self {
+        self.node = self.node.with_max_apply_batch(max_apply_batch);
+        self
+    }
+
     /// Connects to peers and serves requests.
     pub async fn serve(
         self,
         listener: TcpListener,
-        client_rx: mpsc::UnboundedReceiver<(Request, oneshot::Sender<Result<Response>>)>,
+        client_rx: mpsc::UnboundedReceiver<ClientRequest>,
     ) -> Result<()> {
         let (tcp_in_tx, tcp_in_rx) = mpsc::unbounded_channel::<Message>();
         let (tcp_out_tx, tcp_out_rx) = mpsc::unbounded_channel::<Message>();
@@ -66,19 +86,33 @@ impl Server {
         Ok(())
     }
 
-    /// Runs the event loop.
+    /// Runs the event loop. Client requests that have not completed by their deadline are
+    /// responded to with Error::Timeout, checked on every tick.
     async fn eventloop(
         mut node: Node,
         mut node_rx: mpsc::UnboundedReceiver<Message>,
-        mut client_rx: mpsc::UnboundedReceiver<(Request, oneshot::Sender<Result<Response>>)>,
+        mut client_rx: mpsc::UnboundedReceiver<ClientRequest>,
         mut tcp_rx: mpsc::UnboundedReceiver<Message>,
         tcp_tx: mpsc::UnboundedSender<Message>,
     ) -> Result<()> {
         let mut ticker = tokio::time::interval(TICK);
         let mut requests = HashMap::<Vec<u8>, oneshot::Sender<Result<Response>>>::new();
+        let mut deadlines = HashMap::<Vec<u8>, Instant>::new();
         loop {
             tokio::select! {
-                _ = ticker.tick() => node = node.tick()?,
+                _ = ticker.tick() => {
+                    node = node.tick()?;
+                    let now = Instant::now();
+                    deadlines.retain(|id, deadline| {
+                        if *deadline > now {
+                            return true;
+                        }
+                        if let Some(response_tx) = requests.remove(id) {
+                            response_tx.send(Err(Error::Timeout)).ok();
+                        }
+                        false
+                    });
+                }
 
                 Some(msg) = tcp_rx.next() => node = node.step(msg)?,
 
@@ -87,6 +121,7 @@ impl Server {
                         Message{to: Address::Peer(_), ..} => tcp_tx.send(msg)?,
                         Message{to: Address::Peers, ..} => tcp_tx.send(msg)?,
                         Message{to: Address::Client, event: Event::ClientResponse{ id, response }, ..} => {
+                            deadlines.remove(&id);
                             if let Some(response_tx) = requests.remove(&id) {
                                 response_tx
                                     .send(response)
@@ -97,9 +132,16 @@ impl Server {
                     }
                 }
 
-                Some((request, response_tx)) = client_rx.next() => {
+                Some((request, deadline, response_tx)) = client_rx.next() => {
+                    if matches!(deadline, Some(deadline) if deadline <= Instant::now()) {
+                        response_tx.send(Err(Error::Timeout)).ok();
+                        continue;
+                    }
                     let id = Uuid::new_v4().as_bytes().to_vec();
                     requests.insert(id.clone(), response_tx);
+                    if let Some(deadline) = deadline {
+                        deadlines.insert(id.clone(), deadline);
+                    }
                     node = node.step(Message{
                         from: Address::Client,
                         to: Address::Local,
@@ -145,78 +187,80 @@ impl Server {
         Ok(())
     }
 
-    /// Sends outbound messages to peers via TCP.
+    /// Sends outbound messages to peers via TCP, routing them by peer ID.
     async fn tcp_send(
         node_id: String,
         peers: HashMap<String, String>,
         mut out_rx: mpsc::UnboundedReceiver<Message>,
     ) -> Result<()> {
-        let mut peer_txs: HashMap<String, mpsc::Sender<Message>> = HashMap::new();
-
-        for (id, addr) in peers.into_iter() {
-            let (tx, rx) = mpsc::channel::<Message>(1000);
-            peer_txs.insert(id, tx);
-            tokio::spawn(Self::tcp_send_peer(addr, rx));
-        }
+        let mut peers = Peers::new(TcpTransport, peers);
 
         while let Some(mut message) = out_rx.next().await {
             if message.from == Address::Local {
                 message.from = Address::Peer(node_id.clone())
             }
             let to = match &message.to {
-                Address::Peers => peer_txs.keys().cloned().collect(),
+                Address::Peers => peers.ids(),
                 Address::Peer(peer) => vec![peer.to_string()],
                 addr => {
                     error!("Received outbound message for non-TCP address {:?}", addr);
                     continue;
                 }
             };
+            // Raft tolerates lost messages, e.g. by retrying on the next heartbeat, so send
+            // failures are logged rather than propagated.
             for id in to {
-                match peer_txs.get_mut(&id) {
-                    Some(tx) => match tx.try_send(message.clone()) {
-                        Ok(()) => {}
-                        Err(mpsc::error::TrySendError::Full(_)) => {
-                            debug!("Full send buffer for peer {}, discarding message", id)
-                        }
-                        Err(error) => return Err(error.into()),
-                    },
-                    None => error!("Received outbound message for unknown peer {}", id),
+                match peers.send(&id, message.clone()) {
+                    Ok(()) => {}
+                    Err(Error::Abort) => debug!("Failed sending to Raft peer {}, discarding", id),
+                    Err(err) => error!("Failed sending to Raft peer {}: {}", id, err),
                 }
             }
         }
         Ok(())
     }
+}
 
-    /// Sends outbound messages to a peer, continuously reconnecting.
-    async fn tcp_send_peer(addr: String, mut out_rx: mpsc::Receiver<Message>) {
-        loop {
-            match TcpStream::connect(&addr).await {
-                Ok(socket) => {
-                    debug!("Connected to Raft peer {}", addr);
-                    match Self::tcp_send_peer_session(socket, &mut out_rx).await {
-                        Ok(()) => break,
-                        Err(err) => error!("Failed sending to Raft peer {}: {}", addr, err),
-                    }
-                }
-                Err(err) => error!("Failed connecting to Raft peer {}: {}", addr, err),
-            }
-            tokio::time::delay_for(Duration::from_millis(1000)).await;
-        }
-        debug!("Disconnected from Raft peer {}", addr);
-    }
+#[cfg(test)]
+mod tests {
+    use super::super::{Client, Request};
+    use super::*;
+    use crate::raft::state::tests::TestState;
+    use crate::storage::log;
 
-    /// Sends outbound messages to a peer via a TCP session.
-    async fn tcp_send_peer_session(
-        socket: TcpStream,
-        out_rx: &mut mpsc::Receiver<Message>,
-    ) -> Result<()> {
-        let mut stream = tokio_serde::SymmetricallyFramed::<_, Message, _>::new(
-            Framed::new(socket, LengthDelimitedCodec::new()),
-            tokio_serde::formats::SymmetricalBincode::<Message>::default(),
-        );
-        while let Some(message) = out_rx.next().await {
-            stream.send(message).await?;
-        }
+    #[tokio::test(core_threads = 2)]
+    // Requests to a leaderless cluster respond with Error::Timeout at their deadline, rather than
+    // hanging until a leader appears. Requests without a deadline remain pending.
+    async fn eventloop_timeout_leaderless() -> Result<()> {
+        let server = Server::new(
+            "a",
+            vec![("b".to_string(), "b:9705".to_string())].into_iter().collect(),
+            Log::new(Box::new(log::Test::new()))?,
+            Box::new(TestState::new(0)),
+        )
+        .await?;
+        let (client_tx, client_rx) = mpsc::unbounded_channel();
+        let (_tcp_in_tx, tcp_in_rx) = mpsc::unbounded_channel();
+        let (tcp_out_tx, _tcp_out_rx) = mpsc::unbounded_channel();
+        let (task, _eventloop) =
+            Server::eventloop(server.node, server.node_rx, client_rx, tcp_in_rx, tcp_out_tx)
+                .remote_handle();
+        tokio::spawn(task);
+
+        let mut client = Client::new(client_tx.clone());
+        client.set_deadline(Some(Instant::now() + Duration::from_millis(300)));
+        let start = Instant::now();
+        assert_eq!(client.mutate(vec![0x01]).await, Err(Error::Timeout));
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        // An expired deadline times out immediately.
+        assert_eq!(client.query(vec![0x02]).await, Err(Error::Timeout));
+
+        let (response_tx, mut response_rx) = oneshot::channel();
+        client_tx.send((Request::Mutate(vec![0x03]), None, response_tx))?;
+        tokio::time::delay_for(Duration::from_millis(500)).await;
+        assert!(response_rx.try_recv().is_err());
         Ok(())
     }
 }
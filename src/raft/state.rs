@@ -15,6 +15,21 @@ pub trait State: Send {
     /// halts. For any other error, the state is applied and the error propagated to the caller.
     fn mutate(&mut self, index: u64, command: Vec<u8>) -> Result<Vec<u8>>;
 
+    /// Mutates the state machine with a batch of commands and their log indexes, returning their
+    /// results in order. If any command returns Error::Internal, it is returned and the Raft node
+    /// halts. State machines should apply batches atomically, such that none of the batch is
+    /// applied in that case and the entire batch is replayed on restart. By default, the commands
+    /// are mutated one by one, which is only atomic for single-command batches.
+    fn mutate_batch(&mut self, commands: Vec<(u64, Vec<u8>)>) -> Result<Vec<Result<Vec<u8>>>> {
+        commands
+            .into_iter()
+            .map(|(index, command)| match self.mutate(index, command) {
+                Err(error @ Error::Internal(_)) => Err(error),
+                result => Ok(result),
+            })
+            .collect()
+    }
+
     /// Queries the state machine. All errors are propagated to the caller.
     fn query(&self, command: Vec<u8>) -> Result<Vec<u8>>;
 }
@@ -24,8 +39,8 @@ pub trait State: Send {
 pub enum Instruction {
     /// Abort all pending operations, e.g. due to leader change.
     Abort,
-    /// Apply a log entry.
-    Apply { entry: Entry },
+    /// Apply a batch of consecutive log entries.
+    Apply { entries: Vec<Entry> },
     /// Notify the given address with the result of applying the entry at the given index.
     Notify { id: Vec<u8>, address: Address, index: u64 },
     /// Query the state machine when the given term and index has been confirmed by vote.
@@ -108,17 +123,26 @@ impl Driver {
                 self.query_abort()?;
             }
 
-            Instruction::Apply { entry: Entry { index, command, .. } } => {
-                if let Some(command) = command {
-                    debug!("Applying state machine command {}: {:?}", index, command);
-                    match tokio::task::block_in_place(|| state.mutate(index, command)) {
-                        Err(error @ Error::Internal(_)) => return Err(error),
-                        result => self.notify_applied(index, result)?,
-                    };
+            Instruction::Apply { entries } => {
+                let last_index = match entries.last() {
+                    Some(entry) => entry.index,
+                    None => return Ok(()),
+                };
+                let commands: Vec<(u64, Vec<u8>)> = entries
+                    .into_iter()
+                    .filter_map(|Entry { index, command, .. }| {
+                        command.map(|command| (index, command))
+                    })
+                    .collect();
+                debug!("Applying {} state machine commands up to {}", commands.len(), last_index);
+                let indexes: Vec<u64> = commands.iter().map(|(index, _)| *index).collect();
+                let results = tokio::task::block_in_place(|| state.mutate_batch(commands))?;
+                for (index, result) in indexes.into_iter().zip(results) {
+                    self.notify_applied(index, result)?;
                 }
                 // We have to track applied_index here, separately from the state machine, because
                 // no-op log entries are significant for whether a query should be executed.
-                self.applied_index = index;
+                self.applied_index = last_index;
                 // Try to execute any pending queries, since they may have been submitted for a
                 // commit_index which hadn't been applied yet.
                 self.query_execute(state)?;
@@ -354,9 +378,11 @@ pub mod tests {
             index: 2,
             address: Address::Client,
         })?;
-        state_tx.send(Instruction::Apply { entry: Entry { index: 1, term: 1, command: None } })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 2, term: 1, command: Some(vec![0xaf]) },
+            entries: vec![
+                Entry { index: 1, term: 1, command: None },
+                Entry { index: 2, term: 1, command: Some(vec![0xaf]) },
+            ],
         })?;
         std::mem::drop(state_tx);
         assert_eq!(
@@ -390,7 +416,7 @@ pub mod tests {
             quorum: 2,
         })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 1, term: 2, command: Some(vec![0xaf]) },
+            entries: vec![Entry { index: 1, term: 2, command: Some(vec![0xaf]) }],
         })?;
         state_tx.send(Instruction::Vote { term: 2, index: 1, address: Address::Local })?;
         state_tx.send(Instruction::Vote {
@@ -430,7 +456,7 @@ pub mod tests {
             quorum: 2,
         })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 1, term: 1, command: Some(vec![0xaf]) },
+            entries: vec![Entry { index: 1, term: 1, command: Some(vec![0xaf]) }],
         })?;
         state_tx.send(Instruction::Vote { term: 2, index: 1, address: Address::Local })?;
         state_tx.send(Instruction::Vote {
@@ -457,7 +483,7 @@ pub mod tests {
             quorum: 2,
         })?;
         state_tx.send(Instruction::Apply {
-            entry: Entry { index: 1, term: 1, command: Some(vec![0xaf]) },
+            entries: vec![Entry { index: 1, term: 1, command: Some(vec![0xaf]) }],
         })?;
         state_tx.send(Instruction::Vote { term: 1, index: 1, address: Address::Local })?;
         std::mem::drop(state_tx);
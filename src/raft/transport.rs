@@ -0,0 +1,268 @@
+use super::Message;
+use crate::error::{Error, Result};
+
+use ::log::{debug, error};
+use futures::sink::SinkExt as _;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::stream::StreamExt as _;
+use tokio::sync::mpsc;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+/// The number of outbound messages buffered per peer, e.g. while reconnecting.
+const PEER_BUFFER_SIZE: usize = 1000;
+
+/// The delay between attempts to reconnect to a peer.
+const RECONNECT_DELAY: Duration = Duration::from_millis(1000);
+
+/// A network transport for outbound peer messages.
+pub trait Transport: Send {
+    /// Connects to a peer at the given network address, returning a channel for outbound
+    /// messages. The transport is responsible for reconnecting when the connection is lost, and
+    /// the connection is closed when the channel is dropped.
+    fn connect(&mut self, addr: &str) -> mpsc::Sender<Message>;
+}
+
+/// A TCP transport, which sends messages as length-delimited Bincode frames.
+pub struct TcpTransport;
+
+impl Transport for TcpTransport {
+    fn connect(&mut self, addr: &str) -> mpsc::Sender<Message> {
+        let (tx, rx) = mpsc::channel(PEER_BUFFER_SIZE);
+        tokio::spawn(Self::send_peer(addr.to_string(), rx));
+        tx
+    }
+}
+
+impl TcpTransport {
+    /// Sends outbound messages to a peer, continuously reconnecting.
+    async fn send_peer(addr: String, mut out_rx: mpsc::Receiver<Message>) {
+        loop {
+            match TcpStream::connect(&addr).await {
+                Ok(socket) => {
+                    debug!("Connected to Raft peer {}", addr);
+                    match Self::send_peer_session(socket, &mut out_rx).await {
+                        Ok(()) => break,
+                        Err(err) => error!("Failed sending to Raft peer {}: {}", addr, err),
+                    }
+                }
+                Err(err) => error!("Failed connecting to Raft peer {}: {}", addr, err),
+            }
+            tokio::time::delay_for(RECONNECT_DELAY).await;
+        }
+        debug!("Disconnected from Raft peer {}", addr);
+    }
+
+    /// Sends outbound messages to a peer via a TCP session.
+    async fn send_peer_session(
+        socket: TcpStream,
+        out_rx: &mut mpsc::Receiver<Message>,
+    ) -> Result<()> {
+        let mut stream = tokio_serde::SymmetricallyFramed::<_, Message, _>::new(
+            Framed::new(socket, LengthDelimitedCodec::new()),
+            tokio_serde::formats::SymmetricalBincode::<Message>::default(),
+        );
+        while let Some(message) = out_rx.next().await {
+            stream.send(message).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A connected peer.
+struct Peer {
+    addr: String,
+    tx: mpsc::Sender<Message>,
+}
+
+/// A peer registry, which maps node IDs to network addresses and routes outbound messages to
+/// them via a transport. Membership changes must be applied to the registry with add() and
+/// remove().
+pub struct Peers<T: Transport> {
+    transport: T,
+    peers: HashMap<String, Peer>,
+}
+
+impl<T: Transport> Peers<T> {
+    /// Creates a new peer registry, connecting to the given peers (by ID and address).
+    pub fn new(transport: T, peers: HashMap<String, String>) -> Self {
+        let mut registry = Self { transport, peers: HashMap::new() };
+        for (id, addr) in peers {
+            registry.add(&id, &addr);
+        }
+        registry
+    }
+
+    /// Adds a peer, or changes its address. The peer is reconnected if the address changed.
+    pub fn add(&mut self, id: &str, addr: &str) {
+        if self.peers.get(id).map(|peer| peer.addr == addr).unwrap_or(false) {
+            return;
+        }
+        let tx = self.transport.connect(addr);
+        self.peers.insert(id.to_string(), Peer { addr: addr.to_string(), tx });
+    }
+
+    /// Removes a peer, disconnecting it. Returns whether it existed.
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.peers.remove(id).is_some()
+    }
+
+    /// Returns the network address of a peer, if it exists.
+    pub fn addr(&self, id: &str) -> Option<&str> {
+        self.peers.get(id).map(|peer| peer.addr.as_str())
+    }
+
+    /// Returns the IDs of all peers.
+    pub fn ids(&self) -> Vec<String> {
+        self.peers.keys().cloned().collect()
+    }
+
+    /// Sends a message to a peer. Errors with Error::Abort if the message can't be sent right
+    /// now, e.g. because the peer's send buffer is full while it's unreachable, in which case the
+    /// send can be retried later.
+    pub fn send(&mut self, id: &str, message: Message) -> Result<()> {
+        let peer = self
+            .peers
+            .get_mut(id)
+            .ok_or_else(|| Error::Internal(format!("Unknown Raft peer {}", id)))?;
+        match peer.tx.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                debug!("Full send buffer for Raft peer {}", id);
+                Err(Error::Abort)
+            }
+            // The connection was closed, so we reconnect for later sends.
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                debug!("Connection to Raft peer {} closed, reconnecting", id);
+                peer.tx = self.transport.connect(&peer.addr);
+                Err(Error::Abort)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Address, Event};
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::sync::{Arc, Mutex};
+
+    /// A mock transport, which buffers messages in channels with the given capacity, and
+    /// exposes the receivers by address.
+    #[derive(Clone)]
+    struct MockTransport {
+        capacity: usize,
+        endpoints: Arc<Mutex<HashMap<String, mpsc::Receiver<Message>>>>,
+    }
+
+    impl Transport for MockTransport {
+        fn connect(&mut self, addr: &str) -> mpsc::Sender<Message> {
+            let (tx, rx) = mpsc::channel(self.capacity);
+            self.endpoints.lock().unwrap().insert(addr.to_string(), rx);
+            tx
+        }
+    }
+
+    impl MockTransport {
+        fn new(capacity: usize) -> Self {
+            Self { capacity, endpoints: Arc::new(Mutex::new(HashMap::new())) }
+        }
+
+        /// Receives the buffered messages for an address.
+        fn receive(&self, addr: &str) -> Vec<Message> {
+            let mut endpoints = self.endpoints.lock().unwrap();
+            let rx = endpoints.get_mut(addr).unwrap();
+            std::iter::from_fn(|| rx.try_recv().ok()).collect()
+        }
+
+        /// Disconnects an address, closing its channel.
+        fn disconnect(&self, addr: &str) {
+            self.endpoints.lock().unwrap().remove(addr);
+        }
+    }
+
+    fn message(term: u64) -> Message {
+        Message {
+            from: Address::Peer("a".into()),
+            to: Address::Peers,
+            term,
+            event: Event::Heartbeat { commit_index: 0, commit_term: 0 },
+        }
+    }
+
+    fn setup(capacity: usize) -> (MockTransport, Peers<MockTransport>) {
+        let transport = MockTransport::new(capacity);
+        let peers = Peers::new(
+            transport.clone(),
+            vec![("b".into(), "10.0.0.2:9705".into()), ("c".into(), "10.0.0.3:9705".into())]
+                .into_iter()
+                .collect(),
+        );
+        (transport, peers)
+    }
+
+    #[test]
+    fn send() -> Result<()> {
+        let (transport, mut peers) = setup(10);
+        let mut ids = peers.ids();
+        ids.sort();
+        assert_eq!(ids, vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(peers.addr("b"), Some("10.0.0.2:9705"));
+        assert_eq!(peers.addr("x"), None);
+
+        peers.send("b", message(1))?;
+        peers.send("c", message(2))?;
+        peers.send("b", message(3))?;
+        assert_eq!(transport.receive("10.0.0.2:9705"), vec![message(1), message(3)]);
+        assert_eq!(transport.receive("10.0.0.3:9705"), vec![message(2)]);
+
+        assert_eq!(peers.send("x", message(4)), Err(Error::Internal("Unknown Raft peer x".into())));
+        Ok(())
+    }
+
+    #[test]
+    fn send_full() -> Result<()> {
+        let (transport, mut peers) = setup(1);
+        peers.send("b", message(1))?;
+        assert_eq!(peers.send("b", message(2)), Err(Error::Abort));
+        assert_eq!(transport.receive("10.0.0.2:9705"), vec![message(1)]);
+
+        // Once the buffer drains, the send can be retried.
+        peers.send("b", message(2))?;
+        assert_eq!(transport.receive("10.0.0.2:9705"), vec![message(2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn send_reconnect() -> Result<()> {
+        let (transport, mut peers) = setup(10);
+        transport.disconnect("10.0.0.2:9705");
+        assert_eq!(peers.send("b", message(1)), Err(Error::Abort));
+        peers.send("b", message(2))?;
+        assert_eq!(transport.receive("10.0.0.2:9705"), vec![message(2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn membership() -> Result<()> {
+        let (transport, mut peers) = setup(10);
+
+        // Changing a peer's address routes messages to the new address.
+        peers.add("b", "10.0.0.4:9705");
+        assert_eq!(peers.addr("b"), Some("10.0.0.4:9705"));
+        peers.send("b", message(1))?;
+        assert_eq!(transport.receive("10.0.0.4:9705"), vec![message(1)]);
+        assert_eq!(transport.receive("10.0.0.2:9705"), vec![]);
+
+        // Adding a new peer and removing an existing one.
+        peers.add("d", "10.0.0.5:9705");
+        peers.send("d", message(2))?;
+        assert_eq!(transport.receive("10.0.0.5:9705"), vec![message(2)]);
+        assert!(peers.remove("c"));
+        assert!(!peers.remove("c"));
+        assert_eq!(peers.send("c", message(3)), Err(Error::Internal("Unknown Raft peer c".into())));
+        Ok(())
+    }
+}
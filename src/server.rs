@@ -1,49 +1,144 @@
 use crate::error::{Error, Result};
 use crate::raft;
 use crate::sql;
-use crate::sql::engine::{Engine as _, Mode};
-use crate::sql::execution::ResultSet;
-use crate::sql::schema::{Catalog as _, Table};
-use crate::sql::types::Row;
+use crate::sql::engine::{Engine as _, Mode, VacuumScheduler};
+use crate::sql::execution::{CancelToken, ResultSet};
+use crate::sql::schema::{Catalog as _, Privilege, Table, View};
+use crate::sql::types::{Columns, Row, Rows, Value};
 use crate::storage::{kv, log};
 
 use ::log::{error, info};
 use futures::sink::SinkExt as _;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::stream::StreamExt as _;
 use tokio::sync::mpsc;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
+/// The cancellation tokens of connected client sessions, keyed by session ID.
+type Sessions = Arc<Mutex<HashMap<u64, CancelToken>>>;
+
 /// A toyDB server.
 pub struct Server {
     raft: raft::Server,
+    watchers: sql::engine::Watchers,
     raft_listener: Option<TcpListener>,
     sql_listener: Option<TcpListener>,
+    statement_timeout: Option<Duration>,
+    max_aggregate_values: Option<usize>,
+    max_recursive_iterations: Option<u64>,
+    max_sort_memory: Option<usize>,
+    max_statement_memory: Option<usize>,
+    vacuum_interval: Option<Duration>,
+    superuser_password: Option<String>,
 }
 
 impl Server {
-    /// Creates a new toyDB server.
+    /// Creates a new toyDB server. String and binary values are limited to max_value_size bytes,
+    /// and values written to the SQL key/value store (e.g. encoded rows) to max_stored_value_size
+    /// bytes if given. These must be the same across the cluster. Up to sql_cache recently read
+    /// SQL keys are cached in memory, see kv::MVCC::with_cache().
     pub async fn new(
         id: &str,
         peers: HashMap<String, String>,
         raft_store: Box<dyn log::Store>,
         sql_store: Box<dyn kv::Store>,
+        sql_cache: usize,
+        max_value_size: usize,
+        max_stored_value_size: Option<usize>,
     ) -> Result<Self> {
+        let state = sql::engine::Raft::new_state(
+            kv::MVCC::new(sql_store)
+                .with_max_value_size(max_stored_value_size)
+                .with_cache(sql_cache),
+            max_value_size,
+        )?;
+        let watchers = state.watchers();
         Ok(Server {
-            raft: raft::Server::new(
-                id,
-                peers,
-                raft::Log::new(raft_store)?,
-                Box::new(sql::engine::Raft::new_state(kv::MVCC::new(sql_store))?),
-            )
-            .await?,
+            raft: raft::Server::new(id, peers, raft::Log::new(raft_store)?, Box::new(state))
+                .await?,
+            watchers,
             raft_listener: None,
             sql_listener: None,
+            statement_timeout: None,
+            max_aggregate_values: None,
+            max_recursive_iterations: Some(sql::engine::MAX_RECURSIVE_ITERATIONS),
+            max_sort_memory: None,
+            max_statement_memory: None,
+            vacuum_interval: None,
+            superuser_password: None,
         })
     }
 
+    /// Sets a timeout for SQL statements executed by clients, see sql::engine::Session.
+    pub fn with_statement_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.statement_timeout = timeout;
+        self
+    }
+
+    /// Limits the number of values aggregates can buffer for a single SQL statement.
+    pub fn with_max_aggregate_values(mut self, max_aggregate_values: Option<usize>) -> Self {
+        self.max_aggregate_values = max_aggregate_values;
+        self
+    }
+
+    /// Limits the number of iterations of each recursive CTE in a single SQL statement.
+    pub fn with_max_recursive_iterations(mut self, max_recursive_iterations: Option<u64>) -> Self {
+        self.max_recursive_iterations = max_recursive_iterations;
+        self
+    }
+
+    /// Limits the memory each sort in a single SQL statement can buffer before spilling to disk.
+    pub fn with_max_sort_memory(mut self, max_sort_memory: Option<usize>) -> Self {
+        self.max_sort_memory = max_sort_memory;
+        self
+    }
+
+    /// Limits the memory a single SQL statement can buffer in hash joins, aggregates, sorts, and
+    /// DISTINCT.
+    pub fn with_max_statement_memory(mut self, max_statement_memory: Option<usize>) -> Self {
+        self.max_statement_memory = max_statement_memory;
+        self
+    }
+
+    /// Limits the number of unacknowledged Raft log entries the leader sends to each follower.
+    pub fn with_max_inflight_entries(mut self, max_inflight_entries: Option<u64>) -> Self {
+        self.raft = self.raft.with_max_inflight(max_inflight_entries);
+        self
+    }
+
+    /// Limits the size of Raft log entries (i.e. encoded SQL mutations), in bytes. Larger
+    /// mutations error before they are appended to the log.
+    pub fn with_max_entry_size(mut self, max_entry_size: Option<usize>) -> Self {
+        self.raft = self.raft.with_max_entry_size(max_entry_size);
+        self
+    }
+
+    /// Limits the number of committed Raft log entries applied to the SQL storage engine in a
+    /// single atomic write batch.
+    pub fn with_max_apply_batch(mut self, max_apply_batch: Option<usize>) -> Self {
+        self.raft = self.raft.with_max_apply_batch(max_apply_batch);
+        self
+    }
+
+    /// Vacuums the SQL storage engine in the background at the given interval while this node is
+    /// the leader, see sql::engine::VacuumScheduler.
+    pub fn with_vacuum_interval(mut self, interval: Option<Duration>) -> Self {
+        self.vacuum_interval = interval;
+        self
+    }
+
+    /// Sets the password that clients must give to authenticate as the built-in superuser, see
+    /// Request::Authenticate. Client sessions otherwise execute statements as the unprivileged
+    /// sql::engine::ANONYMOUS_USER, and if None they can never become superusers.
+    pub fn with_superuser_password(mut self, password: Option<String>) -> Self {
+        self.superuser_password = password;
+        self
+    }
+
     /// Starts listening on the given ports. Must be called before serve.
     pub async fn listen(mut self, sql_addr: &str, raft_addr: &str) -> Result<Self> {
         let (sql, raft) =
@@ -64,21 +159,66 @@ impl Server {
             .ok_or_else(|| Error::Internal("Must listen before serving".into()))?;
         let (raft_tx, raft_rx) = mpsc::unbounded_channel();
         let sql_engine = sql::engine::Raft::new(raft::Client::new(raft_tx));
+        let _vacuum = match self.vacuum_interval {
+            Some(interval) => Some(VacuumScheduler::start(sql_engine.clone(), interval)?),
+            None => None,
+        };
 
         tokio::try_join!(
             self.raft.serve(raft_listener, raft_rx),
-            Self::serve_sql(sql_listener, sql_engine),
+            Self::serve_sql(
+                sql_listener,
+                sql_engine,
+                self.watchers,
+                self.statement_timeout,
+                self.max_aggregate_values,
+                self.max_recursive_iterations,
+                self.max_sort_memory,
+                self.max_statement_memory,
+                self.superuser_password,
+            ),
         )?;
         Ok(())
     }
 
     /// Serves SQL clients.
-    async fn serve_sql(mut listener: TcpListener, engine: sql::engine::Raft) -> Result<()> {
+    #[allow(clippy::too_many_arguments)]
+    async fn serve_sql(
+        mut listener: TcpListener,
+        engine: sql::engine::Raft,
+        watchers: sql::engine::Watchers,
+        statement_timeout: Option<Duration>,
+        max_aggregate_values: Option<usize>,
+        max_recursive_iterations: Option<u64>,
+        max_sort_memory: Option<usize>,
+        max_statement_memory: Option<usize>,
+        superuser_password: Option<String>,
+    ) -> Result<()> {
+        let sessions = Sessions::default();
+        let superuser_password = Arc::new(superuser_password);
+        let mut next_session_id = 1;
         while let Some(socket) = listener.try_next().await? {
             let peer = socket.peer_addr()?;
-            let session = Session::new(engine.clone())?;
+            let mut sql = engine
+                .session()?
+                .with_timeout(statement_timeout)
+                .with_max_aggregate_values(max_aggregate_values)
+                .with_max_recursive_iterations(max_recursive_iterations)
+                .with_max_sort_memory(max_sort_memory)
+                .with_max_statement_memory(max_statement_memory);
+            sql.set_user(Some(sql::engine::ANONYMOUS_USER))?;
+            let id = next_session_id;
+            next_session_id += 1;
+            let session = Session::new(
+                id,
+                engine.clone(),
+                watchers.clone(),
+                sessions.clone(),
+                superuser_password.clone(),
+                sql,
+            )?;
             tokio::spawn(async move {
-                info!("Client {} connected", peer);
+                info!("Client {} connected as session {}", peer, id);
                 match session.handle(socket).await {
                     Ok(()) => info!("Client {} disconnected", peer),
                     Err(err) => error!("Client {} error: {}", peer, err),
@@ -93,31 +233,85 @@ impl Server {
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Request {
     Execute(String),
+    Prepare(String),
+    ExecutePrepared(u64, Vec<Value>),
+    Validate(String),
     GetTable(String),
     ListTables,
+    GetView(String),
+    ListViews,
     Status,
+    /// Watches a table for committed row changes, which are streamed as Change responses after
+    /// the initial Watch response until the client disconnects. No other requests can be made on
+    /// the connection.
+    Watch(String),
+    ClusterHealth,
+    /// Exports the raw key/value pairs with the given key prefix (all pairs if empty), which are
+    /// streamed as KeyValue responses after the initial Export response.
+    Export(Vec<u8>),
+    /// Imports raw key/value pairs in a single transaction, overwriting existing keys.
+    Import(Vec<(Vec<u8>, Vec<u8>)>),
+    /// Returns the ID of the connection's session, which other connections can use to cancel its
+    /// statements.
+    SessionId,
+    /// Cancels the currently executing statement of the session with the given ID, if any. The
+    /// statement errors, and its transaction is rolled back.
+    Cancel(u64),
+    /// Sets the user of the connection's session, or the built-in superuser if None, see
+    /// sql::engine::Session::set_user(). Requires a superuser.
+    SetUser(Option<String>),
+    /// Authenticates the connection's session as the built-in superuser with the server's
+    /// superuser password, see Server::with_superuser_password(). Sessions start as the
+    /// unprivileged sql::engine::ANONYMOUS_USER.
+    Authenticate(String),
 }
 
 /// A server response.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     Execute(ResultSet),
+    Prepare(u64),
+    Validate(Columns),
     Row(Option<Row>),
     GetTable(Table),
     ListTables(Vec<String>),
+    GetView(View),
+    ListViews(Vec<String>),
     Status(sql::engine::Status),
+    Watch,
+    Change(sql::engine::Change),
+    ClusterHealth(raft::ClusterHealth),
+    Export,
+    KeyValue(Option<(Vec<u8>, Vec<u8>)>),
+    Import(u64),
+    SessionId(u64),
+    Cancel,
+    SetUser,
+    Authenticate,
 }
 
 /// A client session coupled to a SQL session.
 pub struct Session {
+    id: u64,
     engine: sql::engine::Raft,
+    watchers: sql::engine::Watchers,
+    sessions: Sessions,
+    superuser_password: Arc<Option<String>>,
     sql: sql::engine::Session<sql::engine::Raft>,
 }
 
 impl Session {
-    /// Creates a new client session.
-    fn new(engine: sql::engine::Raft) -> Result<Self> {
-        Ok(Self { sql: engine.session()?, engine })
+    /// Creates a new client session, registering it in sessions until it's dropped.
+    fn new(
+        id: u64,
+        engine: sql::engine::Raft,
+        watchers: sql::engine::Watchers,
+        sessions: Sessions,
+        superuser_password: Arc<Option<String>>,
+        sql: sql::engine::Session<sql::engine::Raft>,
+    ) -> Result<Self> {
+        sessions.lock()?.insert(id, sql.cancel_token());
+        Ok(Self { id, sql, engine, watchers, sessions, superuser_password })
     }
 
     /// Handles a client connection.
@@ -127,37 +321,117 @@ impl Session {
             tokio_serde::formats::Bincode::default(),
         );
         while let Some(request) = stream.try_next().await? {
-            let mut response = tokio::task::block_in_place(|| self.request(request));
+            if let Request::Watch(table) = request {
+                return self.watch(stream, table).await;
+            }
             let mut rows: Box<dyn Iterator<Item = Result<Response>> + Send> =
                 Box::new(std::iter::empty());
-            if let Ok(Response::Execute(ResultSet::Query { rows: ref mut resultrows, .. })) =
-                &mut response
-            {
-                rows = Box::new(
-                    std::mem::replace(resultrows, Box::new(std::iter::empty()))
-                        .map(|result| result.map(|row| Response::Row(Some(row))))
-                        .chain(std::iter::once(Ok(Response::Row(None))))
-                        .scan(false, |err_sent, response| match (&err_sent, &response) {
-                            (true, _) => None,
-                            (_, Err(error)) => {
-                                *err_sent = true;
-                                Some(Err(error.clone()))
-                            }
-                            _ => Some(response),
-                        })
-                        .fuse(),
-                );
-            }
+            let response = match request {
+                Request::Export(prefix) => tokio::task::block_in_place(|| {
+                    self.sql.authorize_superuser("export data")?;
+                    self.engine.export(&prefix)
+                })
+                .map(|pairs| {
+                    rows = Self::stream(
+                        pairs.map(|result| result.map(|pair| Response::KeyValue(Some(pair)))),
+                        Response::KeyValue(None),
+                    );
+                    Response::Export
+                }),
+                request => {
+                    let mut response = tokio::task::block_in_place(|| self.request(request));
+                    if let Ok(Response::Execute(ResultSet::Query {
+                        rows: ref mut resultrows,
+                        ..
+                    })) = &mut response
+                    {
+                        rows = Self::stream(
+                            std::mem::replace(resultrows, Rows::new(std::iter::empty()))
+                                .map(|result| result.map(|row| Response::Row(Some(row)))),
+                            Response::Row(None),
+                        );
+                    }
+                    response
+                }
+            };
             stream.send(response).await?;
-            stream.send_all(&mut tokio::stream::iter(rows.map(Ok))).await?;
+            // Rows are fetched lazily from the Raft state machine, which blocks, as does rolling
+            // back the implicit transaction when the rows are dropped.
+            let result = stream
+                .send_all(&mut tokio::stream::iter(
+                    std::iter::from_fn(|| tokio::task::block_in_place(|| rows.next())).map(Ok),
+                ))
+                .await;
+            tokio::task::block_in_place(|| std::mem::drop(rows));
+            result?;
         }
         Ok(())
     }
 
+    /// Streams the given responses followed by the end response, stopping after the first error.
+    fn stream<I>(responses: I, end: Response) -> Box<dyn Iterator<Item = Result<Response>> + Send>
+    where
+        I: Iterator<Item = Result<Response>> + Send + 'static,
+    {
+        Box::new(
+            responses
+                .chain(std::iter::once(Ok(end)))
+                .scan(false, |err_sent, response| match (&err_sent, &response) {
+                    (true, _) => None,
+                    (_, Err(error)) => {
+                        *err_sent = true;
+                        Some(Err(error.clone()))
+                    }
+                    _ => Some(response),
+                })
+                .fuse(),
+        )
+    }
+
+    /// Watches a table, streaming its committed row changes to the client until it disconnects.
+    async fn watch<S>(&mut self, mut stream: S, table: String) -> Result<()>
+    where
+        S: futures::Sink<Result<Response>, Error = std::io::Error>
+            + futures::Stream<Item = std::io::Result<Request>>
+            + Unpin,
+    {
+        let result = tokio::task::block_in_place(|| {
+            self.sql.with_txn(Mode::ReadOnly, |txn| txn.must_read_table(&table))?;
+            self.sql.authorize(&table, Privilege::Select)?;
+            self.watchers.watch(&table)
+        });
+        let mut changes = match result {
+            Ok(changes) => changes,
+            Err(err) => {
+                stream.send(Err(err)).await?;
+                return Ok(());
+            }
+        };
+        stream.send(Ok(Response::Watch)).await?;
+        loop {
+            tokio::select! {
+                Some(change) = changes.recv() => stream.send(Ok(Response::Change(change))).await?,
+                request = stream.next() => match request {
+                    Some(_) => {
+                        let err = Error::Value("Can't make requests while watching".into());
+                        stream.send(Err(err)).await?;
+                        return Ok(());
+                    }
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
     /// Executes a request.
     pub fn request(&mut self, request: Request) -> Result<Response> {
         Ok(match request {
             Request::Execute(query) => Response::Execute(self.sql.execute(&query)?),
+            Request::Prepare(query) => Response::Prepare(self.sql.prepare(&query)?),
+            Request::ExecutePrepared(id, params) => {
+                Response::Execute(self.sql.execute_prepared(id, params)?)
+            }
+            Request::Validate(query) => Response::Validate(self.sql.validate(&query)?),
             Request::GetTable(table) => Response::GetTable(
                 self.sql.with_txn(Mode::ReadOnly, |txn| txn.must_read_table(&table))?,
             ),
@@ -166,13 +440,58 @@ impl Session {
                     Ok(txn.scan_tables()?.map(|t| t.name).collect())
                 })?)
             }
+            Request::GetView(view) => Response::GetView(
+                self.sql.with_txn(Mode::ReadOnly, |txn| txn.must_read_view(&view))?,
+            ),
+            Request::ListViews => {
+                Response::ListViews(self.sql.with_txn(Mode::ReadOnly, |txn| {
+                    Ok(txn.scan_views()?.map(|v| v.name).collect())
+                })?)
+            }
             Request::Status => Response::Status(self.engine.status()?),
+            Request::ClusterHealth => Response::ClusterHealth(self.engine.cluster_health()?),
+            Request::Import(pairs) => {
+                self.sql.authorize_superuser("import data")?;
+                Response::Import(self.engine.import(pairs)?)
+            }
+            Request::SessionId => Response::SessionId(self.id),
+            Request::Cancel(id) => {
+                self.sessions
+                    .lock()?
+                    .get(&id)
+                    .ok_or_else(|| Error::Value(format!("Session {} does not exist", id)))?
+                    .cancel();
+                Response::Cancel
+            }
+            Request::SetUser(user) => {
+                self.sql.set_user(user.as_deref())?;
+                Response::SetUser
+            }
+            Request::Authenticate(password) => match self.superuser_password.as_ref() {
+                Some(expect) if *expect == password => {
+                    self.sql.set_superuser()?;
+                    Response::Authenticate
+                }
+                Some(_) => return Err(Error::Unauthorized("Invalid superuser password".into())),
+                None => {
+                    return Err(Error::Unauthorized("Superuser authentication is disabled".into()))
+                }
+            },
+            Request::Watch(_) => {
+                return Err(Error::Internal("Watch requests are handled by the session".into()))
+            }
+            Request::Export(_) => {
+                return Err(Error::Internal("Export requests are handled by the session".into()))
+            }
         })
     }
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(&self.id);
+        }
         tokio::task::block_in_place(|| self.sql.execute("ROLLBACK").ok());
     }
 }
@@ -1,31 +1,134 @@
-use super::super::schema::{Catalog, Table, Tables};
-use super::super::types::{Expression, Row, Value};
+use super::super::schema::{
+    Catalog, Column, ColumnStats, Table, TableStats, Tables, User, Users, View, Views,
+};
+use super::super::types::{DataType, Date, Decimal, Expression, Row, Time, Timestamp, Value};
 use super::Transaction as _;
 use crate::error::{Error, Result};
 use crate::storage::kv;
 
+use serde::de::{DeserializeSeed, Deserializer, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
+use serde_derive::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::clone::Clone;
 use std::collections::HashSet;
+use std::ops::Bound;
 
-/// A SQL engine based on an underlying MVCC key/value store
+/// The SQL storage format version, covering the key, row, and catalog encodings. This is written
+/// when a store is created and checked when it is opened. It must be incremented whenever these
+/// encodings change incompatibly, along with an upgrade step in KV::upgrade() for older stores.
+///
+/// * 1: initial format.
+/// * 2: rows are tagged with the schema version of their table, see Table::upgrade_row().
+/// * 3: columns have an optional generated expression, see Column::generated.
+pub const VERSION: u64 = 3;
+
+/// The default maximum size of string and binary values, in bytes.
+pub const MAX_VALUE_SIZE: usize = 1024;
+
+/// A SQL engine based on an underlying MVCC key/value store. It applies transactions directly to
+/// the local store, and can be used as an embedded single-node engine without Raft. It's also used
+/// by the Raft engine's state machine on each node.
 pub struct KV {
     /// The underlying key/value store
     pub(super) kv: kv::MVCC,
+    /// The maximum size of string and binary values, in bytes
+    max_value_size: usize,
 }
 
 // FIXME Implement Clone manually due to https://github.com/rust-lang/rust/issues/26925
 impl Clone for KV {
     fn clone(&self) -> Self {
-        KV::new(self.kv.clone())
+        KV { kv: self.kv.clone(), max_value_size: self.max_value_size }
     }
 }
 
 impl KV {
-    /// Creates a new key/value-based SQL engine
-    pub fn new(kv: kv::MVCC) -> Self {
-        Self { kv }
+    /// Creates a new key/value-based SQL engine. Stamps new stores with the current format version,
+    /// and upgrades older stores or rejects stores written by a newer version of toyDB.
+    pub fn new(kv: kv::MVCC) -> Result<Self> {
+        let engine = Self { kv, max_value_size: MAX_VALUE_SIZE };
+        let stored: Option<u64> =
+            engine.get_metadata(b"version")?.map(|v| deserialize(&v)).transpose()?;
+        let mut version = match stored {
+            Some(version) => version,
+            // Stores without any transactions are new, while others predate format versioning
+            // and use version 1.
+            None if engine.kv.status()?.txns == 0 => VERSION,
+            None => 1,
+        };
+        if version > VERSION {
+            return Err(Error::Config(format!(
+                "unsupported store version {}, this version of toyDB supports up to version {}",
+                version, VERSION
+            )));
+        }
+        while version < VERSION {
+            engine.upgrade(version)?;
+            version += 1;
+        }
+        if stored != Some(version) {
+            engine.set_metadata(b"version", serialize(&version)?)?;
+        }
+        Ok(engine)
+    }
+
+    /// Sets the maximum size of string and binary values, in bytes. This is checked when rows are
+    /// written, so it must be the same on all nodes to keep their state machines deterministic.
+    pub fn with_max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = max_value_size;
+        self
+    }
+
+    /// Upgrades the store from the given format version to the next version.
+    fn upgrade(&self, version: u64) -> Result<()> {
+        match version {
+            // Version 2 adds schema versions to tables, and tags rows with them. Existing tables
+            // are at schema version 0.
+            1 => {
+                let mut txn = self.kv.begin()?;
+                let tables =
+                    txn.scan_prefix(&Key::Table(None).encode())?.collect::<Result<Vec<_>>>()?;
+                for (key, value) in tables {
+                    let (name, columns): (String, Vec<ColumnV1>) = deserialize(&value)?;
+                    let table = TableV2 { name, columns, version: 0, layouts: Vec::new() };
+                    let prefix = Key::Row((&table.name).into(), None).encode();
+                    let rows = txn.scan_prefix(&prefix)?.collect::<Result<Vec<_>>>()?;
+                    for (key, value) in rows {
+                        let row: Row = deserialize(&value)?;
+                        txn.set(&key, serialize(&(table.version, row))?)?;
+                    }
+                    txn.set(&key, serialize(&table)?)?;
+                }
+                txn.commit()
+            }
+            // Version 3 adds generated expressions to columns. Existing columns aren't generated.
+            2 => {
+                let mut txn = self.kv.begin()?;
+                let tables =
+                    txn.scan_prefix(&Key::Table(None).encode())?.collect::<Result<Vec<_>>>()?;
+                for (key, value) in tables {
+                    let table: TableV2 = deserialize(&value)?;
+                    let table = Table {
+                        name: table.name,
+                        columns: table.columns.into_iter().map(Column::from).collect(),
+                        version: table.version,
+                        layouts: table.layouts,
+                    };
+                    txn.set(&key, serialize(&table)?)?;
+                }
+                txn.commit()
+            }
+            version => Err(Error::Config(format!(
+                "unsupported store version {}, no upgrade available",
+                version
+            ))),
+        }
+    }
+
+    /// Returns the status of the underlying MVCC store, like Raft::status() without Raft status.
+    pub fn status(&self) -> Result<kv::mvcc::Status> {
+        self.kv.status()
     }
 
     /// Fetches an unversioned metadata value
@@ -37,17 +140,30 @@ impl KV {
     pub fn set_metadata(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
         self.kv.set_metadata(key, value)
     }
+
+    /// Deletes an unversioned metadata value
+    pub fn delete_metadata(&self, key: &[u8]) -> Result<()> {
+        self.kv.delete_metadata(key)
+    }
 }
 
 impl super::Engine for KV {
     type Transaction = Transaction;
 
     fn begin(&self, mode: super::Mode) -> Result<Self::Transaction> {
-        Ok(Self::Transaction::new(self.kv.begin_with_mode(mode)?))
+        Ok(Self::Transaction::new(self.kv.begin_with_mode(mode)?, self.max_value_size))
     }
 
     fn resume(&self, id: u64) -> Result<Self::Transaction> {
-        Ok(Self::Transaction::new(self.kv.resume(id)?))
+        Ok(Self::Transaction::new(self.kv.resume(id)?, self.max_value_size))
+    }
+
+    fn vacuum(&self) -> Result<kv::mvcc::Vacuum> {
+        self.kv.vacuum()
+    }
+
+    fn mvcc_status(&self) -> Result<kv::mvcc::Status> {
+        self.kv.status()
     }
 }
 
@@ -61,15 +177,183 @@ fn deserialize<'a, V: Deserialize<'a>>(bytes: &'a [u8]) -> Result<V> {
     Ok(bincode::deserialize(bytes)?)
 }
 
+/// A table schema as encoded by format version 2, with version 1 columns.
+#[derive(Deserialize, Serialize)]
+struct TableV2 {
+    name: String,
+    columns: Vec<ColumnV1>,
+    version: u64,
+    layouts: Vec<Vec<Option<usize>>>,
+}
+
+/// A column schema as encoded by format versions 1 and 2, i.e. without a generated expression.
+#[derive(Deserialize, Serialize)]
+struct ColumnV1 {
+    name: String,
+    datatype: DataType,
+    primary_key: bool,
+    nullable: bool,
+    default: Option<Value>,
+    unique: bool,
+    references: Option<String>,
+    index: bool,
+}
+
+impl From<ColumnV1> for Column {
+    fn from(c: ColumnV1) -> Self {
+        Column {
+            name: c.name,
+            datatype: c.datatype,
+            primary_key: c.primary_key,
+            nullable: c.nullable,
+            default: c.default,
+            generated: None,
+            unique: c.unique,
+            references: c.references,
+            index: c.index,
+        }
+    }
+}
+
+/// Encodes a row, tagged with the table's current schema version.
+fn encode_row(table: &Table, row: &[Value]) -> Result<Vec<u8>> {
+    serialize(&(table.version, row))
+}
+
+/// Decodes a row, upgrading it to the table's current schema if it was written under a previous
+/// schema version. If columns are given (as current column indexes), only their values are
+/// decoded, and other columns are NULL.
+fn decode_row(table: &Table, bytes: &[u8], columns: Option<&[usize]>) -> Result<Row> {
+    #[cfg(test)]
+    DECODED_ROWS.with(|d| d.set(d.get() + 1));
+    let (version, row) = bincode::config().deserialize_seed(RowSeed { table, columns }, bytes)?;
+    table.upgrade_row(version, row)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The number of rows decoded by decode_row() on this thread, i.e. table row reads, for tests.
+    pub(super) static DECODED_ROWS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    /// The number of values decoded by decode_row() on this thread, for tests.
+    pub(super) static DECODED_VALUES: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+/// Deserializes an encoded row (see encode_row()) into its schema version and values, skipping
+/// the values of columns that aren't given. The row is tagged with its schema version before its
+/// values, so stored positions can be mapped to current columns before decoding them.
+struct RowSeed<'a> {
+    table: &'a Table,
+    columns: Option<&'a [usize]>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for RowSeed<'a> {
+    type Value = (u64, Row);
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_tuple(2, self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for RowSeed<'a> {
+    type Value = (u64, Row);
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "an encoded row")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        use serde::de::Error as _;
+        let version: u64 =
+            seq.next_element()?.ok_or_else(|| A::Error::invalid_length(0, &"row version"))?;
+        let (table, columns) = (self.table, self.columns);
+        let decode = move |position: usize| match columns {
+            None => true,
+            Some(columns) => {
+                let column = match version == table.version {
+                    true => Some(position),
+                    false => table.layouts.get(version as usize).and_then(|l| *l.get(position)?),
+                };
+                matches!(column, Some(c) if columns.contains(&c))
+            }
+        };
+        let values = seq
+            .next_element_seed(ValuesSeed(&decode))?
+            .ok_or_else(|| A::Error::invalid_length(1, &"row values"))?;
+        Ok((version, values))
+    }
+}
+
+/// Deserializes a row's values, only decoding the values at positions for which the closure
+/// returns true. Other values are skipped, and are NULL.
+struct ValuesSeed<'a>(&'a dyn Fn(usize) -> bool);
+
+impl<'de, 'a> DeserializeSeed<'de> for ValuesSeed<'a> {
+    type Value = Row;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        d.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for ValuesSeed<'a> {
+    type Value = Row;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "row values")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut row = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        loop {
+            let value = if (self.0)(row.len()) {
+                let value = seq.next_element::<Value>()?;
+                #[cfg(test)]
+                DECODED_VALUES.with(|d| d.set(d.get() + value.is_some() as u64));
+                value
+            } else {
+                seq.next_element::<SkippedValue>()?.map(|_| Value::Null)
+            };
+            match value {
+                Some(value) => row.push(value),
+                None => return Ok(row),
+            }
+        }
+    }
+}
+
+/// A borrowed mirror of Value, used to skip encoded values without allocating them. Its variants
+/// must match Value's, since the encoding identifies variants by position.
+#[derive(serde_derive::Deserialize)]
+#[allow(dead_code)]
+enum SkippedValue<'a> {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(&'a str),
+    Date(Date),
+    Time(Time),
+    Timestamp(Timestamp),
+    Decimal(Decimal),
+    Bytes(&'a [u8]),
+}
+
 /// An SQL transaction based on an MVCC key/value transaction
 pub struct Transaction {
     txn: kv::mvcc::Transaction,
+    max_value_size: usize,
 }
 
 impl Transaction {
     /// Creates a new SQL transaction from an MVCC transaction
-    fn new(txn: kv::mvcc::Transaction) -> Self {
-        Self { txn }
+    fn new(txn: kv::mvcc::Transaction, max_value_size: usize) -> Self {
+        Self { txn, max_value_size }
     }
 
     /// Loads an index entry
@@ -82,6 +366,238 @@ impl Transaction {
             .unwrap_or_else(HashSet::new))
     }
 
+    /// Locks a row referenced by a foreign key, by rewriting it unchanged. This causes a
+    /// serialization conflict with any concurrent transaction that deletes or otherwise modifies
+    /// the row, since that transaction's referential integrity checks can't see our uncommitted
+    /// references (and vice versa).
+    fn reference_lock(&mut self, table: &str, id: &Value) -> Result<()> {
+        let key = Key::Row(table.into(), Some(id.into())).encode();
+        if let Some(value) = self.txn.get(&key)? {
+            self.txn.set(&key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Locks any rows referenced by a new or updated row (see reference_lock). Only references that
+    /// differ from the old row are locked, since unchanged references have already been locked or
+    /// were written by a committed transaction.
+    fn reference_lock_row(
+        &mut self,
+        table: &Table,
+        row: &[Value],
+        old: Option<&[Value]>,
+    ) -> Result<()> {
+        let id = table.get_row_key(row)?;
+        for (i, column) in table.columns.iter().enumerate() {
+            if let Some(target) = &column.references {
+                let value = &row[i];
+                if value == &Value::Null
+                    || (target == &table.name && value == &id)
+                    || old.map(|old| &old[i] == value).unwrap_or(false)
+                {
+                    continue;
+                }
+                self.reference_lock(target, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a row is not referenced by any foreign keys (excluding self-references from the
+    /// row itself), using the referencing column's index where possible. Checks are immediate, i.e.
+    /// they are done for each row as it is deleted rather than at the end of the statement.
+    fn reference_check(&self, table: &Table, id: &Value) -> Result<()> {
+        for (t, cs) in self.table_references(&table.name, true)? {
+            let t = self.must_read_table(&t)?;
+            for c in cs {
+                let column = t.get_column(&c)?;
+                let referenced = if column.index {
+                    self.index_load(&t.name, &column.name, id)?
+                        .into_iter()
+                        .any(|pk| t.name != table.name || &pk != id)
+                } else {
+                    let i = t.get_column_index(&c)?;
+                    let mut referenced = false;
+                    let mut scan = self.scan(&t.name, None)?;
+                    while let Some(row) = scan.next().transpose()? {
+                        if &row[i] == id && (t.name != table.name || id != &t.get_row_key(&row)?) {
+                            referenced = true;
+                            break;
+                        }
+                    }
+                    referenced
+                };
+                if referenced {
+                    return Err(Error::Value(format!(
+                        "Primary key {} is referenced by table {} column {}",
+                        id, t.name, c
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves all keys with the given prefix to new keys, as given by the rekey closure.
+    fn rekey_prefix<F>(&mut self, prefix: &[u8], rekey: F) -> Result<()>
+    where
+        F: Fn(Key) -> Result<Vec<u8>>,
+    {
+        let entries = self.txn.scan_prefix(prefix)?.collect::<Result<Vec<_>>>()?;
+        for (key, value) in entries {
+            self.txn.set(&rekey(Key::decode(&key)?)?, value)?;
+            self.txn.delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Scans a column's index entries, optionally only for string values with the given prefix.
+    fn scan_index_key_prefix(
+        &self,
+        table: &str,
+        column: &str,
+        prefix: Option<&str>,
+    ) -> Result<super::IndexScan> {
+        let mut key_prefix = self.index_key_prefix(table, column)?;
+        if let Some(prefix) = prefix {
+            key_prefix.extend(kv::encoding::encode_string_value_prefix(prefix));
+        }
+        Ok(Self::decode_index_scan(self.txn.scan_prefix(&key_prefix)?))
+    }
+
+    /// Returns the key prefix of a column's index entries, erroring if the column isn't indexed.
+    fn index_key_prefix(&self, table: &str, column: &str) -> Result<Vec<u8>> {
+        let table = self.must_read_table(&table)?;
+        let column = table.get_column(column)?;
+        if !column.index {
+            return Err(Error::Value(format!("No index for {}.{}", table.name, column.name)));
+        }
+        Ok(Key::Index((&table.name).into(), (&column.name).into(), None).encode())
+    }
+
+    /// Converts a range of values into a range of keys, consisting of the given prefix followed by
+    /// the encoded value (i.e. row or index entry keys). The value encoding is prefixed by a
+    /// datatype tag, so open-ended ranges are bounded by the tag of the other bound's datatype, to
+    /// only scan values of that datatype.
+    fn encode_range(
+        prefix: &[u8],
+        range: (Bound<Value>, Bound<Value>),
+    ) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        use kv::encoding::encode_value;
+        let tag = match &range {
+            (Bound::Included(v), _) | (Bound::Excluded(v), _) => Some(encode_value(v)[0]),
+            (_, Bound::Included(v)) | (_, Bound::Excluded(v)) => Some(encode_value(v)[0]),
+            (Bound::Unbounded, Bound::Unbounded) => None,
+        };
+        // Float zeroes compare equal but have different encodings, with -0.0 ordered before 0.0,
+        // so pick the zero which includes or excludes both as appropriate.
+        let zero = |value: Value, negative: bool| match value {
+            Value::Float(f) if f == 0.0 && f.is_sign_negative() != negative => Value::Float(-f),
+            value => value,
+        };
+        let key = |value: &Value| [prefix, &encode_value(value)].concat();
+        let start = match range.0 {
+            Bound::Included(v) => Bound::Included(key(&zero(v, true))),
+            Bound::Excluded(v) => Bound::Excluded(key(&zero(v, false))),
+            Bound::Unbounded => Bound::Included([prefix, &[tag.unwrap_or(0x00)]].concat()),
+        };
+        let end = match range.1 {
+            Bound::Included(v) => Bound::Included(key(&zero(v, false))),
+            Bound::Excluded(v) => Bound::Excluded(key(&zero(v, true))),
+            Bound::Unbounded => Bound::Excluded([prefix, &[tag.map_or(0xff, |t| t + 1)]].concat()),
+        };
+        (start, end)
+    }
+
+    /// Decodes a key/value scan of index entries.
+    fn decode_index_scan(scan: kv::Scan) -> super::IndexScan {
+        Box::new(scan.map(|r| -> Result<(Value, HashSet<Value>)> {
+            let (k, v) = r?;
+            let value = match Key::decode(&k)? {
+                Key::Index(_, _, Some(pk)) => pk.into_owned(),
+                _ => return Err(Error::Internal("Invalid index key".into())),
+            };
+            Ok((value, deserialize(&v)?))
+        }))
+    }
+
+    /// Scans a table's rows with primary keys in the given range (see scan_range()), starting
+    /// after the given primary key if any, only decoding the given columns if any (see
+    /// scan_columns()). Used by the Raft engine to fetch scans in batches, which continue after
+    /// the last row's primary key, so the primary key columns are always decoded.
+    pub(super) fn scan_after(
+        &self,
+        table: &str,
+        range: (Bound<Value>, Bound<Value>),
+        columns: Option<Vec<usize>>,
+        after: Option<&Value>,
+        filter: Option<Expression>,
+    ) -> Result<super::Scan> {
+        let table = self.must_read_table(&table)?;
+        let columns = columns.map(|mut columns| {
+            columns.extend(table.get_primary_key_indexes());
+            columns
+        });
+        let prefix = Key::Row((&table.name).into(), None).encode();
+        let (mut start, end) = Self::encode_range(&prefix, range);
+        if let Some(id) = after {
+            start = Bound::Excluded(Key::Row((&table.name).into(), Some(id.into())).encode());
+        }
+        Ok(Box::new(
+            self.txn
+                .scan((start, end))?
+                .map(move |r| r.and_then(|(_, v)| decode_row(&table, &v, columns.as_deref())))
+                .filter_map(move |r| match r {
+                    Ok(row) => match &filter {
+                        Some(filter) => match filter.evaluate(Some(&row)) {
+                            Ok(Value::Boolean(b)) if b => Some(Ok(row)),
+                            Ok(Value::Boolean(_)) | Ok(Value::Null) => None,
+                            Ok(v) => Some(Err(Error::Value(format!(
+                                "Filter returned {}, expected boolean",
+                                v
+                            )))),
+                            Err(err) => Some(Err(err)),
+                        },
+                        None => Some(Ok(row)),
+                    },
+                    err => Some(err),
+                }),
+        ))
+    }
+
+    /// Reads a row, only decoding the given columns if any (see read_columns()).
+    pub(super) fn read_row(
+        &self,
+        table: &str,
+        id: &Value,
+        columns: Option<&[usize]>,
+    ) -> Result<Option<Row>> {
+        let table = self.must_read_table(table)?;
+        self.txn
+            .get(&Key::Row((&table.name).into(), Some(id.into())).encode())?
+            .map(|v| decode_row(&table, &v, columns))
+            .transpose()
+    }
+
+    /// Scans raw key/value pairs with the given key prefix (all pairs if empty), starting after the
+    /// given key if any. Used by the Raft engine to export the key/value store in batches.
+    pub(super) fn scan_keys(&self, prefix: &[u8], after: Option<&[u8]>) -> Result<kv::Scan> {
+        match after {
+            _ if !prefix.is_empty() => self.txn.scan_prefix_after(prefix, after),
+            Some(after) => self.txn.scan((Bound::Excluded(after.to_vec()), Bound::Unbounded)),
+            None => self.txn.scan(..),
+        }
+    }
+
+    /// Writes raw key/value pairs, bypassing schemas and constraints. Used by the Raft engine to
+    /// import previously exported pairs.
+    pub(super) fn set_keys(&mut self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        for (key, value) in pairs {
+            self.txn.set(&key, value)?;
+        }
+        Ok(())
+    }
+
     /// Saves an index entry.
     fn index_save(
         &mut self,
@@ -118,17 +634,20 @@ impl super::Transaction for Transaction {
 
     fn create(&mut self, table: &str, row: Row) -> Result<()> {
         let table = self.must_read_table(&table)?;
-        table.validate_row(&row, self)?;
+        let row = table.conform_row(row)?;
+        table.validate_row(&row, self.max_value_size, self)?;
         let id = table.get_row_key(&row)?;
         if self.read(&table.name, &id)?.is_some() {
             return Err(Error::Value(format!(
                 "Primary key {} already exists for table {}",
-                id, table.name
+                table.format_row_key(&row),
+                table.name
             )));
         }
+        self.reference_lock_row(&table, &row, None)?;
         self.txn.set(
             &Key::Row(Cow::Borrowed(&table.name), Some(Cow::Borrowed(&id))).encode(),
-            serialize(&row)?,
+            encode_row(&table, &row)?,
         )?;
 
         // Update indexes
@@ -142,24 +661,7 @@ impl super::Transaction for Transaction {
 
     fn delete(&mut self, table: &str, id: &Value) -> Result<()> {
         let table = self.must_read_table(&table)?;
-        for (t, cs) in self.table_references(&table.name, true)? {
-            let t = self.must_read_table(&t)?;
-            let cs = cs
-                .into_iter()
-                .map(|c| Ok((t.get_column_index(&c)?, c)))
-                .collect::<Result<Vec<_>>>()?;
-            let mut scan = self.scan(&t.name, None)?;
-            while let Some(row) = scan.next().transpose()? {
-                for (i, c) in &cs {
-                    if &row[*i] == id && (table.name != t.name || id != &table.get_row_key(&row)?) {
-                        return Err(Error::Value(format!(
-                            "Primary key {} is referenced by table {} column {}",
-                            id, t.name, c
-                        )));
-                    }
-                }
-            }
-        }
+        self.reference_check(&table, id)?;
 
         let indexes: Vec<_> = table.columns.iter().enumerate().filter(|(_, c)| c.index).collect();
         if !indexes.is_empty() {
@@ -175,10 +677,11 @@ impl super::Transaction for Transaction {
     }
 
     fn read(&self, table: &str, id: &Value) -> Result<Option<Row>> {
-        self.txn
-            .get(&Key::Row(table.into(), Some(id.into())).encode())?
-            .map(|v| deserialize(&v))
-            .transpose()
+        self.read_row(table, id, None)
+    }
+
+    fn read_columns(&self, table: &str, id: &Value, columns: &[usize]) -> Result<Option<Row>> {
+        self.read_row(table, id, Some(columns))
     }
 
     fn read_index(&self, table: &str, column: &str, value: &Value) -> Result<HashSet<Value>> {
@@ -189,53 +692,92 @@ impl super::Transaction for Transaction {
     }
 
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<super::Scan> {
-        let table = self.must_read_table(&table)?;
-        Ok(Box::new(
-            self.txn
-                .scan_prefix(&Key::Row((&table.name).into(), None).encode())?
-                .map(|r| r.and_then(|(_, v)| deserialize(&v)))
-                .filter_map(move |r| match r {
-                    Ok(row) => match &filter {
-                        Some(filter) => match filter.evaluate(Some(&row)) {
-                            Ok(Value::Boolean(b)) if b => Some(Ok(row)),
-                            Ok(Value::Boolean(_)) | Ok(Value::Null) => None,
-                            Ok(v) => Some(Err(Error::Value(format!(
-                                "Filter returned {}, expected boolean",
-                                v
-                            )))),
-                            Err(err) => Some(Err(err)),
-                        },
-                        None => Some(Ok(row)),
-                    },
-                    err => Some(err),
-                }),
-        ))
+        self.scan_after(table, (Bound::Unbounded, Bound::Unbounded), None, None, filter)
+    }
+
+    fn scan_range(
+        &self,
+        table: &str,
+        range: (Bound<Value>, Bound<Value>),
+        filter: Option<Expression>,
+    ) -> Result<super::Scan> {
+        self.scan_after(table, range, None, None, filter)
+    }
+
+    fn scan_columns(
+        &self,
+        table: &str,
+        range: (Bound<Value>, Bound<Value>),
+        columns: &[usize],
+        filter: Option<Expression>,
+    ) -> Result<super::Scan> {
+        self.scan_after(table, range, Some(columns.to_vec()), None, filter)
     }
 
     fn scan_index(&self, table: &str, column: &str) -> Result<super::IndexScan> {
-        let table = self.must_read_table(&table)?;
-        let column = table.get_column(column)?;
-        if !column.index {
-            return Err(Error::Value(format!("No index for {}.{}", table.name, column.name)));
+        self.scan_index_key_prefix(table, column, None)
+    }
+
+    fn scan_index_prefix(
+        &self,
+        table: &str,
+        column: &str,
+        prefix: &str,
+    ) -> Result<super::IndexScan> {
+        self.scan_index_key_prefix(table, column, Some(prefix))
+    }
+
+    fn scan_index_range(
+        &self,
+        table: &str,
+        column: &str,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<super::IndexScan> {
+        if range == (Bound::Unbounded, Bound::Unbounded) {
+            return Err(Error::Internal("Index range scan must have a bound".into()));
         }
-        Ok(Box::new(
-            self.txn
-                .scan_prefix(
-                    &Key::Index((&table.name).into(), (&column.name).into(), None).encode(),
-                )?
-                .map(|r| -> Result<(Value, HashSet<Value>)> {
-                    let (k, v) = r?;
-                    let value = match Key::decode(&k)? {
-                        Key::Index(_, _, Some(pk)) => pk.into_owned(),
-                        _ => return Err(Error::Internal("Invalid index key".into())),
-                    };
-                    Ok((value, deserialize(&v)?))
-                }),
-        ))
+        let prefix = self.index_key_prefix(table, column)?;
+        Ok(Self::decode_index_scan(self.txn.scan(Self::encode_range(&prefix, range))?))
+    }
+
+    fn truncate(&mut self, table: &str) -> Result<u64> {
+        let table = self.must_read_table(table)?;
+        for (t, cs) in self.table_references(&table.name, false)? {
+            let t = self.must_read_table(&t)?;
+            for c in cs {
+                let i = t.get_column_index(&c)?;
+                let mut scan = self.scan(&t.name, None)?;
+                while let Some(row) = scan.next().transpose()? {
+                    if row[i] != Value::Null {
+                        return Err(Error::Value(format!(
+                            "Table {} is referenced by table {} column {}",
+                            table.name, t.name, c
+                        )));
+                    }
+                }
+            }
+        }
+
+        // Rows and index entries are deleted atomically by key prefix, without decoding them, such
+        // that a conflict can't leave index entries behind for deleted rows.
+        let mut prefixes = vec![Key::Row((&table.name).into(), None).encode()];
+        for column in table.columns.iter().filter(|c| c.index) {
+            prefixes.push(Key::Index((&table.name).into(), (&column.name).into(), None).encode());
+        }
+        let counts =
+            self.txn.delete_prefixes(&prefixes.iter().map(|p| p.as_slice()).collect::<Vec<_>>())?;
+        // Statistics are reset to those of an empty table, such that the optimizer doesn't plan
+        // with stale row counts until the next ANALYZE.
+        if self.read_table_stats(&table.name)?.is_some() {
+            let stats = TableStats::collect(table.columns.len(), std::iter::empty())?;
+            self.set_table_stats(&table.name, stats)?;
+        }
+        Ok(counts[0])
     }
 
     fn update(&mut self, table: &str, id: &Value, row: Row) -> Result<()> {
         let table = self.must_read_table(&table)?;
+        let row = table.conform_row(row)?;
         // If the primary key changes we do a delete and create, otherwise we replace the row
         if id != &table.get_row_key(&row)? {
             self.delete(&table.name, id)?;
@@ -243,6 +785,10 @@ impl super::Transaction for Transaction {
             return Ok(());
         }
 
+        // Validate the row before updating indexes, such that a rejected row doesn't leave stale
+        // index entries behind.
+        table.validate_row(&row, self.max_value_size, self)?;
+
         // Update indexes, knowing that the primary key has not changed
         let indexes: Vec<_> = table.columns.iter().enumerate().filter(|(_, c)| c.index).collect();
         if !indexes.is_empty() {
@@ -261,8 +807,12 @@ impl super::Transaction for Transaction {
             }
         }
 
-        table.validate_row(&row, self)?;
-        self.txn.set(&Key::Row(table.name.into(), Some(id.into())).encode(), serialize(&row)?)
+        let old = self.read(&table.name, id)?;
+        self.reference_lock_row(&table, &row, old.as_deref())?;
+        self.txn.set(
+            &Key::Row((&table.name).into(), Some(id.into())).encode(),
+            encode_row(&table, &row)?,
+        )
     }
 }
 
@@ -271,6 +821,9 @@ impl Catalog for Transaction {
         if self.read_table(&table.name)?.is_some() {
             return Err(Error::Value(format!("Table {} already exists", table.name)));
         }
+        if self.read_view(&table.name)?.is_some() {
+            return Err(Error::Value(format!("View {} already exists", table.name)));
+        }
         table.validate(self)?;
         self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)
     }
@@ -287,6 +840,13 @@ impl Catalog for Transaction {
         while let Some(row) = scan.next().transpose()? {
             self.delete(&table.name, &table.get_row_key(&row)?)?
         }
+        self.txn.delete(&Key::TableStats((&table.name).into()).encode())?;
+        // Revoke privileges on the table, such that they don't apply to a new table of that name.
+        for mut user in self.scan_users()? {
+            if user.privileges.remove(&table.name).is_some() {
+                self.update_user(user)?;
+            }
+        }
         self.txn.delete(&Key::Table(Some(table.name.into())).encode())
     }
 
@@ -294,6 +854,163 @@ impl Catalog for Transaction {
         self.txn.get(&Key::Table(Some(table.into())).encode())?.map(|v| deserialize(&v)).transpose()
     }
 
+    fn add_column(&mut self, table: &str, column: Column) -> Result<()> {
+        let mut table = self.must_read_table(table)?;
+        table.add_column(column)?;
+        table.validate(self)?;
+        self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)?;
+
+        // Existing rows aren't rewritten, but take on the default value when read. It must be
+        // valid for every row (e.g. unique or NOT NULL), and is added to the column's index.
+        let i = table.columns.len() - 1;
+        let column = &table.columns[i];
+        let rows = self.scan(&table.name, None)?.collect::<Result<Vec<_>>>()?;
+        for row in &rows {
+            let id = table.get_row_key(row)?;
+            column.validate_value(&table, &id, &row[i], self.max_value_size, self)?;
+            if column.index {
+                let mut index = self.index_load(&table.name, &column.name, &row[i])?;
+                index.insert(id);
+                self.index_save(&table.name, &column.name, &row[i], index)?;
+            }
+        }
+        if let (Some(target), Some(row)) = (&column.references, rows.first()) {
+            if row[i] != Value::Null {
+                self.reference_lock(target, &row[i])?;
+            }
+        }
+
+        // All rows have the same value, so the column statistics can be derived from the table's.
+        if let Some(mut stats) = self.read_table_stats(&table.name)? {
+            let value = rows.first().map(|row| row[i].clone()).unwrap_or(Value::Null);
+            stats.columns.push(match value {
+                Value::Null => ColumnStats {
+                    distinct: 0,
+                    nulls: stats.rows,
+                    min: Value::Null,
+                    max: Value::Null,
+                },
+                value => ColumnStats {
+                    distinct: stats.rows.min(1),
+                    nulls: 0,
+                    min: value.clone(),
+                    max: value,
+                },
+            });
+            self.set_table_stats(&table.name, stats)?;
+        }
+        Ok(())
+    }
+
+    fn drop_column(&mut self, table: &str, column: &str) -> Result<()> {
+        let mut table = self.must_read_table(table)?;
+        let index = table.get_column_index(column)?;
+        let column = table.drop_column(column)?;
+        if column.index {
+            let prefix = Key::Index((&table.name).into(), (&column.name).into(), None).encode();
+            let keys = self.txn.scan_prefix(&prefix)?.map(|r| r.map(|(k, _)| k));
+            for key in keys.collect::<Result<Vec<_>>>()? {
+                self.txn.delete(&key)?;
+            }
+        }
+        if let Some(mut stats) = self.read_table_stats(&table.name)? {
+            stats.columns.remove(index);
+            self.set_table_stats(&table.name, stats)?;
+        }
+        self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)
+    }
+
+    fn rename_column(&mut self, table: &str, column: &str, new_name: &str) -> Result<()> {
+        let mut table = self.must_read_table(table)?;
+        let index = table.get_column_index(column)?;
+        if table.columns.iter().any(|c| c.name == new_name) {
+            return Err(Error::Value(format!(
+                "Column {} already exists in table {}",
+                new_name, table.name
+            )));
+        }
+        if table.columns[index].index {
+            self.rekey_prefix(
+                &Key::Index((&table.name).into(), column.into(), None).encode(),
+                |key| match key {
+                    Key::Index(_, _, value) => {
+                        Ok(Key::Index((&table.name).into(), new_name.into(), value).encode())
+                    }
+                    _ => Err(Error::Internal("Invalid index key".into())),
+                },
+            )?;
+        }
+        table.columns[index].name = new_name.to_string();
+        for column in table.columns.iter_mut() {
+            if let Some(expr) = column.generated.take() {
+                column.generated = Some(expr.transform(
+                    &|e| match e {
+                        Expression::Field(i, _) if i == index => {
+                            Ok(Expression::Field(i, Some((None, new_name.to_string()))))
+                        }
+                        e => Ok(e),
+                    },
+                    &Ok,
+                )?);
+            }
+        }
+        self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)
+    }
+
+    fn rename_table(&mut self, table: &str, new_name: &str) -> Result<()> {
+        let mut table = self.must_read_table(table)?;
+        if self.read_table(new_name)?.is_some() {
+            return Err(Error::Value(format!("Table {} already exists", new_name)));
+        }
+        if self.read_view(new_name)?.is_some() {
+            return Err(Error::Value(format!("View {} already exists", new_name)));
+        }
+
+        // Move rows and index entries to keys under the new table name.
+        self.rekey_prefix(&Key::Row((&table.name).into(), None).encode(), |key| match key {
+            Key::Row(_, pk) => Ok(Key::Row(new_name.into(), pk).encode()),
+            _ => Err(Error::Internal("Invalid row key".into())),
+        })?;
+        for column in table.columns.iter().filter(|c| c.index) {
+            self.rekey_prefix(
+                &Key::Index((&table.name).into(), (&column.name).into(), None).encode(),
+                |key| match key {
+                    Key::Index(_, column, value) => {
+                        Ok(Key::Index(new_name.into(), column, value).encode())
+                    }
+                    _ => Err(Error::Internal("Invalid index key".into())),
+                },
+            )?;
+        }
+
+        // Update foreign key references in other tables, as well as self-references.
+        for (t, cs) in self.table_references(&table.name, false)? {
+            let mut t = self.must_read_table(&t)?;
+            for column in t.columns.iter_mut().filter(|c| cs.contains(&c.name)) {
+                column.references = Some(new_name.to_string());
+            }
+            self.txn.set(&Key::Table(Some((&t.name).into())).encode(), serialize(&t)?)?;
+        }
+        let old_name = std::mem::replace(&mut table.name, new_name.to_string());
+        for column in table.columns.iter_mut() {
+            if column.references.as_deref() == Some(&old_name) {
+                column.references = Some(new_name.to_string());
+            }
+        }
+        if let Some(stats) = self.txn.get(&Key::TableStats((&old_name).into()).encode())? {
+            self.txn.delete(&Key::TableStats((&old_name).into()).encode())?;
+            self.txn.set(&Key::TableStats((&table.name).into()).encode(), stats)?;
+        }
+        for mut user in self.scan_users()? {
+            if let Some(privileges) = user.privileges.remove(&old_name) {
+                user.privileges.insert(table.name.clone(), privileges);
+                self.update_user(user)?;
+            }
+        }
+        self.txn.delete(&Key::Table(Some(old_name.into())).encode())?;
+        self.txn.set(&Key::Table(Some((&table.name).into())).encode(), serialize(&table)?)
+    }
+
     fn scan_tables(&self) -> Result<Tables> {
         Ok(Box::new(
             self.txn
@@ -303,12 +1020,82 @@ impl Catalog for Transaction {
                 .into_iter(),
         ))
     }
+
+    fn create_view(&mut self, view: View) -> Result<()> {
+        if self.read_view(&view.name)?.is_some() {
+            return Err(Error::Value(format!("View {} already exists", view.name)));
+        }
+        if self.read_table(&view.name)?.is_some() {
+            return Err(Error::Value(format!("Table {} already exists", view.name)));
+        }
+        self.txn.set(&Key::View(Some((&view.name).into())).encode(), serialize(&view)?)
+    }
+
+    fn delete_view(&mut self, view: &str) -> Result<()> {
+        let view = self.must_read_view(view)?;
+        self.txn.delete(&Key::View(Some(view.name.into())).encode())
+    }
+
+    fn read_view(&self, view: &str) -> Result<Option<View>> {
+        self.txn.get(&Key::View(Some(view.into())).encode())?.map(|v| deserialize(&v)).transpose()
+    }
+
+    fn scan_views(&self) -> Result<Views> {
+        Ok(Box::new(
+            self.txn
+                .scan_prefix(&Key::View(None).encode())?
+                .map(|r| r.and_then(|(_, v)| deserialize(&v)))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter(),
+        ))
+    }
+
+    fn create_user(&mut self, user: User) -> Result<()> {
+        if self.read_user(&user.name)?.is_some() {
+            return Err(Error::Value(format!("User {} already exists", user.name)));
+        }
+        self.txn.set(&Key::User(Some((&user.name).into())).encode(), serialize(&user)?)
+    }
+
+    fn delete_user(&mut self, user: &str) -> Result<()> {
+        let user = self.must_read_user(user)?;
+        self.txn.delete(&Key::User(Some(user.name.into())).encode())
+    }
+
+    fn read_user(&self, user: &str) -> Result<Option<User>> {
+        self.txn.get(&Key::User(Some(user.into())).encode())?.map(|v| deserialize(&v)).transpose()
+    }
+
+    fn scan_users(&self) -> Result<Users> {
+        Ok(Box::new(
+            self.txn
+                .scan_prefix(&Key::User(None).encode())?
+                .map(|r| r.and_then(|(_, v)| deserialize(&v)))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter(),
+        ))
+    }
+
+    fn update_user(&mut self, user: User) -> Result<()> {
+        self.must_read_user(&user.name)?;
+        self.txn.set(&Key::User(Some((&user.name).into())).encode(), serialize(&user)?)
+    }
+
+    fn read_table_stats(&self, table: &str) -> Result<Option<TableStats>> {
+        self.txn.get(&Key::TableStats(table.into()).encode())?.map(|v| deserialize(&v)).transpose()
+    }
+
+    fn set_table_stats(&mut self, table: &str, stats: TableStats) -> Result<()> {
+        let table = self.must_read_table(table)?;
+        self.txn.set(&Key::TableStats(table.name.into()).encode(), serialize(&stats)?)
+    }
 }
 
 /// Encodes SQL keys, using an order-preserving encoding - see kv::encoding for details. Options can
 /// be None to get a keyspace prefix. We use table and column names directly as identifiers, to
-/// avoid additional indirection and associated overhead. It is not possible to change names, so
-/// this is ok. Uses Cows since we want to borrow when encoding but return owned when decoding.
+/// avoid additional indirection and associated overhead. Renaming a table or an indexed column
+/// therefore rewrites all of its keys, but renames are rare so this is ok. Uses Cows since we want
+/// to borrow when encoding but return owned when decoding.
 enum Key<'a> {
     /// A table schema key for the given table name
     Table(Option<Cow<'a, str>>),
@@ -316,6 +1103,12 @@ enum Key<'a> {
     Index(Cow<'a, str>, Cow<'a, str>, Option<Cow<'a, Value>>),
     /// A key for a row identified by table name and row primary key
     Row(Cow<'a, str>, Option<Cow<'a, Value>>),
+    /// A view definition key for the given view name
+    View(Option<Cow<'a, str>>),
+    /// A table statistics key for the given table name
+    TableStats(Cow<'a, str>),
+    /// A user key for the given user name
+    User(Option<Cow<'a, str>>),
 }
 
 impl<'a> Key<'a> {
@@ -339,6 +1132,11 @@ impl<'a> Key<'a> {
             Self::Row(table, Some(pk)) => {
                 [&[0x03][..], &encode_string(&table), &encode_value(&pk)].concat()
             }
+            Self::View(None) => vec![0x04],
+            Self::View(Some(name)) => [&[0x04][..], &encode_string(&name)].concat(),
+            Self::TableStats(name) => [&[0x05][..], &encode_string(&name)].concat(),
+            Self::User(None) => vec![0x06],
+            Self::User(Some(name)) => [&[0x06][..], &encode_string(&name)].concat(),
         }
     }
 
@@ -354,6 +1152,9 @@ impl<'a> Key<'a> {
                 Some(take_value(bytes)?.into()),
             ),
             0x03 => Self::Row(take_string(bytes)?.into(), Some(take_value(bytes)?.into())),
+            0x04 => Self::View(Some(take_string(bytes)?.into())),
+            0x05 => Self::TableStats(take_string(bytes)?.into()),
+            0x06 => Self::User(Some(take_string(bytes)?.into())),
             b => return Err(Error::Internal(format!("Unknown SQL key prefix {:x?}", b))),
         };
         if !bytes.is_empty() {
@@ -362,3 +1163,427 @@ impl<'a> Key<'a> {
         Ok(key)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::execution::ResultSet;
+    use super::super::Engine as _;
+    use super::*;
+
+    #[test]
+    fn version_new() -> Result<()> {
+        let store = kv::MVCC::new(Box::new(kv::Memory::new()));
+        KV::new(store.clone())?;
+        assert_eq!(store.get_metadata(b"version")?, Some(serialize(&VERSION)?));
+        KV::new(store)?;
+        Ok(())
+    }
+
+    #[test]
+    fn version_unversioned() -> Result<()> {
+        let store = kv::MVCC::new(Box::new(kv::Memory::new()));
+        store.begin()?.commit()?;
+        KV::new(store.clone())?;
+        assert_eq!(store.get_metadata(b"version")?, Some(serialize(&VERSION)?));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_row_columns() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        engine.session()?.execute(
+            "CREATE TABLE test (id INTEGER PRIMARY KEY, v1 BOOLEAN, v2 BOOLEAN, v3 FLOAT, \
+             v4 STRING, v5 DATE, v6 TIME, v7 TIMESTAMP, v8 DECIMAL(10, 2), v9 BLOB)",
+        )?;
+        let table = engine.begin(super::super::Mode::ReadOnly)?.must_read_table("test")?;
+        let row = vec![
+            Value::Integer(1),
+            Value::Null,
+            Value::Boolean(true),
+            Value::Float(3.5),
+            Value::String("foo".into()),
+            Value::Date(Date(1)),
+            Value::Time(Time(2)),
+            Value::Timestamp(Timestamp(3)),
+            Value::Decimal(Decimal::new(314, 2)?),
+            Value::Bytes(vec![1, 2, 3]),
+        ];
+        let bytes = encode_row(&table, &row)?;
+        assert_eq!(decode_row(&table, &bytes, None)?, row);
+
+        // Each column can be decoded on its own, skipping the values of every other datatype.
+        for i in 0..row.len() {
+            let mut expect = vec![Value::Null; row.len()];
+            expect[i] = row[i].clone();
+            assert_eq!(decode_row(&table, &bytes, Some(&[i]))?, expect);
+        }
+        assert_eq!(decode_row(&table, &bytes, Some(&[]))?, vec![Value::Null; row.len()]);
+        Ok(())
+    }
+
+    #[test]
+    fn version_upgrade() -> Result<()> {
+        // Writes a version 1 store, with untagged rows and tables without schema versions.
+        let store = kv::MVCC::new(Box::new(kv::Memory::new()));
+        store.set_metadata(b"version", serialize(&1_u64)?)?;
+        let columns = vec![
+            ColumnV1 {
+                name: "id".into(),
+                datatype: DataType::Integer,
+                primary_key: true,
+                nullable: false,
+                default: None,
+                unique: true,
+                references: None,
+                index: false,
+            },
+            ColumnV1 {
+                name: "value".into(),
+                datatype: DataType::String,
+                primary_key: false,
+                nullable: true,
+                default: Some(Value::Null),
+                unique: false,
+                references: None,
+                index: false,
+            },
+        ];
+        let mut txn = store.begin()?;
+        txn.set(&Key::Table(Some("test".into())).encode(), serialize(&("test", &columns))?)?;
+        for (id, value) in &[(1, "a"), (2, "b")] {
+            txn.set(
+                &Key::Row("test".into(), Some(Cow::Owned(Value::Integer(*id)))).encode(),
+                serialize(&vec![Value::Integer(*id), Value::String(value.to_string())])?,
+            )?;
+        }
+        txn.commit()?;
+
+        // The upgraded store can read, write, and alter the table.
+        let engine = KV::new(store.clone())?;
+        assert_eq!(store.get_metadata(b"version")?, Some(serialize(&VERSION)?));
+        let mut session = engine.session()?;
+        assert_eq!(
+            session.execute("SELECT value FROM test WHERE id = 2")?.into_value()?,
+            Value::String("b".into())
+        );
+        session.execute("ALTER TABLE test ADD COLUMN flag BOOLEAN DEFAULT TRUE")?;
+        session.execute("INSERT INTO test VALUES (3, 'c', FALSE)")?;
+        let txn = engine.begin(super::super::Mode::ReadOnly)?;
+        assert_eq!(txn.must_read_table("test")?.version, 1);
+        assert_eq!(
+            txn.scan("test", None)?.collect::<Result<Vec<_>>>()?,
+            vec![
+                vec![Value::Integer(1), Value::String("a".into()), Value::Boolean(true)],
+                vec![Value::Integer(2), Value::String("b".into()), Value::Boolean(true)],
+                vec![Value::Integer(3), Value::String("c".into()), Value::Boolean(false)],
+            ]
+        );
+        txn.rollback()
+    }
+
+    #[test]
+    fn alter_column_versions() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, a STRING, b INTEGER INDEX)")?;
+        session.execute("INSERT INTO test VALUES (1, 'a1', 10), (2, 'a2', 20)")?;
+
+        // Returns the result rows of a query.
+        let rows = |query: &str| -> Result<Vec<Row>> {
+            match engine.session()?.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect(),
+                result => panic!("Unexpected result {:?}", result),
+            }
+        };
+        let row = |values: &[Value]| values.to_vec();
+        let (i, s, null) = (Value::Integer, |s: &str| Value::String(s.into()), Value::Null);
+
+        // Adding a column doesn't rewrite rows, which are stored with the old schema version and
+        // take on the default value when read, via both scans and key lookups.
+        session.execute("ALTER TABLE test ADD COLUMN c INTEGER NOT NULL DEFAULT 7")?;
+        session.execute("ALTER TABLE test ADD d STRING")?;
+        session.execute("INSERT INTO test VALUES (3, 'a3', 30, 3, 'd3')")?;
+        let txn = engine.begin(super::super::Mode::ReadOnly)?;
+        let table = txn.must_read_table("test")?;
+        assert_eq!(table.version, 2);
+        for (id, version) in &[(1, 0), (3, 2)] {
+            let value = txn.txn.get(&Key::Row("test".into(), Some(Cow::Owned(i(*id)))).encode())?;
+            assert_eq!(deserialize::<(u64, Row)>(&value.unwrap())?.0, *version);
+        }
+        txn.rollback()?;
+        assert_eq!(
+            rows("SELECT * FROM test")?,
+            vec![
+                row(&[i(1), s("a1"), i(10), i(7), null.clone()]),
+                row(&[i(2), s("a2"), i(20), i(7), null.clone()]),
+                row(&[i(3), s("a3"), i(30), i(3), s("d3")]),
+            ]
+        );
+        assert_eq!(rows("SELECT c FROM test WHERE id = 2")?, vec![row(&[i(7)])]);
+
+        // Dropping a column ignores its values in rows written under older schema versions, and
+        // removes its index. Updated rows are written under the current version.
+        session.execute("UPDATE test SET d = 'd2' WHERE id = 2")?;
+        session.execute("ALTER TABLE test DROP COLUMN b")?;
+        session.execute("ALTER TABLE test DROP a")?;
+        assert_eq!(
+            rows("SELECT * FROM test")?,
+            vec![
+                row(&[i(1), i(7), null]),
+                row(&[i(2), i(7), s("d2")]),
+                row(&[i(3), i(3), s("d3")]),
+            ]
+        );
+        let txn = engine.begin(super::super::Mode::ReadOnly)?;
+        assert_eq!(
+            txn.txn.scan_prefix(&Key::Index("test".into(), "b".into(), None).encode())?.count(),
+            0
+        );
+        txn.rollback()?;
+
+        // A column dropped and added again doesn't resurrect old values.
+        session.execute("ALTER TABLE test ADD COLUMN a STRING DEFAULT 'new'")?;
+        session.execute("INSERT INTO test VALUES (4, 4, NULL, 'a4')")?;
+        assert_eq!(
+            rows("SELECT id, a FROM test")?,
+            vec![
+                row(&[i(1), s("new")]),
+                row(&[i(2), s("new")]),
+                row(&[i(3), s("new")]),
+                row(&[i(4), s("a4")])
+            ]
+        );
+
+        // The new column's default must be valid for all existing rows.
+        assert_eq!(
+            session.execute("ALTER TABLE test ADD COLUMN e INTEGER NOT NULL").err(),
+            Some(Error::Value("NULL value not allowed for column e".into()))
+        );
+        assert_eq!(
+            session.execute("ALTER TABLE test ADD COLUMN e INTEGER UNIQUE DEFAULT 1").err(),
+            Some(Error::Value("Unique value 1 already exists for column e".into()))
+        );
+        assert_eq!(
+            session.execute("ALTER TABLE test DROP COLUMN id").err(),
+            Some(Error::Value("Can't drop primary key column id of table test".into()))
+        );
+        assert_eq!(engine.begin(super::super::Mode::ReadOnly)?.must_read_table("test")?.version, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn version_future() -> Result<()> {
+        let store = kv::MVCC::new(Box::new(kv::Memory::new()));
+        let engine = KV::new(store.clone())?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY)")?;
+        session.execute("INSERT INTO test VALUES (1)")?;
+
+        store.set_metadata(b"version", serialize(&(VERSION + 1))?)?;
+        assert_eq!(
+            KV::new(store.clone()).err(),
+            Some(Error::Config(format!(
+                "unsupported store version {}, this version of toyDB supports up to version {}",
+                VERSION + 1,
+                VERSION
+            )))
+        );
+        assert_eq!(store.get_metadata(b"version")?, Some(serialize(&(VERSION + 1))?));
+        Ok(())
+    }
+    #[test]
+    fn max_value_size() -> Result<()> {
+        let store = kv::MVCC::new(Box::new(kv::Memory::new()));
+        let engine = KV::new(store)?.with_max_value_size(4);
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, s STRING, b BYTEA)")?;
+        session.execute("INSERT INTO test VALUES (1, 'abcd', x'00010203')")?;
+        assert_eq!(
+            session.execute("INSERT INTO test VALUES (2, 'abcde', NULL)").err(),
+            Some(Error::Value("Strings cannot be more than 4 bytes".into()))
+        );
+        assert_eq!(
+            session.execute("UPDATE test SET b = x'0001020304' WHERE id = 1").err(),
+            Some(Error::Value("Binary values cannot be more than 4 bytes".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn max_stored_value_size() -> Result<()> {
+        let store = kv::MVCC::new(Box::new(kv::Memory::new())).with_max_value_size(Some(256));
+        let engine = KV::new(store)?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, s STRING)")?;
+
+        // Rows are stored as single values, so the encoded row size is limited.
+        let overhead =
+            serialize(&(0_u64, vec![Value::Integer(1), Value::String("".into())]))?.len();
+        let insert = |id: usize, len: usize| {
+            format!("INSERT INTO test VALUES ({}, '{}')", id, "x".repeat(len - overhead))
+        };
+        session.execute(&insert(1, 256))?;
+        assert_eq!(
+            session.execute(&insert(2, 257)).err(),
+            Some(Error::Value("Value of 257 bytes exceeds maximum size of 256 bytes".into()))
+        );
+        assert_eq!(
+            session.execute(&format!("UPDATE test SET s = '{}'", "x".repeat(257 - overhead))).err(),
+            Some(Error::Value("Value of 257 bytes exceeds maximum size of 256 bytes".into()))
+        );
+        assert_eq!(session.execute("SELECT COUNT(*) FROM test")?.into_value()?, Value::Integer(1));
+        Ok(())
+    }
+
+    #[test]
+    fn scan_index_range() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value FLOAT INDEX)")?;
+        session.execute(
+            "INSERT INTO test VALUES (1, -1.0), (2, -0.0), (3, 0.0), (4, NULL), (5, 2.5), (6, 0.0)",
+        )?;
+        let txn = engine.begin(super::super::Mode::ReadOnly)?;
+
+        // Returns the primary keys of the index entries in the range, in scan order.
+        let scan = |start: Bound<f64>, end: Bound<f64>| -> Result<Vec<Vec<Value>>> {
+            let map = |bound: Bound<f64>| match bound {
+                Bound::Included(f) => Bound::Included(Value::Float(f)),
+                Bound::Excluded(f) => Bound::Excluded(Value::Float(f)),
+                Bound::Unbounded => Bound::Unbounded,
+            };
+            txn.scan_index_range("test", "value", (map(start), map(end)))?
+                .map(|r| {
+                    let mut pks = r?.1.into_iter().collect::<Vec<_>>();
+                    pks.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    Ok(pks)
+                })
+                .collect()
+        };
+        let ids = |ids: &[&[i64]]| -> Vec<Vec<Value>> {
+            ids.iter().map(|ids| ids.iter().map(|id| Value::Integer(*id)).collect()).collect()
+        };
+
+        // Open-ended ranges don't include NULLs.
+        assert_eq!(scan(Bound::Unbounded, Bound::Excluded(0.0))?, ids(&[&[1]]));
+        assert_eq!(scan(Bound::Excluded(-1.0), Bound::Unbounded)?, ids(&[&[2], &[3, 6], &[5]]));
+        assert_eq!(scan(Bound::Included(-1.0), Bound::Included(2.5))?.len(), 4);
+        assert_eq!(scan(Bound::Excluded(2.5), Bound::Unbounded)?, ids(&[]));
+        assert_eq!(scan(Bound::Excluded(1.0), Bound::Excluded(0.0))?, ids(&[]));
+
+        // -0.0 and 0.0 are encoded differently, but are equal.
+        assert_eq!(scan(Bound::Included(0.0), Bound::Included(-0.0))?, ids(&[&[2], &[3, 6]]));
+        assert_eq!(scan(Bound::Excluded(-0.0), Bound::Unbounded)?, ids(&[&[5]]));
+        assert_eq!(scan(Bound::Unbounded, Bound::Excluded(0.0))?, ids(&[&[1]]));
+
+        assert_eq!(
+            txn.scan_index_range("test", "id", (Bound::Unbounded, Bound::Excluded(1.into()))).err(),
+            Some(Error::Value("No index for test.id".into()))
+        );
+        txn.rollback()
+    }
+
+    #[test]
+    // A truncate that conflicts with a concurrent write deletes neither rows nor index entries.
+    fn truncate_conflict() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value INTEGER INDEX)")?;
+        session.execute("INSERT INTO test VALUES (1, 10), (2, 20)")?;
+
+        let mut t1 = engine.begin(super::super::Mode::ReadWrite)?;
+        let mut t2 = engine.begin(super::super::Mode::ReadWrite)?;
+        t2.create("test", vec![Value::Integer(3), Value::Integer(30)])?;
+        assert_eq!(t1.truncate("test"), Err(Error::Serialization));
+        assert_eq!(t1.scan("test", None)?.count(), 2);
+        assert_eq!(t1.scan_index("test", "value")?.count(), 2);
+        t1.rollback()?;
+        t2.commit()?;
+
+        let mut txn = engine.begin(super::super::Mode::ReadWrite)?;
+        assert_eq!(txn.truncate("test")?, 3);
+        assert_eq!(txn.scan("test", None)?.count(), 0);
+        assert_eq!(txn.scan_index("test", "value")?.count(), 0);
+        txn.commit()
+    }
+
+    #[test]
+    // Point reads of rows are served from the MVCC cache, without reading the store.
+    fn cache_row_reads() -> Result<()> {
+        let store = kv::Test::new();
+        let engine = KV::new(kv::MVCC::new(Box::new(store.clone())).with_cache(100))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)")?;
+        session.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b')")?;
+        engine.kv.vacuum()?; // clears the cache
+
+        let txn = engine.begin(super::super::Mode::ReadOnly)?;
+        let row = Some(vec![Value::Integer(1), Value::String("a".into())]);
+        let reads = store.reads();
+        assert_eq!(txn.read("test", &Value::Integer(1))?, row);
+        assert!(store.reads() > reads);
+        let reads = store.reads();
+        assert_eq!(txn.read("test", &Value::Integer(1))?, row);
+        assert_eq!(store.reads(), reads);
+        txn.rollback()?;
+
+        // A new transaction sees updates, still without reading the row from the store.
+        session.execute("UPDATE test SET value = 'c' WHERE id = 1")?;
+        let txn = engine.begin(super::super::Mode::ReadOnly)?;
+        let reads = store.reads();
+        assert_eq!(
+            txn.read("test", &Value::Integer(1))?,
+            Some(vec![Value::Integer(1), Value::String("c".into())])
+        );
+        assert_eq!(store.reads(), reads);
+        txn.rollback()
+    }
+
+    #[test]
+    fn analyze_index_selection() -> Result<()> {
+        let store = kv::Test::new();
+        let engine = KV::new(kv::MVCC::new(Box::new(store.clone())))?;
+        let mut session = engine.session()?;
+        session.execute(
+            "CREATE TABLE test (id INTEGER PRIMARY KEY, code INTEGER INDEX, even BOOLEAN INDEX)",
+        )?;
+        session.execute(&format!(
+            "INSERT INTO test VALUES {}",
+            (1..=2000)
+                .map(|i| format!("({}, {}, {})", i, i, i % 2 == 0))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?;
+        assert_eq!(session.execute("ANALYZE")?, ResultSet::Analyze { tables: vec!["test".into()] });
+
+        // Executes a query, returning its result rows and the number of storage reads.
+        let mut execute = |query: &str| -> Result<(Vec<Row>, u64)> {
+            let reads = store.reads();
+            let rows = match session.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect::<Result<_>>()?,
+                result => panic!("Unexpected result {:?}", result),
+            };
+            Ok((rows, store.reads() - reads))
+        };
+
+        // A highly selective predicate on an indexed column uses an index lookup, avoiding the
+        // full table scan.
+        let (_, scan_reads) = execute("SELECT * FROM test")?;
+        let (rows, lookup_reads) = execute("SELECT * FROM test WHERE code = 7")?;
+        assert_eq!(rows, vec![vec![Value::Integer(7), Value::Integer(7), Value::Boolean(false)]]);
+        assert!(lookup_reads * 100 < scan_reads, "{} vs {} reads", lookup_reads, scan_reads);
+        assert_eq!(
+            execute("EXPLAIN SELECT * FROM test WHERE code = 7")?.0,
+            vec![vec![Value::String(
+                "IndexLookup: test column code (7) (estimated rows=1)".into()
+            )]]
+        );
+
+        // A predicate matching half of the rows uses a full table scan instead.
+        assert_eq!(
+            execute("EXPLAIN SELECT * FROM test WHERE even = TRUE")?.0,
+            vec![vec![Value::String("Scan: test (even = TRUE) (estimated rows=1000)".into())]]
+        );
+        Ok(())
+    }
+}
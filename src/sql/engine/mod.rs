@@ -1,17 +1,29 @@
 //! The SQL engine provides fundamental CRUD storage operations.
 mod kv;
 pub mod raft;
-pub use kv::KV;
-pub use raft::{Raft, Status};
+mod vacuum;
+mod variables;
+pub use kv::{KV, MAX_VALUE_SIZE};
+pub use raft::{Change, KeyScan, Raft, Status, Watchers};
+pub use vacuum::{Cycle, VacuumScheduler};
+pub use variables::{Variables, MAX_RECURSIVE_ITERATIONS};
 
-use super::execution::ResultSet;
+use super::execution::{CancelToken, Limits, MemoryTracker, ResultSet};
 use super::parser::{ast, Parser};
 use super::plan::Plan;
-use super::schema::Catalog;
-use super::types::{Expression, Row, Value};
+use super::schema::{Catalog, Privilege, User};
+use super::types::{Batch, Column, Columns, DataType, Expression, Row, Rows, Value};
 use crate::error::{Error, Result};
+use crate::storage::kv::mvcc::{self, Vacuum};
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use std::time::{Duration, Instant};
+
+/// The built-in unprivileged user, which server sessions execute statements as until they
+/// authenticate as a superuser. It has no privileges, unless a user with this name is created and
+/// granted privileges.
+pub const ANONYMOUS_USER: &str = "anonymous";
 
 /// The SQL engine interface
 pub trait Engine: Clone {
@@ -21,13 +33,38 @@ pub trait Engine: Clone {
     /// Begins a transaction in the given mode
     fn begin(&self, mode: Mode) -> Result<Self::Transaction>;
 
-    /// Begins a session for executing individual statements
+    /// Begins a session for executing individual statements, as the built-in superuser. Servers
+    /// should switch untrusted sessions to a different user, see Session::set_user().
     fn session(&self) -> Result<Session<Self>> {
-        Ok(Session { engine: self.clone(), txn: None })
+        Ok(Session {
+            engine: self.clone(),
+            txn: None,
+            cancel: CancelToken::new(),
+            variables: Variables::default(),
+            prepared: HashMap::new(),
+            next_statement_id: 1,
+            user: None,
+        })
     }
 
     /// Resumes an active transaction with the given ID
     fn resume(&self, id: u64) -> Result<Self::Transaction>;
+
+    /// Garbage collects old row versions that are no longer visible to any transaction
+    fn vacuum(&self) -> Result<Vacuum>;
+
+    /// Returns the status of the underlying MVCC store
+    fn mvcc_status(&self) -> Result<mvcc::Status>;
+
+    /// Returns whether this node is the Raft leader, which schedules background vacuums. Engines
+    /// without Raft are always leaders.
+    fn is_leader(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Sets a deadline for requests made by the engine, including beginning transactions, after
+    /// which they error with Error::Timeout. Only engines with remote requests need to enforce it.
+    fn set_deadline(&mut self, _deadline: Option<Instant>) {}
 }
 
 /// An SQL transaction
@@ -40,19 +77,63 @@ pub trait Transaction: Catalog {
     fn commit(self) -> Result<()>;
     /// Rolls back the transaction
     fn rollback(self) -> Result<()>;
+    /// Sets a deadline for requests made by the transaction, see Engine::set_deadline()
+    fn set_deadline(&mut self, _deadline: Option<Instant>) {}
 
     /// Creates a new table row
     fn create(&mut self, table: &str, row: Row) -> Result<()>;
+    /// Creates a batch of new table rows, e.g. for bulk loads. Implementations can override this
+    /// to avoid per-row overhead.
+    fn create_batch(&mut self, table: &str, rows: Vec<Row>) -> Result<()> {
+        for row in rows {
+            self.create(table, row)?;
+        }
+        Ok(())
+    }
     /// Deletes a table row
     fn delete(&mut self, table: &str, id: &Value) -> Result<()>;
     /// Reads a table row, if it exists
     fn read(&self, table: &str, id: &Value) -> Result<Option<Row>>;
+    /// Reads a table row, if it exists, only decoding the values of the given columns. The other
+    /// columns are NULL.
+    fn read_columns(&self, table: &str, id: &Value, columns: &[usize]) -> Result<Option<Row>>;
     /// Reads an index entry, if it exists
     fn read_index(&self, table: &str, column: &str, value: &Value) -> Result<HashSet<Value>>;
     /// Scans a table's rows
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Scan>;
+    /// Scans a table's rows with primary keys of the bounds' datatype within the given range, in
+    /// primary key order
+    fn scan_range(
+        &self,
+        table: &str,
+        range: (Bound<Value>, Bound<Value>),
+        filter: Option<Expression>,
+    ) -> Result<Scan>;
+    /// Scans a table's rows like scan_range(), only decoding the values of the given columns. The
+    /// other columns are NULL, so the filter may only reference the given columns.
+    fn scan_columns(
+        &self,
+        table: &str,
+        range: (Bound<Value>, Bound<Value>),
+        columns: &[usize],
+        filter: Option<Expression>,
+    ) -> Result<Scan>;
     /// Scans a column's index entries
     fn scan_index(&self, table: &str, column: &str) -> Result<IndexScan>;
+    /// Scans a column's index entries for string values starting with the given prefix
+    fn scan_index_prefix(&self, table: &str, column: &str, prefix: &str) -> Result<IndexScan>;
+    /// Scans a column's index entries for values of the bounds' datatype within the given range,
+    /// in value order
+    fn scan_index_range(
+        &self,
+        table: &str,
+        column: &str,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<IndexScan>;
+    /// Deletes all rows of a table, along with its index entries, and resets its statistics to
+    /// zero rows. Returns the number of deleted rows. Errors if other tables have foreign key
+    /// references to any rows.
+    fn truncate(&mut self, table: &str) -> Result<u64>;
     /// Updates a table row
     fn update(&mut self, table: &str, id: &Value, row: Row) -> Result<()>;
 }
@@ -63,35 +144,313 @@ pub struct Session<E: Engine> {
     engine: E,
     /// The current session transaction, if any
     txn: Option<E::Transaction>,
+    /// The cancellation token of the current statement, reset when a statement begins
+    cancel: CancelToken,
+    /// The session variables, e.g. statement limits
+    variables: Variables,
+    /// Prepared statements, keyed by statement ID
+    prepared: HashMap<u64, Prepared>,
+    /// The ID of the next prepared statement
+    next_statement_id: u64,
+    /// The session user, whose privileges are checked before executing statements, or None for
+    /// the built-in superuser
+    user: Option<String>,
+}
+
+/// A prepared statement, i.e. a parsed statement with parameter placeholders. Plans are not
+/// cached, since they depend on the parameter values (e.g. for index lookups).
+struct Prepared {
+    statement: ast::Statement,
+    parameters: usize,
 }
 
-impl<E: Engine + 'static> Session<E> {
+impl<E: Engine + 'static> Session<E>
+where
+    E::Transaction: Send,
+{
+    /// Sets a statement timeout. Statements that don't complete in time error with Error::Timeout,
+    /// and their transaction is rolled back, including an explicit transaction. Query rows that
+    /// are fetched lazily are also subject to the timeout.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.variables.statement_timeout = timeout;
+        self
+    }
+
+    /// Returns the session's cancellation token, which can be used (e.g. by another thread) to
+    /// cancel the currently executing statement. The statement errors with Error::Cancelled, and
+    /// its transaction is rolled back, including an explicit transaction. Query rows that are
+    /// fetched lazily are also cancelled.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Limits the number of values that DISTINCT and ordered aggregates can buffer in memory for
+    /// a single statement. Statements that exceed it error.
+    pub fn with_max_aggregate_values(mut self, max_aggregate_values: Option<usize>) -> Self {
+        self.variables.max_aggregate_values = max_aggregate_values;
+        self
+    }
+
+    /// Limits the number of iterations of each recursive CTE in a statement that emit rows, which
+    /// guards against runaway recursion. Statements that exceed it error. Defaults to
+    /// MAX_RECURSIVE_ITERATIONS.
+    pub fn with_max_recursive_iterations(mut self, max_recursive_iterations: Option<u64>) -> Self {
+        self.variables.max_recursive_iterations = max_recursive_iterations;
+        self
+    }
+
+    /// Limits the approximate memory, in bytes, that each sort (i.e. ORDER BY) in a statement can
+    /// buffer. Larger sorts spill sorted runs to temporary files, which are merged.
+    pub fn with_max_sort_memory(mut self, max_sort_memory: Option<usize>) -> Self {
+        self.variables.max_sort_memory = max_sort_memory;
+        self
+    }
+
+    /// Limits the approximate memory, in bytes, that a single statement can buffer across all
+    /// hash joins, aggregations, sorts, and DISTINCT. Statements that exceed it error, and their
+    /// memory is released.
+    pub fn with_max_statement_memory(mut self, max_statement_memory: Option<usize>) -> Self {
+        self.variables.max_statement_memory = max_statement_memory;
+        self
+    }
+
+    /// Limits the number of rows a query can return. Queries that return more rows error when
+    /// the excess rows are fetched.
+    pub fn with_max_result_rows(mut self, max_result_rows: Option<u64>) -> Self {
+        self.variables.max_result_rows = max_result_rows;
+        self
+    }
+
+    /// Returns the session variables.
+    pub fn variables(&self) -> &Variables {
+        &self.variables
+    }
+
+    /// Returns the session user, or None for the built-in superuser.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Sets the session user, or the built-in superuser if None (the default). Statements are
+    /// then only executed if the user has the required privileges, see Plan::authorize(). Only
+    /// superusers can change the session user, so a trusted client can drop privileges before
+    /// handing the session over. Errors if the user does not exist (other than ANONYMOUS_USER),
+    /// or in a transaction.
+    pub fn set_user(&mut self, user: Option<&str>) -> Result<()> {
+        if self.txn.is_some() {
+            return Err(Error::Value("Can't change user in a transaction".into()));
+        }
+        self.authorize_superuser("change user")?;
+        if let Some(user) = user.filter(|user| *user != ANONYMOUS_USER) {
+            self.with_txn(Mode::ReadOnly, |txn| txn.must_read_user(user))?;
+        }
+        self.user = user.map(String::from);
+        Ok(())
+    }
+
+    /// Switches the session to the built-in superuser, regardless of the current user. This does
+    /// not authenticate anything: callers must only do so once the client has proven it is
+    /// allowed to, e.g. the server requires the configured superuser password. Errors in a
+    /// transaction.
+    pub fn set_superuser(&mut self) -> Result<()> {
+        if self.txn.is_some() {
+            return Err(Error::Value("Can't change user in a transaction".into()));
+        }
+        self.user = None;
+        Ok(())
+    }
+
+    /// Reads a session user from the catalog, falling back to an unprivileged user for
+    /// ANONYMOUS_USER. Errors if the user does not exist.
+    fn read_user<T: Transaction>(txn: &T, user: &str) -> Result<User> {
+        match txn.read_user(user)? {
+            Some(user) => Ok(user),
+            None if user == ANONYMOUS_USER => Ok(User::new(user.to_string(), false)),
+            None => txn.must_read_user(user),
+        }
+    }
+
+    /// Checks that the session user has the given privilege on a table, or errors with
+    /// Error::Unauthorized.
+    pub fn authorize(&mut self, table: &str, privilege: Privilege) -> Result<()> {
+        match self.user.clone() {
+            Some(user) => self.with_txn(Mode::ReadOnly, |txn| {
+                txn.must_read_table(table)?;
+                Self::read_user(txn, &user)?.authorize(table, privilege)
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the session user is a superuser, or errors with Error::Unauthorized naming the
+    /// given operation.
+    pub fn authorize_superuser(&mut self, operation: &str) -> Result<()> {
+        match self.user.clone() {
+            Some(user) => self.with_txn(Mode::ReadOnly, |txn| {
+                Self::read_user(txn, &user)?.authorize_superuser(operation)
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Executes a query, managing transaction status for the session
     pub fn execute(&mut self, query: &str) -> Result<ResultSet> {
+        let mut parser = Parser::new(query);
+        let statement = parser.parse()?;
+        Self::check_params(parser.parameters(), &[])?;
+        self.execute_statement(statement, Vec::new())
+    }
+
+    /// Prepares a statement with parameter placeholders for later execution, returning its ID.
+    /// The statement is only parsed once, while planning happens on every execution.
+    pub fn prepare(&mut self, query: &str) -> Result<u64> {
+        let mut parser = Parser::new(query);
+        let statement = parser.parse()?;
+        let id = self.next_statement_id;
+        self.next_statement_id += 1;
+        self.prepared.insert(id, Prepared { statement, parameters: parser.parameters() });
+        Ok(id)
+    }
+
+    /// Executes a prepared statement with the given parameter values. The values are bound to the
+    /// parameters as constants, so any type errors are reported where they are used.
+    pub fn execute_prepared(&mut self, id: u64, params: Vec<Value>) -> Result<ResultSet> {
+        let prepared = self
+            .prepared
+            .get(&id)
+            .ok_or_else(|| Error::Value(format!("Prepared statement {} does not exist", id)))?;
+        Self::check_params(prepared.parameters, &params)?;
+        let statement = prepared.statement.clone();
+        self.execute_statement(statement, params)
+    }
+
+    /// Validates a statement without executing it, by parsing and planning it, and returns the
+    /// columns of its result rows (if any). This catches e.g. unknown tables and columns, and
+    /// errors in constant expressions such as type errors. Only the schema is read, and nothing
+    /// is written.
+    pub fn validate(&mut self, query: &str) -> Result<Columns> {
+        let mut parser = Parser::new(query);
+        let statement = parser.parse()?;
+        Self::check_params(parser.parameters(), &[])?;
+        self.validate_statement(statement)
+    }
+
+    /// Validates a parsed statement, see validate().
+    fn validate_statement(&mut self, statement: ast::Statement) -> Result<Columns> {
+        match statement {
+            ast::Statement::Begin { .. }
+            | ast::Statement::Commit
+            | ast::Statement::Rollback
+            | ast::Statement::Vacuum => Ok(Vec::new()),
+            ast::Statement::Set { variable, value } => {
+                self.variables.clone().set(&variable, value.into_value()?)?;
+                Ok(Vec::new())
+            }
+            ast::Statement::Show { variable } => Ok(self.show(variable)?.0),
+            ast::Statement::Explain { statement, .. } => {
+                self.validate_statement(*statement)?;
+                Ok(vec![Column { name: Some("plan".into()), datatype: Some(DataType::String) }])
+            }
+            statement => {
+                let user = self.user.clone();
+                self.with_txn(Mode::ReadOnly, |txn| {
+                    let plan = Plan::build(statement, txn)?;
+                    Self::authorize_plan(&plan, user.as_deref(), txn)?;
+                    plan.check_constants()?;
+                    plan.columns(txn)
+                })
+            }
+        }
+    }
+
+    /// Plans and optimizes a statement, binding the given parameter values, after checking that
+    /// the given session user is authorized to execute it.
+    fn plan<T: Transaction>(
+        statement: ast::Statement,
+        params: Vec<Value>,
+        user: Option<&str>,
+        txn: &mut T,
+    ) -> Result<Plan> {
+        let plan = Plan::build_with_params(statement, txn, params)?;
+        Self::authorize_plan(&plan, user, txn)?;
+        plan.optimize(txn)
+    }
+
+    /// Checks that the given session user, if any, is authorized to execute the plan.
+    fn authorize_plan<T: Transaction>(plan: &Plan, user: Option<&str>, txn: &mut T) -> Result<()> {
+        match user {
+            Some(user) => plan.authorize(&Self::read_user(txn, user)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the number of parameter values matches the number of parameters.
+    fn check_params(parameters: usize, params: &[Value]) -> Result<()> {
+        if params.len() != parameters {
+            return Err(Error::Value(format!(
+                "Expected {} parameters, got {}",
+                parameters,
+                params.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Executes a parsed statement, binding the given parameter values.
+    fn execute_statement(
+        &mut self,
+        statement: ast::Statement,
+        params: Vec<Value>,
+    ) -> Result<ResultSet> {
+        self.cancel.reset();
+        let limits = Limits {
+            deadline: self.variables.statement_timeout.map(|timeout| Instant::now() + timeout),
+            cancel: Some(self.cancel.clone()),
+            max_aggregate_values: self.variables.max_aggregate_values,
+            max_recursive_iterations: self.variables.max_recursive_iterations,
+            max_sort_memory: self.variables.max_sort_memory,
+            memory: MemoryTracker::new(self.variables.max_statement_memory),
+            batch_size: None,
+        };
+        // Requests to remote engines, e.g. Raft round trips, are also subject to the deadline.
+        self.engine.set_deadline(limits.deadline);
+        if let Some(txn) = self.txn.as_mut() {
+            txn.set_deadline(limits.deadline);
+        }
         // FIXME We should match on self.txn as well, but get this error:
         // error[E0009]: cannot bind by-move and by-ref in the same pattern
         // ...which seems like an arbitrary compiler limitation
-        match Parser::new(query).parse()? {
+        match statement {
             ast::Statement::Begin { .. } if self.txn.is_some() => {
                 Err(Error::Value("Already in a transaction".into()))
             }
-            ast::Statement::Begin { readonly: true, version: None } => {
+            ast::Statement::Begin { readonly: None, version, serializable } => {
+                let readonly = Some(self.variables.default_transaction_read_only);
+                self.execute_statement(
+                    ast::Statement::Begin { readonly, version, serializable },
+                    params,
+                )
+            }
+            ast::Statement::Begin { readonly: Some(true), version: None, .. } => {
                 let txn = self.engine.begin(Mode::ReadOnly)?;
                 let result = ResultSet::Begin { id: txn.id(), mode: txn.mode() };
                 self.txn = Some(txn);
                 Ok(result)
             }
-            ast::Statement::Begin { readonly: true, version: Some(version) } => {
+            ast::Statement::Begin { readonly: Some(true), version: Some(version), .. } => {
                 let txn = self.engine.begin(Mode::Snapshot { version })?;
                 let result = ResultSet::Begin { id: txn.id(), mode: txn.mode() };
                 self.txn = Some(txn);
                 Ok(result)
             }
-            ast::Statement::Begin { readonly: false, version: Some(_) } => {
+            ast::Statement::Begin { readonly: Some(false), version: Some(_), .. } => {
                 Err(Error::Value("Can't start read-write transaction in a given version".into()))
             }
-            ast::Statement::Begin { readonly: false, version: None } => {
-                let txn = self.engine.begin(Mode::ReadWrite)?;
+            ast::Statement::Begin { readonly: Some(false), version: None, serializable } => {
+                let txn = self.engine.begin(match serializable {
+                    true => Mode::Serializable,
+                    false => Mode::ReadWrite,
+                })?;
                 let result = ResultSet::Begin { id: txn.id(), mode: txn.mode() };
                 self.txn = Some(txn);
                 Ok(result)
@@ -103,9 +462,13 @@ impl<E: Engine + 'static> Session<E> {
                 let txn = self.txn.take().unwrap();
                 let id = txn.id();
                 if let Err(err) = txn.commit() {
-                    // If the commit fails, we try to recover the transaction.
+                    // A serialization failure can't be retried, so the transaction is rolled back
+                    // and ended. On other errors, we try to recover the transaction.
                     if let Ok(t) = self.engine.resume(id) {
-                        self.txn = Some(t);
+                        match err {
+                            Error::Serialization => t.rollback()?,
+                            _ => self.txn = Some(t),
+                        }
                     }
                     return Err(err);
                 }
@@ -123,25 +486,83 @@ impl<E: Engine + 'static> Session<E> {
                 }
                 Ok(ResultSet::Rollback { id })
             }
-            ast::Statement::Explain(statement) => self.with_txn(Mode::ReadOnly, |txn| {
-                Ok(ResultSet::Explain(Plan::build(*statement, txn)?.optimize(txn)?.0))
-            }),
-            statement if self.txn.is_some() => Plan::build(statement, self.txn.as_mut().unwrap())?
-                .optimize(self.txn.as_mut().unwrap())?
-                .execute(self.txn.as_mut().unwrap()),
-            statement @ ast::Statement::Select { .. } => {
+            ast::Statement::Vacuum if self.txn.is_some() => {
+                Err(Error::Value("Can't vacuum in a transaction".into()))
+            }
+            ast::Statement::Vacuum => {
+                self.authorize_superuser("vacuum")?;
+                let Vacuum { versions, bytes } = self.engine.vacuum()?;
+                Ok(ResultSet::Vacuum { versions, bytes })
+            }
+            ast::Statement::Set { variable, value } => {
+                let value = value.into_value()?;
+                self.variables.set(&variable, value.clone())?;
+                Ok(ResultSet::Set { variable, value })
+            }
+            ast::Statement::Show { variable } => {
+                let (columns, rows) = self.show(variable)?;
+                Ok(ResultSet::Query { columns, rows: Rows::new(rows.into_iter().map(Ok)) })
+            }
+            ast::Statement::Explain { statement, analyze: false } => {
+                let user = self.user.clone();
+                self.with_txn(Mode::ReadOnly, |txn| {
+                    let plan = Self::plan(*statement, params, user.as_deref(), txn)?;
+                    Ok(Self::explain_result(plan.explain(txn)?))
+                })
+            }
+            ast::Statement::Explain { statement, analyze: true }
+                if self.txn.is_some() && !statement.is_query() =>
+            {
+                Err(Error::Value("Can't EXPLAIN ANALYZE a mutation in a transaction".into()))
+            }
+            ast::Statement::Explain { statement, analyze: true } => {
+                // Outside of an explicit transaction, with_txn() rolls back any mutations.
+                let mode = if statement.is_query() { Mode::ReadOnly } else { Mode::ReadWrite };
+                let user = self.user.clone();
+                self.with_txn(mode, |txn| {
+                    let plan = Self::plan(*statement, params, user.as_deref(), txn)?;
+                    Ok(Self::explain_result(plan.analyze(txn, limits)?))
+                })
+            }
+            statement if self.txn.is_some() => {
+                let txn = self.txn.as_mut().unwrap();
+                let result = Self::plan(statement, params, self.user.as_deref(), txn)?
+                    .execute_with_limits(txn, limits);
+                // The statement may have been partially applied, so roll back the transaction.
+                if let Err(Error::Timeout) | Err(Error::Cancelled) = result {
+                    self.txn.take().unwrap().rollback()?;
+                }
+                result.map(|result| self.limit_result_rows(result))
+            }
+            statement if statement.is_query() => {
                 let mut txn = self.engine.begin(Mode::ReadOnly)?;
-                let result =
-                    Plan::build(statement, &mut txn)?.optimize(&mut txn)?.execute(&mut txn);
-                txn.rollback()?;
-                result
+                let plan = Self::plan(statement, params, self.user.as_deref(), &mut txn)?;
+                match plan.execute_with_limits(&mut txn, limits) {
+                    // Engines may fetch rows lazily via the transaction, so it is kept open
+                    // until the rows have been consumed.
+                    Ok(ResultSet::Query { columns, rows }) => {
+                        Ok(self.limit_result_rows(ResultSet::Query {
+                            columns,
+                            rows: Rows::from_batches(RollbackRows { rows, txn: Some(txn) }),
+                        }))
+                    }
+                    result => {
+                        txn.rollback()?;
+                        result
+                    }
+                }
             }
             statement => {
-                let mut txn = self.engine.begin(Mode::ReadWrite)?;
-                match Plan::build(statement, &mut txn)?.optimize(&mut txn)?.execute(&mut txn) {
+                let mut txn =
+                    self.engine.begin(match self.variables.default_transaction_read_only {
+                        true => Mode::ReadOnly,
+                        false => Mode::ReadWrite,
+                    })?;
+                let plan = Self::plan(statement, params, self.user.as_deref(), &mut txn)?;
+                match plan.execute_with_limits(&mut txn, limits) {
                     Ok(result) => {
                         txn.commit()?;
-                        Ok(result)
+                        Ok(self.limit_result_rows(result))
                     }
                     Err(error) => {
                         txn.rollback()?;
@@ -152,6 +573,64 @@ impl<E: Engine + 'static> Session<E> {
         }
     }
 
+    /// Returns the result columns and rows of SHOW for the given session variable, or of SHOW ALL
+    /// if None: the variable names and values, where the values may have different datatypes.
+    fn show(&self, variable: Option<String>) -> Result<(Columns, Vec<Row>)> {
+        Ok(match variable {
+            Some(variable) => {
+                let value = self.variables.get(&variable)?;
+                (
+                    vec![Column { datatype: value.datatype(), name: Some(variable) }],
+                    vec![vec![value]],
+                )
+            }
+            None => (
+                vec![
+                    Column { name: Some("name".into()), datatype: Some(DataType::String) },
+                    Column { name: Some("value".into()), datatype: None },
+                ],
+                Variables::NAMES
+                    .iter()
+                    .map(|name| {
+                        Ok(vec![Value::String(name.to_string()), self.variables.get(name)?])
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+
+    /// Limits the rows of a query result to the max_result_rows session variable, if set. The
+    /// rows error when fetched if there are more.
+    fn limit_result_rows(&self, result: ResultSet) -> ResultSet {
+        match (result, self.variables.max_result_rows) {
+            (ResultSet::Query { columns, rows }, Some(max)) => {
+                let mut count = 0;
+                let rows = rows.map_batches(move |batch| {
+                    count += batch.len() as u64;
+                    if count > max {
+                        return Err(Error::Value(format!(
+                            "Query returned more than max_result_rows={} rows",
+                            max
+                        )));
+                    }
+                    Ok(batch)
+                });
+                ResultSet::Query { columns, rows }
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Returns an EXPLAIN plan as a single-column result set, with one row per line.
+    fn explain_result(plan: String) -> ResultSet {
+        let rows: Vec<Row> =
+            plan.lines().map(|line| vec![Value::String(line.to_string())]).collect();
+        ResultSet::Query {
+            columns: vec![Column { name: Some("plan".into()), datatype: Some(DataType::String) }],
+            rows: Rows::new(rows.into_iter().map(Ok)),
+        }
+    }
+
     /// Runs a closure in the session's transaction, or a new transaction if none is active.
     pub fn with_txn<R, F>(&mut self, mode: Mode, f: F) -> Result<R>
     where
@@ -172,11 +651,1728 @@ impl<E: Engine + 'static> Session<E> {
     }
 }
 
+/// A row batch iterator which rolls back the given transaction once the rows have been consumed,
+/// or when dropped.
+struct RollbackRows<T: Transaction> {
+    rows: Rows,
+    txn: Option<T>,
+}
+
+impl<T: Transaction> Iterator for RollbackRows<T> {
+    type Item = Result<Batch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rows.next_batch() {
+            Some(result) => Some(result),
+            None => self.txn.take()?.rollback().err().map(Err),
+        }
+    }
+}
+
+impl<T: Transaction> Drop for RollbackRows<T> {
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            txn.rollback().ok();
+        }
+    }
+}
+
 /// The transaction mode
 pub type Mode = crate::storage::kv::mvcc::Mode;
 
 /// A row scan iterator
-pub type Scan = Box<dyn DoubleEndedIterator<Item = Result<Row>> + Send>;
+pub type Scan = Box<dyn Iterator<Item = Result<Row>> + Send>;
 
 /// An index scan iterator
 pub type IndexScan = Box<dyn DoubleEndedIterator<Item = Result<(Value, HashSet<Value>)>> + Send>;
+
+#[cfg(test)]
+mod tests {
+    use super::super::schema::{ColumnStats, TableStats};
+    use super::super::types::BATCH_SIZE;
+    use super::*;
+    use crate::storage::kv;
+
+    /// Sets up an empty in-memory KV engine.
+    fn engine() -> Result<KV> {
+        KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))
+    }
+
+    /// Sets up a KV engine with a table of 100 rows, such that a cross join of the table with
+    /// itself three times yields a million rows.
+    fn setup() -> Result<KV> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value INTEGER)")?;
+        session.execute(&format!(
+            "INSERT INTO test VALUES {}",
+            (1..=100).map(|i| format!("({}, 0)", i)).collect::<Vec<_>>().join(", ")
+        ))?;
+        Ok(engine)
+    }
+
+    #[test]
+    fn timeout() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?.with_timeout(Some(Duration::from_millis(50)));
+
+        // A large cross join times out, whether the rows are aggregated or fetched lazily.
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test a, test b, test c").err(),
+            Some(Error::Timeout)
+        );
+        let result = session.execute("SELECT * FROM test a, test b, test c")?;
+        let mut rows = match result {
+            ResultSet::Query { rows, .. } => rows,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        assert_eq!(rows.find_map(|r| r.err()), Some(Error::Timeout));
+        drop(rows);
+
+        // Statements that complete in time are unaffected.
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test")?.into_value()?,
+            Value::Integer(100)
+        );
+        assert_eq!(engine.status()?.txns_active, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn timeout_rollback() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?.with_timeout(Some(Duration::from_millis(50)));
+
+        // A timeout in an explicit transaction rolls back the entire transaction.
+        session.execute("BEGIN")?;
+        session.execute("UPDATE test SET value = 1")?;
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test a, test b, test c").err(),
+            Some(Error::Timeout)
+        );
+        assert_eq!(engine.status()?.txns_active, 0);
+        assert_eq!(
+            session.execute("COMMIT").err(),
+            Some(Error::Value("Not in a transaction".into()))
+        );
+
+        // The writes were discarded, and their locks released, so a concurrent transaction
+        // can write the same rows.
+        let mut other = engine.session()?;
+        assert_eq!(
+            other.execute("SELECT COUNT(*) FROM test WHERE value = 0")?.into_value()?,
+            Value::Integer(100)
+        );
+        assert_eq!(other.execute("UPDATE test SET value = 2")?, ResultSet::Update { count: 100 });
+
+        // An implicit write transaction is also rolled back.
+        let mut session = engine.session()?.with_timeout(Some(Duration::from_nanos(1)));
+        assert_eq!(session.execute("UPDATE test SET value = 3").err(), Some(Error::Timeout));
+        assert_eq!(engine.status()?.txns_active, 0);
+        assert_eq!(
+            other.execute("SELECT COUNT(*) FROM test WHERE value = 2")?.into_value()?,
+            Value::Integer(100)
+        );
+        Ok(())
+    }
+
+    /// Cancels the session's current statement from another thread after the given delay.
+    fn cancel_after(session: &Session<KV>, delay: Duration) -> std::thread::JoinHandle<()> {
+        let cancel = session.cancel_token();
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            cancel.cancel();
+        })
+    }
+
+    #[test]
+    fn cancel() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+
+        // A large cross join is cancelled promptly, whether the rows are aggregated or fetched
+        // lazily.
+        let start = Instant::now();
+        let handle = cancel_after(&session, Duration::from_millis(50));
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test a, test b, test c, test d").err(),
+            Some(Error::Cancelled)
+        );
+        assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+        handle.join().unwrap();
+
+        let result = session.execute("SELECT * FROM test a, test b, test c, test d")?;
+        let mut rows = match result {
+            ResultSet::Query { rows, .. } => rows,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        assert!(rows.next().transpose()?.is_some());
+        session.cancel_token().cancel();
+        assert_eq!(rows.next().transpose(), Err(Error::Cancelled));
+        drop(rows);
+
+        // The session survives, and the next statement isn't cancelled.
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test")?.into_value()?,
+            Value::Integer(100)
+        );
+        assert_eq!(engine.status()?.txns_active, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_rollback() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+
+        // Cancelling a statement in an explicit transaction rolls back the entire transaction.
+        session.execute("BEGIN")?;
+        session.execute("UPDATE test SET value = 1")?;
+        let handle = cancel_after(&session, Duration::from_millis(50));
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test a, test b, test c, test d").err(),
+            Some(Error::Cancelled)
+        );
+        handle.join().unwrap();
+        assert_eq!(engine.status()?.txns_active, 0);
+        assert_eq!(
+            session.execute("COMMIT").err(),
+            Some(Error::Value("Not in a transaction".into()))
+        );
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test WHERE value = 0")?.into_value()?,
+            Value::Integer(100)
+        );
+
+        // An implicit write transaction is also rolled back.
+        let handle = cancel_after(&session, Duration::from_millis(50));
+        assert_eq!(
+            session
+                .execute("UPDATE test SET value = 2 WHERE id IN (SELECT a.id FROM test a, test b, test c, test d)")
+                .err(),
+            Some(Error::Cancelled)
+        );
+        handle.join().unwrap();
+        assert_eq!(engine.status()?.txns_active, 0);
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test WHERE value = 0")?.into_value()?,
+            Value::Integer(100)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exists_short_circuit() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?.with_timeout(Some(Duration::from_secs(10)));
+
+        // An uncorrelated EXISTS subquery stops after its first row, so a cross join that would
+        // yield 100 million rows completes immediately.
+        let subquery = "SELECT * FROM test a, test b, test c, test d";
+        assert_eq!(
+            session
+                .execute(&format!("SELECT COUNT(*) FROM test WHERE EXISTS ({})", subquery))?
+                .into_value()?,
+            Value::Integer(100)
+        );
+        assert_eq!(
+            session
+                .execute(&format!("SELECT COUNT(*) FROM test WHERE NOT EXISTS ({})", subquery))?
+                .into_value()?,
+            Value::Integer(0)
+        );
+        assert_eq!(
+            session.execute(&format!("SELECT EXISTS ({})", subquery))?.into_value()?,
+            Value::Boolean(true)
+        );
+        Ok(())
+    }
+
+    #[test]
+    // The number of values buffered by DISTINCT and ordered aggregates and SELECT DISTINCT in a
+    // single statement can be limited. Their results are covered by the tests/sql goldens.
+    fn max_aggregate_values() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session
+            .execute("CREATE TABLE sales (id INTEGER PRIMARY KEY, region STRING, item INTEGER)")?;
+        let values = (1..=200).map(|i| {
+            let item = if i % 11 == 0 { "NULL".into() } else { (i % 7).to_string() };
+            format!("({}, '{}', {})", i, ["a", "b", "c"][i % 3], item)
+        });
+        session.execute(&format!(
+            "INSERT INTO sales VALUES {}",
+            values.collect::<Vec<_>>().join(", ")
+        ))?;
+
+        let query = |session: &mut Session<KV>, query: &str| -> Result<Vec<Row>> {
+            match session.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect(),
+                result => panic!("Unexpected result {:?}", result),
+            }
+        };
+
+        // There are 7 distinct items in each of the 3 regions.
+        let mut session = engine.session()?.with_max_aggregate_values(Some(21));
+        query(&mut session, "SELECT region, COUNT(DISTINCT item) FROM sales GROUP BY region")?;
+        let mut session = engine.session()?.with_max_aggregate_values(Some(20));
+        assert_eq!(
+            session.execute("SELECT region, COUNT(DISTINCT item) FROM sales GROUP BY region").err(),
+            Some(Error::Value("Aggregates exceeded limit of 20 buffered values".into()))
+        );
+
+        // Ordered aggregates buffer all of their values, and count towards the same limit.
+        let mut session = engine.session()?.with_max_aggregate_values(Some(200));
+        query(&mut session, "SELECT STRING_AGG(region, '' ORDER BY id DESC) FROM sales")?;
+        let mut session = engine.session()?.with_max_aggregate_values(Some(199));
+        assert_eq!(
+            session.execute("SELECT STRING_AGG(region, '' ORDER BY id DESC) FROM sales").err(),
+            Some(Error::Value("Aggregates exceeded limit of 199 buffered values".into()))
+        );
+
+        // SELECT DISTINCT buffers the distinct rows it has seen, and counts towards the same limit.
+        let mut session = engine.session()?.with_max_aggregate_values(Some(3));
+        query(&mut session, "SELECT DISTINCT region FROM sales")?;
+        let mut session = engine.session()?.with_max_aggregate_values(Some(2));
+        assert_eq!(
+            query(&mut session, "SELECT DISTINCT region FROM sales").err(),
+            Some(Error::Value("Distinct exceeded limit of 2 buffered values".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn recursive_iterations() -> Result<()> {
+        let engine = engine()?;
+        let count = "WITH RECURSIVE t (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 10)
+            SELECT COUNT(*) FROM t";
+
+        // Counting to 10 takes 10 iterations of the recursive term, the last of which emits no
+        // rows.
+        let mut session = engine.session()?.with_max_recursive_iterations(Some(10));
+        assert_eq!(session.execute(count)?.into_value()?, Value::Integer(10));
+        let mut session = engine.session()?.with_max_recursive_iterations(Some(9));
+        assert_eq!(
+            session.execute(count).err(),
+            Some(Error::Value("Recursive CTE t exceeded limit of 9 iterations".into()))
+        );
+
+        // UNION ALL doesn't discard duplicate rows, so a cycle never terminates without the limit.
+        session.execute("CREATE TABLE edge (id INTEGER PRIMARY KEY, src INTEGER, dst INTEGER)")?;
+        session.execute("INSERT INTO edge VALUES (1, 1, 2), (2, 2, 3), (3, 3, 1)")?;
+        let cycle = "WITH RECURSIVE t (node) AS (
+                SELECT 1 UNION ALL SELECT dst FROM t JOIN edge ON node = src
+            ) SELECT COUNT(*) FROM t";
+        let mut session = engine.session()?.with_max_recursive_iterations(Some(100));
+        assert_eq!(
+            session.execute(cycle).err(),
+            Some(Error::Value("Recursive CTE t exceeded limit of 100 iterations".into()))
+        );
+
+        // Sessions are limited by default, so unbounded recursion errors rather than running
+        // forever.
+        let mut session = engine.session()?;
+        let unbounded = "WITH RECURSIVE r (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r)
+            SELECT * FROM r";
+        assert_eq!(
+            session.execute(unbounded).err(),
+            Some(Error::Value("Recursive CTE r exceeded limit of 1000 iterations".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn insert_on_conflict_concurrent() -> Result<()> {
+        let engine = setup()?;
+        let insert = "INSERT INTO test VALUES (101, 1), (1, 1) ON CONFLICT DO UPDATE SET value = 2";
+        let mut a = engine.session()?;
+        let mut b = engine.session()?;
+        let mut c = engine.session()?;
+        a.execute("BEGIN")?;
+        b.execute("BEGIN")?;
+
+        // Conflicts are resolved against the transaction's snapshot. Rows written by a concurrent
+        // transaction aren't visible, so the conflicting writes fail with a serialization error.
+        assert_eq!(a.execute(insert)?, ResultSet::Create { count: 2 });
+        assert_eq!(b.execute(insert).err(), Some(Error::Serialization));
+        b.execute("ROLLBACK")?;
+        c.execute("BEGIN")?;
+        a.execute("COMMIT")?;
+        assert_eq!(c.execute(insert).err(), Some(Error::Serialization));
+        c.execute("ROLLBACK")?;
+
+        // Once committed, a later transaction sees the rows and updates them instead.
+        assert_eq!(
+            c.execute("INSERT INTO test VALUES (101, 1) ON CONFLICT DO NOTHING")?,
+            ResultSet::Create { count: 0 }
+        );
+        assert_eq!(c.execute(insert)?, ResultSet::Create { count: 2 });
+        assert_eq!(
+            c.execute("SELECT value FROM test WHERE id = 101")?.into_value()?,
+            Value::Integer(2)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn update_from_concurrent() -> Result<()> {
+        let engine = setup()?;
+        let mut a = engine.session()?;
+        let mut b = engine.session()?;
+        a.execute("CREATE TABLE source (id INTEGER PRIMARY KEY, value INTEGER)")?;
+        a.execute("INSERT INTO source VALUES (1, 7), (2, 8)")?;
+        let update =
+            "UPDATE test SET value = source.value FROM source WHERE test.id = source.id AND source.id = 1";
+        a.execute("BEGIN")?;
+        b.execute("BEGIN")?;
+
+        // Writes to the same target row conflict, like for plain updates, but the source rows
+        // are only read, so concurrent writes to them don't.
+        assert_eq!(a.execute(update)?, ResultSet::Update { count: 1 });
+        assert_eq!(b.execute(update).err(), Some(Error::Serialization));
+        assert_eq!(
+            b.execute("DELETE FROM test USING source WHERE test.id = source.id + 1")?,
+            ResultSet::Delete { count: 2 }
+        );
+        assert_eq!(
+            a.execute("UPDATE source SET value = 9 WHERE id = 2")?,
+            ResultSet::Update { count: 1 }
+        );
+        a.execute("COMMIT")?;
+        b.execute("COMMIT")?;
+
+        let mut c = engine.session()?;
+        assert_eq!(
+            c.execute("SELECT value FROM test WHERE id = 1")?.into_value()?,
+            Value::Integer(7)
+        );
+        assert_eq!(c.execute("SELECT COUNT(*) FROM test")?.into_value()?, Value::Integer(98));
+        Ok(())
+    }
+
+    #[test]
+    fn serializable_write_skew() -> Result<()> {
+        let engine = setup()?;
+        let mut a = engine.session()?;
+        let mut b = engine.session()?;
+        let count = "SELECT COUNT(*) FROM test WHERE value = 0";
+
+        // Each transaction checks that a row has value 0 before setting a different row to 1,
+        // such that only one of them should succeed. Snapshot isolation allows write skew.
+        for (begin, skew) in
+            [("BEGIN", true), ("BEGIN TRANSACTION ISOLATION LEVEL SERIALIZABLE", false)]
+        {
+            a.execute("UPDATE test SET value = 0")?;
+            a.execute(begin)?;
+            b.execute(begin)?;
+            assert_eq!(a.execute(count)?.into_value()?, Value::Integer(100));
+            assert_eq!(b.execute(count)?.into_value()?, Value::Integer(100));
+            a.execute("UPDATE test SET value = 1 WHERE id = 1")?;
+            b.execute("UPDATE test SET value = 1 WHERE id = 2")?;
+            a.execute("COMMIT")?;
+            match b.execute("COMMIT") {
+                Ok(_) => assert!(skew),
+                Err(err) => {
+                    assert!(!skew);
+                    assert_eq!(err, Error::Serialization);
+                    // The failed commit rolled back and ended the transaction.
+                    assert_eq!(
+                        b.execute("ROLLBACK").err(),
+                        Some(Error::Value("Not in a transaction".into()))
+                    );
+                }
+            }
+            let expect = if skew { 98 } else { 99 };
+            assert_eq!(b.execute(count)?.into_value()?, Value::Integer(expect));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn update_not_null_txn() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+        session
+            .execute("CREATE TABLE flags (id INTEGER PRIMARY KEY, flag BOOLEAN NOT NULL INDEX)")?;
+        session.execute("INSERT INTO flags VALUES (1, TRUE), (2, FALSE)")?;
+
+        // A rejected update must not leave any partial changes, e.g. to indexes, since the
+        // transaction remains usable.
+        session.execute("BEGIN")?;
+        assert_eq!(
+            session.execute("UPDATE flags SET flag = NULL WHERE id = 1").err(),
+            Some(Error::Value("NULL value not allowed for column flag".into()))
+        );
+        assert_eq!(
+            session.execute("SELECT id FROM flags WHERE flag = TRUE")?.into_value()?,
+            Value::Integer(1)
+        );
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM flags WHERE flag IS NULL")?.into_value()?,
+            Value::Integer(0)
+        );
+        session.execute("COMMIT")?;
+        Ok(())
+    }
+
+    #[test]
+    fn vacuum() -> Result<()> {
+        let engine = setup()?;
+        let mut a = engine.session()?;
+        let mut b = engine.session()?;
+        b.execute("UPDATE test SET value = 1")?;
+        b.execute("DELETE FROM test WHERE id <= 50")?;
+        a.execute("BEGIN")?;
+        b.execute("DELETE FROM test WHERE id <= 60")?;
+
+        // Versions older than the open transaction's snapshot are removed, except the latest
+        // version of live rows, so the transaction still sees its rows.
+        assert_eq!(
+            a.execute("VACUUM").err(),
+            Some(Error::Value("Can't vacuum in a transaction".into()))
+        );
+        let vacuum = |session: &mut Session<KV>| match session.execute("VACUUM")? {
+            ResultSet::Vacuum { versions, bytes } if bytes > 0 => Ok(versions),
+            r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        };
+        assert_eq!(vacuum(&mut b)?, 200);
+        assert_eq!(a.execute("SELECT COUNT(*) FROM test")?.into_value()?, Value::Integer(50));
+        assert_eq!(b.execute("SELECT COUNT(*) FROM test")?.into_value()?, Value::Integer(40));
+
+        // Once the transaction commits, the rows it could see are removed as well.
+        a.execute("COMMIT")?;
+        assert_eq!(vacuum(&mut b)?, 20);
+        assert_eq!(b.execute("SELECT COUNT(*) FROM test")?.into_value()?, Value::Integer(40));
+        Ok(())
+    }
+
+    #[test]
+    fn prepared() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+        let query = |session: &mut Session<KV>, id: u64, params: Vec<Value>| -> Result<Vec<Row>> {
+            match session.execute_prepared(id, params)? {
+                ResultSet::Query { rows, .. } => rows.collect(),
+                result => panic!("Unexpected result {:?}", result),
+            }
+        };
+
+        // A prepared statement can be executed repeatedly with parameters of different types,
+        // which are bound as values rather than interpolated into the query.
+        let select = session.prepare("SELECT id, value FROM test WHERE id = $1")?;
+        let filter = session.prepare("SELECT id FROM test WHERE id * 2 = $1")?;
+        assert_eq!(
+            query(&mut session, select, vec![Value::Integer(7)])?,
+            vec![vec![Value::Integer(7), Value::Integer(0)]]
+        );
+        assert_eq!(query(&mut session, select, vec![Value::Null])?, Vec::<Row>::new());
+        assert_eq!(
+            query(&mut session, filter, vec![Value::Float(16.0)])?,
+            vec![vec![Value::Integer(8)]]
+        );
+        assert_eq!(
+            query(&mut session, select, vec![Value::String("1 OR TRUE".into())]),
+            Err(Error::Value("Can't compare INTEGER and STRING in id = 1 OR TRUE".into()))
+        );
+        assert_eq!(
+            query(&mut session, filter, vec![Value::String("1 OR TRUE".into())]),
+            Err(Error::Value("Can't compare INTEGER and STRING in id * 2 = 1 OR TRUE".into()))
+        );
+
+        let update = session.prepare("UPDATE test SET value = ? WHERE id = ?")?;
+        assert_eq!(
+            session.execute_prepared(update, vec![Value::Integer(3), Value::Integer(1)])?,
+            ResultSet::Update { count: 1 }
+        );
+        assert_eq!(
+            session.execute_prepared(update, vec![Value::String("x".into()), Value::Integer(2)]),
+            Err(Error::Value("Invalid datatype STRING for INTEGER column value".into()))
+        );
+        assert_eq!(
+            session.execute("SELECT SUM(value) FROM test")?.into_value()?,
+            Value::Integer(3)
+        );
+
+        // Parameters can be used in LIMIT and OFFSET.
+        let limit = session.prepare("SELECT id FROM test ORDER BY id LIMIT $1 OFFSET $2")?;
+        assert_eq!(
+            query(&mut session, limit, vec![Value::Integer(2), Value::Integer(3)])?,
+            vec![vec![Value::Integer(4)], vec![Value::Integer(5)]]
+        );
+        assert_eq!(
+            query(&mut session, limit, vec![Value::Integer(1), Value::Integer(99)])?,
+            vec![vec![Value::Integer(100)]]
+        );
+        assert_eq!(
+            query(&mut session, limit, vec![Value::String("1".into()), Value::Integer(0)]),
+            Err(Error::Value("Invalid limit 1".into()))
+        );
+
+        // The number of parameters must match the highest parameter number.
+        assert_eq!(
+            session.execute_prepared(limit, vec![Value::Integer(1)]).err(),
+            Some(Error::Value("Expected 2 parameters, got 1".into()))
+        );
+        let sparse = session.prepare("SELECT $3")?;
+        assert_eq!(
+            session.execute_prepared(sparse, vec![Value::Integer(1)]).err(),
+            Some(Error::Value("Expected 3 parameters, got 1".into()))
+        );
+        assert_eq!(
+            session.execute("SELECT * FROM test WHERE id = ?").err(),
+            Some(Error::Value("Expected 1 parameters, got 0".into()))
+        );
+        assert_eq!(
+            session.execute_prepared(99, vec![]).err(),
+            Some(Error::Value("Prepared statement 99 does not exist".into()))
+        );
+        assert_eq!(
+            session.prepare("SELECT $0").err(),
+            Some(Error::Parse("Parameter numbers start at $1".into()))
+        );
+
+        // Views are stored as SQL text, so they can't contain parameters.
+        let view = session.prepare("CREATE VIEW v AS SELECT * FROM test WHERE id = $1")?;
+        assert_eq!(
+            session.execute_prepared(view, vec![Value::Integer(1)]).err(),
+            Some(Error::Value("View v can't contain parameters".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn explain() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+        let explain = |session: &mut Session<KV>, query: &str| -> Result<Vec<String>> {
+            match session.execute(query)? {
+                ResultSet::Query { columns, rows } => {
+                    assert_eq!(
+                        columns,
+                        vec![Column {
+                            name: Some("plan".into()),
+                            datatype: Some(DataType::String)
+                        }]
+                    );
+                    rows.map(|row| Ok(row?.remove(0).to_string())).collect()
+                }
+                result => panic!("Unexpected result {:?}", result),
+            }
+        };
+        // Elapsed times vary between runs, so mask them.
+        let time = regex::Regex::new(r"time=\d+\.\d{3}ms").unwrap();
+        let analyze = |session: &mut Session<KV>, query: &str| -> Result<Vec<String>> {
+            Ok(explain(session, query)?
+                .into_iter()
+                .map(|line| time.replace(&line, "time=?").into_owned())
+                .collect())
+        };
+
+        // EXPLAIN outputs the plan without executing it.
+        assert_eq!(
+            explain(&mut session, "EXPLAIN SELECT id FROM test WHERE id > 90 ORDER BY id DESC")?,
+            vec![
+                "Order: test.id desc",
+                "└─ Projection: id",
+                "   └─ Filter: id > 90",
+                "      └─ KeyRangeScan: test [id] range > 90",
+            ]
+        );
+
+        // EXPLAIN ANALYZE annotates each node with its row count and elapsed time.
+        assert_eq!(
+            analyze(
+                &mut session,
+                "EXPLAIN ANALYZE SELECT a.id, b.id FROM test a JOIN test b ON a.id = b.id \
+                 WHERE a.id > 90 ORDER BY a.id LIMIT 5",
+            )?,
+            vec![
+                "Limit: 5 (actual rows=5 time=?)",
+                "└─ Presorted: a.id asc (sort elided) (actual rows=5 time=?)",
+                "   └─ Projection: a.id, b.id (actual rows=5 time=?)",
+                "      └─ MergeJoin: inner on a.id = b.id (actual rows=5 time=?)",
+                "         ├─ Filter: a.id > 90 (actual rows=5 time=?)",
+                "         │  └─ KeyRangeScan: test as a [id] range > 90 (actual rows=5 time=?)",
+                "         └─ Scan: test as b [id] (actual rows=100 time=?)",
+            ]
+        );
+
+        // Scans emit rows in batches, so the scan above fetched the whole table even though the
+        // merge join stopped after the rows with id > 90. Similarly, DISTINCT emits rows as they're
+        // seen, so a limit stops after the first batch when all rows have the same value.
+        assert_eq!(
+            analyze(&mut session, "EXPLAIN ANALYZE SELECT DISTINCT value FROM test LIMIT 1")?,
+            vec![
+                "Limit: 1 (actual rows=1 time=?)",
+                "└─ Distinct (actual rows=1 time=?)",
+                "   └─ Projection: value (actual rows=100 time=?)",
+                "      └─ Scan: test [value] (actual rows=100 time=?)",
+            ]
+        );
+
+        // Mutations are executed, and then rolled back.
+        assert_eq!(
+            analyze(&mut session, "EXPLAIN ANALYZE UPDATE test SET value = 1 WHERE id <= 10")?,
+            vec![
+                "Update: test (value=1) (actual rows=10 time=?)",
+                "└─ Filter: id < 10 OR id = 10 (actual rows=10 time=?)",
+                "   └─ KeyRangeScan: test range <= 10 (actual rows=10 time=?)",
+            ]
+        );
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test WHERE value = 1")?.into_value()?,
+            Value::Integer(0)
+        );
+        assert_eq!(engine.status()?.txns_active, 0);
+
+        // The recursive term of a recursive CTE is built anew for each iteration, and isn't
+        // instrumented.
+        assert_eq!(
+            analyze(
+                &mut session,
+                "EXPLAIN ANALYZE WITH RECURSIVE n (i) AS \
+                 (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 5) SELECT i FROM n",
+            )?,
+            vec![
+                "Projection: i (actual rows=5 time=?)",
+                "└─ CTE: n (inlined) (actual rows=5 time=?)",
+                "   └─ RecursiveUnion: n (all) (actual rows=5 time=?)",
+                "      ├─ Projection: 1 (actual rows=1 time=?)",
+                "      │  └─ Nothing (actual rows=1 time=?)",
+                "      └─ Projection: i + 1",
+                "         └─ Filter: i < 5",
+                "            └─ WorkTable: n",
+            ]
+        );
+
+        // In an explicit transaction, only queries can be analyzed.
+        session.execute("BEGIN")?;
+        assert_eq!(
+            analyze(&mut session, "EXPLAIN ANALYZE SELECT * FROM test WHERE id = 1")?,
+            vec!["KeyLookup: test (1) (actual rows=1 time=?)",]
+        );
+        assert_eq!(
+            session.execute("EXPLAIN ANALYZE DELETE FROM test").err(),
+            Some(Error::Value("Can't EXPLAIN ANALYZE a mutation in a transaction".into()))
+        );
+        session.execute("ROLLBACK")?;
+        Ok(())
+    }
+
+    #[test]
+    fn validate() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+
+        assert_eq!(
+            session.validate("SELECT id, value * 2, value AS v FROM test")?,
+            vec![
+                Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+                Column { name: None, datatype: Some(DataType::Integer) },
+                Column { name: Some("v".into()), datatype: Some(DataType::Integer) }
+            ]
+        );
+        assert_eq!(
+            session.validate("SELECT missing FROM test").err(),
+            Some(Error::Value("Unknown field missing".into()))
+        );
+        assert_eq!(
+            session.validate("SELECT id FROM test WHERE value = 1 + TRUE").err(),
+            Some(Error::Value("Can't add INTEGER and BOOLEAN in 1 + TRUE".into()))
+        );
+        // Lazily evaluated expressions are not checked.
+        session.validate("SELECT CASE WHEN value > 0 THEN 1 / 0 END FROM test")?;
+
+        // Mutations are planned but not executed, including in a transaction.
+        assert_eq!(session.validate("DELETE FROM test")?, vec![]);
+        session.execute("BEGIN")?;
+        session.validate("UPDATE test SET value = 1")?;
+        session.execute("COMMIT")?;
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test WHERE value = 0")?.into_value()?,
+            Value::Integer(100)
+        );
+        Ok(())
+    }
+
+    #[test]
+    // Plans are type checked before execution (see the check_* goldens in tests/sql), so a
+    // mutation with a type error doesn't modify any rows, even in a transaction where an execution
+    // error wouldn't roll back rows that were already modified.
+    fn check() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+        session.execute("BEGIN")?;
+        assert_eq!(
+            session.execute("DELETE FROM test WHERE id <= 10 RETURNING id * 'a'").err(),
+            Some(Error::Value("Can't multiply INTEGER and STRING in id * a".into()))
+        );
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test")?.into_value()?,
+            Value::Integer(100)
+        );
+        session.execute("COMMIT")?;
+        Ok(())
+    }
+
+    #[test]
+    // Session variables are set per session with SET, and shown with SHOW.
+    fn variables() -> Result<()> {
+        let engine = setup()?;
+        let mut session = engine.session()?;
+        let mut other = engine.session()?;
+        let query = |session: &mut Session<KV>, query: &str| -> Result<Vec<Row>> {
+            match session.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect(),
+                result => panic!("Unexpected result {:?}", result),
+            }
+        };
+
+        assert_eq!(session.execute("SHOW max_result_rows")?.into_value()?, Value::Null);
+        assert_eq!(
+            session.execute("SET max_result_rows = 10")?,
+            ResultSet::Set { variable: "max_result_rows".into(), value: Value::Integer(10) }
+        );
+        assert_eq!(session.execute("SHOW max_result_rows")?.into_value()?, Value::Integer(10));
+
+        // Queries returning more rows error when fetched, also in a transaction.
+        assert_eq!(query(&mut session, "SELECT * FROM test WHERE id <= 10")?.len(), 10);
+        assert_eq!(
+            query(&mut session, "SELECT * FROM test WHERE id <= 11"),
+            Err(Error::Value("Query returned more than max_result_rows=10 rows".into()))
+        );
+        session.execute("BEGIN")?;
+        assert_eq!(
+            query(&mut session, "SELECT * FROM test"),
+            Err(Error::Value("Query returned more than max_result_rows=10 rows".into()))
+        );
+        session.execute("COMMIT")?;
+
+        // The variable doesn't affect other sessions.
+        assert_eq!(other.execute("SHOW max_result_rows")?.into_value()?, Value::Null);
+        assert_eq!(query(&mut other, "SELECT * FROM test")?.len(), 100);
+
+        // NULL removes the limit.
+        session.execute("SET max_result_rows TO NULL")?;
+        assert_eq!(query(&mut session, "SELECT * FROM test")?.len(), 100);
+
+        // With default_transaction_read_only, transactions are read-only unless READ WRITE.
+        session.execute("SET default_transaction_read_only = TRUE")?;
+        assert_eq!(session.execute("DELETE FROM test").err(), Some(Error::ReadOnly));
+        assert!(matches!(session.execute("BEGIN")?, ResultSet::Begin { mode: Mode::ReadOnly, .. }));
+        session.execute("ROLLBACK")?;
+        assert!(matches!(
+            session.execute("BEGIN READ WRITE")?,
+            ResultSet::Begin { mode: Mode::ReadWrite, .. }
+        ));
+        session.execute("ROLLBACK")?;
+
+        // Statement timeouts are given in milliseconds.
+        session.execute("SET statement_timeout_ms = 50")?;
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test a, test b, test c").err(),
+            Some(Error::Timeout)
+        );
+        session.execute("SET statement_timeout_ms = NULL")?;
+
+        // SHOW ALL lists all variables.
+        assert_eq!(
+            query(&mut session, "SHOW ALL")?,
+            vec![
+                vec![Value::String("default_transaction_read_only".into()), Value::Boolean(true)],
+                vec![Value::String("max_aggregate_values".into()), Value::Null],
+                vec![Value::String("max_recursive_iterations".into()), Value::Integer(1000)],
+                vec![Value::String("max_result_rows".into()), Value::Null],
+                vec![Value::String("max_sort_memory".into()), Value::Null],
+                vec![Value::String("max_statement_memory".into()), Value::Null],
+                vec![Value::String("statement_timeout_ms".into()), Value::Null],
+            ]
+        );
+
+        // Unknown variables and invalid values error.
+        assert_eq!(
+            session.execute("SET unknown = 1").err(),
+            Some(Error::Value("Unknown session variable unknown".into()))
+        );
+        assert_eq!(
+            session.execute("SHOW unknown").err(),
+            Some(Error::Value("Unknown session variable unknown".into()))
+        );
+        assert_eq!(
+            session.execute("SET max_result_rows = -1").err(),
+            Some(Error::Value(
+                "Invalid value -1 for session variable max_result_rows, expected non-negative \
+                 INTEGER or NULL"
+                    .into()
+            ))
+        );
+        assert_eq!(
+            session.execute("SET default_transaction_read_only = 1").err(),
+            Some(Error::Value(
+                "Invalid value 1 for session variable default_transaction_read_only, expected \
+                 BOOLEAN"
+                    .into()
+            ))
+        );
+        assert_eq!(
+            session.execute("SET max_result_rows = 1 + 1").err(),
+            Some(Error::Parse("SET value must be a literal".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn result_columns() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute(
+            "CREATE TABLE test (id INTEGER PRIMARY KEY, name STRING, price DECIMAL(10, 2), \
+             weight FLOAT, created TIMESTAMP)",
+        )?;
+        session.execute(
+            "INSERT INTO test VALUES (1, 'a', 1.50, 2.0, TIMESTAMP '2024-01-01 00:00:00'), \
+             (2, 'b', NULL, NULL, NULL)",
+        )?;
+
+        // The result columns of a query are named after their column, label, or function, and
+        // have the datatypes inferred from the plan, both when validating and executing it.
+        // Untyped NULL columns default to STRING.
+        let column = |name: Option<&str>, datatype: Option<DataType>| Column {
+            name: name.map(|n| n.to_string()),
+            datatype,
+        };
+        let query = "SELECT id, name AS label, price * 2, price / id, weight + id, UPPER(name), \
+                     id > 1, CAST(id AS STRING), NULL, created FROM test";
+        let expect = vec![
+            column(Some("id"), Some(DataType::Integer)),
+            column(Some("label"), Some(DataType::String)),
+            column(None, Some(DataType::Decimal(38, 2))),
+            column(None, Some(DataType::Decimal(38, 8))),
+            column(None, Some(DataType::Float)),
+            column(Some("upper"), Some(DataType::String)),
+            column(None, Some(DataType::Boolean)),
+            column(None, Some(DataType::String)),
+            column(None, Some(DataType::String)),
+            column(Some("created"), Some(DataType::Timestamp)),
+        ];
+        assert_eq!(session.validate(query)?, expect);
+        match session.execute(query)? {
+            ResultSet::Query { columns, .. } => assert_eq!(columns, expect),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        // Aggregates are named after their function, and their datatype follows their input.
+        let query = "SELECT COUNT(*), SUM(price), AVG(id), MAX(weight) AS heaviest, \
+                     STRING_AGG(name, ','), name FROM test GROUP BY name";
+        let expect = vec![
+            column(Some("count"), Some(DataType::Integer)),
+            column(Some("sum"), Some(DataType::Decimal(38, 2))),
+            column(Some("avg"), Some(DataType::Integer)),
+            column(Some("heaviest"), Some(DataType::Float)),
+            column(Some("string_agg"), Some(DataType::String)),
+            column(Some("name"), Some(DataType::String)),
+        ];
+        match session.execute(query)? {
+            ResultSet::Query { columns, .. } => assert_eq!(columns, expect),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        // RETURNING columns are typed too.
+        match session.execute("UPDATE test SET price = price + 1 RETURNING id, price")? {
+            ResultSet::Returning { columns, .. } => assert_eq!(
+                columns,
+                vec![
+                    column(Some("id"), Some(DataType::Integer)),
+                    column(Some("price"), Some(DataType::Decimal(10, 2))),
+                ]
+            ),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        // Typed NULLs keep their datatype through constant folding and expressions, and UNION
+        // columns that only contain NULLs on one side take the datatype of the other side.
+        let queries = vec![
+            (
+                "SELECT CAST(NULL AS INTEGER), CAST(NULL AS DECIMAL(10, 2)) + 1, NULL",
+                vec![
+                    Some(DataType::Integer),
+                    Some(DataType::Decimal(38, 2)),
+                    Some(DataType::String),
+                ],
+            ),
+            (
+                "SELECT NULL, NULL, CAST(NULL AS BOOLEAN) UNION ALL SELECT 1, created, NULL \
+                 FROM test",
+                vec![Some(DataType::Integer), Some(DataType::Timestamp), Some(DataType::Boolean)],
+            ),
+            (
+                "SELECT id, NULL FROM test UNION SELECT NULL, CAST(NULL AS FLOAT)",
+                vec![Some(DataType::Integer), Some(DataType::Float)],
+            ),
+            ("SELECT NULL UNION SELECT NULL", vec![Some(DataType::String)]),
+        ];
+        for (query, datatypes) in queries {
+            let expect = datatypes.into_iter().map(|d| column(None, d)).collect::<Vec<_>>();
+            let strip = |columns: Vec<Column>| {
+                columns.into_iter().map(|c| column(None, c.datatype)).collect::<Vec<_>>()
+            };
+            assert_eq!(strip(session.validate(query)?), expect, "{}", query);
+            match session.execute(query)? {
+                ResultSet::Query { columns, rows } => {
+                    assert_eq!(strip(columns), expect, "{}", query);
+                    for row in rows {
+                        row?;
+                    }
+                }
+                result => panic!("Unexpected result {:?}", result),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn hash_join_large() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE a (id INTEGER PRIMARY KEY, value INTEGER)")?;
+        session.execute("CREATE TABLE b (id INTEGER PRIMARY KEY, a_id INTEGER)")?;
+        let rows = 50_000;
+        for batch in (0..rows).step_by(1000) {
+            let values = |f: &dyn Fn(i64) -> String| {
+                (batch..batch + 1000).map(f).collect::<Vec<_>>().join(", ")
+            };
+            session.execute(&format!(
+                "INSERT INTO a VALUES {}",
+                values(&|i| format!("({}, {})", i, i % 10))
+            ))?;
+            session.execute(&format!(
+                "INSERT INTO b VALUES {}",
+                values(&|i| format!(
+                    "({}, {})",
+                    i,
+                    if i % 5 == 0 { "NULL".into() } else { (i / 2).to_string() }
+                ))
+            ))?;
+        }
+
+        // A nested-loop join would need 2.5 billion row comparisons, which would not complete in
+        // reasonable time. Every 5th b row has a NULL key, and the rest match one of the first 25k
+        // a rows.
+        let start = std::time::Instant::now();
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM a JOIN b ON a.id = b.a_id")?.into_value()?,
+            Value::Integer(40_000)
+        );
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM a LEFT JOIN b ON a.id = b.a_id")?.into_value()?,
+            Value::Integer(65_000)
+        );
+        assert_eq!(
+            session
+                .execute(
+                    "SELECT COUNT(*) FROM a WHERE EXISTS (SELECT * FROM b WHERE b.a_id = a.id)"
+                )?
+                .into_value()?,
+            Value::Integer(25_000)
+        );
+        assert!(start.elapsed() < Duration::from_secs(60), "{:?}", start.elapsed());
+        Ok(())
+    }
+
+    #[test]
+    fn top_k() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session
+            .execute("CREATE TABLE test (id INTEGER PRIMARY KEY, a INTEGER INDEX, b INTEGER)")?;
+        session.execute(&format!(
+            "INSERT INTO test VALUES {}",
+            (0..1000)
+                .map(|i| format!("({}, {}, {})", i, (i * 7919) % 97, (i * 31) % 1000))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?;
+        let rows = |session: &mut Session<KV>, query: &str| -> Result<Vec<Row>> {
+            match session.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect(),
+                result => panic!("Unexpected result {:?}", result),
+            }
+        };
+        // Masks elapsed times, and returns the plan and its actual row counts.
+        let time = regex::Regex::new(r" time=\d+\.\d{3}ms").unwrap();
+        let analyze = |session: &mut Session<KV>, query: &str| -> Result<Vec<String>> {
+            Ok(rows(session, &format!("EXPLAIN ANALYZE {}", query))?
+                .into_iter()
+                .map(|mut row| time.replace(&row.remove(0).to_string(), "").into_owned())
+                .collect())
+        };
+
+        // An ordered index scan only reads the rows it emits, and matches a full sort.
+        for (query, expect) in [
+            ("SELECT id, a FROM test ORDER BY a DESC LIMIT 5", 0..5),
+            ("SELECT id, a FROM test ORDER BY a LIMIT 5 OFFSET 10", 10..15),
+            ("SELECT id, a FROM test WHERE b < 500 ORDER BY a LIMIT 3", 0..3),
+        ] {
+            let full = query.split(" LIMIT").next().unwrap();
+            assert_eq!(rows(&mut session, query)?, rows(&mut session, full)?[expect.clone()]);
+            let plan = analyze(&mut session, query)?;
+            let scan = plan.last().unwrap();
+            assert!(scan.contains("IndexOrderScan"), "{}", scan);
+            assert!(scan.ends_with(&format!("(actual rows={})", expect.end)), "{}", scan);
+        }
+
+        // A top-k sort of unindexed values must read all rows, but only emits the first ones.
+        let query = "SELECT id, b FROM test ORDER BY b DESC, id LIMIT 5 OFFSET 2";
+        assert_eq!(
+            rows(&mut session, query)?,
+            rows(&mut session, "SELECT id, b FROM test ORDER BY b DESC, id")?[2..7]
+        );
+        assert_eq!(
+            analyze(&mut session, query)?,
+            vec![
+                "Offset: 2 (actual rows=5)",
+                "└─ TopK: test.b desc, test.id asc limit 7 (actual rows=7)",
+                "   └─ Projection: id, b (actual rows=1000)",
+                "      └─ Scan: test [id, b] (actual rows=1000)",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    // Keyset pagination fetches each page with a primary key range scan starting after the last
+    // key of the previous page, such that pages are stable even when lower keys are inserted.
+    fn keyset_pagination() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        let mut writer = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)")?;
+        session.execute(&format!(
+            "INSERT INTO test VALUES {}",
+            (0..100).map(|i| format!("({}, 'a')", i * 2)).collect::<Vec<_>>().join(", ")
+        ))?;
+        let page = session.prepare("SELECT id FROM test WHERE id > $1 ORDER BY id LIMIT 7")?;
+
+        let mut ids = Vec::new();
+        let mut last = Value::Integer(-1);
+        loop {
+            let rows = match session.execute_prepared(page, vec![last.clone()])? {
+                ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+                result => panic!("Unexpected result {:?}", result),
+            };
+            if rows.is_empty() {
+                break;
+            }
+            assert!(rows.len() <= 7);
+            ids.extend(rows.into_iter().map(|mut row| row.remove(0)));
+            last = ids.last().cloned().unwrap();
+            // Insert a row before the current page, which must not shift later pages.
+            if let Value::Integer(id) = last {
+                writer.execute(&format!("INSERT INTO test VALUES ({}, 'b')", id - 1))?;
+            }
+        }
+        assert_eq!(ids, (0..100).map(|i| Value::Integer(i * 2)).collect::<Vec<_>>());
+
+        // Each page only reads the rows it emits.
+        let plan = match session
+            .execute("EXPLAIN ANALYZE SELECT id FROM test WHERE id > 50 ORDER BY id LIMIT 7")?
+        {
+            ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        let scan = plan.last().unwrap()[0].to_string();
+        assert!(scan.contains("└─ KeyRangeScan: test [id] range > 50"), "{}", scan);
+        assert!(scan.contains("(actual rows=7 "), "{}", scan);
+        Ok(())
+    }
+
+    #[test]
+    fn sort_spill() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, a INTEGER, b STRING)")?;
+        let count = 5000;
+        for batch in (0..count).step_by(1000) {
+            session.execute(&format!(
+                "INSERT INTO test VALUES {}",
+                (batch..batch + 1000)
+                    .map(|i| match i % 11 {
+                        0 => format!("({}, NULL, 'b{}')", i, (i * 31) % 13),
+                        _ => format!("({}, {}, 'b{}')", i, (i * 7919) % 97, (i * 31) % 13),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))?;
+        }
+        let query = "SELECT id, a, b FROM test ORDER BY a DESC NULLS FIRST, b ASC";
+        let collect = |rows: Rows| rows.collect::<Result<Vec<_>>>();
+        let expect = match session.execute(query)? {
+            ResultSet::Query { rows, .. } => collect(rows)?,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        assert_eq!(expect.len(), count as usize);
+        let nulls = expect.iter().take_while(|row| row[1] == Value::Null).count();
+        assert_eq!(nulls, (0..count).filter(|i| i % 11 == 0).count());
+        assert!(expect[nulls..].iter().all(|row| row[1] != Value::Null));
+
+        // Returns the spill directories in the temporary directory.
+        let spills = || -> HashSet<std::path::PathBuf> {
+            std::fs::read_dir(std::env::temp_dir())
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .filter(|p| p.file_name().unwrap().to_string_lossy().starts_with("toydb-sort-"))
+                .collect()
+        };
+        let before = spills();
+
+        // A sort with a budget far smaller than the rows spills them to several runs, which are
+        // merged into the same (stable) order and removed once all rows are fetched.
+        let mut session = engine.session()?.with_max_sort_memory(Some(16 * 1024));
+        let mut rows = match session.execute(query)? {
+            ResultSet::Query { rows, .. } => rows,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        let mut actual = vec![rows.next().unwrap()?];
+        let spilled: HashSet<_> = spills().difference(&before).cloned().collect();
+        assert_eq!(spilled.len(), 1);
+        let runs = std::fs::read_dir(spilled.iter().next().unwrap())?.count();
+        assert!(runs > 5, "{} runs", runs);
+        actual.extend(collect(rows)?);
+        assert_eq!(actual, expect);
+        assert!(spilled.iter().all(|dir| !dir.exists()));
+
+        // The spill files are also removed when the rows are dropped before they're all fetched.
+        let mut rows = match session.execute(query)? {
+            ResultSet::Query { rows, .. } => rows,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        assert_eq!(rows.next().transpose()?.as_ref(), expect.first());
+        let spilled: HashSet<_> = spills().difference(&before).cloned().collect();
+        assert_eq!(spilled.len(), 1);
+        drop(rows);
+        assert!(spilled.iter().all(|dir| !dir.exists()));
+
+        // A sort beneath a limit only keeps the first rows in a bounded heap, without spilling.
+        let mut rows = match session.execute(&format!("{} LIMIT 10 OFFSET 5", query))? {
+            ResultSet::Query { rows, .. } => rows,
+            result => panic!("Unexpected result {:?}", result),
+        };
+        assert_eq!(rows.next().transpose()?.as_ref(), expect.get(5));
+        assert!(spills().difference(&before).next().is_none());
+        assert_eq!(collect(rows)?, expect[6..15].to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn statement_memory() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session
+            .execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value INTEGER, label STRING)")?;
+        session.execute(&format!(
+            "INSERT INTO test VALUES {}",
+            (0..2000)
+                .map(|i| format!("({}, {}, 'label {}')", i, i % 10, i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?;
+
+        // Statements that buffer many rows or keys error, while those that buffer few succeed.
+        let mut limited = engine.session()?.with_max_statement_memory(Some(16 * 1024));
+        let exceeded = Error::Value("statement memory limit exceeded".into());
+        for (query, ok) in [
+            ("SELECT id, COUNT(*) FROM test GROUP BY id", false),
+            ("SELECT value, COUNT(*) FROM test GROUP BY value", true),
+            ("SELECT COUNT(DISTINCT label) FROM test", false),
+            ("SELECT COUNT(DISTINCT value) FROM test", true),
+            ("SELECT DISTINCT label FROM test", false),
+            ("SELECT DISTINCT value FROM test", true),
+            ("SELECT * FROM test ORDER BY label", false),
+            ("SELECT * FROM test ORDER BY label LIMIT 10", true),
+            ("SELECT COUNT(*) FROM test a JOIN test b ON a.label = b.label", false),
+            ("SELECT COUNT(*) FROM test a JOIN test b ON a.label = b.label WHERE a.id < 10", true),
+            ("SELECT COUNT(*) FROM test WHERE label IN (SELECT label FROM test)", false),
+            (
+                "SELECT COUNT(*) FROM test WHERE value IN (SELECT value FROM test WHERE id < 10)",
+                true,
+            ),
+        ] {
+            let result = limited.execute(query).and_then(|r| match r {
+                ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>(),
+                r => panic!("Unexpected result {:?}", r),
+            });
+            match ok {
+                true => assert!(result.is_ok(), "{}: {:?}", query, result),
+                false => assert_eq!(result, Err(exceeded.clone()), "{}", query),
+            }
+        }
+
+        // The session remains usable, and sessions without a limit can run the statements.
+        limited.execute("BEGIN")?;
+        assert_eq!(limited.execute("SELECT id FROM test ORDER BY label"), Err(exceeded));
+        limited.execute("INSERT INTO test VALUES (2000, 0, 'label 2000')")?;
+        limited.execute("COMMIT")?;
+        assert_eq!(
+            session.execute("SELECT COUNT(DISTINCT label) FROM test")?.into_value()?,
+            Value::Integer(2001)
+        );
+
+        // The memory is charged while rows are buffered, and released when the result rows are
+        // exhausted or dropped, or when execution errors.
+        let execute = |query: &str, memory: &MemoryTracker| -> Result<Rows> {
+            let mut txn = engine.begin(Mode::ReadOnly)?;
+            let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?.optimize(&mut txn)?;
+            let limits = Limits { memory: memory.clone(), ..Limits::default() };
+            match plan.execute_with_limits(&mut txn, limits)? {
+                ResultSet::Query { rows, .. } => Ok(rows),
+                r => panic!("Unexpected result {:?}", r),
+            }
+        };
+        let memory = MemoryTracker::new(None);
+        let mut rows = execute("SELECT * FROM test ORDER BY label", &memory)?;
+        assert!(rows.next().is_some());
+        assert!(memory.used() > 0);
+        assert_eq!(rows.count(), 2000);
+        assert_eq!(memory.used(), 0);
+
+        let mut rows = execute("SELECT value, COUNT(*) FROM test GROUP BY value", &memory)?;
+        assert!(rows.next().is_some());
+        assert!(memory.used() > 0);
+        drop(rows);
+        assert_eq!(memory.used(), 0);
+
+        let memory = MemoryTracker::new(Some(1024));
+        let result =
+            execute("SELECT DISTINCT label FROM test", &memory)?.collect::<Result<Vec<_>>>();
+        assert!(result.is_err());
+        assert_eq!(memory.used(), 0);
+        Ok(())
+    }
+
+    /// Executes a query with the given scan batch size, returning its rows.
+    fn execute_batched(engine: &KV, query: &str, batch_size: usize) -> Result<Vec<Row>> {
+        let mut txn = engine.begin(Mode::ReadOnly)?;
+        let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?.optimize(&mut txn)?;
+        let limits = Limits { batch_size: Some(batch_size), ..Limits::default() };
+        let rows = match plan.execute_with_limits(&mut txn, limits)? {
+            ResultSet::Query { rows, .. } => rows.collect(),
+            r => panic!("Unexpected result {:?}", r),
+        };
+        txn.rollback()?;
+        rows
+    }
+
+    #[test]
+    // Rows are passed between executors in batches, which doesn't affect the results, even when
+    // limits and offsets cut across batch boundaries.
+    fn batches() -> Result<()> {
+        let engine = setup()?;
+        engine.session()?.execute("UPDATE test SET value = id % 7")?;
+
+        for query in [
+            "SELECT * FROM test",
+            "SELECT id, value * 2 FROM test WHERE value > 2",
+            "SELECT id FROM test WHERE value = 3 LIMIT 5 OFFSET 2",
+            "SELECT id FROM test LIMIT 10 OFFSET 95",
+            "SELECT id FROM test LIMIT 0",
+            "SELECT COUNT(*), SUM(id) FROM test WHERE value != 0",
+            "SELECT value, COUNT(*) FROM test GROUP BY value ORDER BY value",
+            "SELECT a.id, b.id FROM test a JOIN test b ON a.id = b.value * 10 LIMIT 7",
+        ] {
+            let expect = execute_batched(&engine, query, 1)?;
+            for batch_size in [3, 7, BATCH_SIZE] {
+                assert_eq!(execute_batched(&engine, query, batch_size)?, expect, "{}", query);
+            }
+        }
+
+        // Errors are returned for the batch, e.g. for a division by zero in a filter.
+        assert_eq!(
+            execute_batched(&engine, "SELECT * FROM test WHERE 1 / value = 1", 10),
+            Err(Error::Value("Can't divide by zero".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    // A DELETE with a subquery reading the target table deletes the rows that match before the
+    // statement, even when the subquery is evaluated lazily between deletes, i.e. with small
+    // batches.
+    fn delete_subquery_batches() -> Result<()> {
+        for batch_size in [1, 3, BATCH_SIZE] {
+            let engine = engine()?;
+            let mut session = engine.session()?;
+            session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY)")?;
+            session.execute("INSERT INTO test VALUES (1), (2), (3), (4), (5)")?;
+
+            // Every row but the first has a predecessor, so all but the first are deleted.
+            let mut txn = engine.begin(Mode::ReadWrite)?;
+            let query = "DELETE FROM test WHERE EXISTS \
+                (SELECT * FROM test t WHERE t.id < test.id AND t.id + 1 >= test.id)";
+            let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?.optimize(&mut txn)?;
+            let limits = Limits { batch_size: Some(batch_size), ..Limits::default() };
+            assert_eq!(plan.execute_with_limits(&mut txn, limits)?, ResultSet::Delete { count: 4 });
+            txn.commit()?;
+            assert_eq!(
+                execute_batched(&engine, "SELECT * FROM test", BATCH_SIZE)?,
+                vec![vec![Value::Integer(1)]]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    // TRUNCATE deletes all rows and index entries of a table within the transaction, and resets
+    // its statistics to zero rows: other transactions see the rows until commit, and a rollback
+    // restores them.
+    fn truncate() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, name STRING INDEX)")?;
+        session.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b'), (3, 'a')")?;
+        session.execute("ANALYZE test")?;
+        let all = |engine: &KV| execute_batched(engine, "SELECT * FROM test", BATCH_SIZE);
+        let before = all(&engine)?;
+        assert_eq!(before.len(), 3);
+
+        session.execute("BEGIN")?;
+        assert_eq!(session.execute("TRUNCATE test")?, ResultSet::Delete { count: 3 });
+        match session.execute("SELECT * FROM test")? {
+            ResultSet::Query { rows, .. } => assert_eq!(rows.count(), 0),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(all(&engine)?, before);
+        session.execute("ROLLBACK")?;
+        assert_eq!(all(&engine)?, before);
+
+        session.execute("TRUNCATE TABLE test")?;
+        assert_eq!(all(&engine)?, Vec::<Row>::new());
+        let txn = engine.begin(Mode::ReadOnly)?;
+        assert_eq!(txn.scan_index("test", "name")?.count(), 0);
+        assert_eq!(txn.read_index("test", "name", &Value::String("a".into()))?, HashSet::new());
+        let empty = ColumnStats { distinct: 0, nulls: 0, min: Value::Null, max: Value::Null };
+        assert_eq!(
+            txn.read_table_stats("test")?,
+            Some(TableStats { rows: 0, columns: vec![empty.clone(), empty] })
+        );
+        txn.rollback()?;
+
+        // The table remains usable.
+        session.execute("INSERT INTO test VALUES (1, 'c')")?;
+        assert_eq!(
+            execute_batched(&engine, "SELECT id FROM test WHERE name = 'c'", BATCH_SIZE)?,
+            vec![vec![Value::Integer(1)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    // COPY TO and COPY FROM round-trip rows via CSV files, including values with delimiters,
+    // quotes, and line breaks, as well as NULLs, empty strings, and binary values. Generated
+    // columns are recomputed on import.
+    fn copy() -> Result<()> {
+        let dir = tempdir::TempDir::new("toydb")?;
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        for table in &["source", "target"] {
+            session.execute(&format!(
+                "CREATE TABLE {} (id INTEGER PRIMARY KEY, name STRING, score FLOAT, data BYTEA,
+                    twice INTEGER GENERATED ALWAYS AS (id * 2) STORED)",
+                table
+            ))?;
+        }
+        session.execute(
+            r#"INSERT INTO source (id, name, score, data) VALUES
+                (1, 'a,b', 1.5, x'00ff'),
+                (2, 'say "hi"', NULL, NULL),
+                (3, 'two
+lines', -0.25, x''),
+                (4, '', 3.0, x'01'),
+                (5, NULL, 0.0, NULL)"#,
+        )?;
+
+        let csv = path("source.csv");
+        assert_eq!(
+            session.execute(&format!("COPY source TO '{}' WITH (HEADER)", csv))?,
+            ResultSet::Copy { count: 5 }
+        );
+        assert_eq!(
+            std::fs::read_to_string(&csv)?,
+            "id,name,score,data\n1,\"a,b\",1.5,\\x00ff\n2,\"say \"\"hi\"\"\",,\n\
+             3,\"two\nlines\",-0.25,\\x\n4,\"\",3,\\x01\n5,,0,\n"
+        );
+        assert_eq!(
+            session.execute(&format!("COPY target FROM '{}' WITH (HEADER TRUE)", csv))?,
+            ResultSet::Create { count: 5 }
+        );
+        let source = execute_batched(&engine, "SELECT * FROM source", BATCH_SIZE)?;
+        assert_eq!(execute_batched(&engine, "SELECT * FROM target", BATCH_SIZE)?, source);
+
+        // Without a header, fields map to the non-generated columns in order. A header can give
+        // a subset of columns in any order.
+        let tsv = path("other.tsv");
+        std::fs::write(&tsv, "6\tf\t\t\n")?;
+        session.execute(&format!("COPY target FROM '{}' WITH (DELIMITER '\t')", tsv))?;
+        std::fs::write(&tsv, "name\tid\ng\t7\n")?;
+        session.execute(&format!("COPY target FROM '{}' WITH (HEADER, DELIMITER '\t')", tsv))?;
+        assert_eq!(
+            execute_batched(&engine, "SELECT * FROM target WHERE id > 5", BATCH_SIZE)?,
+            vec![
+                vec![
+                    Value::Integer(6),
+                    Value::String("f".into()),
+                    Value::Null,
+                    Value::Null,
+                    Value::Integer(12)
+                ],
+                vec![
+                    Value::Integer(7),
+                    Value::String("g".into()),
+                    Value::Null,
+                    Value::Null,
+                    Value::Integer(14)
+                ],
+            ]
+        );
+
+        // Errors report the line of the offending record, and roll back the whole import.
+        std::fs::write(&tsv, "8,h,1.0,\n9,\"i\nj\",x,\n")?;
+        assert_eq!(
+            session.execute(&format!("COPY target FROM '{}'", tsv)),
+            Err(Error::Value("Line 2, column score: Can't cast x to FLOAT".into()))
+        );
+        std::fs::write(&tsv, "8,h,1.0\n")?;
+        assert_eq!(
+            session.execute(&format!("COPY target FROM '{}'", tsv)),
+            Err(Error::Value("Line 1: expected 4 values, got 3".into()))
+        );
+        std::fs::write(&tsv, "id,double\n8,16\n")?;
+        assert!(session.execute(&format!("COPY target FROM '{}' WITH (HEADER)", tsv)).is_err());
+        assert_eq!(execute_batched(&engine, "SELECT * FROM target", BATCH_SIZE)?.len(), 7);
+        assert!(matches!(
+            session.execute(&format!("COPY target FROM '{}'", path("missing.csv"))),
+            Err(Error::Value(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    // Scans spanning many batches give the same results when rows are passed between executors
+    // in batches as when they're passed one at a time, both for plain and filtered aggregates.
+    fn batches_scan() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value INTEGER)")?;
+        let count = 10_000;
+        for batch in (0..count).step_by(1000) {
+            session.execute(&format!(
+                "INSERT INTO test VALUES {}",
+                (batch..batch + 1000)
+                    .map(|i| format!("({}, {})", i, i % 100))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))?;
+        }
+
+        for (query, expect) in [
+            ("SELECT COUNT(*) FROM test", vec![vec![Value::Integer(count)]]),
+            (
+                "SELECT COUNT(*) FROM test WHERE value * 2 >= 100 AND id % 2 = 0",
+                vec![vec![Value::Integer(count / 4)]],
+            ),
+        ] {
+            assert_eq!(execute_batched(&engine, query, 1)?, expect, "{}", query);
+            assert_eq!(execute_batched(&engine, query, BATCH_SIZE)?, expect, "{}", query);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn projection_pushdown() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute(&format!(
+            "CREATE TABLE wide_table (id INTEGER PRIMARY KEY, a INTEGER, {})",
+            (0..20).map(|i| format!("c{} STRING", i)).collect::<Vec<_>>().join(", ")
+        ))?;
+        session.execute(&format!(
+            "INSERT INTO wide_table VALUES {}",
+            (0..10)
+                .map(|i| format!("({}, {}, {})", i, i * 10, vec!["'value'"; 20].join(", ")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?;
+
+        // Counts the values decoded from stored rows while executing a query, along with its rows.
+        let mut decoded = |query: &str| -> Result<(u64, Vec<Row>)> {
+            super::kv::DECODED_VALUES.with(|d| d.set(0));
+            let rows = match session.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+                result => panic!("Unexpected result {:?}", result),
+            };
+            Ok((super::kv::DECODED_VALUES.with(|d| d.get()), rows))
+        };
+
+        // Star projections decode all 22 columns.
+        let (count, rows) = decoded("SELECT * FROM wide_table")?;
+        assert_eq!((count, rows.len()), (220, 10));
+
+        // Projections only decode the projected columns, and filters and sorts their own columns.
+        // Scans also decode the primary key, to resume scans of the next batch of rows.
+        let (count, rows) = decoded("SELECT a FROM wide_table")?;
+        assert_eq!(count, 20);
+        assert_eq!(rows, (0..10).map(|i| vec![Value::Integer(i * 10)]).collect::<Vec<_>>());
+
+        let (count, rows) =
+            decoded("SELECT a FROM wide_table WHERE c3 = 'value' ORDER BY id DESC")?;
+        assert_eq!(count, 30);
+        assert_eq!(rows, (0..10).rev().map(|i| vec![Value::Integer(i * 10)]).collect::<Vec<_>>());
+
+        // Lookups also only decode the projected columns.
+        let (count, rows) = decoded("SELECT c5 FROM wide_table WHERE id = 3")?;
+        assert_eq!(count, 1);
+        assert_eq!(rows, vec![vec![Value::String("value".into())]]);
+        Ok(())
+    }
+
+    #[test]
+    fn index_only() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute(
+            "CREATE TABLE test (id INTEGER PRIMARY KEY, a INTEGER INDEX, d DECIMAL(10, 2) INDEX, \
+             s STRING INDEX, c STRING)",
+        )?;
+        session.execute(
+            "INSERT INTO test VALUES (1, 1, 1.5, 'foo', 'x'), (2, NULL, NULL, NULL, 'y'), \
+             (3, 3, 2, 'foobar', 'z'), (4, NULL, 1.50, 'bar', 'w')",
+        )?;
+
+        // Executes a query, returning its rows and the number of table rows read.
+        let mut query = |query: &str| -> Result<(Vec<Row>, u64)> {
+            super::kv::DECODED_ROWS.with(|d| d.set(0));
+            let rows = match session.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+                result => panic!("Unexpected result {:?}", result),
+            };
+            Ok((rows, super::kv::DECODED_ROWS.with(|d| d.get())))
+        };
+        let (i, d, s) = (Value::Integer, Value::Decimal, |s: &str| Value::String(s.into()));
+        let dec = |s: &str| d(s.parse().unwrap());
+
+        // Queries only using the indexed column and primary key are index-only, including NULL
+        // values, and don't read any table rows.
+        let (plan, _) = query("EXPLAIN SELECT id, a FROM test WHERE a IS NULL ORDER BY id")?;
+        assert_eq!(
+            plan.last().unwrap()[0],
+            s("   └─ IndexLookup: test [id, a] column a index only (NULL)")
+        );
+        assert_eq!(
+            query("SELECT id, a FROM test WHERE a IS NULL ORDER BY id")?,
+            (vec![vec![i(2), Value::Null], vec![i(4), Value::Null]], 0)
+        );
+        assert_eq!(query("SELECT a FROM test WHERE a >= 1")?, (vec![vec![i(1)], vec![i(3)]], 0));
+        assert_eq!(
+            query("SELECT a FROM test ORDER BY a LIMIT 3")?,
+            (vec![vec![Value::Null], vec![Value::Null], vec![i(1)]], 0)
+        );
+        assert_eq!(
+            query("SELECT id FROM test ORDER BY a DESC LIMIT 3")?,
+            (vec![vec![i(3)], vec![i(1)], vec![i(2)]], 0)
+        );
+        assert_eq!(
+            query("SELECT s FROM test WHERE s LIKE 'foo%'")?,
+            (vec![vec![s("foo")], vec![s("foobar")]], 0)
+        );
+        assert_eq!(query("SELECT COUNT(*) FROM test WHERE a IS NULL")?, (vec![vec![i(2)]], 0));
+
+        // Decimal index values are returned with the column's scale.
+        assert_eq!(
+            query("SELECT id, d FROM test WHERE d = 1.5 ORDER BY id")?,
+            (vec![vec![i(1), dec("1.50")], vec![i(4), dec("1.50")]], 0)
+        );
+        assert_eq!(
+            query("SELECT id, d FROM test WHERE d = 1.5 ORDER BY id")?.0[0][1].to_string(),
+            "1.50"
+        );
+
+        // Other columns are read from the table.
+        assert_eq!(
+            query("SELECT c FROM test WHERE a IS NULL ORDER BY c")?,
+            (vec![vec![s("w")], vec![s("y")]], 2)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn join_order() -> Result<()> {
+        let engine = engine()?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE regions (id INTEGER PRIMARY KEY, name STRING)")?;
+        session.execute("CREATE TABLE customers (id INTEGER PRIMARY KEY, region_id INTEGER)")?;
+        session.execute("CREATE TABLE products (id INTEGER PRIMARY KEY, price INTEGER)")?;
+        session.execute(
+            "CREATE TABLE sales (id INTEGER PRIMARY KEY, customer_id INTEGER, product_id INTEGER)",
+        )?;
+        let insert = |session: &mut Session<KV>, table: &str, rows: Vec<String>| {
+            session.execute(&format!("INSERT INTO {} VALUES {}", table, rows.join(", ")))
+        };
+        insert(&mut session, "regions", (0..5).map(|i| format!("({}, 'r{}')", i, i)).collect())?;
+        insert(
+            &mut session,
+            "customers",
+            (0..100).map(|i| format!("({}, {})", i, i % 5)).collect(),
+        )?;
+        insert(
+            &mut session,
+            "products",
+            (0..50).map(|i| format!("({}, {})", i, i * 10)).collect(),
+        )?;
+        insert(
+            &mut session,
+            "sales",
+            (0..500).map(|i| format!("({}, {}, {})", i, (i * 7) % 100, (i * 13) % 50)).collect(),
+        )?;
+
+        // Returns the query result, and the EXPLAIN ANALYZE plan lines.
+        let time = regex::Regex::new(r" time=\d+\.\d{3}ms").unwrap();
+        let run = |session: &mut Session<KV>, query: &str| -> Result<(Vec<Row>, Vec<String>)> {
+            let mut rows = |query: &str| match session.execute(query)? {
+                ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>(),
+                result => panic!("Unexpected result {:?}", result),
+            };
+            let plan = rows(&format!("EXPLAIN ANALYZE {}", query))?
+                .into_iter()
+                .map(|mut row| time.replace(&row.remove(0).to_string(), "").into_owned())
+                .collect();
+            Ok((rows(query)?, plan))
+        };
+
+        // Sums the actual rows emitted by joins, i.e. the intermediate join results.
+        let actual = regex::Regex::new(r"Join.*\(actual rows=(\d+)\)").unwrap();
+        let join_rows = |plan: &[String]| -> u64 {
+            plan.iter()
+                .filter_map(|l| actual.captures(l))
+                .map(|c| c[1].parse::<u64>().unwrap())
+                .sum()
+        };
+
+        // A star schema query with the tables listed in a poor order, which joins products as a
+        // cross product. Without statistics, the joins are executed in the given order.
+        let query = "SELECT r.name, SUM(p.price) FROM regions r, customers c, products p, sales s \
+                     WHERE c.region_id = r.id AND s.customer_id = c.id AND s.product_id = p.id \
+                     AND r.name = 'r1' GROUP BY r.name";
+        let (expect, plan) = run(&mut session, query)?;
+        assert_eq!(expect, vec![vec![Value::String("r1".into()), Value::Integer(26500)]]);
+        assert_eq!(join_rows(&plan), 1120);
+
+        // Once analyzed, the joins are reordered to avoid the cross product, reducing the
+        // intermediate rows without changing the result.
+        session.execute("ANALYZE")?;
+        let (rows, plan) = run(&mut session, query)?;
+        assert_eq!(rows, expect);
+        assert_eq!(join_rows(&plan), 220);
+        assert!(plan.iter().all(|l| !l.contains("NestedLoopJoin")), "{:?}", plan);
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
-use super::super::schema::{Catalog, Table, Tables};
-use super::super::types::{Expression, Row, Value};
+use super::super::schema::{Catalog, Column, Table, TableStats, Tables, User, Users, View, Views};
+use super::super::types::{Expression, Row, Value, BATCH_SIZE};
 use super::{Engine as _, IndexScan, Mode, Scan, Transaction as _};
 use crate::error::{Error, Result};
 use crate::raft;
@@ -7,7 +7,40 @@ use crate::storage::kv;
 
 use serde::{Deserialize, Serialize};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// The number of rows fetched from the state machine per scan query. This matches the executors'
+/// batch size, such that each batch of a table scan takes a single query.
+const SCAN_BATCH_SIZE: usize = BATCH_SIZE;
+
+/// The approximate number of bytes of key/value pairs written per import mutation, such that large
+/// imports are split across several Raft log entries.
+const IMPORT_BATCH_SIZE: usize = 1 << 20;
+
+/// The number of times an aborted mutation is retried, e.g. after a leader change.
+const MUTATE_RETRIES: u64 = 3;
+
+/// The minimum time given to a transaction rollback with a deadline, such that a statement that
+/// timed out can still roll back its writes, without hanging if the cluster is unavailable.
+const ROLLBACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The number of recently applied request IDs the state machine remembers, to deduplicate
+/// retried mutations.
+const REQUEST_CACHE_SIZE: u64 = 1000;
+
+/// A Raft state machine command, i.e. a mutation with a unique request ID. If a mutation with the
+/// same request ID was recently applied, the state machine returns its result instead of applying
+/// the mutation again, such that mutations can be safely retried.
+#[derive(Clone, Serialize, Deserialize)]
+struct Command {
+    request_id: Vec<u8>,
+    mutation: Mutation,
+}
 
 /// A Raft state machine mutation
 #[derive(Clone, Serialize, Deserialize)]
@@ -18,18 +51,49 @@ enum Mutation {
     Commit(u64),
     /// Rolls back the transaction with the given ID
     Rollback(u64),
+    /// Garbage collects old row versions
+    Vacuum,
 
     /// Creates a new row
     Create { txn_id: u64, table: String, row: Row },
+    /// Creates a batch of new rows
+    CreateBatch { txn_id: u64, table: String, rows: Vec<Row> },
     /// Deletes a row
     Delete { txn_id: u64, table: String, id: Value },
     /// Updates a row
     Update { txn_id: u64, table: String, id: Value, row: Row },
+    /// Deletes all rows of a table
+    Truncate { txn_id: u64, table: String },
 
     /// Creates a table
     CreateTable { txn_id: u64, schema: Table },
     /// Deletes a table
     DeleteTable { txn_id: u64, table: String },
+    /// Renames a table column
+    RenameColumn { txn_id: u64, table: String, column: String, new_name: String },
+    /// Renames a table
+    RenameTable { txn_id: u64, table: String, new_name: String },
+    /// Creates a view
+    CreateView { txn_id: u64, view: View },
+    /// Deletes a view
+    DeleteView { txn_id: u64, view: String },
+    /// Creates a user
+    CreateUser { txn_id: u64, user: User },
+    /// Deletes a user
+    DeleteUser { txn_id: u64, user: String },
+    /// Updates a user
+    UpdateUser { txn_id: u64, user: User },
+    /// Sets a table's statistics
+    SetTableStats { txn_id: u64, table: String, stats: TableStats },
+    /// Adds a table column
+    AddColumn { txn_id: u64, table: String, column: Column },
+    /// Drops a table column
+    DropColumn { txn_id: u64, table: String, column: String },
+    /// Executes a query via the Raft log, such that it's applied on all nodes. Used for the reads
+    /// of serializable transactions, whose commits depend on the reads they recorded.
+    Query(Query),
+    /// Writes raw key/value pairs, e.g. during an import
+    SetKeys { txn_id: u64, pairs: Vec<(Vec<u8>, Vec<u8>)> },
 }
 
 /// A Raft state machine query
@@ -40,19 +104,50 @@ enum Query {
     /// Resumes the active transaction with the given ID
     Resume(u64),
 
-    /// Reads a row
-    Read { txn_id: u64, table: String, id: Value },
+    /// Reads a row, only decoding the given columns if any
+    Read { txn_id: u64, table: String, id: Value, columns: Option<Vec<usize>> },
     /// Reads an index entry
     ReadIndex { txn_id: u64, table: String, column: String, value: Value },
-    /// Scans a table's rows
-    Scan { txn_id: u64, table: String, filter: Option<Expression> },
-    /// Scans an index
-    ScanIndex { txn_id: u64, table: String, column: String },
+    /// Scans up to limit table rows with primary keys in the range, after the given primary key,
+    /// if any, only decoding the given columns if any. Returns the rows and the primary key to
+    /// continue after, or None if the scan is complete.
+    Scan {
+        txn_id: u64,
+        table: String,
+        range: (Bound<Value>, Bound<Value>),
+        columns: Option<Vec<usize>>,
+        filter: Option<Expression>,
+        after: Option<Value>,
+        limit: usize,
+    },
+    /// Scans an index, optionally only for string values with the given prefix
+    ScanIndex { txn_id: u64, table: String, column: String, prefix: Option<String> },
+    /// Scans an index for values within a range
+    ScanIndexRange {
+        txn_id: u64,
+        table: String,
+        column: String,
+        range: (Bound<Value>, Bound<Value>),
+    },
 
     /// Scans the tables
     ScanTables { txn_id: u64 },
     /// Reads a table
     ReadTable { txn_id: u64, table: String },
+    /// Scans the views
+    ScanViews { txn_id: u64 },
+    /// Reads a view
+    ReadView { txn_id: u64, view: String },
+    /// Scans the users
+    ScanUsers { txn_id: u64 },
+    /// Reads a user
+    ReadUser { txn_id: u64, user: String },
+    /// Reads a table's statistics
+    ReadTableStats { txn_id: u64, table: String },
+    /// Scans up to limit raw key/value pairs with the given key prefix (all pairs if empty), after
+    /// the given key, if any. Returns the pairs and the key to continue after, or None if the scan
+    /// is complete.
+    ScanKeys { txn_id: u64, prefix: Vec<u8>, after: Option<Vec<u8>>, limit: usize },
 }
 
 /// Status for the Raft SQL engine.
@@ -62,6 +157,62 @@ pub struct Status {
     pub mvcc: kv::mvcc::Status,
 }
 
+/// A committed change to a table row, as emitted to watchers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    /// A row was inserted
+    Insert { table: String, row: Row },
+    /// A row was updated, possibly changing its primary key
+    Update { table: String, before: Row, after: Row },
+    /// A row was deleted
+    Delete { table: String, row: Row },
+}
+
+impl Change {
+    /// Returns the table of the changed row.
+    pub fn table(&self) -> &str {
+        match self {
+            Self::Insert { table, .. }
+            | Self::Update { table, .. }
+            | Self::Delete { table, .. } => table,
+        }
+    }
+}
+
+/// Watchers of committed row changes, shared between a state machine and its clients. Changes
+/// are emitted as transactions are committed by the state machine, i.e. in commit order.
+#[derive(Clone, Default)]
+pub struct Watchers(Arc<Mutex<Vec<Watcher>>>);
+
+/// A table watcher, with a sender for its changes.
+type Watcher = (String, mpsc::UnboundedSender<Change>);
+
+impl Watchers {
+    /// Watches a table's row changes, returning a receiver for them. Only changes made by
+    /// transactions that begin after this call are emitted. The watch ends when the receiver is
+    /// dropped.
+    pub fn watch(&self, table: &str) -> Result<mpsc::UnboundedReceiver<Change>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.0.lock()?.push((table.to_string(), tx));
+        Ok(rx)
+    }
+
+    /// Returns true if there are any watchers.
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.0.lock()?.is_empty())
+    }
+
+    /// Emits changes to the watchers of their tables, removing watchers that have been dropped.
+    fn emit(&self, changes: Vec<Change>) -> Result<()> {
+        let mut watchers = self.0.lock()?;
+        for change in changes {
+            watchers
+                .retain(|(table, tx)| table != change.table() || tx.send(change.clone()).is_ok());
+        }
+        Ok(())
+    }
+}
+
 /// An SQL engine that wraps a Raft cluster.
 #[derive(Clone)]
 pub struct Raft {
@@ -74,21 +225,84 @@ impl Raft {
         Self { client }
     }
 
-    /// Creates an underlying state machine for a Raft engine.
-    pub fn new_state(kv: kv::MVCC) -> Result<State> {
-        State::new(kv)
+    /// Creates an underlying state machine for a Raft engine, limiting string and binary values
+    /// to max_value_size bytes.
+    pub fn new_state(kv: kv::MVCC, max_value_size: usize) -> Result<State> {
+        State::new(kv, max_value_size)
     }
 
     /// Returns Raft SQL engine status.
     pub fn status(&self) -> Result<Status> {
         Ok(Status {
             raft: futures::executor::block_on(self.client.status())?,
-            mvcc: Raft::deserialize(&futures::executor::block_on(
-                self.client.query(Raft::serialize(&Query::Status)?),
-            )?)?,
+            mvcc: self.mvcc_status()?,
         })
     }
 
+    /// Returns the Raft cluster health as seen by the leader.
+    pub fn cluster_health(&self) -> Result<raft::ClusterHealth> {
+        futures::executor::block_on(self.client.cluster_health())
+    }
+
+    /// Exports the raw key/value pairs of the SQL key/value store with the given key prefix (all
+    /// pairs if empty) in key order, as seen by a read-only transaction. The pairs are fetched
+    /// from the state machine in batches as they are consumed.
+    pub fn export(&self, prefix: &[u8]) -> Result<KeyScan> {
+        KeyScan::new(Transaction::begin(self.client.clone(), Mode::ReadOnly)?, prefix.to_vec())
+    }
+
+    /// Imports raw key/value pairs (e.g. from export()) into the SQL key/value store in a single
+    /// read-write transaction, overwriting existing keys. If a key is given several times, the
+    /// last value wins. The pairs are written in mutations of about IMPORT_BATCH_SIZE bytes, i.e.
+    /// separate Raft log entries, rather than a single large entry. Raw writes bypass schemas and
+    /// constraints, and are not emitted to watchers. Returns the number of keys imported.
+    pub fn import(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<u64> {
+        let pairs: BTreeMap<Vec<u8>, Vec<u8>> = pairs.into_iter().collect();
+        let count = pairs.len() as u64;
+        let txn = Transaction::begin(self.client.clone(), Mode::ReadWrite)?;
+        let result = (|| {
+            let mut batch = Vec::new();
+            let mut size = 0;
+            for (key, value) in pairs {
+                size += key.len() + value.len();
+                batch.push((key, value));
+                if size >= IMPORT_BATCH_SIZE {
+                    txn.set_keys(std::mem::take(&mut batch))?;
+                    size = 0;
+                }
+            }
+            if !batch.is_empty() {
+                txn.set_keys(batch)?;
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => txn.commit()?,
+            Err(err) => {
+                txn.rollback()?;
+                return Err(err);
+            }
+        }
+        Ok(count)
+    }
+
+    /// Executes a mutation with a new request ID. If the mutation is aborted, e.g. due to a leader
+    /// change, it may still be applied, so it's retried with the same request ID.
+    fn mutate(client: &raft::Client, mutation: Mutation) -> Result<Vec<u8>> {
+        let request_id = Uuid::new_v4().as_bytes().to_vec();
+        let command = Raft::serialize(&Command { request_id, mutation })?;
+        let mut retries = 0;
+        loop {
+            match futures::executor::block_on(client.mutate(command.clone())) {
+                Err(Error::Abort) if retries < MUTATE_RETRIES => {
+                    retries += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(100 * retries));
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Serializes a command for the Raft SQL state machine.
     fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>> {
         Ok(bincode::serialize(value)?)
@@ -110,6 +324,25 @@ impl super::Engine for Raft {
     fn resume(&self, id: u64) -> Result<Self::Transaction> {
         Transaction::resume(self.client.clone(), id)
     }
+
+    fn vacuum(&self) -> Result<kv::mvcc::Vacuum> {
+        Raft::deserialize(&Raft::mutate(&self.client, Mutation::Vacuum)?)
+    }
+
+    fn mvcc_status(&self) -> Result<kv::mvcc::Status> {
+        Raft::deserialize(&futures::executor::block_on(
+            self.client.query(Raft::serialize(&Query::Status)?),
+        )?)
+    }
+
+    fn is_leader(&self) -> Result<bool> {
+        let status = futures::executor::block_on(self.client.status())?;
+        Ok(status.leader == status.server)
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.client.set_deadline(deadline)
+    }
 }
 
 /// A Raft-based SQL transaction
@@ -126,9 +359,7 @@ pub struct Transaction {
 impl Transaction {
     /// Starts a transaction in the given mode
     fn begin(client: raft::Client, mode: Mode) -> Result<Self> {
-        let id = Raft::deserialize(&futures::executor::block_on(
-            client.mutate(Raft::serialize(&Mutation::Begin(mode))?),
-        )?)?;
+        let id = Raft::deserialize(&Raft::mutate(&client, Mutation::Begin(mode))?)?;
         Ok(Self { client, id, mode })
     }
 
@@ -142,12 +373,21 @@ impl Transaction {
 
     /// Executes a mutation
     fn mutate(&self, mutation: Mutation) -> Result<Vec<u8>> {
-        futures::executor::block_on(self.client.mutate(Raft::serialize(&mutation)?))
+        Raft::mutate(&self.client, mutation)
     }
 
-    /// Executes a query
+    /// Writes raw key/value pairs
+    fn set_keys(&self, pairs: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::SetKeys { txn_id: self.id, pairs })?)
+    }
+
+    /// Executes a query. Serializable transactions must record their reads on all nodes, so that
+    /// they make the same commit decisions, and thus execute queries as mutations instead.
     fn query(&self, query: Query) -> Result<Vec<u8>> {
-        futures::executor::block_on(self.client.query(Raft::serialize(&query)?))
+        match self.mode {
+            Mode::Serializable => self.mutate(Mutation::Query(query)),
+            _ => futures::executor::block_on(self.client.query(Raft::serialize(&query)?)),
+        }
     }
 }
 
@@ -164,10 +404,16 @@ impl super::Transaction for Transaction {
         Raft::deserialize(&self.mutate(Mutation::Commit(self.id))?)
     }
 
-    fn rollback(self) -> Result<()> {
+    fn rollback(mut self) -> Result<()> {
+        let deadline = self.client.deadline().map(|d| d.max(Instant::now() + ROLLBACK_TIMEOUT));
+        self.client.set_deadline(deadline);
         Raft::deserialize(&self.mutate(Mutation::Rollback(self.id))?)
     }
 
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.client.set_deadline(deadline)
+    }
+
     fn create(&mut self, table: &str, row: Row) -> Result<()> {
         Raft::deserialize(&self.mutate(Mutation::Create {
             txn_id: self.id,
@@ -176,6 +422,14 @@ impl super::Transaction for Transaction {
         })?)
     }
 
+    fn create_batch(&mut self, table: &str, rows: Vec<Row>) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::CreateBatch {
+            txn_id: self.id,
+            table: table.to_string(),
+            rows,
+        })?)
+    }
+
     fn delete(&mut self, table: &str, id: &Value) -> Result<()> {
         Raft::deserialize(&self.mutate(Mutation::Delete {
             txn_id: self.id,
@@ -189,6 +443,16 @@ impl super::Transaction for Transaction {
             txn_id: self.id,
             table: table.to_string(),
             id: id.clone(),
+            columns: None,
+        })?)
+    }
+
+    fn read_columns(&self, table: &str, id: &Value, columns: &[usize]) -> Result<Option<Row>> {
+        Raft::deserialize(&self.query(Query::Read {
+            txn_id: self.id,
+            table: table.to_string(),
+            id: id.clone(),
+            columns: Some(columns.to_vec()),
         })?)
     }
 
@@ -202,29 +466,80 @@ impl super::Transaction for Transaction {
     }
 
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<Scan> {
+        let range = (Bound::Unbounded, Bound::Unbounded);
+        Ok(Box::new(BatchScan::new(self.clone(), table.to_string(), range, None, filter)?))
+    }
+
+    fn scan_range(
+        &self,
+        table: &str,
+        range: (Bound<Value>, Bound<Value>),
+        filter: Option<Expression>,
+    ) -> Result<Scan> {
+        Ok(Box::new(BatchScan::new(self.clone(), table.to_string(), range, None, filter)?))
+    }
+
+    fn scan_columns(
+        &self,
+        table: &str,
+        range: (Bound<Value>, Bound<Value>),
+        columns: &[usize],
+        filter: Option<Expression>,
+    ) -> Result<Scan> {
+        let columns = Some(columns.to_vec());
+        Ok(Box::new(BatchScan::new(self.clone(), table.to_string(), range, columns, filter)?))
+    }
+
+    fn scan_index(&self, table: &str, column: &str) -> Result<IndexScan> {
         Ok(Box::new(
-            Raft::deserialize::<Vec<_>>(&self.query(Query::Scan {
+            Raft::deserialize::<Vec<_>>(&self.query(Query::ScanIndex {
                 txn_id: self.id,
                 table: table.to_string(),
-                filter,
+                column: column.to_string(),
+                prefix: None,
             })?)?
             .into_iter()
             .map(Ok),
         ))
     }
 
-    fn scan_index(&self, table: &str, column: &str) -> Result<IndexScan> {
+    fn scan_index_prefix(&self, table: &str, column: &str, prefix: &str) -> Result<IndexScan> {
         Ok(Box::new(
             Raft::deserialize::<Vec<_>>(&self.query(Query::ScanIndex {
                 txn_id: self.id,
                 table: table.to_string(),
                 column: column.to_string(),
+                prefix: Some(prefix.to_string()),
+            })?)?
+            .into_iter()
+            .map(Ok),
+        ))
+    }
+
+    fn scan_index_range(
+        &self,
+        table: &str,
+        column: &str,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<IndexScan> {
+        Ok(Box::new(
+            Raft::deserialize::<Vec<_>>(&self.query(Query::ScanIndexRange {
+                txn_id: self.id,
+                table: table.to_string(),
+                column: column.to_string(),
+                range,
             })?)?
             .into_iter()
             .map(Ok),
         ))
     }
 
+    fn truncate(&mut self, table: &str) -> Result<u64> {
+        Raft::deserialize(
+            &self.mutate(Mutation::Truncate { txn_id: self.id, table: table.to_string() })?,
+        )
+    }
+
     fn update(&mut self, table: &str, id: &Value, row: Row) -> Result<()> {
         Raft::deserialize(&self.mutate(Mutation::Update {
             txn_id: self.id,
@@ -252,12 +567,239 @@ impl Catalog for Transaction {
         )
     }
 
+    fn add_column(&mut self, table: &str, column: Column) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::AddColumn {
+            txn_id: self.id,
+            table: table.to_string(),
+            column,
+        })?)
+    }
+
+    fn drop_column(&mut self, table: &str, column: &str) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::DropColumn {
+            txn_id: self.id,
+            table: table.to_string(),
+            column: column.to_string(),
+        })?)
+    }
+
+    fn rename_column(&mut self, table: &str, column: &str, new_name: &str) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::RenameColumn {
+            txn_id: self.id,
+            table: table.to_string(),
+            column: column.to_string(),
+            new_name: new_name.to_string(),
+        })?)
+    }
+
+    fn rename_table(&mut self, table: &str, new_name: &str) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::RenameTable {
+            txn_id: self.id,
+            table: table.to_string(),
+            new_name: new_name.to_string(),
+        })?)
+    }
+
     fn scan_tables(&self) -> Result<Tables> {
         Ok(Box::new(
             Raft::deserialize::<Vec<_>>(&self.query(Query::ScanTables { txn_id: self.id })?)?
                 .into_iter(),
         ))
     }
+
+    fn create_view(&mut self, view: View) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::CreateView { txn_id: self.id, view })?)
+    }
+
+    fn delete_view(&mut self, view: &str) -> Result<()> {
+        Raft::deserialize(
+            &self.mutate(Mutation::DeleteView { txn_id: self.id, view: view.to_string() })?,
+        )
+    }
+
+    fn read_view(&self, view: &str) -> Result<Option<View>> {
+        Raft::deserialize(&self.query(Query::ReadView { txn_id: self.id, view: view.to_string() })?)
+    }
+
+    fn scan_views(&self) -> Result<Views> {
+        Ok(Box::new(
+            Raft::deserialize::<Vec<_>>(&self.query(Query::ScanViews { txn_id: self.id })?)?
+                .into_iter(),
+        ))
+    }
+
+    fn create_user(&mut self, user: User) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::CreateUser { txn_id: self.id, user })?)
+    }
+
+    fn delete_user(&mut self, user: &str) -> Result<()> {
+        Raft::deserialize(
+            &self.mutate(Mutation::DeleteUser { txn_id: self.id, user: user.to_string() })?,
+        )
+    }
+
+    fn read_user(&self, user: &str) -> Result<Option<User>> {
+        Raft::deserialize(&self.query(Query::ReadUser { txn_id: self.id, user: user.to_string() })?)
+    }
+
+    fn scan_users(&self) -> Result<Users> {
+        Ok(Box::new(
+            Raft::deserialize::<Vec<_>>(&self.query(Query::ScanUsers { txn_id: self.id })?)?
+                .into_iter(),
+        ))
+    }
+
+    fn update_user(&mut self, user: User) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::UpdateUser { txn_id: self.id, user })?)
+    }
+
+    fn read_table_stats(&self, table: &str) -> Result<Option<TableStats>> {
+        Raft::deserialize(
+            &self.query(Query::ReadTableStats { txn_id: self.id, table: table.to_string() })?,
+        )
+    }
+
+    fn set_table_stats(&mut self, table: &str, stats: TableStats) -> Result<()> {
+        Raft::deserialize(&self.mutate(Mutation::SetTableStats {
+            txn_id: self.id,
+            table: table.to_string(),
+            stats,
+        })?)
+    }
+}
+
+/// A table scan which fetches rows from the state machine in batches as they are consumed, such
+/// that large tables aren't buffered in memory.
+struct BatchScan {
+    /// The transaction to scan in
+    txn: Transaction,
+    /// The table to scan
+    table: String,
+    /// The primary key range to scan
+    range: (Bound<Value>, Bound<Value>),
+    /// The columns to decode, if not all
+    columns: Option<Vec<usize>>,
+    /// The scan filter, applied by the state machine
+    filter: Option<Expression>,
+    /// The rows of the current batch
+    batch: std::vec::IntoIter<Row>,
+    /// The primary key to continue the scan after, or None if there are no more batches
+    after: Option<Value>,
+}
+
+impl BatchScan {
+    /// Creates a new batch scan, fetching the first batch.
+    fn new(
+        txn: Transaction,
+        table: String,
+        range: (Bound<Value>, Bound<Value>),
+        columns: Option<Vec<usize>>,
+        filter: Option<Expression>,
+    ) -> Result<Self> {
+        let batch = Vec::new().into_iter();
+        let mut scan = Self { txn, table, range, columns, filter, batch, after: None };
+        scan.fetch(None)?;
+        Ok(scan)
+    }
+
+    /// Fetches the next batch of rows, after the given primary key.
+    fn fetch(&mut self, after: Option<Value>) -> Result<()> {
+        let (rows, after): (Vec<Row>, Option<Value>) =
+            Raft::deserialize(&self.txn.query(Query::Scan {
+                txn_id: self.txn.id,
+                table: self.table.clone(),
+                range: self.range.clone(),
+                columns: self.columns.clone(),
+                filter: self.filter.clone(),
+                after,
+                limit: SCAN_BATCH_SIZE,
+            })?)?;
+        self.batch = rows.into_iter();
+        self.after = after;
+        Ok(())
+    }
+}
+
+impl Iterator for BatchScan {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.batch.next() {
+                return Some(Ok(row));
+            }
+            let after = self.after.take()?;
+            if let Err(err) = self.fetch(Some(after)) {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// A scan of raw key/value pairs in a read-only transaction, which fetches the pairs from the state
+/// machine in batches as they are consumed. The transaction is rolled back once the pairs have been
+/// consumed, or when dropped.
+pub struct KeyScan {
+    /// The transaction to scan in, or None once rolled back
+    txn: Option<Transaction>,
+    /// The key prefix to scan
+    prefix: Vec<u8>,
+    /// The pairs of the current batch
+    batch: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    /// The key to continue the scan after, or None if there are no more batches
+    after: Option<Vec<u8>>,
+}
+
+impl KeyScan {
+    /// Creates a new key scan, fetching the first batch.
+    fn new(txn: Transaction, prefix: Vec<u8>) -> Result<Self> {
+        let batch = Vec::new().into_iter();
+        let mut scan = Self { txn: Some(txn), prefix, batch, after: None };
+        scan.fetch(None)?;
+        Ok(scan)
+    }
+
+    /// Fetches the next batch of pairs, after the given key.
+    fn fetch(&mut self, after: Option<Vec<u8>>) -> Result<()> {
+        let txn = self.txn.as_ref().ok_or_else(|| Error::Internal("Scan is complete".into()))?;
+        let (pairs, after): (Vec<_>, _) = Raft::deserialize(&txn.query(Query::ScanKeys {
+            txn_id: txn.id,
+            prefix: self.prefix.clone(),
+            after,
+            limit: SCAN_BATCH_SIZE,
+        })?)?;
+        self.batch = pairs.into_iter();
+        self.after = after;
+        Ok(())
+    }
+}
+
+impl Iterator for KeyScan {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pair) = self.batch.next() {
+                return Some(Ok(pair));
+            }
+            match self.after.take() {
+                Some(after) => {
+                    if let Err(err) = self.fetch(Some(after)) {
+                        return Some(Err(err));
+                    }
+                }
+                None => return self.txn.take()?.rollback().err().map(Err),
+            }
+        }
+    }
+}
+
+impl Drop for KeyScan {
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            txn.rollback().ok();
+        }
+    }
 }
 
 /// The Raft state machine for the Raft-based SQL engine, using a KV SQL engine
@@ -266,34 +808,181 @@ pub struct State {
     engine: super::KV,
     /// The last applied index
     applied_index: u64,
+    /// The number of request IDs recorded so far, the last REQUEST_CACHE_SIZE of which are
+    /// remembered.
+    request_seq: u64,
+    /// Watchers of committed row changes
+    watchers: Watchers,
+    /// Row changes of active read-write transactions, emitted to watchers on commit. Only
+    /// transactions that began while there were watchers are tracked.
+    changes: HashMap<u64, Vec<Change>>,
 }
 
 impl State {
     /// Creates a new Raft state maching using the given MVCC key/value store
-    pub fn new(store: kv::MVCC) -> Result<Self> {
-        let engine = super::KV::new(store);
+    pub fn new(store: kv::MVCC, max_value_size: usize) -> Result<Self> {
+        let engine = super::KV::new(store)?.with_max_value_size(max_value_size);
         let applied_index = engine
             .get_metadata(b"applied_index")?
             .map(|b| Raft::deserialize(&b))
             .unwrap_or(Ok(0))?;
-        Ok(State { engine, applied_index })
+        let request_seq =
+            engine.get_metadata(b"request_seq")?.map(|b| Raft::deserialize(&b)).unwrap_or(Ok(0))?;
+        Ok(State {
+            engine,
+            applied_index,
+            request_seq,
+            watchers: Watchers::default(),
+            changes: HashMap::new(),
+        })
+    }
+
+    /// Returns the watchers of the state machine's committed row changes.
+    pub fn watchers(&self) -> Watchers {
+        self.watchers.clone()
+    }
+
+    /// Returns the result of a recently applied request, if any.
+    fn get_request(&self, request_id: &[u8]) -> Result<Option<Result<Vec<u8>>>> {
+        self.engine
+            .get_metadata(&[b"request/", request_id].concat())?
+            .map(|b| Raft::deserialize(&b))
+            .transpose()
+    }
+
+    /// Records the result of an applied request, forgetting the oldest request if the cache is
+    /// full. The requests are stored in a ring of REQUEST_CACHE_SIZE slots.
+    fn set_request(&mut self, request_id: Vec<u8>, result: &Result<Vec<u8>>) -> Result<()> {
+        let slot = [&b"request_slot/"[..], &(self.request_seq % REQUEST_CACHE_SIZE).to_be_bytes()]
+            .concat();
+        if let Some(evicted) = self.engine.get_metadata(&slot)? {
+            self.engine.delete_metadata(&[b"request/", evicted.as_slice()].concat())?;
+        }
+        self.engine.set_metadata(
+            &[b"request/", request_id.as_slice()].concat(),
+            Raft::serialize(result)?,
+        )?;
+        self.engine.set_metadata(&slot, request_id)?;
+        self.request_seq += 1;
+        self.engine.set_metadata(b"request_seq", Raft::serialize(&self.request_seq)?)
+    }
+
+    /// Applies a single state machine command at the given log index.
+    fn mutate_command(&mut self, index: u64, command: Vec<u8>) -> Result<Vec<u8>> {
+        // We don't check that index == applied_index + 1, since the Raft log commits no-op
+        // entries during leader election which we need to ignore.
+        let Command { request_id, mutation } = Raft::deserialize(&command)?;
+        let result = match self.get_request(&request_id)? {
+            Some(result) => result,
+            None => match self.apply(mutation) {
+                error @ Err(Error::Internal(_)) => return error,
+                result => {
+                    self.set_request(request_id, &result)?;
+                    result
+                }
+            },
+        };
+        self.engine.set_metadata(b"applied_index", Raft::serialize(&(index))?)?;
+        self.applied_index = index;
+        result
     }
 
     /// Applies a state machine mutation
     fn apply(&mut self, mutation: Mutation) -> Result<Vec<u8>> {
         match mutation {
-            Mutation::Begin(mode) => Raft::serialize(&self.engine.begin(mode)?.id()),
-            Mutation::Commit(txn_id) => Raft::serialize(&self.engine.resume(txn_id)?.commit()?),
-            Mutation::Rollback(txn_id) => Raft::serialize(&self.engine.resume(txn_id)?.rollback()?),
+            Mutation::Begin(mode) => {
+                let txn = self.engine.begin(mode)?;
+                if mode.mutable() && !self.watchers.is_empty()? {
+                    self.changes.insert(txn.id(), Vec::new());
+                }
+                Raft::serialize(&txn.id())
+            }
+            Mutation::Commit(txn_id) => {
+                self.engine.resume(txn_id)?.commit()?;
+                if let Some(changes) = self.changes.remove(&txn_id) {
+                    self.watchers.emit(changes)?;
+                }
+                Raft::serialize(&())
+            }
+            Mutation::Rollback(txn_id) => {
+                self.engine.resume(txn_id)?.rollback()?;
+                self.changes.remove(&txn_id);
+                Raft::serialize(&())
+            }
+            Mutation::Vacuum => Raft::serialize(&self.engine.vacuum()?),
 
             Mutation::Create { txn_id, table, row } => {
-                Raft::serialize(&self.engine.resume(txn_id)?.create(&table, row)?)
+                let mut txn = self.engine.resume(txn_id)?;
+                match self.changes.get_mut(&txn_id) {
+                    Some(changes) => {
+                        txn.create(&table, row.clone())?;
+                        let row = txn.must_read_table(&table)?.conform_row(row)?;
+                        changes.push(Change::Insert { table, row });
+                    }
+                    None => txn.create(&table, row)?,
+                }
+                Raft::serialize(&())
+            }
+            Mutation::CreateBatch { txn_id, table, rows } => {
+                let mut txn = self.engine.resume(txn_id)?;
+                match self.changes.get_mut(&txn_id) {
+                    Some(changes) => {
+                        let schema = txn.must_read_table(&table)?;
+                        for row in rows {
+                            txn.create(&table, row.clone())?;
+                            let row = schema.conform_row(row)?;
+                            changes.push(Change::Insert { table: table.clone(), row });
+                        }
+                    }
+                    None => txn.create_batch(&table, rows)?,
+                }
+                Raft::serialize(&())
             }
             Mutation::Delete { txn_id, table, id } => {
-                Raft::serialize(&self.engine.resume(txn_id)?.delete(&table, &id)?)
+                let mut txn = self.engine.resume(txn_id)?;
+                match self.changes.get_mut(&txn_id) {
+                    Some(changes) => {
+                        let before = txn.read(&table, &id)?;
+                        txn.delete(&table, &id)?;
+                        if let Some(row) = before {
+                            changes.push(Change::Delete { table, row });
+                        }
+                    }
+                    None => txn.delete(&table, &id)?,
+                }
+                Raft::serialize(&())
             }
             Mutation::Update { txn_id, table, id, row } => {
-                Raft::serialize(&self.engine.resume(txn_id)?.update(&table, &id, row)?)
+                let mut txn = self.engine.resume(txn_id)?;
+                match self.changes.get_mut(&txn_id) {
+                    Some(changes) => {
+                        let before = txn.read(&table, &id)?;
+                        txn.update(&table, &id, row.clone())?;
+                        if let Some(before) = before {
+                            let after = txn.must_read_table(&table)?.conform_row(row)?;
+                            changes.push(Change::Update { table, before, after });
+                        }
+                    }
+                    None => txn.update(&table, &id, row)?,
+                }
+                Raft::serialize(&())
+            }
+            Mutation::Truncate { txn_id, table } => {
+                let mut txn = self.engine.resume(txn_id)?;
+                // The deleted rows are read before truncating, but only recorded as changes once
+                // the truncate has succeeded.
+                let mut deleted = Vec::new();
+                if self.changes.contains_key(&txn_id) {
+                    let mut scan = txn.scan(&table, None)?;
+                    while let Some(row) = scan.next().transpose()? {
+                        deleted.push(Change::Delete { table: table.clone(), row });
+                    }
+                }
+                let count = txn.truncate(&table)?;
+                if let Some(changes) = self.changes.get_mut(&txn_id) {
+                    changes.extend(deleted);
+                }
+                Raft::serialize(&count)
             }
 
             Mutation::CreateTable { txn_id, schema } => {
@@ -302,52 +991,84 @@ impl State {
             Mutation::DeleteTable { txn_id, table } => {
                 Raft::serialize(&self.engine.resume(txn_id)?.delete_table(&table)?)
             }
-        }
-    }
-}
-
-impl raft::State for State {
-    fn applied_index(&self) -> u64 {
-        self.applied_index
-    }
-
-    fn mutate(&mut self, index: u64, command: Vec<u8>) -> Result<Vec<u8>> {
-        // We don't check that index == applied_index + 1, since the Raft log commits no-op
-        // entries during leader election which we need to ignore.
-        match self.apply(Raft::deserialize(&command)?) {
-            error @ Err(Error::Internal(_)) => error,
-            result => {
-                self.engine.set_metadata(b"applied_index", Raft::serialize(&(index))?)?;
-                self.applied_index = index;
-                result
+            Mutation::AddColumn { txn_id, table, column } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.add_column(&table, column)?)
+            }
+            Mutation::DropColumn { txn_id, table, column } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.drop_column(&table, &column)?)
+            }
+            Mutation::RenameColumn { txn_id, table, column, new_name } => Raft::serialize(
+                &self.engine.resume(txn_id)?.rename_column(&table, &column, &new_name)?,
+            ),
+            Mutation::RenameTable { txn_id, table, new_name } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.rename_table(&table, &new_name)?)
+            }
+            Mutation::CreateView { txn_id, view } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.create_view(view)?)
+            }
+            Mutation::DeleteView { txn_id, view } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.delete_view(&view)?)
+            }
+            Mutation::CreateUser { txn_id, user } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.create_user(user)?)
+            }
+            Mutation::DeleteUser { txn_id, user } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.delete_user(&user)?)
+            }
+            Mutation::UpdateUser { txn_id, user } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.update_user(user)?)
+            }
+            Mutation::SetTableStats { txn_id, table, stats } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.set_table_stats(&table, stats)?)
+            }
+            Mutation::Query(query) => self.read(query),
+            Mutation::SetKeys { txn_id, pairs } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.set_keys(pairs)?)
             }
         }
     }
 
-    fn query(&self, command: Vec<u8>) -> Result<Vec<u8>> {
-        match Raft::deserialize(&command)? {
+    /// Executes a state machine query
+    fn read(&self, query: Query) -> Result<Vec<u8>> {
+        match query {
             Query::Resume(id) => {
                 let txn = self.engine.resume(id)?;
                 Raft::serialize(&(txn.id(), txn.mode()))
             }
 
-            Query::Read { txn_id, table, id } => {
-                Raft::serialize(&self.engine.resume(txn_id)?.read(&table, &id)?)
-            }
+            Query::Read { txn_id, table, id, columns } => Raft::serialize(
+                &self.engine.resume(txn_id)?.read_row(&table, &id, columns.as_deref())?,
+            ),
             Query::ReadIndex { txn_id, table, column, value } => {
                 Raft::serialize(&self.engine.resume(txn_id)?.read_index(&table, &column, &value)?)
             }
-            // FIXME These need to stream rows somehow
-            Query::Scan { txn_id, table, filter } => Raft::serialize(
-                &self.engine.resume(txn_id)?.scan(&table, filter)?.collect::<Result<Vec<_>>>()?,
-            ),
-            Query::ScanIndex { txn_id, table, column } => Raft::serialize(
-                &self
-                    .engine
-                    .resume(txn_id)?
-                    .scan_index(&table, &column)?
-                    .collect::<Result<Vec<_>>>()?,
-            ),
+            Query::Scan { txn_id, table, range, columns, filter, after, limit } => {
+                let txn = self.engine.resume(txn_id)?;
+                let rows = txn
+                    .scan_after(&table, range, columns, after.as_ref(), filter)?
+                    .take(limit)
+                    .collect::<Result<Vec<_>>>()?;
+                let after = match rows.last() {
+                    Some(row) if rows.len() == limit => {
+                        Some(txn.must_read_table(&table)?.get_row_key(row)?)
+                    }
+                    _ => None,
+                };
+                Raft::serialize(&(rows, after))
+            }
+            // FIXME This needs to stream index entries somehow
+            Query::ScanIndex { txn_id, table, column, prefix } => {
+                let txn = self.engine.resume(txn_id)?;
+                let scan = match prefix {
+                    Some(prefix) => txn.scan_index_prefix(&table, &column, &prefix)?,
+                    None => txn.scan_index(&table, &column)?,
+                };
+                Raft::serialize(&scan.collect::<Result<Vec<_>>>()?)
+            }
+            Query::ScanIndexRange { txn_id, table, column, range } => {
+                let scan = self.engine.resume(txn_id)?.scan_index_range(&table, &column, range)?;
+                Raft::serialize(&scan.collect::<Result<Vec<_>>>()?)
+            }
             Query::Status => Raft::serialize(&self.engine.kv.status()?),
 
             Query::ReadTable { txn_id, table } => {
@@ -356,6 +1077,608 @@ impl raft::State for State {
             Query::ScanTables { txn_id } => {
                 Raft::serialize(&self.engine.resume(txn_id)?.scan_tables()?.collect::<Vec<_>>())
             }
+            Query::ReadView { txn_id, view } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.read_view(&view)?)
+            }
+            Query::ScanViews { txn_id } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.scan_views()?.collect::<Vec<_>>())
+            }
+            Query::ReadUser { txn_id, user } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.read_user(&user)?)
+            }
+            Query::ScanUsers { txn_id } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.scan_users()?.collect::<Vec<_>>())
+            }
+            Query::ReadTableStats { txn_id, table } => {
+                Raft::serialize(&self.engine.resume(txn_id)?.read_table_stats(&table)?)
+            }
+            Query::ScanKeys { txn_id, prefix, after, limit } => {
+                let pairs = self
+                    .engine
+                    .resume(txn_id)?
+                    .scan_keys(&prefix, after.as_deref())?
+                    .take(limit)
+                    .collect::<Result<Vec<_>>>()?;
+                let after = match pairs.last() {
+                    Some((key, _)) if pairs.len() == limit => Some(key.clone()),
+                    _ => None,
+                };
+                Raft::serialize(&(pairs, after))
+            }
+        }
+    }
+}
+
+impl raft::State for State {
+    fn applied_index(&self) -> u64 {
+        self.applied_index
+    }
+
+    fn mutate(&mut self, index: u64, command: Vec<u8>) -> Result<Vec<u8>> {
+        match self.mutate_batch(vec![(index, command)])?.pop() {
+            Some(result) => result,
+            None => Err(Error::Internal("No result for applied command".into())),
+        }
+    }
+
+    /// Applies the batch in a single MVCC write batch, which is discarded if any command returns
+    /// Error::Internal, such that the batch (including the applied index) is applied atomically.
+    fn mutate_batch(&mut self, commands: Vec<(u64, Vec<u8>)>) -> Result<Vec<Result<Vec<u8>>>> {
+        let (applied_index, request_seq) = (self.applied_index, self.request_seq);
+        self.engine.kv.begin_batch()?;
+        let mut results = Vec::with_capacity(commands.len());
+        for (index, command) in commands {
+            match self.mutate_command(index, command) {
+                Err(error @ Error::Internal(_)) => {
+                    self.engine.kv.abort_batch()?;
+                    self.applied_index = applied_index;
+                    self.request_seq = request_seq;
+                    return Err(error);
+                }
+                result => results.push(result),
+            }
+        }
+        self.engine.kv.commit_batch()?;
+        Ok(results)
+    }
+
+    fn query(&self, command: Vec<u8>) -> Result<Vec<u8>> {
+        self.read(Raft::deserialize(&command)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::execution::ResultSet;
+    use super::super::MAX_VALUE_SIZE;
+    use super::*;
+    use crate::raft::State as _;
+    use crate::sql::types::Timestamp;
+
+    use std::fmt::Display;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    /// Sets up a Raft SQL engine backed by a leader and a follower state machine. See serve().
+    fn setup() -> (Raft, std::thread::JoinHandle<Vec<State>>) {
+        serve(vec![
+            State::new(kv::MVCC::new(Box::new(kv::Memory::new())), MAX_VALUE_SIZE).unwrap(),
+            State::new(kv::MVCC::new(Box::new(kv::Memory::new())), MAX_VALUE_SIZE).unwrap(),
+        ])
+    }
+
+    /// Sets up a Raft SQL engine backed by the given state machines, where the first is the
+    /// leader. Mutations are applied to the leader first and then to the followers after a delay,
+    /// emulating replication lag, and must give the same result on all. Queries are served by the
+    /// leader. Returns the engine and a handle which yields the state machines once the engine has
+    /// been dropped.
+    fn serve(mut states: Vec<State>) -> (Raft, std::thread::JoinHandle<Vec<State>>) {
+        let (request_tx, mut request_rx) = mpsc::unbounded_channel::<raft::ClientRequest>();
+        let handle = std::thread::spawn(move || {
+            let mut index = 0;
+            while let Some((request, _, response_tx)) =
+                futures::executor::block_on(request_rx.recv())
+            {
+                let response: Result<raft::Response> = match request {
+                    raft::Request::Mutate(command) => {
+                        index += 1;
+                        let response = states[0].mutate(index, command.clone());
+                        for follower in states.iter_mut().skip(1) {
+                            std::thread::sleep(std::time::Duration::from_millis(5));
+                            assert_eq!(follower.mutate(index, command.clone()), response);
+                        }
+                        response.map(raft::Response::State)
+                    }
+                    raft::Request::Query(command) => {
+                        states[0].query(command).map(raft::Response::State)
+                    }
+                    raft::Request::Status | raft::Request::ClusterHealth => {
+                        Err(Error::Internal("Unsupported".into()))
+                    }
+                };
+                response_tx.send(response).ok();
+            }
+            states
+        });
+        (Raft::new(raft::Client::new(request_tx)), handle)
+    }
+
+    /// A key/value store which counts the number of items read by scans, and the number of
+    /// flushes.
+    struct CountingStore {
+        kv: kv::Memory,
+        scanned: Arc<AtomicUsize>,
+        flushed: Arc<AtomicUsize>,
+    }
+
+    impl Display for CountingStore {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "counting")
+        }
+    }
+
+    impl kv::Store for CountingStore {
+        fn delete(&mut self, key: &[u8]) -> Result<()> {
+            self.kv.delete(key)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.flushed.fetch_add(1, Ordering::SeqCst);
+            self.kv.flush()
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            self.kv.get(key)
+        }
+
+        fn scan(&self, range: kv::Range) -> kv::Scan {
+            let scanned = self.scanned.clone();
+            Box::new(self.kv.scan(range).inspect(move |_| {
+                scanned.fetch_add(1, Ordering::SeqCst);
+            }))
+        }
+
+        fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+            self.kv.set(key, value)
+        }
+    }
+
+    /// Collects the rows of a query result.
+    fn rows(result: ResultSet) -> Result<Vec<Row>> {
+        match result {
+            ResultSet::Query { rows, .. } => rows.collect(),
+            r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        }
+    }
+
+    #[test]
+    // NOW() must be evaluated once per statement before it is submitted to Raft, such that all
+    // replicas apply the same value regardless of when they apply the mutation.
+    fn now_replication() -> Result<()> {
+        let (engine, handle) = setup();
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE events (id INTEGER PRIMARY KEY, at TIMESTAMP INDEX)")?;
+        session
+            .execute("INSERT INTO events VALUES (1, NOW()), (2, NOW()), (3, CURRENT_TIMESTAMP)")?;
+        session.execute("INSERT INTO events VALUES (4, NOW())")?;
+        let expect = rows(session.execute("SELECT * FROM events")?)?;
+        drop(session);
+        drop(engine);
+
+        // The rows of the first statement share a timestamp, which precedes the second.
+        let at: Vec<Timestamp> = expect
+            .iter()
+            .map(|row| match row[1] {
+                Value::Timestamp(ts) => ts,
+                ref v => panic!("Unexpected value {:?}", v),
+            })
+            .collect();
+        assert_eq!(at[0], at[1]);
+        assert_eq!(at[0], at[2]);
+        assert!(at[3] > at[2]);
+
+        // The follower applied the mutations later, but has the same rows.
+        for state in handle.join().unwrap() {
+            let mut session = state.engine.session()?;
+            assert_eq!(rows(session.execute("SELECT * FROM events")?)?, expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    // Serializable transactions record their reads on all replicas, which must thus make the same
+    // commit decisions (asserted by serve()).
+    fn serializable_replication() -> Result<()> {
+        let (engine, handle) = setup();
+        let mut a = engine.session()?;
+        let mut b = engine.session()?;
+        a.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value INTEGER)")?;
+        a.execute("INSERT INTO test VALUES (1, 0), (2, 0)")?;
+
+        a.execute("BEGIN ISOLATION LEVEL SERIALIZABLE")?;
+        b.execute("BEGIN ISOLATION LEVEL SERIALIZABLE")?;
+        assert_eq!(rows(a.execute("SELECT * FROM test WHERE id = 2")?)?.len(), 1);
+        assert_eq!(rows(b.execute("SELECT * FROM test")?)?.len(), 2);
+        a.execute("UPDATE test SET value = 1 WHERE id = 1")?;
+        b.execute("UPDATE test SET value = 1 WHERE id = 2")?;
+        a.execute("COMMIT")?;
+        assert_eq!(b.execute("COMMIT"), Err(Error::Serialization));
+        assert_eq!(b.execute("ROLLBACK"), Err(Error::Value("Not in a transaction".into())));
+        let expect = rows(a.execute("SELECT * FROM test")?)?;
+        assert_eq!(
+            expect,
+            vec![
+                vec![Value::Integer(1), Value::Integer(1)],
+                vec![Value::Integer(2), Value::Integer(0)]
+            ]
+        );
+        drop((a, b, engine));
+
+        for state in handle.join().unwrap() {
+            assert_eq!(rows(state.engine.session()?.execute("SELECT * FROM test")?)?, expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    // A mutation that is replayed with the same request ID, e.g. when a client retries it after a
+    // leader change, returns the original result without being applied again. The request IDs are
+    // persisted by the state machine, and forgotten once the cache is full.
+    fn request_dedup() -> Result<()> {
+        let mvcc = kv::MVCC::new(Box::new(kv::Memory::new()));
+        let local = super::super::KV::new(mvcc.clone())?;
+        local.session()?.execute("CREATE TABLE items (id INTEGER PRIMARY KEY)")?;
+        let mut state = State::new(mvcc.clone(), MAX_VALUE_SIZE)?;
+        let mut index = 0;
+        let mut mutate = |state: &mut State, request_id: &[u8], mutation: Mutation| {
+            index += 1;
+            state.mutate(
+                index,
+                Raft::serialize(&Command { request_id: request_id.to_vec(), mutation })?,
+            )
+        };
+
+        let txn_id: u64 =
+            Raft::deserialize(&mutate(&mut state, b"a", Mutation::Begin(Mode::ReadWrite))?)?;
+        assert_eq!(
+            Raft::deserialize::<u64>(&mutate(&mut state, b"a", Mutation::Begin(Mode::ReadWrite))?)?,
+            txn_id
+        );
+        assert_eq!(local.status()?.txns_active, 1);
+
+        let create =
+            || Mutation::Create { txn_id, table: "items".into(), row: vec![Value::Integer(1)] };
+        mutate(&mut state, b"b", create())?;
+        mutate(&mut state, b"b", create())?;
+        assert_eq!(
+            mutate(&mut state, b"c", create()),
+            Err(Error::Value("Primary key 1 already exists for table items".into()))
+        );
+        mutate(&mut state, b"d", Mutation::Commit(txn_id))?;
+        assert_eq!(
+            rows(local.session()?.execute("SELECT * FROM items")?)?,
+            vec![vec![Value::Integer(1)]]
+        );
+
+        // The request IDs survive a restart, and errors are replayed too.
+        let mut state = State::new(mvcc, MAX_VALUE_SIZE)?;
+        mutate(&mut state, b"b", create())?;
+        assert_eq!(
+            mutate(&mut state, b"c", create()),
+            Err(Error::Value("Primary key 1 already exists for table items".into()))
+        );
+        mutate(&mut state, b"d", Mutation::Commit(txn_id))?;
+
+        // Once REQUEST_CACHE_SIZE newer requests have been applied, a request is applied again.
+        for i in 0..REQUEST_CACHE_SIZE {
+            mutate(&mut state, &i.to_be_bytes(), Mutation::Rollback(0)).ok();
+        }
+        assert_eq!(
+            mutate(&mut state, b"d", Mutation::Commit(txn_id)),
+            Err(Error::Value(format!("No active transaction {}", txn_id)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    // A batch of commands is applied in a single storage write batch with one flush, and is
+    // applied atomically: if a command fails with an internal error, none of the batch is applied.
+    fn mutate_batch() -> Result<()> {
+        let flushed = Arc::new(AtomicUsize::new(0));
+        let mvcc = kv::MVCC::new(Box::new(CountingStore {
+            kv: kv::Memory::new(),
+            scanned: Arc::new(AtomicUsize::new(0)),
+            flushed: flushed.clone(),
+        }));
+        let local = super::super::KV::new(mvcc.clone())?;
+        local.session()?.execute("CREATE TABLE items (id INTEGER PRIMARY KEY)")?;
+        let mut state = State::new(mvcc.clone(), MAX_VALUE_SIZE)?;
+        let command = |request_id: u64, mutation: Mutation| {
+            Raft::serialize(&Command { request_id: request_id.to_be_bytes().to_vec(), mutation })
+        };
+        let create = |txn_id: u64, id: i64| Mutation::Create {
+            txn_id,
+            table: "items".into(),
+            row: vec![Value::Integer(id)],
+        };
+
+        // Applying 10 commands one by one flushes once per command.
+        flushed.store(0, Ordering::SeqCst);
+        let txn_id: u64 =
+            Raft::deserialize(&state.mutate(1, command(1, Mutation::Begin(Mode::ReadWrite))?)?)?;
+        for i in 2..=9 {
+            state.mutate(i, command(i, create(txn_id, i as i64))?)?;
+        }
+        state.mutate(10, command(10, Mutation::Commit(txn_id))?)?;
+        assert_eq!(flushed.load(Ordering::SeqCst), 10);
+        assert_eq!(state.applied_index, 10);
+
+        // Applying 10 commands in a batch flushes once, and returns the results in order.
+        let txn_id: u64 =
+            Raft::deserialize(&state.mutate(11, command(11, Mutation::Begin(Mode::ReadWrite))?)?)?;
+        flushed.store(0, Ordering::SeqCst);
+        let mut commands = Vec::new();
+        for i in 12..=20 {
+            commands.push((i, command(i, create(txn_id, i as i64))?));
+        }
+        commands.push((21, command(21, Mutation::Commit(txn_id))?));
+        let results = state.mutate_batch(commands)?;
+        assert_eq!(flushed.load(Ordering::SeqCst), 1);
+        assert_eq!(results.len(), 10);
+        assert_eq!(state.applied_index, 21);
+        assert_eq!(rows(local.session()?.execute("SELECT * FROM items")?)?.len(), 17);
+
+        // Non-internal errors are returned as results, and don't affect the rest of the batch.
+        let txn_id: u64 =
+            Raft::deserialize(&state.mutate(22, command(22, Mutation::Begin(Mode::ReadWrite))?)?)?;
+        let results = state.mutate_batch(vec![
+            (23, command(23, create(txn_id, 2))?),
+            (24, command(24, create(txn_id, 100))?),
+        ])?;
+        assert_eq!(
+            results,
+            vec![
+                Err(Error::Value("Primary key 2 already exists for table items".into())),
+                Ok(vec![])
+            ]
+        );
+        assert_eq!(state.applied_index, 24);
+
+        // An internal error midway through a batch discards the entire batch, including the applied
+        // index, both in memory and in storage.
+        flushed.store(0, Ordering::SeqCst);
+        let result = state.mutate_batch(vec![
+            (25, command(25, create(txn_id, 101))?),
+            (26, command(26, Mutation::Commit(txn_id))?),
+            (27, vec![0xff]),
+            (28, command(28, Mutation::Rollback(0))?),
+        ]);
+        assert!(matches!(result, Err(Error::Internal(_))), "{:?}", result);
+        assert_eq!(flushed.load(Ordering::SeqCst), 0);
+        assert_eq!(state.applied_index, 24);
+        assert_eq!(state.request_seq, 24);
+        assert_eq!(rows(local.session()?.execute("SELECT * FROM items WHERE id > 99")?)?.len(), 0);
+
+        // The batch is replayed from the persisted applied index on restart.
+        let mut state = State::new(mvcc, MAX_VALUE_SIZE)?;
+        assert_eq!(state.applied_index, 24);
+        state.mutate_batch(vec![
+            (25, command(25, create(txn_id, 101))?),
+            (26, command(26, Mutation::Commit(txn_id))?),
+        ])?;
+        assert_eq!(state.applied_index, 26);
+        assert_eq!(rows(local.session()?.execute("SELECT * FROM items WHERE id > 99")?)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    // Table scans are fetched from the state machine in batches as the rows are consumed, rather
+    // than reading the entire table up front.
+    fn scan_batches() -> Result<()> {
+        let scanned = Arc::new(AtomicUsize::new(0));
+        let mvcc = kv::MVCC::new(Box::new(CountingStore {
+            kv: kv::Memory::new(),
+            scanned: scanned.clone(),
+            flushed: Arc::new(AtomicUsize::new(0)),
+        }));
+        let local_engine = super::super::KV::new(mvcc.clone())?;
+        let mut local = local_engine.session()?;
+        local.execute("CREATE TABLE items (id INTEGER PRIMARY KEY)")?;
+        local.execute(&format!(
+            "INSERT INTO items VALUES {}",
+            (1..=2500).map(|i| format!("({})", i)).collect::<Vec<_>>().join(", ")
+        ))?;
+
+        let (raft, _) = serve(vec![State::new(mvcc, MAX_VALUE_SIZE)?]);
+        let mut session = raft.session()?;
+        scanned.store(0, Ordering::SeqCst);
+        let mut rows = match session.execute("SELECT * FROM items")? {
+            ResultSet::Query { rows, .. } => rows,
+            r => return Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        };
+
+        // Reading the first row only scans the first batch.
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(1)]));
+        let first = scanned.load(Ordering::SeqCst);
+        assert!(first > SCAN_BATCH_SIZE && first < SCAN_BATCH_SIZE + 10, "scanned {}", first);
+
+        // Consuming the rows fetches the remaining batches, in order.
+        assert_eq!(
+            rows.collect::<Result<Vec<_>>>()?,
+            (2..=2500).map(|i| vec![Value::Integer(i)]).collect::<Vec<_>>()
+        );
+        assert!(scanned.load(Ordering::SeqCst) >= 2500);
+
+        // The implicit transaction is rolled back once the rows are consumed.
+        assert_eq!(local_engine.kv.status()?.txns_active, 0);
+
+        // A primary key range scan only scans the rows in the range.
+        scanned.store(0, Ordering::SeqCst);
+        let rows = match session.execute("SELECT * FROM items WHERE id > 2000 AND id <= 2400")? {
+            ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+            r => return Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        };
+        assert_eq!(rows, (2001..=2400).map(|i| vec![Value::Integer(i)]).collect::<Vec<_>>());
+        let range = scanned.load(Ordering::SeqCst);
+        assert!((400..450).contains(&range), "scanned {}", range);
+        Ok(())
+    }
+
+    #[test]
+    // Raw key/value pairs can be exported by key prefix and imported into another cluster, in a
+    // single transaction whose writes are split into several Raft log entries.
+    fn export_import() -> Result<()> {
+        let (engine, _) = setup();
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, value STRING)")?;
+        session.execute("CREATE TABLE other (id INTEGER PRIMARY KEY)")?;
+        session.execute("INSERT INTO items VALUES (1, 'a'), (2, 'b'), (3, NULL)")?;
+        session.execute("INSERT INTO other VALUES (1)")?;
+
+        // A prefix export only contains matching keys, e.g. table schemas.
+        let schemas = engine.export(&[0x01])?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(schemas.len(), 2);
+        assert!(schemas.iter().all(|(key, _)| key[0] == 0x01));
+        let all = engine.export(&[])?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(all.len(), 6);
+        let mut sorted = all.clone();
+        sorted.sort();
+        assert_eq!(all, sorted);
+
+        // Importing the export into a new cluster yields the same tables and rows, and the
+        // transactions are rolled back once the pairs are consumed.
+        let (fresh, handle) = setup();
+        assert_eq!(fresh.import(all.clone())?, 6);
+        assert_eq!(fresh.export(&[])?.collect::<Result<Vec<_>>>()?, all);
+        let mut fresh_session = fresh.session()?;
+        assert_eq!(
+            rows(fresh_session.execute("SELECT * FROM items")?)?,
+            rows(session.execute("SELECT * FROM items")?)?
+        );
+
+        // Importing the same keys again is idempotent, and the last value of a key wins.
+        let key = all[0].0.clone();
+        assert_eq!(fresh.import(vec![(key.clone(), vec![1]), (key.clone(), all[0].1.clone())])?, 1);
+        assert_eq!(fresh.export(&[])?.collect::<Result<Vec<_>>>()?, all);
+        drop(fresh_session);
+        drop(fresh);
+        for state in handle.join().unwrap() {
+            let mut session = state.engine.session()?;
+            assert_eq!(rows(session.execute("SELECT * FROM items")?)?.len(), 3);
+            assert_eq!(state.engine.kv.status()?.txns_active, 0);
+        }
+
+        // A large import is written in batches of about IMPORT_BATCH_SIZE bytes, i.e. 3 mutations
+        // between its begin and commit, followed by the export's begin and rollback.
+        let (large, handle) = setup();
+        let pairs = (0..5).map(|i| (vec![0xfe, i], vec![i; IMPORT_BATCH_SIZE / 2])).collect();
+        assert_eq!(large.import(pairs)?, 5);
+        assert_eq!(large.export(&[0xfe])?.count(), 5);
+        drop(large);
+        for state in handle.join().unwrap() {
+            assert_eq!(state.applied_index, 7);
+        }
+        Ok(())
+    }
+
+    #[test]
+    // Watchers receive committed row changes of their table in commit order, on all replicas.
+    fn watch() -> Result<()> {
+        let states = vec![
+            State::new(kv::MVCC::new(Box::new(kv::Memory::new())), MAX_VALUE_SIZE)?,
+            State::new(kv::MVCC::new(Box::new(kv::Memory::new())), MAX_VALUE_SIZE)?,
+        ];
+        let watchers: Vec<Watchers> = states.iter().map(|s| s.watchers()).collect();
+        let (engine, handle) = serve(states);
+        let mut a = engine.session()?;
+        let mut b = engine.session()?;
+        a.execute("CREATE TABLE items (id INTEGER PRIMARY KEY, value STRING DEFAULT 'none')")?;
+        a.execute("CREATE TABLE other (id INTEGER PRIMARY KEY)")?;
+
+        // Changes made by transactions that began before the watch are not emitted.
+        b.execute("BEGIN")?;
+        b.execute("INSERT INTO items VALUES (0, 'early')")?;
+        let mut rxs = watchers.iter().map(|w| w.watch("items")).collect::<Result<Vec<_>>>()?;
+        let dropped = watchers[0].watch("items")?;
+        drop(dropped);
+        b.execute("COMMIT")?;
+
+        a.execute("INSERT INTO items (id) VALUES (1), (2)")?;
+        a.execute("INSERT INTO other VALUES (1)")?;
+        a.execute("UPDATE items SET value = 'a' WHERE id = 1")?;
+        a.execute("BEGIN")?;
+        a.execute("DELETE FROM items WHERE id = 2")?;
+        a.execute("ROLLBACK")?;
+        a.execute("BEGIN")?;
+        b.execute("BEGIN")?;
+        a.execute("DELETE FROM items WHERE id = 2")?;
+        b.execute("INSERT INTO items VALUES (3, 'b')")?;
+        b.execute("COMMIT")?;
+        a.execute("UPDATE items SET id = 4 WHERE id = 1")?;
+        a.execute("COMMIT")?;
+        drop(a);
+        drop(b);
+        drop(engine);
+        handle.join().unwrap();
+
+        let row = |id: i64, value: &str| vec![Value::Integer(id), Value::String(value.into())];
+        let table = || "items".to_string();
+        let expect = vec![
+            Change::Insert { table: table(), row: row(1, "none") },
+            Change::Insert { table: table(), row: row(2, "none") },
+            Change::Update { table: table(), before: row(1, "none"), after: row(1, "a") },
+            Change::Insert { table: table(), row: row(3, "b") },
+            Change::Delete { table: table(), row: row(2, "none") },
+            Change::Update { table: table(), before: row(1, "a"), after: row(4, "a") },
+        ];
+        for rx in rxs.iter_mut() {
+            let mut changes = Vec::new();
+            while let Ok(change) = rx.try_recv() {
+                changes.push(change);
+            }
+            assert_eq!(changes, expect);
+        }
+
+        // The dropped watcher was removed, and the others are removed once dropped.
+        assert_eq!(watchers[0].0.lock()?.len(), 1);
+        drop(rxs);
+        for watchers in &watchers {
+            watchers.emit(vec![Change::Delete { table: table(), row: row(5, "c") }])?;
+            assert!(watchers.is_empty()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    // A statement that times out rolls back its transaction via the Raft cluster, even though the
+    // statement deadline has already passed.
+    fn timeout_rollback() -> Result<()> {
+        let (engine, handle) = setup();
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY)")?;
+        session.execute(&format!(
+            "INSERT INTO test VALUES {}",
+            (1..=100).map(|i| format!("({})", i)).collect::<Vec<_>>().join(", ")
+        ))?;
+
+        let mut session = session.with_timeout(Some(Duration::from_millis(50)));
+        session.execute("BEGIN")?;
+        session.execute("DELETE FROM test WHERE id = 1")?;
+        assert_eq!(
+            session.execute("SELECT COUNT(*) FROM test a, test b, test c").err(),
+            Some(Error::Timeout)
+        );
+        drop(session);
+        drop(engine);
+        for state in handle.join().unwrap() {
+            let mut session = state.engine.session()?;
+            assert_eq!(
+                session.execute("SELECT COUNT(*) FROM test")?.into_value()?,
+                Value::Integer(100)
+            );
+            assert_eq!(state.engine.kv.status()?.txns_active, 0);
         }
+        Ok(())
     }
 }
@@ -0,0 +1,206 @@
+use super::Engine;
+use crate::error::{Error, Result};
+use crate::storage::kv::mvcc::Vacuum;
+
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The outcome of a scheduled vacuum cycle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cycle {
+    /// The engine was vacuumed.
+    Vacuumed(Vacuum),
+    /// The cycle was skipped since the node isn't the Raft leader. Vacuums are replicated through
+    /// the Raft log, so only the leader schedules them, and they're applied on all nodes.
+    NotLeader,
+    /// The cycle was skipped since the given transaction has been active since before the
+    /// previous cycle. It holds back the vacuum watermark, so there would be little to remove.
+    LongTransaction(u64),
+    /// The cycle was skipped since the previous cycle was still running at its scheduled time.
+    Overrun,
+    /// The cycle failed with the given error, e.g. because the Raft leader was unavailable.
+    Failed(Error),
+}
+
+/// A background thread which periodically vacuums an SQL engine, see Engine::vacuum(). Cycles are
+/// scheduled at a fixed interval, and are skipped when the node isn't the leader, when a
+/// long-running transaction is active, or when the previous cycle overran. It is stopped when
+/// dropped, which waits for a running cycle to complete.
+pub struct VacuumScheduler {
+    shutdown: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VacuumScheduler {
+    /// Starts a scheduler which vacuums the engine at the given interval.
+    pub fn start<E: Engine + Send + 'static>(engine: E, interval: Duration) -> Result<Self> {
+        Self::start_with_hook(engine, interval, |_| {})
+    }
+
+    /// Starts a scheduler which vacuums the engine at the given interval, calling the hook with
+    /// the outcome of each cycle once it completes.
+    pub fn start_with_hook<E, F>(engine: E, interval: Duration, hook: F) -> Result<Self>
+    where
+        E: Engine + Send + 'static,
+        F: Fn(&Cycle) + Send + 'static,
+    {
+        let (shutdown, rx) = channel();
+        let thread = std::thread::Builder::new().name("toydb-vacuum".into()).spawn(move || {
+            let mut next = Instant::now() + interval;
+            let mut txn_next = None;
+            loop {
+                match rx.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+                }
+                let cycle = Self::cycle(&engine, &mut txn_next).unwrap_or_else(Cycle::Failed);
+                match &cycle {
+                    Cycle::Vacuumed(Vacuum { versions, bytes }) => {
+                        log::debug!("Vacuumed {} versions ({} bytes)", versions, bytes)
+                    }
+                    Cycle::LongTransaction(id) => {
+                        log::debug!("Skipped vacuum, transaction {} is long-running", id)
+                    }
+                    Cycle::Failed(err) => log::error!("Vacuum failed: {}", err),
+                    Cycle::NotLeader | Cycle::Overrun => {}
+                }
+                hook(&cycle);
+
+                next += interval;
+                while next <= Instant::now() {
+                    log::warn!("Skipped vacuum, previous vacuum is still running");
+                    hook(&Cycle::Overrun);
+                    next += interval;
+                }
+            }
+        })?;
+        Ok(Self { shutdown: Some(shutdown), thread: Some(thread) })
+    }
+
+    /// Runs a single vacuum cycle. txn_next is the next transaction ID as of the previous cycle
+    /// on the leader: transactions before it that are still active are long-running.
+    fn cycle<E: Engine>(engine: &E, txn_next: &mut Option<u64>) -> Result<Cycle> {
+        if !engine.is_leader()? {
+            *txn_next = None;
+            return Ok(Cycle::NotLeader);
+        }
+        let status = engine.mvcc_status()?;
+        let previous = txn_next.replace(status.txns + 1);
+        match (status.txns_active_oldest, previous) {
+            (Some(id), Some(previous)) if id < previous => Ok(Cycle::LongTransaction(id)),
+            _ => Ok(Cycle::Vacuumed(engine.vacuum()?)),
+        }
+    }
+}
+
+impl Drop for VacuumScheduler {
+    fn drop(&mut self) {
+        self.shutdown.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Engine as _, Mode, Transaction as _, KV};
+    use super::*;
+    use crate::storage::kv;
+    use std::sync::{Arc, Mutex};
+
+    /// Starts a scheduler with a hook that records the cycles.
+    fn start(
+        engine: KV,
+        interval: Duration,
+        delay: Duration,
+    ) -> Result<(VacuumScheduler, Arc<Mutex<Vec<Cycle>>>)> {
+        let cycles = Arc::new(Mutex::new(Vec::new()));
+        let hook_cycles = cycles.clone();
+        let scheduler = VacuumScheduler::start_with_hook(engine, interval, move |cycle| {
+            let mut cycles = hook_cycles.lock().unwrap();
+            if cycles.is_empty() {
+                std::thread::sleep(delay);
+            }
+            cycles.push(cycle.clone());
+        })?;
+        Ok((scheduler, cycles))
+    }
+
+    /// Waits until the recorded cycles satisfy the predicate, returning them.
+    fn wait(cycles: &Arc<Mutex<Vec<Cycle>>>, predicate: impl Fn(&[Cycle]) -> bool) -> Vec<Cycle> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let cycles = cycles.lock().unwrap().clone();
+            if predicate(&cycles) {
+                return cycles;
+            }
+            assert!(Instant::now() < deadline, "cycles not scheduled: {:?}", cycles);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn vacuumed(cycles: &[Cycle]) -> usize {
+        cycles.iter().filter(|c| matches!(c, Cycle::Vacuumed(_))).count()
+    }
+
+    #[test]
+    // Vacuums run periodically, and are skipped while a transaction has been active since before
+    // the previous cycle.
+    fn schedule() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)")?;
+        session.execute("INSERT INTO test VALUES (1, 'a'), (2, 'b')")?;
+        session.execute("UPDATE test SET value = 'c'")?;
+
+        let (scheduler, cycles) =
+            start(engine.clone(), Duration::from_millis(10), Duration::from_millis(0))?;
+        let c = wait(&cycles, |c| vacuumed(c) >= 3);
+        match &c[0] {
+            Cycle::Vacuumed(vacuum) => assert_eq!(vacuum.versions, 2),
+            cycle => panic!("unexpected cycle {:?}", cycle),
+        }
+        assert!(c[1..].iter().all(|c| matches!(
+            c,
+            Cycle::Vacuumed(Vacuum { versions: 0, bytes: 0 }) | Cycle::Overrun
+        )));
+
+        // A long-running transaction skips cycles, once it has been active for a whole cycle.
+        let txn = engine.begin(Mode::ReadWrite)?;
+        let id = txn.id();
+        let start = cycles.lock().unwrap().len();
+        let c = wait(&cycles, |c| {
+            c[start..].iter().filter(|c| **c == Cycle::LongTransaction(id)).count() >= 3
+        });
+        let skipped = c.iter().position(|c| *c == Cycle::LongTransaction(id)).unwrap();
+        assert!(vacuumed(&c[start..skipped]) <= 2);
+        assert_eq!(vacuumed(&c[skipped..]), 0);
+
+        // Once it completes, vacuums resume.
+        txn.rollback()?;
+        let start = cycles.lock().unwrap().len();
+        wait(&cycles, |c| vacuumed(&c[start..]) >= 2);
+
+        // Dropping the scheduler stops it.
+        drop(scheduler);
+        let count = cycles.lock().unwrap().len();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(cycles.lock().unwrap().len(), count);
+        Ok(())
+    }
+
+    #[test]
+    // Cycles are skipped while the previous cycle is still running.
+    fn overrun() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let (_scheduler, cycles) =
+            start(engine, Duration::from_millis(10), Duration::from_millis(55))?;
+        let c = wait(&cycles, |c| vacuumed(c) >= 2);
+        assert!(matches!(c[0], Cycle::Vacuumed(_)));
+        assert!(c[1..6].iter().all(|c| *c == Cycle::Overrun), "{:?}", c);
+        assert!(matches!(c.last(), Some(Cycle::Vacuumed(_))));
+        Ok(())
+    }
+}
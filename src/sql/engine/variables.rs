@@ -0,0 +1,110 @@
+use crate::error::{Error, Result};
+use crate::sql::types::Value;
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// The default maximum number of iterations of each recursive CTE, such that a recursive CTE
+/// without a termination condition errors rather than running forever.
+pub const MAX_RECURSIVE_ITERATIONS: u64 = 1000;
+
+/// Session variables, which configure statement execution for a session. They can be set with
+/// SET and shown with SHOW, and are initialized via the Session::with_*() methods. Limits are
+/// non-negative integers, or NULL for no limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variables {
+    /// Whether transactions (including implicit ones) are read-only unless BEGIN READ WRITE is
+    /// given, i.e. default_transaction_read_only.
+    pub default_transaction_read_only: bool,
+    /// The maximum number of values buffered by aggregates in a statement, if any.
+    pub max_aggregate_values: Option<usize>,
+    /// The maximum number of iterations of recursive CTEs, if any. Defaults to
+    /// MAX_RECURSIVE_ITERATIONS.
+    pub max_recursive_iterations: Option<u64>,
+    /// The maximum number of rows returned by a query, if any.
+    pub max_result_rows: Option<u64>,
+    /// The memory budget of each sort in a statement, if any.
+    pub max_sort_memory: Option<usize>,
+    /// The memory limit of each statement, if any.
+    pub max_statement_memory: Option<usize>,
+    /// The statement timeout, if any, i.e. statement_timeout_ms.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Default for Variables {
+    fn default() -> Self {
+        Self {
+            default_transaction_read_only: false,
+            max_aggregate_values: None,
+            max_recursive_iterations: Some(MAX_RECURSIVE_ITERATIONS),
+            max_result_rows: None,
+            max_sort_memory: None,
+            max_statement_memory: None,
+            statement_timeout: None,
+        }
+    }
+}
+
+impl Variables {
+    /// The names of all session variables, in the order listed by SHOW ALL.
+    pub const NAMES: [&'static str; 7] = [
+        "default_transaction_read_only",
+        "max_aggregate_values",
+        "max_recursive_iterations",
+        "max_result_rows",
+        "max_sort_memory",
+        "max_statement_memory",
+        "statement_timeout_ms",
+    ];
+
+    /// Returns the value of the given session variable.
+    pub fn get(&self, name: &str) -> Result<Value> {
+        let limit = |limit: Option<u64>| match limit {
+            Some(limit) => Value::Integer(i64::try_from(limit).unwrap_or(i64::MAX)),
+            None => Value::Null,
+        };
+        Ok(match name {
+            "default_transaction_read_only" => Value::Boolean(self.default_transaction_read_only),
+            "max_aggregate_values" => limit(self.max_aggregate_values.map(|v| v as u64)),
+            "max_recursive_iterations" => limit(self.max_recursive_iterations),
+            "max_result_rows" => limit(self.max_result_rows),
+            "max_sort_memory" => limit(self.max_sort_memory.map(|v| v as u64)),
+            "max_statement_memory" => limit(self.max_statement_memory.map(|v| v as u64)),
+            "statement_timeout_ms" => {
+                limit(self.statement_timeout.map(|t| t.as_millis().min(u64::MAX as u128) as u64))
+            }
+            name => return Err(Error::Value(format!("Unknown session variable {}", name))),
+        })
+    }
+
+    /// Sets the given session variable, erroring if the value has the wrong type.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<()> {
+        let invalid = |value: &Value, expected: &str| {
+            Error::Value(format!(
+                "Invalid value {} for session variable {}, expected {}",
+                value, name, expected
+            ))
+        };
+        let limit = |value: Value| match value {
+            Value::Null => Ok(None),
+            Value::Integer(i) if i >= 0 => Ok(Some(i as u64)),
+            value => Err(invalid(&value, "non-negative INTEGER or NULL")),
+        };
+        match name {
+            "default_transaction_read_only" => match value {
+                Value::Boolean(b) => self.default_transaction_read_only = b,
+                value => return Err(invalid(&value, "BOOLEAN")),
+            },
+            "max_aggregate_values" => self.max_aggregate_values = limit(value)?.map(|v| v as usize),
+            "max_recursive_iterations" => self.max_recursive_iterations = limit(value)?,
+            "max_result_rows" => self.max_result_rows = limit(value)?,
+            "max_sort_memory" => self.max_sort_memory = limit(value)?.map(|v| v as usize),
+            "max_statement_memory" => self.max_statement_memory = limit(value)?.map(|v| v as usize),
+            "statement_timeout_ms" => {
+                self.statement_timeout = limit(value)?.map(Duration::from_millis)
+            }
+            name => return Err(Error::Value(format!("Unknown session variable {}", name))),
+        }
+        Ok(())
+    }
+}
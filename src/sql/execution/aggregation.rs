@@ -1,62 +1,67 @@
 use super::super::engine::Transaction;
 use super::super::plan::Aggregate;
-use super::super::types::{Column, Value};
-use super::{Executor, ResultSet};
+use super::super::types::{Column, Rows, Value};
+use super::{Executor, MemoryReservation, MemoryTracker, ResultSet};
 use crate::error::{Error, Result};
 
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem::discriminant;
 
-/// An aggregation executor
+/// An aggregation executor. Groups and buffered values are charged to the statement's memory
+/// tracker until the aggregated rows are emitted.
 pub struct Aggregation<T: Transaction> {
     source: Box<dyn Executor<T>>,
     aggregates: Vec<Aggregate>,
-    accumulators: HashMap<Vec<Value>, Vec<Box<dyn Accumulator>>>,
+    max_aggregate_values: Option<usize>,
+    memory: MemoryTracker,
 }
 
 impl<T: Transaction> Aggregation<T> {
-    pub fn new(source: Box<dyn Executor<T>>, aggregates: Vec<Aggregate>) -> Box<Self> {
-        Box::new(Self { source, aggregates, accumulators: HashMap::new() })
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        aggregates: Vec<Aggregate>,
+        max_aggregate_values: Option<usize>,
+        memory: MemoryTracker,
+    ) -> Box<Self> {
+        Box::new(Self { source, aggregates, max_aggregate_values, memory })
     }
 }
 
 impl<T: Transaction> Executor<T> for Aggregation<T> {
-    #[allow(clippy::or_fun_call)]
-    fn execute(mut self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let agg_count = self.aggregates.len();
+        let input_count = self.aggregates.iter().map(|a| a.inputs()).sum();
+        let mut groups =
+            Groups::new(self.aggregates, self.max_aggregate_values, self.memory.reserve());
         match self.source.execute(txn)? {
             ResultSet::Query { columns, mut rows } => {
-                while let Some(mut row) = rows.next().transpose()? {
-                    self.accumulators
-                        .entry(row.split_off(self.aggregates.len()))
-                        .or_insert(
-                            self.aggregates.iter().map(|agg| Accumulator::from(agg)).collect(),
-                        )
-                        .iter_mut()
-                        .zip(row)
-                        .try_for_each(|(acc, value)| acc.accumulate(&value))?
+                while let Some(batch) = rows.next_batch().transpose()? {
+                    for row in batch {
+                        groups.accumulate(row)?;
+                    }
                 }
+                groups.accumulate_ordered()?;
                 // If there were no rows and no group-by columns, return a row of empty accumulators:
                 // SELECT COUNT(*) FROM t WHERE FALSE
-                if self.accumulators.is_empty() && self.aggregates.len() == columns.len() {
-                    self.accumulators.insert(
+                if groups.accumulators.is_empty() && input_count == columns.len() {
+                    groups.accumulators.insert(
                         Vec::new(),
-                        self.aggregates.iter().map(|agg| Accumulator::from(agg)).collect(),
+                        groups.aggregates.iter().map(|agg| Accumulator::from(agg)).collect(),
                     );
                 }
+                let Groups { accumulators, memory, .. } = groups;
                 Ok(ResultSet::Query {
-                    columns: columns
-                        .into_iter()
-                        .enumerate()
-                        .map(|(i, c)| if i < agg_count { Column { name: None } } else { c })
+                    columns: (0..agg_count)
+                        .map(|_| Column { name: None, datatype: None })
+                        .chain(columns.into_iter().skip(input_count))
                         .collect(),
-                    rows: Box::new(self.accumulators.into_iter().map(|(bucket, accs)| {
-                        Ok(accs
-                            .into_iter()
+                    rows: memory.hold(Rows::new(accumulators.into_iter().map(|(bucket, accs)| {
+                        accs.into_iter()
                             .map(|acc| acc.aggregate())
-                            .chain(bucket.into_iter())
-                            .collect())
-                    })),
+                            .chain(bucket.into_iter().map(Ok))
+                            .collect()
+                    }))),
                 })
             }
             r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
@@ -64,23 +69,145 @@ impl<T: Transaction> Executor<T> for Aggregation<T> {
     }
 }
 
+/// Aggregation state for groups
+#[allow(clippy::type_complexity)]
+struct Groups {
+    aggregates: Vec<Aggregate>,
+    accumulators: HashMap<Vec<Value>, Vec<Box<dyn Accumulator>>>,
+    /// Values seen by DISTINCT aggregates, as group, aggregate index, and value
+    distinct: HashSet<(Vec<Value>, usize, Value)>,
+    /// Values buffered for ordered aggregates, by group and aggregate index, with their sort keys
+    ordered: HashMap<(Vec<Value>, usize), Vec<(Vec<Value>, Value)>>,
+    /// The number of values buffered in distinct and ordered
+    buffered: usize,
+    max_buffered: Option<usize>,
+    /// The memory charged for groups and buffered values
+    memory: MemoryReservation,
+}
+
+impl Groups {
+    fn new(
+        aggregates: Vec<Aggregate>,
+        max_buffered: Option<usize>,
+        memory: MemoryReservation,
+    ) -> Self {
+        Self {
+            aggregates,
+            accumulators: HashMap::new(),
+            distinct: HashSet::new(),
+            ordered: HashMap::new(),
+            buffered: 0,
+            max_buffered,
+            memory,
+        }
+    }
+
+    /// Accumulates a source row, containing the aggregate inputs followed by the group values.
+    fn accumulate(&mut self, mut row: Vec<Value>) -> Result<()> {
+        let group = row.split_off(self.aggregates.iter().map(|a| a.inputs()).sum());
+        if !self.accumulators.contains_key(&group) {
+            // Charge new groups for their values and (approximately) their accumulators.
+            self.memory.grow_value(&group)?;
+            self.memory.grow(self.aggregates.len() * std::mem::size_of::<Value>())?;
+            let accumulators = self.aggregates.iter().map(|agg| Accumulator::from(agg)).collect();
+            self.accumulators.insert(group.clone(), accumulators);
+        }
+        let aggregates = &self.aggregates;
+        let accumulators = self
+            .accumulators
+            .get_mut(&group)
+            .ok_or_else(|| Error::Internal("Group accumulators not found".into()))?;
+        let mut values = row.into_iter();
+        for (i, aggregate) in aggregates.iter().enumerate() {
+            let value = values.next().unwrap_or(Value::Null);
+            let (aggregate, keys) = match aggregate {
+                Aggregate::Ordered(aggregate, orders) => {
+                    (&**aggregate, Some(values.by_ref().take(orders.len()).collect()))
+                }
+                aggregate => (aggregate, None),
+            };
+            // NULLs are skipped by all aggregates, including DISTINCT aggregates which also skip
+            // values already seen in the group.
+            if value == Value::Null {
+                continue;
+            }
+            if let Aggregate::Distinct(_) = aggregate {
+                if !self.distinct.insert((group.clone(), i, value.clone())) {
+                    continue;
+                }
+                self.memory.grow_value(&(&group, &value))?;
+                self.buffered += 1;
+            }
+            // Ordered aggregates buffer their values until all rows have been seen.
+            match keys {
+                Some(keys) => {
+                    self.memory.grow_value(&(&keys, &value))?;
+                    self.ordered.entry((group.clone(), i)).or_default().push((keys, value));
+                    self.buffered += 1;
+                }
+                None => accumulators[i].accumulate(&value)?,
+            }
+        }
+        match self.max_buffered {
+            Some(max) if self.buffered > max => {
+                Err(Error::Value(format!("Aggregates exceeded limit of {} buffered values", max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Accumulates the buffered values of ordered aggregates, in order.
+    fn accumulate_ordered(&mut self) -> Result<()> {
+        for ((group, i), mut values) in self.ordered.drain() {
+            let orders = match &self.aggregates[i] {
+                Aggregate::Ordered(_, orders) => orders,
+                aggregate => {
+                    return Err(Error::Internal(format!("Unexpected aggregate {}", aggregate)))
+                }
+            };
+            values.sort_by(|(a, _), (b, _)| {
+                for (j, (direction, nulls)) in orders.iter().enumerate() {
+                    match direction.compare(nulls, &a[j], &b[j]) {
+                        Ordering::Equal => {}
+                        o => return o,
+                    }
+                }
+                Ordering::Equal
+            });
+            let accumulator = self
+                .accumulators
+                .get_mut(&group)
+                .map(|accumulators| &mut accumulators[i])
+                .ok_or_else(|| Error::Internal("Missing accumulator for group".into()))?;
+            for (_, value) in values {
+                accumulator.accumulate(&value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 // An accumulator
 pub trait Accumulator: std::fmt::Debug + Send {
     // Accumulates a value
     fn accumulate(&mut self, value: &Value) -> Result<()>;
 
     // Calculates a final aggregate
-    fn aggregate(&self) -> Value;
+    fn aggregate(&self) -> Result<Value>;
 }
 
 impl dyn Accumulator {
-    fn from(aggregate: &Aggregate) -> Box<dyn Accumulator> {
+    pub(super) fn from(aggregate: &Aggregate) -> Box<dyn Accumulator> {
         match aggregate {
             Aggregate::Average => Box::new(Average::new()),
             Aggregate::Count => Box::new(Count::new()),
             Aggregate::Max => Box::new(Max::new()),
             Aggregate::Min => Box::new(Min::new()),
             Aggregate::Sum => Box::new(Sum::new()),
+            Aggregate::StringAgg(separator) => Box::new(StringAgg::new(separator.clone())),
+            Aggregate::Distinct(aggregate) | Aggregate::Ordered(aggregate, _) => {
+                Self::from(aggregate)
+            }
         }
     }
 }
@@ -106,8 +233,8 @@ impl Accumulator for Count {
         Ok(())
     }
 
-    fn aggregate(&self) -> Value {
-        Value::Integer(self.count as i64)
+    fn aggregate(&self) -> Result<Value> {
+        Ok(Value::Integer(self.count as i64))
     }
 }
 
@@ -131,16 +258,17 @@ impl Accumulator for Average {
         Ok(())
     }
 
-    fn aggregate(&self) -> Value {
-        match (self.sum.aggregate(), self.count.aggregate()) {
+    fn aggregate(&self) -> Result<Value> {
+        Ok(match (self.sum.aggregate()?, self.count.aggregate()?) {
             (Value::Integer(s), Value::Integer(c)) => Value::Integer(s / c),
             (Value::Float(s), Value::Integer(c)) => Value::Float(s / c as f64),
+            (Value::Decimal(s), Value::Integer(c)) => Value::Decimal(s.checked_div(c.into())?),
             _ => Value::Null,
-        }
+        })
     }
 }
 
-// Maximum value
+// Maximum non-null value
 #[derive(Debug)]
 pub struct Max {
     max: Option<Value>,
@@ -154,9 +282,12 @@ impl Max {
 
 impl Accumulator for Max {
     fn accumulate(&mut self, value: &Value) -> Result<()> {
+        if *value == Value::Null {
+            return Ok(());
+        }
         if let Some(max) = &mut self.max {
             match value.partial_cmp(max) {
-                _ if max.datatype() != value.datatype() => *max = Value::Null,
+                _ if discriminant(max) != discriminant(value) => *max = Value::Null,
                 None => *max = Value::Null,
                 Some(Ordering::Greater) => *max = value.clone(),
                 Some(Ordering::Equal) | Some(Ordering::Less) => {}
@@ -167,15 +298,15 @@ impl Accumulator for Max {
         Ok(())
     }
 
-    fn aggregate(&self) -> Value {
-        match &self.max {
+    fn aggregate(&self) -> Result<Value> {
+        Ok(match &self.max {
             Some(value) => value.clone(),
             None => Value::Null,
-        }
+        })
     }
 }
 
-// Minimum value
+// Minimum non-null value
 #[derive(Debug)]
 pub struct Min {
     min: Option<Value>,
@@ -189,9 +320,12 @@ impl Min {
 
 impl Accumulator for Min {
     fn accumulate(&mut self, value: &Value) -> Result<()> {
+        if *value == Value::Null {
+            return Ok(());
+        }
         if let Some(min) = &mut self.min {
             match value.partial_cmp(min) {
-                _ if min.datatype() != value.datatype() => *min = Value::Null,
+                _ if discriminant(min) != discriminant(value) => *min = Value::Null,
                 None => *min = Value::Null,
                 Some(Ordering::Less) => *min = value.clone(),
                 Some(Ordering::Equal) | Some(Ordering::Greater) => {}
@@ -202,15 +336,15 @@ impl Accumulator for Min {
         Ok(())
     }
 
-    fn aggregate(&self) -> Value {
-        match &self.min {
+    fn aggregate(&self) -> Result<Value> {
+        Ok(match &self.min {
             Some(value) => value.clone(),
             None => Value::Null,
-        }
+        })
     }
 }
 
-// Sum of values
+// Sum of non-null values
 #[derive(Debug)]
 pub struct Sum {
     sum: Option<Value>,
@@ -229,15 +363,54 @@ impl Accumulator for Sum {
             (Some(Value::Float(s)), Value::Float(f)) => Some(Value::Float(s + f)),
             (None, Value::Integer(i)) => Some(Value::Integer(*i)),
             (None, Value::Float(f)) => Some(Value::Float(*f)),
+            (Some(Value::Decimal(s)), Value::Decimal(d)) => {
+                Some(Value::Decimal(s.checked_add(*d)?))
+            }
+            (None, Value::Decimal(d)) => Some(Value::Decimal(*d)),
+            (_, Value::Null) => return Ok(()),
             _ => Some(Value::Null),
         };
         Ok(())
     }
 
-    fn aggregate(&self) -> Value {
-        match &self.sum {
+    fn aggregate(&self) -> Result<Value> {
+        Ok(match &self.sum {
             Some(value) => value.clone(),
             None => Value::Null,
+        })
+    }
+}
+
+// Concatenation of non-null values, separated by a separator
+#[derive(Debug)]
+pub struct StringAgg {
+    separator: String,
+    string: Option<String>,
+}
+
+impl StringAgg {
+    pub fn new(separator: String) -> Self {
+        Self { separator, string: None }
+    }
+}
+
+impl Accumulator for StringAgg {
+    fn accumulate(&mut self, value: &Value) -> Result<()> {
+        match (&mut self.string, value) {
+            (_, Value::Null) => {}
+            (Some(string), value) => {
+                string.push_str(&self.separator);
+                string.push_str(&value.to_string());
+            }
+            (None, value) => self.string = Some(value.to_string()),
         }
+        Ok(())
+    }
+
+    fn aggregate(&self) -> Result<Value> {
+        Ok(match &self.string {
+            Some(string) => Value::String(string.clone()),
+            None => Value::Null,
+        })
     }
 }
@@ -0,0 +1,387 @@
+use super::super::engine::Transaction;
+use super::super::types::{DataType, Value, BATCH_SIZE};
+use super::mutation::Insert;
+use super::{Executor, ResultSet};
+use crate::error::{Error, Result};
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// A COPY FROM executor, which inserts the rows of a CSV file into a table. Values are converted
+/// to the column datatypes, and errors are reported with the line and column of the value. Rows
+/// are inserted in batches of BATCH_SIZE rows, i.e. one Raft mutation per batch.
+pub struct CopyFrom {
+    table: String,
+    path: String,
+    header: bool,
+    delimiter: char,
+}
+
+impl CopyFrom {
+    pub fn new(table: String, path: String, header: bool, delimiter: char) -> Box<Self> {
+        Box::new(Self { table, path, header, delimiter })
+    }
+
+    /// Converts a CSV field to a value of the given datatype. Binary values are given in hex as
+    /// \x00ff, and other values are cast from strings.
+    fn parse_value(field: Option<String>, datatype: &DataType) -> Result<Value> {
+        match (field, datatype) {
+            (None, _) => Ok(Value::Null),
+            (Some(s), DataType::Bytes) => match s.strip_prefix("\\x") {
+                Some(hex) if hex.len() % 2 == 0 => (0..hex.len())
+                    .step_by(2)
+                    .map(|i| {
+                        hex.get(i..i + 2)
+                            .and_then(|b| u8::from_str_radix(b, 16).ok())
+                            .ok_or_else(|| Error::Value(format!("Invalid hex value {}", s)))
+                    })
+                    .collect::<Result<_>>()
+                    .map(Value::Bytes),
+                _ => Err(Error::Value(format!("Invalid hex value {}", s))),
+            },
+            (Some(s), datatype) => Value::String(s).cast(datatype),
+        }
+    }
+}
+
+impl<T: Transaction> Executor<T> for CopyFrom {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_read_table(&self.table)?;
+        let file = File::open(&self.path)
+            .map_err(|err| Error::Value(format!("Can't open {}: {}", self.path, err)))?;
+        let mut records = CsvReader::new(BufReader::new(file), self.delimiter);
+
+        let columns: Vec<String> = match self.header {
+            true => match records.next().transpose()? {
+                Some((line, fields)) => fields
+                    .into_iter()
+                    .map(|f| {
+                        f.ok_or_else(|| Error::Value(format!("Line {}: empty column name", line)))
+                    })
+                    .collect::<Result<_>>()?,
+                None => return Ok(ResultSet::Create { count: 0 }),
+            },
+            false => table
+                .columns
+                .iter()
+                .filter(|c| c.generated.is_none())
+                .map(|c| c.name.clone())
+                .collect(),
+        };
+        let datatypes = columns
+            .iter()
+            .map(|c| Ok(table.get_column(c)?.datatype.clone()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut count = 0;
+        let mut batch = Vec::new();
+        for record in records {
+            let (line, fields) = record?;
+            if fields.len() != columns.len() {
+                return Err(Error::Value(format!(
+                    "Line {}: expected {} values, got {}",
+                    line,
+                    columns.len(),
+                    fields.len()
+                )));
+            }
+            let values = fields
+                .into_iter()
+                .zip(&datatypes)
+                .zip(&columns)
+                .map(|((field, datatype), column)| {
+                    Self::parse_value(field, datatype).map_err(|err| {
+                        Error::Value(format!("Line {}, column {}: {}", line, column, err))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mut row = Insert::<T>::make_row(&table, &columns, values)
+                .map_err(|err| Error::Value(format!("Line {}: {}", line, err)))?;
+            table
+                .generate_row(&mut row)
+                .map_err(|err| Error::Value(format!("Line {}: {}", line, err)))?;
+            batch.push(row);
+            if batch.len() >= BATCH_SIZE {
+                txn.create_batch(&table.name, std::mem::take(&mut batch))?;
+            }
+            count += 1;
+        }
+        if !batch.is_empty() {
+            txn.create_batch(&table.name, batch)?;
+        }
+        Ok(ResultSet::Create { count })
+    }
+}
+
+/// A COPY TO executor, which writes the non-generated columns of a table's rows to a CSV file in
+/// primary key order. The rows are streamed from a table scan, rather than buffered in memory.
+pub struct CopyTo {
+    table: String,
+    path: String,
+    header: bool,
+    delimiter: char,
+}
+
+impl CopyTo {
+    pub fn new(table: String, path: String, header: bool, delimiter: char) -> Box<Self> {
+        Box::new(Self { table, path, header, delimiter })
+    }
+
+    /// Formats a value as a CSV field, such that it parses back to the same value, see
+    /// CopyFrom::parse_value().
+    fn format_value(value: Value) -> Option<String> {
+        match value {
+            Value::Null => None,
+            Value::String(s) => Some(s),
+            Value::Bytes(b) => {
+                Some(format!("\\x{}", b.iter().map(|b| format!("{:02x}", b)).collect::<String>()))
+            }
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+impl<T: Transaction> Executor<T> for CopyTo {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_read_table(&self.table)?;
+        let columns: Vec<usize> =
+            (0..table.columns.len()).filter(|i| table.columns[*i].generated.is_none()).collect();
+        let file = File::create(&self.path)
+            .map_err(|err| Error::Value(format!("Can't create {}: {}", self.path, err)))?;
+        let mut writer = CsvWriter::new(BufWriter::new(file), self.delimiter);
+        if self.header {
+            writer.write_record(
+                columns.iter().map(|i| Some(table.columns[*i].name.clone())).collect(),
+            )?;
+        }
+        let mut count = 0;
+        let mut scan = txn.scan(&table.name, None)?;
+        while let Some(mut row) = scan.next().transpose()? {
+            let fields = columns
+                .iter()
+                .map(|i| Self::format_value(std::mem::replace(&mut row[*i], Value::Null)))
+                .collect();
+            writer.write_record(fields)?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(ResultSet::Copy { count })
+    }
+}
+
+/// A CSV reader, following RFC 4180. Records are separated by LF or CRLF line breaks, and fields
+/// by the delimiter. Fields can be quoted with ", in which case they may contain delimiters, line
+/// breaks, and quotes escaped as "". Unquoted empty fields are NULL (None), while quoted empty
+/// fields are empty strings. Yields records along with the line number they start on.
+struct CsvReader<R: BufRead> {
+    input: R,
+    delimiter: char,
+    line: u64,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    fn new(input: R, delimiter: char) -> Self {
+        Self { input, delimiter, line: 0 }
+    }
+
+    /// Reads the next record, if any.
+    fn read_record(&mut self) -> Result<Option<(u64, Vec<Option<String>>)>> {
+        let start = self.line + 1;
+        let mut record = String::new();
+        // A record continues on the next line while inside a quoted field, i.e. while it contains
+        // an odd number of quotes, since escaped quotes come in pairs.
+        loop {
+            if self.input.read_line(&mut record)? == 0 {
+                if record.is_empty() {
+                    return Ok(None);
+                }
+                return Err(Error::Value(format!("Line {}: unterminated quoted field", start)));
+            }
+            self.line += 1;
+            if record.matches('"').count() % 2 == 0 {
+                break;
+            }
+        }
+        if record.ends_with('\n') {
+            record.pop();
+            if record.ends_with('\r') {
+                record.pop();
+            }
+        }
+        Ok(Some((start, self.parse_record(&record, start)?)))
+    }
+
+    /// Parses a record's fields.
+    fn parse_record(&self, record: &str, line: u64) -> Result<Vec<Option<String>>> {
+        let mut fields = Vec::new();
+        let mut chars = record.chars().peekable();
+        loop {
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => field.push(c),
+                        None => {
+                            return Err(Error::Value(format!(
+                                "Line {}: unterminated quoted field",
+                                line
+                            )))
+                        }
+                    }
+                }
+                fields.push(Some(field));
+                match chars.next() {
+                    Some(c) if c == self.delimiter => {}
+                    Some(c) => {
+                        return Err(Error::Value(format!(
+                            "Line {}: unexpected character {} after quoted field",
+                            line, c
+                        )))
+                    }
+                    None => return Ok(fields),
+                }
+            } else {
+                let mut field = String::new();
+                let end = loop {
+                    match chars.next() {
+                        Some(c) if c == self.delimiter => break false,
+                        Some('"') => {
+                            return Err(Error::Value(format!(
+                                "Line {}: unexpected quote in unquoted field",
+                                line
+                            )))
+                        }
+                        Some(c) => field.push(c),
+                        None => break true,
+                    }
+                };
+                fields.push(if field.is_empty() { None } else { Some(field) });
+                if end {
+                    return Ok(fields);
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<(u64, Vec<Option<String>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// A CSV writer, following RFC 4180 like CsvReader. Records are terminated by LF, and fields are
+/// quoted if they're empty or contain delimiters, quotes, or line breaks.
+struct CsvWriter<W: Write> {
+    output: W,
+    delimiter: char,
+}
+
+impl<W: Write> CsvWriter<W> {
+    fn new(output: W, delimiter: char) -> Self {
+        Self { output, delimiter }
+    }
+
+    /// Writes a record, where None fields are written as NULL (i.e. unquoted empty fields).
+    fn write_record(&mut self, fields: Vec<Option<String>>) -> Result<()> {
+        let mut record = String::new();
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                record.push(self.delimiter);
+            }
+            match field {
+                None => {}
+                Some(f)
+                    if f.is_empty()
+                        || f.contains(|c| {
+                            c == self.delimiter || matches!(c, '"' | '\n' | '\r')
+                        }) =>
+                {
+                    record.push('"');
+                    record.push_str(&f.replace('"', "\"\""));
+                    record.push('"');
+                }
+                Some(f) => record.push_str(&f),
+            }
+        }
+        record.push('\n');
+        Ok(self.output.write_all(record.as_bytes())?)
+    }
+
+    /// Flushes any buffered output.
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.output.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn read(input: &str, delimiter: char) -> Result<Vec<(u64, Vec<Option<String>>)>> {
+        CsvReader::new(input.as_bytes(), delimiter).collect()
+    }
+
+    fn fields(fields: &[Option<&str>]) -> Vec<Option<String>> {
+        fields.iter().map(|f| f.map(String::from)).collect()
+    }
+
+    #[test]
+    fn reader() -> Result<()> {
+        assert_eq!(read("", ',')?, vec![]);
+        assert_eq!(
+            read("a,b,c\n1,,\"\"\r\n\"x,\"\"y\"\"\nz\",2,3", ',')?,
+            vec![
+                (1, fields(&[Some("a"), Some("b"), Some("c")])),
+                (2, fields(&[Some("1"), None, Some("")])),
+                (3, fields(&[Some("x,\"y\"\nz"), Some("2"), Some("3")])),
+            ]
+        );
+        assert_eq!(read("\n", ',')?, vec![(1, fields(&[None]))]);
+        assert_eq!(read("a;b,c\n", ';')?, vec![(1, fields(&[Some("a"), Some("b,c")]))]);
+
+        assert_eq!(
+            read("a\n\"b\nc", ',').err(),
+            Some(Error::Value("Line 2: unterminated quoted field".into()))
+        );
+        assert_eq!(
+            read("\"a\"b", ',').err(),
+            Some(Error::Value("Line 1: unexpected character b after quoted field".into()))
+        );
+        assert_eq!(
+            read("a\"b\"", ',').err(),
+            Some(Error::Value("Line 1: unexpected quote in unquoted field".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writer() -> Result<()> {
+        let records = vec![
+            fields(&[Some("a"), None, Some("")]),
+            fields(&[Some("x,\"y\"\r\nz"), Some(" b "), Some("c;d")]),
+            fields(&[None]),
+        ];
+        let mut output = Vec::new();
+        let mut writer = CsvWriter::new(&mut output, ',');
+        for record in records.clone() {
+            writer.write_record(record)?;
+        }
+        writer.flush()?;
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "a,,\"\"\n\"x,\"\"y\"\"\r\nz\", b ,c;d\n\n");
+
+        let read = read(&output, ',')?.into_iter().map(|(_, r)| r).collect::<Vec<_>>();
+        assert_eq!(read, records);
+        Ok(())
+    }
+}
@@ -1,17 +1,21 @@
 use super::super::engine::Transaction;
-use super::super::types::{Expression, Rows};
-use super::{Executor, ResultSet, Row, Value};
+use super::super::types::{self, Expression, Rows};
+use super::{Executor, MemoryReservation, MemoryTracker, ResultSet, Row, Value};
 use crate::error::{Error, Result};
 
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 
 /// A nested loop join executor, which checks each row in the left source against every row in
-/// the right source using the given predicate.
+/// the right source using the given predicate. Full outer joins keep track of which right rows
+/// were matched, and emit the unmatched ones once the left source is exhausted.
 pub struct NestedLoopJoin<T: Transaction> {
     left: Box<dyn Executor<T>>,
     right: Box<dyn Executor<T>>,
     predicate: Option<Expression>,
     outer: bool,
+    full: bool,
 }
 
 impl<T: Transaction> NestedLoopJoin<T> {
@@ -20,8 +24,9 @@ impl<T: Transaction> NestedLoopJoin<T> {
         right: Box<dyn Executor<T>>,
         predicate: Option<Expression>,
         outer: bool,
+        full: bool,
     ) -> Box<Self> {
-        Box::new(Self { left, right, predicate, outer })
+        Box::new(Self { left, right, predicate, outer, full })
     }
 }
 
@@ -29,18 +34,21 @@ impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         if let ResultSet::Query { mut columns, rows } = self.left.execute(txn)? {
             if let ResultSet::Query { columns: rcolumns, rows: rrows } = self.right.execute(txn)? {
+                let left_width = columns.len();
                 let right_width = rcolumns.len();
                 columns.extend(rcolumns);
                 // FIXME Since making the iterators or sources clonable is non-trivial (requiring
                 // either avoiding Rust standard iterators or making sources generic), we simply
                 // fetch the entire right result as a vector.
                 return Ok(ResultSet::Query {
-                    rows: Box::new(NestedLoopRows::new(
+                    rows: Rows::new(NestedLoopRows::new(
                         rows,
                         rrows.collect::<Result<Vec<_>>>()?,
+                        left_width,
                         right_width,
                         self.predicate,
                         self.outer,
+                        self.full,
                     )),
                     columns,
                 });
@@ -53,31 +61,39 @@ impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
 struct NestedLoopRows {
     left: Rows,
     left_row: Option<Result<Row>>,
-    right: Box<dyn Iterator<Item = Row> + Send>,
-    right_vec: Vec<Row>,
+    left_empty: Vec<Value>,
+    right: Vec<Row>,
+    right_index: usize,
     right_empty: Vec<Value>,
     right_hit: bool,
+    right_matched: Vec<bool>,
     predicate: Option<Expression>,
     outer: bool,
+    full: bool,
 }
 
 impl NestedLoopRows {
     fn new(
         mut left: Rows,
         right: Vec<Row>,
+        left_width: usize,
         right_width: usize,
         predicate: Option<Expression>,
         outer: bool,
+        full: bool,
     ) -> Self {
         Self {
             left_row: left.next(),
             left,
-            right: Box::new(right.clone().into_iter()),
-            right_vec: right,
+            left_empty: std::iter::repeat(Value::Null).take(left_width).collect(),
+            right_index: 0,
             right_empty: std::iter::repeat(Value::Null).take(right_width).collect(),
             right_hit: false,
+            right_matched: vec![false; right.len()],
+            right,
             predicate,
             outer,
+            full,
         }
     }
 
@@ -93,7 +109,7 @@ impl NestedLoopRows {
 
             // Otherwise, continue with the next left row and reset the right source.
             self.left_row = self.left.next();
-            self.right = Box::new(self.right_vec.clone().into_iter());
+            self.right_index = 0;
 
             // If this is an outer join, when we reach the end of the right items without a hit,
             // we should return a row with nulls for the right fields.
@@ -104,19 +120,38 @@ impl NestedLoopRows {
             }
             self.right_hit = false;
         }
-        self.left_row.clone().transpose()
+        if let Some(Err(err)) = self.left_row.take() {
+            return Err(err);
+        }
+
+        // Once the left source is exhausted, full outer joins return the unmatched right rows
+        // with nulls for the left fields.
+        if self.full {
+            while self.right_index < self.right.len() {
+                let index = self.right_index;
+                self.right_index += 1;
+                if !self.right_matched[index] {
+                    let mut row = self.left_empty.clone();
+                    row.extend(self.right[index].iter().cloned());
+                    return Ok(Some(row));
+                }
+            }
+        }
+        Ok(None)
     }
 
     /// Tries to find the next combined row that matches the predicate in the remaining right rows.
     fn try_next_hit(&mut self, left_row: &[Value]) -> Result<Option<Row>> {
-        while let Some(right_row) = self.right.next() {
+        while self.right_index < self.right.len() {
+            let index = self.right_index;
+            self.right_index += 1;
             let mut row = left_row.to_vec();
-            row.extend(right_row);
+            row.extend(self.right[index].iter().cloned());
             if let Some(predicate) = &self.predicate {
                 match predicate.evaluate(Some(&row))? {
-                    Value::Boolean(true) => return Ok(Some(row)),
-                    Value::Boolean(false) => {}
-                    Value::Null => {}
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) => continue,
+                    Value::Null => continue,
                     value => {
                         return Err(Error::Value(format!(
                             "Join predicate returned {}, expected boolean",
@@ -124,9 +159,9 @@ impl NestedLoopRows {
                         )))
                     }
                 }
-            } else {
-                return Ok(Some(row));
             }
+            self.right_matched[index] = true;
+            return Ok(Some(row));
         }
         Ok(None)
     }
@@ -140,24 +175,97 @@ impl Iterator for NestedLoopRows {
     }
 }
 
-/// A hash join executor
+/// A semi-join executor, which returns the left rows that have a matching right row for the
+/// given predicate, or that have no match for anti-joins. Only the left columns are returned.
+/// Each left row is checked against every right row, as in a nested loop join.
+pub struct SemiJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+    predicate: Option<Expression>,
+    anti: bool,
+}
+
+impl<T: Transaction> SemiJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        right: Box<dyn Executor<T>>,
+        predicate: Option<Expression>,
+        anti: bool,
+    ) -> Box<Self> {
+        Box::new(Self { left, right, predicate, anti })
+    }
+}
+
+impl<T: Transaction> Executor<T> for SemiJoin<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if let ResultSet::Query { columns, rows } = self.left.execute(txn)? {
+            if let ResultSet::Query { rows: rrows, .. } = self.right.execute(txn)? {
+                let right = rrows.collect::<Result<Vec<_>>>()?;
+                let (predicate, anti) = (self.predicate, self.anti);
+                let rows = rows.filter_map(move |res| {
+                    let row = match res {
+                        Ok(row) => row,
+                        Err(err) => return Some(Err(err)),
+                    };
+                    let mut hit = false;
+                    for right_row in &right {
+                        let mut joined = row.clone();
+                        joined.extend(right_row.iter().cloned());
+                        match predicate.as_ref().map(|p| p.evaluate(Some(&joined))).transpose() {
+                            Ok(None) | Ok(Some(Value::Boolean(true))) => {
+                                hit = true;
+                                break;
+                            }
+                            Ok(Some(Value::Boolean(false))) | Ok(Some(Value::Null)) => {}
+                            Ok(Some(value)) => {
+                                return Some(Err(Error::Value(format!(
+                                    "Join predicate returned {}, expected boolean",
+                                    value
+                                ))))
+                            }
+                            Err(err) => return Some(Err(err)),
+                        }
+                    }
+                    if hit != anti {
+                        Some(Ok(row))
+                    } else {
+                        None
+                    }
+                });
+                return Ok(ResultSet::Query { columns, rows: Rows::new(rows) });
+            }
+        }
+        Err(Error::Internal("Unexpected result set".into()))
+    }
+}
+
+/// A hash join executor, which builds a hash table of the rows in one source keyed by the join
+/// fields, and probes it with the rows of the other source. Rows match if all of their join fields
+/// are equal. Inner joins build the table from the smaller source, while outer joins always build
+/// it from the right source since every left row must be emitted. Full outer joins additionally
+/// emit the right rows whose key did not match any left row. Buffered rows are charged to the
+/// statement's memory tracker until the joined rows have been emitted.
 pub struct HashJoin<T: Transaction> {
     left: Box<dyn Executor<T>>,
-    left_field: usize,
+    left_fields: Vec<usize>,
     right: Box<dyn Executor<T>>,
-    right_field: usize,
+    right_fields: Vec<usize>,
     outer: bool,
+    full: bool,
+    memory: MemoryTracker,
 }
 
 impl<T: Transaction> HashJoin<T> {
     pub fn new(
         left: Box<dyn Executor<T>>,
-        left_field: usize,
+        left_fields: Vec<usize>,
         right: Box<dyn Executor<T>>,
-        right_field: usize,
+        right_fields: Vec<usize>,
         outer: bool,
+        full: bool,
+        memory: MemoryTracker,
     ) -> Box<Self> {
-        Box::new(Self { left, left_field, right, right_field, outer })
+        Box::new(Self { left, left_fields, right, right_fields, outer, full, memory })
     }
 }
 
@@ -165,38 +273,499 @@ impl<T: Transaction> Executor<T> for HashJoin<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         if let ResultSet::Query { mut columns, rows } = self.left.execute(txn)? {
             if let ResultSet::Query { columns: rcolumns, rows: rrows } = self.right.execute(txn)? {
-                let (l, r, outer) = (self.left_field, self.right_field, self.outer);
-                let right: HashMap<Value, Row> = rrows
-                    .map(|res| match res {
-                        Ok(row) if row.len() <= r => {
-                            Err(Error::Internal(format!("Right index {} out of bounds", r)))
-                        }
-                        Ok(row) => Ok((row[r].clone(), row)),
-                        Err(err) => Err(err),
-                    })
-                    .collect::<Result<_>>()?;
-                let empty = std::iter::repeat(Value::Null).take(rcolumns.len());
+                let (l, r) = (self.left_fields, self.right_fields);
+                let mut memory = self.memory.reserve();
+                let left_empty: Row = std::iter::repeat(Value::Null).take(columns.len()).collect();
+                let empty: Row = std::iter::repeat(Value::Null).take(rcolumns.len()).collect();
                 columns.extend(rcolumns);
-                let rows = Box::new(rows.filter_map(move |res| match res {
-                    Ok(row) if row.len() <= l => {
-                        Some(Err(Error::Value(format!("Left index {} out of bounds", l))))
+                if self.full {
+                    // The left rows must be read up front to find the matched right rows, which
+                    // are those with a key that is also present in the left source.
+                    let left = HashTable::buffer(rows, &mut memory)?;
+                    let mut keys = HashSet::new();
+                    for row in &left {
+                        if let Some(key) = HashTable::key(row, &l)? {
+                            keys.insert(key);
+                        }
                     }
-                    Ok(mut row) => match right.get(&row[l]) {
-                        Some(hit) => {
-                            row.extend(hit.clone());
-                            Some(Ok(row))
+                    let (mut matched, mut unmatched) = (Vec::new(), Vec::new());
+                    for row in rrows {
+                        let row = row?;
+                        memory.grow_value(&row)?;
+                        match HashTable::key(&row, &r)? {
+                            Some(key) if keys.contains(&key) => matched.push(row),
+                            _ => unmatched.push(row),
+                        }
+                    }
+                    let right = HashTable::build(matched, r)?;
+                    let unmatched = unmatched.into_iter().map(move |row| {
+                        let mut joined = left_empty.clone();
+                        joined.extend(row);
+                        Ok(joined)
+                    });
+                    return Ok(ResultSet::Query {
+                        columns,
+                        rows: memory.hold(Rows::new(
+                            right
+                                .probe(Rows::new(left.into_iter().map(Ok)), l, Some(empty), false)
+                                .chain(unmatched),
+                        )),
+                    });
+                }
+                if self.outer {
+                    let right = HashTable::build(HashTable::buffer(rrows, &mut memory)?, r)?;
+                    return Ok(ResultSet::Query {
+                        columns,
+                        rows: memory.hold(Rows::new(right.probe(rows, l, Some(empty), false))),
+                    });
+                }
+
+                // Since we don't know the size of the sources up front, read rows from both in
+                // lockstep until one of them is exhausted, and build the table from that one.
+                // The buffered rows of the other source are probed first.
+                let (mut left, mut right) = (rows, rrows);
+                let (mut left_buffer, mut right_buffer) = (Vec::new(), Vec::new());
+                let rows = loop {
+                    match left.next().transpose()? {
+                        Some(row) => {
+                            memory.grow_value(&row)?;
+                            left_buffer.push(row)
+                        }
+                        None => {
+                            let table = HashTable::build(left_buffer, l)?;
+                            let probe = Rows::new(right_buffer.into_iter().map(Ok).chain(right));
+                            break table.probe(probe, r, None, true);
+                        }
+                    }
+                    match right.next().transpose()? {
+                        Some(row) => {
+                            memory.grow_value(&row)?;
+                            right_buffer.push(row)
+                        }
+                        None => {
+                            let table = HashTable::build(right_buffer, r)?;
+                            let probe = Rows::new(left_buffer.into_iter().map(Ok).chain(left));
+                            break table.probe(probe, l, None, false);
+                        }
+                    }
+                };
+                return Ok(ResultSet::Query { columns, rows: memory.hold(Rows::new(rows)) });
+            }
+        }
+        Err(Error::Internal("Unexpected result set".into()))
+    }
+}
+
+/// A hash semi-join executor, which builds a set of the join keys of the right rows, and emits the
+/// left rows whose key is in it, or not in it for anti-joins. Only the left columns are returned.
+/// As for a nested-loop semi-join with an equality predicate, NULL keys never match, so anti-joins
+/// emit left rows with NULL keys. Null-aware anti-joins instead consider NULL keys to match any
+/// row, as for NOT IN: if the right source is non-empty, left rows with NULL keys are never
+/// emitted, and no left rows are emitted at all if the right source contains a NULL key. The keys
+/// are charged to the statement's memory tracker.
+pub struct HashSemiJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    left_fields: Vec<usize>,
+    right: Box<dyn Executor<T>>,
+    right_fields: Vec<usize>,
+    anti: bool,
+    null_aware: bool,
+    memory: MemoryTracker,
+}
+
+impl<T: Transaction> HashSemiJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        left_fields: Vec<usize>,
+        right: Box<dyn Executor<T>>,
+        right_fields: Vec<usize>,
+        anti: bool,
+        null_aware: bool,
+        memory: MemoryTracker,
+    ) -> Box<Self> {
+        Box::new(Self { left, left_fields, right, right_fields, anti, null_aware, memory })
+    }
+}
+
+impl<T: Transaction> Executor<T> for HashSemiJoin<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if let ResultSet::Query { columns, rows } = self.left.execute(txn)? {
+            if let ResultSet::Query { rows: rrows, .. } = self.right.execute(txn)? {
+                let (mut keys, mut empty, mut null) = (HashSet::new(), true, false);
+                let mut memory = self.memory.reserve();
+                for row in rrows {
+                    empty = false;
+                    match HashTable::key(&row?, &self.right_fields)? {
+                        Some(key) => {
+                            memory.grow_value(&key)?;
+                            keys.insert(key);
                         }
-                        None if outer => {
-                            row.extend(empty.clone());
+                        None => null = true,
+                    }
+                }
+                let (fields, anti) = (self.left_fields, self.anti);
+                let null_aware = self.null_aware && anti && !empty;
+                let rows = rows.filter_map(move |res| {
+                    let row = match res {
+                        Ok(row) => row,
+                        Err(err) => return Some(Err(err)),
+                    };
+                    match HashTable::key(&row, &fields) {
+                        Ok(_) if null_aware && null => None,
+                        Ok(None) if null_aware => None,
+                        Ok(key) if matches!(&key, Some(k) if keys.contains(k)) != anti => {
                             Some(Ok(row))
                         }
-                        None => None,
-                    },
-                    Err(err) => Some(Err(err)),
-                }));
-                return Ok(ResultSet::Query { columns, rows });
+                        Ok(_) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                });
+                return Ok(ResultSet::Query { columns, rows: memory.hold(Rows::new(rows)) });
             }
         }
         Err(Error::Internal("Unexpected result set".into()))
     }
 }
+
+/// A merge join executor, for left and right sources that are both sorted in ascending order by
+/// their join fields. The sources are read in lockstep, buffering only the right rows with the
+/// current left key, such that left rows with duplicate keys are joined with all of them. Rows with
+/// NULL or NaN keys never match. The joined rows are emitted in left row order.
+pub struct MergeJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    left_fields: Vec<usize>,
+    right: Box<dyn Executor<T>>,
+    right_fields: Vec<usize>,
+    outer: bool,
+    full: bool,
+}
+
+impl<T: Transaction> MergeJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        left_fields: Vec<usize>,
+        right: Box<dyn Executor<T>>,
+        right_fields: Vec<usize>,
+        outer: bool,
+        full: bool,
+    ) -> Box<Self> {
+        Box::new(Self { left, left_fields, right, right_fields, outer, full })
+    }
+}
+
+impl<T: Transaction> Executor<T> for MergeJoin<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if let ResultSet::Query { mut columns, rows } = self.left.execute(txn)? {
+            if let ResultSet::Query { columns: rcolumns, rows: rrows } = self.right.execute(txn)? {
+                let left_empty = vec![Value::Null; columns.len()];
+                let right_empty = vec![Value::Null; rcolumns.len()];
+                columns.extend(rcolumns);
+                return Ok(ResultSet::Query {
+                    columns,
+                    rows: Rows::new(MergeJoinRows {
+                        left: rows,
+                        left_fields: self.left_fields,
+                        left_key: None,
+                        left_empty,
+                        right: rrows,
+                        right_fields: self.right_fields,
+                        right_next: None,
+                        right_key: None,
+                        right_empty,
+                        group: Vec::new(),
+                        group_key: None,
+                        group_matched: false,
+                        pending: VecDeque::new(),
+                        outer: self.outer,
+                        full: self.full,
+                    }),
+                });
+            }
+        }
+        Err(Error::Internal("Unexpected result set".into()))
+    }
+}
+
+/// A row and its join key, if any.
+type KeyedRow = (Row, Option<Vec<Value>>);
+
+struct MergeJoinRows {
+    left: Rows,
+    left_fields: Vec<usize>,
+    /// The key of the last left row, to check that the left rows are sorted
+    left_key: Option<Vec<Value>>,
+    left_empty: Row,
+    right: Rows,
+    right_fields: Vec<usize>,
+    /// The next right row and its key, if it has been read but not yet consumed
+    right_next: Option<KeyedRow>,
+    /// The key of the last consumed right row, to check that the right rows are sorted
+    right_key: Option<Vec<Value>>,
+    right_empty: Row,
+    /// The right rows with the current group key, and whether any left row matched them
+    group: Vec<Row>,
+    group_key: Option<Vec<Value>>,
+    group_matched: bool,
+    /// Joined rows that are ready to be emitted
+    pending: VecDeque<Row>,
+    outer: bool,
+    full: bool,
+}
+
+impl MergeJoinRows {
+    /// Returns the join key of a row, or None if any of the key values are NULL or NaN since the
+    /// row can't match any other row.
+    fn key(row: &[Value], fields: &[usize]) -> Result<Option<Vec<Value>>> {
+        let mut key = Vec::with_capacity(fields.len());
+        for field in fields {
+            match row.get(*field) {
+                Some(Value::Null) => return Ok(None),
+                Some(Value::Float(f)) if f.is_nan() => return Ok(None),
+                Some(value) => key.push(value.clone()),
+                None => return Err(Error::Internal(format!("Join field {} out of bounds", field))),
+            }
+        }
+        Ok(Some(key))
+    }
+
+    /// Compares two join keys, erroring if their values can't be compared.
+    fn compare(a: &[Value], b: &[Value]) -> Result<Ordering> {
+        for (a, b) in a.iter().zip(b) {
+            match a.partial_cmp(b) {
+                Some(Ordering::Equal) => {}
+                Some(ordering) => return Ok(ordering),
+                None => return Err(Error::Value(format!("Can't compare {} and {}", a, b))),
+            }
+        }
+        Ok(Ordering::Equal)
+    }
+
+    /// Checks that a key is not less than the previous key of the same source, and records it.
+    fn check_sorted(previous: &mut Option<Vec<Value>>, key: &[Value]) -> Result<()> {
+        if let Some(previous) = previous {
+            if Self::compare(previous, key)? == Ordering::Greater {
+                return Err(Error::Internal("Merge join source is not sorted".into()));
+            }
+        }
+        *previous = Some(key.to_vec());
+        Ok(())
+    }
+
+    /// Peeks at the next right row and its key, if any.
+    fn peek_right(&mut self) -> Result<Option<&KeyedRow>> {
+        if self.right_next.is_none() {
+            if let Some(row) = self.right.next().transpose()? {
+                let key = Self::key(&row, &self.right_fields)?;
+                self.right_next = Some((row, key));
+            }
+        }
+        Ok(self.right_next.as_ref())
+    }
+
+    /// Takes the next right row and its key, if any.
+    fn take_right(&mut self) -> Result<Option<KeyedRow>> {
+        self.peek_right()?;
+        if let Some((_, Some(key))) = &self.right_next {
+            Self::check_sorted(&mut self.right_key, key)?;
+        }
+        Ok(self.right_next.take())
+    }
+
+    /// Joins a right row with NULLs for the left fields, for unmatched rows in full outer joins.
+    fn unmatched_right(&self, row: Row) -> Row {
+        let mut joined = self.left_empty.clone();
+        joined.extend(row);
+        joined
+    }
+
+    /// Clears the current group of right rows. For full outer joins, unmatched rows are emitted.
+    fn clear_group(&mut self) {
+        let group = std::mem::take(&mut self.group);
+        if self.full && !self.group_matched {
+            let unmatched: Vec<Row> = group.into_iter().map(|r| self.unmatched_right(r)).collect();
+            self.pending.extend(unmatched);
+        }
+        self.group_key = None;
+        self.group_matched = false;
+    }
+
+    /// Advances the right source to the group of rows with the given key, skipping past rows with
+    /// smaller keys, which are emitted for full outer joins.
+    fn advance_right(&mut self, key: &[Value]) -> Result<()> {
+        if let Some(group_key) = &self.group_key {
+            if Self::compare(group_key, key)? == Ordering::Equal {
+                return Ok(());
+            }
+        }
+        self.clear_group();
+        loop {
+            let ordering = match self.peek_right()? {
+                Some((_, Some(right_key))) => Self::compare(right_key, key)?,
+                Some((_, None)) => Ordering::Less,
+                None => break,
+            };
+            if ordering == Ordering::Greater {
+                break;
+            }
+            if let Some((row, right_key)) = self.take_right()? {
+                if ordering == Ordering::Equal {
+                    self.group.push(row);
+                    self.group_key = right_key;
+                } else if self.full {
+                    let row = self.unmatched_right(row);
+                    self.pending.push_back(row);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Tries to get the next joined row, with error handling.
+    fn try_next(&mut self) -> Result<Option<Row>> {
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Ok(Some(row));
+            }
+            let left_row = match self.left.next().transpose()? {
+                Some(row) => row,
+                None => break,
+            };
+            if let Some(key) = Self::key(&left_row, &self.left_fields)? {
+                Self::check_sorted(&mut self.left_key, &key)?;
+                self.advance_right(&key)?;
+                if !self.group.is_empty() {
+                    self.group_matched = true;
+                    for right_row in &self.group {
+                        let mut joined = left_row.clone();
+                        joined.extend(right_row.iter().cloned());
+                        self.pending.push_back(joined);
+                    }
+                    continue;
+                }
+            }
+            if self.outer {
+                let mut joined = left_row;
+                joined.extend(self.right_empty.iter().cloned());
+                self.pending.push_back(joined);
+            }
+        }
+
+        // Once the left source is exhausted, full outer joins emit the remaining right rows.
+        if self.full {
+            self.clear_group();
+            if let Some(row) = self.pending.pop_front() {
+                return Ok(Some(row));
+            }
+            if let Some((row, _)) = self.take_right()? {
+                return Ok(Some(self.unmatched_right(row)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for MergeJoinRows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+/// A hash table of rows keyed by the values of the join fields, used by the hash join executor.
+struct HashTable(HashMap<Vec<Value>, Vec<Row>>);
+
+impl HashTable {
+    /// Buffers the given rows, charging them to the memory reservation.
+    fn buffer(rows: Rows, memory: &mut MemoryReservation) -> Result<Vec<Row>> {
+        rows.map(|row| {
+            let row = row?;
+            memory.grow_value(&row)?;
+            Ok(row)
+        })
+        .collect()
+    }
+
+    /// Builds a hash table from the given rows. NULL = NULL is not true, so rows with NULL keys
+    /// never match and are omitted.
+    fn build(rows: Vec<Row>, fields: Vec<usize>) -> Result<Self> {
+        let mut table: HashMap<Vec<Value>, Vec<Row>> = HashMap::new();
+        for row in rows {
+            if let Some(key) = Self::key(&row, &fields)? {
+                table.entry(key).or_default().push(row);
+            }
+        }
+        Ok(Self(table))
+    }
+
+    /// Returns the join key of a row, i.e. the normalized values of the join fields, or None if
+    /// any of them are NULL since the row can't match any other row.
+    fn key(row: &[Value], fields: &[usize]) -> Result<Option<Vec<Value>>> {
+        let mut key = Vec::with_capacity(fields.len());
+        for field in fields {
+            match row.get(*field).map(Self::normalize) {
+                Some(Value::Null) => return Ok(None),
+                Some(value) => key.push(value),
+                None => return Err(Error::Internal(format!("Join field {} out of bounds", field))),
+            }
+        }
+        Ok(Some(key))
+    }
+
+    /// Normalizes a join key value, such that numeric values which compare as equal also hash
+    /// equally, by converting integral floats and decimals to integers.
+    fn normalize(value: &Value) -> Value {
+        match value {
+            Value::Float(f) => {
+                types::float_to_integer(*f).map(Value::Integer).unwrap_or(value.clone())
+            }
+            Value::Decimal(d) => {
+                let p = 10_i128.pow(d.scale() as u32);
+                match i64::try_from(d.mantissa() / p) {
+                    Ok(i) if d.mantissa() % p == 0 => Value::Integer(i),
+                    _ => value.clone(),
+                }
+            }
+            value => value.clone(),
+        }
+    }
+
+    /// Probes the hash table with the given rows, joining each row with all matching rows in the
+    /// table. Rows without a match are joined with the given empty row if any (for outer joins),
+    /// otherwise they are omitted. If swap is true, the matching table rows are placed first.
+    fn probe(
+        self,
+        rows: Rows,
+        fields: Vec<usize>,
+        empty: Option<Row>,
+        swap: bool,
+    ) -> impl Iterator<Item = Result<Row>> + Send {
+        rows.flat_map(move |res| {
+            let row = match res {
+                Ok(row) => row,
+                Err(err) => return vec![Err(err)],
+            };
+            let hits = match Self::key(&row, &fields) {
+                Ok(key) => key.and_then(|key| self.0.get(&key)),
+                Err(err) => return vec![Err(err)],
+            };
+            match (hits, &empty) {
+                (Some(hits), _) => hits
+                    .iter()
+                    .map(|hit| {
+                        let (mut joined, other) =
+                            if swap { (hit.clone(), &row) } else { (row.clone(), hit) };
+                        joined.extend(other.iter().cloned());
+                        Ok(joined)
+                    })
+                    .collect(),
+                (None, Some(empty)) => {
+                    let mut joined = row;
+                    joined.extend(empty.iter().cloned());
+                    vec![Ok(joined)]
+                }
+                (None, None) => vec![],
+            }
+        })
+    }
+}
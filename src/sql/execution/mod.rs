@@ -1,24 +1,41 @@
 mod aggregation;
+mod copy;
 mod join;
 mod mutation;
 mod query;
 mod schema;
+mod sort;
 mod source;
+mod window;
 
 use aggregation::Aggregation;
-use join::{HashJoin, NestedLoopJoin};
-use mutation::{Delete, Insert, Update};
-use query::{Filter, Limit, Offset, Order, Projection};
-use schema::{CreateTable, DropTable};
-use source::{IndexLookup, KeyLookup, Nothing, Scan};
+use copy::{CopyFrom, CopyTo};
+use join::{HashJoin, HashSemiJoin, MergeJoin, NestedLoopJoin, SemiJoin};
+use mutation::{Delete, Insert, Truncate, Update};
+use query::{Cte, Distinct, Filter, Limit, Offset, Projection, RecursiveUnion, SetOperation};
+use schema::{
+    AddColumn, Analyze, CreateTable, CreateUser, CreateView, DropColumn, DropTable, DropUser,
+    DropView, Grant, RenameColumn, RenameTable,
+};
+use sort::Order;
+use source::{
+    IndexLookup, IndexOrderScan, IndexPrefixScan, IndexRangeScan, KeyLookup, KeyRangeScan, Nothing,
+    Scan, SystemScan, Values, WorkTable,
+};
+use window::Window;
 
 use super::engine::{Mode, Transaction};
 use super::plan::Node;
-use super::types::{Columns, Row, Rows, Value};
+use super::types::{Batch, Columns, Row, Rows, Value, BATCH_SIZE};
 use crate::error::{Error, Result};
 
 use derivative::Derivative;
 use serde_derive::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// A plan executor
 pub trait Executor<T: Transaction> {
@@ -27,46 +44,460 @@ pub trait Executor<T: Transaction> {
 }
 
 impl<T: Transaction + 'static> dyn Executor<T> {
-    /// Builds an executor for a plan node, consuming it
-    pub fn build(node: Node) -> Box<dyn Executor<T>> {
-        match node {
-            Node::Aggregation { source, aggregates } => {
-                Aggregation::new(Self::build(*source), aggregates)
-            }
-            Node::CreateTable { schema } => CreateTable::new(schema),
-            Node::Delete { table, source } => Delete::new(table, Self::build(*source)),
-            Node::DropTable { table } => DropTable::new(table),
-            Node::Filter { source, predicate } => Filter::new(Self::build(*source), predicate),
-            Node::HashJoin { left, left_field, right, right_field, outer } => HashJoin::new(
-                Self::build(*left),
-                left_field.0,
-                Self::build(*right),
-                right_field.0,
-                outer,
+    /// Builds an executor for a plan node, consuming it. Execution is subject to the given limits.
+    pub fn build(node: Node, limits: Limits) -> Box<dyn Executor<T>> {
+        Self::build_node(node, limits, None, &Cell::new(0))
+    }
+
+    /// Builds an executor like build(), instrumenting each node to record its execution
+    /// statistics in the given stats.
+    pub fn build_analyzed(node: Node, limits: Limits, stats: &Stats) -> Box<dyn Executor<T>> {
+        Self::build_node(node, limits, Some(stats), &Cell::new(0))
+    }
+
+    /// Builds an executor for a plan node, where index is the node's pre-order index in the plan.
+    fn build_node(
+        node: Node,
+        limits: Limits,
+        stats: Option<&Stats>,
+        index: &Cell<usize>,
+    ) -> Box<dyn Executor<T>> {
+        let node_index = index.replace(index.get() + 1);
+        let build = |node: Node| Self::build_node(node, limits.clone(), stats, index);
+        let executor: Box<dyn Executor<T>> = match node {
+            Node::AddColumn { table, column } => AddColumn::new(table, column),
+            Node::Aggregation { source, aggregates } => Aggregation::new(
+                build(*source),
+                aggregates,
+                limits.max_aggregate_values,
+                limits.memory.clone(),
+            ),
+            Node::Analyze { tables } => Analyze::new(tables),
+            Node::CopyFrom { table, path, header, delimiter } => {
+                CopyFrom::new(table, path, header, delimiter)
+            }
+            Node::CopyTo { table, path, header, delimiter } => {
+                CopyTo::new(table, path, header, delimiter)
+            }
+            Node::CreateTable { schema, if_not_exists } => CreateTable::new(schema, if_not_exists),
+            Node::CreateUser { user } => CreateUser::new(user),
+            Node::CreateView { view } => CreateView::new(view),
+            Node::Cte { source, name: _, alias: _, columns } => Cte::new(build(*source), columns),
+            Node::Delete { table, source, returning } => {
+                Delete::new(table, build(*source), returning)
+            }
+            Node::Distinct { source, on } => Distinct::new(
+                build(*source),
+                on,
+                limits.max_aggregate_values,
+                limits.memory.clone(),
             ),
-            Node::IndexLookup { table, alias: _, column, values } => {
-                IndexLookup::new(table, column, values)
+            Node::DropColumn { table, column } => DropColumn::new(table, column),
+            Node::DropTable { table, if_exists } => DropTable::new(table, if_exists),
+            Node::DropUser { user, if_exists } => DropUser::new(user, if_exists),
+            Node::DropView { view, if_exists } => DropView::new(view, if_exists),
+            Node::Filter { source, predicate } => Filter::new(build(*source), predicate),
+            Node::Grant { privileges, table, user } => Grant::new(privileges, table, user, false),
+            Node::HashJoin { left, left_fields, right, right_fields, outer, full } => {
+                HashJoin::new(
+                    build(*left),
+                    left_fields.into_iter().map(|(i, _)| i).collect(),
+                    build(*right),
+                    right_fields.into_iter().map(|(i, _)| i).collect(),
+                    outer,
+                    full,
+                    limits.memory.clone(),
+                )
+            }
+            Node::HashSemiJoin { left, left_fields, right, right_fields, anti, null_aware } => {
+                HashSemiJoin::new(
+                    build(*left),
+                    left_fields.into_iter().map(|(i, _)| i).collect(),
+                    build(*right),
+                    right_fields.into_iter().map(|(i, _)| i).collect(),
+                    anti,
+                    null_aware,
+                    limits.memory.clone(),
+                )
+            }
+            Node::IndexLookup { table, alias: _, column, values, columns, index_only } => {
+                IndexLookup::new(table, column, values, columns, index_only)
+            }
+            Node::IndexOrderScan {
+                table,
+                alias: _,
+                column,
+                filter,
+                reverse,
+                limit,
+                columns,
+                index_only,
+            } => IndexOrderScan::new(table, column, filter, reverse, limit, columns, index_only),
+            Node::IndexPrefixScan { table, alias: _, column, prefix, columns, index_only } => {
+                IndexPrefixScan::new(table, column, prefix, columns, index_only)
+            }
+            Node::IndexRangeScan { table, alias: _, column, range, columns, index_only } => {
+                IndexRangeScan::new(table, column, range, columns, index_only)
+            }
+            Node::Insert { table, columns, source, on_conflict, returning } => {
+                Insert::new(table, columns, build(*source), on_conflict, returning)
+            }
+            Node::KeyLookup { table, alias: _, keys, columns } => {
+                KeyLookup::new(table, keys, columns)
             }
-            Node::Insert { table, columns, expressions } => {
-                Insert::new(table, columns, expressions)
+            Node::KeyRangeScan { table, alias: _, range, columns } => {
+                KeyRangeScan::new(table, range, columns)
             }
-            Node::KeyLookup { table, alias: _, keys } => KeyLookup::new(table, keys),
-            Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
-            Node::NestedLoopJoin { left, left_size: _, right, predicate, outer } => {
-                NestedLoopJoin::new(Self::build(*left), Self::build(*right), predicate, outer)
+            Node::Limit { source, limit } => Limit::new(build(*source), limit),
+            Node::MergeJoin { left, left_fields, right, right_fields, outer, full } => {
+                MergeJoin::new(
+                    build(*left),
+                    left_fields.into_iter().map(|(i, _)| i).collect(),
+                    build(*right),
+                    right_fields.into_iter().map(|(i, _)| i).collect(),
+                    outer,
+                    full,
+                )
+            }
+            Node::NestedLoopJoin { left, left_size: _, right, predicate, outer, full } => {
+                NestedLoopJoin::new(build(*left), build(*right), predicate, outer, full)
             }
             Node::Nothing => Nothing::new(),
-            Node::Offset { source, offset } => Offset::new(Self::build(*source), offset),
-            Node::Order { source, orders } => Order::new(Self::build(*source), orders),
+            Node::Offset { source, offset } => Offset::new(build(*source), offset),
+            Node::Order { source, orders } => Order::new(
+                build(*source),
+                orders,
+                limits.max_sort_memory,
+                None,
+                limits.memory.clone(),
+            ),
+            // The source rows are already sorted, so they're emitted as-is.
+            Node::Presorted { source, orders: _ } => build(*source),
             Node::Projection { source, expressions } => {
-                Projection::new(Self::build(*source), expressions)
+                Projection::new(build(*source), expressions)
+            }
+            Node::RecursiveUnion { seed, recursive, name, id, all } => {
+                let seed = build(*seed);
+                // The recursive term is built anew for each iteration, and isn't instrumented.
+                let size = Cell::new(0);
+                recursive
+                    .clone()
+                    .transform(&|n| Ok(n), &|n| {
+                        size.set(size.get() + 1);
+                        Ok(n)
+                    })
+                    .ok();
+                index.set(index.get() + size.get());
+                RecursiveUnion::new(seed, *recursive, name, id, all, limits.clone())
+            }
+            Node::RenameColumn { table, column, new_name } => {
+                RenameColumn::new(table, column, new_name)
             }
-            Node::Scan { table, filter, alias: _ } => Scan::new(table, filter),
-            Node::Update { table, source, expressions } => Update::new(
+            Node::RenameTable { table, new_name } => RenameTable::new(table, new_name),
+            Node::Revoke { privileges, table, user } => Grant::new(privileges, table, user, true),
+            Node::Scan { table, filter, alias: _, columns } => {
+                Scan::new(table, filter, columns, limits.batch_size.unwrap_or(BATCH_SIZE))
+            }
+            Node::SemiJoin { left, left_size: _, right, predicate, anti } => {
+                SemiJoin::new(build(*left), build(*right), predicate, anti)
+            }
+            Node::Update { table, source, expressions, returning } => Update::new(
                 table,
-                Self::build(*source),
+                build(*source),
                 expressions.into_iter().map(|(i, _, e)| (i, e)).collect(),
+                returning,
+            ),
+            Node::SetOperation { operator, left, right, all } => {
+                SetOperation::new(operator, build(*left), build(*right), all)
+            }
+            Node::SystemScan { table, alias: _ } => SystemScan::new(table),
+            Node::TopK { source, orders, limit } => Order::new(
+                build(*source),
+                orders,
+                limits.max_sort_memory,
+                Some(limit),
+                limits.memory.clone(),
             ),
+            Node::Truncate { table } => Truncate::new(table),
+            Node::Values { rows } => Values::new(rows),
+            Node::View { source, name: _, alias: _, columns } => Cte::new(build(*source), columns),
+            Node::Window { source, functions } => Window::new(build(*source), functions),
+            Node::WorkTable { name, id: _ } => WorkTable::new(name),
+        };
+        Self::wrap(executor, limits, stats, node_index)
+    }
+
+    /// Wraps a node's executor to enforce the deadline and cancellation, and record statistics,
+    /// if any.
+    fn wrap(
+        executor: Box<dyn Executor<T>>,
+        limits: Limits,
+        stats: Option<&Stats>,
+        node_index: usize,
+    ) -> Box<dyn Executor<T>> {
+        let executor = match (limits.deadline, limits.cancel) {
+            (None, None) => executor,
+            (deadline, cancel) => Interrupt::new(executor, deadline, cancel),
+        };
+        match stats {
+            Some(stats) => Instrument::new(executor, stats.clone(), node_index),
+            None => executor,
+        }
+    }
+}
+
+/// Execution limits for a statement
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// The deadline for execution, after which it errors with Error::Timeout
+    pub deadline: Option<Instant>,
+    /// A cancellation token, which makes execution error with Error::Cancelled once cancelled
+    pub cancel: Option<CancelToken>,
+    /// The maximum number of values buffered by DISTINCT and ordered aggregates, across all groups
+    pub max_aggregate_values: Option<usize>,
+    /// The maximum number of iterations of each recursive CTE that emit rows
+    pub max_recursive_iterations: Option<u64>,
+    /// The approximate memory, in bytes, that each sort can buffer before spilling sorted runs to
+    /// temporary files
+    pub max_sort_memory: Option<usize>,
+    /// Tracks the memory buffered by the statement's executors, erroring past its limit
+    pub memory: MemoryTracker,
+    /// The number of rows per batch fetched by table scans, or BATCH_SIZE if None
+    pub batch_size: Option<usize>,
+}
+
+/// A statement cancellation token. It can be cloned and shared with other threads, which can cancel
+/// the statement by calling cancel().
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Creates a new cancellation token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the statement.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    /// Returns whether the statement has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Resets the token, such that it can be used for another statement.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst)
+    }
+}
+
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Tracks the approximate memory used by a statement's executors that buffer rows, i.e. hash joins,
+/// aggregations, sorts, and DISTINCT, erroring once the total exceeds the statement's limit, if
+/// any. Clones share the same total. Executors charge memory via reservations, which release their
+/// charge when dropped.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTracker {
+    used: Arc<AtomicUsize>,
+    limit: Option<usize>,
+}
+
+impl MemoryTracker {
+    /// Creates a new memory tracker with the given limit in bytes, if any.
+    pub fn new(limit: Option<usize>) -> Self {
+        Self { used: Arc::new(AtomicUsize::new(0)), limit }
+    }
+
+    /// Returns the number of bytes currently charged.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Creates a new, empty memory reservation charged to the tracker.
+    pub fn reserve(&self) -> MemoryReservation {
+        MemoryReservation { tracker: self.clone(), size: 0 }
+    }
+}
+
+impl PartialEq for MemoryTracker {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.used, &other.used) && self.limit == other.limit
+    }
+}
+
+/// Memory charged to a memory tracker by an executor, which is released when dropped.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    tracker: MemoryTracker,
+    size: usize,
+}
+
+impl MemoryReservation {
+    /// Charges the given number of bytes, erroring if the tracker exceeds its limit.
+    pub fn grow(&mut self, bytes: usize) -> Result<()> {
+        self.size += bytes;
+        let used = self.tracker.used.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        match self.tracker.limit {
+            Some(limit) if used > limit => {
+                Err(Error::Value("statement memory limit exceeded".into()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Charges the approximate size of a value, i.e. its encoded size.
+    pub fn grow_value<V: serde::Serialize>(&mut self, value: &V) -> Result<()> {
+        self.grow(bincode::serialized_size(value)? as usize)
+    }
+
+    /// Releases the given number of bytes.
+    pub fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.size);
+        self.size -= bytes;
+        self.tracker.used.fetch_sub(bytes, Ordering::SeqCst);
+    }
+
+    /// Returns the given rows, holding the reservation until they are exhausted or dropped.
+    pub fn hold(self, rows: Rows) -> Rows {
+        Rows::from_batches(HeldRows { rows, reservation: Some(self) })
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.shrink(self.size)
+    }
+}
+
+/// A row iterator holding a memory reservation, see MemoryReservation::hold().
+struct HeldRows {
+    rows: Rows,
+    reservation: Option<MemoryReservation>,
+}
+
+impl Iterator for HeldRows {
+    type Item = Result<Batch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.rows.next_batch();
+        if next.is_none() {
+            self.reservation = None;
+        }
+        next
+    }
+}
+
+/// Wraps an executor, erroring with Error::Timeout for any batch of rows emitted after the deadline,
+/// or with Error::Cancelled once the statement is cancelled. Since executors pull rows from their
+/// sources, this also interrupts executors that are processing their source rows, e.g. during a
+/// mutation, sort, or join.
+struct Interrupt<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    deadline: Option<Instant>,
+    cancel: Option<CancelToken>,
+}
+
+impl<T: Transaction> Interrupt<T> {
+    fn new(
+        source: Box<dyn Executor<T>>,
+        deadline: Option<Instant>,
+        cancel: Option<CancelToken>,
+    ) -> Box<Self> {
+        Box::new(Self { source, deadline, cancel })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Interrupt<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (deadline, cancel) = (self.deadline, self.cancel);
+        match self.source.execute(txn)? {
+            ResultSet::Query { columns, rows } => Ok(ResultSet::Query {
+                columns,
+                rows: rows.map_batches(move |batch| {
+                    if matches!(&cancel, Some(c) if c.is_cancelled()) {
+                        Err(Error::Cancelled)
+                    } else if matches!(deadline, Some(d) if Instant::now() >= d) {
+                        Err(Error::Timeout)
+                    } else {
+                        Ok(batch)
+                    }
+                }),
+            }),
+            result => Ok(result),
+        }
+    }
+}
+
+/// Execution statistics for plan nodes, keyed by the node's pre-order index in the plan. Nodes
+/// that weren't executed have no statistics.
+pub type Stats = Arc<Mutex<HashMap<usize, NodeStats>>>;
+
+/// Execution statistics for a plan node
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NodeStats {
+    /// The number of rows emitted, or affected for mutations
+    pub rows: u64,
+    /// The time spent executing the node, including its sources
+    pub time: Duration,
+}
+
+/// Wraps an executor, recording its execution statistics. Rows are counted and timed as their
+/// batches are fetched.
+struct Instrument<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    stats: Stats,
+    index: usize,
+}
+
+impl<T: Transaction> Instrument<T> {
+    fn new(source: Box<dyn Executor<T>>, stats: Stats, index: usize) -> Box<Self> {
+        Box::new(Self { source, stats, index })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Instrument<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (stats, index) = (self.stats, self.index);
+        let start = Instant::now();
+        let result = self.source.execute(txn)?;
+        let time = start.elapsed();
+        let rows = match &result {
+            ResultSet::Create { count }
+            | ResultSet::Copy { count }
+            | ResultSet::Delete { count }
+            | ResultSet::Update { count }
+            | ResultSet::Returning { count, .. } => *count,
+            _ => 0,
+        };
+        stats.lock()?.insert(index, NodeStats { rows, time });
+        match result {
+            ResultSet::Query { columns, mut rows } => Ok(ResultSet::Query {
+                columns,
+                rows: Rows::from_batches(std::iter::from_fn(move || {
+                    let start = Instant::now();
+                    let batch = rows.next_batch()?;
+                    let time = start.elapsed();
+                    match stats.lock() {
+                        Ok(mut stats) => {
+                            let node = stats.entry(index).or_default();
+                            node.time += time;
+                            if let Ok(batch) = &batch {
+                                node.rows += batch.len() as u64;
+                            }
+                            Some(batch)
+                        }
+                        Err(err) => Some(Err(err.into())),
+                    }
+                })),
+            }),
+            result => Ok(result),
         }
     }
 }
@@ -100,6 +531,10 @@ pub enum ResultSet {
     Update {
         count: u64,
     },
+    // Rows copied to a file
+    Copy {
+        count: u64,
+    },
     // Table created
     CreateTable {
         name: String,
@@ -108,6 +543,34 @@ pub enum ResultSet {
     DropTable {
         name: String,
     },
+    // Table altered
+    AlterTable {
+        name: String,
+    },
+    // View created
+    CreateView {
+        name: String,
+    },
+    // View dropped
+    DropView {
+        name: String,
+    },
+    // User created
+    CreateUser {
+        name: String,
+    },
+    // User dropped
+    DropUser {
+        name: String,
+    },
+    // Privileges granted to a user
+    Grant {
+        user: String,
+    },
+    // Privileges revoked from a user
+    Revoke {
+        user: String,
+    },
     // Query result
     Query {
         columns: Columns,
@@ -116,14 +579,32 @@ pub enum ResultSet {
         #[serde(skip, default = "ResultSet::empty_rows")]
         rows: Rows,
     },
-    // Explain result
-    Explain(Node),
+    // Rows mutated, with the RETURNING clause evaluated on the mutated rows
+    Returning {
+        count: u64,
+        columns: Columns,
+        rows: Vec<Row>,
+    },
+    // Session variable set
+    Set {
+        variable: String,
+        value: Value,
+    },
+    // Table statistics collected
+    Analyze {
+        tables: Vec<String>,
+    },
+    // Old record versions garbage collected
+    Vacuum {
+        versions: u64,
+        bytes: u64,
+    },
 }
 
 impl ResultSet {
     /// Creates an empty row iteratur, for use by serde(default).
     fn empty_rows() -> Rows {
-        Box::new(std::iter::empty())
+        Rows::empty()
     }
 
     /// Converts the ResultSet into a row, or errors if not a query result with rows.
@@ -1,24 +1,75 @@
 use super::super::engine::Transaction;
-use super::super::schema::Table;
+use super::super::plan::{Node, OnConflict};
+use super::super::schema::{Catalog, Table};
 use super::super::types::{Expression, Row, Value};
 use super::{Executor, ResultSet};
 use crate::error::{Error, Result};
 
 use std::collections::{HashMap, HashSet};
 
-/// An INSERT executor
-pub struct Insert {
+/// A RETURNING clause, which evaluates its expressions on the rows affected by a mutation. The
+/// rows are the table columns, followed by any other columns from the mutation's source (e.g.
+/// UPDATE FROM), and are conformed to the table schema first, such that they're returned as
+/// stored. Without expressions, the mutation just returns its count.
+struct Returning {
+    expressions: Vec<(Expression, Option<String>)>,
+    rows: Vec<Row>,
+}
+
+impl Returning {
+    fn new(expressions: Vec<(Expression, Option<String>)>) -> Self {
+        Self { expressions, rows: Vec::new() }
+    }
+
+    /// Evaluates the expressions on an affected row.
+    fn add(&mut self, table: &Table, row: &[Value]) -> Result<()> {
+        if self.expressions.is_empty() {
+            return Ok(());
+        }
+        let (columns, other) = row.split_at(table.columns.len().min(row.len()));
+        let mut row = table.conform_row(columns.to_vec())?;
+        row.extend_from_slice(other);
+        self.rows.push(
+            self.expressions.iter().map(|(e, _)| e.evaluate(Some(&row))).collect::<Result<_>>()?,
+        );
+        Ok(())
+    }
+
+    /// Returns the result set of the mutation, or the given result set if there are no
+    /// expressions.
+    fn result<C: Catalog>(self, count: u64, result: ResultSet, catalog: &C) -> Result<ResultSet> {
+        if self.expressions.is_empty() {
+            return Ok(result);
+        }
+        let columns = Node::returning_columns(&self.expressions, &[], catalog)?;
+        Ok(ResultSet::Returning { count, columns, rows: self.rows })
+    }
+}
+
+/// An INSERT executor, which inserts the rows of its source. Rows that conflict with an existing
+/// row are handled as given by on_conflict, if any, and otherwise result in an error. The count
+/// includes both inserted and updated rows.
+pub struct Insert<T: Transaction> {
     table: String,
     columns: Vec<String>,
-    rows: Vec<Vec<Expression>>,
+    source: Box<dyn Executor<T>>,
+    on_conflict: Option<OnConflict>,
+    returning: Returning,
 }
 
-impl Insert {
-    pub fn new(table: String, columns: Vec<String>, rows: Vec<Vec<Expression>>) -> Box<Self> {
-        Box::new(Self { table, columns, rows })
+impl<T: Transaction> Insert<T> {
+    pub fn new(
+        table: String,
+        columns: Vec<String>,
+        source: Box<dyn Executor<T>>,
+        on_conflict: Option<OnConflict>,
+        returning: Vec<(Expression, Option<String>)>,
+    ) -> Box<Self> {
+        Box::new(Self { table, columns, source, on_conflict, returning: Returning::new(returning) })
     }
 
     // Builds a row from a set of column names and values, padding it with default values.
+    // Generated columns can't be given, and are left as NULL to be computed by generate_row().
     pub fn make_row(table: &Table, columns: &[String], values: Vec<Value>) -> Result<Row> {
         if columns.len() != values.len() {
             return Err(Error::Value("Column and value counts do not match".into()));
@@ -32,7 +83,15 @@ impl Insert {
         }
         let mut row = Row::new();
         for column in table.columns.iter() {
-            if let Some(value) = inputs.get(&column.name) {
+            if column.generated.is_some() {
+                if inputs.contains_key(&column.name) {
+                    return Err(Error::Value(format!(
+                        "Can't write to generated column {}",
+                        column.name
+                    )));
+                }
+                row.push(Value::Null)
+            } else if let Some(value) = inputs.get(&column.name) {
                 row.push(value.clone())
             } else if let Some(value) = &column.default {
                 row.push(value.clone())
@@ -43,35 +102,109 @@ impl Insert {
         Ok(row)
     }
 
-    /// Pads a row with default values where possible.
+    /// Pads a row with default values where possible. Generated columns can't be given, and are
+    /// padded with NULL to be computed by generate_row().
     fn pad_row(table: &Table, mut row: Row) -> Result<Row> {
+        if let Some(column) = table.columns.iter().take(row.len()).find(|c| c.generated.is_some()) {
+            return Err(Error::Value(format!("Can't write to generated column {}", column.name)));
+        }
         for column in table.columns.iter().skip(row.len()) {
-            if let Some(default) = &column.default {
+            if column.generated.is_some() {
+                row.push(Value::Null)
+            } else if let Some(default) = &column.default {
                 row.push(default.clone())
             } else {
-                return Err(Error::Value(format!("No default value for column {}", column.name)));
+                return Err(Error::Value(format!("No value given for column {}", column.name)));
             }
         }
         Ok(row)
     }
+
+    /// Looks up an existing row with the same values as the given row in the given columns, i.e.
+    /// the primary key columns or a unique column. NULL values never conflict.
+    fn find_conflict(
+        txn: &T,
+        table: &Table,
+        columns: &[usize],
+        row: &[Value],
+    ) -> Result<Option<Row>> {
+        if columns == table.get_primary_key_indexes().as_slice() {
+            return txn.read(&table.name, &table.get_row_key(row)?);
+        }
+        let (index, column) = match columns {
+            [index] => (*index, &table.columns[*index]),
+            _ => return Err(Error::Internal("Invalid ON CONFLICT columns".into())),
+        };
+        let value = row.get(index).unwrap_or(&Value::Null);
+        if value == &Value::Null {
+            return Ok(None);
+        }
+        if column.index {
+            return match txn.read_index(&table.name, &column.name, value)?.into_iter().next() {
+                Some(id) => txn.read(&table.name, &id),
+                None => Ok(None),
+            };
+        }
+        let mut scan = txn.scan(&table.name, None)?;
+        while let Some(existing) = scan.next().transpose()? {
+            if existing.get(index) == Some(value) {
+                return Ok(Some(existing));
+            }
+        }
+        Ok(None)
+    }
 }
 
-impl<T: Transaction> Executor<T> for Insert {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+impl<T: Transaction> Executor<T> for Insert<T> {
+    fn execute(mut self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let table = txn.must_read_table(&self.table)?;
-        let mut count = 0;
-        for expressions in self.rows {
-            let mut row =
-                expressions.into_iter().map(|expr| expr.evaluate(None)).collect::<Result<_>>()?;
-            if self.columns.is_empty() {
-                row = Self::pad_row(&table, row)?;
-            } else {
-                row = Self::make_row(&table, &self.columns, row)?;
+        match self.source.execute(txn)? {
+            ResultSet::Query { rows, .. } => {
+                // The source may read the table we're inserting into, e.g. INSERT INTO t SELECT *
+                // FROM t, and could see our own inserts if fetched lazily. We therefore buffer the
+                // rows before inserting them.
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                let mut count = 0;
+                for mut row in rows {
+                    if self.columns.is_empty() {
+                        row = Self::pad_row(&table, row)?;
+                    } else {
+                        row = Self::make_row(&table, &self.columns, row)?;
+                    }
+                    table.generate_row(&mut row)?;
+                    // Rows inserted earlier by this statement are also considered existing rows.
+                    let existing = match &self.on_conflict {
+                        Some(on_conflict) => {
+                            row = table.conform_row(row)?;
+                            Self::find_conflict(txn, &table, &on_conflict.columns, &row)?
+                        }
+                        None => None,
+                    };
+                    match (existing, &self.on_conflict) {
+                        (None, _) => {
+                            self.returning.add(&table, &row)?;
+                            txn.create(&table.name, row)?
+                        }
+                        (Some(existing), Some(OnConflict { update: Some(expressions), .. })) => {
+                            let id = table.get_row_key(&existing)?;
+                            let mut new = existing.clone();
+                            let mut input = existing;
+                            input.extend(row);
+                            for (field, _, expr) in expressions {
+                                new[*field] = expr.evaluate(Some(&input))?;
+                            }
+                            table.generate_row(&mut new)?;
+                            self.returning.add(&table, &new)?;
+                            txn.update(&table.name, &id, new)?;
+                        }
+                        (Some(_), _) => continue,
+                    }
+                    count += 1;
+                }
+                self.returning.result(count, ResultSet::Create { count }, txn)
             }
-            txn.create(&table.name, row)?;
-            count += 1;
+            r => Err(Error::Internal(format!("Unexpected response {:?}", r))),
         }
-        Ok(ResultSet::Create { count })
     }
 }
 
@@ -80,6 +213,7 @@ pub struct Update<T: Transaction> {
     table: String,
     source: Box<dyn Executor<T>>,
     expressions: Vec<(usize, Expression)>,
+    returning: Returning,
 }
 
 impl<T: Transaction> Update<T> {
@@ -87,38 +221,45 @@ impl<T: Transaction> Update<T> {
         table: String,
         source: Box<dyn Executor<T>>,
         expressions: Vec<(usize, Expression)>,
+        returning: Vec<(Expression, Option<String>)>,
     ) -> Box<Self> {
-        Box::new(Self { table, source, expressions })
+        Box::new(Self { table, source, expressions, returning: Returning::new(returning) })
     }
 }
 
 impl<T: Transaction> Executor<T> for Update<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+    fn execute(mut self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         match self.source.execute(txn)? {
-            ResultSet::Query { mut rows, .. } => {
+            ResultSet::Query { rows, .. } => {
                 let table = txn.must_read_table(&self.table)?;
 
-                // The iterator will see our changes, such that the same item may be iterated over
-                // multiple times. We keep track of the primary keys here to avoid that, althought
-                // it may cause ballooning memory usage for large updates.
-                //
-                // FIXME This is not safe for primary key updates, which may still be processed
-                // multiple times - it should be possible to come up with a pathological case that
-                // loops forever (e.g. UPDATE test SET id = id + 1).
-                let mut updated = HashSet::new();
-                while let Some(row) = rows.next().transpose()? {
-                    let id = table.get_row_key(&row)?;
-                    if updated.contains(&id) {
-                        continue;
+                // Rows may be fetched lazily, in which case the iterator could see our changes and
+                // e.g. process primary key updates multiple times. We therefore buffer the rows
+                // before updating them.
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+
+                // With UPDATE FROM, source rows are target rows followed by the FROM columns. A
+                // target row matching several FROM rows would be ambiguous, so it's an error.
+                let mut seen = HashSet::new();
+                for row in &rows {
+                    let id = table.get_row_key(row)?;
+                    if !seen.insert(id.clone()) {
+                        return Err(Error::Value(format!(
+                            "Row {} in table {} matched multiple FROM rows",
+                            id, table.name
+                        )));
                     }
                     let mut new = row.clone();
                     for (field, expr) in &self.expressions {
-                        new[*field] = expr.evaluate(Some(&row))?;
+                        new[*field] = expr.evaluate(Some(row))?;
                     }
+                    table.generate_row(&mut new)?;
+                    self.returning.add(&table, &new)?;
+                    new.truncate(table.columns.len());
                     txn.update(&table.name, &id, new)?;
-                    updated.insert(id);
                 }
-                Ok(ResultSet::Update { count: updated.len() as u64 })
+                let count = rows.len() as u64;
+                self.returning.result(count, ResultSet::Update { count }, txn)
             }
             r => Err(Error::Internal(format!("Unexpected response {:?}", r))),
         }
@@ -129,27 +270,58 @@ impl<T: Transaction> Executor<T> for Update<T> {
 pub struct Delete<T: Transaction> {
     table: String,
     source: Box<dyn Executor<T>>,
+    returning: Returning,
 }
 
 impl<T: Transaction> Delete<T> {
-    pub fn new(table: String, source: Box<dyn Executor<T>>) -> Box<Self> {
-        Box::new(Self { table, source })
+    pub fn new(
+        table: String,
+        source: Box<dyn Executor<T>>,
+        returning: Vec<(Expression, Option<String>)>,
+    ) -> Box<Self> {
+        Box::new(Self { table, source, returning: Returning::new(returning) })
     }
 }
 
 impl<T: Transaction> Executor<T> for Delete<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+    fn execute(mut self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let table = txn.must_read_table(&self.table)?;
         let mut count = 0;
         match self.source.execute(txn)? {
             ResultSet::Query { mut rows, .. } => {
+                // With DELETE USING, a target row may match several USING rows, but is only
+                // deleted once.
+                let mut seen = HashSet::new();
                 while let Some(row) = rows.next().transpose()? {
-                    txn.delete(&table.name, &table.get_row_key(&row)?)?;
-                    count += 1
+                    let id = table.get_row_key(&row)?;
+                    if seen.insert(id.clone()) {
+                        self.returning.add(&table, &row)?;
+                        txn.delete(&table.name, &id)?;
+                        count += 1
+                    }
                 }
-                Ok(ResultSet::Delete { count })
+                self.returning.result(count, ResultSet::Delete { count }, txn)
             }
             r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
         }
     }
 }
+
+/// A TRUNCATE executor, which deletes all rows of a table via prefix deletes rather than
+/// row-by-row.
+pub struct Truncate {
+    table: String,
+}
+
+impl Truncate {
+    pub fn new(table: String) -> Box<Self> {
+        Box::new(Self { table })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Truncate {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let count = txn.truncate(&self.table)?;
+        Ok(ResultSet::Delete { count })
+    }
+}
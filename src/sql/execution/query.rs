@@ -1,9 +1,11 @@
 use super::super::engine::Transaction;
-use super::super::plan::Direction;
-use super::super::types::{Column, Expression, Row, Value};
-use super::{Executor, ResultSet};
+use super::super::plan::{Node, SetOperator};
+use super::super::types::{Column, DataType, Expression, Row, Rows, Value};
+use super::{Executor, Limits, MemoryTracker, ResultSet};
 use crate::error::{Error, Result};
 
+use std::collections::{HashMap, HashSet};
+
 /// A filter executor
 pub struct Filter<T: Transaction> {
     source: Box<dyn Executor<T>>,
@@ -22,15 +24,79 @@ impl<T: Transaction> Executor<T> for Filter<T> {
             let predicate = self.predicate;
             Ok(ResultSet::Query {
                 columns,
-                rows: Box::new(rows.filter_map(move |r| {
-                    r.and_then(|row| match predicate.evaluate(Some(&row))? {
-                        Value::Boolean(true) => Ok(Some(row)),
-                        Value::Boolean(false) => Ok(None),
-                        Value::Null => Ok(None),
-                        value => Err(Error::Value(format!(
-                            "Filter returned {}, expected boolean",
-                            value
-                        ))),
+                rows: rows.map_batches(move |batch| {
+                    let mut filtered = Vec::with_capacity(batch.len());
+                    for row in batch {
+                        match predicate.evaluate(Some(&row))? {
+                            Value::Boolean(true) => filtered.push(row),
+                            Value::Boolean(false) | Value::Null => {}
+                            value => {
+                                return Err(Error::Value(format!(
+                                    "Filter returned {}, expected boolean",
+                                    value
+                                )))
+                            }
+                        }
+                    }
+                    Ok(filtered)
+                }),
+            })
+        } else {
+            Err(Error::Internal("Unexpected result".into()))
+        }
+    }
+}
+
+/// A DISTINCT executor, which streams the first source row of each distinct key. The keys seen so
+/// far are kept in a hash set, whose buffered values are limited by max_aggregate_values and charged
+/// to the statement's memory tracker.
+pub struct Distinct<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    on: Vec<Expression>,
+    max_aggregate_values: Option<usize>,
+    memory: MemoryTracker,
+}
+
+impl<T: Transaction> Distinct<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        on: Vec<Expression>,
+        max_aggregate_values: Option<usize>,
+        memory: MemoryTracker,
+    ) -> Box<Self> {
+        Box::new(Self { source, on, max_aggregate_values, memory })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Distinct<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if let ResultSet::Query { columns, rows } = self.source.execute(txn)? {
+            let (on, max) = (self.on, self.max_aggregate_values);
+            let mut seen: HashSet<Row> = HashSet::new();
+            let mut values = 0;
+            let mut memory = self.memory.reserve();
+            Ok(ResultSet::Query {
+                columns,
+                rows: Rows::new(rows.filter_map(move |r| {
+                    r.and_then(|row| {
+                        let key: Row = if on.is_empty() {
+                            row.clone()
+                        } else {
+                            on.iter().map(|e| e.evaluate(Some(&row))).collect::<Result<_>>()?
+                        };
+                        if seen.contains(&key) {
+                            return Ok(None);
+                        }
+                        values += key.len();
+                        if let Some(max) = max.filter(|max| values > *max) {
+                            return Err(Error::Value(format!(
+                                "Distinct exceeded limit of {} buffered values",
+                                max
+                            )));
+                        }
+                        memory.grow_value(&key)?;
+                        seen.insert(key);
+                        Ok(Some(row))
                     })
                     .transpose()
                 })),
@@ -66,19 +132,20 @@ impl<T: Transaction> Executor<T> for Projection<T> {
                 .enumerate()
                 .map(|(i, e)| {
                     if let Some(Some(label)) = labels.get(i) {
-                        Column { name: Some(label.clone()) }
+                        Column { name: Some(label.clone()), datatype: None }
                     } else if let Expression::Field(i, _) = e {
-                        columns.get(*i).cloned().unwrap_or(Column { name: None })
+                        columns.get(*i).cloned().unwrap_or(Column { name: None, datatype: None })
                     } else {
-                        Column { name: None }
+                        Column { name: None, datatype: None }
                     }
                 })
                 .collect();
-            let rows = Box::new(rows.map(move |r| {
-                r.and_then(|row| {
-                    Ok(expressions.iter().map(|e| e.evaluate(Some(&row))).collect::<Result<_>>()?)
-                })
-            }));
+            let rows = rows.map_batches(move |batch| {
+                batch
+                    .iter()
+                    .map(|row| expressions.iter().map(|e| e.evaluate(Some(row))).collect())
+                    .collect()
+            });
             Ok(ResultSet::Query { columns, rows })
         } else {
             Err(Error::Internal("Unexpected result".into()))
@@ -86,65 +153,6 @@ impl<T: Transaction> Executor<T> for Projection<T> {
     }
 }
 
-/// An ORDER BY executor
-pub struct Order<T: Transaction> {
-    source: Box<dyn Executor<T>>,
-    order: Vec<(Expression, Direction)>,
-}
-
-impl<T: Transaction> Order<T> {
-    pub fn new(source: Box<dyn Executor<T>>, order: Vec<(Expression, Direction)>) -> Box<Self> {
-        Box::new(Self { source, order })
-    }
-}
-
-impl<T: Transaction> Executor<T> for Order<T> {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        match self.source.execute(txn)? {
-            ResultSet::Query { columns, mut rows } => {
-                // FIXME Since we can't return errors from the sort_by closure, we have to
-                // pre-evaluate all values. This means that we can't short-circuit evaluation,
-                // and have to temporarily store evaluated values, which is bad for performance
-                // and memory usage respectively
-                struct Item {
-                    row: Row,
-                    values: Vec<Value>,
-                };
-                let mut items = Vec::new();
-                while let Some(row) = rows.next().transpose()? {
-                    let mut values = Vec::new();
-                    for (expr, _) in self.order.iter() {
-                        values.push(expr.evaluate(Some(&row))?);
-                    }
-                    items.push(Item { row, values })
-                }
-
-                let order = &self.order;
-                items.sort_by(|a, b| {
-                    for (i, (_, order)) in order.iter().enumerate() {
-                        let value_a = &a.values[i];
-                        let value_b = &b.values[i];
-                        match value_a.partial_cmp(value_b) {
-                            Some(std::cmp::Ordering::Equal) => {}
-                            Some(o) => {
-                                return if *order == Direction::Ascending { o } else { o.reverse() }
-                            }
-                            None => {}
-                        }
-                    }
-                    std::cmp::Ordering::Equal
-                });
-
-                Ok(ResultSet::Query {
-                    columns,
-                    rows: Box::new(items.into_iter().map(|i| Ok(i.row))),
-                })
-            }
-            r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
-        }
-    }
-}
-
 /// A LIMIT executor
 pub struct Limit<T: Transaction> {
     source: Box<dyn Executor<T>>,
@@ -159,8 +167,20 @@ impl<T: Transaction> Limit<T> {
 
 impl<T: Transaction> Executor<T> for Limit<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        if let ResultSet::Query { columns, rows } = self.source.execute(txn)? {
-            Ok(ResultSet::Query { columns, rows: Box::new(rows.take(self.limit as usize)) })
+        if let ResultSet::Query { columns, mut rows } = self.source.execute(txn)? {
+            let mut remaining = self.limit as usize;
+            // Source batches are only fetched while rows remain, and the last one is truncated.
+            let batches = std::iter::from_fn(move || {
+                if remaining == 0 {
+                    return None;
+                }
+                Some(rows.next_batch()?.map(|mut batch| {
+                    batch.truncate(remaining);
+                    remaining -= batch.len();
+                    batch
+                }))
+            });
+            Ok(ResultSet::Query { columns, rows: Rows::from_batches(batches) })
         } else {
             Err(Error::Internal("Unexpected result".into()))
         }
@@ -182,9 +202,253 @@ impl<T: Transaction> Offset<T> {
 impl<T: Transaction> Executor<T> for Offset<T> {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         if let ResultSet::Query { columns, rows } = self.source.execute(txn)? {
-            Ok(ResultSet::Query { columns, rows: Box::new(rows.skip(self.offset as usize)) })
+            let mut remaining = self.offset as usize;
+            let rows = rows.map_batches(move |mut batch| {
+                let skip = remaining.min(batch.len());
+                remaining -= skip;
+                batch.drain(..skip);
+                Ok(batch)
+            });
+            Ok(ResultSet::Query { columns, rows })
+        } else {
+            Err(Error::Internal("Unexpected result".into()))
+        }
+    }
+}
+
+/// A common table expression or view executor, which passes through the source rows under the given
+/// column labels
+pub struct Cte<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    columns: Vec<Option<String>>,
+}
+
+impl<T: Transaction> Cte<T> {
+    pub fn new(source: Box<dyn Executor<T>>, columns: Vec<Option<String>>) -> Box<Self> {
+        Box::new(Self { source, columns })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Cte<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if let ResultSet::Query { rows, .. } = self.source.execute(txn)? {
+            let columns =
+                self.columns.into_iter().map(|name| Column { name, datatype: None }).collect();
+            Ok(ResultSet::Query { columns, rows })
         } else {
             Err(Error::Internal("Unexpected result".into()))
         }
     }
 }
+
+/// A set operation executor. It buffers the left and right rows, and unifies the types of each
+/// column: values must be of the same type or both numbers, ignoring NULLs, and integers are
+/// widened to floats in columns that contain floats. Rows are then combined by the operator, and
+/// deduplicated unless all is true, retaining the order of their first occurrence. NULLs are
+/// considered equal to each other when comparing rows.
+pub struct SetOperation<T: Transaction> {
+    operator: SetOperator,
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+    all: bool,
+}
+
+impl<T: Transaction> SetOperation<T> {
+    pub fn new(
+        operator: SetOperator,
+        left: Box<dyn Executor<T>>,
+        right: Box<dyn Executor<T>>,
+        all: bool,
+    ) -> Box<Self> {
+        Box::new(Self { operator, left, right, all })
+    }
+}
+
+impl<T: Transaction> Executor<T> for SetOperation<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match (self.left.execute(txn)?, self.right.execute(txn)?) {
+            (
+                ResultSet::Query { columns, rows: left },
+                ResultSet::Query { columns: right_columns, rows: right },
+            ) => {
+                if columns.len() != right_columns.len() {
+                    return Err(Error::Internal(format!(
+                        "{} inputs have {} and {} columns",
+                        self.operator.to_string().to_uppercase(),
+                        columns.len(),
+                        right_columns.len()
+                    )));
+                }
+                let mut left = left.collect::<Result<Vec<_>>>()?;
+                let mut right = right.collect::<Result<Vec<_>>>()?;
+                Self::unify(self.operator, columns.len(), &mut left, &mut right)?;
+                let rows = Self::combine(self.operator, self.all, left, right);
+                Ok(ResultSet::Query { columns, rows: Rows::new(rows.into_iter().map(Ok)) })
+            }
+            (ResultSet::Query { .. }, r) | (r, _) => {
+                Err(Error::Internal(format!("Unexpected result {:?}", r)))
+            }
+        }
+    }
+}
+
+impl<T: Transaction> SetOperation<T> {
+    /// Checks that the left and right values in each column have compatible types, and widens
+    /// integers to floats in columns that contain floats.
+    fn unify(
+        operator: SetOperator,
+        width: usize,
+        left: &mut [Row],
+        right: &mut [Row],
+    ) -> Result<()> {
+        let numeric =
+            |t: &DataType| matches!(t, DataType::Integer | DataType::Float | DataType::Decimal(..));
+        for i in 0..width {
+            let mut expect: Option<DataType> = None;
+            let mut float = false;
+            for value in left.iter().chain(right.iter()).filter_map(|row| row.get(i)) {
+                let datatype = match value.datatype() {
+                    Some(datatype) => datatype,
+                    None => continue,
+                };
+                float |= datatype == DataType::Float;
+                match &expect {
+                    Some(e) if !(numeric(e) && numeric(&datatype)) && *e != datatype => {
+                        return Err(Error::Value(format!(
+                            "{} column {} has incompatible types {} and {}",
+                            operator.to_string().to_uppercase(),
+                            i + 1,
+                            e,
+                            datatype
+                        )))
+                    }
+                    Some(_) => {}
+                    None => expect = Some(datatype),
+                }
+            }
+            if float {
+                for value in
+                    left.iter_mut().chain(right.iter_mut()).filter_map(|row| row.get_mut(i))
+                {
+                    if let Value::Integer(n) = value {
+                        *value = Value::Float(*n as f64);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Combines the left and right rows using the operator.
+    fn combine(operator: SetOperator, all: bool, left: Vec<Row>, right: Vec<Row>) -> Vec<Row> {
+        match (operator, all) {
+            (SetOperator::Union, true) => left.into_iter().chain(right).collect(),
+            (SetOperator::Union, false) => {
+                let mut seen = HashSet::new();
+                left.into_iter().chain(right).filter(|row| seen.insert(row.clone())).collect()
+            }
+            // For INTERSECT ALL and EXCEPT ALL, each right row matches (and thus emits or
+            // discards) a single left row, tracked by counting the right rows.
+            (SetOperator::Intersect, true) | (SetOperator::Except, true) => {
+                let mut counts: HashMap<Row, usize> = HashMap::new();
+                for row in right {
+                    *counts.entry(row).or_default() += 1;
+                }
+                let intersect = operator == SetOperator::Intersect;
+                left.into_iter()
+                    .filter(|row| match counts.get_mut(row) {
+                        Some(count) if *count > 0 => {
+                            *count -= 1;
+                            intersect
+                        }
+                        _ => !intersect,
+                    })
+                    .collect()
+            }
+            (SetOperator::Intersect, false) | (SetOperator::Except, false) => {
+                let right: HashSet<Row> = right.into_iter().collect();
+                let intersect = operator == SetOperator::Intersect;
+                let mut seen = HashSet::new();
+                left.into_iter()
+                    .filter(|row| right.contains(row) == intersect && seen.insert(row.clone()))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A recursive CTE executor. It emits the seed rows, and then repeatedly executes the recursive
+/// term with the work table replaced by the rows emitted by the previous iteration, until an
+/// iteration emits no rows. Unless all is true (i.e. UNION rather than UNION ALL), rows that have
+/// already been emitted are discarded, so e.g. cycles in a graph terminate.
+pub struct RecursiveUnion<T: Transaction> {
+    seed: Box<dyn Executor<T>>,
+    recursive: Node,
+    name: String,
+    id: usize,
+    all: bool,
+    limits: Limits,
+}
+
+impl<T: Transaction> RecursiveUnion<T> {
+    pub fn new(
+        seed: Box<dyn Executor<T>>,
+        recursive: Node,
+        name: String,
+        id: usize,
+        all: bool,
+        limits: Limits,
+    ) -> Box<Self> {
+        Box::new(Self { seed, recursive, name, id, all, limits })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for RecursiveUnion<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (columns, mut delta) = match self.seed.execute(txn)? {
+            ResultSet::Query { columns, rows } => (columns, rows.collect::<Result<Vec<_>>>()?),
+            r => return Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        };
+        let mut seen = HashSet::new();
+        if !self.all {
+            delta.retain(|row| seen.insert(row.clone()));
+        }
+        let mut rows = Vec::new();
+        let mut iterations = 0;
+        while !delta.is_empty() {
+            if let Some(max) = self.limits.max_recursive_iterations {
+                if iterations >= max {
+                    return Err(Error::Value(format!(
+                        "Recursive CTE {} exceeded limit of {} iterations",
+                        self.name, max
+                    )));
+                }
+            }
+            iterations += 1;
+
+            let id = self.id;
+            let work_table: Vec<Vec<Expression>> = delta
+                .iter()
+                .map(|row| row.iter().cloned().map(Expression::Constant).collect())
+                .collect();
+            let recursive = self.recursive.clone().transform(
+                &|n| match n {
+                    Node::WorkTable { id: i, .. } if i == id => {
+                        Ok(Node::Values { rows: work_table.clone() })
+                    }
+                    n => Ok(n),
+                },
+                &|n| Ok(n),
+            )?;
+            rows.append(&mut delta);
+            delta = match <dyn Executor<T>>::build(recursive, self.limits.clone()).execute(txn)? {
+                ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+                r => return Err(Error::Internal(format!("Unexpected result {:?}", r))),
+            };
+            if !self.all {
+                delta.retain(|row| seen.insert(row.clone()));
+            }
+        }
+        Ok(ResultSet::Query { columns, rows: Rows::new(rows.into_iter().map(Ok)) })
+    }
+}
@@ -1,41 +1,267 @@
 use super::super::engine::Transaction;
-use super::super::schema::Table;
+use super::super::schema::{Column, Privilege, Table, TableStats, User, View};
 use super::{Executor, ResultSet};
 use crate::error::Result;
 
-/// A CREATE TABLE executor
+/// An ANALYZE executor, which collects statistics for tables by scanning all of their rows.
+pub struct Analyze {
+    tables: Vec<String>,
+}
+
+impl Analyze {
+    pub fn new(tables: Vec<String>) -> Box<Self> {
+        Box::new(Self { tables })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Analyze {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        for name in &self.tables {
+            let table = txn.must_read_table(name)?;
+            let stats = TableStats::collect(table.columns.len(), txn.scan(name, None)?)?;
+            txn.set_table_stats(name, stats)?;
+        }
+        Ok(ResultSet::Analyze { tables: self.tables })
+    }
+}
+
+/// A CREATE TABLE executor. With IF NOT EXISTS, an existing table is left as-is without comparing
+/// its definition.
 pub struct CreateTable {
     table: Table,
+    if_not_exists: bool,
 }
 
 impl CreateTable {
-    pub fn new(table: Table) -> Box<Self> {
-        Box::new(Self { table })
+    pub fn new(table: Table, if_not_exists: bool) -> Box<Self> {
+        Box::new(Self { table, if_not_exists })
     }
 }
 
 impl<T: Transaction> Executor<T> for CreateTable {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let name = self.table.name.clone();
+        if self.if_not_exists && txn.read_table(&name)?.is_some() {
+            return Ok(ResultSet::CreateTable { name });
+        }
         txn.create_table(self.table)?;
         Ok(ResultSet::CreateTable { name })
     }
 }
 
-/// A DROP TABLE executor
+/// A DROP TABLE executor. With IF EXISTS, a missing table is ignored.
 pub struct DropTable {
     table: String,
+    if_exists: bool,
 }
 
 impl DropTable {
-    pub fn new(table: String) -> Box<Self> {
-        Box::new(Self { table })
+    pub fn new(table: String, if_exists: bool) -> Box<Self> {
+        Box::new(Self { table, if_exists })
     }
 }
 
 impl<T: Transaction> Executor<T> for DropTable {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if self.if_exists && txn.read_table(&self.table)?.is_none() {
+            return Ok(ResultSet::DropTable { name: self.table });
+        }
         txn.delete_table(&self.table)?;
         Ok(ResultSet::DropTable { name: self.table })
     }
 }
+
+/// An ALTER TABLE ADD COLUMN executor
+pub struct AddColumn {
+    table: String,
+    column: Column,
+}
+
+impl AddColumn {
+    pub fn new(table: String, column: Column) -> Box<Self> {
+        Box::new(Self { table, column })
+    }
+}
+
+impl<T: Transaction> Executor<T> for AddColumn {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.add_column(&self.table, self.column)?;
+        Ok(ResultSet::AlterTable { name: self.table })
+    }
+}
+
+/// An ALTER TABLE DROP COLUMN executor
+pub struct DropColumn {
+    table: String,
+    column: String,
+}
+
+impl DropColumn {
+    pub fn new(table: String, column: String) -> Box<Self> {
+        Box::new(Self { table, column })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropColumn {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.drop_column(&self.table, &self.column)?;
+        Ok(ResultSet::AlterTable { name: self.table })
+    }
+}
+
+/// An ALTER TABLE RENAME COLUMN executor
+pub struct RenameColumn {
+    table: String,
+    column: String,
+    new_name: String,
+}
+
+impl RenameColumn {
+    pub fn new(table: String, column: String, new_name: String) -> Box<Self> {
+        Box::new(Self { table, column, new_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for RenameColumn {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.rename_column(&self.table, &self.column, &self.new_name)?;
+        Ok(ResultSet::AlterTable { name: self.table })
+    }
+}
+
+/// An ALTER TABLE RENAME TO executor
+pub struct RenameTable {
+    table: String,
+    new_name: String,
+}
+
+impl RenameTable {
+    pub fn new(table: String, new_name: String) -> Box<Self> {
+        Box::new(Self { table, new_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for RenameTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.rename_table(&self.table, &self.new_name)?;
+        Ok(ResultSet::AlterTable { name: self.new_name })
+    }
+}
+
+/// A CREATE VIEW executor
+pub struct CreateView {
+    view: View,
+}
+
+impl CreateView {
+    pub fn new(view: View) -> Box<Self> {
+        Box::new(Self { view })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateView {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let name = self.view.name.clone();
+        txn.create_view(self.view)?;
+        Ok(ResultSet::CreateView { name })
+    }
+}
+
+/// A DROP VIEW executor. With IF EXISTS, a missing view is ignored.
+pub struct DropView {
+    view: String,
+    if_exists: bool,
+}
+
+impl DropView {
+    pub fn new(view: String, if_exists: bool) -> Box<Self> {
+        Box::new(Self { view, if_exists })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropView {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if self.if_exists && txn.read_view(&self.view)?.is_none() {
+            return Ok(ResultSet::DropView { name: self.view });
+        }
+        txn.delete_view(&self.view)?;
+        Ok(ResultSet::DropView { name: self.view })
+    }
+}
+
+/// A CREATE USER executor
+pub struct CreateUser {
+    user: User,
+}
+
+impl CreateUser {
+    pub fn new(user: User) -> Box<Self> {
+        Box::new(Self { user })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateUser {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let name = self.user.name.clone();
+        txn.create_user(self.user)?;
+        Ok(ResultSet::CreateUser { name })
+    }
+}
+
+/// A DROP USER executor. With IF EXISTS, a missing user is ignored.
+pub struct DropUser {
+    user: String,
+    if_exists: bool,
+}
+
+impl DropUser {
+    pub fn new(user: String, if_exists: bool) -> Box<Self> {
+        Box::new(Self { user, if_exists })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropUser {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        if self.if_exists && txn.read_user(&self.user)?.is_none() {
+            return Ok(ResultSet::DropUser { name: self.user });
+        }
+        txn.delete_user(&self.user)?;
+        Ok(ResultSet::DropUser { name: self.user })
+    }
+}
+
+/// A GRANT or REVOKE executor, which adds privileges on a table to a user or removes them.
+/// Granting privileges the user already has, or revoking ones it doesn't have, is a noop.
+pub struct Grant {
+    privileges: Vec<Privilege>,
+    table: String,
+    user: String,
+    revoke: bool,
+}
+
+impl Grant {
+    pub fn new(privileges: Vec<Privilege>, table: String, user: String, revoke: bool) -> Box<Self> {
+        Box::new(Self { privileges, table, user, revoke })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Grant {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let mut user = txn.must_read_user(&self.user)?;
+        let table = txn.must_read_table(&self.table)?;
+        let privileges = user.privileges.entry(table.name).or_default();
+        if self.revoke {
+            for privilege in &self.privileges {
+                privileges.remove(privilege);
+            }
+            user.privileges.retain(|_, privileges| !privileges.is_empty());
+        } else {
+            privileges.extend(self.privileges);
+        }
+        txn.update_user(user)?;
+        Ok(match self.revoke {
+            true => ResultSet::Revoke { user: self.user },
+            false => ResultSet::Grant { user: self.user },
+        })
+    }
+}
@@ -0,0 +1,302 @@
+use super::super::engine::Transaction;
+use super::super::plan::{Direction, Nulls};
+use super::super::types::{Expression, Row, Rows, Value};
+use super::{Executor, MemoryReservation, MemoryTracker, ResultSet};
+use crate::error::{Error, Result};
+
+use log::error;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write as _};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// An ORDER BY executor. Rows are sorted in memory until they exceed the memory budget, if any,
+/// at which point the sorted rows are spilled to a temporary file as a sorted run. The runs are
+/// then merged while emitting rows, i.e. an external merge sort. If only the first limit rows are
+/// needed (i.e. for a TopK node), they are kept in a bounded heap instead. The sort is stable, such
+/// that rows with equal sort values are emitted in source order. Buffered rows are charged to the
+/// statement's memory tracker until they are emitted or spilled.
+pub struct Order<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    orders: Vec<(Expression, Direction, Nulls)>,
+    max_memory: Option<usize>,
+    limit: Option<u64>,
+    memory: MemoryTracker,
+}
+
+impl<T: Transaction> Order<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        orders: Vec<(Expression, Direction, Nulls)>,
+        max_memory: Option<usize>,
+        limit: Option<u64>,
+        memory: MemoryTracker,
+    ) -> Box<Self> {
+        Box::new(Self { source, orders, max_memory, limit, memory })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Order<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match self.source.execute(txn)? {
+            ResultSet::Query { columns, rows } => {
+                let (exprs, directions): (Vec<_>, Vec<_>) =
+                    self.orders.into_iter().map(|(e, d, n)| (e, (d, n))).unzip();
+                let directions = Arc::new(directions);
+                // Since we can't return errors from the comparison function, we have to
+                // pre-evaluate all sort values. This means that we can't short-circuit evaluation.
+                let mut items = rows.enumerate().map(move |(seq, row)| {
+                    let row = row?;
+                    let values =
+                        exprs.iter().map(|e| e.evaluate(Some(&row))).collect::<Result<_>>()?;
+                    Ok(Item { row, values, seq })
+                });
+                let mut memory = self.memory.reserve();
+                let rows: Rows = match (self.limit, self.max_memory) {
+                    (Some(limit), _) => sort_top(&mut items, &directions, limit, &mut memory)?,
+                    (None, Some(max_memory)) => {
+                        sort_external(&mut items, &directions, max_memory, &mut memory)?
+                    }
+                    (None, None) => {
+                        let mut buffer = Vec::new();
+                        for item in items {
+                            let item = item?;
+                            memory.grow(item.size()?)?;
+                            buffer.push(item);
+                        }
+                        buffer.sort_by(|a, b| a.cmp(b, &directions));
+                        Rows::new(buffer.into_iter().map(|item| Ok(item.row)))
+                    }
+                };
+                Ok(ResultSet::Query { columns, rows: memory.hold(rows) })
+            }
+            r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        }
+    }
+}
+
+/// Sorts the items keeping only the first limit items, using a bounded max-heap which evicts the
+/// greatest item when full.
+fn sort_top(
+    items: &mut dyn Iterator<Item = Result<Item>>,
+    directions: &Arc<Vec<(Direction, Nulls)>>,
+    limit: u64,
+    memory: &mut MemoryReservation,
+) -> Result<Rows> {
+    let mut heap = BinaryHeap::new();
+    if limit > 0 {
+        for item in items {
+            let item = item?;
+            memory.grow(item.size()?)?;
+            heap.push(HeapItem { item, run: 0, directions: directions.clone() });
+            if heap.len() as u64 > limit {
+                if let Some(evicted) = heap.pop() {
+                    memory.shrink(evicted.item.size()?);
+                }
+            }
+        }
+    }
+    Ok(Rows::new(heap.into_sorted_vec().into_iter().map(|h| Ok(h.item.row))))
+}
+
+/// Sorts the items with an external merge sort, spilling sorted runs to temporary files whenever
+/// the buffered items exceed max_memory bytes. If all items fit in memory, nothing is spilled.
+fn sort_external(
+    items: &mut dyn Iterator<Item = Result<Item>>,
+    directions: &Arc<Vec<(Direction, Nulls)>>,
+    max_memory: usize,
+    reservation: &mut MemoryReservation,
+) -> Result<Rows> {
+    let mut spill: Option<Spill> = None;
+    let mut buffer = Vec::new();
+    let mut memory = 0;
+    for item in items {
+        let item = item?;
+        let size = item.size()?;
+        memory += size;
+        reservation.grow(size)?;
+        buffer.push(item);
+        if memory > max_memory {
+            buffer.sort_by(|a, b| a.cmp(b, directions));
+            let spill = match &mut spill {
+                Some(spill) => spill,
+                None => spill.get_or_insert(Spill::new()?),
+            };
+            spill.write_run(buffer.drain(..))?;
+            reservation.shrink(memory);
+            memory = 0;
+        }
+    }
+    buffer.sort_by(|a, b| a.cmp(b, directions));
+    Ok(Rows::new(Merge::new(spill, buffer, directions.clone())?))
+}
+
+/// A row being sorted, with its evaluated sort values and its position in the source.
+#[derive(Serialize, Deserialize)]
+struct Item {
+    row: Row,
+    values: Vec<Value>,
+    seq: usize,
+}
+
+impl Item {
+    /// Compares the sort values of two items in the given directions and NULL placements, see
+    /// Direction::compare(). Ties are broken by source position, for a stable sort.
+    fn cmp(&self, other: &Self, directions: &[(Direction, Nulls)]) -> Ordering {
+        for ((a, b), (direction, nulls)) in self.values.iter().zip(&other.values).zip(directions) {
+            match direction.compare(nulls, a, b) {
+                Ordering::Equal => {}
+                o => return o,
+            }
+        }
+        self.seq.cmp(&other.seq)
+    }
+
+    /// Returns the approximate memory size of the item, i.e. its encoded size.
+    fn size(&self) -> Result<usize> {
+        Ok(bincode::serialized_size(self)? as usize)
+    }
+}
+
+/// An item in a binary heap, ordered by its sort values and directions, with the run it was read
+/// from during a merge.
+struct HeapItem {
+    item: Item,
+    run: usize,
+    directions: Arc<Vec<(Direction, Nulls)>>,
+}
+
+impl Ord for HeapItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.item.cmp(&other.item, &self.directions)
+    }
+}
+
+impl PartialOrd for HeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapItem {}
+
+/// Sorted runs spilled to files in a temporary directory, which is removed when dropped (e.g. when
+/// the sort completes, errors, or its rows are dropped by a disconnecting client).
+struct Spill {
+    dir: PathBuf,
+    runs: Vec<BufReader<File>>,
+}
+
+impl Spill {
+    /// The prefix of temporary spill directory names.
+    const DIR_PREFIX: &'static str = "toydb-sort-";
+
+    /// Creates a new spill directory in the system's temporary directory.
+    fn new() -> Result<Self> {
+        let dir =
+            std::env::temp_dir().join(format!("{}{}", Self::DIR_PREFIX, uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir)?;
+        Ok(Self { dir, runs: Vec::new() })
+    }
+
+    /// Writes a sorted run to a new spill file.
+    fn write_run(&mut self, items: impl Iterator<Item = Item>) -> Result<()> {
+        let path = self.dir.join(format!("run-{}", self.runs.len()));
+        let mut file = BufWriter::new(File::create(&path)?);
+        for item in items {
+            bincode::serialize_into(&mut file, &item)?;
+        }
+        file.flush()?;
+        self.runs.push(BufReader::new(File::open(&path)?));
+        Ok(())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        self.runs.clear();
+        if let Err(err) = std::fs::remove_dir_all(&self.dir) {
+            error!("Failed to remove sort spill directory {}: {}", self.dir.display(), err);
+        }
+    }
+}
+
+/// A k-way merge of the sorted runs of an external sort, using a min-heap of the next item of each
+/// run. The in-memory buffer of remaining items is the last run.
+struct Merge {
+    spill: Option<Spill>,
+    buffer: std::vec::IntoIter<Item>,
+    heap: BinaryHeap<std::cmp::Reverse<HeapItem>>,
+    directions: Arc<Vec<(Direction, Nulls)>>,
+}
+
+impl Merge {
+    fn new(
+        spill: Option<Spill>,
+        buffer: Vec<Item>,
+        directions: Arc<Vec<(Direction, Nulls)>>,
+    ) -> Result<Self> {
+        let mut merge =
+            Self { spill, buffer: buffer.into_iter(), heap: BinaryHeap::new(), directions };
+        let runs = merge.spill.as_ref().map(|s| s.runs.len()).unwrap_or(0);
+        for run in 0..=runs {
+            merge.push_next(run)?;
+        }
+        Ok(merge)
+    }
+
+    /// Reads the next item of the given run, if any, and pushes it onto the heap.
+    fn push_next(&mut self, run: usize) -> Result<()> {
+        let item = match self.spill.as_mut().and_then(|s| s.runs.get_mut(run)) {
+            Some(file) => match bincode::deserialize_from(file) {
+                Ok(item) => Some(item),
+                Err(err) => match *err {
+                    bincode::ErrorKind::Io(err)
+                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    {
+                        None
+                    }
+                    err => return Err(Box::new(err).into()),
+                },
+            },
+            None => self.buffer.next(),
+        };
+        if let Some(item) = item {
+            let directions = self.directions.clone();
+            self.heap.push(std::cmp::Reverse(HeapItem { item, run, directions }));
+        }
+        Ok(())
+    }
+
+    // Tries to get the next row, with error handling.
+    fn try_next(&mut self) -> Result<Option<Row>> {
+        match self.heap.pop() {
+            Some(std::cmp::Reverse(HeapItem { item, run, .. })) => {
+                self.push_next(run)?;
+                Ok(Some(item.row))
+            }
+            None => {
+                // Remove the spill files as soon as the merge completes.
+                self.spill = None;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
@@ -1,19 +1,28 @@
 use super::super::engine::Transaction;
-use super::super::types::{Column, Expression, Row, Value};
+use super::super::schema::{SystemTable, Table};
+use super::super::types::{Column, Expression, Row, Rows, Value};
 use super::{Executor, ResultSet};
-use crate::error::Result;
+use crate::error::{Error, Result};
 
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::ops::Bound;
 
-/// A table scan executor
+/// A table scan executor, which fetches rows from the engine in batches of batch_size rows
 pub struct Scan {
     table: String,
     filter: Option<Expression>,
+    columns: Option<Vec<String>>,
+    batch_size: usize,
 }
 
 impl Scan {
-    pub fn new(table: String, filter: Option<Expression>) -> Box<Self> {
-        Box::new(Self { table, filter })
+    pub fn new(
+        table: String,
+        filter: Option<Expression>,
+        columns: Option<Vec<String>>,
+        batch_size: usize,
+    ) -> Box<Self> {
+        Box::new(Self { table, filter, columns, batch_size })
     }
 }
 
@@ -21,8 +30,23 @@ impl<T: Transaction> Executor<T> for Scan {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let table = txn.must_read_table(&self.table)?;
         Ok(ResultSet::Query {
-            columns: table.columns.iter().map(|c| Column { name: Some(c.name.clone()) }).collect(),
-            rows: Box::new(txn.scan(&table.name, self.filter)?),
+            columns: table
+                .columns
+                .iter()
+                .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+                .collect(),
+            rows: Rows::chunked(
+                match column_indexes(&table, self.columns)? {
+                    Some(columns) => txn.scan_columns(
+                        &table.name,
+                        (Bound::Unbounded, Bound::Unbounded),
+                        &columns,
+                        self.filter,
+                    )?,
+                    None => txn.scan(&table.name, self.filter)?,
+                },
+                self.batch_size,
+            ),
         })
     }
 }
@@ -31,28 +55,69 @@ impl<T: Transaction> Executor<T> for Scan {
 pub struct KeyLookup {
     table: String,
     keys: Vec<Value>,
+    columns: Option<Vec<String>>,
 }
 
 impl KeyLookup {
-    pub fn new(table: String, keys: Vec<Value>) -> Box<Self> {
-        Box::new(Self { table, keys })
+    pub fn new(table: String, keys: Vec<Value>, columns: Option<Vec<String>>) -> Box<Self> {
+        Box::new(Self { table, keys, columns })
     }
 }
 
 impl<T: Transaction> Executor<T> for KeyLookup {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let table = txn.must_read_table(&self.table)?;
+        let columns = column_indexes(&table, self.columns)?;
 
         // FIXME Is there a way to pass the txn into an iterator closure instead?
         let rows = self
             .keys
             .into_iter()
-            .filter_map(|key| txn.read(&table.name, &key).transpose())
+            .filter_map(|key| read_row(txn, &table, &key, &columns).transpose())
             .collect::<Result<Vec<Row>>>()?;
 
         Ok(ResultSet::Query {
-            columns: table.columns.iter().map(|c| Column { name: Some(c.name.clone()) }).collect(),
-            rows: Box::new(rows.into_iter().map(Ok)),
+            columns: table
+                .columns
+                .iter()
+                .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+                .collect(),
+            rows: Rows::new(rows.into_iter().map(Ok)),
+        })
+    }
+}
+
+/// A primary key range scan executor, which scans the rows with primary keys within a range in
+/// primary key order. Rows are streamed from the engine, such that e.g. a limit stops the scan.
+pub struct KeyRangeScan {
+    table: String,
+    range: (Bound<Value>, Bound<Value>),
+    columns: Option<Vec<String>>,
+}
+
+impl KeyRangeScan {
+    pub fn new(
+        table: String,
+        range: (Bound<Value>, Bound<Value>),
+        columns: Option<Vec<String>>,
+    ) -> Box<Self> {
+        Box::new(Self { table, range, columns })
+    }
+}
+
+impl<T: Transaction> Executor<T> for KeyRangeScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_read_table(&self.table)?;
+        Ok(ResultSet::Query {
+            columns: table
+                .columns
+                .iter()
+                .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+                .collect(),
+            rows: Rows::new(match column_indexes(&table, self.columns)? {
+                Some(columns) => txn.scan_columns(&table.name, self.range, &columns, None)?,
+                None => txn.scan_range(&table.name, self.range, None)?,
+            }),
         })
     }
 }
@@ -62,36 +127,273 @@ pub struct IndexLookup {
     table: String,
     column: String,
     values: Vec<Value>,
+    columns: Option<Vec<String>>,
+    index_only: bool,
 }
 
 impl IndexLookup {
-    pub fn new(table: String, column: String, values: Vec<Value>) -> Box<Self> {
-        Box::new(Self { table, column, values })
+    pub fn new(
+        table: String,
+        column: String,
+        values: Vec<Value>,
+        columns: Option<Vec<String>>,
+        index_only: bool,
+    ) -> Box<Self> {
+        Box::new(Self { table, column, values, columns, index_only })
     }
 }
 
 impl<T: Transaction> Executor<T> for IndexLookup {
     fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
         let table = txn.must_read_table(&self.table)?;
+        let columns = column_indexes(&table, self.columns)?;
 
-        let mut pks: HashSet<Value> = HashSet::new();
+        let mut pks: HashMap<Value, Value> = HashMap::new();
         for value in self.values {
-            pks.extend(txn.read_index(&self.table, &self.column, &value)?);
+            for pk in txn.read_index(&self.table, &self.column, &value)? {
+                pks.insert(pk, value.clone());
+            }
         }
+        let entries = pks.into_iter().map(|(pk, value)| (value, pk)).collect();
+        let rows = read_entries(txn, &table, &self.column, entries, &columns, self.index_only)?;
 
-        // FIXME Is there a way to pass the txn into an iterator closure instead?
-        let rows = pks
-            .into_iter()
-            .filter_map(|pk| txn.read(&table.name, &pk).transpose())
-            .collect::<Result<Vec<Row>>>()?;
+        Ok(ResultSet::Query {
+            columns: table
+                .columns
+                .iter()
+                .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+                .collect(),
+            rows: Rows::new(rows.into_iter().map(Ok)),
+        })
+    }
+}
+
+/// An index prefix scan executor, which scans index entries for string values with a prefix
+pub struct IndexPrefixScan {
+    table: String,
+    column: String,
+    prefix: String,
+    columns: Option<Vec<String>>,
+    index_only: bool,
+}
+
+impl IndexPrefixScan {
+    pub fn new(
+        table: String,
+        column: String,
+        prefix: String,
+        columns: Option<Vec<String>>,
+        index_only: bool,
+    ) -> Box<Self> {
+        Box::new(Self { table, column, prefix, columns, index_only })
+    }
+}
+
+impl<T: Transaction> Executor<T> for IndexPrefixScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_read_table(&self.table)?;
+        let columns = column_indexes(&table, self.columns)?;
+
+        let mut entries: Vec<(Value, Value)> = Vec::new();
+        for entry in txn.scan_index_prefix(&self.table, &self.column, &self.prefix)? {
+            let (value, pks) = entry?;
+            entries.extend(pks.into_iter().map(|pk| (value.clone(), pk)));
+        }
+        let rows = read_entries(txn, &table, &self.column, entries, &columns, self.index_only)?;
+
+        Ok(ResultSet::Query {
+            columns: table
+                .columns
+                .iter()
+                .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+                .collect(),
+            rows: Rows::new(rows.into_iter().map(Ok)),
+        })
+    }
+}
+
+/// An index range scan executor, which scans index entries for values within a range. Rows are
+/// returned in index value order, and rows with equal values in primary key order.
+pub struct IndexRangeScan {
+    table: String,
+    column: String,
+    range: (Bound<Value>, Bound<Value>),
+    columns: Option<Vec<String>>,
+    index_only: bool,
+}
+
+impl IndexRangeScan {
+    pub fn new(
+        table: String,
+        column: String,
+        range: (Bound<Value>, Bound<Value>),
+        columns: Option<Vec<String>>,
+        index_only: bool,
+    ) -> Box<Self> {
+        Box::new(Self { table, column, range, columns, index_only })
+    }
+}
+
+impl<T: Transaction> Executor<T> for IndexRangeScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_read_table(&self.table)?;
+        let columns = column_indexes(&table, self.columns)?;
+
+        let mut entries: Vec<(Value, Value)> = Vec::new();
+        for entry in txn.scan_index_range(&self.table, &self.column, self.range)? {
+            let (value, pks) = entry?;
+            let mut pks: Vec<Value> = pks.into_iter().collect();
+            pks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            entries.extend(pks.into_iter().map(|pk| (value.clone(), pk)));
+        }
+        let rows = read_entries(txn, &table, &self.column, entries, &columns, self.index_only)?;
 
         Ok(ResultSet::Query {
-            columns: table.columns.iter().map(|c| Column { name: Some(c.name.clone()) }).collect(),
-            rows: Box::new(rows.into_iter().map(Ok)),
+            columns: table
+                .columns
+                .iter()
+                .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+                .collect(),
+            rows: Rows::new(rows.into_iter().map(Ok)),
         })
     }
 }
 
+/// An index order scan executor, which reads the rows of each index entry in turn until it has
+/// read limit rows matching the filter.
+pub struct IndexOrderScan {
+    table: String,
+    column: String,
+    filter: Option<Expression>,
+    reverse: bool,
+    limit: u64,
+    columns: Option<Vec<String>>,
+    index_only: bool,
+}
+
+impl IndexOrderScan {
+    pub fn new(
+        table: String,
+        column: String,
+        filter: Option<Expression>,
+        reverse: bool,
+        limit: u64,
+        columns: Option<Vec<String>>,
+        index_only: bool,
+    ) -> Box<Self> {
+        Box::new(Self { table, column, filter, reverse, limit, columns, index_only })
+    }
+}
+
+impl<T: Transaction> Executor<T> for IndexOrderScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_read_table(&self.table)?;
+        let columns = column_indexes(&table, self.columns)?;
+        let mut entries = txn.scan_index(&self.table, &self.column)?;
+        if self.reverse {
+            entries = Box::new(entries.rev());
+        }
+
+        let mut rows = Vec::new();
+        while (rows.len() as u64) < self.limit {
+            let (value, mut pks): (Value, Vec<Value>) = match entries.next().transpose()? {
+                Some((value, pks)) => (value, pks.into_iter().collect()),
+                None => break,
+            };
+            pks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            for pk in pks {
+                let row = if self.index_only {
+                    index_row(&table, &self.column, value.clone(), pk)?
+                } else {
+                    match read_row(txn, &table, &pk, &columns)? {
+                        Some(row) => row,
+                        None => continue,
+                    }
+                };
+                if let Some(filter) = &self.filter {
+                    match filter.evaluate(Some(&row))? {
+                        Value::Boolean(true) => {}
+                        Value::Boolean(false) | Value::Null => continue,
+                        value => {
+                            return Err(Error::Value(format!(
+                                "Filter returned {}, expected boolean",
+                                value
+                            )))
+                        }
+                    }
+                }
+                rows.push(row);
+                if rows.len() as u64 >= self.limit {
+                    break;
+                }
+            }
+        }
+
+        Ok(ResultSet::Query {
+            columns: table
+                .columns
+                .iter()
+                .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+                .collect(),
+            rows: Rows::new(rows.into_iter().map(Ok)),
+        })
+    }
+}
+
+/// Resolves the names of the columns to read, if not all, to column indexes.
+fn column_indexes(table: &Table, columns: Option<Vec<String>>) -> Result<Option<Vec<usize>>> {
+    columns.map(|c| c.iter().map(|c| table.get_column_index(c)).collect()).transpose()
+}
+
+/// Reads a table row, only decoding the given columns if any.
+fn read_row<T: Transaction>(
+    txn: &mut T,
+    table: &Table,
+    id: &Value,
+    columns: &Option<Vec<usize>>,
+) -> Result<Option<Row>> {
+    match columns {
+        Some(columns) => txn.read_columns(&table.name, id, columns),
+        None => txn.read(&table.name, id),
+    }
+}
+
+/// Builds a row from an index entry's column value and primary key, for index-only scans. The
+/// other columns are NULL, except a single-column primary key (see Table::index_covers()).
+/// Decimal index values are stored without trailing zeroes, so they're conformed to the column.
+fn index_row(table: &Table, column: &str, value: Value, pk: Value) -> Result<Row> {
+    let mut row = vec![Value::Null; table.columns.len()];
+    if let [index] = table.get_primary_key_indexes().as_slice() {
+        row[*index] = pk;
+    }
+    let index = table.get_column_index(column)?;
+    row[index] = table.columns[index].conform_value(value)?;
+    Ok(row)
+}
+
+/// Reads the rows of the given index entries, as column values and primary keys. Index-only
+/// scans build the rows from the entries alone, otherwise the rows are read from the table.
+fn read_entries<T: Transaction>(
+    txn: &mut T,
+    table: &Table,
+    column: &str,
+    entries: Vec<(Value, Value)>,
+    columns: &Option<Vec<usize>>,
+    index_only: bool,
+) -> Result<Vec<Row>> {
+    if index_only {
+        return entries
+            .into_iter()
+            .map(|(value, pk)| index_row(table, column, value, pk))
+            .collect();
+    }
+    // FIXME Is there a way to pass the txn into an iterator closure instead?
+    entries
+        .into_iter()
+        .filter_map(|(_, pk)| read_row(txn, table, &pk, columns).transpose())
+        .collect()
+}
+
 /// An executor that produces a single empty row
 pub struct Nothing;
 
@@ -105,7 +407,76 @@ impl<T: Transaction> Executor<T> for Nothing {
     fn execute(self: Box<Self>, _: &mut T) -> Result<ResultSet> {
         Ok(ResultSet::Query {
             columns: Vec::new(),
-            rows: Box::new(std::iter::once(Ok(Row::new()))),
+            rows: Rows::new(std::iter::once(Ok(Row::new()))),
         })
     }
 }
+
+/// An executor that emits rows of constant expressions
+pub struct Values {
+    rows: Vec<Vec<Expression>>,
+}
+
+impl Values {
+    pub fn new(rows: Vec<Vec<Expression>>) -> Box<Self> {
+        Box::new(Self { rows })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Values {
+    fn execute(self: Box<Self>, _: &mut T) -> Result<ResultSet> {
+        let width = self.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        Ok(ResultSet::Query {
+            columns: (0..width).map(|_| Column { name: None, datatype: None }).collect(),
+            rows: Rows::new(self.rows.into_iter().map(|exprs| {
+                exprs.into_iter().map(|expr| expr.evaluate(None)).collect::<Result<_>>()
+            })),
+        })
+    }
+}
+
+/// A system table scan executor, which generates the rows from the catalog
+pub struct SystemScan {
+    table: SystemTable,
+}
+
+impl SystemScan {
+    pub fn new(table: SystemTable) -> Box<Self> {
+        Box::new(Self { table })
+    }
+}
+
+impl<T: Transaction> Executor<T> for SystemScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        Ok(ResultSet::Query {
+            columns: self
+                .table
+                .columns()
+                .into_iter()
+                .map(|(name, datatype)| Column {
+                    name: Some(name.into()),
+                    datatype: Some(datatype),
+                })
+                .collect(),
+            rows: Rows::new(self.table.scan(txn)?.into_iter().map(Ok)),
+        })
+    }
+}
+
+/// A recursive CTE work table executor. Work tables are replaced by the rows of the previous
+/// iteration before the recursive term is executed, so this always errors.
+pub struct WorkTable {
+    name: String,
+}
+
+impl WorkTable {
+    pub fn new(name: String) -> Box<Self> {
+        Box::new(Self { name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for WorkTable {
+    fn execute(self: Box<Self>, _: &mut T) -> Result<ResultSet> {
+        Err(Error::Internal(format!("Unexpected work table for {}", self.name)))
+    }
+}
@@ -0,0 +1,183 @@
+use super::super::engine::Transaction;
+use super::super::plan::{Direction, Nulls, Window as WindowKind, WindowFunction};
+use super::super::types::{Column, Row, Rows, Value};
+use super::aggregation::Accumulator;
+use super::{Executor, ResultSet};
+use crate::error::{Error, Result};
+
+use std::cmp::Ordering;
+
+/// A window function executor. It buffers all source rows, and for each distinct window
+/// specification (partition and order) sorts the rows by partition and then window order, scanning
+/// them to compute the values of all functions with that specification. The function values are
+/// appended to the source rows, which are emitted in the order of the last sort.
+pub struct Window<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    functions: Vec<WindowFunction>,
+}
+
+impl<T: Transaction> Window<T> {
+    pub fn new(source: Box<dyn Executor<T>>, functions: Vec<WindowFunction>) -> Box<Self> {
+        Box::new(Self { source, functions })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Window<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let functions = self.functions;
+        match self.source.execute(txn)? {
+            ResultSet::Query { mut columns, mut rows } => {
+                let mut items = Vec::new();
+                while let Some(row) = rows.next().transpose()? {
+                    items.push(row);
+                }
+                let mut values: Vec<Vec<Value>> =
+                    items.iter().map(|_| vec![Value::Null; functions.len()]).collect();
+
+                // Group functions by window specification, such that they share a sort.
+                let mut specs: Vec<Vec<usize>> = Vec::new();
+                for (i, function) in functions.iter().enumerate() {
+                    match specs.iter_mut().find(|spec| {
+                        let other = &functions[spec[0]];
+                        other.partition == function.partition && other.order == function.order
+                    }) {
+                        Some(spec) => spec.push(i),
+                        None => specs.push(vec![i]),
+                    }
+                }
+
+                let mut order: Vec<usize> = (0..items.len()).collect();
+                for spec in specs {
+                    order = Self::evaluate(&functions, &spec, &items, &mut values)?;
+                }
+
+                columns.extend(functions.iter().map(|_| Column { name: None, datatype: None }));
+                let mut rows: Vec<Option<Row>> = items
+                    .into_iter()
+                    .zip(values)
+                    .map(|(mut row, values)| {
+                        row.extend(values);
+                        Some(row)
+                    })
+                    .collect();
+                let rows = order
+                    .into_iter()
+                    .map(|i| rows[i].take().ok_or_else(|| Error::Internal("Missing row".into())))
+                    .collect::<Vec<_>>();
+                Ok(ResultSet::Query { columns, rows: Rows::new(rows.into_iter()) })
+            }
+            r => Err(Error::Internal(format!("Unexpected result {:?}", r))),
+        }
+    }
+}
+
+impl<T: Transaction> Window<T> {
+    /// Evaluates the given functions, which share a window specification, for all rows. The
+    /// function values are stored in values by row and function index. Returns the row indexes in
+    /// window order.
+    fn evaluate(
+        functions: &[WindowFunction],
+        spec: &[usize],
+        rows: &[Row],
+        values: &mut [Vec<Value>],
+    ) -> Result<Vec<usize>> {
+        let window = &functions[spec[0]];
+
+        // Evaluate the partition and order keys up front, since sort_by can't return errors.
+        struct Keys {
+            partition: Vec<Value>,
+            order: Vec<Value>,
+        }
+        let mut keys = Vec::new();
+        for row in rows {
+            keys.push(Keys {
+                partition: window
+                    .partition
+                    .iter()
+                    .map(|e| e.evaluate(Some(row)))
+                    .collect::<Result<_>>()?,
+                order: window
+                    .order
+                    .iter()
+                    .map(|(e, _, _)| e.evaluate(Some(row)))
+                    .collect::<Result<_>>()?,
+            });
+        }
+        let compare =
+            |a: &[Value], b: &[Value], orders: &mut dyn Iterator<Item = (&Direction, &Nulls)>| {
+                for ((a, b), (direction, nulls)) in a.iter().zip(b).zip(orders) {
+                    match direction.compare(nulls, a, b) {
+                        Ordering::Equal => {}
+                        o => return o,
+                    }
+                }
+                Ordering::Equal
+            };
+        let partition_order = || std::iter::repeat((&Direction::Ascending, &Nulls::First));
+        let window_order = || window.order.iter().map(|(_, d, n)| (d, n));
+        let same_partition = |a: &Keys, b: &Keys| {
+            compare(&a.partition, &b.partition, &mut partition_order()) == Ordering::Equal
+        };
+        let peers = |a: &Keys, b: &Keys| {
+            same_partition(a, b)
+                && compare(&a.order, &b.order, &mut window_order()) == Ordering::Equal
+        };
+
+        let mut order: Vec<usize> = (0..rows.len()).collect();
+        order.sort_by(|&a, &b| {
+            compare(&keys[a].partition, &keys[b].partition, &mut partition_order())
+                .then_with(|| compare(&keys[a].order, &keys[b].order, &mut window_order()))
+        });
+
+        // Scan the sorted rows one peer group at a time, resetting the counters and aggregates at
+        // partition boundaries. Aggregates accumulate the entire peer group before yielding values.
+        let mut accumulators: Vec<Option<Box<dyn Accumulator>>> = Vec::new();
+        let (mut before, mut groups) = (0, 0);
+        let mut start = 0;
+        while start < order.len() {
+            if start == 0 || !same_partition(&keys[order[start - 1]], &keys[order[start]]) {
+                accumulators = spec
+                    .iter()
+                    .map(|&f| match &functions[f].window {
+                        WindowKind::Aggregate(aggregate) => {
+                            Some(<dyn Accumulator>::from(aggregate))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                before = 0;
+                groups = 0;
+            }
+            let mut end = start + 1;
+            while end < order.len() && peers(&keys[order[start]], &keys[order[end]]) {
+                end += 1;
+            }
+            groups += 1;
+            for (accumulator, &f) in accumulators.iter_mut().zip(spec) {
+                if let Some(accumulator) = accumulator {
+                    for &i in &order[start..end] {
+                        let value = match functions[f].args.first() {
+                            Some(expr) => expr.evaluate(Some(&rows[i]))?,
+                            None => Value::Null,
+                        };
+                        accumulator.accumulate(&value)?;
+                    }
+                }
+                for (n, &i) in order[start..end].iter().enumerate() {
+                    values[i][f] = match (&functions[f].window, &accumulator) {
+                        (WindowKind::RowNumber, _) => Value::Integer(before + n as i64 + 1),
+                        (WindowKind::Rank, _) => Value::Integer(before + 1),
+                        (WindowKind::DenseRank, _) => Value::Integer(groups),
+                        (WindowKind::Aggregate(_), Some(accumulator)) => accumulator.aggregate()?,
+                        (WindowKind::Aggregate(_), None) => {
+                            return Err(Error::Internal("Missing window accumulator".into()))
+                        }
+                    };
+                }
+            }
+            before += (end - start) as i64;
+            start = end;
+        }
+        Ok(order)
+    }
+}
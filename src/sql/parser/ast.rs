@@ -1,4 +1,5 @@
-use super::super::types::DataType;
+use super::super::schema::Privilege;
+use super::super::types::{DataType, Value};
 use crate::error::Result;
 
 use std::collections::BTreeMap;
@@ -9,44 +10,219 @@ use std::mem::replace;
 #[allow(clippy::large_enum_variant)]
 pub enum Statement {
     Begin {
-        readonly: bool,
+        /// Whether the transaction is READ ONLY or READ WRITE, or None for the session default.
+        readonly: Option<bool>,
         version: Option<u64>,
+        serializable: bool,
     },
     Commit,
     Rollback,
-    Explain(Box<Statement>),
+    Explain {
+        statement: Box<Statement>,
+        /// Executes the statement and annotates the plan with execution statistics.
+        analyze: bool,
+    },
+    Vacuum,
+    /// Sets a session variable to the given value.
+    Set {
+        variable: String,
+        value: Literal,
+    },
+    /// Shows a session variable, or all session variables if None.
+    Show {
+        variable: Option<String>,
+    },
+    /// Lists the names of all tables.
+    ShowTables,
+    /// Shows the CREATE TABLE statement of a table.
+    ShowCreateTable {
+        table: String,
+    },
 
+    /// Collects statistics for the given table, or all tables if None.
+    Analyze {
+        table: Option<String>,
+    },
+    AlterTable {
+        name: String,
+        action: AlterTable,
+    },
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        /// A table-level PRIMARY KEY constraint, listing the key columns.
+        primary_key: Option<Vec<String>>,
+        if_not_exists: bool,
+    },
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    CreateView {
+        name: String,
+        /// The view query as SQL text, which has been parsed as a valid query.
+        query: String,
+    },
+    DropView {
+        name: String,
+        if_exists: bool,
+    },
+    /// Creates a user without any privileges, or a superuser which has all privileges.
+    CreateUser {
+        name: String,
+        superuser: bool,
+    },
+    DropUser {
+        name: String,
+        if_exists: bool,
+    },
+    /// Grants privileges on a table to a user.
+    Grant {
+        privileges: Vec<Privilege>,
+        table: String,
+        user: String,
+    },
+    /// Revokes privileges on a table from a user.
+    Revoke {
+        privileges: Vec<Privilege>,
+        table: String,
+        user: String,
     },
-    DropTable(String),
 
+    /// Inserts the rows of a CSV file on the server into a table. With header, the first line
+    /// gives the column names, otherwise it contains all non-generated columns in table order.
+    CopyFrom {
+        table: String,
+        path: String,
+        header: bool,
+        delimiter: char,
+    },
+    /// Writes the non-generated columns of a table's rows to a CSV file on the server, with a
+    /// first line of column names if header is true.
+    CopyTo {
+        table: String,
+        path: String,
+        header: bool,
+        delimiter: char,
+    },
     Delete {
         table: String,
+        /// Other tables joined with the target table, in a USING clause.
+        using: Vec<FromItem>,
         r#where: Option<Expression>,
+        /// A RETURNING clause, where an empty list means *.
+        returning: Option<Vec<(Expression, Option<String>)>>,
     },
     Insert {
         table: String,
         columns: Option<Vec<String>>,
-        values: Vec<Vec<Expression>>,
+        source: InsertSource,
+        on_conflict: Option<OnConflict>,
+        /// A RETURNING clause, where an empty list means *.
+        returning: Option<Vec<(Expression, Option<String>)>>,
+    },
+    /// Deletes all rows of a table.
+    Truncate {
+        table: String,
     },
     Update {
         table: String,
         set: BTreeMap<String, Expression>,
+        /// Other tables joined with the target table, in a FROM clause.
+        from: Vec<FromItem>,
         r#where: Option<Expression>,
+        /// A RETURNING clause, where an empty list means *.
+        returning: Option<Vec<(Expression, Option<String>)>>,
     },
 
     Select {
         select: Vec<(Expression, Option<String>)>,
+        /// A DISTINCT clause, if any. An empty list means DISTINCT on all result columns,
+        /// otherwise DISTINCT ON the given expressions.
+        distinct: Option<Vec<Expression>>,
         from: Vec<FromItem>,
         r#where: Option<Expression>,
         group_by: Vec<Expression>,
         having: Option<Expression>,
-        order: Vec<(Expression, Order)>,
+        order: Vec<(Expression, Order, Option<Nulls>)>,
         offset: Option<Expression>,
         limit: Option<Expression>,
     },
+
+    /// Combines the result rows of two queries with a set operator, discarding duplicate rows
+    /// unless all is true (e.g. UNION rather than UNION ALL). The ORDER BY, OFFSET, and LIMIT
+    /// clauses apply to the combined rows.
+    SetOperation {
+        operator: SetOperator,
+        left: Box<Statement>,
+        right: Box<Statement>,
+        all: bool,
+        order: Vec<(Expression, Order, Option<Nulls>)>,
+        offset: Option<Expression>,
+        limit: Option<Expression>,
+    },
+
+    /// A statement with a WITH clause, giving common table expressions (CTEs) that can be
+    /// referenced by name in the statement. With RECURSIVE, CTEs can reference themselves.
+    With {
+        recursive: bool,
+        ctes: Vec<Cte>,
+        statement: Box<Statement>,
+    },
+}
+
+impl Statement {
+    /// Returns true if the statement is a read-only query, i.e. a SELECT statement or set
+    /// operation with any WITH clause.
+    pub fn is_query(&self) -> bool {
+        match self {
+            Self::Select { .. }
+            | Self::SetOperation { .. }
+            | Self::ShowTables
+            | Self::ShowCreateTable { .. } => true,
+            Self::With { statement, .. } => statement.is_query(),
+            _ => false,
+        }
+    }
+}
+
+/// A common table expression in a WITH clause
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cte {
+    pub name: String,
+    /// Column aliases for the query result columns, if given.
+    pub columns: Option<Vec<String>>,
+    pub query: Statement,
+}
+
+/// The source rows of an INSERT statement
+#[derive(Clone, Debug, PartialEq)]
+pub enum InsertSource {
+    Values(Vec<Vec<Expression>>),
+    Select(Box<Statement>),
+}
+
+/// An INSERT ON CONFLICT clause, with the conflict target columns, if given
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnConflict {
+    pub columns: Option<Vec<String>>,
+    pub action: ConflictAction,
+}
+
+/// An ON CONFLICT action
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConflictAction {
+    Nothing,
+    Update(BTreeMap<String, Expression>),
+}
+
+/// An ALTER TABLE action
+#[derive(Clone, Debug, PartialEq)]
+pub enum AlterTable {
+    AddColumn { column: Column },
+    DropColumn { column: String },
+    RenameColumn { column: String, name: String },
+    RenameTable { name: String },
 }
 
 /// A FROM item
@@ -56,6 +232,11 @@ pub enum FromItem {
         name: String,
         alias: Option<String>,
     },
+    /// A read-only system table in the system schema, e.g. system.tables.
+    SystemTable {
+        name: String,
+        alias: Option<String>,
+    },
     Join {
         left: Box<FromItem>,
         right: Box<FromItem>,
@@ -71,6 +252,7 @@ pub enum JoinType {
     Inner,
     Left,
     Right,
+    Full,
 }
 
 /// A column
@@ -81,6 +263,8 @@ pub struct Column {
     pub primary_key: bool,
     pub nullable: Option<bool>,
     pub default: Option<Expression>,
+    /// The expression of a stored generated column, i.e. GENERATED ALWAYS AS (expr) STORED.
+    pub generated: Option<Expression>,
     pub unique: bool,
     pub index: bool,
     pub references: Option<String>,
@@ -93,6 +277,21 @@ pub enum Order {
     Descending,
 }
 
+/// Placement of NULLs in a sort order, i.e. NULLS FIRST or NULLS LAST
+#[derive(Clone, Debug, PartialEq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+/// Set operators
+#[derive(Clone, Debug, PartialEq)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
 /// Expressions
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
@@ -101,6 +300,24 @@ pub enum Expression {
     Literal(Literal),
     Function(String, Vec<Expression>),
     Operation(Operation),
+    /// CASE with an optional operand, WHEN/THEN branches, and an optional ELSE result.
+    Case(Option<Box<Expression>>, Vec<(Expression, Expression)>, Option<Box<Expression>>),
+    /// CAST of an expression to a datatype.
+    Cast(Box<Expression>, DataType),
+    /// TRY_CAST of an expression to a datatype, yielding NULL if the cast fails.
+    TryCast(Box<Expression>, DataType),
+    /// The argument of a DISTINCT aggregate function, e.g. COUNT(DISTINCT x).
+    Distinct(Box<Expression>),
+    /// An ORDER BY expression of an aggregate function, e.g. STRING_AGG(x, ',' ORDER BY y).
+    OrderBy(Box<Expression>, Order, Option<Nulls>),
+    /// A window function call with an OVER clause, as function name, arguments, PARTITION BY
+    /// expressions, and ORDER BY expressions.
+    Window(String, Vec<Expression>, Vec<Expression>, Vec<(Expression, Order, Option<Nulls>)>),
+    /// A scalar subquery, which must be a SELECT statement returning a single column.
+    Subquery(Box<Statement>),
+    /// A parameter placeholder with its 1-based parameter number, bound when executing a
+    /// prepared statement.
+    Parameter(usize),
 }
 
 impl From<Literal> for Expression {
@@ -123,6 +340,29 @@ pub enum Literal {
     Integer(i64),
     Float(f64),
     String(String),
+    Date(String),
+    Time(String),
+    Timestamp(String),
+    Decimal(String),
+    Bytes(Vec<u8>),
+}
+
+impl Literal {
+    /// Converts the literal to a value, parsing and validating temporal and decimal strings.
+    pub fn into_value(self) -> Result<Value> {
+        Ok(match self {
+            Self::Null => Value::Null,
+            Self::Boolean(b) => Value::Boolean(b),
+            Self::Integer(i) => Value::Integer(i),
+            Self::Float(f) => Value::Float(f),
+            Self::String(s) => Value::String(s),
+            Self::Date(s) => Value::String(s).cast(&DataType::Date)?,
+            Self::Time(s) => Value::String(s).cast(&DataType::Time)?,
+            Self::Timestamp(s) => Value::String(s).cast(&DataType::Timestamp)?,
+            Self::Decimal(s) => Value::Decimal(s.parse()?),
+            Self::Bytes(b) => Value::Bytes(b),
+        })
+    }
 }
 
 /// Operations (done by operators)
@@ -134,9 +374,17 @@ pub enum Operation {
     Or(Box<Expression>, Box<Expression>),
 
     // Comparison operators
+    /// BETWEEN with an operand, lower bound, and upper bound (inclusive).
+    Between(Box<Expression>, Box<Expression>, Box<Expression>),
     Equal(Box<Expression>, Box<Expression>),
+    /// EXISTS with a subquery, which must be a SELECT statement.
+    Exists(Box<Statement>),
     GreaterThan(Box<Expression>, Box<Expression>),
     GreaterThanOrEqual(Box<Expression>, Box<Expression>),
+    In(Box<Expression>, Vec<Expression>),
+    /// IN with an uncorrelated subquery, which must be a SELECT statement.
+    InSubquery(Box<Expression>, Box<Statement>),
+    IsDistinctFrom(Box<Expression>, Box<Expression>),
     IsNull(Box<Expression>),
     LessThan(Box<Expression>, Box<Expression>),
     LessThanOrEqual(Box<Expression>, Box<Expression>),
@@ -154,7 +402,8 @@ pub enum Operation {
     Subtract(Box<Expression>, Box<Expression>),
 
     // String operators
-    Like(Box<Expression>, Box<Expression>),
+    ILike(Box<Expression>, Box<Expression>, Option<char>),
+    Like(Box<Expression>, Box<Expression>, Option<char>),
 }
 
 impl Expression {
@@ -189,9 +438,11 @@ impl Expression {
             | Self::Operation(Exponentiate(lhs, rhs))
             | Self::Operation(GreaterThan(lhs, rhs))
             | Self::Operation(GreaterThanOrEqual(lhs, rhs))
+            | Self::Operation(ILike(lhs, rhs, _))
+            | Self::Operation(IsDistinctFrom(lhs, rhs))
             | Self::Operation(LessThan(lhs, rhs))
             | Self::Operation(LessThanOrEqual(lhs, rhs))
-            | Self::Operation(Like(lhs, rhs))
+            | Self::Operation(Like(lhs, rhs, _))
             | Self::Operation(Modulo(lhs, rhs))
             | Self::Operation(Multiply(lhs, rhs))
             | Self::Operation(NotEqual(lhs, rhs))
@@ -203,19 +454,64 @@ impl Expression {
 
             Self::Operation(Assert(expr))
             | Self::Operation(Factorial(expr))
+            | Self::Operation(InSubquery(expr, _))
             | Self::Operation(IsNull(expr))
             | Self::Operation(Negate(expr))
             | Self::Operation(Not(expr)) => {
                 Self::replace_with(expr, |e| e.transform(before, after))?
             }
 
+            Self::Operation(Between(expr, low, high)) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                Self::replace_with(low, |e| e.transform(before, after))?;
+                Self::replace_with(high, |e| e.transform(before, after))?;
+            }
+
+            Self::Operation(In(expr, list)) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                for item in list {
+                    Self::replace_with(item, |e| e.transform(before, after))?;
+                }
+            }
+
             Self::Function(_, exprs) => {
                 for expr in exprs {
                     Self::replace_with(expr, |e| e.transform(before, after))?;
                 }
             }
 
-            Self::Literal(_) | Self::Field(_, _) | Self::Column(_) => {}
+            Self::Cast(expr, _)
+            | Self::Distinct(expr)
+            | Self::OrderBy(expr, _, _)
+            | Self::TryCast(expr, _) => Self::replace_with(expr, |e| e.transform(before, after))?,
+
+            Self::Window(_, args, partition, order) => {
+                for expr in
+                    args.iter_mut().chain(partition).chain(order.iter_mut().map(|(e, _, _)| e))
+                {
+                    Self::replace_with(expr, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Case(operand, branches, r#else) => {
+                if let Some(operand) = operand {
+                    Self::replace_with(operand, |e| e.transform(before, after))?;
+                }
+                for (condition, result) in branches {
+                    Self::replace_with(condition, |e| e.transform(before, after))?;
+                    Self::replace_with(result, |e| e.transform(before, after))?;
+                }
+                if let Some(r#else) = r#else {
+                    Self::replace_with(r#else, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Operation(Exists(_))
+            | Self::Subquery(_)
+            | Self::Literal(_)
+            | Self::Field(_, _)
+            | Self::Column(_)
+            | Self::Parameter(_) => {}
         };
         after(self)
     }
@@ -241,9 +537,11 @@ impl Expression {
                 | Self::Operation(Exponentiate(lhs, rhs))
                 | Self::Operation(GreaterThan(lhs, rhs))
                 | Self::Operation(GreaterThanOrEqual(lhs, rhs))
+                | Self::Operation(ILike(lhs, rhs, _))
+                | Self::Operation(IsDistinctFrom(lhs, rhs))
                 | Self::Operation(LessThan(lhs, rhs))
                 | Self::Operation(LessThanOrEqual(lhs, rhs))
-                | Self::Operation(Like(lhs, rhs))
+                | Self::Operation(Like(lhs, rhs, _))
                 | Self::Operation(Modulo(lhs, rhs))
                 | Self::Operation(Multiply(lhs, rhs))
                 | Self::Operation(NotEqual(lhs, rhs))
@@ -252,10 +550,19 @@ impl Expression {
 
                 Self::Operation(Assert(expr))
                 | Self::Operation(Factorial(expr))
+                | Self::Operation(InSubquery(expr, _))
                 | Self::Operation(IsNull(expr))
                 | Self::Operation(Negate(expr))
                 | Self::Operation(Not(expr)) => expr.walk(visitor),
 
+                Self::Operation(Between(expr, low, high)) => {
+                    expr.walk(visitor) && low.walk(visitor) && high.walk(visitor)
+                }
+
+                Self::Operation(In(expr, list)) => {
+                    expr.walk(visitor) && list.iter().all(|item| item.walk(visitor))
+                }
+
                 Self::Function(_, exprs) => {
                     for expr in exprs {
                         if !expr.walk(visitor) {
@@ -265,7 +572,31 @@ impl Expression {
                     true
                 }
 
-                Self::Literal(_) | Self::Field(_, _) | Self::Column(_) => true,
+                Self::Cast(expr, _)
+                | Self::Distinct(expr)
+                | Self::OrderBy(expr, _, _)
+                | Self::TryCast(expr, _) => expr.walk(visitor),
+
+                Self::Window(_, args, partition, order) => args
+                    .iter()
+                    .chain(partition)
+                    .chain(order.iter().map(|(e, _, _)| e))
+                    .all(|expr| expr.walk(visitor)),
+
+                Self::Case(operand, branches, r#else) => {
+                    operand.iter().all(|operand| operand.walk(visitor))
+                        && branches.iter().all(|(condition, result)| {
+                            condition.walk(visitor) && result.walk(visitor)
+                        })
+                        && r#else.iter().all(|r#else| r#else.walk(visitor))
+                }
+
+                Self::Operation(Exists(_))
+                | Self::Subquery(_)
+                | Self::Literal(_)
+                | Self::Field(_, _)
+                | Self::Column(_)
+                | Self::Parameter(_) => true,
             }
     }
 }
@@ -8,7 +8,10 @@ use std::str::Chars;
 pub enum Token {
     Number(String),
     String(String),
+    HexString(String),
     Ident(String),
+    /// A numbered parameter placeholder, e.g. $1.
+    Parameter(String),
     Keyword(Keyword),
     Period,
     Equal,
@@ -34,10 +37,15 @@ pub enum Token {
 
 impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Token::Parameter(n) = self {
+            return write!(f, "${}", n);
+        }
         f.write_str(match self {
             Token::Number(n) => n,
             Token::String(s) => s,
+            Token::HexString(s) => s,
             Token::Ident(s) => s,
+            Token::Parameter(_) => unreachable!(),
             Token::Keyword(k) => k.to_str(),
             Token::Period => ".",
             Token::Equal => "=",
@@ -72,44 +80,78 @@ impl From<Keyword> for Token {
 /// Lexer keywords
 #[derive(Clone, Debug, PartialEq)]
 pub enum Keyword {
+    Add,
+    All,
+    Alter,
+    Always,
+    Analyze,
     And,
     As,
     Asc,
     Begin,
+    Between,
+    Blob,
     Bool,
     Boolean,
     By,
+    Bytea,
+    Case,
+    Cast,
     Char,
+    Column,
     Commit,
+    Conflict,
+    Copy,
     Create,
     Cross,
+    CurrentTimestamp,
+    Date,
+    Decimal,
     Default,
     Delete,
     Desc,
+    Distinct,
+    Do,
     Double,
     Drop,
+    Else,
+    End,
+    Escape,
+    Except,
+    Exists,
     Explain,
     False,
     Float,
+    For,
     From,
+    Full,
+    Generated,
+    Grant,
     Group,
     Having,
+    If,
+    ILike,
+    In,
     Index,
     Infinity,
     Inner,
     Insert,
     Int,
     Integer,
+    Intersect,
     Into,
     Is,
+    Isolation,
     Join,
     Key,
     Left,
+    Level,
     Like,
     Limit,
     NaN,
     Not,
     Null,
+    Numeric,
     Of,
     Offset,
     On,
@@ -117,25 +159,49 @@ pub enum Keyword {
     Or,
     Order,
     Outer,
+    Over,
+    Partition,
     Primary,
+    Privileges,
     Read,
+    Recursive,
     References,
+    Rename,
+    Returning,
+    Revoke,
     Right,
     Rollback,
     Select,
+    Serializable,
     Set,
+    Show,
+    Snapshot,
+    Stored,
     String,
+    Superuser,
     System,
     Table,
     Text,
+    Then,
     Time,
+    Timestamp,
+    To,
     Transaction,
     True,
+    Truncate,
+    TryCast,
+    Union,
     Unique,
     Update,
+    User,
+    Using,
+    Vacuum,
     Values,
     Varchar,
+    View,
+    When,
     Where,
+    With,
     Write,
 }
 
@@ -143,44 +209,78 @@ impl Keyword {
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(ident: &str) -> Option<Self> {
         Some(match ident.to_uppercase().as_ref() {
+            "ADD" => Self::Add,
+            "ALL" => Self::All,
+            "ALTER" => Self::Alter,
+            "ALWAYS" => Self::Always,
+            "ANALYZE" => Self::Analyze,
             "AS" => Self::As,
             "ASC" => Self::Asc,
             "AND" => Self::And,
             "BEGIN" => Self::Begin,
+            "BETWEEN" => Self::Between,
+            "BLOB" => Self::Blob,
             "BOOL" => Self::Bool,
             "BOOLEAN" => Self::Boolean,
             "BY" => Self::By,
+            "BYTEA" => Self::Bytea,
+            "CASE" => Self::Case,
+            "CAST" => Self::Cast,
             "CHAR" => Self::Char,
+            "COLUMN" => Self::Column,
             "COMMIT" => Self::Commit,
+            "CONFLICT" => Self::Conflict,
+            "COPY" => Self::Copy,
             "CREATE" => Self::Create,
             "CROSS" => Self::Cross,
+            "CURRENT_TIMESTAMP" => Self::CurrentTimestamp,
+            "DATE" => Self::Date,
+            "DECIMAL" => Self::Decimal,
             "DEFAULT" => Self::Default,
             "DELETE" => Self::Delete,
             "DESC" => Self::Desc,
+            "DISTINCT" => Self::Distinct,
+            "DO" => Self::Do,
             "DOUBLE" => Self::Double,
             "DROP" => Self::Drop,
+            "ELSE" => Self::Else,
+            "END" => Self::End,
+            "ESCAPE" => Self::Escape,
+            "EXCEPT" => Self::Except,
+            "EXISTS" => Self::Exists,
             "EXPLAIN" => Self::Explain,
             "FALSE" => Self::False,
             "FLOAT" => Self::Float,
+            "FOR" => Self::For,
             "FROM" => Self::From,
+            "FULL" => Self::Full,
+            "GENERATED" => Self::Generated,
+            "GRANT" => Self::Grant,
             "GROUP" => Self::Group,
             "HAVING" => Self::Having,
+            "IF" => Self::If,
+            "ILIKE" => Self::ILike,
+            "IN" => Self::In,
             "INDEX" => Self::Index,
             "INFINITY" => Self::Infinity,
             "INNER" => Self::Inner,
             "INSERT" => Self::Insert,
             "INT" => Self::Int,
             "INTEGER" => Self::Integer,
+            "INTERSECT" => Self::Intersect,
             "INTO" => Self::Into,
             "IS" => Self::Is,
+            "ISOLATION" => Self::Isolation,
             "JOIN" => Self::Join,
             "KEY" => Self::Key,
             "LEFT" => Self::Left,
+            "LEVEL" => Self::Level,
             "LIKE" => Self::Like,
             "LIMIT" => Self::Limit,
             "NAN" => Self::NaN,
             "NOT" => Self::Not,
             "NULL" => Self::Null,
+            "NUMERIC" => Self::Numeric,
             "OF" => Self::Of,
             "OFFSET" => Self::Offset,
             "ON" => Self::On,
@@ -188,25 +288,49 @@ impl Keyword {
             "OR" => Self::Or,
             "ORDER" => Self::Order,
             "OUTER" => Self::Outer,
+            "OVER" => Self::Over,
+            "PARTITION" => Self::Partition,
             "PRIMARY" => Self::Primary,
+            "PRIVILEGES" => Self::Privileges,
             "READ" => Self::Read,
+            "RECURSIVE" => Self::Recursive,
             "REFERENCES" => Self::References,
+            "RENAME" => Self::Rename,
+            "RETURNING" => Self::Returning,
+            "REVOKE" => Self::Revoke,
             "RIGHT" => Self::Right,
             "ROLLBACK" => Self::Rollback,
             "SELECT" => Self::Select,
+            "SERIALIZABLE" => Self::Serializable,
             "SET" => Self::Set,
+            "SHOW" => Self::Show,
+            "SNAPSHOT" => Self::Snapshot,
+            "STORED" => Self::Stored,
             "STRING" => Self::String,
+            "SUPERUSER" => Self::Superuser,
             "SYSTEM" => Self::System,
             "TABLE" => Self::Table,
             "TEXT" => Self::Text,
+            "THEN" => Self::Then,
             "TIME" => Self::Time,
+            "TIMESTAMP" => Self::Timestamp,
+            "TO" => Self::To,
             "TRANSACTION" => Self::Transaction,
             "TRUE" => Self::True,
+            "TRUNCATE" => Self::Truncate,
+            "TRY_CAST" => Self::TryCast,
+            "UNION" => Self::Union,
             "UNIQUE" => Self::Unique,
             "UPDATE" => Self::Update,
+            "USER" => Self::User,
+            "USING" => Self::Using,
+            "VACUUM" => Self::Vacuum,
             "VALUES" => Self::Values,
             "VARCHAR" => Self::Varchar,
+            "VIEW" => Self::View,
+            "WHEN" => Self::When,
             "WHERE" => Self::Where,
+            "WITH" => Self::With,
             "WRITE" => Self::Write,
             _ => return None,
         })
@@ -214,44 +338,78 @@ impl Keyword {
 
     pub fn to_str(&self) -> &str {
         match self {
+            Self::Add => "ADD",
+            Self::All => "ALL",
+            Self::Alter => "ALTER",
+            Self::Always => "ALWAYS",
+            Self::Analyze => "ANALYZE",
             Self::As => "AS",
             Self::Asc => "ASC",
             Self::And => "AND",
             Self::Begin => "BEGIN",
+            Self::Between => "BETWEEN",
+            Self::Blob => "BLOB",
             Self::Bool => "BOOL",
             Self::Boolean => "BOOLEAN",
             Self::By => "BY",
+            Self::Bytea => "BYTEA",
+            Self::Case => "CASE",
+            Self::Cast => "CAST",
             Self::Char => "CHAR",
+            Self::Column => "COLUMN",
             Self::Commit => "COMMIT",
+            Self::Conflict => "CONFLICT",
+            Self::Copy => "COPY",
             Self::Create => "CREATE",
             Self::Cross => "CROSS",
+            Self::CurrentTimestamp => "CURRENT_TIMESTAMP",
+            Self::Date => "DATE",
+            Self::Decimal => "DECIMAL",
             Self::Default => "DEFAULT",
             Self::Delete => "DELETE",
             Self::Desc => "DESC",
+            Self::Distinct => "DISTINCT",
+            Self::Do => "DO",
             Self::Double => "DOUBLE",
             Self::Drop => "DROP",
+            Self::Else => "ELSE",
+            Self::End => "END",
+            Self::Escape => "ESCAPE",
+            Self::Except => "EXCEPT",
+            Self::Exists => "EXISTS",
             Self::Explain => "EXPLAIN",
             Self::False => "FALSE",
             Self::Float => "FLOAT",
+            Self::For => "FOR",
             Self::From => "FROM",
+            Self::Full => "FULL",
+            Self::Generated => "GENERATED",
+            Self::Grant => "GRANT",
             Self::Group => "GROUP",
             Self::Having => "HAVING",
+            Self::If => "IF",
+            Self::ILike => "ILIKE",
+            Self::In => "IN",
             Self::Index => "INDEX",
             Self::Infinity => "INFINITY",
             Self::Inner => "INNER",
             Self::Insert => "INSERT",
             Self::Int => "INT",
             Self::Integer => "INTEGER",
+            Self::Intersect => "INTERSECT",
             Self::Into => "INTO",
             Self::Is => "IS",
+            Self::Isolation => "ISOLATION",
             Self::Join => "JOIN",
             Self::Key => "KEY",
             Self::Left => "LEFT",
+            Self::Level => "LEVEL",
             Self::Like => "LIKE",
             Self::Limit => "LIMIT",
             Self::NaN => "NAN",
             Self::Not => "NOT",
             Self::Null => "NULL",
+            Self::Numeric => "NUMERIC",
             Self::Of => "OF",
             Self::Offset => "OFFSET",
             Self::On => "ON",
@@ -259,25 +417,49 @@ impl Keyword {
             Self::Outer => "OUTER",
             Self::Or => "OR",
             Self::Order => "ORDER",
+            Self::Over => "OVER",
+            Self::Partition => "PARTITION",
             Self::Primary => "PRIMARY",
+            Self::Privileges => "PRIVILEGES",
             Self::Read => "READ",
+            Self::Recursive => "RECURSIVE",
             Self::References => "REFERENCES",
+            Self::Rename => "RENAME",
+            Self::Returning => "RETURNING",
+            Self::Revoke => "REVOKE",
             Self::Right => "RIGHT",
             Self::Rollback => "ROLLBACK",
             Self::Select => "SELECT",
+            Self::Serializable => "SERIALIZABLE",
             Self::Set => "SET",
+            Self::Show => "SHOW",
+            Self::Snapshot => "SNAPSHOT",
+            Self::Stored => "STORED",
             Self::String => "STRING",
+            Self::Superuser => "SUPERUSER",
             Self::System => "SYSTEM",
             Self::Table => "TABLE",
             Self::Text => "TEXT",
+            Self::Then => "THEN",
             Self::Time => "TIME",
+            Self::Timestamp => "TIMESTAMP",
+            Self::To => "TO",
             Self::Transaction => "TRANSACTION",
             Self::True => "TRUE",
+            Self::Truncate => "TRUNCATE",
+            Self::TryCast => "TRY_CAST",
+            Self::Union => "UNION",
             Self::Unique => "UNIQUE",
             Self::Update => "UPDATE",
+            Self::User => "USER",
+            Self::Using => "USING",
+            Self::Vacuum => "VACUUM",
             Self::Values => "VALUES",
             Self::Varchar => "VARCHAR",
+            Self::View => "VIEW",
+            Self::When => "WHEN",
             Self::Where => "WHERE",
+            Self::With => "WITH",
             Self::Write => "WRITE",
         }
     }
@@ -290,8 +472,11 @@ impl std::fmt::Display for Keyword {
 }
 
 /// A lexer tokenizes an input string as an iterator
+#[derive(Clone)]
 pub struct Lexer<'a> {
     iter: Peekable<Chars<'a>>,
+    /// The byte offset of the next character in the input string.
+    offset: usize,
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -313,7 +498,19 @@ impl<'a> Lexer<'a> {
     /// Creates a new lexer for the given input string
     #[allow(dead_code)]
     pub fn new(input: &'a str) -> Lexer<'a> {
-        Lexer { iter: input.chars().peekable() }
+        Lexer { iter: input.chars().peekable(), offset: 0 }
+    }
+
+    /// Returns the byte offset in the input string following the last scanned token.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Grabs the next character, if any
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.iter.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
     }
 
     /// Consumes any whitespace characters
@@ -324,13 +521,13 @@ impl<'a> Lexer<'a> {
     /// Grabs the next character if it matches the predicate function
     fn next_if<F: Fn(char) -> bool>(&mut self, predicate: F) -> Option<char> {
         self.iter.peek().filter(|&c| predicate(*c))?;
-        self.iter.next()
+        self.next_char()
     }
 
     /// Grabs the next single-character token if the tokenizer function returns one
     fn next_if_token<F: Fn(char) -> Option<Token>>(&mut self, tokenizer: F) -> Option<Token> {
         let token = self.iter.peek().and_then(|&c| tokenizer(c))?;
-        self.iter.next();
+        self.next_char();
         Some(token)
     }
 
@@ -350,21 +547,47 @@ impl<'a> Lexer<'a> {
             Some('\'') => self.scan_string(),
             Some('"') => self.scan_ident_quoted(),
             Some(c) if c.is_digit(10) => Ok(self.scan_number()),
-            Some(c) if c.is_alphabetic() => Ok(self.scan_ident()),
+            Some(c) if c.is_alphabetic() => self.scan_ident(),
+            Some('$') => self.scan_parameter(),
             Some(_) => Ok(self.scan_symbol()),
             None => Ok(None),
         }
     }
 
-    /// Scans the input for the next ident or keyword token, if any
-    fn scan_ident(&mut self) -> Option<Token> {
-        let mut name = self.next_if(|c| c.is_alphabetic())?.to_string();
+    /// Scans the input for the next numbered parameter placeholder, e.g. $1.
+    fn scan_parameter(&mut self) -> Result<Option<Token>> {
+        if self.next_if(|c| c == '$').is_none() {
+            return Ok(None);
+        }
+        let mut n = String::new();
+        while let Some(c) = self.next_if(|c| c.is_digit(10)) {
+            n.push(c)
+        }
+        if n.is_empty() {
+            return Err(Error::Parse("Expected parameter number after $".into()));
+        }
+        Ok(Some(Token::Parameter(n)))
+    }
+
+    /// Scans the input for the next ident or keyword token, if any. An x immediately followed by
+    /// a string literal is a hexadecimal string, e.g. x'DEADBEEF'.
+    fn scan_ident(&mut self) -> Result<Option<Token>> {
+        let mut name = match self.next_if(|c| c.is_alphabetic()) {
+            Some(c) => c.to_string(),
+            None => return Ok(None),
+        };
+        if (name == "x" || name == "X") && self.iter.peek() == Some(&'\'') {
+            return match self.scan_string()? {
+                Some(Token::String(s)) => Ok(Some(Token::HexString(s))),
+                token => Ok(token),
+            };
+        }
         while let Some(c) = self.next_if(|c| c.is_alphanumeric() || c == '_') {
             name.push(c)
         }
-        Keyword::from_str(&name)
+        Ok(Keyword::from_str(&name)
             .map(Token::Keyword)
-            .or_else(|| Some(Token::Ident(name.to_lowercase())))
+            .or_else(|| Some(Token::Ident(name.to_lowercase()))))
     }
 
     /// Scans the input for the next quoted ident, if any
@@ -374,7 +597,7 @@ impl<'a> Lexer<'a> {
         }
         let mut ident = String::new();
         loop {
-            match self.iter.next() {
+            match self.next_char() {
                 Some('"') if self.next_if(|c| c == '"').is_some() => ident.push('"'),
                 Some('"') => break,
                 Some(c) => ident.push(c),
@@ -412,7 +635,7 @@ impl<'a> Lexer<'a> {
         }
         let mut s = String::new();
         loop {
-            match self.iter.next() {
+            match self.next_char() {
                 Some('\'') if self.next_if(|c| c == '\'').is_some() => s.push('\''),
                 Some('\'') => break,
                 Some(c) => s.push(c),
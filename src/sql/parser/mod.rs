@@ -2,7 +2,8 @@ pub mod ast;
 mod lexer;
 pub use lexer::{Keyword, Lexer, Token};
 
-use super::types::DataType;
+use super::schema::Privilege;
+use super::types::{DataType, Value, DECIMAL_MAX_PRECISION};
 use crate::error::{Error, Result};
 
 use lazy_static::lazy_static;
@@ -11,13 +12,33 @@ use std::collections::BTreeMap;
 
 /// An SQL parser
 pub struct Parser<'a> {
+    input: &'a str,
     lexer: std::iter::Peekable<Lexer<'a>>,
+    /// The number of tokens consumed so far.
+    consumed: usize,
+    /// The number of ? parameter placeholders parsed so far, which are numbered in order.
+    placeholders: usize,
+    /// The highest parameter number parsed so far.
+    parameters: usize,
 }
 
 impl<'a> Parser<'a> {
     /// Creates a new parser for the given string input
-    pub fn new(query: &str) -> Parser {
-        Parser { lexer: Lexer::new(query).peekable() }
+    pub fn new(query: &'a str) -> Parser<'a> {
+        Parser {
+            input: query,
+            lexer: Lexer::new(query).peekable(),
+            consumed: 0,
+            placeholders: 0,
+            parameters: 0,
+        }
+    }
+
+    /// Returns the number of parameters of the parsed statement, i.e. the highest parameter
+    /// number used by a placeholder. Parameters are given either as $1, $2, etc or as ?, which is
+    /// numbered by its position among the ? placeholders.
+    pub fn parameters(&self) -> usize {
+        self.parameters
     }
 
     /// Parses the input string into an AST statement
@@ -30,7 +51,26 @@ impl<'a> Parser<'a> {
 
     /// Grabs the next lexer token, or throws an error if none is found.
     fn next(&mut self) -> Result<Token> {
-        self.lexer.next().unwrap_or_else(|| Err(Error::Parse("Unexpected end of input".into())))
+        let token = self
+            .lexer
+            .next()
+            .unwrap_or_else(|| Err(Error::Parse("Unexpected end of input".into())))?;
+        self.consumed += 1;
+        Ok(token)
+    }
+
+    /// Returns the input text of the consumed tokens in the given range, by scanning the input
+    /// again up to the end of the range.
+    fn input_span(&self, start: usize, end: usize) -> Result<&'a str> {
+        let mut lexer = Lexer::new(self.input);
+        for _ in 0..start {
+            lexer.next().transpose()?;
+        }
+        let from = lexer.offset();
+        for _ in start..end {
+            lexer.next().transpose()?;
+        }
+        Ok(self.input[from..lexer.offset()].trim())
     }
 
     /// Grabs the next lexer token, and returns it if it was expected or
@@ -58,6 +98,22 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Grabs the next string literal, or throws an error if none is found.
+    fn next_string(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::String(s) => Ok(s),
+            token => Err(Error::Parse(format!("Expected string, got {}", token))),
+        }
+    }
+
+    /// Grabs the next number literal as a u8, or throws an error if none is found.
+    fn next_u8(&mut self) -> Result<u8> {
+        match self.next()? {
+            Token::Number(n) => Ok(n.parse()?),
+            token => Err(Error::Parse(format!("Expected number, got {}", token))),
+        }
+    }
+
     /// Grabs the next lexer token if it satisfies the predicate function
     fn next_if<F: Fn(&Token) -> bool>(&mut self, predicate: F) -> Option<Token> {
         self.peek().unwrap_or(None).filter(|t| predicate(&t))?;
@@ -72,6 +128,13 @@ impl<'a> Parser<'a> {
             .and_then(|token| O::from(&token))
             .filter(|op| op.prec() >= min_prec)
         {
+            let lookahead = operator.lookahead();
+            if !lookahead.is_empty() {
+                match self.peek_second()? {
+                    Some(token) if lookahead.contains(&token) => {}
+                    _ => return Ok(None),
+                }
+            }
             self.next()?;
             Ok(Some(operator.augment(self)?))
         } else {
@@ -99,6 +162,14 @@ impl<'a> Parser<'a> {
         self.lexer.peek().cloned().transpose()
     }
 
+    /// Peeks the lexer token following the next one, if any. This clones the lexer, and should
+    /// only be used where a single token of lookahead is insufficient.
+    fn peek_second(&mut self) -> Result<Option<Token>> {
+        let mut lexer = self.lexer.clone();
+        lexer.next();
+        lexer.next().transpose()
+    }
+
     /// Parses an SQL statement
     fn parse_statement(&mut self) -> Result<ast::Statement> {
         match self.peek()? {
@@ -106,15 +177,26 @@ impl<'a> Parser<'a> {
             Some(Token::Keyword(Keyword::Commit)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Rollback)) => self.parse_transaction(),
 
+            Some(Token::Keyword(Keyword::Alter)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Drop)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Grant)) => self.parse_statement_grant(),
+            Some(Token::Keyword(Keyword::Revoke)) => self.parse_statement_grant(),
 
+            Some(Token::Keyword(Keyword::Copy)) => self.parse_statement_copy(),
             Some(Token::Keyword(Keyword::Delete)) => self.parse_statement_delete(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_statement_insert(),
             Some(Token::Keyword(Keyword::Select)) => self.parse_statement_select(),
+            Some(Token::Keyword(Keyword::Truncate)) => self.parse_statement_truncate(),
             Some(Token::Keyword(Keyword::Update)) => self.parse_statement_update(),
+            Some(Token::Keyword(Keyword::With)) => self.parse_statement_with(),
 
+            Some(Token::Keyword(Keyword::Analyze)) => self.parse_statement_analyze(),
             Some(Token::Keyword(Keyword::Explain)) => self.parse_statement_explain(),
+            Some(Token::Keyword(Keyword::Vacuum)) => self.parse_statement_vacuum(),
+
+            Some(Token::Keyword(Keyword::Set)) => self.parse_statement_set(),
+            Some(Token::Keyword(Keyword::Show)) => self.parse_statement_show(),
 
             Some(token) => Err(Error::Parse(format!("Unexpected token {}", token))),
             None => Err(Error::Parse("Unexpected end of input".into())),
@@ -124,61 +206,193 @@ impl<'a> Parser<'a> {
     /// Parses a DDL statement
     fn parse_ddl(&mut self) -> Result<ast::Statement> {
         match self.next()? {
+            Token::Keyword(Keyword::Alter) => match self.next()? {
+                Token::Keyword(Keyword::Table) => self.parse_ddl_alter_table(),
+                token => Err(Error::Parse(format!("Unexpected token {}", token))),
+            },
             Token::Keyword(Keyword::Create) => match self.next()? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(),
+                Token::Keyword(Keyword::View) => self.parse_ddl_create_view(),
+                Token::Keyword(Keyword::User) => self.parse_ddl_create_user(),
                 token => Err(Error::Parse(format!("Unexpected token {}", token))),
             },
             Token::Keyword(Keyword::Drop) => match self.next()? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_drop_table(),
+                Token::Keyword(Keyword::View) => self.parse_ddl_drop_view(),
+                Token::Keyword(Keyword::User) => self.parse_ddl_drop_user(),
                 token => Err(Error::Parse(format!("Unexpected token {}", token))),
             },
             token => Err(Error::Parse(format!("Unexpected token {}", token))),
         }
     }
 
+    /// Parses an ALTER TABLE DDL statement. The ALTER TABLE prefix has
+    /// already been consumed.
+    fn parse_ddl_alter_table(&mut self) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        let action = match self.next()? {
+            Token::Keyword(Keyword::Add) => {
+                self.next_if_token(Keyword::Column.into());
+                ast::AlterTable::AddColumn { column: self.parse_ddl_columnspec()? }
+            }
+            Token::Keyword(Keyword::Drop) => {
+                self.next_if_token(Keyword::Column.into());
+                ast::AlterTable::DropColumn { column: self.next_ident()? }
+            }
+            Token::Keyword(Keyword::Rename) => match self.next_if_token(Keyword::To.into()) {
+                Some(_) => ast::AlterTable::RenameTable { name: self.next_ident()? },
+                None => {
+                    self.next_if_token(Keyword::Column.into());
+                    let column = self.next_ident()?;
+                    self.next_expect(Some(Keyword::To.into()))?;
+                    ast::AlterTable::RenameColumn { column, name: self.next_ident()? }
+                }
+            },
+            token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+        };
+        Ok(ast::Statement::AlterTable { name, action })
+    }
+
     /// Parses a CREATE TABLE DDL statement. The CREATE TABLE prefix has
     /// already been consumed.
     fn parse_ddl_create_table(&mut self) -> Result<ast::Statement> {
+        let if_not_exists = self.next_if_token(Keyword::If.into()).is_some();
+        if if_not_exists {
+            self.next_expect(Some(Keyword::Not.into()))?;
+            self.next_expect(Some(Keyword::Exists.into()))?;
+        }
         let name = self.next_ident()?;
         self.next_expect(Some(Token::OpenParen))?;
 
+        let mut columns = Vec::new();
+        let mut primary_key = None;
+        loop {
+            if self.next_if_token(Keyword::Primary.into()).is_some() {
+                if primary_key.is_some() {
+                    return Err(Error::Value(format!("Multiple primary keys in table {}", name)));
+                }
+                primary_key = Some(self.parse_ddl_primary_key()?);
+            } else {
+                columns.push(self.parse_ddl_columnspec()?);
+            }
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(ast::Statement::CreateTable { name, columns, primary_key, if_not_exists })
+    }
+
+    /// Parses a table-level PRIMARY KEY constraint, e.g. PRIMARY KEY (a, b). The PRIMARY keyword
+    /// has already been consumed.
+    fn parse_ddl_primary_key(&mut self) -> Result<Vec<String>> {
+        self.next_expect(Some(Keyword::Key.into()))?;
+        self.next_expect(Some(Token::OpenParen))?;
         let mut columns = Vec::new();
         loop {
-            columns.push(self.parse_ddl_columnspec()?);
+            columns.push(self.next_ident()?);
             if self.next_if_token(Token::Comma).is_none() {
                 break;
             }
         }
         self.next_expect(Some(Token::CloseParen))?;
-        Ok(ast::Statement::CreateTable { name, columns })
+        Ok(columns)
     }
 
     /// Parses a DROP TABLE DDL statement. The DROP TABLE prefix has
     /// already been consumed.
     fn parse_ddl_drop_table(&mut self) -> Result<ast::Statement> {
-        Ok(ast::Statement::DropTable(self.next_ident()?))
+        let if_exists = self.next_if_token(Keyword::If.into()).is_some();
+        if if_exists {
+            self.next_expect(Some(Keyword::Exists.into()))?;
+        }
+        Ok(ast::Statement::DropTable { name: self.next_ident()?, if_exists })
+    }
+
+    /// Parses a CREATE VIEW DDL statement. The CREATE VIEW prefix has already been consumed.
+    fn parse_ddl_create_view(&mut self) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        self.next_expect(Some(Keyword::As.into()))?;
+        let start = self.consumed;
+        if !self.parse_statement()?.is_query() {
+            return Err(Error::Parse(format!("View {} must be defined by a query", name)));
+        }
+        let query = self.input_span(start, self.consumed)?.to_string();
+        Ok(ast::Statement::CreateView { name, query })
+    }
+
+    /// Parses a DROP VIEW DDL statement. The DROP VIEW prefix has already been consumed.
+    fn parse_ddl_drop_view(&mut self) -> Result<ast::Statement> {
+        let if_exists = self.next_if_token(Keyword::If.into()).is_some();
+        if if_exists {
+            self.next_expect(Some(Keyword::Exists.into()))?;
+        }
+        Ok(ast::Statement::DropView { name: self.next_ident()?, if_exists })
+    }
+
+    /// Parses a CREATE USER statement. The CREATE USER prefix has already been consumed.
+    fn parse_ddl_create_user(&mut self) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        let superuser = self.next_if_token(Keyword::Superuser.into()).is_some();
+        Ok(ast::Statement::CreateUser { name, superuser })
+    }
+
+    /// Parses a DROP USER statement. The DROP USER prefix has already been consumed.
+    fn parse_ddl_drop_user(&mut self) -> Result<ast::Statement> {
+        let if_exists = self.next_if_token(Keyword::If.into()).is_some();
+        if if_exists {
+            self.next_expect(Some(Keyword::Exists.into()))?;
+        }
+        Ok(ast::Statement::DropUser { name: self.next_ident()?, if_exists })
+    }
+
+    /// Parses the optional precision and scale of a DECIMAL datatype, i.e. (precision[, scale]).
+    /// The precision defaults to the maximum precision, and the scale to 0.
+    fn parse_ddl_decimal(&mut self) -> Result<DataType> {
+        let (mut precision, mut scale) = (DECIMAL_MAX_PRECISION, 0);
+        if self.next_if_token(Token::OpenParen).is_some() {
+            precision = self.next_u8()?;
+            if self.next_if_token(Token::Comma).is_some() {
+                scale = self.next_u8()?;
+            }
+            self.next_expect(Some(Token::CloseParen))?;
+        }
+        Ok(DataType::Decimal(precision, scale))
+    }
+
+    /// Parses a datatype, e.g. in a column specification or CAST expression.
+    fn parse_datatype(&mut self) -> Result<DataType> {
+        Ok(match self.next()? {
+            Token::Keyword(Keyword::Blob) => DataType::Bytes,
+            Token::Keyword(Keyword::Bool) => DataType::Boolean,
+            Token::Keyword(Keyword::Boolean) => DataType::Boolean,
+            Token::Keyword(Keyword::Bytea) => DataType::Bytes,
+            Token::Keyword(Keyword::Char) => DataType::String,
+            Token::Keyword(Keyword::Date) => DataType::Date,
+            Token::Keyword(Keyword::Decimal) => self.parse_ddl_decimal()?,
+            Token::Keyword(Keyword::Double) => DataType::Float,
+            Token::Keyword(Keyword::Float) => DataType::Float,
+            Token::Keyword(Keyword::Int) => DataType::Integer,
+            Token::Keyword(Keyword::Integer) => DataType::Integer,
+            Token::Keyword(Keyword::Numeric) => self.parse_ddl_decimal()?,
+            Token::Keyword(Keyword::String) => DataType::String,
+            Token::Keyword(Keyword::Text) => DataType::String,
+            Token::Keyword(Keyword::Time) => DataType::Time,
+            Token::Keyword(Keyword::Timestamp) => DataType::Timestamp,
+            Token::Keyword(Keyword::Varchar) => DataType::String,
+            token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+        })
     }
 
     /// Parses a column specification
     fn parse_ddl_columnspec(&mut self) -> Result<ast::Column> {
         let mut column = ast::Column {
             name: self.next_ident()?,
-            datatype: match self.next()? {
-                Token::Keyword(Keyword::Bool) => DataType::Boolean,
-                Token::Keyword(Keyword::Boolean) => DataType::Boolean,
-                Token::Keyword(Keyword::Char) => DataType::String,
-                Token::Keyword(Keyword::Double) => DataType::Float,
-                Token::Keyword(Keyword::Float) => DataType::Float,
-                Token::Keyword(Keyword::Int) => DataType::Integer,
-                Token::Keyword(Keyword::Integer) => DataType::Integer,
-                Token::Keyword(Keyword::String) => DataType::String,
-                Token::Keyword(Keyword::Text) => DataType::String,
-                Token::Keyword(Keyword::Varchar) => DataType::String,
-                token => return Err(Error::Parse(format!("Unexpected token {}", token))),
-            },
+            datatype: self.parse_datatype()?,
             primary_key: false,
             nullable: None,
             default: None,
+            generated: None,
             unique: false,
             index: false,
             references: None,
@@ -209,6 +423,14 @@ impl<'a> Parser<'a> {
                     column.nullable = Some(false)
                 }
                 Keyword::Default => column.default = Some(self.parse_expression(0)?),
+                Keyword::Generated => {
+                    self.next_expect(Some(Keyword::Always.into()))?;
+                    self.next_expect(Some(Keyword::As.into()))?;
+                    self.next_expect(Some(Token::OpenParen))?;
+                    column.generated = Some(self.parse_expression(0)?);
+                    self.next_expect(Some(Token::CloseParen))?;
+                    self.next_expect(Some(Keyword::Stored.into()))?;
+                }
                 Keyword::Unique => column.unique = true,
                 Keyword::Index => column.index = true,
                 Keyword::References => column.references = Some(self.next_ident()?),
@@ -223,16 +445,34 @@ impl<'a> Parser<'a> {
         self.next_expect(Some(Keyword::Delete.into()))?;
         self.next_expect(Some(Keyword::From.into()))?;
         let table = self.next_ident()?;
-        Ok(ast::Statement::Delete { table, r#where: self.parse_clause_where()? })
+        let using = self.parse_clause_from(Keyword::Using)?;
+        let r#where = self.parse_clause_where()?;
+        Ok(ast::Statement::Delete {
+            table,
+            using,
+            r#where,
+            returning: self.parse_clause_returning()?,
+        })
     }
 
-    /// Parses a delete statement
+    /// Parses an analyze statement
+    fn parse_statement_analyze(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Some(Keyword::Analyze.into()))?;
+        let table = match self.peek()? {
+            Some(Token::Ident(_)) => Some(self.next_ident()?),
+            _ => None,
+        };
+        Ok(ast::Statement::Analyze { table })
+    }
+
+    /// Parses an explain statement
     fn parse_statement_explain(&mut self) -> Result<ast::Statement> {
         self.next_expect(Some(Keyword::Explain.into()))?;
+        let analyze = self.next_if_token(Keyword::Analyze.into()).is_some();
         if let Some(Token::Keyword(Keyword::Explain)) = self.peek()? {
             return Err(Error::Parse("Cannot nest EXPLAIN statements".into()));
         }
-        Ok(ast::Statement::Explain(Box::new(self.parse_statement()?)))
+        Ok(ast::Statement::Explain { statement: Box::new(self.parse_statement()?), analyze })
     }
 
     /// Parses an insert statement
@@ -256,33 +496,164 @@ impl<'a> Parser<'a> {
             None
         };
 
-        self.next_expect(Some(Keyword::Values.into()))?;
-        let mut values = Vec::new();
-        loop {
-            self.next_expect(Some(Token::OpenParen))?;
-            let mut exprs = Vec::new();
+        let source = if let Some(Token::Keyword(Keyword::Select))
+        | Some(Token::Keyword(Keyword::With)) = self.peek()?
+        {
+            ast::InsertSource::Select(Box::new(self.parse_query()?))
+        } else {
+            self.next_expect(Some(Keyword::Values.into()))?;
+            let mut values = Vec::new();
             loop {
-                exprs.push(self.parse_expression(0)?);
-                match self.next()? {
-                    Token::CloseParen => break,
-                    Token::Comma => {}
-                    token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                self.next_expect(Some(Token::OpenParen))?;
+                let mut exprs = Vec::new();
+                loop {
+                    exprs.push(self.parse_expression(0)?);
+                    match self.next()? {
+                        Token::CloseParen => break,
+                        Token::Comma => {}
+                        token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                    }
+                }
+                values.push(exprs);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
                 }
             }
-            values.push(exprs);
-            if self.next_if_token(Token::Comma).is_none() {
-                break;
+            ast::InsertSource::Values(values)
+        };
+
+        Ok(ast::Statement::Insert {
+            table,
+            columns,
+            source,
+            on_conflict: self.parse_clause_on_conflict()?,
+            returning: self.parse_clause_returning()?,
+        })
+    }
+
+    /// Parses a query, i.e. a select statement with an optional WITH clause
+    fn parse_query(&mut self) -> Result<ast::Statement> {
+        match self.peek()? {
+            Some(Token::Keyword(Keyword::Select)) => self.parse_statement_select(),
+            Some(Token::Keyword(Keyword::With)) => {
+                let (recursive, ctes) = self.parse_clause_with()?;
+                let statement = Box::new(self.parse_statement_select()?);
+                Ok(ast::Statement::With { recursive, ctes, statement })
             }
+            Some(token) => Err(Error::Parse(format!("Unexpected token {}, wanted SELECT", token))),
+            None => Err(Error::Parse("Unexpected end of input".into())),
         }
-
-        Ok(ast::Statement::Insert { table, columns, values })
     }
 
-    /// Parses a select statement
+    /// Parses a select statement, along with any further select statements combined with it by
+    /// set operators. INTERSECT binds tighter than UNION and EXCEPT, which are left-associative.
+    /// The ORDER BY, LIMIT, and OFFSET clauses of the last select statement apply to the combined
+    /// rows, and can't be given for the others.
     fn parse_statement_select(&mut self) -> Result<ast::Statement> {
+        let mut select = self.parse_select()?;
+        let mut terms = Vec::new();
+        let mut operators = Vec::new();
+        while let Some(operator) = self.parse_set_operator()? {
+            let all = self.next_if_token(Keyword::All.into()).is_some();
+            match self.peek()? {
+                Some(Token::Keyword(Keyword::Select)) => {}
+                Some(token) => {
+                    return Err(Error::Parse(format!("Unexpected token {}, wanted SELECT", token)))
+                }
+                None => return Err(Error::Parse("Unexpected end of input".into())),
+            }
+            match &select {
+                ast::Statement::Select { order, offset, limit, .. }
+                | ast::Statement::SetOperation { order, offset, limit, .. }
+                    if !order.is_empty() || offset.is_some() || limit.is_some() =>
+                {
+                    return Err(Error::Parse(
+                        "ORDER BY, LIMIT, and OFFSET must follow the last SELECT of a set operation"
+                            .into(),
+                    ))
+                }
+                _ => {}
+            }
+            let right = self.parse_select()?;
+            select = match operator {
+                ast::SetOperator::Intersect => Self::set_operation(operator, select, right, all),
+                _ => {
+                    terms.push(select);
+                    operators.push((operator, all));
+                    right
+                }
+            };
+        }
+        if terms.is_empty() {
+            return Ok(select);
+        }
+
+        let (order, offset, limit) = match &mut select {
+            ast::Statement::Select { order, offset, limit, .. }
+            | ast::Statement::SetOperation { order, offset, limit, .. } => {
+                (std::mem::take(order), offset.take(), limit.take())
+            }
+            _ => return Err(Error::Internal("Expected SELECT statement".into())),
+        };
+        terms.push(select);
+        let mut terms = terms.into_iter();
+        let mut statement = terms.next().ok_or_else(|| Error::Internal("No SELECT".into()))?;
+        for ((operator, all), right) in operators.into_iter().zip(terms) {
+            statement = Self::set_operation(operator, statement, right, all);
+        }
+        if let ast::Statement::SetOperation { order: o, offset: off, limit: l, .. } = &mut statement
+        {
+            *o = order;
+            *off = offset;
+            *l = limit;
+        }
+        Ok(statement)
+    }
+
+    /// Parses a set operator, if any.
+    fn parse_set_operator(&mut self) -> Result<Option<ast::SetOperator>> {
+        let operator = match self.peek()? {
+            Some(Token::Keyword(Keyword::Union)) => ast::SetOperator::Union,
+            Some(Token::Keyword(Keyword::Intersect)) => ast::SetOperator::Intersect,
+            Some(Token::Keyword(Keyword::Except)) => ast::SetOperator::Except,
+            _ => return Ok(None),
+        };
+        self.next()?;
+        Ok(Some(operator))
+    }
+
+    /// Builds a set operation of two select statements. If the right statement has ORDER BY,
+    /// LIMIT, or OFFSET clauses, they're moved to the set operation.
+    fn set_operation(
+        operator: ast::SetOperator,
+        left: ast::Statement,
+        mut right: ast::Statement,
+        all: bool,
+    ) -> ast::Statement {
+        let (order, offset, limit) = match &mut right {
+            ast::Statement::Select { order, offset, limit, .. } => {
+                (std::mem::take(order), offset.take(), limit.take())
+            }
+            _ => (Vec::new(), None, None),
+        };
+        ast::Statement::SetOperation {
+            operator,
+            left: Box::new(left),
+            right: Box::new(right),
+            all,
+            order,
+            offset,
+            limit,
+        }
+    }
+
+    /// Parses a single select statement
+    fn parse_select(&mut self) -> Result<ast::Statement> {
+        let (distinct, select) = self.parse_clause_select()?;
         Ok(ast::Statement::Select {
-            select: self.parse_clause_select()?,
-            from: self.parse_clause_from()?,
+            select,
+            distinct,
+            from: self.parse_clause_from(Keyword::From)?,
             r#where: self.parse_clause_where()?,
             group_by: self.parse_clause_group_by()?,
             having: self.parse_clause_having()?,
@@ -300,40 +671,196 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parses a GRANT or REVOKE statement, e.g. GRANT SELECT, INSERT ON TABLE t TO u or
+    /// REVOKE ALL PRIVILEGES ON t FROM u
+    fn parse_statement_grant(&mut self) -> Result<ast::Statement> {
+        let grant = match self.next()? {
+            Token::Keyword(Keyword::Grant) => true,
+            Token::Keyword(Keyword::Revoke) => false,
+            token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+        };
+        let privileges = if self.next_if_token(Keyword::All.into()).is_some() {
+            self.next_if_token(Keyword::Privileges.into());
+            Privilege::ALL.to_vec()
+        } else {
+            let mut privileges = Vec::new();
+            loop {
+                privileges.push(match self.next()? {
+                    Token::Keyword(Keyword::Select) => Privilege::Select,
+                    Token::Keyword(Keyword::Insert) => Privilege::Insert,
+                    Token::Keyword(Keyword::Update) => Privilege::Update,
+                    Token::Keyword(Keyword::Delete) => Privilege::Delete,
+                    token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                });
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+            privileges
+        };
+        self.next_expect(Some(Keyword::On.into()))?;
+        self.next_if_token(Keyword::Table.into());
+        let table = self.next_ident()?;
+        if grant {
+            self.next_expect(Some(Keyword::To.into()))?;
+            Ok(ast::Statement::Grant { privileges, table, user: self.next_ident()? })
+        } else {
+            self.next_expect(Some(Keyword::From.into()))?;
+            Ok(ast::Statement::Revoke { privileges, table, user: self.next_ident()? })
+        }
+    }
+
+    /// Parses a truncate statement
+    fn parse_statement_truncate(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Some(Keyword::Truncate.into()))?;
+        self.next_if_token(Keyword::Table.into());
+        Ok(ast::Statement::Truncate { table: self.next_ident()? })
+    }
+
     /// Parses an update statement
     fn parse_statement_update(&mut self) -> Result<ast::Statement> {
         self.next_expect(Some(Keyword::Update.into()))?;
         let table = self.next_ident()?;
-        self.next_expect(Some(Keyword::Set.into()))?;
+        let set = self.parse_clause_set()?;
+        let from = self.parse_clause_from(Keyword::From)?;
+        let r#where = self.parse_clause_where()?;
+        Ok(ast::Statement::Update {
+            table,
+            set,
+            from,
+            r#where,
+            returning: self.parse_clause_returning()?,
+        })
+    }
 
-        let mut set = BTreeMap::new();
-        loop {
-            let column = self.next_ident()?;
+    /// Parses a vacuum statement
+    fn parse_statement_vacuum(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Some(Keyword::Vacuum.into()))?;
+        Ok(ast::Statement::Vacuum)
+    }
+
+    /// Parses a SET statement, whose value must be a literal
+    fn parse_statement_set(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Some(Keyword::Set.into()))?;
+        let variable = self.next_ident()?;
+        if self.next_if_token(Keyword::To.into()).is_none() {
             self.next_expect(Some(Token::Equal))?;
-            let expr = self.parse_expression(0)?;
-            if set.contains_key(&column) {
-                return Err(Error::Value(format!("Duplicate values given for column {}", column)));
-            }
-            set.insert(column, expr);
-            if self.next_if_token(Token::Comma).is_none() {
-                break;
+        }
+        let value = match self.parse_expression(0)? {
+            ast::Expression::Literal(literal) => literal,
+            ast::Expression::Operation(ast::Operation::Negate(expr)) => match *expr {
+                ast::Expression::Literal(ast::Literal::Integer(i)) => ast::Literal::Integer(-i),
+                ast::Expression::Literal(ast::Literal::Float(f)) => ast::Literal::Float(-f),
+                _ => return Err(Error::Parse("SET value must be a literal".into())),
+            },
+            _ => return Err(Error::Parse("SET value must be a literal".into())),
+        };
+        Ok(ast::Statement::Set { variable, value })
+    }
+
+    /// Parses a COPY statement, e.g. COPY movies FROM 'movies.csv' WITH (HEADER, DELIMITER ';')
+    fn parse_statement_copy(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Some(Keyword::Copy.into()))?;
+        let table = self.next_ident()?;
+        let from = match self.next()? {
+            Token::Keyword(Keyword::From) => true,
+            Token::Keyword(Keyword::To) => false,
+            token => return Err(Error::Parse(format!("Expected FROM or TO, got {}", token))),
+        };
+        let path = self.next_string()?;
+        let mut header = false;
+        let mut delimiter = ',';
+        if self.next_if_token(Keyword::With.into()).is_some() {
+            self.next_expect(Some(Token::OpenParen))?;
+            loop {
+                match self.next_ident()?.as_str() {
+                    "header" => {
+                        header = match self.next_if_token(Keyword::False.into()) {
+                            Some(_) => false,
+                            None => {
+                                self.next_if_token(Keyword::True.into());
+                                true
+                            }
+                        }
+                    }
+                    "delimiter" => {
+                        let s = self.next_string()?;
+                        let mut chars = s.chars();
+                        delimiter = match (chars.next(), chars.next()) {
+                            (Some(c), None) if !matches!(c, '"' | '\n' | '\r') => c,
+                            _ => {
+                                return Err(Error::Parse(format!(
+                                    "Invalid COPY delimiter '{}', must be a single character \
+                                     other than a quote or newline",
+                                    s
+                                )))
+                            }
+                        };
+                    }
+                    option => return Err(Error::Parse(format!("Unknown COPY option {}", option))),
+                }
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
             }
+            self.next_expect(Some(Token::CloseParen))?;
         }
+        Ok(match from {
+            true => ast::Statement::CopyFrom { table, path, header, delimiter },
+            false => ast::Statement::CopyTo { table, path, header, delimiter },
+        })
+    }
 
-        Ok(ast::Statement::Update { table, set, r#where: self.parse_clause_where()? })
+    /// Parses a SHOW statement
+    fn parse_statement_show(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Some(Keyword::Show.into()))?;
+        if self.next_if_token(Keyword::All.into()).is_some() {
+            return Ok(ast::Statement::Show { variable: None });
+        }
+        if self.next_if_token(Keyword::Create.into()).is_some() {
+            self.next_expect(Some(Keyword::Table.into()))?;
+            return Ok(ast::Statement::ShowCreateTable { table: self.next_ident()? });
+        }
+        match self.next_ident()? {
+            variable if variable == "tables" => Ok(ast::Statement::ShowTables),
+            variable => Ok(ast::Statement::Show { variable: Some(variable) }),
+        }
+    }
+
+    /// Parses a statement with a WITH clause
+    fn parse_statement_with(&mut self) -> Result<ast::Statement> {
+        let (recursive, ctes) = self.parse_clause_with()?;
+        let statement = Box::new(match self.peek()? {
+            Some(Token::Keyword(Keyword::Delete)) => self.parse_statement_delete()?,
+            Some(Token::Keyword(Keyword::Insert)) => self.parse_statement_insert()?,
+            Some(Token::Keyword(Keyword::Select)) => self.parse_statement_select()?,
+            Some(Token::Keyword(Keyword::Update)) => self.parse_statement_update()?,
+            Some(token) => return Err(Error::Parse(format!("Unexpected token {}", token))),
+            None => return Err(Error::Parse("Unexpected end of input".into())),
+        });
+        Ok(ast::Statement::With { recursive, ctes, statement })
     }
 
     /// Parses a transaction statement
     fn parse_transaction(&mut self) -> Result<ast::Statement> {
         match self.next()? {
             Token::Keyword(Keyword::Begin) => {
-                let mut readonly = false;
+                let mut readonly = None;
                 let mut version = None;
+                let mut serializable = false;
                 self.next_if_token(Keyword::Transaction.into());
                 if self.next_if_token(Keyword::Read.into()).is_some() {
                     match self.next()? {
-                        Token::Keyword(Keyword::Only) => readonly = true,
-                        Token::Keyword(Keyword::Write) => readonly = false,
+                        Token::Keyword(Keyword::Only) => readonly = Some(true),
+                        Token::Keyword(Keyword::Write) => readonly = Some(false),
+                        token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                    }
+                }
+                if self.next_if_token(Keyword::Isolation.into()).is_some() {
+                    self.next_expect(Some(Keyword::Level.into()))?;
+                    match self.next()? {
+                        Token::Keyword(Keyword::Serializable) => serializable = true,
+                        Token::Keyword(Keyword::Snapshot) => serializable = false,
                         token => return Err(Error::Parse(format!("Unexpected token {}", token))),
                     }
                 }
@@ -351,7 +878,7 @@ impl<'a> Parser<'a> {
                         }
                     }
                 }
-                Ok(ast::Statement::Begin { readonly, version })
+                Ok(ast::Statement::Begin { readonly, version, serializable })
             }
             Token::Keyword(Keyword::Commit) => Ok(ast::Statement::Commit),
             Token::Keyword(Keyword::Rollback) => Ok(ast::Statement::Rollback),
@@ -359,10 +886,44 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Parses a from clause
-    fn parse_clause_from(&mut self) -> Result<Vec<ast::FromItem>> {
+    /// Parses a WITH clause, consisting of one or more common table expressions, and returns
+    /// whether it's recursive
+    fn parse_clause_with(&mut self) -> Result<(bool, Vec<ast::Cte>)> {
+        self.next_expect(Some(Keyword::With.into()))?;
+        let recursive = self.next_if_token(Keyword::Recursive.into()).is_some();
+        let mut ctes = Vec::new();
+        loop {
+            let name = self.next_ident()?;
+            let columns = if self.next_if_token(Token::OpenParen).is_some() {
+                let mut columns = Vec::new();
+                loop {
+                    columns.push(self.next_ident()?);
+                    match self.next()? {
+                        Token::CloseParen => break,
+                        Token::Comma => {}
+                        token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                    }
+                }
+                Some(columns)
+            } else {
+                None
+            };
+            self.next_expect(Some(Keyword::As.into()))?;
+            self.next_expect(Some(Token::OpenParen))?;
+            let query = self.parse_query()?;
+            self.next_expect(Some(Token::CloseParen))?;
+            ctes.push(ast::Cte { name, columns, query });
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok((recursive, ctes))
+    }
+
+    /// Parses a from clause, introduced by the given keyword (i.e. FROM, or USING for DELETE)
+    fn parse_clause_from(&mut self, keyword: Keyword) -> Result<Vec<ast::FromItem>> {
         let mut from = Vec::new();
-        if self.next_if_token(Keyword::From.into()).is_none() {
+        if self.next_if_token(keyword.into()).is_none() {
             return Ok(from);
         }
         loop {
@@ -393,9 +954,16 @@ impl<'a> Parser<'a> {
         self.parse_clause_from_table()
     }
 
-    // Parses a from clause table
+    // Parses a from clause table, which may be a system table qualified by the system schema
     fn parse_clause_from_table(&mut self) -> Result<ast::FromItem> {
+        let system = self.next_if_token(Keyword::System.into()).is_some();
+        if system {
+            self.next_expect(Some(Token::Period))?;
+        }
         let name = self.next_ident()?;
+        if !system && self.peek()? == Some(Token::Period) {
+            return Err(Error::Parse(format!("Unknown schema {}", name)));
+        }
         let alias = if self.next_if_token(Keyword::As.into()).is_some() {
             Some(self.next_ident()?)
         } else if let Some(Token::Ident(_)) = self.peek()? {
@@ -403,6 +971,9 @@ impl<'a> Parser<'a> {
         } else {
             None
         };
+        if system {
+            return Ok(ast::FromItem::SystemTable { name, alias });
+        }
         Ok(ast::FromItem::Table { name, alias })
     }
 
@@ -424,6 +995,10 @@ impl<'a> Parser<'a> {
             self.next_if_token(Keyword::Outer.into());
             self.next_expect(Some(Keyword::Join.into()))?;
             Ok(Some(ast::JoinType::Right))
+        } else if self.next_if_token(Keyword::Full.into()).is_some() {
+            self.next_if_token(Keyword::Outer.into());
+            self.next_expect(Some(Keyword::Join.into()))?;
+            Ok(Some(ast::JoinType::Full))
         } else {
             Ok(None)
         }
@@ -453,24 +1028,67 @@ impl<'a> Parser<'a> {
         Ok(Some(self.parse_expression(0)?))
     }
 
+    /// Parses an INSERT ON CONFLICT clause, if present
+    fn parse_clause_on_conflict(&mut self) -> Result<Option<ast::OnConflict>> {
+        if self.next_if_token(Keyword::On.into()).is_none() {
+            return Ok(None);
+        }
+        self.next_expect(Some(Keyword::Conflict.into()))?;
+        let columns = if self.next_if_token(Token::OpenParen).is_some() {
+            let mut columns = Vec::new();
+            loop {
+                columns.push(self.next_ident()?);
+                match self.next()? {
+                    Token::CloseParen => break,
+                    Token::Comma => {}
+                    token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+                }
+            }
+            Some(columns)
+        } else {
+            None
+        };
+        self.next_expect(Some(Keyword::Do.into()))?;
+        let action = match self.next()? {
+            // NOTHING isn't a reserved keyword, so it can still be used as an identifier.
+            Token::Ident(ident) if ident == "nothing" => ast::ConflictAction::Nothing,
+            Token::Keyword(Keyword::Update) => {
+                ast::ConflictAction::Update(self.parse_clause_set()?)
+            }
+            token => return Err(Error::Parse(format!("Unexpected token {}", token))),
+        };
+        Ok(Some(ast::OnConflict { columns, action }))
+    }
+
     /// Parses an order clause
-    fn parse_clause_order(&mut self) -> Result<Vec<(ast::Expression, ast::Order)>> {
+    fn parse_clause_order(
+        &mut self,
+    ) -> Result<Vec<(ast::Expression, ast::Order, Option<ast::Nulls>)>> {
         if self.next_if_token(Keyword::Order.into()).is_none() {
             return Ok(Vec::new());
         }
         self.next_expect(Some(Keyword::By.into()))?;
         let mut orders = Vec::new();
         loop {
-            orders.push((
-                self.parse_expression(0)?,
-                if self.next_if_token(Keyword::Asc.into()).is_some() {
-                    ast::Order::Ascending
-                } else if self.next_if_token(Keyword::Desc.into()).is_some() {
-                    ast::Order::Descending
-                } else {
-                    ast::Order::Ascending
+            let expr = self.parse_expression(0)?;
+            let order = if self.next_if_token(Keyword::Asc.into()).is_some() {
+                ast::Order::Ascending
+            } else if self.next_if_token(Keyword::Desc.into()).is_some() {
+                ast::Order::Descending
+            } else {
+                ast::Order::Ascending
+            };
+            // NULLS, FIRST, and LAST aren't reserved keywords, so they can still be used as
+            // identifiers.
+            let nulls = match self.next_if_token(Token::Ident("nulls".into())) {
+                Some(_) => match self.next()? {
+                    Token::Ident(ident) if ident == "first" => Some(ast::Nulls::First),
+                    Token::Ident(ident) if ident == "last" => Some(ast::Nulls::Last),
+                    token => return Err(Error::Parse(format!("Unexpected token {}", token))),
                 },
-            ));
+                None => None,
+            };
+            orders.push((expr, order, nulls));
             if self.next_if_token(Token::Comma).is_none() {
                 break;
             }
@@ -479,11 +1097,50 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses a select clause
-    fn parse_clause_select(&mut self) -> Result<Vec<(ast::Expression, Option<String>)>> {
-        let mut select = Vec::new();
+    #[allow(clippy::type_complexity)]
+    fn parse_clause_select(
+        &mut self,
+    ) -> Result<(Option<Vec<ast::Expression>>, Vec<(ast::Expression, Option<String>)>)> {
         if self.next_if_token(Keyword::Select.into()).is_none() {
-            return Ok(select);
+            return Ok((None, Vec::new()));
         }
+        let distinct = self.parse_clause_distinct()?;
+        Ok((distinct, self.parse_select_list()?))
+    }
+
+    /// Parses a DISTINCT or DISTINCT ON (...) clause following SELECT, if any. An empty list
+    /// means plain DISTINCT.
+    fn parse_clause_distinct(&mut self) -> Result<Option<Vec<ast::Expression>>> {
+        if self.next_if_token(Keyword::Distinct.into()).is_none() {
+            return Ok(None);
+        }
+        let mut exprs = Vec::new();
+        if self.next_if_token(Keyword::On.into()).is_some() {
+            self.next_expect(Some(Token::OpenParen))?;
+            loop {
+                exprs.push(self.parse_expression(0)?);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+            self.next_expect(Some(Token::CloseParen))?;
+        }
+        Ok(Some(exprs))
+    }
+
+    /// Parses a RETURNING clause, if any. An empty list means RETURNING *.
+    #[allow(clippy::type_complexity)]
+    fn parse_clause_returning(&mut self) -> Result<Option<Vec<(ast::Expression, Option<String>)>>> {
+        if self.next_if_token(Keyword::Returning.into()).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_select_list()?))
+    }
+
+    /// Parses a list of expressions with optional labels, as in a SELECT clause. An empty list
+    /// means *.
+    fn parse_select_list(&mut self) -> Result<Vec<(ast::Expression, Option<String>)>> {
+        let mut select = Vec::new();
         loop {
             if self.next_if_token(Token::Asterisk).is_some() && select.is_empty() {
                 break;
@@ -505,6 +1162,25 @@ impl<'a> Parser<'a> {
         Ok(select)
     }
 
+    /// Parses a SET clause, e.g. in an UPDATE statement
+    fn parse_clause_set(&mut self) -> Result<BTreeMap<String, ast::Expression>> {
+        self.next_expect(Some(Keyword::Set.into()))?;
+        let mut set = BTreeMap::new();
+        loop {
+            let column = self.next_ident()?;
+            self.next_expect(Some(Token::Equal))?;
+            let expr = self.parse_expression(0)?;
+            if set.contains_key(&column) {
+                return Err(Error::Value(format!("Duplicate values given for column {}", column)));
+            }
+            set.insert(column, expr);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(set)
+    }
+
     /// Parses a WHERE clause
     fn parse_clause_where(&mut self) -> Result<Option<ast::Expression>> {
         if self.next_if_token(Keyword::Where.into()).is_none() {
@@ -522,32 +1198,157 @@ impl<'a> Parser<'a> {
             self.parse_expression_atom()?
         };
         while let Some(postfix) = self.next_if_operator::<PostfixOperator>(min_prec)? {
-            lhs = postfix.build(lhs)
+            let rhs = match postfix {
+                PostfixOperator::IsDistinctFrom { .. } => {
+                    Some(self.parse_expression(postfix.prec() + postfix.assoc())?)
+                }
+                _ => None,
+            };
+            lhs = postfix.build(lhs, rhs)
         }
         while let Some(infix) = self.next_if_operator::<InfixOperator>(min_prec)? {
-            lhs = infix.build(lhs, self.parse_expression(infix.prec() + infix.assoc())?)
+            let rhs = match infix {
+                InfixOperator::Between | InfixOperator::NotBetween => {
+                    self.parse_expression_between(infix.prec() + infix.assoc())?
+                }
+                InfixOperator::In | InfixOperator::NotIn => self.parse_expression_in()?,
+                _ => {
+                    let rhs = self.parse_expression(infix.prec() + infix.assoc())?;
+                    let escape = match infix {
+                        InfixOperator::ILike
+                        | InfixOperator::Like
+                        | InfixOperator::NotILike
+                        | InfixOperator::NotLike => self.parse_expression_escape()?,
+                        _ => None,
+                    };
+                    Operand::Expression(rhs, escape)
+                }
+            };
+            lhs = infix.build(lhs, rhs)?
         }
         Ok(lhs)
     }
 
+    /// Parses function call arguments following the opening parenthesis, including the closing
+    /// parenthesis. Handles the SQL syntax SUBSTRING(s FROM i [FOR n]) and POSITION(a IN b).
+    fn parse_expression_function_args(&mut self, name: &str) -> Result<Vec<ast::Expression>> {
+        let mut args = Vec::new();
+        while self.next_if_token(Token::CloseParen).is_none() {
+            if !args.is_empty() {
+                self.next_expect(Some(Token::Comma))?;
+            }
+            if args.is_empty() && self.next_if_token(Keyword::Distinct.into()).is_some() {
+                args.push(ast::Expression::Distinct(Box::new(self.parse_expression(0)?)));
+            } else if name == "count" && self.next_if_token(Token::Asterisk).is_some() {
+                // FIXME Ugly hack to handle COUNT(*)
+                args.push(ast::Expression::Literal(ast::Literal::Boolean(true)));
+            } else if name == "position" && args.is_empty() {
+                // The first argument can't contain IN operators, to allow POSITION(a IN b).
+                args.push(self.parse_expression(InfixOperator::In.prec() + 1)?);
+            } else {
+                args.push(self.parse_expression(0)?);
+            }
+            let order = self.parse_clause_order()?;
+            if !order.is_empty() {
+                args.extend(
+                    order.into_iter().map(|(e, o, n)| ast::Expression::OrderBy(Box::new(e), o, n)),
+                );
+                self.next_expect(Some(Token::CloseParen))?;
+                break;
+            }
+            match (name, args.len()) {
+                ("substring", 1) if self.next_if_token(Keyword::From.into()).is_some() => {
+                    args.push(self.parse_expression(0)?);
+                    if self.next_if_token(Keyword::For.into()).is_some() {
+                        args.push(self.parse_expression(0)?);
+                    }
+                    self.next_expect(Some(Token::CloseParen))?;
+                    break;
+                }
+                ("position", 1) if self.next_if_token(Keyword::In.into()).is_some() => {
+                    args.push(self.parse_expression(0)?);
+                    self.next_expect(Some(Token::CloseParen))?;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        Ok(args)
+    }
+
+    /// Parses the window specification of an OVER clause for the given function call, i.e.
+    /// ([PARTITION BY expr, ...] [ORDER BY expr [ASC|DESC] [NULLS FIRST|LAST], ...]).
+    fn parse_expression_window(
+        &mut self,
+        name: String,
+        args: Vec<ast::Expression>,
+    ) -> Result<ast::Expression> {
+        self.next_expect(Some(Token::OpenParen))?;
+        let mut partition = Vec::new();
+        if self.next_if_token(Keyword::Partition.into()).is_some() {
+            self.next_expect(Some(Keyword::By.into()))?;
+            loop {
+                partition.push(self.parse_expression(0)?);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+        }
+        let order = self.parse_clause_order()?;
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(ast::Expression::Window(name, args, partition, order))
+    }
+
+    /// Parses the bounds of a BETWEEN operator, separated by AND. The bounds are parsed with the
+    /// given minimum precedence, which must be above AND.
+    fn parse_expression_between(&mut self, min_prec: u8) -> Result<Operand> {
+        let low = self.parse_expression(min_prec)?;
+        self.next_expect(Some(Keyword::And.into()))?;
+        let high = self.parse_expression(min_prec)?;
+        Ok(Operand::Range(low, high))
+    }
+
+    /// Parses the right-hand side of an IN operator: either a parenthesized list of expressions,
+    /// or a subquery.
+    fn parse_expression_in(&mut self) -> Result<Operand> {
+        self.next_expect(Some(Token::OpenParen))?;
+        if let Some(Token::Keyword(Keyword::Select)) = self.peek()? {
+            let select = self.parse_statement_select()?;
+            self.next_expect(Some(Token::CloseParen))?;
+            return Ok(Operand::Subquery(Box::new(select)));
+        }
+        let mut list = vec![self.parse_expression(0)?];
+        while self.next_if_token(Token::Comma).is_some() {
+            list.push(self.parse_expression(0)?);
+        }
+        self.next_expect(Some(Token::CloseParen))?;
+        Ok(Operand::List(list))
+    }
+
+    /// Parses an optional ESCAPE clause for a LIKE pattern, which must be a single character
+    fn parse_expression_escape(&mut self) -> Result<Option<char>> {
+        if self.next_if_token(Keyword::Escape.into()).is_none() {
+            return Ok(None);
+        }
+        let escape = self.next_string()?;
+        let mut chars = escape.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Some(c)),
+            _ => Err(Error::Parse(format!("ESCAPE must be a single character, got '{}'", escape))),
+        }
+    }
+
     /// Parses an expression atom
     fn parse_expression_atom(&mut self) -> Result<ast::Expression> {
         Ok(match self.next()? {
             Token::Ident(i) => {
                 if self.next_if_token(Token::OpenParen).is_some() {
-                    let mut args = Vec::new();
-                    while self.next_if_token(Token::CloseParen).is_none() {
-                        if !args.is_empty() {
-                            self.next_expect(Some(Token::Comma))?;
-                        }
-                        if i == "count" && self.next_if_token(Token::Asterisk).is_some() {
-                            // FIXME Ugly hack to handle COUNT(*)
-                            args.push(ast::Expression::Literal(ast::Literal::Boolean(true)));
-                        } else {
-                            args.push(self.parse_expression(0)?);
-                        }
+                    let args = self.parse_expression_function_args(&i)?;
+                    if self.next_if_token(Keyword::Over.into()).is_some() {
+                        self.parse_expression_window(i, args)?
+                    } else {
+                        ast::Expression::Function(i, args)
                     }
-                    ast::Expression::Function(i, args)
                 } else {
                     let mut relation = None;
                     let mut field = i;
@@ -566,19 +1367,105 @@ impl<'a> Parser<'a> {
                 }
             }
             Token::OpenParen => {
-                let expr = self.parse_expression(0)?;
+                let expr = match self.peek()? {
+                    Some(Token::Keyword(Keyword::Select)) => {
+                        ast::Expression::Subquery(Box::new(self.parse_statement_select()?))
+                    }
+                    _ => self.parse_expression(0)?,
+                };
                 self.next_expect(Some(Token::CloseParen))?;
                 expr
             }
             Token::String(s) => ast::Literal::String(s).into(),
+            Token::HexString(s) => ast::Literal::Bytes(Self::parse_hex(&s)?).into(),
+            Token::Question => {
+                self.placeholders += 1;
+                self.parameters = self.parameters.max(self.placeholders);
+                ast::Expression::Parameter(self.placeholders)
+            }
+            Token::Parameter(n) => match n.parse()? {
+                0 => return Err(Error::Parse("Parameter numbers start at $1".into())),
+                n => {
+                    self.parameters = self.parameters.max(n);
+                    ast::Expression::Parameter(n)
+                }
+            },
+            Token::Keyword(Keyword::Case) => self.parse_expression_case()?,
+            Token::Keyword(keyword @ Keyword::Cast)
+            | Token::Keyword(keyword @ Keyword::TryCast) => {
+                self.next_expect(Some(Token::OpenParen))?;
+                let expr = Box::new(self.parse_expression(0)?);
+                self.next_expect(Some(Keyword::As.into()))?;
+                let datatype = self.parse_datatype()?;
+                self.next_expect(Some(Token::CloseParen))?;
+                match keyword {
+                    Keyword::TryCast => ast::Expression::TryCast(expr, datatype),
+                    _ => ast::Expression::Cast(expr, datatype),
+                }
+            }
+            Token::Keyword(Keyword::CurrentTimestamp) => {
+                ast::Expression::Function("current_timestamp".into(), Vec::new())
+            }
+            Token::Keyword(Keyword::Date) => ast::Literal::Date(self.next_string()?).into(),
+            Token::Keyword(Keyword::Exists) => {
+                self.next_expect(Some(Token::OpenParen))?;
+                let select = self.parse_statement_select()?;
+                self.next_expect(Some(Token::CloseParen))?;
+                ast::Operation::Exists(Box::new(select)).into()
+            }
+            Token::Keyword(Keyword::Decimal) | Token::Keyword(Keyword::Numeric) => {
+                ast::Literal::Decimal(self.next_string()?).into()
+            }
             Token::Keyword(Keyword::False) => ast::Literal::Boolean(false).into(),
             Token::Keyword(Keyword::Infinity) => ast::Literal::Float(std::f64::INFINITY).into(),
             Token::Keyword(Keyword::NaN) => ast::Literal::Float(std::f64::NAN).into(),
             Token::Keyword(Keyword::Null) => ast::Literal::Null.into(),
+            Token::Keyword(Keyword::Time) => ast::Literal::Time(self.next_string()?).into(),
+            Token::Keyword(Keyword::Timestamp) => {
+                ast::Literal::Timestamp(self.next_string()?).into()
+            }
             Token::Keyword(Keyword::True) => ast::Literal::Boolean(true).into(),
             t => return Err(Error::Parse(format!("Expected expression atom, found {}", t))),
         })
     }
+
+    /// Parses the remainder of a CASE expression, after the CASE keyword. If an operand is given
+    /// (the simple form), the WHEN expressions are values to compare it with, otherwise they are
+    /// boolean conditions (the searched form).
+    fn parse_expression_case(&mut self) -> Result<ast::Expression> {
+        let mut operand = None;
+        if self.next_if_token(Keyword::When.into()).is_none() {
+            operand = Some(Box::new(self.parse_expression(0)?));
+            self.next_expect(Some(Keyword::When.into()))?;
+        }
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.parse_expression(0)?;
+            self.next_expect(Some(Keyword::Then.into()))?;
+            branches.push((condition, self.parse_expression(0)?));
+            if self.next_if_token(Keyword::When.into()).is_none() {
+                break;
+            }
+        }
+        let mut r#else = None;
+        if self.next_if_token(Keyword::Else.into()).is_some() {
+            r#else = Some(Box::new(self.parse_expression(0)?));
+        }
+        self.next_expect(Some(Keyword::End.into()))?;
+        Ok(ast::Expression::Case(operand, branches, r#else))
+    }
+
+    /// Decodes the hexadecimal digits of a hex string literal, e.g. x'DEADBEEF'.
+    fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+        let err = || Error::Parse(format!("Invalid hexadecimal string x'{}'", hex));
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(err());
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| err()))
+            .collect()
+    }
 }
 
 /// An operator trait, to help with parsing of operators
@@ -587,6 +1474,11 @@ trait Operator: Sized {
     fn from(token: &Token) -> Option<Self>;
     /// Augments an operator by allowing it to parse any modifiers.
     fn augment(self, parser: &mut Parser) -> Result<Self>;
+    /// Returns tokens of which one must follow the operator token for the operator to apply, if
+    /// any. Used for multi-token operators like NOT LIKE, to leave e.g. NOT NULL to the caller.
+    fn lookahead(&self) -> Vec<Token> {
+        Vec::new()
+    }
     /// Returns the operator's associativity
     fn assoc(&self) -> u8;
     /// Returns the operator's precedence
@@ -639,42 +1531,86 @@ impl Operator for PrefixOperator {
 enum InfixOperator {
     Add,
     And,
+    Between,
     Divide,
     Equal,
     Exponentiate,
     GreaterThan,
     GreaterThanOrEqual,
+    ILike,
+    In,
     LessThan,
     LessThanOrEqual,
     Like,
     Modulo,
     Multiply,
+    NotBetween,
     NotEqual,
+    NotILike,
+    NotIn,
+    NotLike,
     Or,
     Subtract,
 }
 
+/// The right-hand side of an infix operator.
+enum Operand {
+    /// A single expression, with an optional ESCAPE character for LIKE patterns.
+    Expression(ast::Expression, Option<char>),
+    /// A parenthesized list of expressions, for IN.
+    List(Vec<ast::Expression>),
+    /// Lower and upper bounds, for BETWEEN.
+    Range(ast::Expression, ast::Expression),
+    /// A SELECT subquery, for IN.
+    Subquery(Box<ast::Statement>),
+}
+
 impl InfixOperator {
-    fn build(&self, lhs: ast::Expression, rhs: ast::Expression) -> ast::Expression {
-        let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
-        match self {
-            Self::Add => ast::Operation::Add(lhs, rhs),
-            Self::And => ast::Operation::And(lhs, rhs),
-            Self::Divide => ast::Operation::Divide(lhs, rhs),
-            Self::Equal => ast::Operation::Equal(lhs, rhs),
-            Self::Exponentiate => ast::Operation::Exponentiate(lhs, rhs),
-            Self::GreaterThan => ast::Operation::GreaterThan(lhs, rhs),
-            Self::GreaterThanOrEqual => ast::Operation::GreaterThanOrEqual(lhs, rhs),
-            Self::LessThan => ast::Operation::LessThan(lhs, rhs),
-            Self::LessThanOrEqual => ast::Operation::LessThanOrEqual(lhs, rhs),
-            Self::Like => ast::Operation::Like(lhs, rhs),
-            Self::Modulo => ast::Operation::Modulo(lhs, rhs),
-            Self::Multiply => ast::Operation::Multiply(lhs, rhs),
-            Self::NotEqual => ast::Operation::NotEqual(lhs, rhs),
-            Self::Or => ast::Operation::Or(lhs, rhs),
-            Self::Subtract => ast::Operation::Subtract(lhs, rhs),
-        }
-        .into()
+    fn build(&self, lhs: ast::Expression, rhs: Operand) -> Result<ast::Expression> {
+        let lhs = Box::new(lhs);
+        let operation = match (self, rhs) {
+            (Self::Between, Operand::Range(low, high))
+            | (Self::NotBetween, Operand::Range(low, high)) => {
+                ast::Operation::Between(lhs, Box::new(low), Box::new(high))
+            }
+            (Self::In, Operand::List(list)) | (Self::NotIn, Operand::List(list)) => {
+                ast::Operation::In(lhs, list)
+            }
+            (Self::In, Operand::Subquery(select)) | (Self::NotIn, Operand::Subquery(select)) => {
+                ast::Operation::InSubquery(lhs, select)
+            }
+            (_, Operand::Expression(rhs, escape)) => {
+                let rhs = Box::new(rhs);
+                match self {
+                    Self::Add => ast::Operation::Add(lhs, rhs),
+                    Self::And => ast::Operation::And(lhs, rhs),
+                    Self::Divide => ast::Operation::Divide(lhs, rhs),
+                    Self::Equal => ast::Operation::Equal(lhs, rhs),
+                    Self::Exponentiate => ast::Operation::Exponentiate(lhs, rhs),
+                    Self::GreaterThan => ast::Operation::GreaterThan(lhs, rhs),
+                    Self::GreaterThanOrEqual => ast::Operation::GreaterThanOrEqual(lhs, rhs),
+                    Self::ILike | Self::NotILike => ast::Operation::ILike(lhs, rhs, escape),
+                    Self::LessThan => ast::Operation::LessThan(lhs, rhs),
+                    Self::LessThanOrEqual => ast::Operation::LessThanOrEqual(lhs, rhs),
+                    Self::Like | Self::NotLike => ast::Operation::Like(lhs, rhs, escape),
+                    Self::Modulo => ast::Operation::Modulo(lhs, rhs),
+                    Self::Multiply => ast::Operation::Multiply(lhs, rhs),
+                    Self::NotEqual => ast::Operation::NotEqual(lhs, rhs),
+                    Self::Or => ast::Operation::Or(lhs, rhs),
+                    Self::Subtract => ast::Operation::Subtract(lhs, rhs),
+                    Self::Between | Self::In | Self::NotBetween | Self::NotIn => {
+                        return Err(Error::Internal("Invalid operand for infix operator".into()))
+                    }
+                }
+            }
+            (_, _) => return Err(Error::Internal("Invalid operand for infix operator".into())),
+        };
+        Ok(match self {
+            Self::NotBetween | Self::NotILike | Self::NotIn | Self::NotLike => {
+                ast::Operation::Not(Box::new(operation.into())).into()
+            }
+            _ => operation.into(),
+        })
     }
 }
 
@@ -687,7 +1623,12 @@ impl Operator for InfixOperator {
             Token::GreaterThan => Self::GreaterThan,
             Token::GreaterThanOrEqual => Self::GreaterThanOrEqual,
             Token::Keyword(Keyword::And) => Self::And,
+            Token::Keyword(Keyword::Between) => Self::Between,
+            Token::Keyword(Keyword::ILike) => Self::ILike,
+            Token::Keyword(Keyword::In) => Self::In,
             Token::Keyword(Keyword::Like) => Self::Like,
+            // NOT is followed by LIKE, ILIKE, IN, or BETWEEN, which augment() chooses between.
+            Token::Keyword(Keyword::Not) => Self::NotLike,
             Token::Keyword(Keyword::Or) => Self::Or,
             Token::LessOrGreaterThan => Self::NotEqual,
             Token::LessThan => Self::LessThan,
@@ -701,10 +1642,31 @@ impl Operator for InfixOperator {
         })
     }
 
-    fn augment(self, _parser: &mut Parser) -> Result<Self> {
+    fn augment(self, parser: &mut Parser) -> Result<Self> {
+        if let Self::NotLike = self {
+            return Ok(match parser.next()? {
+                Token::Keyword(Keyword::Between) => Self::NotBetween,
+                Token::Keyword(Keyword::ILike) => Self::NotILike,
+                Token::Keyword(Keyword::In) => Self::NotIn,
+                Token::Keyword(Keyword::Like) => Self::NotLike,
+                token => return Err(Error::Parse(format!("Unexpected token {} after NOT", token))),
+            });
+        }
         Ok(self)
     }
 
+    fn lookahead(&self) -> Vec<Token> {
+        match self {
+            Self::NotLike => vec![
+                Keyword::Between.into(),
+                Keyword::ILike.into(),
+                Keyword::In.into(),
+                Keyword::Like.into(),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
     fn assoc(&self) -> u8 {
         match self {
             Self::Exponentiate => ASSOC_RIGHT,
@@ -716,7 +1678,16 @@ impl Operator for InfixOperator {
         match self {
             Self::Or => 1,
             Self::And => 2,
-            Self::Equal | Self::NotEqual | Self::Like => 3,
+            Self::Between
+            | Self::Equal
+            | Self::ILike
+            | Self::In
+            | Self::Like
+            | Self::NotBetween
+            | Self::NotEqual
+            | Self::NotILike
+            | Self::NotIn
+            | Self::NotLike => 3,
             Self::GreaterThan
             | Self::GreaterThanOrEqual
             | Self::LessThan
@@ -728,19 +1699,34 @@ impl Operator for InfixOperator {
     }
 }
 
+/// Postfix operators. IS [NOT] DISTINCT FROM takes a right-hand operand, but is parsed as a
+/// postfix operator since it shares the IS keyword with IS [NOT] NULL.
 enum PostfixOperator {
     Factorial,
     // FIXME Compiler bug? Why is this considered dead code?
     #[allow(dead_code)]
+    IsDistinctFrom {
+        not: bool,
+    },
+    #[allow(dead_code)]
     IsNull {
         not: bool,
     },
 }
 
 impl PostfixOperator {
-    fn build(&self, lhs: ast::Expression) -> ast::Expression {
+    fn build(&self, lhs: ast::Expression, rhs: Option<ast::Expression>) -> ast::Expression {
         let lhs = Box::new(lhs);
         match self {
+            Self::IsDistinctFrom { not } => {
+                let rhs = Box::new(rhs.unwrap_or(ast::Literal::Null.into()));
+                match not {
+                    true => ast::Operation::Not(Box::new(
+                        ast::Operation::IsDistinctFrom(lhs, rhs).into(),
+                    )),
+                    false => ast::Operation::IsDistinctFrom(lhs, rhs),
+                }
+            }
             Self::IsNull { not } => match not {
                 true => ast::Operation::Not(Box::new(ast::Operation::IsNull(lhs).into())),
                 false => ast::Operation::IsNull(lhs),
@@ -761,16 +1747,16 @@ impl Operator for PostfixOperator {
     }
 
     fn augment(mut self, parser: &mut Parser) -> Result<Self> {
-        #[allow(clippy::single_match)]
-        match &mut self {
-            Self::IsNull { ref mut not } => {
-                if parser.next_if_token(Keyword::Not.into()).is_some() {
-                    *not = true
-                };
+        if let Self::IsNull { not } = self {
+            let not = not || parser.next_if_token(Keyword::Not.into()).is_some();
+            if parser.next_if_token(Keyword::Distinct.into()).is_some() {
+                parser.next_expect(Some(Keyword::From.into()))?;
+                self = Self::IsDistinctFrom { not };
+            } else {
                 parser.next_expect(Some(Keyword::Null.into()))?;
+                self = Self::IsNull { not };
             }
-            _ => {}
-        };
+        }
         Ok(self)
     }
 
@@ -795,3 +1781,19 @@ pub(super) fn format_ident(ident: &str) -> String {
         format!("\"{}\"", ident.replace("\"", "\"\""))
     }
 }
+
+// Formats a value as a literal, which parses back to the same value
+pub(super) fn format_value(value: &Value) -> String {
+    match value {
+        Value::Float(f) if f.is_nan() => "NAN".into(),
+        Value::Float(f) if f.is_infinite() && *f > 0.0 => "INFINITY".into(),
+        Value::Float(f) if f.is_infinite() => "-INFINITY".into(),
+        Value::Float(f) => format!("{:?}", f),
+        Value::String(s) => format!("'{}'", s.replace("'", "''")),
+        Value::Date(d) => format!("DATE '{}'", d),
+        Value::Time(t) => format!("TIME '{}'", t),
+        Value::Timestamp(t) => format!("TIMESTAMP '{}'", t),
+        Value::Decimal(d) => format!("DECIMAL '{}'", d),
+        value => value.to_string(),
+    }
+}
@@ -4,14 +4,18 @@ use optimizer::Optimizer as _;
 use planner::Planner;
 
 use super::engine::Transaction;
-use super::execution::{Executor, ResultSet};
-use super::parser::ast;
-use super::schema::{Catalog, Table};
-use super::types::{Expression, Value};
-use crate::error::Result;
+use super::execution::{Executor, Limits, NodeStats, ResultSet, Stats};
+use super::parser::{ast, format_value};
+use super::schema::{self, Catalog, Privilege, SystemTable, Table, TableStats, User, View};
+use super::types::{Column, Columns, DataType, Expression, Function, Value};
+use super::types::{DECIMAL_DIVISION_SCALE, DECIMAL_MAX_PRECISION};
+use crate::error::{Error, Result};
 
 use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::fmt::{self, Display};
+use std::ops::Bound;
 
 /// A query plan
 #[derive(Debug)]
@@ -24,82 +28,477 @@ impl Display for Plan {
 }
 
 impl Plan {
-    /// Builds a plan from an AST statement.
+    /// Builds a plan from an AST statement. The plan's expressions are type checked, such that
+    /// type errors are returned before execution, see Node::check().
     pub fn build<C: Catalog>(statement: ast::Statement, catalog: &mut C) -> Result<Self> {
-        Planner::new(catalog).build(statement)
+        let plan = Planner::new(catalog)?.build(statement)?;
+        plan.0.check(catalog)?;
+        Ok(plan)
     }
 
-    /// Executes the plan, consuming it.
+    /// Builds a plan from an AST statement, binding the given values to its parameters.
+    pub fn build_with_params<C: Catalog>(
+        statement: ast::Statement,
+        catalog: &mut C,
+        params: Vec<Value>,
+    ) -> Result<Self> {
+        let plan = Planner::new(catalog)?.with_params(params).build(statement)?;
+        plan.0.check(catalog)?;
+        Ok(plan)
+    }
+
+    /// Executes the plan, consuming it. Subqueries are executed first, and replaced by their
+    /// results: IN subqueries by lists of their result values, scalar subqueries by their result
+    /// value (or NULL if empty), and EXISTS subqueries by whether they returned any rows.
     pub fn execute<T: Transaction + 'static>(self, txn: &mut T) -> Result<ResultSet> {
-        Executor::build(self.0).execute(txn)
+        self.execute_with_limits(txn, Limits::default())
+    }
+
+    /// Executes the plan like execute(), subject to the given execution limits. For example, it
+    /// errors with Error::Timeout if execution (including fetching any result rows) is not
+    /// complete by the deadline.
+    pub fn execute_with_limits<T: Transaction + 'static>(
+        self,
+        txn: &mut T,
+        limits: Limits,
+    ) -> Result<ResultSet> {
+        self.execute_with_stats(txn, limits, None)
+    }
+
+    /// Returns the columns of the plan's result rows, without executing it, see Node::columns().
+    pub fn columns<C: Catalog>(&self, catalog: &C) -> Result<Columns> {
+        Self::result_columns(&self.0, catalog)
+    }
+
+    /// Returns the result columns of a root node. Columns whose datatype can't be inferred, i.e.
+    /// which only contain untyped NULLs, default to STRING as in PostgreSQL. This is only done for
+    /// the final result, such that e.g. UNION columns can take the datatype of the other side.
+    fn result_columns<C: Catalog>(root: &Node, catalog: &C) -> Result<Columns> {
+        Ok(root
+            .columns(catalog)?
+            .into_iter()
+            .map(|c| Column { datatype: c.datatype.or(Some(DataType::String)), ..c })
+            .collect())
+    }
+
+    /// Checks that the plan's constant expressions can be evaluated, e.g. that they don't contain
+    /// type errors, without executing the plan. Lazily evaluated CASE branches and COALESCE
+    /// arguments are skipped, since they may never be evaluated.
+    pub fn check_constants(&self) -> Result<()> {
+        self.0.clone().transform(&|n| Ok(n), &|n| {
+            n.transform_expressions(
+                &|e| match e {
+                    e if !e.contains(&|expr| {
+                        matches!(
+                            expr,
+                            Expression::Field(..)
+                                | Expression::Exists(_)
+                                | Expression::InSubquery(..)
+                                | Expression::Subquery(_)
+                        )
+                    }) =>
+                    {
+                        Ok(Expression::Constant(e.evaluate(None)?))
+                    }
+                    Expression::Case(..) | Expression::Function(Function::Coalesce, _) => {
+                        Ok(Expression::Constant(Value::Null))
+                    }
+                    e => Ok(e),
+                },
+                &|e| Ok(e),
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Checks that the user has the privileges required to execute the plan, or errors with
+    /// Error::Unauthorized. Reading a table, including via views and subqueries, requires SELECT,
+    /// and mutations require the privilege of the mutation on the target table (along with SELECT,
+    /// since they read the target rows). Schema changes, ANALYZE, COPY (which accesses files on
+    /// the server), and user management require a superuser.
+    pub fn authorize(&self, user: &User) -> Result<()> {
+        if user.superuser {
+            return Ok(());
+        }
+        self.0.clone().transform(&|n| Ok(n), &|n| {
+            n.authorize(user)?;
+            n.transform_expressions(&|e| Ok(e), &|e| {
+                match &e {
+                    Expression::Exists(subquery)
+                    | Expression::InSubquery(_, subquery)
+                    | Expression::Subquery(subquery) => {
+                        Plan((**subquery).clone()).authorize(user)?
+                    }
+                    _ => {}
+                }
+                Ok(e)
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Formats the plan for EXPLAIN, annotating table scans and lookups with their estimated
+    /// number of rows if the table has been analyzed.
+    pub fn explain<C: Catalog>(&self, catalog: &mut C) -> Result<String> {
+        let mut estimates = self.0.estimate_all(catalog)?.into_iter();
+        Ok(self.0.format_annotated("".into(), true, true, &mut || {
+            Self::format_estimate(estimates.next().flatten())
+        }))
+    }
+
+    /// Executes the plan like execute_with_limits(), fetching all result rows and discarding
+    /// them, and returns the plan formatted like explain() and annotated with the actual row count
+    /// and elapsed time of each node. Subqueries are not instrumented.
+    pub fn analyze<T: Transaction + 'static>(self, txn: &mut T, limits: Limits) -> Result<String> {
+        let root = self.0.clone();
+        let mut estimates = root.estimate_all(txn)?.into_iter();
+        let stats = Stats::default();
+        if let ResultSet::Query { rows, .. } = self.execute_with_stats(txn, limits, Some(&stats))? {
+            for row in rows {
+                row?;
+            }
+        }
+        let stats = stats.lock()?;
+        let mut index = 0;
+        Ok(root.format_annotated("".into(), true, true, &mut || {
+            index += 1;
+            let estimate = Self::format_estimate(estimates.next().flatten());
+            match stats.get(&(index - 1)) {
+                Some(NodeStats { rows, time }) => format!(
+                    "{} (actual rows={} time={:.3}ms)",
+                    estimate,
+                    rows,
+                    time.as_secs_f64() * 1000.0
+                ),
+                None => estimate,
+            }
+        }))
+    }
+
+    /// Formats an estimated number of rows for EXPLAIN, if any.
+    fn format_estimate(estimate: Option<f64>) -> String {
+        match estimate {
+            Some(rows) => format!(" (estimated rows={})", rows.round() as u64),
+            None => String::new(),
+        }
+    }
+
+    /// Executes the plan, recording execution statistics for each node if stats is given.
+    fn execute_with_stats<T: Transaction + 'static>(
+        self,
+        txn: &mut T,
+        limits: Limits,
+        stats: Option<&Stats>,
+    ) -> Result<ResultSet> {
+        let txn = RefCell::new(txn);
+        let root = self.0.transform_subqueries(&|expr| {
+            let execute = |subquery: Node| match Plan(subquery)
+                .execute_with_limits(&mut **txn.borrow_mut(), limits.clone())?
+            {
+                ResultSet::Query { columns, rows } => Ok((columns.len(), rows)),
+                r => Err(Error::Internal(format!("Unexpected subquery result {:?}", r))),
+            };
+            Ok(match expr {
+                Expression::Exists(subquery) => {
+                    let (_, mut rows) = execute(*subquery)?;
+                    Expression::Constant(Value::Boolean(rows.next().transpose()?.is_some()))
+                }
+                Expression::InSubquery(lhs, subquery) => match execute(*subquery)? {
+                    (1, rows) => Expression::In(
+                        lhs,
+                        rows.map(|row| row.map(|mut row| Expression::Constant(row.remove(0))))
+                            .collect::<Result<_>>()?,
+                    ),
+                    (columns, _) => {
+                        return Err(Error::Value(format!(
+                            "IN subquery must return a single column, got {}",
+                            columns
+                        )))
+                    }
+                },
+                Expression::Subquery(subquery) => match execute(*subquery)? {
+                    (1, mut rows) => match (rows.next().transpose()?, rows.next().transpose()?) {
+                        (Some(mut row), None) => Expression::Constant(row.remove(0)),
+                        (None, _) => Expression::Constant(Value::Null),
+                        (Some(_), Some(_)) => {
+                            return Err(Error::Value(
+                                "Scalar subquery returned more than one row".into(),
+                            ))
+                        }
+                    },
+                    (columns, _) => {
+                        return Err(Error::Value(format!(
+                            "Scalar subquery must return a single column, got {}",
+                            columns
+                        )))
+                    }
+                },
+                expr => expr,
+            })
+        })?;
+        // The result columns are taken from the plan, which infers their datatypes.
+        let txn = txn.into_inner();
+        let columns = Self::result_columns(&root, &*txn)?;
+        let result = match stats {
+            Some(stats) => Executor::build_analyzed(root, limits, stats),
+            None => Executor::build(root, limits),
+        }
+        .execute(txn)?;
+        Ok(match result {
+            ResultSet::Query { rows, .. } => ResultSet::Query { columns, rows },
+            ResultSet::Returning { count, rows, .. } => {
+                ResultSet::Returning { count, columns, rows }
+            }
+            result => result,
+        })
     }
 
     /// Optimizes the plan, consuming it.
     pub fn optimize<C: Catalog>(self, catalog: &mut C) -> Result<Self> {
-        let mut root = self.0;
-        root = optimizer::ConstantFolder.optimize(root)?;
+        let catalog = RefCell::new(catalog);
+        let mut root = self.0.transform_subqueries(&|expr| {
+            let optimize = |subquery: Box<Node>| -> Result<Box<Node>> {
+                Ok(Box::new(Plan(*subquery).optimize(&mut **catalog.borrow_mut())?.0))
+            };
+            Ok(match expr {
+                Expression::Exists(subquery) => Expression::Exists(optimize(subquery)?),
+                Expression::InSubquery(lhs, subquery) => {
+                    Expression::InSubquery(lhs, optimize(subquery)?)
+                }
+                Expression::Subquery(subquery) => Expression::Subquery(optimize(subquery)?),
+                expr => expr,
+            })
+        })?;
+        let catalog = catalog.into_inner();
+        // Unlabeled function calls are named after their function (see Node::columns()), so label
+        // them before constant folding replaces them with their value.
+        root = root.transform(&|n| Ok(n), &|n| match n {
+            Node::Projection { source, expressions } => Ok(Node::Projection {
+                source,
+                expressions: expressions
+                    .into_iter()
+                    .map(|(expr, label)| match (label, &expr) {
+                        (None, Expression::Function(function, _)) => {
+                            let label = Some(function.to_string());
+                            (expr, label)
+                        }
+                        (label, _) => (expr, label),
+                    })
+                    .collect(),
+            }),
+            n => Ok(n),
+        })?;
+        root = optimizer::ConstantFolder::new(&*catalog).optimize(root)?;
         root = optimizer::FilterPushdown.optimize(root)?;
         root = optimizer::IndexLookup::new(catalog).optimize(root)?;
         root = optimizer::NoopCleaner.optimize(root)?;
-        root = optimizer::JoinType.optimize(root)?;
+        root = optimizer::JoinOrder::new(catalog).optimize(root)?;
+        root = optimizer::DistinctElision::new(catalog).optimize(root)?;
+        root = optimizer::ProjectionPushdown::new(catalog).optimize(root)?;
+        root = optimizer::JoinType::new(catalog).optimize(root)?;
+        root = optimizer::SortElision::new(catalog).optimize(root)?;
+        root = optimizer::TopK::new(catalog).optimize(root)?;
         Ok(Plan(root))
     }
 }
 
 /// A plan node
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Node {
+    /// Adds a column to a table (i.e. ALTER TABLE ADD COLUMN).
+    AddColumn {
+        table: String,
+        column: schema::Column,
+    },
     Aggregation {
         source: Box<Node>,
         aggregates: Vec<Aggregate>,
     },
+    /// Collects statistics for the given tables.
+    Analyze {
+        tables: Vec<String>,
+    },
+    /// Inserts the rows of a CSV file into a table (i.e. COPY FROM), see ast::Statement::CopyFrom.
+    CopyFrom {
+        table: String,
+        path: String,
+        header: bool,
+        delimiter: char,
+    },
+    /// Writes a table's rows to a CSV file (i.e. COPY TO), see ast::Statement::CopyTo.
+    CopyTo {
+        table: String,
+        path: String,
+        header: bool,
+        delimiter: char,
+    },
     CreateTable {
         schema: Table,
+        if_not_exists: bool,
+    },
+    /// Creates a user (i.e. CREATE USER).
+    CreateUser {
+        user: User,
     },
+    CreateView {
+        view: View,
+    },
+    /// A common table expression, referenced by name and optional alias. The CTE is inlined, i.e.
+    /// its source is planned and executed separately for each reference. The source columns are
+    /// renamed to the given column labels.
+    Cte {
+        source: Box<Node>,
+        name: String,
+        alias: Option<String>,
+        columns: Vec<Option<String>>,
+    },
+    /// Deletes the rows of the source from the table. The returning expressions, if any, are
+    /// evaluated on the deleted rows and emitted (i.e. RETURNING).
     Delete {
         table: String,
         source: Box<Node>,
+        returning: Vec<(Expression, Option<String>)>,
+    },
+    /// Discards source rows whose values of the given expressions equal those of a previous row,
+    /// where NULLs are equal (i.e. DISTINCT ON). An empty list compares all columns (i.e.
+    /// DISTINCT). The first row of each key is emitted as it is seen, preserving source order.
+    Distinct {
+        source: Box<Node>,
+        on: Vec<Expression>,
+    },
+    /// Drops a column from a table (i.e. ALTER TABLE DROP COLUMN).
+    DropColumn {
+        table: String,
+        column: String,
     },
     DropTable {
         table: String,
+        if_exists: bool,
+    },
+    /// Drops a user (i.e. DROP USER).
+    DropUser {
+        user: String,
+        if_exists: bool,
+    },
+    DropView {
+        view: String,
+        if_exists: bool,
     },
     Filter {
         source: Box<Node>,
         predicate: Expression,
     },
+    /// Grants privileges on a table to a user (i.e. GRANT).
+    Grant {
+        privileges: Vec<Privilege>,
+        table: String,
+        user: String,
+    },
+    /// Joins the left and right rows where all of the given left and right fields are equal.
     HashJoin {
         left: Box<Node>,
-        left_field: (usize, Option<(Option<String>, String)>),
+        left_fields: JoinFields,
         right: Box<Node>,
-        right_field: (usize, Option<(Option<String>, String)>),
+        right_fields: JoinFields,
         outer: bool,
+        /// For full outer joins, unmatched right rows are also emitted (requires outer).
+        full: bool,
+    },
+    /// Like SemiJoin, but matching left and right rows where all of the given left and right fields
+    /// are equal.
+    HashSemiJoin {
+        left: Box<Node>,
+        left_fields: JoinFields,
+        right: Box<Node>,
+        right_fields: JoinFields,
+        anti: bool,
+        /// For anti-joins, NULL keys match any row, as for NOT IN (requires a single field).
+        null_aware: bool,
     },
     IndexLookup {
         table: String,
         alias: Option<String>,
         column: String,
         values: Vec<Value>,
+        columns: Option<Vec<String>>,
+        index_only: bool,
     },
+    /// Scans a table's rows in the order of an indexed column's values, descending if reverse is
+    /// true, with rows of equal values in primary key order. Only rows matching the filter are
+    /// emitted, and the scan stops after limit rows, i.e. for ORDER BY ... LIMIT.
+    IndexOrderScan {
+        table: String,
+        alias: Option<String>,
+        column: String,
+        filter: Option<Expression>,
+        reverse: bool,
+        limit: u64,
+        columns: Option<Vec<String>>,
+        index_only: bool,
+    },
+    IndexPrefixScan {
+        table: String,
+        alias: Option<String>,
+        column: String,
+        prefix: String,
+        columns: Option<Vec<String>>,
+        index_only: bool,
+    },
+    /// Scans the index entries of a column for values within the given range, returning the rows
+    /// in index value order.
+    IndexRangeScan {
+        table: String,
+        alias: Option<String>,
+        column: String,
+        range: (Bound<Value>, Bound<Value>),
+        columns: Option<Vec<String>>,
+        index_only: bool,
+    },
+    /// Inserts the rows of the source into the table. The returning expressions, if any, are
+    /// evaluated on the inserted or updated rows and emitted (i.e. RETURNING).
     Insert {
         table: String,
         columns: Vec<String>,
-        expressions: Vec<Vec<Expression>>,
+        source: Box<Node>,
+        on_conflict: Option<OnConflict>,
+        returning: Vec<(Expression, Option<String>)>,
     },
     KeyLookup {
         table: String,
         alias: Option<String>,
         keys: Vec<Value>,
+        columns: Option<Vec<String>>,
+    },
+    /// Scans the rows of a table with primary keys within the given range, in primary key order.
+    KeyRangeScan {
+        table: String,
+        alias: Option<String>,
+        range: (Bound<Value>, Bound<Value>),
+        columns: Option<Vec<String>>,
     },
     Limit {
         source: Box<Node>,
         limit: u64,
     },
+    /// Like HashJoin, but joins left and right sources that are both sorted in ascending order by
+    /// their join fields, by merging them in key order. Only rows with equal keys are buffered.
+    MergeJoin {
+        left: Box<Node>,
+        left_fields: JoinFields,
+        right: Box<Node>,
+        right_fields: JoinFields,
+        outer: bool,
+        /// For full outer joins, unmatched right rows are also emitted (requires outer).
+        full: bool,
+    },
     NestedLoopJoin {
         left: Box<Node>,
         left_size: usize,
         right: Box<Node>,
         predicate: Option<Expression>,
         outer: bool,
+        /// For full outer joins, unmatched right rows are also emitted (requires outer).
+        full: bool,
     },
     Nothing,
     Offset {
@@ -108,21 +507,114 @@ pub enum Node {
     },
     Order {
         source: Box<Node>,
-        orders: Vec<(Expression, Direction)>,
+        orders: Vec<(Expression, Direction, Nulls)>,
+    },
+    /// An ORDER BY whose sort was elided since the source rows are already in the given order.
+    /// The source rows are emitted as-is, the node only shows the elided sort in plans.
+    Presorted {
+        source: Box<Node>,
+        orders: Vec<(Expression, Direction, Nulls)>,
     },
     Projection {
         source: Box<Node>,
         expressions: Vec<(Expression, Option<String>)>,
     },
+    /// Evaluates a recursive CTE: the seed rows are emitted, and the recursive term is then
+    /// evaluated repeatedly with the work table given by id containing the rows emitted by the
+    /// previous iteration, until it emits no rows. With UNION (i.e. unless all is true), rows
+    /// that have already been emitted are discarded.
+    RecursiveUnion {
+        seed: Box<Node>,
+        recursive: Box<Node>,
+        name: String,
+        id: usize,
+        all: bool,
+    },
+    RenameColumn {
+        table: String,
+        column: String,
+        new_name: String,
+    },
+    RenameTable {
+        table: String,
+        new_name: String,
+    },
+    /// Revokes privileges on a table from a user (i.e. REVOKE).
+    Revoke {
+        privileges: Vec<Privilege>,
+        table: String,
+        user: String,
+    },
     Scan {
         table: String,
         alias: Option<String>,
         filter: Option<Expression>,
+        columns: Option<Vec<String>>,
     },
+    /// Returns the left rows that have a matching right row for the predicate, or that have no
+    /// match if anti is true. Only the left columns are returned.
+    SemiJoin {
+        left: Box<Node>,
+        left_size: usize,
+        right: Box<Node>,
+        predicate: Option<Expression>,
+        anti: bool,
+    },
+    /// Combines the left and right rows, which must have the same number of columns and
+    /// compatible column types, using a set operator. Rows are deduplicated unless all is true.
+    /// The column names are taken from the left rows.
+    SetOperation {
+        operator: SetOperator,
+        left: Box<Node>,
+        right: Box<Node>,
+        all: bool,
+    },
+    /// Scans a read-only system table, whose rows are generated from the catalog.
+    SystemScan {
+        table: SystemTable,
+        alias: Option<String>,
+    },
+    /// Like Order, but only emits the first limit rows, which are kept in a bounded heap rather
+    /// than sorting all of the source rows, i.e. for ORDER BY ... LIMIT.
+    TopK {
+        source: Box<Node>,
+        orders: Vec<(Expression, Direction, Nulls)>,
+        limit: u64,
+    },
+    /// Deletes all rows of a table, along with its index entries and statistics (i.e. TRUNCATE).
+    Truncate {
+        table: String,
+    },
+    /// Updates the rows of the source in the table. The returning expressions, if any, are
+    /// evaluated on the updated rows and emitted (i.e. RETURNING).
     Update {
         table: String,
         source: Box<Node>,
         expressions: Vec<(usize, Option<String>, Expression)>,
+        returning: Vec<(Expression, Option<String>)>,
+    },
+    /// Emits the given rows of constant expressions, e.g. for INSERT ... VALUES.
+    Values {
+        rows: Vec<Vec<Expression>>,
+    },
+    /// A view, referenced by name and optional alias. Like a CTE, the view query is inlined at
+    /// each reference, and the source columns are renamed to the given column labels.
+    View {
+        source: Box<Node>,
+        name: String,
+        alias: Option<String>,
+        columns: Vec<Option<String>>,
+    },
+    /// Evaluates window functions, appending their values to the source rows.
+    Window {
+        source: Box<Node>,
+        functions: Vec<WindowFunction>,
+    },
+    /// The work table of a recursive CTE, which is replaced by the previous iteration's rows
+    /// before each evaluation of the recursive term.
+    WorkTable {
+        name: String,
+        id: usize,
     },
 }
 
@@ -135,40 +627,97 @@ impl Node {
     {
         self = before(self)?;
         self = match self {
-            n @ Self::CreateTable { .. }
+            n @ Self::AddColumn { .. }
+            | n @ Self::Analyze { .. }
+            | n @ Self::CopyFrom { .. }
+            | n @ Self::CopyTo { .. }
+            | n @ Self::CreateTable { .. }
+            | n @ Self::CreateUser { .. }
+            | n @ Self::CreateView { .. }
+            | n @ Self::DropColumn { .. }
             | n @ Self::DropTable { .. }
+            | n @ Self::DropUser { .. }
+            | n @ Self::DropView { .. }
+            | n @ Self::Grant { .. }
             | n @ Self::IndexLookup { .. }
-            | n @ Self::Insert { .. }
+            | n @ Self::IndexOrderScan { .. }
+            | n @ Self::IndexPrefixScan { .. }
+            | n @ Self::IndexRangeScan { .. }
             | n @ Self::KeyLookup { .. }
+            | n @ Self::KeyRangeScan { .. }
             | n @ Self::Nothing
-            | n @ Self::Scan { .. } => n,
+            | n @ Self::RenameColumn { .. }
+            | n @ Self::RenameTable { .. }
+            | n @ Self::Revoke { .. }
+            | n @ Self::Scan { .. }
+            | n @ Self::SystemScan { .. }
+            | n @ Self::Truncate { .. }
+            | n @ Self::Values { .. }
+            | n @ Self::WorkTable { .. } => n,
 
             Self::Aggregation { source, aggregates } => {
                 Self::Aggregation { source: source.transform(before, after)?.into(), aggregates }
             }
-            Self::Delete { table, source } => {
-                Self::Delete { table, source: source.transform(before, after)?.into() }
+            Self::Cte { source, name, alias, columns } => {
+                Self::Cte { source: source.transform(before, after)?.into(), name, alias, columns }
+            }
+            Self::Delete { table, source, returning } => {
+                Self::Delete { table, source: source.transform(before, after)?.into(), returning }
+            }
+            Self::Distinct { source, on } => {
+                Self::Distinct { source: source.transform(before, after)?.into(), on }
             }
             Self::Filter { source, predicate } => {
                 Self::Filter { source: source.transform(before, after)?.into(), predicate }
             }
-            Self::HashJoin { left, left_field, right, right_field, outer } => Self::HashJoin {
-                left: left.transform(before, after)?.into(),
-                left_field,
-                right: right.transform(before, after)?.into(),
-                right_field,
-                outer,
+            Self::HashJoin { left, left_fields, right, right_fields, outer, full } => {
+                Self::HashJoin {
+                    left: left.transform(before, after)?.into(),
+                    left_fields,
+                    right: right.transform(before, after)?.into(),
+                    right_fields,
+                    outer,
+                    full,
+                }
+            }
+            Self::HashSemiJoin { left, left_fields, right, right_fields, anti, null_aware } => {
+                Self::HashSemiJoin {
+                    left: left.transform(before, after)?.into(),
+                    left_fields,
+                    right: right.transform(before, after)?.into(),
+                    right_fields,
+                    anti,
+                    null_aware,
+                }
+            }
+            Self::Insert { table, columns, source, on_conflict, returning } => Self::Insert {
+                table,
+                columns,
+                source: source.transform(before, after)?.into(),
+                on_conflict,
+                returning,
             },
             Self::Limit { source, limit } => {
                 Self::Limit { source: source.transform(before, after)?.into(), limit }
             }
-            Self::NestedLoopJoin { left, left_size, right, predicate, outer } => {
+            Self::MergeJoin { left, left_fields, right, right_fields, outer, full } => {
+                Self::MergeJoin {
+                    left: left.transform(before, after)?.into(),
+                    left_fields,
+                    right: right.transform(before, after)?.into(),
+                    right_fields,
+                    outer,
+                    full,
+                }
+            }
+            Self::NestedLoopJoin { left, left_size, right, predicate, outer, full } => {
                 Self::NestedLoopJoin {
                     left: left.transform(before, after)?.into(),
                     left_size,
                     right: right.transform(before, after)?.into(),
                     predicate,
                     outer,
+                    full,
                 }
             }
             Self::Offset { source, offset } => {
@@ -177,16 +726,558 @@ impl Node {
             Self::Order { source, orders } => {
                 Self::Order { source: source.transform(before, after)?.into(), orders }
             }
+            Self::Presorted { source, orders } => {
+                Self::Presorted { source: source.transform(before, after)?.into(), orders }
+            }
             Self::Projection { source, expressions } => {
                 Self::Projection { source: source.transform(before, after)?.into(), expressions }
             }
-            Self::Update { table, source, expressions } => {
-                Self::Update { table, source: source.transform(before, after)?.into(), expressions }
+            Self::RecursiveUnion { seed, recursive, name, id, all } => Self::RecursiveUnion {
+                seed: seed.transform(before, after)?.into(),
+                recursive: recursive.transform(before, after)?.into(),
+                name,
+                id,
+                all,
+            },
+            Self::SemiJoin { left, left_size, right, predicate, anti } => Self::SemiJoin {
+                left: left.transform(before, after)?.into(),
+                left_size,
+                right: right.transform(before, after)?.into(),
+                predicate,
+                anti,
+            },
+            Self::SetOperation { operator, left, right, all } => Self::SetOperation {
+                operator,
+                left: left.transform(before, after)?.into(),
+                right: right.transform(before, after)?.into(),
+                all,
+            },
+            Self::TopK { source, orders, limit } => {
+                Self::TopK { source: source.transform(before, after)?.into(), orders, limit }
+            }
+            Self::Update { table, source, expressions, returning } => Self::Update {
+                table,
+                source: source.transform(before, after)?.into(),
+                expressions,
+                returning,
+            },
+            Self::View { source, name, alias, columns } => {
+                Self::View { source: source.transform(before, after)?.into(), name, alias, columns }
+            }
+            Self::Window { source, functions } => {
+                Self::Window { source: source.transform(before, after)?.into(), functions }
             }
         };
         after(self)
     }
 
+    /// Returns the columns of the rows emitted by the node, i.e. the columns that its executor
+    /// returns in the result set, or the RETURNING columns of mutations, with their inferred
+    /// datatypes. Aggregates and window functions are named after their function. Nodes that
+    /// don't emit rows have no columns. Only the table schemas are read from the catalog.
+    pub fn columns<C: Catalog>(&self, catalog: &C) -> Result<Columns> {
+        Ok(match self {
+            Self::Aggregation { source, aggregates } => {
+                let mut columns = source.columns(catalog)?;
+                let mut input = 0;
+                let mut aggregated = Vec::new();
+                for aggregate in aggregates {
+                    let datatype = columns.get(input).and_then(|c| c.datatype.clone());
+                    aggregated.push(Column {
+                        name: Some(aggregate.name().into()),
+                        datatype: aggregate.datatype(datatype),
+                    });
+                    input += aggregate.inputs();
+                }
+                aggregated.extend(columns.drain(input.min(columns.len())..));
+                aggregated
+            }
+            Self::Cte { source, columns, .. } | Self::View { source, columns, .. } => columns
+                .iter()
+                .zip(source.columns(catalog)?)
+                .map(|(name, c)| Column { name: name.clone(), datatype: c.datatype })
+                .collect(),
+            Self::Delete { source, returning, .. } | Self::Update { source, returning, .. } => {
+                Self::returning_columns(returning, &source.columns(catalog)?, catalog)?
+            }
+            Self::Insert { table, returning, .. } => {
+                let columns = Self::table_columns(&catalog.must_read_table(table)?);
+                Self::returning_columns(returning, &columns, catalog)?
+            }
+            Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. }
+            | Self::Order { source, .. }
+            | Self::Presorted { source, .. }
+            | Self::TopK { source, .. } => source.columns(catalog)?,
+            Self::HashJoin { left, right, .. }
+            | Self::MergeJoin { left, right, .. }
+            | Self::NestedLoopJoin { left, right, .. } => {
+                let mut columns = left.columns(catalog)?;
+                columns.extend(right.columns(catalog)?);
+                columns
+            }
+            Self::IndexLookup { table, .. }
+            | Self::IndexOrderScan { table, .. }
+            | Self::IndexPrefixScan { table, .. }
+            | Self::IndexRangeScan { table, .. }
+            | Self::KeyLookup { table, .. }
+            | Self::KeyRangeScan { table, .. }
+            | Self::Scan { table, .. } => Self::table_columns(&catalog.must_read_table(table)?),
+            Self::Projection { source, expressions } => {
+                let columns = source.columns(catalog)?;
+                expressions
+                    .iter()
+                    .map(|(e, label)| {
+                        let datatype = e.datatype(&columns, catalog)?;
+                        Ok(match (label, e) {
+                            (Some(label), _) => Column { name: Some(label.clone()), datatype },
+                            (None, Expression::Field(i, _)) => Column {
+                                name: columns.get(*i).and_then(|c| c.name.clone()),
+                                datatype,
+                            },
+                            (None, Expression::Function(function, _)) => {
+                                Column { name: Some(function.to_string()), datatype }
+                            }
+                            (None, _) => Column { name: None, datatype },
+                        })
+                    })
+                    .collect::<Result<_>>()?
+            }
+            Self::HashSemiJoin { left, .. }
+            | Self::RecursiveUnion { seed: left, .. }
+            | Self::SemiJoin { left, .. } => left.columns(catalog)?,
+            // The datatype of columns that are NULL on the left is taken from the right.
+            Self::SetOperation { left, right, .. } => left
+                .columns(catalog)?
+                .into_iter()
+                .zip(right.columns(catalog)?)
+                .map(|(l, r)| Column { name: l.name, datatype: l.datatype.or(r.datatype) })
+                .collect(),
+            Self::SystemScan { table, .. } => table
+                .columns()
+                .into_iter()
+                .map(|(name, datatype)| Column {
+                    name: Some(name.into()),
+                    datatype: Some(datatype),
+                })
+                .collect(),
+            Self::Values { rows } => {
+                let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+                let mut columns: Columns =
+                    (0..width).map(|_| Column { name: None, datatype: None }).collect();
+                for row in rows {
+                    for (column, expr) in columns.iter_mut().zip(row) {
+                        if column.datatype.is_none() {
+                            column.datatype = expr.datatype(&[], catalog)?;
+                        }
+                    }
+                }
+                columns
+            }
+            Self::Window { source, functions } => {
+                let mut columns = source.columns(catalog)?;
+                let windowed = functions
+                    .iter()
+                    .map(|f| {
+                        let datatype = match f.args.first() {
+                            Some(arg) => arg.datatype(&columns, catalog)?,
+                            None => None,
+                        };
+                        Ok(Column {
+                            name: Some(f.window.name().into()),
+                            datatype: f.window.datatype(datatype),
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                columns.extend(windowed);
+                columns
+            }
+            Self::WorkTable { name, .. } => {
+                return Err(Error::Internal(format!("Unexpected work table for {}", name)))
+            }
+            Self::AddColumn { .. }
+            | Self::Analyze { .. }
+            | Self::CopyFrom { .. }
+            | Self::CopyTo { .. }
+            | Self::CreateTable { .. }
+            | Self::CreateUser { .. }
+            | Self::CreateView { .. }
+            | Self::DropColumn { .. }
+            | Self::DropTable { .. }
+            | Self::DropUser { .. }
+            | Self::DropView { .. }
+            | Self::Grant { .. }
+            | Self::Nothing
+            | Self::RenameColumn { .. }
+            | Self::RenameTable { .. }
+            | Self::Revoke { .. }
+            | Self::Truncate { .. } => Vec::new(),
+        })
+    }
+
+    /// Returns the columns of a table's rows.
+    fn table_columns(table: &Table) -> Columns {
+        table
+            .columns
+            .iter()
+            .map(|c| Column { name: Some(c.name.clone()), datatype: Some(c.datatype.clone()) })
+            .collect()
+    }
+
+    /// Returns the columns of a mutation's RETURNING expressions, evaluated on rows with the
+    /// given columns. Unlabeled fields are named after their column.
+    pub fn returning_columns<C: Catalog>(
+        returning: &[(Expression, Option<String>)],
+        columns: &[Column],
+        catalog: &C,
+    ) -> Result<Columns> {
+        returning
+            .iter()
+            .map(|(e, label)| {
+                let datatype = e.datatype(columns, catalog)?;
+                Ok(match (label, e) {
+                    (Some(label), _) => Column { name: Some(label.clone()), datatype },
+                    (None, Expression::Field(_, Some((_, name)))) => {
+                        Column { name: Some(name.clone()), datatype }
+                    }
+                    (None, _) => Column { name: None, datatype },
+                })
+            })
+            .collect()
+    }
+
+    /// Checks the types of the node tree's expressions without executing it, such that e.g. a
+    /// mutation with a type error doesn't modify any rows. Errors with Error::Value if an operator
+    /// is applied to incompatible types (see Expression::check()), or if a predicate isn't
+    /// boolean. The work table of a recursive CTE has unknown column types while checking.
+    pub fn check<C: Catalog>(&self, catalog: &C) -> Result<()> {
+        self.clone().transform(
+            &|n| match n {
+                Self::RecursiveUnion { seed, recursive, name, id, all } => {
+                    let recursive = recursive.transform(
+                        &|n| match n {
+                            Self::WorkTable { id: work, .. } if work == id => {
+                                Ok(Self::Values { rows: Vec::new() })
+                            }
+                            n => Ok(n),
+                        },
+                        &|n| Ok(n),
+                    )?;
+                    Ok(Self::RecursiveUnion { seed, recursive: Box::new(recursive), name, id, all })
+                }
+                n => {
+                    n.check_expressions(catalog)?;
+                    Ok(n)
+                }
+            },
+            &|n| Ok(n),
+        )?;
+        Ok(())
+    }
+
+    /// Checks that the user has the privileges required by the node itself, but not its children.
+    /// Helper for Plan::authorize().
+    fn authorize(&self, user: &User) -> Result<()> {
+        match self {
+            Self::AddColumn { .. }
+            | Self::CreateTable { .. }
+            | Self::CreateView { .. }
+            | Self::DropColumn { .. }
+            | Self::DropTable { .. }
+            | Self::DropView { .. }
+            | Self::RenameColumn { .. }
+            | Self::RenameTable { .. } => user.authorize_superuser("change the schema"),
+            Self::Analyze { .. } => user.authorize_superuser("analyze tables"),
+            Self::CopyFrom { .. } | Self::CopyTo { .. } => user.authorize_superuser("copy files"),
+            Self::CreateUser { .. }
+            | Self::DropUser { .. }
+            | Self::Grant { .. }
+            | Self::Revoke { .. } => user.authorize_superuser("manage users"),
+
+            Self::IndexLookup { table, .. }
+            | Self::IndexOrderScan { table, .. }
+            | Self::IndexPrefixScan { table, .. }
+            | Self::IndexRangeScan { table, .. }
+            | Self::KeyLookup { table, .. }
+            | Self::KeyRangeScan { table, .. }
+            | Self::Scan { table, .. } => user.authorize(table, Privilege::Select),
+
+            // The rows to delete or update are read by a scan of the table, which requires SELECT.
+            Self::Delete { table, .. } => user.authorize(table, Privilege::Delete),
+            Self::Update { table, .. } => user.authorize(table, Privilege::Update),
+            Self::Insert { table, on_conflict, returning, .. } => {
+                user.authorize(table, Privilege::Insert)?;
+                if matches!(on_conflict, Some(c) if c.update.is_some()) {
+                    user.authorize(table, Privilege::Update)?;
+                }
+                if !returning.is_empty() {
+                    user.authorize(table, Privilege::Select)?;
+                }
+                Ok(())
+            }
+            Self::Truncate { table } => user.authorize(table, Privilege::Delete),
+
+            Self::Aggregation { .. }
+            | Self::Cte { .. }
+            | Self::Distinct { .. }
+            | Self::Filter { .. }
+            | Self::HashJoin { .. }
+            | Self::HashSemiJoin { .. }
+            | Self::Limit { .. }
+            | Self::MergeJoin { .. }
+            | Self::NestedLoopJoin { .. }
+            | Self::Nothing
+            | Self::Offset { .. }
+            | Self::Order { .. }
+            | Self::Presorted { .. }
+            | Self::Projection { .. }
+            | Self::RecursiveUnion { .. }
+            | Self::SemiJoin { .. }
+            | Self::SetOperation { .. }
+            | Self::SystemScan { .. }
+            | Self::TopK { .. }
+            | Self::Values { .. }
+            | Self::View { .. }
+            | Self::Window { .. }
+            | Self::WorkTable { .. } => Ok(()),
+        }
+    }
+
+    /// Checks the node's own expressions against the columns of the rows they're evaluated on.
+    /// Helper for check().
+    fn check_expressions<C: Catalog>(&self, catalog: &C) -> Result<()> {
+        let check =
+            |exprs: &mut dyn Iterator<Item = &Expression>, columns: &[Column]| -> Result<()> {
+                for expr in exprs {
+                    expr.check(columns, catalog)?;
+                }
+                Ok(())
+            };
+        let predicate = |kind: &str, expr: &Expression, columns: &[Column]| -> Result<()> {
+            expr.check(columns, catalog)?;
+            match expr.datatype(columns, catalog)? {
+                Some(DataType::Boolean) | None => Ok(()),
+                Some(datatype) => Err(Error::Value(format!(
+                    "{} {} has type {}, expected BOOLEAN",
+                    kind, expr, datatype
+                ))),
+            }
+        };
+        match self {
+            Self::Filter { source, predicate: expr } => {
+                predicate("Filter predicate", expr, &source.columns(catalog)?)?
+            }
+            Self::IndexOrderScan { table, filter: Some(expr), .. }
+            | Self::Scan { table, filter: Some(expr), .. } => {
+                let columns = Self::table_columns(&catalog.must_read_table(table)?);
+                predicate("Filter predicate", expr, &columns)?
+            }
+            Self::NestedLoopJoin { left, right, predicate: Some(expr), .. }
+            | Self::SemiJoin { left, right, predicate: Some(expr), .. } => {
+                let mut columns = left.columns(catalog)?;
+                columns.extend(right.columns(catalog)?);
+                predicate("Join predicate", expr, &columns)?
+            }
+            Self::Delete { source, returning, .. } => {
+                check(&mut returning.iter().map(|(e, _)| e), &source.columns(catalog)?)?
+            }
+            Self::Distinct { source, on } => check(&mut on.iter(), &source.columns(catalog)?)?,
+            Self::Insert { table, returning, .. } => {
+                let columns = Self::table_columns(&catalog.must_read_table(table)?);
+                check(&mut returning.iter().map(|(e, _)| e), &columns)?
+            }
+            Self::Order { source, orders }
+            | Self::Presorted { source, orders }
+            | Self::TopK { source, orders, .. } => {
+                check(&mut orders.iter().map(|(e, _, _)| e), &source.columns(catalog)?)?
+            }
+            Self::Projection { source, expressions } => {
+                check(&mut expressions.iter().map(|(e, _)| e), &source.columns(catalog)?)?
+            }
+            Self::Update { source, expressions, returning, .. } => {
+                let columns = source.columns(catalog)?;
+                check(&mut expressions.iter().map(|(_, _, e)| e), &columns)?;
+                check(&mut returning.iter().map(|(e, _)| e), &columns)?
+            }
+            Self::Values { rows } => check(&mut rows.iter().flatten(), &[])?,
+            Self::Window { source, functions } => {
+                let columns = source.columns(catalog)?;
+                for function in functions {
+                    check(&mut function.args.iter(), &columns)?;
+                    check(&mut function.partition.iter(), &columns)?;
+                    check(&mut function.order.iter().map(|(e, _, _)| e), &columns)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Returns the known sort order of the rows emitted by the node, as field indexes and
+    /// directions: rows are ordered by the first field, then by the second, and so on. Table scans
+    /// emit rows in primary key order and index scans in index value order, while other nodes
+    /// preserve (part of) their source order, or have no known order. Only the table schemas are
+    /// read from the catalog.
+    pub fn ordering<C: Catalog>(&self, catalog: &C) -> Result<Vec<(usize, Direction)>> {
+        Ok(match self {
+            Self::Scan { table, .. } => {
+                let table = catalog.must_read_table(table)?;
+                Self::key_ordering(&table, table.get_primary_key_indexes())
+            }
+            Self::KeyRangeScan { table, .. } => {
+                let table = catalog.must_read_table(table)?;
+                Self::key_ordering(&table, table.get_primary_key_indexes())
+            }
+            Self::IndexPrefixScan { table, column, .. } => {
+                vec![(
+                    catalog.must_read_table(table)?.get_column_index(column)?,
+                    Direction::Ascending,
+                )]
+            }
+            // Rows with equal index values are emitted in primary key order.
+            Self::IndexRangeScan { table, column, .. } => {
+                let table = catalog.must_read_table(table)?;
+                let mut fields = vec![table.get_column_index(column)?];
+                fields.extend(table.get_primary_key_indexes());
+                Self::key_ordering(&table, fields)
+            }
+            // Rows with equal index values are emitted in primary key order, even when reversed.
+            Self::IndexOrderScan { table, column, reverse, .. } => {
+                let table = catalog.must_read_table(table)?;
+                let mut fields = vec![table.get_column_index(column)?];
+                fields.extend(table.get_primary_key_indexes());
+                let mut ordering = Self::key_ordering(&table, fields);
+                if *reverse {
+                    ordering[0].1 = Direction::Descending;
+                }
+                ordering
+            }
+            Self::Cte { source, .. }
+            | Self::Distinct { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Offset { source, .. }
+            | Self::Presorted { source, .. }
+            | Self::View { source, .. } => source.ordering(catalog)?,
+            // Joins that emit the joined rows for each left row in turn preserve the left order,
+            // except for full outer joins which also emit unmatched right rows.
+            Self::HashSemiJoin { left, .. }
+            | Self::MergeJoin { left, full: false, .. }
+            | Self::NestedLoopJoin { left, full: false, .. }
+            | Self::SemiJoin { left, .. } => left.ordering(catalog)?,
+            // Orderings have the default NULL placement, so they end at any other placement.
+            Self::Order { orders, .. } | Self::TopK { orders, .. } => orders
+                .iter()
+                .map_while(|(expr, dir, nulls)| match expr {
+                    Expression::Field(i, _) if *nulls == Nulls::default_for(dir) => {
+                        Some((*i, dir.clone()))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            Self::Projection { source, expressions } => source
+                .ordering(catalog)?
+                .into_iter()
+                .map_while(|(field, dir)| {
+                    let i = expressions
+                        .iter()
+                        .position(|(e, _)| matches!(e, Expression::Field(i, _) if *i == field))?;
+                    Some((i, dir))
+                })
+                .collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    /// Returns the ascending order of rows sorted by the key encoding of the given table fields.
+    /// Distinct float and decimal keys can compare as equal (e.g. -0.0 and 0.0), so rows are not
+    /// ordered by any fields following them.
+    fn key_ordering(table: &Table, fields: Vec<usize>) -> Vec<(usize, Direction)> {
+        let mut ordering = Vec::new();
+        for field in fields {
+            ordering.push((field, Direction::Ascending));
+            if matches!(
+                table.columns.get(field).map(|c| &c.datatype),
+                Some(DataType::Float) | Some(DataType::Decimal(..)) | None
+            ) {
+                break;
+            }
+        }
+        ordering
+    }
+
+    /// Estimates the number of rows emitted by a table scan or lookup node, using the table's
+    /// statistics. Returns None for other nodes, or if the table hasn't been analyzed.
+    pub fn estimate_rows<C: Catalog>(&self, catalog: &C) -> Result<Option<f64>> {
+        match self {
+            Self::IndexLookup { table, .. }
+            | Self::IndexOrderScan { table, .. }
+            | Self::IndexPrefixScan { table, .. }
+            | Self::IndexRangeScan { table, .. }
+            | Self::KeyLookup { table, .. }
+            | Self::KeyRangeScan { table, .. }
+            | Self::Scan { table, .. } => match catalog.read_table_stats(table)? {
+                Some(stats) => self.estimate_rows_with(&catalog.must_read_table(table)?, &stats),
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Estimates the number of rows emitted by a table scan or lookup node, using the given table
+    /// schema and statistics.
+    pub fn estimate_rows_with(&self, table: &Table, stats: &TableStats) -> Result<Option<f64>> {
+        Ok(Some(match self {
+            Self::IndexLookup { column, values, .. } => {
+                stats.estimate_lookup(table.get_column_index(column)?, values)
+            }
+            Self::IndexOrderScan { filter, limit, .. } => {
+                stats.estimate(filter.as_ref()).min(*limit as f64)
+            }
+            Self::IndexPrefixScan { .. } => stats.rows as f64 * TableStats::DEFAULT_SELECTIVITY,
+            Self::IndexRangeScan { column, range, .. } => {
+                stats.estimate_range(table.get_column_index(column)?, range)
+            }
+            Self::KeyLookup { keys, .. } => (keys.len() as f64).min(stats.rows as f64),
+            Self::KeyRangeScan { range, .. } => {
+                stats.estimate_range(table.get_column_index(&table.get_primary_key()?.name)?, range)
+            }
+            Self::Scan { filter, .. } => stats.estimate(filter.as_ref()),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Estimates the number of rows emitted by each node in pre-order, see estimate_rows().
+    fn estimate_all<C: Catalog>(&self, catalog: &C) -> Result<Vec<Option<f64>>> {
+        let estimates = RefCell::new(Vec::new());
+        self.clone().transform(
+            &|n| {
+                estimates.borrow_mut().push(n.estimate_rows(catalog)?);
+                Ok(n)
+            },
+            &|n| Ok(n),
+        )?;
+        Ok(estimates.into_inner())
+    }
+
+    /// Replaces all subquery expressions (EXISTS, IN, and scalar subqueries) in the node tree by
+    /// the result of the given closure. Nested subqueries are not visited.
+    fn transform_subqueries<F>(self, f: &F) -> Result<Self>
+    where
+        F: Fn(Expression) -> Result<Expression>,
+    {
+        self.transform(&|n| Ok(n), &|n| {
+            n.transform_expressions(&|e| Ok(e), &|e| match e {
+                e @ Expression::Exists(_)
+                | e @ Expression::InSubquery(_, _)
+                | e @ Expression::Subquery(_) => f(e),
+                e => Ok(e),
+            })
+        })
+    }
+
     /// Transforms all expressions in a node by calling .transform() on them with the given closure.
     pub fn transform_expressions<B, A>(self, before: &B, after: &A) -> Result<Self>
     where
@@ -194,69 +1285,260 @@ impl Node {
         A: Fn(Expression) -> Result<Expression>,
     {
         Ok(match self {
-            n @ Self::Aggregation { .. }
+            n @ Self::AddColumn { .. }
+            | n @ Self::Aggregation { .. }
+            | n @ Self::Analyze { .. }
+            | n @ Self::CopyFrom { .. }
+            | n @ Self::CopyTo { .. }
             | n @ Self::CreateTable { .. }
-            | n @ Self::Delete { .. }
+            | n @ Self::CreateUser { .. }
+            | n @ Self::CreateView { .. }
+            | n @ Self::Cte { .. }
+            | n @ Self::DropColumn { .. }
             | n @ Self::DropTable { .. }
+            | n @ Self::DropUser { .. }
+            | n @ Self::DropView { .. }
+            | n @ Self::Grant { .. }
             | n @ Self::HashJoin { .. }
+            | n @ Self::HashSemiJoin { .. }
             | n @ Self::IndexLookup { .. }
+            | n @ Self::IndexPrefixScan { .. }
+            | n @ Self::IndexRangeScan { .. }
             | n @ Self::KeyLookup { .. }
+            | n @ Self::KeyRangeScan { .. }
             | n @ Self::Limit { .. }
+            | n @ Self::MergeJoin { .. }
             | n @ Self::NestedLoopJoin { predicate: None, .. }
             | n @ Self::Nothing
             | n @ Self::Offset { .. }
-            | n @ Self::Scan { filter: None, .. } => n,
+            | n @ Self::RecursiveUnion { .. }
+            | n @ Self::RenameColumn { .. }
+            | n @ Self::RenameTable { .. }
+            | n @ Self::Revoke { .. }
+            | n @ Self::IndexOrderScan { filter: None, .. }
+            | n @ Self::Scan { filter: None, .. }
+            | n @ Self::SemiJoin { predicate: None, .. }
+            | n @ Self::SetOperation { .. }
+            | n @ Self::SystemScan { .. }
+            | n @ Self::Truncate { .. }
+            | n @ Self::View { .. }
+            | n @ Self::WorkTable { .. } => n,
 
             Self::Filter { source, predicate } => {
                 Self::Filter { source, predicate: predicate.transform(before, after)? }
             }
-            Self::Insert { table, columns, expressions } => Self::Insert {
+            Self::Order { source, orders } => Self::Order {
+                source,
+                orders: orders
+                    .into_iter()
+                    .map(|(e, o, n)| e.transform(before, after).map(|e| (e, o, n)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::Presorted { source, orders } => Self::Presorted {
+                source,
+                orders: orders
+                    .into_iter()
+                    .map(|(e, o, n)| e.transform(before, after).map(|e| (e, o, n)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::NestedLoopJoin {
+                left,
+                left_size,
+                right,
+                predicate: Some(predicate),
+                outer,
+                full,
+            } => Self::NestedLoopJoin {
+                left,
+                left_size,
+                right,
+                predicate: Some(predicate.transform(before, after)?),
+                outer,
+                full,
+            },
+            Self::Delete { table, source, returning } => Self::Delete {
+                table,
+                source,
+                returning: returning
+                    .into_iter()
+                    .map(|(e, l)| Ok((e.transform(before, after)?, l)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::Distinct { source, on } => Self::Distinct {
+                source,
+                on: on.into_iter().map(|e| e.transform(before, after)).collect::<Result<_>>()?,
+            },
+            Self::Insert { table, columns, source, on_conflict, returning } => Self::Insert {
+                table,
+                columns,
+                source,
+                on_conflict,
+                returning: returning
+                    .into_iter()
+                    .map(|(e, l)| Ok((e.transform(before, after)?, l)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::Projection { source, expressions } => Self::Projection {
+                source,
+                expressions: expressions
+                    .into_iter()
+                    .map(|(e, l)| Ok((e.transform(before, after)?, l)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::Scan { table, alias, filter: Some(filter), columns } => {
+                Self::Scan { table, alias, filter: Some(filter.transform(before, after)?), columns }
+            }
+            Self::IndexOrderScan {
                 table,
+                alias,
+                column,
+                filter: Some(filter),
+                reverse,
+                limit,
                 columns,
-                expressions: expressions
-                    .into_iter()
-                    .map(|exprs| exprs.into_iter().map(|e| e.transform(before, after)).collect())
-                    .collect::<Result<_>>()?,
+                index_only,
+            } => Self::IndexOrderScan {
+                table,
+                alias,
+                column,
+                filter: Some(filter.transform(before, after)?),
+                reverse,
+                limit,
+                columns,
+                index_only,
             },
-            Self::Order { source, orders } => Self::Order {
+            Self::TopK { source, orders, limit } => Self::TopK {
                 source,
                 orders: orders
                     .into_iter()
-                    .map(|(e, o)| e.transform(before, after).map(|e| (e, o)))
+                    .map(|(e, o, n)| e.transform(before, after).map(|e| (e, o, n)))
                     .collect::<Result<_>>()?,
+                limit,
             },
-            Self::NestedLoopJoin { left, left_size, right, predicate: Some(predicate), outer } => {
-                Self::NestedLoopJoin {
+            Self::SemiJoin { left, left_size, right, predicate: Some(predicate), anti } => {
+                Self::SemiJoin {
                     left,
                     left_size,
                     right,
                     predicate: Some(predicate.transform(before, after)?),
-                    outer,
+                    anti,
                 }
             }
-            Self::Projection { source, expressions } => Self::Projection {
+            Self::Update { table, source, expressions, returning } => Self::Update {
+                table,
                 source,
                 expressions: expressions
+                    .into_iter()
+                    .map(|(i, l, e)| e.transform(before, after).map(|e| (i, l, e)))
+                    .collect::<Result<_>>()?,
+                returning: returning
                     .into_iter()
                     .map(|(e, l)| Ok((e.transform(before, after)?, l)))
                     .collect::<Result<_>>()?,
             },
-            Self::Scan { table, alias, filter: Some(filter) } => {
-                Self::Scan { table, alias, filter: Some(filter.transform(before, after)?) }
-            }
-            Self::Update { table, source, expressions } => Self::Update {
-                table,
+            Self::Values { rows } => Self::Values {
+                rows: rows
+                    .into_iter()
+                    .map(|exprs| exprs.into_iter().map(|e| e.transform(before, after)).collect())
+                    .collect::<Result<_>>()?,
+            },
+            Self::Window { source, functions } => Self::Window {
                 source,
-                expressions: expressions
+                functions: functions
                     .into_iter()
-                    .map(|(i, l, e)| e.transform(before, after).map(|e| (i, l, e)))
+                    .map(|f| f.transform_expressions(before, after))
                     .collect::<Result<_>>()?,
             },
         })
     }
 
+    // Formats the columns read by a scan node, if not all, e.g. " [id, title]".
+    fn format_columns(columns: &Option<Vec<String>>) -> String {
+        match columns {
+            Some(columns) => format!(" [{}]", columns.join(", ")),
+            None => String::new(),
+        }
+    }
+
+    // Formats the index column of an index scan node, noting index-only scans.
+    fn format_index_column(column: &str, index_only: bool) -> String {
+        match index_only {
+            true => format!(" column {} index only", column),
+            false => format!(" column {}", column),
+        }
+    }
+
+    // Formats a value range for display, e.g. ">= 1 AND < 5".
+    fn format_range(range: &(Bound<Value>, Bound<Value>)) -> String {
+        let bounds = [
+            match &range.0 {
+                Bound::Included(v) => Some(format!(">= {}", v)),
+                Bound::Excluded(v) => Some(format!("> {}", v)),
+                Bound::Unbounded => None,
+            },
+            match &range.1 {
+                Bound::Included(v) => Some(format!("<= {}", v)),
+                Bound::Excluded(v) => Some(format!("< {}", v)),
+                Bound::Unbounded => None,
+            },
+        ];
+        bounds.iter().flatten().cloned().collect::<Vec<_>>().join(" AND ")
+    }
+
+    // Formats a RETURNING clause of a mutation node, if any.
+    fn format_returning(returning: &[(Expression, Option<String>)]) -> String {
+        if returning.is_empty() {
+            return String::new();
+        }
+        format!(
+            " returning {}",
+            returning.iter().map(|(expr, _)| expr.to_string()).collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    // Formats a join type for display.
+    fn format_privileges(privileges: &[Privilege]) -> String {
+        privileges.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+    }
+
+    fn format_join_type(outer: bool, full: bool) -> &'static str {
+        match (outer, full) {
+            (true, true) => "full outer",
+            (true, false) => "outer",
+            (false, _) => "inner",
+        }
+    }
+
+    // Formats the fields of a hash or merge join for display, as equalities joined by AND.
+    fn format_join_fields(left_fields: &[JoinField], right_fields: &[JoinField]) -> String {
+        let format = |field: &JoinField, side: &str| match field {
+            (_, Some((Some(t), n))) => format!("{}.{}", t, n),
+            (_, Some((None, n))) => n.clone(),
+            (i, None) => format!("{} #{}", side, i),
+        };
+        left_fields
+            .iter()
+            .zip(right_fields)
+            .map(|(l, r)| format!("{} = {}", format(l, "left"), format(r, "right")))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    }
+
     // Displays the node, where prefix gives the node prefix.
-    pub fn format(&self, mut indent: String, root: bool, last: bool) -> String {
+    pub fn format(&self, indent: String, root: bool, last: bool) -> String {
+        self.format_annotated(indent, root, last, &mut || String::new())
+    }
+
+    // Displays the node like format(), appending the annotation returned by annotate to each
+    // node's line. It is called for each node in pre-order.
+    pub fn format_annotated(
+        &self,
+        mut indent: String,
+        root: bool,
+        last: bool,
+        annotate: &mut dyn FnMut() -> String,
+    ) -> String {
+        let annotation = annotate();
         let mut s = indent.clone();
         if !last {
             s += "├─ ";
@@ -266,51 +1548,127 @@ impl Node {
             indent += "   ";
         }
         match self {
+            Self::AddColumn { table, column } => {
+                s += &format!("AddColumn: {}.{}\n", table, column.name);
+            }
             Self::Aggregation { source, aggregates } => {
                 s += &format!(
                     "Aggregation: {}\n",
                     aggregates.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
                 );
-                s += &source.format(indent, false, true);
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::Analyze { tables } => {
+                s += &format!("Analyze: {}\n", tables.join(", "));
+            }
+            Self::CopyFrom { table, path, .. } => {
+                s += &format!("CopyFrom: {} from {}\n", table, format_value(&path.clone().into()));
+            }
+            Self::CopyTo { table, path, .. } => {
+                s += &format!("CopyTo: {} to {}\n", table, format_value(&path.clone().into()));
+            }
+            Self::CreateTable { schema, if_not_exists } => {
+                s += &format!("CreateTable: {}", schema.name);
+                if *if_not_exists {
+                    s += " (if not exists)";
+                }
+                s += "\n";
+            }
+            Self::CreateUser { user } => {
+                s += &format!("CreateUser: {}", user.name);
+                if user.superuser {
+                    s += " (superuser)";
+                }
+                s += "\n";
+            }
+            Self::CreateView { view } => {
+                s += &format!("CreateView: {}\n", view.name);
+            }
+            Self::Cte { source, name, alias, columns: _ } => {
+                s += &format!("CTE: {}", name);
+                if let Some(alias) = alias {
+                    s += &format!(" as {}", alias);
+                }
+                s += " (inlined)\n";
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::Delete { source, table, returning } => {
+                s += &format!("Delete: {}{}\n", table, Self::format_returning(returning));
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::Distinct { source, on } => {
+                s += "Distinct";
+                if !on.is_empty() {
+                    s += &format!(
+                        " on: {}",
+                        on.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+                    );
+                }
+                s += "\n";
+                s += &source.format_annotated(indent, false, true, annotate);
             }
-            Self::CreateTable { schema } => {
-                s += &format!("CreateTable: {}\n", schema.name);
+            Self::DropColumn { table, column } => {
+                s += &format!("DropColumn: {}.{}\n", table, column);
             }
-            Self::Delete { source, table } => {
-                s += &format!("Delete: {}\n", table);
-                s += &source.format(indent, false, true);
+            Self::DropTable { table, if_exists } => {
+                s += &format!("DropTable: {}", table);
+                if *if_exists {
+                    s += " (if exists)";
+                }
+                s += "\n";
+            }
+            Self::DropUser { user, if_exists } => {
+                s += &format!("DropUser: {}", user);
+                if *if_exists {
+                    s += " (if exists)";
+                }
+                s += "\n";
             }
-            Self::DropTable { table } => {
-                s += &format!("DropTable: {}\n", table);
+            Self::DropView { view, if_exists } => {
+                s += &format!("DropView: {}", view);
+                if *if_exists {
+                    s += " (if exists)";
+                }
+                s += "\n";
             }
             Self::Filter { source, predicate } => {
                 s += &format!("Filter: {}\n", predicate);
-                s += &source.format(indent, false, true);
+                s += &source.format_annotated(indent, false, true, annotate);
             }
-            Self::HashJoin { left, left_field, right, right_field, outer } => {
+            Self::Grant { privileges, table, user } => {
                 s += &format!(
-                    "HashJoin: {} on {} = {}\n",
-                    if *outer { "outer" } else { "inner" },
-                    match left_field {
-                        (_, Some((Some(t), n))) => format!("{}.{}", t, n),
-                        (_, Some((None, n))) => n.clone(),
-                        (i, None) => format!("left #{}", i),
-                    },
-                    match right_field {
-                        (_, Some((Some(t), n))) => format!("{}.{}", t, n),
-                        (_, Some((None, n))) => n.clone(),
-                        (i, None) => format!("right #{}", i),
-                    },
+                    "Grant: {} on {} to {}\n",
+                    Self::format_privileges(privileges),
+                    table,
+                    user
+                );
+            }
+            Self::HashJoin { left, left_fields, right, right_fields, outer, full } => {
+                s += &format!(
+                    "HashJoin: {} on {}\n",
+                    Self::format_join_type(*outer, *full),
+                    Self::format_join_fields(left_fields, right_fields),
+                );
+                s += &left.format_annotated(indent.clone(), false, false, annotate);
+                s += &right.format_annotated(indent, false, true, annotate);
+            }
+            Self::HashSemiJoin { left, left_fields, right, right_fields, anti, null_aware } => {
+                s += &format!(
+                    "HashSemiJoin: {}{} on {}\n",
+                    if *anti { "anti" } else { "semi" },
+                    if *null_aware { " null-aware" } else { "" },
+                    Self::format_join_fields(left_fields, right_fields),
                 );
-                s += &left.format(indent.clone(), false, false);
-                s += &right.format(indent, false, true);
+                s += &left.format_annotated(indent.clone(), false, false, annotate);
+                s += &right.format_annotated(indent, false, true, annotate);
             }
-            Self::IndexLookup { table, column, alias, values } => {
+            Self::IndexLookup { table, column, alias, values, columns, index_only } => {
                 s += &format!("IndexLookup: {}", table);
                 if let Some(alias) = alias {
                     s += &format!(" as {}", alias);
                 }
-                s += &format!(" column {}", column);
+                s += &Self::format_columns(columns);
+                s += &Self::format_index_column(column, *index_only);
                 if !values.is_empty() && values.len() < 10 {
                     s += &format!(
                         " ({})",
@@ -321,14 +1679,64 @@ impl Node {
                 }
                 s += "\n";
             }
-            Self::Insert { table, columns: _, expressions } => {
-                s += &format!("Insert: {} ({} rows)\n", table, expressions.len());
+            Self::IndexOrderScan {
+                table,
+                alias,
+                column,
+                filter,
+                reverse,
+                limit,
+                columns,
+                index_only,
+            } => {
+                s += &format!("IndexOrderScan: {}", table);
+                if let Some(alias) = alias {
+                    s += &format!(" as {}", alias);
+                }
+                s += &Self::format_columns(columns);
+                s += &Self::format_index_column(column, *index_only);
+                if *reverse {
+                    s += " desc";
+                }
+                s += &format!(" limit {}", limit);
+                if let Some(expr) = filter {
+                    s += &format!(" ({})", expr);
+                }
+                s += "\n";
+            }
+            Self::IndexPrefixScan { table, alias, column, prefix, columns, index_only } => {
+                s += &format!("IndexPrefixScan: {}", table);
+                if let Some(alias) = alias {
+                    s += &format!(" as {}", alias);
+                }
+                s += &Self::format_columns(columns);
+                s += &Self::format_index_column(column, *index_only);
+                s += &format!(" prefix {}\n", prefix);
+            }
+            Self::IndexRangeScan { table, alias, column, range, columns, index_only } => {
+                s += &format!("IndexRangeScan: {}", table);
+                if let Some(alias) = alias {
+                    s += &format!(" as {}", alias);
+                }
+                s += &Self::format_columns(columns);
+                s += &Self::format_index_column(column, *index_only);
+                s += &format!(" range {}\n", Self::format_range(range));
+            }
+            Self::Insert { table, columns: _, source, on_conflict, returning } => {
+                s += &format!("Insert: {}", table);
+                if let Some(on_conflict) = on_conflict {
+                    s += &format!(" (on conflict {})", on_conflict);
+                }
+                s += &Self::format_returning(returning);
+                s += "\n";
+                s += &source.format_annotated(indent, false, true, annotate);
             }
-            Self::KeyLookup { table, alias, keys } => {
+            Self::KeyLookup { table, alias, keys, columns } => {
                 s += &format!("KeyLookup: {}", table);
                 if let Some(alias) = alias {
                     s += &format!(" as {}", alias);
                 }
+                s += &Self::format_columns(columns);
                 if !keys.is_empty() && keys.len() < 10 {
                     s += &format!(
                         " ({})",
@@ -339,36 +1747,64 @@ impl Node {
                 }
                 s += "\n";
             }
+            Self::KeyRangeScan { table, alias, range, columns } => {
+                s += &format!("KeyRangeScan: {}", table);
+                if let Some(alias) = alias {
+                    s += &format!(" as {}", alias);
+                }
+                s += &Self::format_columns(columns);
+                s += &format!(" range {}\n", Self::format_range(range));
+            }
             Self::Limit { source, limit } => {
                 s += &format!("Limit: {}\n", limit);
-                s += &source.format(indent, false, true);
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::MergeJoin { left, left_fields, right, right_fields, outer, full } => {
+                s += &format!(
+                    "MergeJoin: {} on {}\n",
+                    Self::format_join_type(*outer, *full),
+                    Self::format_join_fields(left_fields, right_fields),
+                );
+                s += &left.format_annotated(indent.clone(), false, false, annotate);
+                s += &right.format_annotated(indent, false, true, annotate);
             }
-            Self::NestedLoopJoin { left, left_size: _, right, predicate, outer } => {
-                s += &format!("NestedLoopJoin: {}", if *outer { "outer" } else { "inner" });
+            Self::NestedLoopJoin { left, left_size: _, right, predicate, outer, full } => {
+                s += &format!("NestedLoopJoin: {}", Self::format_join_type(*outer, *full));
                 if let Some(expr) = predicate {
                     s += &format!(" on {}", expr);
                 }
                 s += "\n";
-                s += &left.format(indent.clone(), false, false);
-                s += &right.format(indent, false, true);
+                s += &left.format_annotated(indent.clone(), false, false, annotate);
+                s += &right.format_annotated(indent, false, true, annotate);
             }
             Self::Nothing {} => {
                 s += "Nothing\n";
             }
             Self::Offset { source, offset } => {
                 s += &format!("Offset: {}\n", offset);
-                s += &source.format(indent, false, true);
+                s += &source.format_annotated(indent, false, true, annotate);
             }
             Self::Order { source, orders } => {
                 s += &format!(
                     "Order: {}\n",
                     orders
                         .iter()
-                        .map(|(expr, dir)| format!("{} {}", expr, dir))
+                        .map(|(expr, dir, nulls)| format_order(expr, dir, nulls))
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
-                s += &source.format(indent, false, true);
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::Presorted { source, orders } => {
+                s += &format!(
+                    "Presorted: {} (sort elided)\n",
+                    orders
+                        .iter()
+                        .map(|(expr, dir, nulls)| format_order(expr, dir, nulls))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                s += &source.format_annotated(indent, false, true, annotate);
             }
             Self::Projection { source, expressions } => {
                 s += &format!(
@@ -379,21 +1815,85 @@ impl Node {
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
-                s += &source.format(indent, false, true);
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::RecursiveUnion { seed, recursive, name, id: _, all } => {
+                s += &format!("RecursiveUnion: {}", name);
+                if *all {
+                    s += " (all)";
+                }
+                s += "\n";
+                s += &seed.format_annotated(indent.clone(), false, false, annotate);
+                s += &recursive.format_annotated(indent, false, true, annotate);
+            }
+            Self::RenameColumn { table, column, new_name } => {
+                s += &format!("RenameColumn: {}.{} to {}\n", table, column, new_name);
+            }
+            Self::RenameTable { table, new_name } => {
+                s += &format!("RenameTable: {} to {}\n", table, new_name);
+            }
+            Self::Revoke { privileges, table, user } => {
+                s += &format!(
+                    "Revoke: {} on {} from {}\n",
+                    Self::format_privileges(privileges),
+                    table,
+                    user
+                );
             }
-            Self::Scan { table, alias, filter } => {
+            Self::Scan { table, alias, filter, columns } => {
                 s += &format!("Scan: {}", table);
                 if let Some(alias) = alias {
                     s += &format!(" as {}", alias);
                 }
+                s += &Self::format_columns(columns);
                 if let Some(expr) = filter {
                     s += &format!(" ({})", expr);
                 }
                 s += "\n";
             }
-            Self::Update { source, table, expressions } => {
+            Self::SemiJoin { left, left_size: _, right, predicate, anti } => {
+                s += &format!("SemiJoin: {}", if *anti { "anti" } else { "semi" });
+                if let Some(expr) = predicate {
+                    s += &format!(" on {}", expr);
+                }
+                s += "\n";
+                s += &left.format_annotated(indent.clone(), false, false, annotate);
+                s += &right.format_annotated(indent, false, true, annotate);
+            }
+            Self::SetOperation { operator, left, right, all } => {
+                s += &format!("SetOperation: {}", operator);
+                if *all {
+                    s += " (all)";
+                }
+                s += "\n";
+                s += &left.format_annotated(indent.clone(), false, false, annotate);
+                s += &right.format_annotated(indent, false, true, annotate);
+            }
+            Self::SystemScan { table, alias } => {
+                s += &format!("SystemScan: {}", table);
+                if let Some(alias) = alias {
+                    s += &format!(" as {}", alias);
+                }
+                s += "\n";
+            }
+            Self::TopK { source, orders, limit } => {
+                s += &format!(
+                    "TopK: {} limit {}\n",
+                    orders
+                        .iter()
+                        .map(|(expr, dir, nulls)| format_order(expr, dir, nulls))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    limit
+                );
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::Truncate { table } => {
+                s += &format!("Truncate: {}\n", table);
+            }
+            Self::Update { source, table, expressions, returning } => {
                 s += &format!(
-                    "Update: {} ({})\n",
+                    "Update: {} ({}){}\n",
                     table,
                     expressions
                         .iter()
@@ -403,11 +1903,36 @@ impl Node {
                             e
                         ))
                         .collect::<Vec<_>>()
-                        .join(",")
+                        .join(","),
+                    Self::format_returning(returning)
+                );
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::Values { rows } => {
+                s += &format!("Values: {} rows\n", rows.len());
+            }
+            Self::View { source, name, alias, columns: _ } => {
+                s += &format!("View: {}", name);
+                if let Some(alias) = alias {
+                    s += &format!(" as {}", alias);
+                }
+                s += "\n";
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::Window { source, functions } => {
+                s += &format!(
+                    "Window: {}\n",
+                    functions.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(", ")
                 );
-                s += &source.format(indent, false, true);
+                s += &source.format_annotated(indent, false, true, annotate);
+            }
+            Self::WorkTable { name, id: _ } => {
+                s += &format!("WorkTable: {}\n", name);
             }
         };
+        if let Some(end) = s.find('\n') {
+            s.insert_str(end, &annotation);
+        }
         if root {
             s = s.trim_end().to_string()
         }
@@ -422,13 +1947,63 @@ impl Display for Node {
 }
 
 /// An aggregate operation
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Aggregate {
     Average,
     Count,
     Max,
     Min,
     Sum,
+    /// Concatenates non-NULL values as strings, with the given separator
+    StringAgg(String),
+    /// Aggregates distinct non-NULL values only
+    Distinct(Box<Aggregate>),
+    /// Aggregates values in the given order, sorting by additional input values
+    Ordered(Box<Aggregate>, Vec<(Direction, Nulls)>),
+}
+
+impl Aggregate {
+    /// Returns the number of input values of the aggregate: the aggregated value followed by any
+    /// sort keys.
+    pub fn inputs(&self) -> usize {
+        match self {
+            Self::Ordered(aggregate, orders) => aggregate.inputs() + orders.len(),
+            _ => 1,
+        }
+    }
+
+    /// Returns the name of the aggregate function, e.g. count.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Average => "avg",
+            Self::Count => "count",
+            Self::Max => "max",
+            Self::Min => "min",
+            Self::Sum => "sum",
+            Self::StringAgg(_) => "string_agg",
+            Self::Distinct(aggregate) | Self::Ordered(aggregate, _) => aggregate.name(),
+        }
+    }
+
+    /// Infers the datatype of the aggregate's result, given the datatype of its input values, if
+    /// known. This follows the accumulators, e.g. the average of integers is an integer.
+    pub fn datatype(&self, input: Option<DataType>) -> Option<DataType> {
+        match (self, input) {
+            (Self::Count, _) => Some(DataType::Integer),
+            (Self::StringAgg(_), _) => Some(DataType::String),
+            (Self::Average, Some(DataType::Decimal(_, scale))) => Some(DataType::Decimal(
+                DECIMAL_MAX_PRECISION,
+                (scale + DECIMAL_DIVISION_SCALE).min(DECIMAL_MAX_PRECISION),
+            )),
+            (Self::Sum, Some(DataType::Decimal(_, scale))) => {
+                Some(DataType::Decimal(DECIMAL_MAX_PRECISION, scale))
+            }
+            (Self::Average | Self::Max | Self::Min | Self::Sum, input) => input,
+            (Self::Distinct(aggregate) | Self::Ordered(aggregate, _), input) => {
+                aggregate.datatype(input)
+            }
+        }
+    }
 }
 
 impl Display for Aggregate {
@@ -437,6 +2012,23 @@ impl Display for Aggregate {
             f,
             "{}",
             match self {
+                Self::StringAgg(separator) => return write!(f, "string_agg {:?}", separator),
+                Self::Distinct(aggregate) => return write!(f, "{} distinct", aggregate),
+                Self::Ordered(aggregate, orders) => {
+                    return write!(
+                        f,
+                        "{} ordered {}",
+                        aggregate,
+                        orders
+                            .iter()
+                            .map(|(dir, nulls)| match nulls == &Nulls::default_for(dir) {
+                                true => dir.to_string(),
+                                false => format!("{} {}", dir, nulls),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
                 Self::Average => "average",
                 Self::Count => "count",
                 Self::Max => "maximum",
@@ -449,13 +2041,200 @@ impl Display for Aggregate {
 
 pub type Aggregates = Vec<Aggregate>;
 
+/// A hash join field, as a source field index and optional label.
+pub type JoinField = (usize, Option<(Option<String>, String)>);
+
+pub type JoinFields = Vec<JoinField>;
+
+/// A window function
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Window {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Aggregate(Aggregate),
+}
+
+impl Window {
+    /// Returns the name of the window function, e.g. row_number.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::RowNumber => "row_number",
+            Self::Rank => "rank",
+            Self::DenseRank => "dense_rank",
+            Self::Aggregate(aggregate) => aggregate.name(),
+        }
+    }
+
+    /// Infers the datatype of the window function's result, given the datatype of its first
+    /// argument, if any.
+    pub fn datatype(&self, input: Option<DataType>) -> Option<DataType> {
+        match self {
+            Self::RowNumber | Self::Rank | Self::DenseRank => Some(DataType::Integer),
+            Self::Aggregate(aggregate) => aggregate.datatype(input),
+        }
+    }
+}
+
+impl Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RowNumber => write!(f, "row_number"),
+            Self::Rank => write!(f, "rank"),
+            Self::DenseRank => write!(f, "dense_rank"),
+            Self::Aggregate(aggregate) => write!(f, "{}", aggregate),
+        }
+    }
+}
+
+/// A window function call. It is evaluated for each row over the rows in the same partition,
+/// sorted by the window order, up to and including the row's peers (rows with equal order values).
+/// Without an order, all rows in the partition are peers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowFunction {
+    pub window: Window,
+    pub args: Vec<Expression>,
+    pub partition: Vec<Expression>,
+    pub order: Vec<(Expression, Direction, Nulls)>,
+}
+
+impl WindowFunction {
+    /// Transforms all expressions of the function call by calling .transform() on them.
+    fn transform_expressions<B, A>(self, before: &B, after: &A) -> Result<Self>
+    where
+        B: Fn(Expression) -> Result<Expression>,
+        A: Fn(Expression) -> Result<Expression>,
+    {
+        Ok(Self {
+            window: self.window,
+            args: self
+                .args
+                .into_iter()
+                .map(|e| e.transform(before, after))
+                .collect::<Result<_>>()?,
+            partition: self
+                .partition
+                .into_iter()
+                .map(|e| e.transform(before, after))
+                .collect::<Result<_>>()?,
+            order: self
+                .order
+                .into_iter()
+                .map(|(e, o, n)| e.transform(before, after).map(|e| (e, o, n)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |exprs: Vec<String>| exprs.join(", ");
+        write!(
+            f,
+            "{}({}) over (",
+            self.window,
+            join(self.args.iter().map(|e| e.to_string()).collect())
+        )?;
+        if !self.partition.is_empty() {
+            write!(
+                f,
+                "partition by {}",
+                join(self.partition.iter().map(|e| e.to_string()).collect())
+            )?;
+            if !self.order.is_empty() {
+                write!(f, " ")?;
+            }
+        }
+        if !self.order.is_empty() {
+            write!(
+                f,
+                "order by {}",
+                join(self.order.iter().map(|(e, o, n)| format_order(e, o, n)).collect())
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A set operator
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+impl Display for SetOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Union => "union",
+                Self::Intersect => "intersect",
+                Self::Except => "except",
+            }
+        )
+    }
+}
+
+/// The conflict handling of an INSERT. A row conflicts with an existing row if they have equal
+/// values in the given columns, i.e. the primary key columns or a unique column. Conflicting rows
+/// are skipped, unless update expressions are given: the existing row is then updated by
+/// evaluating them on a row consisting of the existing row followed by the conflicting row.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OnConflict {
+    pub columns: Vec<usize>,
+    pub update: Option<Vec<(usize, Option<String>, Expression)>>,
+}
+
+impl Display for OnConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.update {
+            None => write!(f, "do nothing"),
+            Some(expressions) => write!(
+                f,
+                "do update {}",
+                expressions
+                    .iter()
+                    .map(|(i, l, e)| format!(
+                        "{}={}",
+                        l.clone().unwrap_or_else(|| format!("#{}", i)),
+                        e
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
 /// A sort order direction
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     Ascending,
     Descending,
 }
 
+impl Direction {
+    /// Compares two sort values in the direction, placing NULLs as given. Values that can't be
+    /// compared (e.g. of different datatypes) are considered equal.
+    pub fn compare(&self, nulls: &Nulls, a: &Value, b: &Value) -> Ordering {
+        match (a, b) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) if *nulls == Nulls::First => Ordering::Less,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) if *nulls == Nulls::First => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            (a, b) => match a.partial_cmp(b) {
+                Some(o) if *self == Self::Ascending => o,
+                Some(o) => o.reverse(),
+                None => Ordering::Equal,
+            },
+        }
+    }
+}
+
 impl Display for Direction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -468,3 +2247,42 @@ impl Display for Direction {
         )
     }
 }
+
+/// The placement of NULLs in a sort order. By default, NULLs sort before all other values like in
+/// indexes, i.e. first in ascending order and last in descending order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+impl Nulls {
+    /// Returns the default NULL placement for the given direction.
+    pub fn default_for(direction: &Direction) -> Self {
+        match direction {
+            Direction::Ascending => Self::First,
+            Direction::Descending => Self::Last,
+        }
+    }
+}
+
+impl Display for Nulls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::First => "nulls first",
+                Self::Last => "nulls last",
+            }
+        )
+    }
+}
+
+/// Formats a sort order, omitting the NULL placement if it's the default for the direction.
+fn format_order(expr: &Expression, direction: &Direction, nulls: &Nulls) -> String {
+    match nulls == &Nulls::default_for(direction) {
+        true => format!("{} {}", expr, direction),
+        false => format!("{} {} {}", expr, direction, nulls),
+    }
+}
@@ -1,9 +1,13 @@
-use super::super::schema::Catalog;
-use super::super::types::{Expression, Value};
-use super::Node;
-use crate::error::Result;
+use super::super::schema::{Catalog, Column, Table, TableStats};
+use super::super::types::{DataType, Expression, Pattern, Value};
+use super::{Direction, JoinFields, Node, Nulls};
+use crate::error::{Error, Result};
 
-use std::mem::replace;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::mem::{discriminant, replace};
+use std::ops::Bound;
 
 /// A plan optimizer
 pub trait Optimizer {
@@ -11,27 +15,128 @@ pub trait Optimizer {
 }
 
 /// A constant folding optimizer, which replaces constant expressions with their evaluated value, to
-/// prevent it from being re-evaluated over and over again during plan execution.
-pub struct ConstantFolder;
+/// prevent it from being re-evaluated over and over again during plan execution. Expressions that
+/// fail to evaluate are left as is, such that the error only occurs if they are actually evaluated
+/// (e.g. not for untaken CASE branches). CASE branches with constant conditions are pruned.
+/// Expressions that evaluate to NULL keep their inferred datatype as a typed NULL, e.g.
+/// CAST(NULL AS INTEGER), such that the datatypes of result columns don't change.
+pub struct ConstantFolder<'a, C: Catalog> {
+    catalog: &'a C,
+}
 
-impl Optimizer for ConstantFolder {
+impl<'a, C: Catalog> ConstantFolder<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for ConstantFolder<'a, C> {
     fn optimize(&self, node: Node) -> Result<Node> {
         node.transform(&|n| Ok(n), &|n| {
             n.transform_expressions(
                 &|e| {
                     if !e.contains(&|expr| matches!(expr, Expression::Field(_, _))) {
-                        Ok(Expression::Constant(e.evaluate(None)?))
+                        Ok(match e.evaluate(None) {
+                            Ok(Value::Null) => match e.datatype(&[], self.catalog)? {
+                                Some(datatype) => Expression::Cast(
+                                    Box::new(Expression::Constant(Value::Null)),
+                                    datatype,
+                                ),
+                                None => Expression::Constant(Value::Null),
+                            },
+                            Ok(value) => Expression::Constant(value),
+                            Err(_) => e,
+                        })
                     } else {
                         Ok(e)
                     }
                 },
-                &|e| Ok(e),
+                &|e| match e {
+                    Expression::Case(operand, branches, r#else) => {
+                        Ok(self.prune_case(operand, branches, r#else))
+                    }
+                    Expression::ILike(lhs, rhs, escape) => {
+                        Ok(self.compile_like(lhs, rhs, escape, true))
+                    }
+                    Expression::Like(lhs, rhs, escape) => {
+                        Ok(self.compile_like(lhs, rhs, escape, false))
+                    }
+                    e => Ok(e),
+                },
             )
         })
     }
 }
 
+impl<'a, C: Catalog> ConstantFolder<'a, C> {
+    /// Compiles LIKE and ILIKE operations with constant patterns, such that the pattern is only
+    /// compiled once rather than for every row. Invalid patterns are left as is, to error when
+    /// evaluated.
+    fn compile_like(
+        &self,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+        escape: Option<char>,
+        case_insensitive: bool,
+    ) -> Expression {
+        if let Expression::Constant(Value::String(pattern)) = &*rhs {
+            if let Ok(pattern) = Pattern::new(pattern.clone(), escape, case_insensitive) {
+                return Expression::Matches(lhs, pattern);
+            }
+        }
+        if case_insensitive {
+            Expression::ILike(lhs, rhs, escape)
+        } else {
+            Expression::Like(lhs, rhs, escape)
+        }
+    }
+
+    /// Prunes CASE branches whose conditions are constant: branches that can never match are
+    /// removed, and a branch that always matches becomes the ELSE result, or replaces the entire
+    /// expression if no branches precede it.
+    fn prune_case(
+        &self,
+        operand: Option<Box<Expression>>,
+        branches: Vec<(Expression, Expression)>,
+        mut r#else: Option<Box<Expression>>,
+    ) -> Expression {
+        let constant_operand = match operand.as_deref() {
+            Some(Expression::Constant(value)) => Some(Some(value)),
+            Some(_) => None,
+            None => Some(None),
+        };
+        let mut pruned = Vec::new();
+        for (condition, result) in branches {
+            let matched = match (&condition, constant_operand) {
+                (Expression::Constant(value), Some(None)) => Some(value.clone()),
+                (Expression::Constant(value), Some(Some(operand))) => Expression::Equal(
+                    Box::new(Expression::Constant(operand.clone())),
+                    Box::new(Expression::Constant(value.clone())),
+                )
+                .evaluate(None)
+                .ok(),
+                _ => None,
+            };
+            match matched {
+                Some(Value::Boolean(true)) if pruned.is_empty() => return result,
+                Some(Value::Boolean(true)) => {
+                    r#else = Some(Box::new(result));
+                    break;
+                }
+                Some(Value::Boolean(false)) | Some(Value::Null) => {}
+                _ => pruned.push((condition, result)),
+            }
+        }
+        if pruned.is_empty() {
+            return r#else.map(|e| *e).unwrap_or(Expression::Constant(Value::Null));
+        }
+        Expression::Case(operand, pruned, r#else)
+    }
+}
+
 /// A filter pushdown optimizer, which moves filter predicates into or closer to the source node.
+/// Predicates are pushed through projections, CTEs, and views, and split by the join sources they
+/// reference, such that they are evaluated as early as possible (e.g. as table scan filters).
 pub struct FilterPushdown;
 
 impl Optimizer for FilterPushdown {
@@ -51,15 +156,39 @@ impl Optimizer for FilterPushdown {
                         })
                     }
                 }
+                // Full outer joins emit unmatched rows from both sides, so predicates can't be
+                // pushed down into either source.
                 Node::NestedLoopJoin {
                     mut left,
                     left_size,
                     mut right,
                     predicate: Some(predicate),
                     outer,
+                    full: false,
                 } => {
-                    let predicate = self.pushdown_join(predicate, &mut left, &mut right, left_size);
-                    Ok(Node::NestedLoopJoin { left, left_size, right, predicate, outer })
+                    let predicate =
+                        self.pushdown_join(predicate, &mut left, &mut right, left_size, outer);
+                    Ok(Node::NestedLoopJoin {
+                        left,
+                        left_size,
+                        right,
+                        predicate,
+                        outer,
+                        full: false,
+                    })
+                }
+                // Anti-joins emit left rows that don't match, so left-only predicates can't be
+                // pushed down into the left source.
+                Node::SemiJoin {
+                    mut left,
+                    left_size,
+                    mut right,
+                    predicate: Some(predicate),
+                    anti: false,
+                } => {
+                    let predicate =
+                        self.pushdown_join(predicate, &mut left, &mut right, left_size, false);
+                    Ok(Node::SemiJoin { left, left_size, right, predicate, anti: false })
                 }
                 n => Ok(n),
             },
@@ -70,6 +199,8 @@ impl Optimizer for FilterPushdown {
 
 impl FilterPushdown {
     /// Attempts to push an expression down into a target node, returns any remaining expression.
+    /// Expressions pushed below a node are added as a filter node, which is itself pushed further
+    /// down as the transform descends into it.
     fn pushdown(&self, mut expression: Expression, target: &mut Node) -> Option<Expression> {
         match target {
             Node::Scan { ref mut filter, .. } => {
@@ -78,12 +209,66 @@ impl FilterPushdown {
                 }
                 filter.replace(expression)
             }
-            Node::NestedLoopJoin { ref mut predicate, .. } => {
+            // Inner join predicates are pushed into either source by pushdown_join().
+            Node::NestedLoopJoin { ref mut predicate, outer: false, full: false, .. } => {
                 if let Some(predicate) = predicate.take() {
                     expression = Expression::And(Box::new(expression), Box::new(predicate));
                 }
                 predicate.replace(expression)
             }
+            // Outer joins emit unmatched left rows extended with NULLs, so only expressions that
+            // reference the left source alone can be pushed down, into the left source. The rest
+            // must be evaluated on the joined rows.
+            Node::NestedLoopJoin { ref mut left, left_size, outer: true, full: false, .. } => {
+                let boundary = *left_size;
+                let (push, keep): (Vec<Expression>, Vec<Expression>) =
+                    expression.into_cnf_vec().into_iter().partition(|e| {
+                        !e.contains(&|e| matches!(e, Expression::Field(i, _) if *i >= boundary))
+                    });
+                if let Some(push) = Expression::from_cnf_vec(push) {
+                    Self::add_filter(left, push);
+                }
+                Expression::from_cnf_vec(keep)
+            }
+            // Semi-joins only emit left rows, and CTEs and views only rename their source columns.
+            Node::SemiJoin { left: ref mut source, .. }
+            | Node::Cte { ref mut source, .. }
+            | Node::View { ref mut source, .. } => {
+                Self::add_filter(source, expression);
+                None
+            }
+            // Field references are replaced by the projected expressions, unless these contain
+            // subqueries which would then be executed again. Filters aren't pushed into
+            // aggregations, so HAVING filters are left above the projection.
+            Node::Projection { ref mut source, ref expressions } => {
+                if let Node::Aggregation { .. } = **source {
+                    return Some(expression);
+                }
+                if expression.contains(&|e| match e {
+                    Expression::Field(i, _) => match expressions.get(*i) {
+                        Some((e, _)) => e.contains(&|e| {
+                            matches!(
+                                e,
+                                Expression::Exists(_)
+                                    | Expression::InSubquery(..)
+                                    | Expression::Subquery(_)
+                            )
+                        }),
+                        None => true,
+                    },
+                    _ => false,
+                }) {
+                    return Some(expression);
+                }
+                let expression = expression
+                    .transform(&|e| Ok(e), &|e| match e {
+                        Expression::Field(i, _) => Ok(expressions[i].0.clone()),
+                        e => Ok(e),
+                    })
+                    .unwrap();
+                Self::add_filter(source, expression);
+                None
+            }
             Node::Filter { ref mut predicate, .. } => {
                 let p = replace(predicate, Expression::Constant(Value::Null));
                 replace(predicate, Expression::And(Box::new(p), Box::new(expression)));
@@ -93,14 +278,22 @@ impl FilterPushdown {
         }
     }
 
+    /// Inserts a filter node with the given predicate above a node.
+    fn add_filter(node: &mut Box<Node>, predicate: Expression) {
+        let source = replace(&mut **node, Node::Nothing);
+        **node = Node::Filter { source: Box::new(source), predicate };
+    }
+
     /// Attempts to partition a join predicate and push parts of it down into either source,
-    /// returning any remaining expression.
+    /// returning any remaining expression. For outer joins, expressions that only reference the
+    /// left source are kept in the join predicate, since unmatched left rows are emitted anyway.
     fn pushdown_join(
         &self,
         predicate: Expression,
         left: &mut Node,
         right: &mut Node,
         boundary: usize,
+        outer: bool,
     ) -> Option<Expression> {
         // Convert the predicate into conjunctive normal form, and partition into expressions
         // only referencing the left or right sources, leaving cross-source expressions.
@@ -132,11 +325,16 @@ impl FilterPushdown {
                     if let Some(lvals) = push_left.iter().find_map(|e| e.as_lookup(*l)) {
                         push_right.push(Expression::from_lookup(*r, rn.clone(), lvals));
                     } else if let Some(rvals) = push_right.iter().find_map(|e| e.as_lookup(*r)) {
-                        push_left.push(Expression::from_lookup(*l, ln.clone(), rvals));
+                        if !outer {
+                            push_left.push(Expression::from_lookup(*l, ln.clone(), rvals));
+                        }
                     }
                 }
             }
         }
+        if outer {
+            cnf.append(&mut push_left);
+        }
 
         // Push predicates down into the sources.
         if let Some(push_left) = Expression::from_cnf_vec(push_left) {
@@ -155,24 +353,125 @@ impl FilterPushdown {
                     &|e| Ok(e),
                 )
                 .unwrap();
+            // Any remainder is evaluated on the joined rows, so its references are shifted back.
             if let Some(remainder) = self.pushdown(push_right, right) {
-                cnf.push(remainder)
+                cnf.push(
+                    remainder
+                        .transform(
+                            &|e| match e {
+                                Expression::Field(i, label) => {
+                                    Ok(Expression::Field(i + boundary, label))
+                                }
+                                e => Ok(e),
+                            },
+                            &|e| Ok(e),
+                        )
+                        .unwrap(),
+                )
             }
         }
         Expression::from_cnf_vec(cnf)
     }
 }
 
-/// An index lookup optimizer, which converts table scans to index lookups.
+/// An index lookup optimizer, which converts table scans to key or index lookups. If the table has
+/// been analyzed, the access path with the lowest estimated cost is chosen.
 pub struct IndexLookup<'a, C: Catalog> {
     catalog: &'a mut C,
 }
 
 impl<'a, C: Catalog> IndexLookup<'a, C> {
+    /// The cost of reading a row via a key or index lookup, relative to reading a row during a
+    /// full table scan, since lookups read each row individually rather than sequentially.
+    const LOOKUP_ROW_COST: f64 = 4.0;
+
     pub fn new(catalog: &'a mut C) -> Self {
         Self { catalog }
     }
 
+    // Looks up a composite primary key, if the CNF vector has a lookup for each key column. Removes
+    // these from the CNF vector, and returns all combinations of the looked up values as keys.
+    fn composite_lookup(
+        &self,
+        table: &Table,
+        cnf: &mut Vec<Expression>,
+    ) -> Result<Option<Vec<Value>>> {
+        let mut used = Vec::new();
+        let mut keys: Vec<Vec<Value>> = vec![Vec::new()];
+        for i in table.get_primary_key_indexes() {
+            let column = &table.columns[i];
+            let lookup = cnf
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| !used.contains(j))
+                .find_map(|(j, e)| e.as_lookup(i).map(|values| (j, values)));
+            let (j, values) = match lookup {
+                Some(lookup) => lookup,
+                None => return Ok(None),
+            };
+            used.push(j);
+            keys = keys
+                .into_iter()
+                .flat_map(|key| {
+                    values.iter().map(move |v| {
+                        let mut key = key.clone();
+                        key.push(column.lookup_value(v.clone()));
+                        key
+                    })
+                })
+                .collect();
+        }
+        used.sort_unstable();
+        for j in used.into_iter().rev() {
+            cnf.remove(j);
+        }
+        Ok(Some(keys.into_iter().map(|key| table.make_key(key)).collect::<Result<_>>()?))
+    }
+
+    // Estimates the cost of a lookup node, in terms of full table scan row reads.
+    fn cost(node: &Node, table: &Table, stats: &TableStats) -> Result<f64> {
+        let rows = node.estimate_rows_with(table, stats)?.unwrap_or(stats.rows as f64);
+        Ok(match node {
+            Node::IndexLookup { values, .. } => values.len() as f64 + rows * Self::LOOKUP_ROW_COST,
+            // Key range scans read rows sequentially, like a table scan.
+            Node::KeyRangeScan { .. } => rows,
+            _ => rows * Self::LOOKUP_ROW_COST,
+        })
+    }
+
+    // Combines a range bound with another bound on the given column, returning the narrower one
+    // (the one ordered as given). Bounds that don't have the column's datatype are ignored.
+    fn range_bound(
+        column: &Column,
+        bound: Bound<Value>,
+        other: Bound<Value>,
+        narrower: Ordering,
+    ) -> Bound<Value> {
+        let other = match other {
+            Bound::Included(v) => Bound::Included(column.lookup_value(v)),
+            Bound::Excluded(v) => Bound::Excluded(column.lookup_value(v)),
+            Bound::Unbounded => return bound,
+        };
+        match (&other, &bound) {
+            (Bound::Included(v), _) | (Bound::Excluded(v), _)
+                if v.datatype().map(|d| discriminant(&d))
+                    != Some(discriminant(&column.datatype)) =>
+            {
+                bound
+            }
+            (_, Bound::Unbounded) => other,
+            (Bound::Included(a), Bound::Included(b))
+            | (Bound::Included(a), Bound::Excluded(b))
+            | (Bound::Excluded(a), Bound::Included(b))
+            | (Bound::Excluded(a), Bound::Excluded(b)) => match a.partial_cmp(b) {
+                Some(ordering) if ordering == narrower => other,
+                Some(Ordering::Equal) if matches!(other, Bound::Excluded(_)) => other,
+                _ => bound,
+            },
+            (_, _) => bound,
+        }
+    }
+
     // Wraps a node in a filter for the given CNF vector, if any, otherwise returns the bare node.
     fn wrap_cnf(&self, node: Node, cnf: Vec<Expression>) -> Node {
         if let Some(predicate) = Expression::from_cnf_vec(cnf) {
@@ -186,35 +485,142 @@ impl<'a, C: Catalog> IndexLookup<'a, C> {
 impl<'a, C: Catalog> Optimizer for IndexLookup<'a, C> {
     fn optimize(&self, node: Node) -> Result<Node> {
         node.transform(&|n| Ok(n), &|n| match n {
-            Node::Scan { table, alias, filter: Some(filter) } => {
-                let columns = self.catalog.must_read_table(&table)?.columns;
-                let pk = columns.iter().position(|c| c.primary_key).unwrap();
+            Node::Scan { table, alias, filter: Some(filter), columns: read } => {
+                let schema = self.catalog.must_read_table(&table)?;
+                let key = schema.get_primary_key_indexes();
+                let columns = &schema.columns;
 
                 // Convert the filter into conjunctive normal form, and try to convert each
-                // sub-expression into a lookup. If a lookup is found, return a lookup node and then
-                // apply the remaining conjunctions as a filter node, if any.
-                let mut cnf = filter.clone().into_cnf_vec();
+                // sub-expression into a lookup. Each candidate lookup node is paired with the
+                // remaining conjunctions, which are applied as a filter node, if any. Composite
+                // primary keys need a lookup for each key column.
+                let cnf = filter.clone().into_cnf_vec();
+                let mut lookups = Vec::new();
+                if key.len() > 1 {
+                    let mut cnf = cnf.clone();
+                    if let Some(keys) = self.composite_lookup(&schema, &mut cnf)? {
+                        let (table, alias, columns) = (table.clone(), alias.clone(), read.clone());
+                        lookups.push((Node::KeyLookup { table, alias, keys, columns }, cnf));
+                    }
+                }
                 for i in 0..cnf.len() {
-                    if let Some(keys) = cnf[i].as_lookup(pk) {
-                        cnf.remove(i);
-                        return Ok(self.wrap_cnf(Node::KeyLookup { table, alias, keys }, cnf));
+                    let mut rest = cnf.clone();
+                    rest.remove(i);
+                    if let [pk] = key.as_slice() {
+                        if let Some(keys) = cnf[i].as_lookup(*pk) {
+                            let keys =
+                                keys.into_iter().map(|k| columns[*pk].lookup_value(k)).collect();
+                            let (table, alias, columns) =
+                                (table.clone(), alias.clone(), read.clone());
+                            let node = Node::KeyLookup { table, alias, keys, columns };
+                            lookups.push((node, rest.clone()));
+                        }
                     }
                     for (ci, column) in columns.iter().enumerate().filter(|(_, c)| c.index) {
                         if let Some(values) = cnf[i].as_lookup(ci) {
-                            cnf.remove(i);
-                            return Ok(self.wrap_cnf(
+                            let values =
+                                values.into_iter().map(|v| column.lookup_value(v)).collect();
+                            lookups.push((
                                 Node::IndexLookup {
-                                    table,
-                                    alias,
+                                    table: table.clone(),
+                                    alias: alias.clone(),
                                     column: column.name.clone(),
                                     values,
+                                    columns: read.clone(),
+                                    index_only: false,
                                 },
-                                cnf,
+                                rest.clone(),
                             ));
                         }
                     }
                 }
-                Ok(Node::Scan { table, alias, filter: Some(filter) })
+
+                // Range comparisons on a primary key or indexed column can use a key or index range
+                // scan, combining the bounds of all comparisons on the column. Only bounds with the
+                // column's datatype are used, since keys are ordered by datatype first. The
+                // comparisons are still applied as a filter.
+                if let [pk] = key.as_slice() {
+                    let mut range = (Bound::Unbounded, Bound::Unbounded);
+                    for (start, end) in cnf.iter().filter_map(|e| e.as_range_lookup(*pk)) {
+                        range.0 =
+                            Self::range_bound(&columns[*pk], range.0, start, Ordering::Greater);
+                        range.1 = Self::range_bound(&columns[*pk], range.1, end, Ordering::Less);
+                    }
+                    if range != (Bound::Unbounded, Bound::Unbounded) {
+                        let (table, alias, columns) = (table.clone(), alias.clone(), read.clone());
+                        let node = Node::KeyRangeScan { table, alias, range, columns };
+                        lookups.push((node, cnf.clone()));
+                    }
+                }
+                for (ci, column) in columns.iter().enumerate().filter(|(_, c)| c.index) {
+                    let mut range = (Bound::Unbounded, Bound::Unbounded);
+                    for (start, end) in cnf.iter().filter_map(|e| e.as_range_lookup(ci)) {
+                        range.0 = Self::range_bound(column, range.0, start, Ordering::Greater);
+                        range.1 = Self::range_bound(column, range.1, end, Ordering::Less);
+                    }
+                    if range != (Bound::Unbounded, Bound::Unbounded) {
+                        lookups.push((
+                            Node::IndexRangeScan {
+                                table: table.clone(),
+                                alias: alias.clone(),
+                                column: column.name.clone(),
+                                range,
+                                columns: read.clone(),
+                                index_only: false,
+                            },
+                            cnf.clone(),
+                        ));
+                    }
+                }
+
+                // Without table statistics, use the first lookup found. Otherwise, use the
+                // cheapest lookup, unless a full table scan is cheaper.
+                match self.catalog.read_table_stats(&table)? {
+                    None => {
+                        if let Some((node, cnf)) = lookups.into_iter().next() {
+                            return Ok(self.wrap_cnf(node, cnf));
+                        }
+                    }
+                    Some(stats) if !lookups.is_empty() => {
+                        let mut best = None;
+                        let mut best_cost = stats.rows as f64;
+                        for (node, cnf) in lookups {
+                            let cost = Self::cost(&node, &schema, &stats)?;
+                            if cost < best_cost {
+                                best = Some((node, cnf));
+                                best_cost = cost;
+                            }
+                        }
+                        return Ok(match best {
+                            Some((node, cnf)) => self.wrap_cnf(node, cnf),
+                            None => {
+                                Node::Scan { table, alias, filter: Some(filter), columns: read }
+                            }
+                        });
+                    }
+                    Some(_) => {}
+                }
+                // Otherwise, try to convert a LIKE prefix match on an indexed string column into an
+                // index prefix scan. The LIKE match is still applied as a filter.
+                for (ci, column) in columns.iter().enumerate() {
+                    if !column.index || column.datatype != DataType::String {
+                        continue;
+                    }
+                    if let Some(prefix) = cnf.iter().find_map(|e| e.as_prefix_lookup(ci)) {
+                        return Ok(Node::Filter {
+                            source: Box::new(Node::IndexPrefixScan {
+                                table,
+                                alias,
+                                column: column.name.clone(),
+                                prefix,
+                                columns: read,
+                                index_only: false,
+                            }),
+                            predicate: filter,
+                        });
+                    }
+                }
+                Ok(Node::Scan { table, alias, filter: Some(filter), columns: read })
             }
             n => Ok(n),
         })
@@ -230,23 +636,30 @@ impl Optimizer for NoopCleaner {
     fn optimize(&self, node: Node) -> Result<Node> {
         use Expression::*;
         node.transform(
-            // While descending the node tree, clean up boolean expressions.
+            // While descending the node tree, clean up boolean expressions. These rewrites must
+            // hold under three-valued logic, since the expressions may be negated or projected:
+            // x AND NULL is NULL when x is true, and x OR NULL is NULL when x is false, so NULL
+            // operands are left alone. Two constant operands are only left unfolded by the
+            // constant folder if evaluation errors (e.g. 1 AND TRUE), so they're left alone too.
             &|n| {
                 n.transform_expressions(&|e| Ok(e), &|e| match &e {
+                    And(lhs, rhs) | Or(lhs, rhs)
+                        if matches!((&**lhs, &**rhs), (Constant(_), Constant(_))) =>
+                    {
+                        Ok(e)
+                    }
                     And(lhs, rhs) => match (&**lhs, &**rhs) {
                         (Constant(Value::Boolean(false)), _)
-                        | (Constant(Value::Null), _)
-                        | (_, Constant(Value::Boolean(false)))
-                        | (_, Constant(Value::Null)) => Ok(Constant(Value::Boolean(false))),
+                        | (_, Constant(Value::Boolean(false))) => {
+                            Ok(Constant(Value::Boolean(false)))
+                        }
                         (Constant(Value::Boolean(true)), e)
                         | (e, Constant(Value::Boolean(true))) => Ok(e.clone()),
                         _ => Ok(e),
                     },
                     Or(lhs, rhs) => match (&**lhs, &**rhs) {
                         (Constant(Value::Boolean(false)), e)
-                        | (Constant(Value::Null), e)
-                        | (e, Constant(Value::Boolean(false)))
-                        | (e, Constant(Value::Null)) => Ok(e.clone()),
+                        | (e, Constant(Value::Boolean(false))) => Ok(e.clone()),
                         (Constant(Value::Boolean(true)), _)
                         | (_, Constant(Value::Boolean(true))) => Ok(Constant(Value::Boolean(true))),
                         _ => Ok(e),
@@ -268,40 +681,1482 @@ impl Optimizer for NoopCleaner {
     }
 }
 
-// Optimizes join types, currently by swapping nested-loop joins with hash joins where appropriate.
-pub struct JoinType;
+/// Reorders groups of three or more inner (or cross) joined relations, based on their estimated
+/// cardinalities. The relations are joined in the left-deep order which minimizes the estimated
+/// number of intermediate rows, found by dynamic programming over the subsets of relations.
+/// Relations are only joined without a join predicate (i.e. as a cross product) if unavoidable.
+/// Outer joins are never reordered, but inner joins beneath them form separate groups. The order is
+/// only changed if all relations can be estimated (i.e. are analyzed tables) and the new order is
+/// estimated to be cheaper, in which case a projection restores the original column order.
+pub struct JoinOrder<'a, C: Catalog> {
+    catalog: &'a C,
+}
+
+/// A relation of a join group, i.e. a join source which is not itself an inner join.
+struct JoinRelation {
+    node: Node,
+    /// The estimated number of rows.
+    rows: f64,
+    /// The relation's table and statistics. Relations emit all table columns.
+    table: Table,
+    stats: TableStats,
+    /// The label of the relation's fields, i.e. its alias or table name.
+    label: String,
+    /// The index of the relation's first field in the group's rows.
+    offset: usize,
+}
+
+/// A predicate of a join group, with the relations it references as a bitmask.
+struct JoinPredicate {
+    expression: Expression,
+    relations: usize,
+    selectivity: f64,
+}
+
+impl<'a, C: Catalog> JoinOrder<'a, C> {
+    /// The maximum number of relations to reorder, since the search is exponential.
+    const MAX_RELATIONS: usize = 8;
+
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+
+    /// Returns a join group relation for a node, if it reads an analyzed table, i.e. a table scan
+    /// or lookup with an optional filter.
+    fn relation(&self, node: &Node) -> Result<Option<JoinRelation>> {
+        let (predicate, source) = match node {
+            Node::Filter { source, predicate } => (Some(predicate), &**source),
+            node => (None, node),
+        };
+        let (table, alias) = match source {
+            Node::IndexLookup { table, alias, .. }
+            | Node::IndexOrderScan { table, alias, .. }
+            | Node::IndexPrefixScan { table, alias, .. }
+            | Node::IndexRangeScan { table, alias, .. }
+            | Node::KeyLookup { table, alias, .. }
+            | Node::KeyRangeScan { table, alias, .. }
+            | Node::Scan { table, alias, .. } => (table, alias),
+            _ => return Ok(None),
+        };
+        let stats = match self.catalog.read_table_stats(table)? {
+            Some(stats) => stats,
+            None => return Ok(None),
+        };
+        let table = self.catalog.must_read_table(table)?;
+        let mut rows = match source.estimate_rows_with(&table, &stats)? {
+            Some(rows) => rows,
+            None => return Ok(None),
+        };
+        if let Some(predicate) = predicate {
+            rows *= match stats.rows {
+                0 => 0.0,
+                count => stats.estimate(Some(predicate)) / count as f64,
+            };
+        }
+        let label = alias.clone().unwrap_or_else(|| table.name.clone());
+        Ok(Some(JoinRelation { node: node.clone(), rows, table, stats, label, offset: 0 }))
+    }
+
+    /// Flattens a group of inner joins into its relations and CNF predicates, with fields
+    /// referring to the concatenated rows of the relations. The relations joined by each join are
+    /// appended to joins as (left, right) bitmasks, in post-order. Returns false if a relation
+    /// can't be estimated.
+    fn flatten(
+        &self,
+        node: &Node,
+        relations: &mut Vec<JoinRelation>,
+        predicates: &mut Vec<Expression>,
+        joins: &mut Vec<(usize, usize)>,
+    ) -> Result<bool> {
+        match node {
+            Node::NestedLoopJoin { left, right, predicate, outer: false, full: false, .. } => {
+                let (offset, start) = (Self::width(relations), relations.len());
+                if !self.flatten(left, relations, predicates, joins)? {
+                    return Ok(false);
+                }
+                let middle = relations.len();
+                if !self.flatten(right, relations, predicates, joins)? {
+                    return Ok(false);
+                }
+                if let Some(predicate) = predicate {
+                    for expr in predicate.clone().into_cnf_vec() {
+                        predicates.push(expr.transform(&|e| Ok(e), &|e| match e {
+                            Expression::Field(i, label) => Ok(Expression::Field(i + offset, label)),
+                            e => Ok(e),
+                        })?);
+                    }
+                }
+                let mask = |from: usize, to: usize| (from..to).fold(0, |mask, i| mask | 1 << i);
+                joins.push((mask(start, middle), mask(middle, relations.len())));
+                Ok(true)
+            }
+            node => match self.relation(node)? {
+                Some(mut relation) if relations.len() < Self::MAX_RELATIONS => {
+                    relation.offset = Self::width(relations);
+                    relations.push(relation);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+        }
+    }
+
+    /// Returns the total number of fields of the given relations.
+    fn width(relations: &[JoinRelation]) -> usize {
+        relations.last().map_or(0, |r| r.offset + r.table.columns.len())
+    }
+
+    /// Returns the index of the relation containing a field, and the field's table column index.
+    fn field(relations: &[JoinRelation], field: usize) -> (usize, usize) {
+        let i = relations.iter().rposition(|r| r.offset <= field).unwrap_or(0);
+        (i, field - relations[i].offset)
+    }
+
+    /// Estimates the selectivity of a join predicate. Equijoins of two relations' columns match a
+    /// row for each distinct value of the column with the most distinct values, assuming the
+    /// other column's values are contained in it. Other predicates use the default selectivity.
+    fn selectivity(relations: &[JoinRelation], expr: &Expression) -> f64 {
+        if let Expression::Equal(lhs, rhs) = expr {
+            if let (Expression::Field(l, _), Expression::Field(r, _)) = (&**lhs, &**rhs) {
+                let ((li, lc), (ri, rc)) = (Self::field(relations, *l), Self::field(relations, *r));
+                if li != ri {
+                    let distinct = |i: usize, c: usize| {
+                        relations[i].stats.columns.get(c).map_or(1, |c| c.distinct)
+                    };
+                    return 1.0 / distinct(li, lc).max(distinct(ri, rc)).max(1) as f64;
+                }
+            }
+        }
+        TableStats::DEFAULT_SELECTIVITY
+    }
+
+    /// Reorders a group of inner joins, if beneficial. Returns the node as-is otherwise.
+    fn reorder(&self, node: Node) -> Result<Node> {
+        let (mut relations, mut exprs, mut joins) = (Vec::new(), Vec::new(), Vec::new());
+        if !self.flatten(&node, &mut relations, &mut exprs, &mut joins)? || relations.len() < 3 {
+            return Ok(node);
+        }
+        let predicates: Vec<JoinPredicate> = exprs
+            .into_iter()
+            .map(|expression| {
+                let fields = RefCell::new(0);
+                expression.walk(&|e| {
+                    if let Expression::Field(i, _) = e {
+                        *fields.borrow_mut() |= 1 << Self::field(&relations, *i).0;
+                    }
+                    true
+                });
+                let selectivity = Self::selectivity(&relations, &expression);
+                JoinPredicate { expression, relations: fields.into_inner(), selectivity }
+            })
+            .collect();
+
+        // The estimated number of rows of a join of the given relations, regardless of order.
+        let rows = |mask: usize| -> f64 {
+            let rows: f64 = relations
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & 1 << i != 0)
+                .map(|(_, r)| r.rows)
+                .product();
+            predicates
+                .iter()
+                .filter(|p| p.relations & mask == p.relations)
+                .fold(rows, |rows, p| rows * p.selectivity)
+        };
+        // Whether any predicate joins the left and right relations.
+        let connected = |left: usize, right: usize| {
+            predicates.iter().any(|p| {
+                p.relations & left != 0
+                    && p.relations & right != 0
+                    && p.relations & (left | right) == p.relations
+            })
+        };
+
+        // Find the cheapest left-deep order, as (cross product, cost, order) for each subset of
+        // relations, where the cost is the total number of rows emitted by its joins. Plans
+        // without cross products are always preferred. Later relations are tried first, and ties
+        // keep the first plan found, such that the original order is kept for equal costs.
+        let full = (1 << relations.len()) - 1;
+        let mut best: Vec<Option<(bool, f64, Vec<usize>)>> = vec![None; full + 1];
+        for i in 0..relations.len() {
+            best[1 << i] = Some((false, 0.0, vec![i]));
+        }
+        for mask in 1..=full {
+            if mask.count_ones() < 2 {
+                continue;
+            }
+            for i in (0..relations.len()).rev().filter(|i| mask & 1 << i != 0) {
+                let rest = mask & !(1 << i);
+                if let Some((cross, cost, order)) = &best[rest] {
+                    let cross = *cross || !connected(rest, 1 << i);
+                    let cost = cost + rows(mask);
+                    let better = match &best[mask] {
+                        Some((c, x, _)) => (cross, cost) < (*c, *x),
+                        None => true,
+                    };
+                    if better {
+                        let order = order.iter().copied().chain(std::iter::once(i)).collect();
+                        best[mask] = Some((cross, cost, order));
+                    }
+                }
+            }
+        }
+        let (cross, cost, order) = best[full].take().unwrap_or((true, f64::INFINITY, Vec::new()));
+        let current = joins.iter().fold((false, 0.0), |(cross, cost), (left, right)| {
+            (cross || !connected(*left, *right), cost + rows(left | right))
+        });
+        if (cross, cost) >= current {
+            return Ok(node);
+        }
+
+        // Build the left-deep join tree, placing each predicate in the first join where all of
+        // its relations are available, and project the original columns.
+        let mut positions = vec![0; Self::width(&relations)];
+        let mut width = 0;
+        for i in &order {
+            let relation = &relations[*i];
+            for column in 0..relation.table.columns.len() {
+                positions[relation.offset + column] = width + column;
+            }
+            width += relation.table.columns.len();
+        }
+        let remap = |expr: Expression| {
+            expr.transform(&|e| Ok(e), &|e| match e {
+                Expression::Field(i, label) => Ok(Expression::Field(positions[i], label)),
+                e => Ok(e),
+            })
+        };
+        let labels: Vec<_> = relations
+            .iter()
+            .flat_map(|r| r.table.columns.iter().map(move |c| (r.label.clone(), c.name.clone())))
+            .collect();
+        let mut nodes: Vec<_> =
+            relations.into_iter().map(|r| Some((r.node, r.table.columns.len()))).collect();
+        let mut predicates: Vec<_> = predicates.into_iter().map(Some).collect();
+        let mut take = |i: usize| {
+            nodes[i].take().ok_or_else(|| Error::Internal(format!("Join relation {} reused", i)))
+        };
+        let (mut node, mut left_size) = take(order[0])?;
+        let mut mask = 1 << order[0];
+        for i in order.into_iter().skip(1) {
+            let (right, size) = take(i)?;
+            mask |= 1 << i;
+            let mut predicate = Vec::new();
+            for p in predicates.iter_mut() {
+                if matches!(p, Some(p) if p.relations & mask == p.relations) {
+                    if let Some(p) = p.take() {
+                        predicate.push(remap(p.expression)?);
+                    }
+                }
+            }
+            node = Node::NestedLoopJoin {
+                left: Box::new(node),
+                left_size,
+                right: Box::new(right),
+                predicate: Expression::from_cnf_vec(predicate),
+                outer: false,
+                full: false,
+            };
+            left_size += size;
+        }
+        Ok(Node::Projection {
+            source: Box::new(node),
+            expressions: labels
+                .into_iter()
+                .enumerate()
+                .map(|(i, (table, column))| {
+                    (Expression::Field(positions[i], Some((Some(table), column))), None)
+                })
+                .collect(),
+        })
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for JoinOrder<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        // Join groups are reordered while descending, such that each group is reordered as a
+        // whole. Its joins are then visited again, but are already in the cheapest order.
+        node.transform(
+            &|n| match n {
+                n @ Node::NestedLoopJoin { outer: false, full: false, .. } => self.reorder(n),
+                n => Ok(n),
+            },
+            &|n| Ok(n),
+        )
+    }
+}
+
+// Optimizes join types, by swapping nested-loop joins with merge joins if both sources are sorted by
+// the join fields, or hash joins otherwise, where appropriate.
+pub struct JoinType<'a, C: Catalog> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog> JoinType<'a, C> {
+    /// The maximum number of row combinations for which nested-loop joins are kept, since hash
+    /// joins have a higher constant cost.
+    const NESTED_LOOP_MAX_ROWS: u64 = 100;
+
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+
+    /// Estimates the number of rows returned by a node, if known. There are no table statistics,
+    /// so only nodes with a known number of rows (e.g. key lookups) can be estimated.
+    fn estimate_rows(node: &Node) -> Option<u64> {
+        match node {
+            Node::KeyLookup { keys, .. } => Some(keys.len() as u64),
+            Node::Limit { source, limit } => {
+                Some(Self::estimate_rows(source).map_or(*limit, |rows| rows.min(*limit)))
+            }
+            Node::Nothing => Some(0),
+            Node::Filter { source, .. }
+            | Node::Offset { source, .. }
+            | Node::Order { source, .. }
+            | Node::Projection { source, .. } => Self::estimate_rows(source),
+            _ => None,
+        }
+    }
+
+    /// Returns the left and right fields of an equijoin predicate, i.e. a conjunction of equalities
+    /// between a left and a right field, or None if the predicate has any other expressions.
+    fn equijoin_fields(
+        predicate: &Expression,
+        left_size: usize,
+    ) -> Option<(JoinFields, JoinFields)> {
+        let (mut left_fields, mut right_fields) = (Vec::new(), Vec::new());
+        for expr in predicate.clone().into_cnf_vec() {
+            match expr {
+                Expression::Equal(a, b) => match (*a, *b) {
+                    (Expression::Field(a, a_label), Expression::Field(b, b_label))
+                        if (a < left_size) != (b < left_size) =>
+                    {
+                        if a < left_size {
+                            left_fields.push((a, a_label));
+                            right_fields.push((b - left_size, b_label));
+                        } else {
+                            left_fields.push((b, b_label));
+                            right_fields.push((a - left_size, a_label));
+                        }
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+        if left_fields.is_empty() {
+            return None;
+        }
+        Some((left_fields, right_fields))
+    }
 
-impl Optimizer for JoinType {
+    /// Returns the equality of a null-aware anti-join predicate, i.e. `(a = b) IS DISTINCT FROM
+    /// FALSE` as planned for NOT IN, where NULL comparisons count as matches.
+    fn null_aware_equal(predicate: &Expression, anti: bool) -> Option<&Expression> {
+        match predicate {
+            Expression::IsDistinctFrom(lhs, rhs) if anti => match (&**lhs, &**rhs) {
+                (equal @ Expression::Equal(..), Expression::Constant(Value::Boolean(false))) => {
+                    Some(equal)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Checks whether a join with the given sources is large enough to use a hash join.
+    fn is_large(left: &Node, right: &Node) -> bool {
+        match (Self::estimate_rows(left), Self::estimate_rows(right)) {
+            (Some(left), Some(right)) => left.saturating_mul(right) > Self::NESTED_LOOP_MAX_ROWS,
+            _ => true,
+        }
+    }
+
+    /// Returns the join fields in the order that the left and right sources are sorted by, if
+    /// both are sorted in ascending order by all of their join fields, i.e. if the ordering of
+    /// each source starts with its join fields in the same order. Otherwise, returns None.
+    fn merge_fields(
+        left_fields: JoinFields,
+        right_fields: JoinFields,
+        left_ordering: &[(usize, Direction)],
+        right_ordering: &[(usize, Direction)],
+    ) -> Option<(JoinFields, JoinFields)> {
+        let mut pairs: Vec<_> = left_fields.into_iter().zip(right_fields).collect();
+        let (mut left_fields, mut right_fields) = (Vec::new(), Vec::new());
+        for ((l, l_dir), (r, r_dir)) in left_ordering.iter().zip(right_ordering) {
+            if pairs.is_empty() {
+                break;
+            }
+            if *l_dir != Direction::Ascending || *r_dir != Direction::Ascending {
+                return None;
+            }
+            let i = pairs.iter().position(|((li, _), (ri, _))| li == l && ri == r)?;
+            let (left_field, right_field) = pairs.remove(i);
+            left_fields.push(left_field);
+            right_fields.push(right_field);
+        }
+        if !pairs.is_empty() {
+            return None;
+        }
+        Some((left_fields, right_fields))
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for JoinType<'a, C> {
     fn optimize(&self, node: Node) -> Result<Node> {
         node.transform(
+            &|n| Ok(n),
+            // The join types are chosen while ascending, since the chosen join types of the
+            // sources determine their sort order.
             &|n| match n {
-                // Replace nested-loop equijoins with hash joins.
+                // Replace nested-loop equijoins with merge or hash joins, if the predicate only
+                // compares fields from different sources for equality and the sources are large
+                // enough. Merge joins are used if both sources are already sorted by the join
+                // fields, which avoids building a hash table.
                 Node::NestedLoopJoin {
                     left,
                     left_size,
                     right,
-                    predicate: Some(Expression::Equal(a, b)),
+                    predicate: Some(predicate),
                     outer,
-                } => match (*a, *b) {
-                    (Expression::Field(a, a_label), Expression::Field(b, b_label)) => {
-                        let (left_field, right_field) = if a < left_size {
-                            ((a, a_label), (b - left_size, b_label))
-                        } else {
-                            ((b, b_label), (a - left_size, a_label))
-                        };
-                        Ok(Node::HashJoin { left, left_field, right, right_field, outer })
+                    full,
+                } if Self::is_large(&left, &right) => {
+                    match Self::equijoin_fields(&predicate, left_size) {
+                        Some((left_fields, right_fields)) => {
+                            match Self::merge_fields(
+                                left_fields.clone(),
+                                right_fields.clone(),
+                                &left.ordering(self.catalog)?,
+                                &right.ordering(self.catalog)?,
+                            ) {
+                                Some((left_fields, right_fields)) => Ok(Node::MergeJoin {
+                                    left,
+                                    left_fields,
+                                    right,
+                                    right_fields,
+                                    outer,
+                                    full,
+                                }),
+                                None => Ok(Node::HashJoin {
+                                    left,
+                                    left_fields,
+                                    right,
+                                    right_fields,
+                                    outer,
+                                    full,
+                                }),
+                            }
+                        }
+                        None => Ok(Node::NestedLoopJoin {
+                            left,
+                            left_size,
+                            right,
+                            predicate: Some(predicate),
+                            outer,
+                            full,
+                        }),
                     }
-                    (a, b) => Ok(Node::NestedLoopJoin {
-                        left,
-                        left_size,
-                        right,
-                        predicate: Some(Expression::Equal(a.into(), b.into())),
-                        outer,
-                    }),
-                },
+                }
+                Node::SemiJoin { left, left_size, right, predicate: Some(predicate), anti }
+                    if Self::is_large(&left, &right) =>
+                {
+                    let (fields, null_aware) = match Self::null_aware_equal(&predicate, anti) {
+                        Some(equal) => (Self::equijoin_fields(equal, left_size), true),
+                        None => (Self::equijoin_fields(&predicate, left_size), false),
+                    };
+                    match fields {
+                        Some((left_fields, right_fields)) => Ok(Node::HashSemiJoin {
+                            left,
+                            left_fields,
+                            right,
+                            right_fields,
+                            anti,
+                            null_aware,
+                        }),
+                        None => Ok(Node::SemiJoin {
+                            left,
+                            left_size,
+                            right,
+                            predicate: Some(predicate),
+                            anti,
+                        }),
+                    }
+                }
                 n => Ok(n),
             },
-            &|n| Ok(n),
         )
     }
 }
+
+/// Elides sorts of rows that are already sorted, replacing ORDER BY nodes whose source is known
+/// to emit rows in the given order (e.g. a primary key scan ordered by the primary key) with
+/// presorted nodes, which emit the source rows as-is.
+pub struct SortElision<'a, C: Catalog> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog> SortElision<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+
+    /// Checks whether rows with the given ordering (see Node::ordering) are sorted by the orders.
+    fn is_sorted(
+        orders: &[(Expression, Direction, Nulls)],
+        ordering: &[(usize, Direction)],
+    ) -> bool {
+        orders.len() <= ordering.len()
+            && orders.iter().zip(ordering).all(|((expr, dir, nulls), (field, sorted_dir))| {
+                matches!(expr, Expression::Field(i, _) if i == field)
+                    && dir == sorted_dir
+                    && *nulls == Nulls::default_for(dir)
+            })
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for SortElision<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| Ok(n), &|n| match n {
+            Node::Order { source, orders } => {
+                if Self::is_sorted(&orders, &source.ordering(self.catalog)?) {
+                    Ok(Node::Presorted { source, orders })
+                } else {
+                    Ok(Node::Order { source, orders })
+                }
+            }
+            n => Ok(n),
+        })
+    }
+}
+
+/// Elides DISTINCT nodes whose source rows are already distinct by the compared fields, e.g. when
+/// they include the primary key of a single-table scan. Uniqueness is tracked through nodes that
+/// emit a subset of their source rows as-is, and projections of plain fields.
+pub struct DistinctElision<'a, C: Catalog> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog> DistinctElision<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+
+    /// Checks whether the node's rows are known to be unique by the given fields.
+    fn is_unique(&self, node: &Node, fields: &HashSet<usize>) -> Result<bool> {
+        Ok(match node {
+            Node::IndexLookup { table, .. }
+            | Node::IndexOrderScan { table, .. }
+            | Node::IndexPrefixScan { table, .. }
+            | Node::IndexRangeScan { table, .. }
+            | Node::KeyLookup { table, .. }
+            | Node::KeyRangeScan { table, .. }
+            | Node::Scan { table, .. } => {
+                let table = self.catalog.must_read_table(table)?;
+                table.get_primary_key_indexes().iter().all(|i| fields.contains(i))
+            }
+            // Aggregations emit one row per group, with the group values following the aggregates.
+            Node::Aggregation { aggregates, .. } => {
+                let columns = node.columns(self.catalog)?.len();
+                (aggregates.len()..columns).all(|i| fields.contains(&i))
+            }
+            Node::Projection { source, expressions } => {
+                let source_fields = fields
+                    .iter()
+                    .filter_map(|i| match expressions.get(*i) {
+                        Some((Expression::Field(f, _), _)) => Some(*f),
+                        _ => None,
+                    })
+                    .collect();
+                self.is_unique(source, &source_fields)?
+            }
+            Node::Distinct { source, .. }
+            | Node::Filter { source, .. }
+            | Node::Limit { source, .. }
+            | Node::Offset { source, .. }
+            | Node::Order { source, .. }
+            | Node::Presorted { source, .. }
+            | Node::TopK { source, .. } => self.is_unique(source, fields)?,
+            Node::HashSemiJoin { left, .. } | Node::SemiJoin { left, .. } => {
+                self.is_unique(left, fields)?
+            }
+            _ => false,
+        })
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for DistinctElision<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| Ok(n), &|n| match n {
+            Node::Distinct { source, on } => {
+                let fields = match on.is_empty() {
+                    true => (0..source.columns(self.catalog)?.len()).collect(),
+                    false => on
+                        .iter()
+                        .filter_map(|e| match e {
+                            Expression::Field(i, _) => Some(*i),
+                            _ => None,
+                        })
+                        .collect(),
+                };
+                if self.is_unique(&source, &fields)? {
+                    Ok(*source)
+                } else {
+                    Ok(Node::Distinct { source, on })
+                }
+            }
+            n => Ok(n),
+        })
+    }
+}
+
+/// Optimizes sorts beneath a limit (i.e. ORDER BY ... LIMIT), which only have to emit the first
+/// rows. If the sorted rows can be read in order from the index of a table column, the table scan
+/// is replaced by an index order scan which stops after the limit. Otherwise, the sort is replaced
+/// by a TopK node, which keeps the first rows in a bounded heap. Offsets beneath the limit are
+/// handled by taking limit+offset rows.
+pub struct TopK<'a, C: Catalog> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog> TopK<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+
+    /// Returns a node that emits the first limit rows of the source in the given order.
+    fn top(
+        &self,
+        source: Box<Node>,
+        orders: Vec<(Expression, Direction, Nulls)>,
+        limit: u64,
+    ) -> Result<Node> {
+        if let (Some(table), Some((_, direction, _))) = (Self::scan_table(&source), orders.first())
+        {
+            let reverse = *direction == Direction::Descending;
+            let schema = self.catalog.must_read_table(table)?;
+            for column in schema.columns.iter().filter(|c| c.index) {
+                let scan =
+                    match Self::index_order_scan(&source, &schema, &column.name, reverse, limit) {
+                        Some(scan) => scan,
+                        None => continue,
+                    };
+                if SortElision::<C>::is_sorted(&orders, &scan.ordering(self.catalog)?) {
+                    return Ok(Node::Presorted { source: Box::new(scan), orders });
+                }
+            }
+        }
+        Ok(Node::TopK { source, orders, limit })
+    }
+
+    /// Returns the table of the node's table scan, if any. Only projections are traversed, since
+    /// they emit each source row in order. Filtered key range scans are considered table scans,
+    /// since the filter contains the range comparisons.
+    fn scan_table(node: &Node) -> Option<&str> {
+        match node {
+            Node::Scan { table, .. } => Some(table),
+            Node::Filter { source, .. } => match &**source {
+                Node::KeyRangeScan { table, .. } => Some(table),
+                _ => None,
+            },
+            Node::Projection { source, .. } => Self::scan_table(source),
+            _ => None,
+        }
+    }
+
+    /// Returns the node with its table scan (see scan_table()) replaced by an index order scan,
+    /// which only reads the index if it covers the scanned columns.
+    fn index_order_scan(
+        node: &Node,
+        schema: &Table,
+        column: &str,
+        reverse: bool,
+        limit: u64,
+    ) -> Option<Node> {
+        match node {
+            Node::Scan { table, alias, filter, columns } => Some(Node::IndexOrderScan {
+                table: table.clone(),
+                alias: alias.clone(),
+                column: column.to_string(),
+                filter: filter.clone(),
+                reverse,
+                limit,
+                columns: columns.clone(),
+                index_only: schema.index_covers(column, columns.as_deref()),
+            }),
+            Node::Filter { source, predicate } => match &**source {
+                Node::KeyRangeScan { table, alias, columns, .. } => Some(Node::IndexOrderScan {
+                    table: table.clone(),
+                    alias: alias.clone(),
+                    column: column.to_string(),
+                    filter: Some(predicate.clone()),
+                    reverse,
+                    limit,
+                    columns: columns.clone(),
+                    index_only: schema.index_covers(column, columns.as_deref()),
+                }),
+                _ => None,
+            },
+            Node::Projection { source, expressions } => Some(Node::Projection {
+                source: Box::new(Self::index_order_scan(source, schema, column, reverse, limit)?),
+                expressions: expressions.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for TopK<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| Ok(n), &|n| match n {
+            Node::Limit { source, limit } => match *source {
+                Node::Order { source, orders } => self.top(source, orders, limit),
+                Node::Offset { source, offset } => match *source {
+                    Node::Order { source, orders } => Ok(Node::Offset {
+                        source: Box::new(self.top(source, orders, limit.saturating_add(offset))?),
+                        offset,
+                    }),
+                    source => Ok(Node::Limit {
+                        source: Box::new(Node::Offset { source: Box::new(source), offset }),
+                        limit,
+                    }),
+                },
+                source => Ok(Node::Limit { source: Box::new(source), limit }),
+            },
+            n => Ok(n),
+        })
+    }
+}
+
+/// Pushes the columns required by projections down into table and index scans, such that the scans
+/// only decode the values of those columns from stored rows, leaving the other columns NULL. The
+/// columns are pushed through nodes that pass on source values as-is (i.e. filters, sorts, limits,
+/// offsets, DISTINCT ON, nested-loop joins, semi-joins, and projections of plain fields such as
+/// those added by JoinOrder), adding the columns they use themselves. This runs before join types are chosen,
+/// since hash and merge joins don't know the width of their left source. Scans where all columns
+/// are required read entire rows, e.g. for SELECT *.
+pub struct ProjectionPushdown<'a, C: Catalog> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog> ProjectionPushdown<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+
+    /// Adds the source fields referenced by an expression to a set of fields.
+    fn add_fields(fields: &mut HashSet<usize>, expr: &Expression) {
+        let found = RefCell::new(Vec::new());
+        expr.walk(&|e| {
+            if let Expression::Field(i, _) = e {
+                found.borrow_mut().push(*i)
+            }
+            true
+        });
+        fields.extend(found.into_inner());
+    }
+
+    /// Splits the required fields of a join into left and right source fields.
+    fn split(required: HashSet<usize>, left_size: usize) -> (HashSet<usize>, HashSet<usize>) {
+        let (left, right): (HashSet<usize>, HashSet<usize>) =
+            required.into_iter().partition(|i| *i < left_size);
+        (left, right.into_iter().map(|i| i - left_size).collect())
+    }
+
+    /// Returns the names of the table columns to read for the required fields, or None if all
+    /// columns are required.
+    fn columns(&self, table: &str, required: &HashSet<usize>) -> Result<Option<Vec<String>>> {
+        let table = self.catalog.must_read_table(table)?;
+        if (0..table.columns.len()).all(|i| required.contains(&i)) {
+            return Ok(None);
+        }
+        Ok(Some(
+            table
+                .columns
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| required.contains(i))
+                .map(|(_, c)| c.name)
+                .collect(),
+        ))
+    }
+
+    /// Like columns(), but for a scan of the given column's index, also returning whether the
+    /// index covers the columns such that the scan can only read the index.
+    fn index_columns(
+        &self,
+        table: &str,
+        column: &str,
+        required: &HashSet<usize>,
+    ) -> Result<(Option<Vec<String>>, bool)> {
+        let columns = self.columns(table, required)?;
+        let index_only =
+            self.catalog.must_read_table(table)?.index_covers(column, columns.as_deref());
+        Ok((columns, index_only))
+    }
+
+    /// Pushes the required fields of the node's rows down into its scans, if possible.
+    fn push(&self, node: Node, mut required: HashSet<usize>) -> Result<Node> {
+        Ok(match node {
+            Node::Filter { source, predicate } => {
+                Self::add_fields(&mut required, &predicate);
+                Node::Filter { source: Box::new(self.push(*source, required)?), predicate }
+            }
+            Node::Distinct { source, on } if !on.is_empty() => {
+                on.iter().for_each(|e| Self::add_fields(&mut required, e));
+                Node::Distinct { source: Box::new(self.push(*source, required)?), on }
+            }
+            Node::Limit { source, limit } => {
+                Node::Limit { source: Box::new(self.push(*source, required)?), limit }
+            }
+            Node::Offset { source, offset } => {
+                Node::Offset { source: Box::new(self.push(*source, required)?), offset }
+            }
+            Node::Order { source, orders } => {
+                orders.iter().for_each(|(e, _, _)| Self::add_fields(&mut required, e));
+                Node::Order { source: Box::new(self.push(*source, required)?), orders }
+            }
+            Node::Presorted { source, orders } => {
+                orders.iter().for_each(|(e, _, _)| Self::add_fields(&mut required, e));
+                Node::Presorted { source: Box::new(self.push(*source, required)?), orders }
+            }
+            Node::TopK { source, orders, limit } => {
+                orders.iter().for_each(|(e, _, _)| Self::add_fields(&mut required, e));
+                Node::TopK { source: Box::new(self.push(*source, required)?), orders, limit }
+            }
+            Node::Projection { source, expressions }
+                if expressions.iter().all(|(e, _)| matches!(e, Expression::Field(..))) =>
+            {
+                let mut source_required = HashSet::new();
+                for i in required {
+                    if let Some((e, _)) = expressions.get(i) {
+                        Self::add_fields(&mut source_required, e);
+                    }
+                }
+                Node::Projection {
+                    source: Box::new(self.push(*source, source_required)?),
+                    expressions,
+                }
+            }
+            Node::NestedLoopJoin { left, left_size, right, predicate, outer, full } => {
+                if let Some(predicate) = &predicate {
+                    Self::add_fields(&mut required, predicate);
+                }
+                let (left_required, right_required) = Self::split(required, left_size);
+                Node::NestedLoopJoin {
+                    left: Box::new(self.push(*left, left_required)?),
+                    left_size,
+                    right: Box::new(self.push(*right, right_required)?),
+                    predicate,
+                    outer,
+                    full,
+                }
+            }
+            // Semi-joins only emit left columns, so the right source only needs the columns used
+            // by the predicate.
+            Node::SemiJoin { left, left_size, right, predicate, anti } => {
+                if let Some(predicate) = &predicate {
+                    Self::add_fields(&mut required, predicate);
+                }
+                let (left_required, right_required) = Self::split(required, left_size);
+                Node::SemiJoin {
+                    left: Box::new(self.push(*left, left_required)?),
+                    left_size,
+                    right: Box::new(self.push(*right, right_required)?),
+                    predicate,
+                    anti,
+                }
+            }
+            Node::IndexLookup { table, alias, column, values, .. } => {
+                let (columns, index_only) = self.index_columns(&table, &column, &required)?;
+                Node::IndexLookup { table, alias, column, values, columns, index_only }
+            }
+            Node::IndexOrderScan { table, alias, column, filter, reverse, limit, .. } => {
+                if let Some(filter) = &filter {
+                    Self::add_fields(&mut required, filter);
+                }
+                let (columns, index_only) = self.index_columns(&table, &column, &required)?;
+                Node::IndexOrderScan {
+                    table,
+                    alias,
+                    column,
+                    filter,
+                    reverse,
+                    limit,
+                    columns,
+                    index_only,
+                }
+            }
+            Node::IndexPrefixScan { table, alias, column, prefix, .. } => {
+                let (columns, index_only) = self.index_columns(&table, &column, &required)?;
+                Node::IndexPrefixScan { table, alias, column, prefix, columns, index_only }
+            }
+            Node::IndexRangeScan { table, alias, column, range, .. } => {
+                let (columns, index_only) = self.index_columns(&table, &column, &required)?;
+                Node::IndexRangeScan { table, alias, column, range, columns, index_only }
+            }
+            Node::KeyLookup { table, alias, keys, columns: _ } => {
+                let columns = self.columns(&table, &required)?;
+                Node::KeyLookup { table, alias, keys, columns }
+            }
+            Node::KeyRangeScan { table, alias, range, columns: _ } => {
+                let columns = self.columns(&table, &required)?;
+                Node::KeyRangeScan { table, alias, range, columns }
+            }
+            Node::Scan { table, alias, filter, columns: _ } => {
+                if let Some(filter) = &filter {
+                    Self::add_fields(&mut required, filter);
+                }
+                let columns = self.columns(&table, &required)?;
+                Node::Scan { table, alias, filter, columns }
+            }
+            // Other nodes may use all source columns, and any projections beneath them are
+            // optimized separately.
+            n => n,
+        })
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for ProjectionPushdown<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| Ok(n), &|n| match n {
+            Node::Projection { source, expressions } => {
+                let mut required = HashSet::new();
+                expressions.iter().for_each(|(e, _)| Self::add_fields(&mut required, e));
+                Ok(Node::Projection {
+                    source: Box::new(self.push(*source, required)?),
+                    expressions,
+                })
+            }
+            n => Ok(n),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::engine::{Engine as _, Mode, Transaction as _, KV};
+    use super::super::super::execution::ResultSet;
+    use super::super::super::parser::Parser;
+    use super::super::super::schema::ColumnStats;
+    use super::super::Plan;
+    use super::*;
+    use crate::storage::kv;
+
+    /// Plans and optimizes a query against a table with the given simulated statistics, if any,
+    /// returning the EXPLAIN output.
+    fn plan(stats: Option<TableStats>, query: &str) -> Result<String> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        engine.session()?.execute(
+            "CREATE TABLE test (id INTEGER PRIMARY KEY, a INTEGER INDEX, b INTEGER INDEX)",
+        )?;
+        let mut txn = engine.begin(Mode::ReadWrite)?;
+        if let Some(stats) = stats {
+            txn.set_table_stats("test", stats)?;
+        }
+        let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?.optimize(&mut txn)?;
+        let explain = plan.explain(&mut txn)?;
+        txn.rollback()?;
+        Ok(explain)
+    }
+
+    /// Simulates statistics for 1000 rows, with the given number of distinct values in columns a
+    /// and b, evenly distributed from 0.
+    fn stats(a: u64, b: u64) -> TableStats {
+        let column = |distinct: u64| ColumnStats {
+            distinct,
+            nulls: 0,
+            min: Value::Integer(0),
+            max: Value::Integer(distinct as i64 - 1),
+        };
+        TableStats { rows: 1000, columns: vec![column(1000), column(a), column(b)] }
+    }
+
+    #[test]
+    fn index_lookup_stats() -> Result<()> {
+        let query = "SELECT * FROM test WHERE a = 1 AND b = 1";
+
+        // Without statistics, the first lookup is used.
+        assert_eq!(plan(None, query)?, "Filter: b = 1\n└─ IndexLookup: test column a (1)");
+
+        // With statistics, the most selective index is used.
+        assert_eq!(
+            plan(Some(stats(1000, 2)), query)?,
+            "Filter: b = 1\n└─ IndexLookup: test column a (1) (estimated rows=1)"
+        );
+        assert_eq!(
+            plan(Some(stats(2, 1000)), query)?,
+            "Filter: a = 1\n└─ IndexLookup: test column b (1) (estimated rows=1)"
+        );
+
+        // If neither index is selective enough, a full table scan is cheaper.
+        assert_eq!(
+            plan(Some(stats(2, 4)), query)?,
+            "Scan: test (a = 1 AND b = 1) (estimated rows=125)"
+        );
+        assert_eq!(
+            plan(Some(stats(2, 100)), query)?,
+            "Filter: a = 1\n└─ IndexLookup: test column b (1) (estimated rows=10)"
+        );
+
+        // Values outside of the column's range match no rows.
+        assert_eq!(
+            plan(Some(stats(2, 4)), "SELECT * FROM test WHERE a = 7")?,
+            "IndexLookup: test column a (7) (estimated rows=0)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn key_lookup_stats() -> Result<()> {
+        let query = "SELECT * FROM test WHERE id IN (1, 2, 3) AND a = 7";
+
+        // Without statistics, the primary key lookup is used.
+        assert_eq!(plan(None, query)?, "Filter: a = 7\n└─ KeyLookup: test (1, 2, 3)");
+        assert_eq!(
+            plan(Some(stats(100, 100)), query)?,
+            "Filter: a = 7\n└─ KeyLookup: test (1, 2, 3) (estimated rows=3)"
+        );
+
+        // If the index lookup is estimated to return fewer rows (here none, since the value is
+        // outside of the column's range), it is used instead.
+        assert_eq!(
+            plan(Some(stats(2, 100)), query)?,
+            "Filter: id IN (1, 2, 3)\n└─ IndexLookup: test column a (7) (estimated rows=0)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn range_stats() -> Result<()> {
+        // Range predicates estimate their selectivity by interpolating between the column's
+        // minimum and maximum values, and use an index range scan if selective enough.
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE a > 89")?,
+            "Filter: a > 89\n└─ IndexRangeScan: test column a range > 89 (estimated rows=101)"
+        );
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE a >= 10 AND a < 20")?,
+            "Filter: a > 10 OR a = 10 AND a < 20\n└─ IndexRangeScan: test column a range >= 10 AND < 20 (estimated rows=111)"
+        );
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE a > 9")?,
+            "Scan: test (a > 9) (estimated rows=909)"
+        );
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE a < 10 OR a > 1000")?,
+            "Scan: test (a < 10 OR a > 1000) (estimated rows=101)"
+        );
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE a = 1 AND b < 50")?,
+            "Filter: b < 50\n└─ IndexLookup: test column a (1) (estimated rows=10)"
+        );
+
+        // Primary key ranges use a key range scan, unless it would read most rows anyway.
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE id > 990")?,
+            "Filter: id > 990\n└─ KeyRangeScan: test range > 990 (estimated rows=9)"
+        );
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE id >= 0 AND id < 999")?,
+            "Scan: test (id > 0 OR id = 0 AND id < 999) (estimated rows=1000)"
+        );
+
+        // Key range scans are sorted by primary key, so keyset pagination stops after the page.
+        assert_eq!(
+            plan(None, "SELECT * FROM test WHERE id > 10 ORDER BY id LIMIT 5")?,
+            "Limit: 5\n└─ Presorted: id asc (sort elided)\n   └─ Filter: id > 10\n      └─ KeyRangeScan: test range > 10"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn filter_pushdown_outer_join() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE a (id INTEGER PRIMARY KEY, value INTEGER)")?;
+        session.execute("CREATE TABLE b (id INTEGER PRIMARY KEY, a_id INTEGER, value INTEGER)")?;
+        session.execute("INSERT INTO a VALUES (1, 1), (2, NULL), (3, 3), (4, 4)")?;
+        session.execute("INSERT INTO b VALUES (1, 1, 1), (2, 1, NULL), (3, 3, 5), (4, NULL, 2)")?;
+
+        // Outer join results must be the same with and without the optimizer, including for
+        // predicates on the null-extended side and on the preserved side in the join predicate.
+        let queries = [
+            "SELECT * FROM a LEFT JOIN b ON a.id = b.a_id AND a.value = 1",
+            "SELECT * FROM a LEFT JOIN b ON a.id = b.a_id AND b.value > 1",
+            "SELECT * FROM a LEFT JOIN b ON a.id = b.a_id WHERE b.id IS NULL",
+            "SELECT * FROM a LEFT JOIN b ON a.id = b.a_id WHERE a.value > 1 AND b.value IS NULL",
+            "SELECT * FROM a LEFT JOIN b ON a.id = b.a_id AND a.id = 1 WHERE a.id < 3",
+            "SELECT * FROM a LEFT JOIN b ON a.id = b.a_id AND b.id = 1 WHERE b.a_id = 1",
+            "SELECT * FROM b RIGHT JOIN a ON a.id = b.a_id AND a.value = 1 WHERE b.id IS NULL",
+            "SELECT * FROM a FULL JOIN b ON a.id = b.a_id WHERE a.id > 1 OR b.value > 1",
+            "SELECT * FROM a JOIN b ON a.id = b.a_id LEFT JOIN b c ON c.a_id = b.id WHERE c.id IS NULL",
+        ];
+        for query in queries.iter() {
+            let mut txn = engine.begin(Mode::ReadOnly)?;
+            let execute = |plan: Plan, txn: &mut _| -> Result<Vec<Vec<Value>>> {
+                let mut rows = match plan.execute(txn)? {
+                    ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+                    r => panic!("Unexpected result {:?}", r),
+                };
+                rows.sort_by_cached_key(|row| format!("{:?}", row));
+                Ok(rows)
+            };
+            let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?;
+            let expect = execute(Plan(plan.0.clone()), &mut txn)?;
+            let actual = execute(plan.optimize(&mut txn)?, &mut txn)?;
+            assert_eq!(actual, expect, "{}", query);
+            txn.rollback()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn join_type_hash_join() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE a (id INTEGER PRIMARY KEY, x INTEGER, y STRING)")?;
+        session.execute("CREATE TABLE b (id INTEGER PRIMARY KEY, x FLOAT, y STRING)")?;
+        session.execute(
+            "INSERT INTO a VALUES (1, 1, 'a'), (2, 1, 'b'), (3, 2, NULL), (4, NULL, 'a'), (5, 3, 'c')",
+        )?;
+        session.execute(
+            "INSERT INTO b VALUES (1, 1.0, 'a'), (2, 1.0, 'a'), (3, 2.0, NULL), (4, NULL, 'b'), (5, 4.5, 'c')",
+        )?;
+
+        // Hash joins must return the same rows as nested-loop joins, including for NULL keys,
+        // duplicate keys, multiple join fields, and numeric values of different types.
+        let queries = [
+            "SELECT * FROM a JOIN b ON a.x = b.x",
+            "SELECT * FROM a JOIN b ON a.x = b.x AND b.y = a.y",
+            "SELECT * FROM a LEFT JOIN b ON a.x = b.x AND a.y = b.y",
+            "SELECT * FROM a RIGHT JOIN b ON a.x = b.x",
+            "SELECT * FROM a FULL JOIN b ON a.x = b.x AND a.y = b.y",
+            "SELECT * FROM a WHERE EXISTS (SELECT * FROM b WHERE b.x = a.x AND b.y = a.y)",
+            "SELECT * FROM a WHERE NOT EXISTS (SELECT * FROM b WHERE b.x = a.x)",
+        ];
+        for query in queries.iter() {
+            let mut txn = engine.begin(Mode::ReadOnly)?;
+            let execute = |plan: Plan, txn: &mut _| -> Result<Vec<Vec<Value>>> {
+                let mut rows = match plan.execute(txn)? {
+                    ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+                    r => panic!("Unexpected result {:?}", r),
+                };
+                rows.sort_by_cached_key(|row| format!("{:?}", row));
+                Ok(rows)
+            };
+            let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?;
+            let expect = execute(Plan(plan.0.clone()), &mut txn)?;
+            let optimized = plan.optimize(&mut txn)?;
+            assert!(optimized.explain(&mut txn)?.contains("Hash"), "{}", query);
+            let actual = execute(optimized, &mut txn)?;
+            assert_eq!(actual, expect, "{}", query);
+            txn.rollback()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn join_type_merge_join() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE a (k INTEGER, n INTEGER, v STRING, PRIMARY KEY (k, n))")?;
+        session.execute("CREATE TABLE b (k INTEGER, n INTEGER, v STRING, PRIMARY KEY (k, n))")?;
+        session.execute("CREATE TABLE e (k INTEGER, n INTEGER, v STRING, PRIMARY KEY (k, n))")?;
+        session.execute(
+            "INSERT INTO a VALUES (1, 1, 'a'), (1, 2, 'b'), (1, 3, 'a'), (2, 1, 'a'), (4, 1, 'b'),
+                (4, 2, 'b'), (6, 1, 'a')",
+        )?;
+        session.execute(
+            "INSERT INTO b VALUES (0, 1, 'a'), (1, 1, 'a'), (1, 2, 'a'), (3, 1, 'b'), (4, 1, 'b'),
+                (4, 2, 'a'), (4, 3, 'b')",
+        )?;
+
+        // Merge joins must return the same rows as nested-loop joins, including for duplicate
+        // keys on both sides, multiple join fields, and empty sources.
+        let queries = [
+            "SELECT * FROM a JOIN b ON a.k = b.k",
+            "SELECT * FROM a JOIN b ON b.n = a.n AND a.k = b.k",
+            "SELECT * FROM a LEFT JOIN b ON a.k = b.k",
+            "SELECT * FROM a RIGHT JOIN b ON a.k = b.k",
+            "SELECT * FROM a FULL JOIN b ON a.k = b.k",
+            "SELECT * FROM a JOIN e ON a.k = e.k",
+            "SELECT * FROM e JOIN a ON e.k = a.k",
+            "SELECT * FROM a LEFT JOIN e ON a.k = e.k",
+            "SELECT * FROM e FULL JOIN a ON e.k = a.k",
+        ];
+        for query in queries.iter() {
+            let mut txn = engine.begin(Mode::ReadOnly)?;
+            let execute = |plan: Plan, txn: &mut _| -> Result<Vec<Vec<Value>>> {
+                let mut rows = match plan.execute(txn)? {
+                    ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+                    r => panic!("Unexpected result {:?}", r),
+                };
+                rows.sort_by_cached_key(|row| format!("{:?}", row));
+                Ok(rows)
+            };
+            let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?;
+            let expect = execute(Plan(plan.0.clone()), &mut txn)?;
+            let optimized = plan.optimize(&mut txn)?;
+            assert!(optimized.explain(&mut txn)?.contains("MergeJoin"), "{}", query);
+            let actual = execute(optimized, &mut txn)?;
+            assert_eq!(actual, expect, "{}", query);
+            txn.rollback()?;
+        }
+
+        // A join on a field that the source isn't sorted by uses a hash join.
+        let mut txn = engine.begin(Mode::ReadOnly)?;
+        let plan =
+            Plan::build(Parser::new("SELECT * FROM a JOIN b ON a.n = b.k").parse()?, &mut txn)?
+                .optimize(&mut txn)?;
+        assert!(plan.explain(&mut txn)?.contains("HashJoin"));
+        txn.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn sort_elision() -> Result<()> {
+        // Ordering a primary key scan by the primary key doesn't add a sort.
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY id")?,
+            "Presorted: id asc (sort elided)\n└─ Scan: test"
+        );
+
+        // Other orders, or sources without a known order, are sorted.
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY id DESC")?,
+            "Order: id desc\n└─ Scan: test"
+        );
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY a, id")?,
+            "Order: a asc, id asc\n└─ Scan: test"
+        );
+        assert_eq!(
+            plan(None, "SELECT a, id FROM test WHERE b = 1 OR b = 2 ORDER BY id")?,
+            "Order: test.id asc\n└─ Projection: a, id\n   └─ IndexLookup: test [id, a] column b (1, 2)"
+        );
+
+        // Index range scans are sorted by index value, then by primary key.
+        assert_eq!(
+            plan(None, "SELECT a, id FROM test WHERE a > 1 ORDER BY id")?,
+            "Order: test.id asc\n└─ Projection: a, id\n   └─ Filter: a > 1\n      └─ IndexRangeScan: test [id, a] column a index only range > 1"
+        );
+        assert_eq!(
+            plan(Some(stats(100, 100)), "SELECT * FROM test WHERE a > 89 ORDER BY a, id")?,
+            "Presorted: a asc, id asc (sort elided)\n└─ Filter: a > 89\n   └─ IndexRangeScan: test column a range > 89 (estimated rows=101)"
+        );
+
+        // Merge joins emit rows in left order, but hash joins don't.
+        assert_eq!(
+            plan(None, "SELECT * FROM test x JOIN test y ON x.id = y.id ORDER BY x.id")?,
+            "Presorted: x.id asc (sort elided)\n└─ MergeJoin: inner on x.id = y.id\n   ├─ Scan: test as x\n   └─ Scan: test as y"
+        );
+        assert_eq!(
+            plan(None, "SELECT * FROM test x JOIN test y ON x.a = y.id ORDER BY x.id")?,
+            "Order: x.id asc\n└─ HashJoin: inner on x.a = y.id\n   ├─ Scan: test as x\n   └─ Scan: test as y"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn top_k() -> Result<()> {
+        // Sorts of indexed columns beneath a limit use an ordered index scan, reversed for
+        // descending orders. Offsets are included in the scan limit.
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY a LIMIT 3")?,
+            "Presorted: a asc (sort elided)\n└─ IndexOrderScan: test column a limit 3"
+        );
+        assert_eq!(
+            plan(None, "SELECT b, id FROM test WHERE id > 1 ORDER BY b DESC LIMIT 3 OFFSET 2")?,
+            "Offset: 2\n└─ Presorted: test.b desc (sort elided)\n   └─ Projection: b, id\n      └─ IndexOrderScan: test [id, b] column b index only desc limit 5 (id > 1)"
+        );
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY a, id LIMIT 3")?,
+            "Presorted: a asc, id asc (sort elided)\n└─ IndexOrderScan: test column a limit 3"
+        );
+
+        // Other sorts beneath a limit use a top-k sort, since rows with equal index values are
+        // emitted in primary key order even when reversed.
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY a DESC, id DESC LIMIT 3")?,
+            "TopK: a desc, id desc limit 3\n└─ Scan: test"
+        );
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY a + 1 LIMIT 3 OFFSET 1")?,
+            "Offset: 1\n└─ TopK: a + 1 asc limit 4\n   └─ Scan: test"
+        );
+        assert_eq!(
+            plan(None, "SELECT * FROM test WHERE a = 1 ORDER BY b LIMIT 3")?,
+            "TopK: b asc limit 3\n└─ IndexLookup: test column a (1)"
+        );
+
+        // Sorts without a limit, or already sorted, are left as is.
+        assert_eq!(plan(None, "SELECT * FROM test ORDER BY a")?, "Order: a asc\n└─ Scan: test");
+        assert_eq!(
+            plan(None, "SELECT * FROM test ORDER BY id LIMIT 3")?,
+            "Limit: 3\n└─ Presorted: id asc (sort elided)\n   └─ Scan: test"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn projection_pushdown() -> Result<()> {
+        // Scans only read the columns used by projections, filters, sorts, and joins.
+        assert_eq!(plan(None, "SELECT a FROM test")?, "Projection: a\n└─ Scan: test [a]");
+        assert_eq!(
+            plan(None, "SELECT a FROM test WHERE id = 1")?,
+            "Projection: a\n└─ KeyLookup: test [a] (1)"
+        );
+        assert_eq!(
+            plan(None, "SELECT a FROM test WHERE b > 1 ORDER BY id")?,
+            "Projection: #0\n└─ Order: test.id asc\n   └─ Projection: a, id\n      └─ Filter: b > 1\n         └─ IndexRangeScan: test column b range > 1"
+        );
+        assert_eq!(
+            plan(None, "SELECT x.a FROM test x JOIN test y ON x.id = y.b")?,
+            "Projection: x.a\n└─ HashJoin: inner on x.id = y.b\n   ├─ Scan: test as x [id, a]\n   └─ Scan: test as y [b]"
+        );
+        assert_eq!(
+            plan(None, "SELECT COUNT(*) FROM test")?,
+            "Projection: #0\n└─ Aggregation: count\n   └─ Projection: TRUE\n      └─ Scan: test []"
+        );
+
+        // Star projections, or expressions using all columns, read entire rows.
+        assert_eq!(plan(None, "SELECT * FROM test")?, "Scan: test");
+        assert_eq!(plan(None, "SELECT b, a, id FROM test")?, "Projection: b, a, id\n└─ Scan: test");
+        assert_eq!(
+            plan(None, "SELECT id + a + b FROM test")?,
+            "Projection: id + a + b\n└─ Scan: test"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn index_only() -> Result<()> {
+        // Index scans only read the index when it covers the scanned columns, i.e. the indexed
+        // column and the primary key.
+        assert_eq!(
+            plan(None, "SELECT a FROM test WHERE a = 1")?,
+            "Projection: a\n└─ IndexLookup: test [a] column a index only (1)"
+        );
+        assert_eq!(
+            plan(None, "SELECT id, a FROM test WHERE a > 1")?,
+            "Projection: id, a\n└─ Filter: a > 1\n   └─ IndexRangeScan: test [id, a] column a index only range > 1"
+        );
+        assert_eq!(
+            plan(None, "SELECT id FROM test ORDER BY a LIMIT 3")?,
+            "Projection: #0\n└─ Presorted: test.a asc (sort elided)\n   └─ Projection: id, a\n      └─ IndexOrderScan: test [id, a] column a index only limit 3"
+        );
+
+        // Other columns, including in filters, require reading the table rows.
+        assert_eq!(
+            plan(None, "SELECT b FROM test WHERE a = 1")?,
+            "Projection: b\n└─ IndexLookup: test [b] column a (1)"
+        );
+        assert_eq!(
+            plan(None, "SELECT a FROM test WHERE a = 1 AND b = 2")?,
+            "Projection: a\n└─ Filter: b = 2\n   └─ IndexLookup: test [a, b] column a (1)"
+        );
+        assert_eq!(plan(None, "SELECT * FROM test WHERE a = 1")?, "IndexLookup: test column a (1)");
+        Ok(())
+    }
+
+    #[test]
+    fn join_order() -> Result<()> {
+        let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+        let mut session = engine.session()?;
+        session.execute("CREATE TABLE a (id INTEGER PRIMARY KEY, b_id INTEGER)")?;
+        session.execute("CREATE TABLE b (id INTEGER PRIMARY KEY, c_id INTEGER)")?;
+        session.execute("CREATE TABLE c (id INTEGER PRIMARY KEY, value INTEGER)")?;
+
+        // Simulates statistics for the tables a, b, and c with the given number of rows, where
+        // foreign keys reference all rows of the referenced table.
+        let plan = |rows: [u64; 3], query: &str| -> Result<String> {
+            let mut txn = engine.begin(Mode::ReadWrite)?;
+            let column = |distinct: u64| ColumnStats {
+                distinct,
+                nulls: 0,
+                min: Value::Integer(0),
+                max: Value::Integer(distinct as i64 - 1),
+            };
+            for (i, (table, fk)) in [("a", rows[1]), ("b", rows[2]), ("c", 10)].iter().enumerate() {
+                let stats =
+                    TableStats { rows: rows[i], columns: vec![column(rows[i]), column(*fk)] };
+                txn.set_table_stats(table, stats)?;
+            }
+            let plan = Plan::build(Parser::new(query).parse()?, &mut txn)?.optimize(&mut txn)?;
+            let explain = plan.explain(&mut txn)?;
+            txn.rollback()?;
+            Ok(explain)
+        };
+
+        // Joining the smaller tables b and c first yields fewer intermediate rows, so the joins are
+        // reordered with a projection restoring the column order. If a is smaller, it is kept.
+        let query = "SELECT * FROM a JOIN b ON a.b_id = b.id JOIN c ON b.c_id = c.id";
+        assert_eq!(
+            plan([1000, 100, 10], query)?,
+            "Projection: a.id, a.b_id, b.id, b.c_id, c.id, c.value\n└─ HashJoin: inner on b.id = a.b_id\n   ├─ HashJoin: inner on b.c_id = c.id\n   │  ├─ Scan: b (estimated rows=100)\n   │  └─ Scan: c (estimated rows=10)\n   └─ Scan: a (estimated rows=1000)"
+        );
+        assert_eq!(
+            plan([10, 100, 1000], query)?,
+            "HashJoin: inner on b.c_id = c.id\n├─ HashJoin: inner on a.b_id = b.id\n│  ├─ Scan: a (estimated rows=10)\n│  └─ Scan: b (estimated rows=100)\n└─ Scan: c (estimated rows=1000)"
+        );
+
+        // Filters are taken into account, and projections are pushed through the reordering.
+        assert_eq!(
+            plan([1000, 1000, 10], &format!("{} WHERE c.value = 1", query.replace('*', "a.id")))?,
+            "Projection: a.id\n└─ Projection: a.id, a.b_id, b.id, b.c_id, c.id, c.value\n   └─ HashJoin: inner on b.id = a.b_id\n      ├─ HashJoin: inner on b.c_id = c.id\n      │  ├─ Scan: b (estimated rows=1000)\n      │  └─ Scan: c (c.value = 1) (estimated rows=1)\n      └─ Scan: a (estimated rows=1000)"
+        );
+
+        // Cross products are avoided where possible.
+        assert_eq!(
+            plan([10, 10, 10], "SELECT a.id FROM a, c, b WHERE a.b_id = b.id AND b.c_id = c.id")?,
+            "Projection: a.id\n└─ Projection: a.id, a.b_id, c.id, c.value, b.id, b.c_id\n   └─ HashJoin: inner on b.c_id = c.id\n      ├─ HashJoin: inner on a.b_id = b.id\n      │  ├─ Scan: a (estimated rows=10)\n      │  └─ Scan: b (estimated rows=10)\n      └─ Scan: c [id] (estimated rows=10)"
+        );
+
+        // Outer joins are not reordered.
+        assert_eq!(
+            plan([1000, 100, 10], "SELECT * FROM a LEFT JOIN b ON a.b_id = b.id JOIN c ON b.c_id = c.id")?,
+            "HashJoin: inner on b.c_id = c.id\n├─ HashJoin: outer on a.b_id = b.id\n│  ├─ Scan: a (estimated rows=1000)\n│  └─ Scan: b (estimated rows=100)\n└─ Scan: c (estimated rows=10)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn distinct_elision() -> Result<()> {
+        // Rows that include the primary key of a single-table scan are already distinct.
+        assert_eq!(
+            plan(None, "SELECT DISTINCT id, a FROM test")?,
+            "Projection: id, a\n└─ Scan: test [id, a]"
+        );
+        assert_eq!(
+            plan(None, "SELECT DISTINCT * FROM test WHERE a > 1")?,
+            "Filter: a > 1\n└─ IndexRangeScan: test column a range > 1"
+        );
+        assert_eq!(
+            plan(None, "SELECT DISTINCT ON (id) a FROM test ORDER BY id")?,
+            "Projection: #0\n└─ Presorted: test.id asc (sort elided)\n   └─ Projection: a, id\n      └─ Scan: test [id, a]"
+        );
+        assert_eq!(
+            plan(None, "SELECT DISTINCT a, id FROM test WHERE id IN (1, 2)")?,
+            "Projection: a, id\n└─ KeyLookup: test [id, a] (1, 2)"
+        );
+
+        // Groups are distinct, so are the rows of an aggregation.
+        assert_eq!(
+            plan(None, "SELECT DISTINCT a FROM test GROUP BY a")?,
+            "Projection: test.a\n└─ Aggregation: \n   └─ Projection: a\n      └─ Scan: test [a]"
+        );
+
+        // Other columns may contain duplicates.
+        assert_eq!(
+            plan(None, "SELECT DISTINCT a FROM test")?,
+            "Distinct\n└─ Projection: a\n   └─ Scan: test [a]"
+        );
+        assert_eq!(
+            plan(None, "SELECT DISTINCT id + 1 FROM test")?,
+            "Distinct\n└─ Projection: id + 1\n   └─ Scan: test [id]"
+        );
+        assert_eq!(plan(None, "SELECT DISTINCT a.id FROM test a, test b")?, "Distinct\n└─ Projection: a.id\n   └─ NestedLoopJoin: inner\n      ├─ Scan: test as a [id]\n      └─ Scan: test as b []");
+        Ok(())
+    }
+}
@@ -1,21 +1,71 @@
-use super::super::parser::ast;
-use super::super::schema::{Catalog, Column, Table};
-use super::super::types::{Expression, Value};
-use super::{Aggregate, Direction, Node, Plan};
+use super::super::parser::{ast, Parser};
+use super::super::schema::{Catalog, Column, SystemTable, Table, User, View};
+use super::super::types::{self, DataType, Expression, Timestamp, Value};
+use super::{Aggregate, Direction, Node, Nulls, OnConflict, Plan, SetOperator};
+use super::{Window, WindowFunction};
 use crate::error::{Error, Result};
 
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::mem::replace;
 
 /// A query plan builder.
 pub struct Planner<'a, C: Catalog> {
     catalog: &'a mut C,
+    /// The statement timestamp, returned by NOW() and CURRENT_TIMESTAMP. This is fixed when the
+    /// statement is planned, so that the same value is used for the entire statement and carried
+    /// as a constant in any resulting Raft mutations rather than being re-evaluated by replicas.
+    now: Timestamp,
+    /// Common table expressions visible to the statement being planned, in definition order.
+    /// Later CTEs shadow earlier ones of the same name, and all of them shadow tables.
+    ctes: RefCell<Vec<Cte>>,
+    /// The number of recursive CTE work tables planned so far, used to assign work table IDs.
+    work_tables: Cell<usize>,
+    /// The current subquery nesting depth.
+    subqueries: Cell<usize>,
+    /// The views currently being expanded, outermost first, used to detect cyclic references.
+    views: RefCell<Vec<String>>,
+    /// The parameter values of a prepared statement, bound to its parameter placeholders.
+    params: Vec<Value>,
+}
+
+/// A common table expression visible to the statement being planned.
+struct Cte {
+    name: String,
+    columns: Vec<Option<String>>,
+    source: CteSource,
+}
+
+/// The source of a common table expression.
+#[allow(clippy::large_enum_variant)]
+enum CteSource {
+    /// A planned CTE query, which is inlined at each reference.
+    Node(Node),
+    /// The work table of a recursive CTE whose recursive term is being planned, with the subquery
+    /// depth of the recursive term and the number of references to it so far.
+    WorkTable { id: usize, depth: usize, references: Cell<usize> },
+    /// A recursive CTE that can't be referenced here, with the error message.
+    Invalid(String),
 }
 
 impl<'a, C: Catalog> Planner<'a, C> {
     /// Creates a new planner.
-    pub fn new(catalog: &'a mut C) -> Self {
-        Self { catalog }
+    pub fn new(catalog: &'a mut C) -> Result<Self> {
+        Ok(Self {
+            catalog,
+            now: Timestamp::now()?,
+            ctes: RefCell::new(Vec::new()),
+            work_tables: Cell::new(0),
+            subqueries: Cell::new(0),
+            views: RefCell::new(Vec::new()),
+            params: Vec::new(),
+        })
+    }
+
+    /// Sets the parameter values bound to the statement's parameter placeholders.
+    pub fn with_params(mut self, params: Vec<Value>) -> Self {
+        self.params = params;
+        self
     }
 
     /// Builds a plan for an AST statement.
@@ -34,92 +84,285 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 )))
             }
 
-            ast::Statement::Explain(_) => {
+            ast::Statement::Explain { .. } => {
                 return Err(Error::Internal("Unexpected explain statement".into()))
             }
 
+            ast::Statement::Vacuum => {
+                return Err(Error::Internal("Unexpected vacuum statement".into()))
+            }
+
+            ast::Statement::Set { .. } | ast::Statement::Show { .. } => {
+                return Err(Error::Internal("Unexpected session variable statement".into()))
+            }
+
+            // Catalog introspection statements, as queries over system tables.
+            ast::Statement::ShowTables => {
+                let label = Some((Some(SystemTable::Tables.name().into()), "name".into()));
+                Node::Projection {
+                    source: Box::new(Node::SystemScan { table: SystemTable::Tables, alias: None }),
+                    expressions: vec![(Expression::Field(0, label), None)],
+                }
+            }
+            ast::Statement::ShowCreateTable { table } => {
+                let table = self.catalog.must_read_table(&table)?;
+                let label =
+                    |name: &str| Some((Some(SystemTable::Tables.name().into()), name.into()));
+                Node::Projection {
+                    source: Box::new(Node::Filter {
+                        source: Box::new(Node::SystemScan {
+                            table: SystemTable::Tables,
+                            alias: None,
+                        }),
+                        predicate: Expression::Equal(
+                            Box::new(Expression::Field(0, label("name"))),
+                            Box::new(Expression::Constant(Value::String(table.name))),
+                        ),
+                    }),
+                    expressions: vec![(Expression::Field(1, label("sql")), None)],
+                }
+            }
+
+            ast::Statement::Analyze { table: Some(table) } => {
+                Node::Analyze { tables: vec![self.catalog.must_read_table(&table)?.name] }
+            }
+            ast::Statement::Analyze { table: None } => {
+                Node::Analyze { tables: self.catalog.scan_tables()?.map(|t| t.name).collect() }
+            }
+
             // DDL statements (schema changes).
-            ast::Statement::CreateTable { name, columns } => Node::CreateTable {
-                schema: Table::new(
+            ast::Statement::AlterTable { name, action } => match action {
+                ast::AlterTable::AddColumn { column } => {
+                    if column.generated.is_some() {
+                        return Err(Error::Value(format!(
+                            "Can't add generated column {} to existing table {}",
+                            column.name, name
+                        )));
+                    }
+                    Node::AddColumn { table: name, column: self.build_column(column, false)? }
+                }
+                ast::AlterTable::DropColumn { column } => Node::DropColumn { table: name, column },
+                ast::AlterTable::RenameColumn { column, name: new_name } => {
+                    Node::RenameColumn { table: name, column, new_name }
+                }
+                ast::AlterTable::RenameTable { name: new_name } => {
+                    Node::RenameTable { table: name, new_name }
+                }
+            },
+
+            ast::Statement::CreateTable { name, mut columns, primary_key, if_not_exists } => {
+                self.build_primary_key(&name, &mut columns, primary_key)?;
+                let composite = columns.iter().filter(|c| c.primary_key).count() > 1;
+                let generated = columns.iter().map(|c| c.generated.clone()).collect();
+                let mut schema = Table::new(
                     name,
                     columns
                         .into_iter()
-                        .map(|c| {
-                            let nullable = c.nullable.unwrap_or(!c.primary_key);
-                            let default = match c.default {
-                                Some(expr) => Some(self.evaluate_constant(expr)?),
-                                None if nullable => Some(Value::Null),
-                                None => None,
-                            };
-                            Ok(Column {
-                                name: c.name,
-                                datatype: c.datatype,
-                                primary_key: c.primary_key,
-                                nullable,
-                                default,
-                                index: c.index && !c.primary_key,
-                                unique: c.unique || c.primary_key,
-                                references: c.references,
-                            })
-                        })
+                        .map(|c| self.build_column(c, composite))
                         .collect::<Result<_>>()?,
-                )?,
-            },
+                )?;
+                self.build_generated(&mut schema, generated)?;
+                Node::CreateTable { schema, if_not_exists }
+            }
+
+            ast::Statement::DropTable { name, if_exists } => {
+                Node::DropTable { table: name, if_exists }
+            }
+
+            // The view query is planned to check that it's valid, but is stored as SQL text.
+            ast::Statement::CreateView { name, query } => {
+                self.build_view(&name, &query)?;
+                Node::CreateView { view: View { name, query } }
+            }
+
+            ast::Statement::DropView { name, if_exists } => {
+                Node::DropView { view: name, if_exists }
+            }
 
-            ast::Statement::DropTable(table) => Node::DropTable { table },
+            ast::Statement::CreateUser { name, superuser } => {
+                Node::CreateUser { user: User::new(name, superuser) }
+            }
+
+            ast::Statement::DropUser { name, if_exists } => {
+                Node::DropUser { user: name, if_exists }
+            }
+
+            ast::Statement::Grant { privileges, table, user } => {
+                Node::Grant { privileges, table: self.catalog.must_read_table(&table)?.name, user }
+            }
+
+            ast::Statement::Revoke { privileges, table, user } => {
+                Node::Revoke { privileges, table: self.catalog.must_read_table(&table)?.name, user }
+            }
 
             // DML statements (mutations).
-            ast::Statement::Delete { table, r#where } => {
-                let scope = &mut Scope::from_table(self.catalog.must_read_table(&table)?)?;
+            ast::Statement::Delete { table, using, r#where, returning } => {
+                let scope = &mut Scope::from_table(self.read_target_table(&table)?)?;
                 Node::Delete {
                     table: table.clone(),
-                    source: Box::new(Node::Scan {
-                        table,
-                        alias: None,
-                        filter: r#where.map(|e| self.build_expression(scope, e)).transpose()?,
-                    }),
+                    source: Box::new(self.build_mutation_source(scope, table, using, r#where)?),
+                    returning: self.build_returning(scope, returning)?,
                 }
             }
 
-            ast::Statement::Insert { table, columns, values } => Node::Insert {
-                table,
-                columns: columns.unwrap_or_else(Vec::new),
-                expressions: values
-                    .into_iter()
-                    .map(|exprs| {
-                        exprs
-                            .into_iter()
-                            .map(|expr| self.build_expression(&mut Scope::constant(), expr))
-                            .collect::<Result<_>>()
-                    })
-                    .collect::<Result<_>>()?,
+            ast::Statement::Truncate { table } => {
+                Node::Truncate { table: self.read_target_table(&table)?.name }
+            }
+
+            ast::Statement::CopyFrom { table, path, header, delimiter } => Node::CopyFrom {
+                table: self.read_target_table(&table)?.name,
+                path,
+                header,
+                delimiter,
             },
 
-            ast::Statement::Update { table, set, r#where } => {
-                let scope = &mut Scope::from_table(self.catalog.must_read_table(&table)?)?;
+            ast::Statement::CopyTo { table, path, header, delimiter } => Node::CopyTo {
+                table: self.catalog.must_read_table(&table)?.name,
+                path,
+                header,
+                delimiter,
+            },
+
+            ast::Statement::Insert { table, columns, source, on_conflict, returning } => {
+                Node::Insert {
+                    returning: self.build_returning(
+                        &mut Scope::from_table(self.read_target_table(&table)?)?,
+                        returning,
+                    )?,
+                    on_conflict: on_conflict
+                        .map(|on_conflict| self.build_on_conflict(&table, on_conflict))
+                        .transpose()?,
+                    table,
+                    columns: columns.unwrap_or_else(Vec::new),
+                    source: Box::new(match source {
+                        ast::InsertSource::Values(values) => Node::Values {
+                            rows: values
+                                .into_iter()
+                                .map(|exprs| {
+                                    exprs
+                                        .into_iter()
+                                        .map(|expr| {
+                                            self.build_expression(&mut Scope::constant(), expr)
+                                        })
+                                        .collect::<Result<_>>()
+                                })
+                                .collect::<Result<_>>()?,
+                        },
+                        ast::InsertSource::Select(query) => {
+                            self.build_query(&mut Scope::new(), *query)?
+                        }
+                    }),
+                }
+            }
+
+            ast::Statement::Update { table, set, from, r#where, returning } => {
+                // SET columns are resolved in the target table alone, since FROM tables may have
+                // columns with the same names.
+                let table_schema = self.read_target_table(&table)?;
+                let target = Scope::from_table(table_schema.clone())?;
+                let scope = &mut target.clone();
                 Node::Update {
                     table: table.clone(),
-                    source: Box::new(Node::Scan {
-                        table,
-                        alias: None,
-                        filter: r#where.map(|e| self.build_expression(scope, e)).transpose()?,
-                    }),
+                    source: Box::new(self.build_mutation_source(scope, table, from, r#where)?),
                     expressions: set
                         .into_iter()
                         .map(|(c, e)| {
+                            Self::check_writable(&table_schema, &c)?;
                             Ok((
-                                scope.resolve(None, &c)?,
+                                target.resolve(None, &c)?,
                                 Some(c),
                                 self.build_expression(scope, e)?,
                             ))
                         })
                         .collect::<Result<_>>()?,
+                    returning: self.build_returning(scope, returning)?,
                 }
             }
 
             // Queries.
+            statement @ ast::Statement::Select { .. }
+            | statement @ ast::Statement::SetOperation { .. } => {
+                self.build_query(&mut Scope::new(), statement)?
+            }
+
+            ast::Statement::With { recursive, ctes, statement } => {
+                self.build_with(recursive, ctes, || self.build_statement(*statement))?
+            }
+        })
+    }
+
+    /// Reads the target table of a mutation. Views can't be modified.
+    fn read_target_table(&self, table: &str) -> Result<Table> {
+        if self.catalog.read_view(table)?.is_some() {
+            return Err(Error::Value(format!("Can't modify view {}", table)));
+        }
+        self.catalog.must_read_table(table)
+    }
+
+    /// Builds the source rows of an UPDATE or DELETE statement, given the scope of the target
+    /// table. If other tables are given (i.e. UPDATE FROM or DELETE USING), the target table is
+    /// joined with them, and the rows consist of the target table's columns followed by the other
+    /// tables' columns, which are added to the scope. A target row may then occur several times,
+    /// once per matching row of the other tables. The WHERE clause is built as for SELECT, so it
+    /// may contain correlated EXISTS and IN subqueries.
+    fn build_mutation_source(
+        &self,
+        scope: &mut Scope,
+        table: String,
+        from: Vec<ast::FromItem>,
+        r#where: Option<ast::Expression>,
+    ) -> Result<Node> {
+        let mut node = Node::Scan { table, alias: None, filter: None, columns: None };
+        if !from.is_empty() {
+            let left_size = scope.len();
+            let mut right_scope = Scope::new();
+            let right = self.build_from_clause(&mut right_scope, from)?;
+            scope.merge(right_scope)?;
+            node = Node::NestedLoopJoin {
+                left: Box::new(node),
+                left_size,
+                right: Box::new(right),
+                predicate: None,
+                outer: false,
+                full: false,
+            };
+        }
+        match r#where {
+            Some(expr) => self.build_where_clause(scope, node, expr),
+            None => Ok(node),
+        }
+    }
+
+    /// Builds a RETURNING clause of a mutation, where an empty list returns all columns in the
+    /// scope.
+    fn build_returning(
+        &self,
+        scope: &mut Scope,
+        returning: Option<Vec<(ast::Expression, Option<String>)>>,
+    ) -> Result<Vec<(Expression, Option<String>)>> {
+        match returning {
+            None => Ok(Vec::new()),
+            Some(returning) if returning.is_empty() => (0..scope.len())
+                .map(|i| Ok((Expression::Field(i, scope.get_label(i)?), None)))
+                .collect(),
+            Some(returning) => returning
+                .into_iter()
+                .map(|(expr, label)| Ok((self.build_expression(scope, expr)?, label)))
+                .collect(),
+        }
+    }
+
+    /// Builds a query, i.e. a SELECT statement with an optional WITH clause. The scope is updated
+    /// with the query's result columns.
+    fn build_query(&self, scope: &mut Scope, statement: ast::Statement) -> Result<Node> {
+        Ok(match statement {
+            ast::Statement::With { recursive, ctes, statement } => {
+                self.build_with(recursive, ctes, || self.build_query(scope, *statement))?
+            }
+
             ast::Statement::Select {
                 mut select,
+                mut distinct,
                 from,
                 r#where,
                 group_by,
@@ -128,8 +371,6 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 offset,
                 limit,
             } => {
-                let scope = &mut Scope::new();
-
                 // Build FROM clause.
                 let mut node = if !from.is_empty() {
                     self.build_from_clause(scope, from)?
@@ -141,22 +382,76 @@ impl<'a, C: Catalog> Planner<'a, C> {
 
                 // Build WHERE clause.
                 if let Some(expr) = r#where {
-                    node = Node::Filter {
-                        source: Box::new(node),
-                        predicate: self.build_expression(scope, expr)?,
-                    };
+                    node = self.build_where_clause(scope, node, expr)?;
                 };
 
                 // Build SELECT clause.
                 let mut hidden = 0;
+                if select.is_empty() && (!group_by.is_empty() || having.is_some()) {
+                    return Err(Error::Value("Can't select * with GROUP BY or HAVING".into()));
+                }
                 if !select.is_empty() {
-                    // Inject hidden SELECT columns for fields and aggregates used in ORDER BY and
-                    // HAVING expressions but not present in existing SELECT output. These will be
-                    // removed again by a later projection.
+                    // Window functions are evaluated after HAVING, so with window functions the
+                    // HAVING clause is evaluated right after the aggregation instead of after the
+                    // final projection. It is temporarily appended to the SELECT expressions, such
+                    // that aggregates and groups are extracted from it, with any references to
+                    // SELECT labels replaced by the labelled expressions.
+                    let windowed = select
+                        .iter()
+                        .map(|(e, _)| e)
+                        .chain(order.iter().map(|(e, _, _)| e))
+                        .chain(distinct.iter().flatten())
+                        .any(|e| self.is_window(e));
+                    if let Some(expr) = &having {
+                        if self.is_window(expr) {
+                            return Err(Error::Value(
+                                "Window functions can't be used in HAVING".into(),
+                            ));
+                        }
+                    }
+                    let mut windowed_having = false;
+                    match having.take() {
+                        Some(mut expr) if windowed => {
+                            expr.transform_mut(
+                                &mut |e| match e {
+                                    ast::Expression::Field(None, ref l) => Ok(select
+                                        .iter()
+                                        .find(|(_, label)| label.as_ref() == Some(l))
+                                        .map(|(e, _)| e.clone())
+                                        .unwrap_or(e)),
+                                    e => Ok(e),
+                                },
+                                &mut |e| Ok(e),
+                            )?;
+                            if self.is_window(&expr) {
+                                return Err(Error::Value(
+                                    "Window functions can't be used in HAVING".into(),
+                                ));
+                            }
+                            select.push((expr, None));
+                            windowed_having = true;
+                        }
+                        expr => having = expr,
+                    }
+
+                    // Inject hidden SELECT columns for fields and aggregates used in ORDER BY,
+                    // DISTINCT ON, and HAVING expressions but not present in existing SELECT
+                    // output. These will be removed again by a later projection. SELECT DISTINCT
+                    // only compares the SELECT output, so it can't be ordered by other values.
                     if let Some(ref mut expr) = having {
                         hidden += self.inject_hidden(expr, &mut select)?;
                     }
-                    for (expr, _) in order.iter_mut() {
+                    for (expr, _, _) in order.iter_mut() {
+                        let order_hidden = self.inject_hidden(expr, &mut select)?;
+                        if order_hidden > 0 && matches!(&distinct, Some(on) if on.is_empty()) {
+                            return Err(Error::Value(
+                                "ORDER BY expressions must appear in select list with DISTINCT"
+                                    .into(),
+                            ));
+                        }
+                        hidden += order_hidden;
+                    }
+                    for expr in distinct.iter_mut().flatten() {
                         hidden += self.inject_hidden(expr, &mut select)?;
                     }
 
@@ -176,15 +471,54 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     // - Aggregation: max(#0), min(#1) group by #2
                     // - Projection: (#0 - #1) / 100
                     let aggregates = self.extract_aggregates(&mut select)?;
+                    // A HAVING clause without aggregates or groups aggregates all rows into a
+                    // single group, as in SQL.
                     let groups = self.extract_groups(&mut select, group_by, aggregates.len())?;
-                    if !aggregates.is_empty() || !groups.is_empty() {
+                    let mut source_scope = None;
+                    if !aggregates.is_empty()
+                        || !groups.is_empty()
+                        || having.is_some()
+                        || windowed_having
+                    {
+                        source_scope = Some(scope.clone());
                         node = self.build_aggregation(scope, node, groups, aggregates)?;
                     }
+                    if windowed_having {
+                        let (expr, _) = select
+                            .pop()
+                            .ok_or_else(|| Error::Internal("Missing HAVING expression".into()))?;
+                        if let Some(source_scope) = &source_scope {
+                            self.check_grouped(scope, source_scope, &expr)?;
+                        }
+                        let predicate = self.build_expression(scope, expr)?;
+                        node = Node::Filter { source: Box::new(node), predicate };
+                    }
+
+                    // Build any window functions, which are evaluated after the aggregation and
+                    // HAVING clause but before the final projection. For example:
+                    //
+                    // SELECT title, RANK() OVER (PARTITION BY genre_id ORDER BY rating DESC)
+                    // FROM movies
+                    //
+                    // Results in the following nodes:
+                    //
+                    // - Scan: movies
+                    // - Window: rank() over (partition by genre_id order by rating desc)
+                    // - Projection: title, #n
+                    if windowed {
+                        node =
+                            self.build_window(scope, source_scope.as_ref(), node, &mut select)?;
+                    }
 
                     // Build the remaining non-aggregate projection.
                     let expressions: Vec<(Expression, Option<String>)> = select
                         .into_iter()
-                        .map(|(e, l)| Ok((self.build_expression(scope, e)?, l)))
+                        .map(|(e, l)| {
+                            if let Some(source_scope) = &source_scope {
+                                self.check_grouped(scope, source_scope, &e)?;
+                            }
+                            Ok((self.build_expression(scope, e)?, l))
+                        })
                         .collect::<Result<_>>()?;
                     scope.project(&expressions)?;
                     node = Node::Projection { source: Box::new(node), expressions };
@@ -198,60 +532,575 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     };
                 };
 
-                // Build ORDER clause.
-                if !order.is_empty() {
-                    node = Node::Order {
-                        source: Box::new(node),
-                        orders: order
-                            .into_iter()
-                            .map(|(e, o)| {
-                                Ok((
-                                    self.build_expression(scope, e)?,
-                                    match o {
-                                        ast::Order::Ascending => Direction::Ascending,
-                                        ast::Order::Descending => Direction::Descending,
-                                    },
-                                ))
-                            })
-                            .collect::<Result<_>>()?,
-                    };
+                // Build ORDER, DISTINCT, OFFSET, and LIMIT clauses.
+                let columns = scope.len() - hidden;
+                node =
+                    self.build_order_limit(scope, node, columns, order, distinct, offset, limit)?;
+
+                // Remove any hidden columns.
+                if hidden > 0 {
+                    let expressions: Vec<_> = (0..(scope.len() - hidden))
+                        .map(|i| (Expression::Field(i, None), None))
+                        .collect();
+                    scope.project(&expressions)?;
+                    node = Node::Projection { source: Box::new(node), expressions }
                 }
 
-                // Build OFFSET clause.
-                if let Some(expr) = offset {
-                    node = Node::Offset {
-                        source: Box::new(node),
-                        offset: match self.evaluate_constant(expr)? {
-                            Value::Integer(i) if i >= 0 => Ok(i as u64),
-                            v => Err(Error::Value(format!("Invalid offset {}", v))),
-                        }?,
-                    }
+                node
+            }
+
+            // The left query determines the column names, and the right query is planned in a
+            // separate scope. ORDER BY, OFFSET, and LIMIT apply to the combined rows. For example:
+            //
+            // SELECT id FROM movies UNION SELECT id FROM genres ORDER BY id
+            //
+            // Results in the following nodes:
+            //
+            // - Order: id asc
+            //   - SetOperation: union
+            //     - Projection: id
+            //       - Scan: movies
+            //     - Projection: id
+            //       - Scan: genres
+            ast::Statement::SetOperation { operator, left, right, all, order, offset, limit } => {
+                let left = self.build_query(scope, *left)?;
+                let mut right_scope = Scope::new();
+                let right = self.build_query(&mut right_scope, *right)?;
+                let operator = match operator {
+                    ast::SetOperator::Union => SetOperator::Union,
+                    ast::SetOperator::Intersect => SetOperator::Intersect,
+                    ast::SetOperator::Except => SetOperator::Except,
+                };
+                let node =
+                    self.build_set_operation(operator, scope, left, &right_scope, right, all)?;
+                let columns = scope.len();
+                self.build_order_limit(scope, node, columns, order, None, offset, limit)?
+            }
+
+            statement => return Err(Error::Internal(format!("Unexpected query {:?}", statement))),
+        })
+    }
+
+    /// Builds a set operation on two query nodes with the given scopes, which must have the same
+    /// number of columns. Duplicate rows are discarded unless all is true.
+    fn build_set_operation(
+        &self,
+        operator: SetOperator,
+        scope: &Scope,
+        left: Node,
+        right_scope: &Scope,
+        right: Node,
+        all: bool,
+    ) -> Result<Node> {
+        if scope.len() != right_scope.len() {
+            return Err(Error::Value(format!(
+                "{} queries have different numbers of columns: {} and {}",
+                operator.to_string().to_uppercase(),
+                scope.len(),
+                right_scope.len()
+            )));
+        }
+        Ok(Node::SetOperation { operator, left: Box::new(left), right: Box::new(right), all })
+    }
+
+    /// Builds ORDER BY, DISTINCT, OFFSET, and LIMIT clauses for a query node with the given number
+    /// of output columns, followed by any hidden columns. DISTINCT is applied after ORDER BY, such
+    /// that DISTINCT ON keeps the first row of each key in the given order.
+    #[allow(clippy::too_many_arguments)]
+    fn build_order_limit(
+        &self,
+        scope: &mut Scope,
+        mut node: Node,
+        columns: usize,
+        order: Vec<(ast::Expression, ast::Order, Option<ast::Nulls>)>,
+        distinct: Option<Vec<ast::Expression>>,
+        offset: Option<ast::Expression>,
+        limit: Option<ast::Expression>,
+    ) -> Result<Node> {
+        // Build ORDER clause.
+        if !order.is_empty() {
+            node = Node::Order {
+                source: Box::new(node),
+                orders: order
+                    .into_iter()
+                    .map(|(e, o, n)| {
+                        let (direction, nulls) = Self::build_order(o, n);
+                        Ok((
+                            self.build_output_expression(scope, columns, e, "ORDER BY")?,
+                            direction,
+                            nulls,
+                        ))
+                    })
+                    .collect::<Result<_>>()?,
+            };
+        }
+
+        // Build DISTINCT clause. Plain DISTINCT compares the output columns, which must be given
+        // explicitly if there are hidden columns.
+        if let Some(on) = distinct {
+            let on = if on.is_empty() && columns < scope.len() {
+                (0..columns)
+                    .map(|i| Ok(Expression::Field(i, scope.get_label(i)?)))
+                    .collect::<Result<_>>()?
+            } else {
+                on.into_iter()
+                    .map(|e| self.build_output_expression(scope, columns, e, "DISTINCT ON"))
+                    .collect::<Result<_>>()?
+            };
+            node = Node::Distinct { source: Box::new(node), on };
+        }
+
+        // Build OFFSET clause.
+        if let Some(expr) = offset {
+            node = Node::Offset {
+                source: Box::new(node),
+                offset: match self.evaluate_constant(expr)? {
+                    Value::Integer(i) if i >= 0 => Ok(i as u64),
+                    v => Err(Error::Value(format!("Invalid offset {}", v))),
+                }?,
+            }
+        }
+
+        // Build LIMIT clause.
+        if let Some(expr) = limit {
+            node = Node::Limit {
+                source: Box::new(node),
+                limit: match self.evaluate_constant(expr)? {
+                    Value::Integer(i) if i >= 0 => Ok(i as u64),
+                    v => Err(Error::Value(format!("Invalid limit {}", v))),
+                }?,
+            }
+        }
+        Ok(node)
+    }
+
+    /// Builds a sort direction and NULL placement, defaulting to Nulls::default_for().
+    fn build_order(order: ast::Order, nulls: Option<ast::Nulls>) -> (Direction, Nulls) {
+        let direction = match order {
+            ast::Order::Ascending => Direction::Ascending,
+            ast::Order::Descending => Direction::Descending,
+        };
+        let nulls = match nulls {
+            Some(ast::Nulls::First) => Nulls::First,
+            Some(ast::Nulls::Last) => Nulls::Last,
+            None => Nulls::default_for(&direction),
+        };
+        (direction, nulls)
+    }
+
+    /// Builds an ORDER BY or DISTINCT ON expression for a query node with the given number of
+    /// output columns. Integer literals refer to output columns by position, from 1.
+    fn build_output_expression(
+        &self,
+        scope: &mut Scope,
+        columns: usize,
+        expr: ast::Expression,
+        clause: &str,
+    ) -> Result<Expression> {
+        match expr {
+            ast::Expression::Literal(ast::Literal::Integer(i)) => {
+                if i < 1 || i as usize > columns {
+                    return Err(Error::Value(format!(
+                        "{} position {} is not in select list",
+                        clause, i
+                    )));
                 }
+                let index = i as usize - 1;
+                Ok(Expression::Field(index, scope.get_label(index)?))
+            }
+            expr => self.build_expression(scope, expr),
+        }
+    }
 
-                // Build LIMIT clause.
-                if let Some(expr) = limit {
-                    node = Node::Limit {
-                        source: Box::new(node),
-                        limit: match self.evaluate_constant(expr)? {
-                            Value::Integer(i) if i >= 0 => Ok(i as u64),
-                            v => Err(Error::Value(format!("Invalid limit {}", v))),
-                        }?,
-                    }
+    /// Builds a statement with a WITH clause. The common table expressions are planned in order,
+    /// such that each can reference the previous ones, and are then visible to the statement
+    /// built by the given closure. They are inlined at each reference.
+    fn build_with<F>(&self, recursive: bool, ctes: Vec<ast::Cte>, build: F) -> Result<Node>
+    where
+        F: FnOnce() -> Result<Node>,
+    {
+        let depth = self.ctes.borrow().len();
+        let result = (|| {
+            let mut names = HashSet::new();
+            for cte in ctes {
+                if !names.insert(cte.name.clone()) {
+                    return Err(Error::Value(format!("Duplicate CTE name {}", cte.name)));
                 }
+                let cte = self.build_cte(recursive, cte)?;
+                self.ctes.borrow_mut().push(cte);
+            }
+            build()
+        })();
+        self.ctes.borrow_mut().truncate(depth);
+        result
+    }
 
-                // Remove any hidden columns.
-                if hidden > 0 {
-                    node = Node::Projection {
-                        source: Box::new(node),
-                        expressions: (0..(scope.len() - hidden))
-                            .map(|i| (Expression::Field(i, None), None))
-                            .collect(),
+    /// Builds a common table expression. In a recursive WITH clause, the CTE's name refers to
+    /// the CTE itself, which can only be referenced once in the FROM clause of its recursive
+    /// term. The recursive term is evaluated repeatedly against the work table, containing the
+    /// rows emitted by the previous iteration, until it emits no rows. For example:
+    ///
+    /// WITH RECURSIVE t (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 3) SELECT * FROM t
+    ///
+    /// Results in the following nodes:
+    ///
+    /// - CTE: t
+    ///   - RecursiveUnion: t (all)
+    ///     - Projection: 1
+    ///     - Projection: n + 1
+    ///       - Filter: n < 3
+    ///         - WorkTable: t
+    fn build_cte(&self, recursive: bool, cte: ast::Cte) -> Result<Cte> {
+        let ast::Cte { name, columns: aliases, query } = cte;
+
+        // In a recursive WITH clause, the right-most query of a UNION is a recursive term if it
+        // references the CTE, and the left query is the seed.
+        let (query, term) = match query {
+            ast::Statement::SetOperation {
+                operator: ast::SetOperator::Union,
+                left,
+                right,
+                all,
+                order,
+                offset: None,
+                limit: None,
+            } if recursive && order.is_empty() => (*left, Some((*right, all))),
+            query => (query, None),
+        };
+
+        let mut scope = Scope::new();
+        let seed = if recursive {
+            let message = match term {
+                Some(_) => {
+                    format!("Recursive CTE {} can't reference itself in its seed query", name)
+                }
+                None => format!("CTE {} can only reference itself in a UNION recursive term", name),
+            };
+            let source = CteSource::Invalid(message);
+            self.with_cte(Cte { name: name.clone(), columns: Vec::new(), source }, || {
+                self.build_query(&mut scope, query)
+            })?
+            .0
+        } else {
+            self.build_query(&mut scope, query)?
+        };
+
+        let mut columns: Vec<Option<String>> =
+            scope.columns.iter().map(|(_, label)| label.clone()).collect();
+        let aliases = aliases.unwrap_or_default();
+        if aliases.len() > columns.len() {
+            return Err(Error::Value(format!(
+                "CTE {} has {} columns, but {} column aliases were given",
+                name,
+                columns.len(),
+                aliases.len()
+            )));
+        }
+        for (column, alias) in columns.iter_mut().zip(aliases) {
+            *column = Some(alias);
+        }
+
+        let (term, all) = match term {
+            Some(term) => term,
+            None => return Ok(Cte { name, columns, source: CteSource::Node(seed) }),
+        };
+        let aggregated = match &term {
+            ast::Statement::Select { select, group_by, having, .. } => {
+                !group_by.is_empty()
+                    || having.is_some()
+                    || select.iter().any(|(e, _)| self.is_aggregate(e) || self.is_window(e))
+            }
+            _ => false,
+        };
+        let id = self.work_tables.get();
+        self.work_tables.set(id + 1);
+        let source =
+            CteSource::WorkTable { id, depth: self.subqueries.get(), references: Cell::new(0) };
+        let mut term_scope = Scope::new();
+        let (recursive, work_table) = self
+            .with_cte(Cte { name: name.clone(), columns: columns.clone(), source }, || {
+                self.build_query(&mut term_scope, term)
+            })?;
+        if let CteSource::WorkTable { references, .. } = work_table.source {
+            if references.get() == 0 {
+                let node = self.build_set_operation(
+                    SetOperator::Union,
+                    &scope,
+                    seed,
+                    &term_scope,
+                    recursive,
+                    all,
+                )?;
+                return Ok(Cte { name, columns, source: CteSource::Node(node) });
+            }
+        }
+        if aggregated {
+            return Err(Error::Value(format!(
+                "Recursive CTE {} can't use aggregates, window functions, GROUP BY, or HAVING in \
+                 its recursive term",
+                name
+            )));
+        }
+        if term_scope.len() != columns.len() {
+            return Err(Error::Value(format!(
+                "Recursive CTE {} has {} columns in its seed query, but {} in its recursive term",
+                name,
+                columns.len(),
+                term_scope.len()
+            )));
+        }
+        let node = Node::RecursiveUnion {
+            seed: Box::new(seed),
+            recursive: Box::new(recursive),
+            name: name.clone(),
+            id,
+            all,
+        };
+        Ok(Cte { name, columns, source: CteSource::Node(node) })
+    }
+
+    /// Builds a view query, returning the node and its column labels. The query is planned in a
+    /// separate context, such that CTEs of the referencing statement aren't visible to it. Views
+    /// may reference other views, but not themselves, neither directly nor indirectly.
+    fn build_view(&self, name: &str, query: &str) -> Result<(Node, Vec<Option<String>>)> {
+        if self.views.borrow().iter().any(|v| v == name) {
+            let mut cycle = self.views.borrow().clone();
+            cycle.push(name.to_string());
+            return Err(Error::Value(format!("Cyclic view reference {}", cycle.join(" -> "))));
+        }
+        let statement = Parser::new(query).parse()?;
+        let ctes = self.ctes.replace(Vec::new());
+        self.views.borrow_mut().push(name.to_string());
+        let mut scope = Scope::new();
+        let result = self.build_query(&mut scope, statement);
+        self.views.borrow_mut().pop();
+        self.ctes.replace(ctes);
+        Ok((result?, scope.columns.into_iter().map(|(_, label)| label).collect()))
+    }
+
+    /// Builds a node with the given CTE temporarily visible, returning the node and the CTE.
+    fn with_cte<F>(&self, cte: Cte, build: F) -> Result<(Node, Cte)>
+    where
+        F: FnOnce() -> Result<Node>,
+    {
+        self.ctes.borrow_mut().push(cte);
+        let result = build();
+        let cte = self.ctes.borrow_mut().pop().ok_or_else(|| Error::Internal("No CTE".into()))?;
+        Ok((result?, cte))
+    }
+
+    /// Builds a table column schema from a column specification. Composite indicates that the
+    /// column is part of a composite primary key, whose columns are not unique by themselves.
+    fn build_column(&self, c: ast::Column, composite: bool) -> Result<Column> {
+        let key = c.primary_key && !composite;
+        let nullable = c.nullable.unwrap_or(!c.primary_key);
+        // Generated columns are computed on write, so they have no default (see build_generated).
+        let generated = c.generated.is_some();
+        if generated && c.primary_key {
+            return Err(Error::Value(format!("Primary key {} can't be generated", c.name)));
+        }
+        if generated && c.default.is_some() {
+            return Err(Error::Value(format!(
+                "Generated column {} can't have a default value",
+                c.name
+            )));
+        }
+        let default = match c.default {
+            Some(expr) => Some(self.evaluate_constant(expr)?),
+            None if nullable && !generated => Some(Value::Null),
+            None => None,
+        };
+        let mut column = Column {
+            name: c.name,
+            datatype: c.datatype,
+            primary_key: c.primary_key,
+            nullable,
+            default: None,
+            generated: None,
+            index: c.index && !key,
+            unique: c.unique || key,
+            references: c.references,
+        };
+        column.default = default.map(|v| column.conform_value(v)).transpose()?;
+        Ok(column)
+    }
+
+    /// Builds the expressions of a new table's generated columns, given in column order. They are
+    /// evaluated on the table's rows, and must be deterministic and only reference non-generated
+    /// columns, such that they can be computed once the other values of a row are known.
+    fn build_generated(
+        &self,
+        table: &mut Table,
+        exprs: Vec<Option<ast::Expression>>,
+    ) -> Result<()> {
+        let columns = Node::table_columns(table);
+        let mut scope = Scope::from_table(table.clone())?;
+        for (i, expr) in exprs.iter().enumerate() {
+            let expr = match expr {
+                Some(expr) => expr.clone(),
+                None => continue,
+            };
+            let name = &table.columns[i].name;
+            if expr.contains(&|e| {
+                matches!(e, ast::Expression::Function(f, _) if f == "now" || f == "current_timestamp")
+            }) {
+                return Err(Error::Value(format!("Generated column {} must be deterministic", name)));
+            }
+            // Fields are labeled by column name alone, such that they're unaffected by table
+            // renames.
+            let expr = self.build_expression(&mut scope, expr)?.transform(
+                &|e| match e {
+                    Expression::Field(i, _) => {
+                        Ok(Expression::Field(i, columns[i].name.clone().map(|n| (None, n))))
                     }
+                    e => Ok(e),
+                },
+                &Ok,
+            )?;
+            if expr.contains(&|e| {
+                matches!(
+                    e,
+                    Expression::Subquery(_) | Expression::Exists(_) | Expression::InSubquery(..)
+                )
+            }) {
+                return Err(Error::Value(format!(
+                    "Generated column {} can't contain subqueries",
+                    name
+                )));
+            }
+            if let Some(j) = (0..exprs.len()).find(|j| {
+                exprs[*j].is_some()
+                    && expr.contains(&|e| matches!(e, Expression::Field(f, _) if f == j))
+            }) {
+                return Err(Error::Value(format!(
+                    "Generated column {} can't reference generated column {}",
+                    name, table.columns[j].name
+                )));
+            }
+            let column = &mut table.columns[i];
+            if let Some(datatype) = expr.datatype(&columns, self.catalog)? {
+                let conforms = match (&column.datatype, &datatype) {
+                    (DataType::Decimal(..), DataType::Integer)
+                    | (DataType::Decimal(..), DataType::Float)
+                    | (DataType::Decimal(..), DataType::Decimal(..)) => true,
+                    (expect, actual) => expect == actual,
+                };
+                if !conforms {
+                    return Err(Error::Value(format!(
+                        "Generated column {} has datatype {}, must be {}",
+                        column.name, datatype, column.datatype
+                    )));
                 }
+            }
+            column.generated = Some(expr);
+        }
+        Ok(())
+    }
 
-                node
+    /// Checks that a column can be written directly, i.e. that it isn't a generated column.
+    fn check_writable(table: &Table, column: &str) -> Result<()> {
+        match table.columns.iter().find(|c| c.name == column) {
+            Some(c) if c.generated.is_some() => {
+                Err(Error::Value(format!("Can't write to generated column {}", c.name)))
             }
-        })
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies a table-level PRIMARY KEY constraint to the given columns, marking them as primary
+    /// key columns. Composite keys are ordered by column, so the key columns must be given in the
+    /// same order as in the table.
+    fn build_primary_key(
+        &self,
+        table: &str,
+        columns: &mut [ast::Column],
+        primary_key: Option<Vec<String>>,
+    ) -> Result<()> {
+        if columns.iter().filter(|c| c.primary_key).count() > 1 {
+            return Err(Error::Value(format!("Multiple primary keys in table {}", table)));
+        }
+        let names = match primary_key {
+            Some(_) if columns.iter().any(|c| c.primary_key) => {
+                return Err(Error::Value(format!("Multiple primary keys in table {}", table)))
+            }
+            Some(names) => names,
+            None => return Ok(()),
+        };
+        let mut last = None;
+        for name in names {
+            let index = match columns.iter().position(|c| c.name == name) {
+                Some(index) => index,
+                None => {
+                    return Err(Error::Value(format!(
+                        "Primary key column {} not found in table {}",
+                        name, table
+                    )))
+                }
+            };
+            if columns[index].primary_key {
+                return Err(Error::Value(format!("Duplicate primary key column {}", name)));
+            }
+            if last.map(|last| index < last).unwrap_or(false) {
+                return Err(Error::Value(format!(
+                    "Primary key columns must be given in table column order, found {}",
+                    name
+                )));
+            }
+            columns[index].primary_key = true;
+            last = Some(index);
+        }
+        Ok(())
+    }
+
+    /// Builds an INSERT ON CONFLICT clause. The conflict columns default to the primary key, and
+    /// must otherwise be the primary key columns or a single unique column. Update expressions
+    /// refer to the existing row by the table name, and to the inserted row as excluded.
+    fn build_on_conflict(&self, table: &str, on_conflict: ast::OnConflict) -> Result<OnConflict> {
+        let table = self.catalog.must_read_table(table)?;
+        let primary_key = table.get_primary_key_indexes();
+        let columns = match on_conflict.columns {
+            None => primary_key,
+            Some(names) => {
+                let mut columns = names
+                    .iter()
+                    .map(|name| table.get_column_index(name))
+                    .collect::<Result<Vec<_>>>()?;
+                columns.sort_unstable();
+                columns.dedup();
+                if columns != primary_key
+                    && !(columns.len() == 1 && table.columns[columns[0]].unique)
+                {
+                    return Err(Error::Value(format!(
+                        "ON CONFLICT columns ({}) must be the primary key or a unique column of \
+                         table {}",
+                        names.join(", "),
+                        table.name
+                    )));
+                }
+                columns
+            }
+        };
+        let update = match on_conflict.action {
+            ast::ConflictAction::Nothing => None,
+            ast::ConflictAction::Update(set) => {
+                let name = table.name.clone();
+                let mut scope = Scope::from_table(table.clone())?;
+                scope.add_table("excluded".into(), table.clone())?;
+                Some(
+                    set.into_iter()
+                        .map(|(c, e)| {
+                            Self::check_writable(&table, &c)?;
+                            Ok((
+                                scope.resolve(Some(&name), &c)?,
+                                Some(c),
+                                self.build_expression(&mut scope, e)?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
+                )
+            }
+        };
+        Ok(OnConflict { columns, update })
     }
 
     /// Builds a FROM clause consisting of several items. Each item is either a single table or a
@@ -273,6 +1122,7 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 right: Box::new(right),
                 predicate: None,
                 outer: false,
+                full: false,
             };
             scope.merge(right_scope)?;
         }
@@ -286,11 +1136,61 @@ impl<'a, C: Catalog> Planner<'a, C> {
     fn build_from_item(&self, scope: &mut Scope, item: ast::FromItem) -> Result<Node> {
         Ok(match item {
             ast::FromItem::Table { name, alias } => {
+                if let Some(cte) = self.ctes.borrow().iter().rev().find(|cte| cte.name == name) {
+                    let label = alias.clone().unwrap_or_else(|| name.clone());
+                    return Ok(match &cte.source {
+                        CteSource::Node(node) => {
+                            scope.add_relation(label, cte.columns.clone())?;
+                            Node::Cte {
+                                source: Box::new(node.clone()),
+                                name,
+                                alias,
+                                columns: cte.columns.clone(),
+                            }
+                        }
+                        CteSource::WorkTable { id, depth, references } => {
+                            if self.subqueries.get() > *depth {
+                                return Err(Error::Value(format!(
+                                    "Recursive CTE {} can't be referenced in a subquery",
+                                    name
+                                )));
+                            }
+                            if references.get() > 0 {
+                                return Err(Error::Value(format!(
+                                    "Recursive CTE {} can only be referenced once in its \
+                                     recursive term",
+                                    name
+                                )));
+                            }
+                            references.set(references.get() + 1);
+                            scope.add_relation(label, cte.columns.clone())?;
+                            Node::WorkTable { name, id: *id }
+                        }
+                        CteSource::Invalid(message) => return Err(Error::Value(message.clone())),
+                    });
+                }
+                if let Some(view) = self.catalog.read_view(&name)? {
+                    let (source, columns) = self.build_view(&view.name, &view.query)?;
+                    scope.add_relation(
+                        alias.clone().unwrap_or_else(|| name.clone()),
+                        columns.clone(),
+                    )?;
+                    return Ok(Node::View { source: Box::new(source), name, alias, columns });
+                }
                 scope.add_table(
                     alias.clone().unwrap_or_else(|| name.clone()),
                     self.catalog.must_read_table(&name)?,
                 )?;
-                Node::Scan { table: name, alias, filter: None }
+                Node::Scan { table: name, alias, filter: None, columns: None }
+            }
+
+            ast::FromItem::SystemTable { name, alias } => {
+                let table = SystemTable::from_name(&name)?;
+                scope.add_relation(
+                    alias.clone().unwrap_or(name),
+                    table.columns().into_iter().map(|(c, _)| Some(c.to_string())).collect(),
+                )?;
+                Node::SystemScan { table, alias }
             }
 
             ast::FromItem::Join { left, right, r#type, predicate } => {
@@ -304,11 +1204,13 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 let left_size = scope.len();
                 let right = Box::new(self.build_from_item(scope, *right)?);
                 let predicate = predicate.map(|e| self.build_expression(scope, e)).transpose()?;
-                let outer = match r#type {
-                    ast::JoinType::Cross | ast::JoinType::Inner => false,
-                    ast::JoinType::Left | ast::JoinType::Right => true,
+                let (outer, full) = match r#type {
+                    ast::JoinType::Cross | ast::JoinType::Inner => (false, false),
+                    ast::JoinType::Left | ast::JoinType::Right => (true, false),
+                    ast::JoinType::Full => (true, true),
                 };
-                let mut node = Node::NestedLoopJoin { left, left_size, right, predicate, outer };
+                let mut node =
+                    Node::NestedLoopJoin { left, left_size, right, predicate, outer, full };
                 if matches!(r#type, ast::JoinType::Right) {
                     let expressions = (left_size..scope.len())
                         .chain(0..left_size)
@@ -322,7 +1224,209 @@ impl<'a, C: Catalog> Planner<'a, C> {
         })
     }
 
-    /// Builds an aggregation node. All aggregate parameters and GROUP BY expressions are evaluated
+    /// Builds a WHERE clause filtering the given source node, with any correlated EXISTS and IN
+    /// conditions as semi-joins.
+    fn build_where_clause(
+        &self,
+        scope: &mut Scope,
+        source: Node,
+        expr: ast::Expression,
+    ) -> Result<Node> {
+        let mut node = source;
+        let mut semijoins = Vec::new();
+        if let Some(predicate) = self.build_where(scope, expr, &mut semijoins)? {
+            node = Node::Filter { source: Box::new(node), predicate };
+        }
+        for (right, predicate, anti) in semijoins {
+            node = Node::SemiJoin {
+                left: Box::new(node),
+                left_size: scope.len(),
+                right: Box::new(right),
+                predicate,
+                anti,
+            };
+        }
+        Ok(node)
+    }
+
+    /// Builds a WHERE predicate. Correlated EXISTS conditions can't be evaluated as expressions,
+    /// and are instead returned as semi-joins (the subquery source, join predicate, and whether
+    /// it's an anti-join for NOT EXISTS). IN subqueries are also returned as semi-joins, which
+    /// can be executed as hash joins rather than comparing each row with every subquery value.
+    /// This requires them to be top-level conjunctions, i.e. joined by AND. Returns None if there
+    /// is no predicate left.
+    fn build_where(
+        &self,
+        scope: &mut Scope,
+        expr: ast::Expression,
+        semijoins: &mut Vec<(Node, Option<Expression>, bool)>,
+    ) -> Result<Option<Expression>> {
+        Ok(match expr {
+            ast::Expression::Operation(ast::Operation::And(lhs, rhs)) => {
+                match (
+                    self.build_where(scope, *lhs, semijoins)?,
+                    self.build_where(scope, *rhs, semijoins)?,
+                ) {
+                    (Some(lhs), Some(rhs)) => Some(Expression::And(lhs.into(), rhs.into())),
+                    (lhs, None) => lhs,
+                    (None, rhs) => rhs,
+                }
+            }
+            ast::Expression::Operation(ast::Operation::Exists(select)) => {
+                self.build_where_exists(scope, *select, false, semijoins)?
+            }
+            ast::Expression::Operation(ast::Operation::InSubquery(lhs, select)) => {
+                self.build_where_in(scope, *lhs, *select, false, semijoins)?
+            }
+            ast::Expression::Operation(ast::Operation::Not(expr)) => match *expr {
+                ast::Expression::Operation(ast::Operation::Exists(select)) => {
+                    self.build_where_exists(scope, *select, true, semijoins)?
+                }
+                ast::Expression::Operation(ast::Operation::InSubquery(lhs, select)) => {
+                    self.build_where_in(scope, *lhs, *select, true, semijoins)?
+                }
+                expr => Some(Expression::Not(self.build_expression(scope, expr)?.into())),
+            },
+            expr => Some(self.build_expression(scope, expr)?),
+        })
+    }
+
+    /// Builds an [NOT] EXISTS condition in a WHERE clause, returning it as an expression if it's
+    /// uncorrelated and otherwise as a semi-join. Correlated subqueries only have their FROM and
+    /// WHERE clauses planned, so other clauses that affect the result aren't supported.
+    fn build_where_exists(
+        &self,
+        scope: &Scope,
+        select: ast::Statement,
+        anti: bool,
+        semijoins: &mut Vec<(Node, Option<Expression>, bool)>,
+    ) -> Result<Option<Expression>> {
+        let supported = self.is_correlatable(&select);
+        match self.build_subquery(scope, select)? {
+            SubqueryPlan::Uncorrelated(node) => {
+                let exists = Expression::Exists(node.into());
+                Ok(Some(if anti { Expression::Not(exists.into()) } else { exists }))
+            }
+            SubqueryPlan::Correlated(..) if !supported => Err(Self::correlated_unsupported()),
+            SubqueryPlan::Correlated(source, predicate, _) => {
+                semijoins.push((source, predicate, anti));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Builds an [NOT] IN subquery condition in a WHERE clause, returning it as a semi-join that
+    /// matches the left-hand expression against the subquery's single column. Uncorrelated
+    /// subqueries are used as the right source as-is (falling back to an expression if they
+    /// don't return a single column, which errors during execution). Correlated subqueries only
+    /// have their FROM and WHERE clauses planned, as for EXISTS, and the SELECT expression is
+    /// evaluated on the joined rows.
+    ///
+    /// For NOT IN, a NULL comparison is considered a match, such that the anti-join emits no rows
+    /// for NULL left-hand values or if the subquery returns a NULL (unless it's empty). This is
+    /// the standard SQL behavior, where NOT IN yields NULL rather than TRUE in these cases.
+    fn build_where_in(
+        &self,
+        scope: &mut Scope,
+        lhs: ast::Expression,
+        select: ast::Statement,
+        anti: bool,
+        semijoins: &mut Vec<(Node, Option<Expression>, bool)>,
+    ) -> Result<Option<Expression>> {
+        let supported = self.is_correlatable(&select);
+        let lhs = self.build_expression(scope, lhs)?;
+        let (source, predicate, rhs) = match self.build_subquery(scope, select)? {
+            SubqueryPlan::Uncorrelated(node) => match node.columns(&*self.catalog)?.as_slice() {
+                [column] => {
+                    let label = column.name.clone().map(|name| (None, name));
+                    (node, None, Expression::Field(scope.len(), label))
+                }
+                _ => {
+                    let expr = Expression::InSubquery(lhs.into(), node.into());
+                    return Ok(Some(if anti { Expression::Not(expr.into()) } else { expr }));
+                }
+            },
+            SubqueryPlan::Correlated(..) if !supported => {
+                return Err(Self::correlated_unsupported())
+            }
+            SubqueryPlan::Correlated(source, predicate, mut exprs) if exprs.len() == 1 => {
+                (source, predicate, exprs.remove(0))
+            }
+            SubqueryPlan::Correlated(..) => {
+                return Err(Error::Value("IN subquery must select a single column".into()))
+            }
+        };
+        let mut matches = Expression::Equal(lhs.into(), rhs.into());
+        if anti {
+            matches = Expression::IsDistinctFrom(
+                matches.into(),
+                Expression::Constant(Value::Boolean(false)).into(),
+            );
+        }
+        let predicate = match predicate {
+            Some(predicate) => Expression::And(predicate.into(), matches.into()),
+            None => matches,
+        };
+        semijoins.push((source, Some(predicate), anti));
+        Ok(None)
+    }
+
+    /// Returns true if a subquery can be planned as a correlated subquery, i.e. it doesn't use
+    /// clauses that correlated subqueries don't plan.
+    fn is_correlatable(&self, select: &ast::Statement) -> bool {
+        match select {
+            ast::Statement::Select { select, group_by, having, offset, limit, .. } => {
+                group_by.is_empty()
+                    && having.is_none()
+                    && offset.is_none()
+                    && limit.is_none()
+                    && !select.iter().any(|(e, _)| self.is_aggregate(e))
+            }
+            _ => false,
+        }
+    }
+
+    /// Builds a subquery. If the subquery can't be built on its own, it's built as a correlated
+    /// subquery which can reference columns in the given outer scope, returning the error if that
+    /// fails too. The subquery depth is tracked while building it.
+    fn build_subquery(&self, scope: &Scope, statement: ast::Statement) -> Result<SubqueryPlan> {
+        self.subqueries.set(self.subqueries.get() + 1);
+        let result = (|| {
+            let err = match self.build_statement(statement.clone()) {
+                Ok(node) => return Ok(SubqueryPlan::Uncorrelated(node)),
+                Err(err) => err,
+            };
+            match statement {
+                ast::Statement::Select { select, from, r#where, .. } if !from.is_empty() => {
+                    let mut inner = Scope::new();
+                    let source = self.build_from_clause(&mut inner, from)?;
+                    let mut scope = match scope.correlate(inner) {
+                        Ok(scope) => scope,
+                        Err(_) => return Err(err),
+                    };
+                    let predicate = match r#where.map(|e| self.build_expression(&mut scope, e)) {
+                        Some(Ok(predicate)) => Some(predicate),
+                        Some(Err(_)) => return Err(err),
+                        None => None,
+                    };
+                    // The SELECT expressions are only used by IN, but must always be valid.
+                    let mut exprs = Vec::new();
+                    for (expr, _) in select.into_iter().filter(|(e, _)| !self.is_aggregate(e)) {
+                        match self.build_expression(&mut scope, expr) {
+                            Ok(expr) => exprs.push(expr),
+                            Err(_) => return Err(err),
+                        }
+                    }
+                    Ok(SubqueryPlan::Correlated(source, predicate, exprs))
+                }
+                _ => Err(err),
+            }
+        })();
+        self.subqueries.set(self.subqueries.get() - 1);
+        result
+    }
+
+    /// Builds an aggregation node. All aggregate inputs and GROUP BY expressions are evaluated
     /// in a pre-projection, whose results are fed into an Aggregate node. This node computes the
     /// aggregates for the given groups, passing the group values through directly.
     fn build_aggregation(
@@ -330,33 +1434,30 @@ impl<'a, C: Catalog> Planner<'a, C> {
         scope: &mut Scope,
         source: Node,
         groups: Vec<(ast::Expression, Option<String>)>,
-        aggregations: Vec<(Aggregate, ast::Expression)>,
+        aggregations: Vec<(Aggregate, Vec<ast::Expression>)>,
     ) -> Result<Node> {
         let mut aggregates = Vec::new();
         let mut expressions = Vec::new();
-        for (aggregate, expr) in aggregations {
+        for (aggregate, inputs) in aggregations {
             aggregates.push(aggregate);
-            expressions.push((self.build_expression(scope, expr)?, None));
+            for expr in inputs {
+                expressions.push((self.build_expression(scope, expr)?, None));
+            }
         }
+        let mut group_expressions = Vec::new();
         for (expr, label) in groups {
-            expressions.push((self.build_expression(scope, expr)?, label));
+            group_expressions.push((self.build_expression(scope, expr)?, label));
         }
+        // We pass null values for the aggregates here since we don't want field references to hit
+        // the fields in scope before the aggregation.
         scope.project(
-            &expressions
+            &aggregates
                 .iter()
-                .cloned()
-                .enumerate()
-                .map(|(i, (e, l))| {
-                    if i < aggregates.len() {
-                        // We pass null values here since we don't want field references to hit
-                        // the fields in scope before the aggregation.
-                        (Expression::Constant(Value::Null), None)
-                    } else {
-                        (e, l)
-                    }
-                })
+                .map(|_| (Expression::Constant(Value::Null), None))
+                .chain(group_expressions.iter().cloned())
                 .collect::<Vec<_>>(),
         )?;
+        expressions.extend(group_expressions);
         let node = Node::Aggregation {
             source: Box::new(Node::Projection { source: Box::new(source), expressions }),
             aggregates,
@@ -366,42 +1467,173 @@ impl<'a, C: Catalog> Planner<'a, C> {
 
     /// Extracts aggregate functions from an AST expression tree. This finds the aggregate
     /// function calls, replaces them with ast::Expression::Column(i), maps the aggregate functions
-    /// to aggregates, and returns them along with their argument expressions.
+    /// to aggregates, and returns them along with their input expressions.
     fn extract_aggregates(
         &self,
         exprs: &mut [(ast::Expression, Option<String>)],
-    ) -> Result<Vec<(Aggregate, ast::Expression)>> {
+    ) -> Result<Vec<(Aggregate, Vec<ast::Expression>)>> {
         let mut aggregates = Vec::new();
         for (expr, _) in exprs {
             expr.transform_mut(
                 &mut |mut e| match &mut e {
-                    ast::Expression::Function(f, args) if args.len() == 1 => {
-                        if let Some(aggregate) = self.aggregate_from_name(f) {
-                            aggregates.push((aggregate, args.remove(0)));
-                            Ok(ast::Expression::Column(aggregates.len() - 1))
-                        } else {
-                            Ok(e)
-                        }
+                    ast::Expression::Function(f, args) if self.aggregate_from_name(f).is_some() => {
+                        aggregates.push(self.build_aggregate(f, std::mem::take(args))?);
+                        Ok(ast::Expression::Column(aggregates.len() - 1))
                     }
                     _ => Ok(e),
                 },
                 &mut |e| Ok(e),
             )?;
         }
-        for (_, expr) in &aggregates {
-            if self.is_aggregate(expr) {
+        for (_, inputs) in &aggregates {
+            if inputs.iter().any(|expr| self.is_aggregate(expr)) {
                 return Err(Error::Value("Aggregate functions can't be nested".into()));
             }
         }
         Ok(aggregates)
     }
 
+    /// Builds an aggregate for an aggregate function call, returning it along with its input
+    /// expressions: the aggregated expression followed by any ORDER BY expressions.
+    fn build_aggregate(
+        &self,
+        name: &str,
+        args: Vec<ast::Expression>,
+    ) -> Result<(Aggregate, Vec<ast::Expression>)> {
+        let mut aggregate = self
+            .aggregate_from_name(name)
+            .ok_or_else(|| Error::Internal(format!("Unknown aggregate {}", name)))?;
+        let (args, orders): (Vec<_>, Vec<_>) =
+            args.into_iter().partition(|arg| !matches!(arg, ast::Expression::OrderBy(..)));
+        let expect = if let Aggregate::StringAgg(_) = aggregate { 2 } else { 1 };
+        if args.len() != expect {
+            return Err(Error::Value(format!(
+                "Function {} takes {} argument{}, got {}",
+                name,
+                expect,
+                if expect == 1 { "" } else { "s" },
+                args.len()
+            )));
+        }
+        let mut args = args.into_iter();
+        let arg =
+            args.next().ok_or_else(|| Error::Internal("Missing aggregate argument".into()))?;
+        if let Aggregate::StringAgg(separator) = &mut aggregate {
+            *separator = match args.next().map(|arg| self.evaluate_constant(arg)).transpose()? {
+                Some(Value::String(s)) => s,
+                _ => return Err(Error::Value("STRING_AGG separator must be a string".into())),
+            };
+        }
+        let mut inputs = match arg {
+            ast::Expression::Distinct(arg) => {
+                aggregate = Aggregate::Distinct(Box::new(aggregate));
+                vec![*arg]
+            }
+            arg => vec![arg],
+        };
+        if !orders.is_empty() {
+            let mut directions = Vec::new();
+            for order in orders {
+                if let ast::Expression::OrderBy(expr, order, nulls) = order {
+                    inputs.push(*expr);
+                    directions.push(Self::build_order(order, nulls));
+                }
+            }
+            aggregate = Aggregate::Ordered(Box::new(aggregate), directions);
+        }
+        Ok((aggregate, inputs))
+    }
+
+    /// Builds a window node for the window functions in the given SELECT expressions, replacing
+    /// them with Column references to the window function values. These are appended to the
+    /// source rows, and added to the scope. Window function inputs are evaluated in the given
+    /// scope, and if given an aggregation source scope they may only use grouped fields.
+    fn build_window(
+        &self,
+        scope: &mut Scope,
+        source_scope: Option<&Scope>,
+        source: Node,
+        select: &mut [(ast::Expression, Option<String>)],
+    ) -> Result<Node> {
+        let offset = scope.len();
+        let mut windows = Vec::new();
+        for (expr, _) in select.iter_mut() {
+            expr.transform_mut(
+                &mut |e| match e {
+                    ast::Expression::Window(name, args, partition, order) => {
+                        windows.push((name, args, partition, order));
+                        Ok(ast::Expression::Column(offset + windows.len() - 1))
+                    }
+                    e => Ok(e),
+                },
+                &mut |e| Ok(e),
+            )?;
+        }
+        let mut functions = Vec::new();
+        for (name, args, partition, order) in windows {
+            for expr in args.iter().chain(&partition).chain(order.iter().map(|(e, _, _)| e)) {
+                if self.is_window(expr) {
+                    return Err(Error::Value("Window functions can't be nested".into()));
+                }
+                if let Some(source_scope) = source_scope {
+                    self.check_grouped(scope, source_scope, expr)?;
+                }
+            }
+            let (window, args) = match name.as_str() {
+                "row_number" | "rank" | "dense_rank" if !args.is_empty() => {
+                    return Err(Error::Value(format!(
+                        "Function {} takes 0 arguments, got {}",
+                        name,
+                        args.len()
+                    )))
+                }
+                "row_number" => (Window::RowNumber, args),
+                "rank" => (Window::Rank, args),
+                "dense_rank" => (Window::DenseRank, args),
+                _ if self.aggregate_from_name(&name).is_some() => {
+                    match self.build_aggregate(&name, args)? {
+                        (Aggregate::Distinct(_), _) | (Aggregate::Ordered(..), _) => {
+                            return Err(Error::Value(
+                                "DISTINCT and ORDER BY can't be used in window functions".into(),
+                            ))
+                        }
+                        (aggregate, inputs) => (Window::Aggregate(aggregate), inputs),
+                    }
+                }
+                _ => return Err(Error::Value(format!("Unknown window function {}", name))),
+            };
+            functions.push(WindowFunction {
+                window,
+                args: args
+                    .into_iter()
+                    .map(|e| self.build_expression(scope, e))
+                    .collect::<Result<_>>()?,
+                partition: partition
+                    .into_iter()
+                    .map(|e| self.build_expression(scope, e))
+                    .collect::<Result<_>>()?,
+                order: order
+                    .into_iter()
+                    .map(|(e, o, n)| {
+                        let (direction, nulls) = Self::build_order(o, n);
+                        Ok((self.build_expression(scope, e)?, direction, nulls))
+                    })
+                    .collect::<Result<_>>()?,
+            });
+        }
+        for _ in &functions {
+            scope.add_column(None, None);
+        }
+        Ok(Node::Window { source: Box::new(source), functions })
+    }
+
     /// Extracts group by expressions, and replaces them with column references with the given
     /// offset. These can be either an arbitray expression, a reference to a SELECT column, or the
     /// same expression as a SELECT column. The following are all valid:
     ///
     /// SELECT released / 100 AS century, COUNT(*) FROM movies GROUP BY century
     /// SELECT released / 100, COUNT(*) FROM movies GROUP BY released / 100
+    /// SELECT released / 100 + 1, COUNT(*) FROM movies GROUP BY released / 100
     /// SELECT COUNT(*) FROM movies GROUP BY released / 100
     fn extract_groups(
         &self,
@@ -429,7 +1661,17 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 ));
                 continue;
             }
-            // Otherwise, just use the group expression directly
+            // Otherwise, use the group expression directly, replacing any occurrences of it within
+            // SELECT expressions. Plain fields are instead resolved via the scope.
+            if !matches!(g, ast::Expression::Field(..)) {
+                let column = ast::Expression::Column(offset + groups.len());
+                for (expr, _) in exprs.iter_mut() {
+                    expr.transform_mut(
+                        &mut |e| Ok(if e == g { column.clone() } else { e }),
+                        &mut |e| Ok(e),
+                    )?;
+                }
+            }
             groups.push((g, None))
         }
         // Make sure no group expressions contain Column references, which would be placed here
@@ -442,6 +1684,36 @@ impl<'a, C: Catalog> Planner<'a, C> {
         Ok(groups)
     }
 
+    /// Checks that an expression evaluated after an aggregation only references fields that are
+    /// available after it, i.e. GROUP BY expressions, and not other fields of the source.
+    fn check_grouped(&self, scope: &Scope, source: &Scope, expr: &ast::Expression) -> Result<()> {
+        let mut ungrouped = None;
+        expr.clone().transform(
+            &mut |e| {
+                if let ast::Expression::Field(table, name) = &e {
+                    if ungrouped.is_none()
+                        && scope.resolve(table.as_deref(), name).is_err()
+                        && source.resolve(table.as_deref(), name).is_ok()
+                    {
+                        ungrouped = Some(match table {
+                            Some(table) => format!("{}.{}", table, name),
+                            None => name.clone(),
+                        });
+                    }
+                }
+                Ok(e)
+            },
+            &mut |e| Ok(e),
+        )?;
+        match ungrouped {
+            Some(field) => Err(Error::Value(format!(
+                "Field {} must be used in GROUP BY or an aggregate function",
+                field
+            ))),
+            None => Ok(()),
+        }
+    }
+
     /// Injects hidden expressions into SELECT expressions. This is used for ORDER BY and HAVING, in
     /// order to apply these to fields or aggregates that are not present in the SELECT output, e.g.
     /// to order on a column that is not selected. This is done by replacing the relevant parts of
@@ -452,12 +1724,9 @@ impl<'a, C: Catalog> Planner<'a, C> {
         expr: &mut ast::Expression,
         select: &mut Vec<(ast::Expression, Option<String>)>,
     ) -> Result<usize> {
-        // Replace any identical expressions or label references with column references.
-        for (i, (sexpr, label)) in select.iter().enumerate() {
-            if expr == sexpr {
-                replace(expr, ast::Expression::Column(i));
-                continue;
-            }
+        // Replace any label references or identical expressions with column references. Labels
+        // take precedence over table columns, e.g. for SELECT a AS b, b AS a ... ORDER BY a.
+        for (i, (_, label)) in select.iter().enumerate() {
             if let Some(label) = label {
                 expr.transform_mut(
                     &mut |e| match e {
@@ -470,6 +1739,11 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 )?;
             }
         }
+        for (i, (sexpr, _)) in select.iter().enumerate() {
+            if expr == sexpr {
+                replace(expr, ast::Expression::Column(i));
+            }
+        }
         // Any remaining aggregate functions and field references must be extracted as hidden
         // columns.
         let mut hidden = 0;
@@ -487,7 +1761,7 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     hidden += 1;
                     Ok(ast::Expression::Column(select.len() - 1))
                 }
-                ast::Expression::Field(_, _) => {
+                ast::Expression::Field(_, _) | ast::Expression::Window(..) => {
                     select.push((e, None));
                     hidden += 1;
                     Ok(ast::Expression::Column(select.len() - 1))
@@ -506,6 +1780,7 @@ impl<'a, C: Catalog> Planner<'a, C> {
             "count" => Some(Aggregate::Count),
             "max" => Some(Aggregate::Max),
             "min" => Some(Aggregate::Min),
+            "string_agg" => Some(Aggregate::StringAgg(String::new())),
             "sum" => Some(Aggregate::Sum),
             _ => None,
         }
@@ -519,24 +1794,102 @@ impl<'a, C: Catalog> Planner<'a, C> {
         })
     }
 
+    /// Checks whether a given expression contains a window function.
+    fn is_window(&self, expr: &ast::Expression) -> bool {
+        expr.contains(&|e| matches!(e, ast::Expression::Window(..)))
+    }
+
     /// Builds an expression from an AST expression
     fn build_expression(&self, scope: &mut Scope, expr: ast::Expression) -> Result<Expression> {
         use Expression::*;
         Ok(match expr {
-            ast::Expression::Literal(l) => Constant(match l {
-                ast::Literal::Null => Value::Null,
-                ast::Literal::Boolean(b) => Value::Boolean(b),
-                ast::Literal::Integer(i) => Value::Integer(i),
-                ast::Literal::Float(f) => Value::Float(f),
-                ast::Literal::String(s) => Value::String(s),
-            }),
+            ast::Expression::Literal(l) => Constant(l.into_value()?),
+            ast::Expression::Parameter(i) => {
+                if let Some(view) = self.views.borrow().last() {
+                    return Err(Error::Value(format!("View {} can't contain parameters", view)));
+                }
+                match self.params.get(i - 1) {
+                    Some(value) => Constant(value.clone()),
+                    None => return Err(Error::Value(format!("No value for parameter ${}", i))),
+                }
+            }
             ast::Expression::Column(i) => Field(i, scope.get_label(i)?),
             ast::Expression::Field(table, name) => {
                 Field(scope.resolve(table.as_deref(), &name)?, Some((table, name)))
             }
-            ast::Expression::Function(name, _) => {
-                return Err(Error::Value(format!("Unknown function {}", name,)))
+            ast::Expression::Function(name, args) => match name.as_str() {
+                "current_timestamp" | "now" if args.is_empty() => {
+                    Constant(Value::Timestamp(self.now))
+                }
+                _ => match types::Function::from_name(&name) {
+                    Some(_)
+                        if args.iter().any(|arg| matches!(arg, ast::Expression::OrderBy(..))) =>
+                    {
+                        return Err(Error::Value(
+                            "ORDER BY can only be used in aggregate functions".into(),
+                        ))
+                    }
+                    Some(function) => {
+                        function.check_args(args.len())?;
+                        let args = args
+                            .into_iter()
+                            .map(|arg| self.build_expression(scope, arg))
+                            .collect::<Result<Vec<_>>>()?;
+                        if function == types::Function::Coalesce {
+                            Self::check_result_types("COALESCE arguments", args.iter())?;
+                        }
+                        Function(function, args)
+                    }
+                    None if matches!(name.as_str(), "row_number" | "rank" | "dense_rank") => {
+                        return Err(Error::Value(format!(
+                            "Window function {} requires an OVER clause",
+                            name
+                        )))
+                    }
+                    None => return Err(Error::Value(format!("Unknown function {}", name))),
+                },
+            },
+            ast::Expression::Case(operand, branches, r#else) => {
+                let operand =
+                    operand.map(|o| self.build_expression(scope, *o)).transpose()?.map(Box::new);
+                let branches = branches
+                    .into_iter()
+                    .map(|(condition, result)| {
+                        Ok((
+                            self.build_expression(scope, condition)?,
+                            self.build_expression(scope, result)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let r#else =
+                    r#else.map(|e| self.build_expression(scope, *e)).transpose()?.map(Box::new);
+                Self::check_result_types(
+                    "CASE branches",
+                    branches.iter().map(|(_, result)| result).chain(r#else.as_deref()),
+                )?;
+                Case(operand, branches, r#else)
+            }
+            ast::Expression::Distinct(_) => {
+                return Err(Error::Value("DISTINCT can only be used in aggregate functions".into()))
+            }
+            ast::Expression::OrderBy(..) => {
+                return Err(Error::Value("ORDER BY can only be used in aggregate functions".into()))
+            }
+            ast::Expression::Window(..) => {
+                return Err(Error::Value(
+                    "Window functions can only be used in SELECT and ORDER BY".into(),
+                ))
             }
+            ast::Expression::Cast(expr, datatype) => {
+                Cast(self.build_expression(scope, *expr)?.into(), datatype)
+            }
+            ast::Expression::TryCast(expr, datatype) => {
+                TryCast(self.build_expression(scope, *expr)?.into(), datatype)
+            }
+            ast::Expression::Subquery(select) => match self.build_subquery(scope, *select)? {
+                SubqueryPlan::Uncorrelated(node) => Subquery(node.into()),
+                SubqueryPlan::Correlated(..) => return Err(Self::correlated_error()),
+            },
             ast::Expression::Operation(op) => match op {
                 // Logical operators
                 ast::Operation::And(lhs, rhs) => And(
@@ -550,6 +1903,14 @@ impl<'a, C: Catalog> Planner<'a, C> {
                 ),
 
                 // Comparison operators
+                ast::Operation::Between(expr, low, high) => self.build_expression(
+                    scope,
+                    ast::Operation::And(
+                        Box::new(ast::Operation::GreaterThanOrEqual(expr.clone(), low).into()),
+                        Box::new(ast::Operation::LessThanOrEqual(expr, high).into()),
+                    )
+                    .into(),
+                )?,
                 ast::Operation::Equal(lhs, rhs) => Equal(
                     self.build_expression(scope, *lhs)?.into(),
                     self.build_expression(scope, *rhs)?.into(),
@@ -570,6 +1931,27 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     )
                     .into(),
                 ),
+                ast::Operation::In(lhs, list) => In(
+                    self.build_expression(scope, *lhs)?.into(),
+                    list.into_iter()
+                        .map(|e| self.build_expression(scope, e))
+                        .collect::<Result<_>>()?,
+                ),
+                ast::Operation::Exists(select) => match self.build_subquery(scope, *select)? {
+                    SubqueryPlan::Uncorrelated(node) => Exists(node.into()),
+                    SubqueryPlan::Correlated(..) => return Err(Self::correlated_error()),
+                },
+                ast::Operation::InSubquery(lhs, select) => InSubquery(
+                    self.build_expression(scope, *lhs)?.into(),
+                    match self.build_subquery(scope, *select)? {
+                        SubqueryPlan::Uncorrelated(node) => node.into(),
+                        SubqueryPlan::Correlated(..) => return Err(Self::correlated_error()),
+                    },
+                ),
+                ast::Operation::IsDistinctFrom(lhs, rhs) => IsDistinctFrom(
+                    self.build_expression(scope, *lhs)?.into(),
+                    self.build_expression(scope, *rhs)?.into(),
+                ),
                 ast::Operation::IsNull(expr) => IsNull(self.build_expression(scope, *expr)?.into()),
                 ast::Operation::LessThan(lhs, rhs) => LessThan(
                     self.build_expression(scope, *lhs)?.into(),
@@ -587,9 +1969,15 @@ impl<'a, C: Catalog> Planner<'a, C> {
                     )
                     .into(),
                 ),
-                ast::Operation::Like(lhs, rhs) => Like(
+                ast::Operation::ILike(lhs, rhs, escape) => ILike(
+                    self.build_expression(scope, *lhs)?.into(),
+                    self.build_expression(scope, *rhs)?.into(),
+                    escape,
+                ),
+                ast::Operation::Like(lhs, rhs, escape) => Like(
                     self.build_expression(scope, *lhs)?.into(),
                     self.build_expression(scope, *rhs)?.into(),
+                    escape,
                 ),
                 ast::Operation::NotEqual(lhs, rhs) => Not(Equal(
                     self.build_expression(scope, *lhs)?.into(),
@@ -631,19 +2019,76 @@ impl<'a, C: Catalog> Planner<'a, C> {
         })
     }
 
+    /// Checks that the possible results of an expression (e.g. CASE branches or COALESCE
+    /// arguments) have compatible types, i.e. that they are all numbers or all of the same type,
+    /// ignoring NULLs. Only constant results can be checked when planning, since the types of
+    /// other expressions aren't known until evaluation. The description is used in errors.
+    fn check_result_types<'e>(
+        description: &str,
+        results: impl Iterator<Item = &'e Expression>,
+    ) -> Result<()> {
+        let numeric =
+            |t: &DataType| matches!(t, DataType::Integer | DataType::Float | DataType::Decimal(..));
+        let mut expect: Option<DataType> = None;
+        for result in results {
+            let datatype = match result {
+                Expression::Constant(value) => value.datatype(),
+                Expression::Cast(_, datatype) | Expression::TryCast(_, datatype) => {
+                    Some(datatype.clone())
+                }
+                _ => None,
+            };
+            match (&expect, datatype) {
+                (Some(e), Some(d)) if !(numeric(e) && numeric(&d)) && *e != d => {
+                    return Err(Error::Value(format!(
+                        "{} have incompatible types {} and {}",
+                        description, e, d
+                    )))
+                }
+                (None, Some(d)) => expect = Some(d),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an error for a correlated subquery in an unsupported position.
+    fn correlated_error() -> Error {
+        Error::Value(
+            "Correlated subqueries are only supported in WHERE EXISTS and IN conditions".into(),
+        )
+    }
+
+    /// Returns an error for a correlated subquery using clauses that can't be correlated.
+    fn correlated_unsupported() -> Error {
+        Error::Value(
+            "Correlated subqueries can't use aggregates, GROUP BY, HAVING, LIMIT, or OFFSET".into(),
+        )
+    }
+
     /// Builds and evaluates a constant AST expression.
     fn evaluate_constant(&self, expr: ast::Expression) -> Result<Value> {
         self.build_expression(&mut Scope::constant(), expr)?.evaluate(None)
     }
 }
 
+/// A subquery plan.
+enum SubqueryPlan {
+    /// An uncorrelated subquery, which is planned on its own.
+    Uncorrelated(Node),
+    /// A correlated subquery, given as the source node of its FROM clause, its WHERE predicate,
+    /// and its non-aggregate SELECT expressions. The expressions are evaluated on rows consisting
+    /// of the outer scope's columns followed by the source's columns.
+    Correlated(Node, Option<Expression>, Vec<Expression>),
+}
+
 /// Manages names available to expressions and executors, and maps them onto columns/fields.
 #[derive(Clone, Debug)]
 pub struct Scope {
     // If true, the scope is constant and cannot contain any variables.
     constant: bool,
-    // Currently visible tables, by query name (i.e. alias or actual name).
-    tables: HashMap<String, Table>,
+    // Currently visible tables and CTEs, by query name (i.e. alias or actual name).
+    tables: HashSet<String>,
     // Column labels, if any (qualified by table name when available)
     columns: Vec<(Option<String>, Option<String>)>,
     // Qualified names to column indexes.
@@ -659,7 +2104,7 @@ impl Scope {
     fn new() -> Self {
         Self {
             constant: false,
-            tables: HashMap::new(),
+            tables: HashSet::new(),
             columns: Vec::new(),
             qualified: HashMap::new(),
             unqualified: HashMap::new(),
@@ -702,16 +2147,21 @@ impl Scope {
 
     /// Adds a table to the scope.
     fn add_table(&mut self, label: String, table: Table) -> Result<()> {
+        self.add_relation(label, table.columns.into_iter().map(|c| Some(c.name)).collect())
+    }
+
+    /// Adds a named relation with the given column labels to the scope, e.g. a table or CTE.
+    fn add_relation(&mut self, label: String, columns: Vec<Option<String>>) -> Result<()> {
         if self.constant {
             return Err(Error::Internal("Can't modify constant scope".into()));
         }
-        if self.tables.contains_key(&label) {
+        if self.tables.contains(&label) {
             return Err(Error::Value(format!("Duplicate table name {}", label)));
         }
-        for column in &table.columns {
-            self.add_column(Some(label.clone()), Some(column.name.clone()));
+        for column in columns {
+            self.add_column(Some(label.clone()), column);
         }
-        self.tables.insert(label, table);
+        self.tables.insert(label);
         Ok(())
     }
 
@@ -742,11 +2192,11 @@ impl Scope {
         if self.constant {
             return Err(Error::Internal("Can't modify constant scope".into()));
         }
-        for (label, table) in scope.tables {
-            if self.tables.contains_key(&label) {
+        for label in scope.tables {
+            if self.tables.contains(&label) {
                 return Err(Error::Value(format!("Duplicate table name {}", label)));
             }
-            self.tables.insert(label, table);
+            self.tables.insert(label);
         }
         for (table, label) in scope.columns {
             self.add_column(table, label);
@@ -754,6 +2204,29 @@ impl Scope {
         Ok(())
     }
 
+    /// Creates a scope for a correlated subquery, by appending the subquery's scope to this outer
+    /// scope. As in SQL, names in the subquery shadow names in the outer scope.
+    fn correlate(&self, inner: Scope) -> Result<Self> {
+        if self.constant {
+            return Err(Error::Internal("Can't modify constant scope".into()));
+        }
+        let mut scope = self.clone();
+        for label in inner.tables.iter() {
+            scope.qualified.retain(|(table, _), _| table != label);
+        }
+        for (_, label) in inner.columns.iter() {
+            if let Some(label) = label {
+                scope.unqualified.remove(label);
+                scope.ambiguous.remove(label);
+            }
+        }
+        scope.tables.extend(inner.tables);
+        for (table, label) in inner.columns {
+            scope.add_column(table, label);
+        }
+        Ok(scope)
+    }
+
     /// Resolves a name, optionally qualified by a table name.
     fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize> {
         if self.constant {
@@ -763,7 +2236,7 @@ impl Scope {
             )));
         }
         if let Some(table) = table {
-            if !self.tables.contains_key(table) {
+            if !self.tables.contains(table) {
                 return Err(Error::Value(format!("Unknown table {}", table)));
             }
             self.qualified
@@ -1,10 +1,13 @@
 use super::engine::Transaction;
-use super::parser::format_ident;
-use super::types::{DataType, Value};
+use super::parser::{format_ident, format_value};
+use super::types::{self, DataType, Decimal, Expression, Row, Value, DECIMAL_MAX_PRECISION};
 use crate::error::{Error, Result};
+use crate::storage::kv::encoding::encode_value;
 
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt::{self, Display};
+use std::ops::Bound;
 
 /// The catalog stores schema information
 pub trait Catalog {
@@ -14,8 +17,38 @@ pub trait Catalog {
     fn delete_table(&mut self, table: &str) -> Result<()>;
     /// Reads a table, if it exists
     fn read_table(&self, table: &str) -> Result<Option<Table>>;
+    /// Adds a column to a table, with its default value for existing rows
+    fn add_column(&mut self, table: &str, column: Column) -> Result<()>;
+    /// Drops a column from a table
+    fn drop_column(&mut self, table: &str, column: &str) -> Result<()>;
+    /// Renames a table column
+    fn rename_column(&mut self, table: &str, column: &str, new_name: &str) -> Result<()>;
+    /// Renames a table, updating any references to it
+    fn rename_table(&mut self, table: &str, new_name: &str) -> Result<()>;
     /// Iterates over all tables
     fn scan_tables(&self) -> Result<Tables>;
+    /// Creates a new view
+    fn create_view(&mut self, view: View) -> Result<()>;
+    /// Deletes an existing view, or errors if it does not exist
+    fn delete_view(&mut self, view: &str) -> Result<()>;
+    /// Reads a view, if it exists
+    fn read_view(&self, view: &str) -> Result<Option<View>>;
+    /// Iterates over all views
+    fn scan_views(&self) -> Result<Views>;
+    /// Creates a new user
+    fn create_user(&mut self, user: User) -> Result<()>;
+    /// Deletes an existing user, or errors if it does not exist
+    fn delete_user(&mut self, user: &str) -> Result<()>;
+    /// Reads a user, if it exists
+    fn read_user(&self, user: &str) -> Result<Option<User>>;
+    /// Iterates over all users
+    fn scan_users(&self) -> Result<Users>;
+    /// Updates an existing user, e.g. its privileges, or errors if it does not exist
+    fn update_user(&mut self, user: User) -> Result<()>;
+    /// Reads a table's statistics, if it has been analyzed
+    fn read_table_stats(&self, table: &str) -> Result<Option<TableStats>>;
+    /// Sets a table's statistics, replacing any existing statistics
+    fn set_table_stats(&mut self, table: &str, stats: TableStats) -> Result<()>;
 
     /// Reads a table, and errors if it does not exist
     fn must_read_table(&self, table: &str) -> Result<Table> {
@@ -23,6 +56,16 @@ pub trait Catalog {
             .ok_or_else(|| Error::Value(format!("Table {} does not exist", table)))
     }
 
+    /// Reads a view, and errors if it does not exist
+    fn must_read_view(&self, view: &str) -> Result<View> {
+        self.read_view(view)?.ok_or_else(|| Error::Value(format!("View {} does not exist", view)))
+    }
+
+    /// Reads a user, and errors if it does not exist
+    fn must_read_user(&self, user: &str) -> Result<User> {
+        self.read_user(user)?.ok_or_else(|| Error::Value(format!("User {} does not exist", user)))
+    }
+
     /// Returns all references to a table, as table,column pairs.
     fn table_references(&self, table: &str, with_self: bool) -> Result<Vec<(String, Vec<String>)>> {
         Ok(self
@@ -46,20 +89,122 @@ pub trait Catalog {
 /// A table scan iterator
 pub type Tables = Box<dyn DoubleEndedIterator<Item = Table> + Send>;
 
+/// A view scan iterator
+pub type Views = Box<dyn DoubleEndedIterator<Item = View> + Send>;
+
+/// A user scan iterator
+pub type Users = Box<dyn DoubleEndedIterator<Item = User> + Send>;
+
 /// A table schema
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    /// The schema version, incremented when columns are added or dropped. Rows are stored along
+    /// with the schema version they were written under, such that schema changes don't have to
+    /// rewrite existing rows.
+    pub version: u64,
+    /// The row layouts of previous schema versions, indexed by version. For each stored column of
+    /// a row written under that version, this gives the index of the corresponding current column,
+    /// or None if the column has since been dropped.
+    pub layouts: Vec<Vec<Option<usize>>>,
 }
 
 impl Table {
     /// Creates a new table schema
     pub fn new(name: String, columns: Vec<Column>) -> Result<Self> {
-        let table = Self { name, columns };
+        let table = Self { name, columns, version: 0, layouts: Vec::new() };
         Ok(table)
     }
 
+    /// Adds a column, creating a new schema version. Existing rows take on the column's default
+    /// value, or NULL if it has none.
+    pub fn add_column(&mut self, column: Column) -> Result<()> {
+        if self.columns.iter().any(|c| c.name == column.name) {
+            return Err(Error::Value(format!(
+                "Column {} already exists in table {}",
+                column.name, self.name
+            )));
+        }
+        if column.primary_key {
+            return Err(Error::Value(format!(
+                "Can't add primary key column {} to table {}",
+                column.name, self.name
+            )));
+        }
+        self.layouts.push((0..self.columns.len()).map(Some).collect());
+        self.version += 1;
+        self.columns.push(column);
+        Ok(())
+    }
+
+    /// Drops a column, creating a new schema version. The column's values are ignored when
+    /// decoding rows written under previous versions.
+    pub fn drop_column(&mut self, name: &str) -> Result<Column> {
+        let index = self.get_column_index(name)?;
+        if self.columns[index].primary_key {
+            return Err(Error::Value(format!(
+                "Can't drop primary key column {} of table {}",
+                name, self.name
+            )));
+        }
+        if let Some(c) = self.columns.iter().find(|c| {
+            c.generated.as_ref().map_or(false, |e| {
+                e.contains(&|e| matches!(e, Expression::Field(i, _) if *i == index))
+            })
+        }) {
+            return Err(Error::Value(format!(
+                "Can't drop column {} referenced by generated column {}",
+                name, c.name
+            )));
+        }
+        for column in self.columns.iter_mut() {
+            if let Some(expr) = column.generated.take() {
+                column.generated = Some(expr.transform(
+                    &|e| match e {
+                        Expression::Field(i, label) if i > index => {
+                            Ok(Expression::Field(i - 1, label))
+                        }
+                        e => Ok(e),
+                    },
+                    &Ok,
+                )?);
+            }
+        }
+        self.layouts.push((0..self.columns.len()).map(Some).collect());
+        self.version += 1;
+        for layout in self.layouts.iter_mut() {
+            for i in layout.iter_mut() {
+                *i = match *i {
+                    Some(i) if i == index => None,
+                    Some(i) if i > index => Some(i - 1),
+                    i => i,
+                };
+            }
+        }
+        Ok(self.columns.remove(index))
+    }
+
+    /// Upgrades a row written under the given schema version to the current schema, by mapping
+    /// its stored values onto the current columns. Values of dropped columns are discarded, and
+    /// columns added since then take on their default value.
+    pub fn upgrade_row(&self, version: u64, row: Row) -> Result<Row> {
+        if version == self.version {
+            return Ok(row);
+        }
+        let layout = self.layouts.get(version as usize).ok_or_else(|| {
+            Error::Internal(format!("Unknown schema version {} for table {}", version, self.name))
+        })?;
+        let mut upgraded: Row =
+            self.columns.iter().map(|c| c.default.clone().unwrap_or(Value::Null)).collect();
+        for (value, index) in row.into_iter().zip(layout) {
+            if let Some(index) = index {
+                upgraded[*index] = value;
+            }
+        }
+        Ok(upgraded)
+    }
+
     /// Fetches a column by name
     pub fn get_column(&self, name: &str) -> Result<&Column> {
         self.columns.iter().find(|c| c.name == name).ok_or_else(|| {
@@ -74,24 +219,65 @@ impl Table {
         })
     }
 
-    /// Returns the primary key column of the table
+    /// Returns the primary key column of the table, erroring for composite primary keys
     pub fn get_primary_key(&self) -> Result<&Column> {
-        self.columns
-            .iter()
-            .find(|c| c.primary_key)
-            .ok_or_else(|| Error::Value(format!("Primary key not found in table {}", self.name)))
+        match self.get_primary_key_indexes().as_slice() {
+            [index] => Ok(&self.columns[*index]),
+            [] => Err(Error::Value(format!("Primary key not found in table {}", self.name))),
+            _ => Err(Error::Value(format!("Table {} has a composite primary key", self.name))),
+        }
+    }
+
+    /// Checks whether the index of the given column covers the given columns (all columns if
+    /// None), i.e. whether their values can be read from the index entries alone. Index entries
+    /// map column values to primary keys, so they cover the column and a single-column primary key.
+    pub fn index_covers(&self, column: &str, columns: Option<&[String]>) -> bool {
+        let pk = self.get_primary_key().ok().map(|c| c.name.as_str());
+        let covered = |name: &str| name == column || Some(name) == pk;
+        match columns {
+            Some(columns) => columns.iter().all(|c| covered(c)),
+            None => self.columns.iter().all(|c| covered(&c.name)),
+        }
+    }
+
+    /// Returns the indexes of the primary key columns, in key order
+    pub fn get_primary_key_indexes(&self) -> Vec<usize> {
+        self.columns.iter().enumerate().filter(|(_, c)| c.primary_key).map(|(i, _)| i).collect()
     }
 
-    /// Returns the primary key value of a row
+    /// Returns the primary key value of a row, see make_key().
     pub fn get_row_key(&self, row: &[Value]) -> Result<Value> {
-        row.get(
-            self.columns
-                .iter()
-                .position(|c| c.primary_key)
-                .ok_or_else(|| Error::Value("Primary key not found".into()))?,
-        )
-        .cloned()
-        .ok_or_else(|| Error::Value("Primary key value not found for row".into()))
+        let values = self
+            .get_primary_key_indexes()
+            .into_iter()
+            .map(|i| row.get(i).cloned())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::Value("Primary key value not found for row".into()))?;
+        self.make_key(values)
+    }
+
+    /// Builds a primary key value from the values of the primary key columns. For composite
+    /// primary keys, this is a binary value concatenating the order-preserving key encodings of
+    /// the values, such that keys are ordered by the first column, then the second, and so on.
+    pub fn make_key(&self, mut values: Vec<Value>) -> Result<Value> {
+        match values.len() {
+            0 => Err(Error::Value("Primary key not found".into())),
+            1 => Ok(values.remove(0)),
+            _ => Ok(Value::Bytes(values.iter().flat_map(encode_value).collect())),
+        }
+    }
+
+    /// Formats the primary key of a row for display, e.g. in error messages
+    pub fn format_row_key(&self, row: &[Value]) -> String {
+        let values = self
+            .get_primary_key_indexes()
+            .into_iter()
+            .map(|i| row.get(i).unwrap_or(&Value::Null).to_string())
+            .collect::<Vec<_>>();
+        match values.as_slice() {
+            [value] => value.clone(),
+            values => format!("({})", values.join(", ")),
+        }
     }
 
     /// Validates the table schema
@@ -99,25 +285,46 @@ impl Table {
         if self.columns.is_empty() {
             return Err(Error::Value(format!("Table {} has no columns", self.name)));
         }
-        match self.columns.iter().filter(|c| c.primary_key).count() {
-            1 => {}
-            0 => return Err(Error::Value(format!("No primary key in table {}", self.name))),
-            _ => return Err(Error::Value(format!("Multiple primary keys in table {}", self.name))),
-        };
+        if !self.columns.iter().any(|c| c.primary_key) {
+            return Err(Error::Value(format!("No primary key in table {}", self.name)));
+        }
         for column in &self.columns {
             column.validate(self, txn)?;
         }
         Ok(())
     }
 
-    /// Validates a row
-    pub fn validate_row(&self, row: &[Value], txn: &mut dyn Transaction) -> Result<()> {
+    /// Computes the values of generated columns from the rest of the row.
+    pub fn generate_row(&self, row: &mut Row) -> Result<()> {
+        for (i, column) in self.columns.iter().enumerate() {
+            if let Some(expr) = &column.generated {
+                row[i] = column.conform_value(expr.evaluate(Some(row))?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Conforms a row's values to the column datatypes, see Column::conform_value().
+    pub fn conform_row(&self, row: Row) -> Result<Row> {
+        if row.len() != self.columns.len() {
+            return Err(Error::Value(format!("Invalid row size for table {}", self.name)));
+        }
+        self.columns.iter().zip(row).map(|(column, value)| column.conform_value(value)).collect()
+    }
+
+    /// Validates a row, with string and binary values limited to max_value_size bytes
+    pub fn validate_row(
+        &self,
+        row: &[Value],
+        max_value_size: usize,
+        txn: &mut dyn Transaction,
+    ) -> Result<()> {
         if row.len() != self.columns.len() {
             return Err(Error::Value(format!("Invalid row size for table {}", self.name)));
         }
         let pk = self.get_row_key(row)?;
         for (column, value) in self.columns.iter().zip(row.iter()) {
-            column.validate_value(self, &pk, value, txn)?;
+            column.validate_value(self, &pk, value, max_value_size, txn)?;
         }
         Ok(())
     }
@@ -125,12 +332,218 @@ impl Table {
 
 impl Display for Table {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "CREATE TABLE {} (\n{}\n)",
-            format_ident(&self.name),
-            self.columns.iter().map(|c| format!("  {}", c)).collect::<Vec<String>>().join(",\n")
-        )
+        // Composite primary keys are given as a separate PRIMARY KEY clause.
+        let key = self.get_primary_key_indexes();
+        let mut lines = self
+            .columns
+            .iter()
+            .map(|c| match key.len() {
+                1 => format!("  {}", c),
+                _ => format!("  {}", Column { primary_key: false, ..c.clone() }),
+            })
+            .collect::<Vec<String>>();
+        if key.len() > 1 {
+            lines.push(format!(
+                "  PRIMARY KEY ({})",
+                key.iter()
+                    .map(|i| format_ident(&self.columns[*i].name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        write!(f, "CREATE TABLE {} (\n{}\n)", format_ident(&self.name), lines.join(",\n"))
+    }
+}
+
+/// A view, i.e. a named query which is expanded into a subquery when referenced. The query is
+/// stored as SQL text, and is parsed and planned on each reference.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct View {
+    pub name: String,
+    pub query: String,
+}
+
+impl Display for View {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CREATE VIEW {} AS {}", format_ident(&self.name), self.query)
+    }
+}
+
+/// A database user, with privileges on individual tables. Users are not authenticated, they are
+/// only used to restrict what a session can do, see Session::set_user(). Only the built-in
+/// superuser requires authentication, see server::Request::Authenticate.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct User {
+    pub name: String,
+    /// Superusers have all privileges, and are the only users that can change the schema (e.g.
+    /// CREATE TABLE) or manage users and their privileges.
+    pub superuser: bool,
+    /// The privileges granted on tables, keyed by table name.
+    pub privileges: BTreeMap<String, BTreeSet<Privilege>>,
+}
+
+impl User {
+    /// Creates a new user without any privileges
+    pub fn new(name: String, superuser: bool) -> Self {
+        Self { name, superuser, privileges: BTreeMap::new() }
+    }
+
+    /// Checks whether the user has the given privilege on a table, which superusers always have
+    pub fn has_privilege(&self, table: &str, privilege: Privilege) -> bool {
+        self.superuser || matches!(self.privileges.get(table), Some(p) if p.contains(&privilege))
+    }
+
+    /// Errors with Error::Unauthorized unless the user has the given privilege on a table
+    pub fn authorize(&self, table: &str, privilege: Privilege) -> Result<()> {
+        if !self.has_privilege(table, privilege) {
+            return Err(Error::Unauthorized(format!(
+                "User {} does not have {} privilege on table {}",
+                self.name, privilege, table
+            )));
+        }
+        Ok(())
+    }
+
+    /// Errors with Error::Unauthorized unless the user is a superuser, for the given operation
+    pub fn authorize_superuser(&self, operation: &str) -> Result<()> {
+        if !self.superuser {
+            return Err(Error::Unauthorized(format!(
+                "User {} must be a superuser to {}",
+                self.name, operation
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A table privilege, which can be granted to users
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Privilege {
+    /// All privileges, i.e. GRANT ALL
+    pub const ALL: [Self; 4] = [Self::Select, Self::Insert, Self::Update, Self::Delete];
+}
+
+impl Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Select => "SELECT",
+            Self::Insert => "INSERT",
+            Self::Update => "UPDATE",
+            Self::Delete => "DELETE",
+        })
+    }
+}
+
+/// A read-only system table in the system schema, e.g. system.tables, which describes the
+/// catalog. Its rows are generated from the catalog when it is scanned.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum SystemTable {
+    /// Tables, with their CREATE TABLE statement.
+    Tables,
+    /// Table columns, with their datatype and constraints.
+    Columns,
+    /// Secondary indexes, by table and column.
+    Indexes,
+}
+
+impl SystemTable {
+    /// Looks up a system table by name, without the system schema, or errors if it doesn't exist.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "tables" => Ok(Self::Tables),
+            "columns" => Ok(Self::Columns),
+            "indexes" => Ok(Self::Indexes),
+            name => Err(Error::Value(format!("System table system.{} does not exist", name))),
+        }
+    }
+
+    /// Returns the table name, without the system schema.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Tables => "tables",
+            Self::Columns => "columns",
+            Self::Indexes => "indexes",
+        }
+    }
+
+    /// Returns the table's column names and datatypes.
+    pub fn columns(&self) -> Vec<(&'static str, DataType)> {
+        match self {
+            Self::Tables => vec![("name", DataType::String), ("sql", DataType::String)],
+            Self::Columns => vec![
+                ("table_name", DataType::String),
+                ("column_name", DataType::String),
+                ("ordinal_position", DataType::Integer),
+                ("data_type", DataType::String),
+                ("is_nullable", DataType::Boolean),
+                ("column_default", DataType::String),
+                ("generation_expression", DataType::String),
+                ("is_primary_key", DataType::Boolean),
+                ("is_unique", DataType::Boolean),
+                ("referenced_table", DataType::String),
+            ],
+            Self::Indexes => vec![
+                ("table_name", DataType::String),
+                ("column_name", DataType::String),
+                ("is_unique", DataType::Boolean),
+            ],
+        }
+    }
+
+    /// Generates the table's rows from the catalog, ordered by table name and column position.
+    pub fn scan<C: Catalog + ?Sized>(&self, catalog: &C) -> Result<Vec<Row>> {
+        let string = |s: &str| Value::String(s.to_string());
+        let mut rows = Vec::new();
+        for table in catalog.scan_tables()? {
+            match self {
+                Self::Tables => rows.push(vec![string(&table.name), string(&table.to_string())]),
+                Self::Columns => {
+                    for (i, column) in table.columns.iter().enumerate() {
+                        rows.push(vec![
+                            string(&table.name),
+                            string(&column.name),
+                            Value::Integer(i as i64 + 1),
+                            string(&column.datatype.to_string()),
+                            Value::Boolean(column.nullable),
+                            column
+                                .default
+                                .as_ref()
+                                .map_or(Value::Null, |v| string(&format_value(v))),
+                            column
+                                .generated
+                                .as_ref()
+                                .map_or(Value::Null, |e| string(&e.to_string())),
+                            Value::Boolean(column.primary_key),
+                            Value::Boolean(column.unique),
+                            column.references.as_deref().map_or(Value::Null, string),
+                        ])
+                    }
+                }
+                Self::Indexes => {
+                    for column in table.columns.iter().filter(|c| c.index) {
+                        rows.push(vec![
+                            string(&table.name),
+                            string(&column.name),
+                            Value::Boolean(column.unique),
+                        ])
+                    }
+                }
+            }
+        }
+        Ok(rows)
+    }
+}
+
+impl Display for SystemTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "system.{}", self.name())
     }
 }
 
@@ -147,6 +560,9 @@ pub struct Column {
     pub nullable: bool,
     /// The default value of the column
     pub default: Option<Value>,
+    /// The expression of a stored generated column, evaluated on the rest of the row whenever the
+    /// row is written. It may only reference non-generated columns of the table.
+    pub generated: Option<Expression>,
     /// Whether the column should only take unique values
     pub unique: bool,
     /// The table which is referenced by this foreign key
@@ -162,14 +578,30 @@ impl Column {
         if self.primary_key && self.nullable {
             return Err(Error::Value(format!("Primary key {} cannot be nullable", self.name)));
         }
-        if self.primary_key && !self.unique {
+        if self.primary_key && !self.unique && table.get_primary_key_indexes().len() == 1 {
             return Err(Error::Value(format!("Primary key {} must be unique", self.name)));
         }
 
+        // Validate datatype
+        if let DataType::Decimal(precision, scale) = self.datatype {
+            if precision < 1 || precision > DECIMAL_MAX_PRECISION {
+                return Err(Error::Value(format!(
+                    "Precision {} for column {} must be between 1 and {}",
+                    precision, self.name, DECIMAL_MAX_PRECISION
+                )));
+            }
+            if scale > precision {
+                return Err(Error::Value(format!(
+                    "Scale {} for column {} can't exceed precision {}",
+                    scale, self.name, precision
+                )));
+            }
+        }
+
         // Validate default value
         if let Some(default) = &self.default {
             if let Some(datatype) = default.datatype() {
-                if datatype != self.datatype {
+                if !self.accepts(&datatype) {
                     return Err(Error::Value(format!(
                         "Default value for column {} has datatype {}, must be {}",
                         self.name, datatype, self.datatype
@@ -181,7 +613,7 @@ impl Column {
                     self.name
                 )));
             }
-        } else if self.nullable {
+        } else if self.nullable && self.generated.is_none() {
             return Err(Error::Value(format!(
                 "Nullable column {} must have a default value",
                 self.name
@@ -200,6 +632,12 @@ impl Column {
                     reference, self.name
                 )));
             };
+            if target.get_primary_key_indexes().len() > 1 {
+                return Err(Error::Value(format!(
+                    "Can't reference table {} with composite primary key from column {}",
+                    target.name, self.name
+                )));
+            }
             if self.datatype != target.get_primary_key()?.datatype {
                 return Err(Error::Value(format!(
                     "Can't reference {} primary key of table {} from {} column {}",
@@ -214,19 +652,63 @@ impl Column {
         Ok(())
     }
 
+    /// Returns true if the column can store values of the given datatype. DECIMAL columns can
+    /// store decimals with the same scale and at most the same precision.
+    fn accepts(&self, datatype: &DataType) -> bool {
+        match (&self.datatype, datatype) {
+            (DataType::Decimal(precision, scale), DataType::Decimal(p, s)) => {
+                s == scale && p <= precision
+            }
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    /// Conforms a value to the column datatype, by casting numbers to DECIMAL columns, i.e.
+    /// rounding them to the column scale and erroring if they exceed its precision. Other values
+    /// are returned as-is, and must be checked with validate_value().
+    pub fn conform_value(&self, value: Value) -> Result<Value> {
+        match (&self.datatype, value) {
+            (DataType::Decimal(..), v @ Value::Integer(_))
+            | (DataType::Decimal(..), v @ Value::Float(_))
+            | (DataType::Decimal(..), v @ Value::Decimal(_)) => v.cast(&self.datatype),
+            (_, v) => Ok(v),
+        }
+    }
+
+    /// Converts a lookup value to the column's key encoding, i.e. numbers to DECIMAL for DECIMAL
+    /// columns, without rounding or range checks, and integers and floats to each other if they
+    /// convert exactly. Values that can't be converted are returned as-is, and will simply not
+    /// match any keys, since they don't compare equal to any value of the column's datatype.
+    pub fn lookup_value(&self, value: Value) -> Value {
+        match (&self.datatype, value) {
+            (DataType::Integer, Value::Float(f)) => {
+                types::float_to_integer(f).map(Value::Integer).unwrap_or(Value::Float(f))
+            }
+            (DataType::Float, Value::Integer(i)) => {
+                types::integer_to_float(i).map(Value::Float).unwrap_or(Value::Integer(i))
+            }
+            (DataType::Decimal(..), Value::Integer(i)) => Value::Decimal(Decimal::from(i)),
+            (DataType::Decimal(..), Value::Float(f)) => {
+                Decimal::from_f64(f).map(Value::Decimal).unwrap_or(Value::Float(f))
+            }
+            (_, v) => v,
+        }
+    }
+
     /// Validates a column value
     pub fn validate_value(
         &self,
         table: &Table,
         pk: &Value,
         value: &Value,
+        max_value_size: usize,
         txn: &mut dyn Transaction,
     ) -> Result<()> {
         // Validate datatype
         match value.datatype() {
             None if self.nullable => Ok(()),
             None => Err(Error::Value(format!("NULL value not allowed for column {}", self.name))),
-            Some(ref datatype) if datatype != &self.datatype => Err(Error::Value(format!(
+            Some(ref datatype) if !self.accepts(datatype) => Err(Error::Value(format!(
                 "Invalid datatype {} for {} column {}",
                 datatype, self.datatype, self.name
             ))),
@@ -235,9 +717,13 @@ impl Column {
 
         // Validate value
         match value {
-            Value::String(s) if s.len() > 1024 => {
-                Err(Error::Value("Strings cannot be more than 1024 bytes".into()))
+            Value::String(s) if s.len() > max_value_size => {
+                Err(Error::Value(format!("Strings cannot be more than {} bytes", max_value_size)))
             }
+            Value::Bytes(b) if b.len() > max_value_size => Err(Error::Value(format!(
+                "Binary values cannot be more than {} bytes",
+                max_value_size
+            ))),
             _ => Ok(()),
         }?;
 
@@ -248,8 +734,8 @@ impl Column {
                 Value::Float(f) if f.is_nan() => Ok(()),
                 v if target == &table.name && v == pk => Ok(()),
                 v if txn.read(target, v)?.is_none() => Err(Error::Value(format!(
-                    "Referenced primary key {} in table {} does not exist",
-                    v, target,
+                    "Referenced primary key {} in table {} does not exist for table {} column {}",
+                    v, target, table.name, self.name,
                 ))),
                 _ => Ok(()),
             }?;
@@ -286,13 +772,16 @@ impl Display for Column {
             sql += " NOT NULL";
         }
         if let Some(default) = &self.default {
-            sql += &format!(" DEFAULT {}", default);
+            sql += &format!(" DEFAULT {}", format_value(default));
+        }
+        if let Some(generated) = &self.generated {
+            sql += &format!(" GENERATED ALWAYS AS ({}) STORED", generated);
         }
         if self.unique && !self.primary_key {
             sql += " UNIQUE";
         }
         if let Some(reference) = &self.references {
-            sql += &format!(" REFERENCES {}", reference);
+            sql += &format!(" REFERENCES {}", format_ident(reference));
         }
         if self.index {
             sql += " INDEX";
@@ -300,3 +789,196 @@ impl Display for Column {
         write!(f, "{}", sql)
     }
 }
+
+/// Table statistics, collected by ANALYZE and used by the optimizer to estimate the number of rows
+/// matching a predicate. They are not updated by writes, and may be stale.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TableStats {
+    /// The number of rows in the table
+    pub rows: u64,
+    /// Column statistics, in table column order
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Column statistics
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ColumnStats {
+    /// The number of distinct non-NULL values
+    pub distinct: u64,
+    /// The number of NULL values
+    pub nulls: u64,
+    /// The smallest non-NULL value, or NULL if there are none
+    pub min: Value,
+    /// The largest non-NULL value, or NULL if there are none
+    pub max: Value,
+}
+
+impl TableStats {
+    /// The selectivity of predicates that can't be estimated from the statistics.
+    pub const DEFAULT_SELECTIVITY: f64 = 1.0 / 3.0;
+
+    /// Collects statistics for a table's rows.
+    pub fn collect(columns: usize, rows: impl Iterator<Item = Result<Row>>) -> Result<Self> {
+        let mut count = 0;
+        let mut distinct: Vec<HashSet<Value>> = vec![HashSet::new(); columns];
+        let mut stats =
+            vec![
+                ColumnStats { distinct: 0, nulls: 0, min: Value::Null, max: Value::Null };
+                columns
+            ];
+        for row in rows {
+            count += 1;
+            for ((value, stats), distinct) in row?.into_iter().zip(&mut stats).zip(&mut distinct) {
+                if value == Value::Null {
+                    stats.nulls += 1;
+                    continue;
+                }
+                if stats.min == Value::Null || value < stats.min {
+                    stats.min = value.clone();
+                }
+                if stats.max == Value::Null || value > stats.max {
+                    stats.max = value.clone();
+                }
+                distinct.insert(value);
+            }
+        }
+        for (stats, distinct) in stats.iter_mut().zip(distinct) {
+            stats.distinct = distinct.len() as u64;
+        }
+        Ok(Self { rows: count, columns: stats })
+    }
+
+    /// Estimates the number of rows matching the given filter, where fields refer to table
+    /// columns.
+    pub fn estimate(&self, filter: Option<&Expression>) -> f64 {
+        self.rows as f64 * filter.map_or(1.0, |f| self.selectivity(f))
+    }
+
+    /// Estimates the number of rows with the given column values, as looked up via a primary key
+    /// or index.
+    pub fn estimate_lookup(&self, column: usize, values: &[Value]) -> f64 {
+        let selectivity: f64 = values.iter().map(|v| self.selectivity_equal(column, v)).sum();
+        self.rows as f64 * selectivity.min(1.0)
+    }
+
+    /// Estimates the number of rows with column values within the given range, as scanned via an
+    /// index. NULL values are never within a range.
+    pub fn estimate_range(&self, column: usize, range: &(Bound<Value>, Bound<Value>)) -> f64 {
+        let non_null = 1.0 - self.selectivity_equal(column, &Value::Null);
+        let bound = |bound: &Bound<Value>, greater: bool| match bound {
+            Bound::Included(v) => {
+                self.selectivity_range(column, v, greater) + self.selectivity_equal(column, v)
+            }
+            Bound::Excluded(v) => self.selectivity_range(column, v, greater),
+            Bound::Unbounded => non_null,
+        };
+        let selectivity = bound(&range.0, true) + bound(&range.1, false) - non_null;
+        self.rows as f64 * selectivity.max(0.0).min(non_null)
+    }
+
+    /// Estimates the fraction of rows matching a predicate, assuming columns are independent and
+    /// values are uniformly distributed between the column's minimum and maximum values.
+    fn selectivity(&self, expr: &Expression) -> f64 {
+        use Expression::*;
+        match expr {
+            Constant(Value::Boolean(true)) => 1.0,
+            Constant(Value::Boolean(false)) | Constant(Value::Null) => 0.0,
+            And(lhs, rhs) => self.selectivity(lhs) * self.selectivity(rhs),
+            Or(lhs, rhs) => {
+                let (lhs, rhs) = (self.selectivity(lhs), self.selectivity(rhs));
+                lhs + rhs - lhs * rhs
+            }
+            Not(expr) => 1.0 - self.selectivity(expr),
+            Equal(lhs, rhs) => match (&**lhs, &**rhs) {
+                (Field(i, _), Constant(v)) | (Constant(v), Field(i, _)) => {
+                    if v == &Value::Null {
+                        0.0
+                    } else {
+                        self.selectivity_equal(*i, v)
+                    }
+                }
+                _ => Self::DEFAULT_SELECTIVITY,
+            },
+            In(lhs, list) => match &**lhs {
+                Field(i, _) => list
+                    .iter()
+                    .map(|e| match e {
+                        Constant(Value::Null) => 0.0,
+                        Constant(v) => self.selectivity_equal(*i, v),
+                        _ => Self::DEFAULT_SELECTIVITY,
+                    })
+                    .sum::<f64>()
+                    .min(1.0),
+                _ => Self::DEFAULT_SELECTIVITY,
+            },
+            IsNull(expr) => match &**expr {
+                Field(i, _) => self.selectivity_equal(*i, &Value::Null),
+                _ => Self::DEFAULT_SELECTIVITY,
+            },
+            GreaterThan(lhs, rhs) => match (&**lhs, &**rhs) {
+                (Field(i, _), Constant(v)) => self.selectivity_range(*i, v, true),
+                (Constant(v), Field(i, _)) => self.selectivity_range(*i, v, false),
+                _ => Self::DEFAULT_SELECTIVITY,
+            },
+            LessThan(lhs, rhs) => match (&**lhs, &**rhs) {
+                (Field(i, _), Constant(v)) => self.selectivity_range(*i, v, false),
+                (Constant(v), Field(i, _)) => self.selectivity_range(*i, v, true),
+                _ => Self::DEFAULT_SELECTIVITY,
+            },
+            _ => Self::DEFAULT_SELECTIVITY,
+        }
+    }
+
+    /// Estimates the fraction of rows where a column equals the given value, where NULL matches
+    /// NULL values.
+    fn selectivity_equal(&self, column: usize, value: &Value) -> f64 {
+        let stats = match self.columns.get(column) {
+            Some(stats) if self.rows > 0 => stats,
+            Some(_) => return 0.0,
+            None => return Self::DEFAULT_SELECTIVITY,
+        };
+        if value == &Value::Null {
+            return stats.nulls as f64 / self.rows as f64;
+        }
+        if stats.distinct == 0 || value < &stats.min || value > &stats.max {
+            return 0.0;
+        }
+        (self.rows - stats.nulls) as f64 / stats.distinct as f64 / self.rows as f64
+    }
+
+    /// Estimates the fraction of rows where a column is greater than (or less than) the given
+    /// value. Numeric values are interpolated between the column's minimum and maximum values.
+    fn selectivity_range(&self, column: usize, value: &Value, greater: bool) -> f64 {
+        let stats = match self.columns.get(column) {
+            Some(stats) if self.rows > 0 => stats,
+            Some(_) => return 0.0,
+            None => return Self::DEFAULT_SELECTIVITY,
+        };
+        if value == &Value::Null || stats.distinct == 0 {
+            return 0.0;
+        }
+        let fraction = match (greater, Self::as_f64(&stats.min), Self::as_f64(&stats.max)) {
+            (true, _, _) if value >= &stats.max => 0.0,
+            (false, _, _) if value <= &stats.min => 0.0,
+            (true, _, _) if value < &stats.min => 1.0,
+            (false, _, _) if value > &stats.max => 1.0,
+            (greater, Some(min), Some(max)) if max > min => match Self::as_f64(value) {
+                Some(v) if greater => (max - v) / (max - min),
+                Some(v) => (v - min) / (max - min),
+                None => Self::DEFAULT_SELECTIVITY,
+            },
+            _ => Self::DEFAULT_SELECTIVITY,
+        };
+        fraction * (self.rows - stats.nulls) as f64 / self.rows as f64
+    }
+
+    /// Converts a numeric value to a float, for interpolation.
+    fn as_f64(value: &Value) -> Option<f64> {
+        match value {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::Decimal(d) => Some(d.to_f64()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Date and time types. These are stored as plain integers relative to the Unix epoch, and use
+//! the proleptic Gregorian calendar without time zones (all timestamps are UTC). Conversions
+//! between days and civil dates use the algorithms from
+//! http://howardhinnant.github.io/date_algorithms.html
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// A calendar date, as the number of days since 1970-01-01.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Date(pub i64);
+
+/// A time of day, as the number of microseconds since midnight.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Time(pub i64);
+
+/// A point in time, as the number of microseconds since 1970-01-01 00:00:00 UTC.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Timestamp(pub i64);
+
+impl Date {
+    /// Creates a date from a year, month, and day, or errors if the date is invalid.
+    pub fn from_ymd(year: i64, month: i64, day: i64) -> Result<Self> {
+        if !(1..=9999).contains(&year) {
+            return Err(Error::Value(format!("Year {} out of range", year)));
+        }
+        if !(1..=12).contains(&month) {
+            return Err(Error::Value(format!("Month {} out of range", month)));
+        }
+        if day < 1 || day > days_in_month(year, month) {
+            return Err(Error::Value(format!("Day {} out of range", day)));
+        }
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        Ok(Self(era * 146_097 + doe - 719_468))
+    }
+
+    /// Returns the year, month, and day of the date.
+    pub fn ymd(self) -> (i64, i64, i64) {
+        let z = self.0 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = Error;
+
+    /// Parses an ISO-8601 date, i.e. YYYY-MM-DD.
+    fn from_str(s: &str) -> Result<Self> {
+        let err = || Error::Value(format!("Invalid date '{}'", s));
+        let parts: Vec<&str> = s.split('-').collect();
+        match parts.as_slice() {
+            [y, m, d] if y.len() == 4 && m.len() == 2 && d.len() == 2 => Self::from_ymd(
+                parse_digits(y).ok_or_else(err)?,
+                parse_digits(m).ok_or_else(err)?,
+                parse_digits(d).ok_or_else(err)?,
+            )
+            .map_err(|e| Error::Value(format!("Invalid date '{}': {}", s, e))),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl fmt::Debug for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl FromStr for Time {
+    type Err = Error;
+
+    /// Parses an ISO-8601 time of day, i.e. HH:MM[:SS[.ffffff]].
+    fn from_str(s: &str) -> Result<Self> {
+        let err = || Error::Value(format!("Invalid time '{}'", s));
+        let (hms, fraction) = match s.find('.') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let parts: Vec<&str> = hms.split(':').collect();
+        let (hour, minute, second) = match parts.as_slice() {
+            [h, m] if h.len() == 2 && m.len() == 2 && fraction.is_none() => {
+                (parse_digits(h).ok_or_else(err)?, parse_digits(m).ok_or_else(err)?, 0)
+            }
+            [h, m, s] if h.len() == 2 && m.len() == 2 && s.len() == 2 => (
+                parse_digits(h).ok_or_else(err)?,
+                parse_digits(m).ok_or_else(err)?,
+                parse_digits(s).ok_or_else(err)?,
+            ),
+            _ => return Err(err()),
+        };
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(err());
+        }
+        let micros = match fraction {
+            Some(f) if !f.is_empty() && f.len() <= 6 => {
+                parse_digits(f).ok_or_else(err)? * 10_i64.pow(6 - f.len() as u32)
+            }
+            Some(_) => return Err(err()),
+            None => 0,
+        };
+        Ok(Self((hour * 3600 + minute * 60 + second) * MICROS_PER_SECOND + micros))
+    }
+}
+
+impl Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.0 / MICROS_PER_SECOND;
+        write!(f, "{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)?;
+        let micros = self.0 % MICROS_PER_SECOND;
+        if micros > 0 {
+            write!(f, ".{}", format!("{:06}", micros).trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Timestamp {
+    /// Returns the current system time.
+    pub fn now() -> Result<Self> {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Internal(format!("System clock error: {}", e)))?;
+        Ok(Self(elapsed.as_micros() as i64))
+    }
+
+    /// Returns the date part of the timestamp.
+    pub fn date(self) -> Date {
+        Date(self.0.div_euclid(MICROS_PER_DAY))
+    }
+
+    /// Returns the time part of the timestamp.
+    pub fn time(self) -> Time {
+        Time(self.0.rem_euclid(MICROS_PER_DAY))
+    }
+}
+
+impl From<Date> for Timestamp {
+    fn from(date: Date) -> Self {
+        Self(date.0 * MICROS_PER_DAY)
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = Error;
+
+    /// Parses an ISO-8601 timestamp, i.e. YYYY-MM-DD[(T| )HH:MM[:SS[.ffffff]][Z]]. Time zone
+    /// offsets other than UTC are not supported.
+    fn from_str(s: &str) -> Result<Self> {
+        let err = |e: Error| Error::Value(format!("Invalid timestamp '{}': {}", s, e));
+        let (date, time) = match s.find(&['T', ' '][..]) {
+            Some(i) => (&s[..i], Some(s[i + 1..].trim_end_matches('Z'))),
+            None => (s, None),
+        };
+        let date: Date = date.parse().map_err(err)?;
+        let time = time.map(|t| t.parse()).transpose().map_err(err)?.unwrap_or(Time(0));
+        Ok(Self(Timestamp::from(date).0 + time.0))
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.date(), self.time())
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// Returns the number of days in the given month.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses a non-empty string of ASCII digits.
+fn parse_digits(s: &str) -> Option<i64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date() -> Result<()> {
+        for (s, days) in &[
+            ("1970-01-01", 0),
+            ("1969-12-31", -1),
+            ("2000-02-29", 11016),
+            ("2024-01-31", 19753),
+            ("0001-01-01", -719_162),
+            ("9999-12-31", 2_932_896),
+        ] {
+            let date: Date = s.parse()?;
+            assert_eq!(date, Date(*days));
+            assert_eq!(&date.to_string(), s);
+        }
+        for s in &["2023-02-29", "2024-13-01", "2024-00-10", "2024-1-31", "24-01-31", "x"] {
+            assert!(s.parse::<Date>().is_err(), "{} should be invalid", s);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn time() -> Result<()> {
+        for (s, micros, display) in &[
+            ("00:00", 0, "00:00:00"),
+            ("12:34:56", 45_296_000_000, "12:34:56"),
+            ("23:59:59.5", 86_399_500_000, "23:59:59.5"),
+            ("01:02:03.000004", 3_723_000_004, "01:02:03.000004"),
+        ] {
+            let time: Time = s.parse()?;
+            assert_eq!(time, Time(*micros));
+            assert_eq!(&time.to_string(), display);
+        }
+        for s in &["24:00:00", "12:60", "12:00:00.1234567", "12:00.5", "1:00", ""] {
+            assert!(s.parse::<Time>().is_err(), "{} should be invalid", s);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn timestamp() -> Result<()> {
+        for (s, micros, display) in &[
+            ("1970-01-01", 0, "1970-01-01 00:00:00"),
+            ("1969-12-31 23:59:59", -1_000_000, "1969-12-31 23:59:59"),
+            ("2024-01-31T12:30:00Z", 1_706_704_200_000_000, "2024-01-31 12:30:00"),
+            ("2024-01-31 12:30:00.25", 1_706_704_200_250_000, "2024-01-31 12:30:00.25"),
+        ] {
+            let ts: Timestamp = s.parse()?;
+            assert_eq!(ts, Timestamp(*micros));
+            assert_eq!(&ts.to_string(), display);
+        }
+        for s in &["2024-01-31 25:00:00", "2024-01-31T", "2024-01-31 12:00:00+01:00"] {
+            assert!(s.parse::<Timestamp>().is_err(), "{} should be invalid", s);
+        }
+        Ok(())
+    }
+}
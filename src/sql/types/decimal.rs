@@ -0,0 +1,453 @@
+//! Exact fixed-point decimal numbers, for e.g. monetary values where floating point rounding errors
+//! are unacceptable. A decimal is stored as an integer mantissa and a scale, i.e. the number of
+//! fractional digits, such that the value is mantissa * 10^-scale. Values can have at most 38
+//! digits, which is the largest number of decimal digits that fits in an i128.
+//!
+//! Arithmetic is exact where possible, and errors on overflow rather than wrapping or losing
+//! precision. The scale of the result is:
+//!
+//! * Addition, subtraction, and remainder: the largest scale of the operands.
+//! * Multiplication: the sum of the operand scales, rounded to at most 38 fractional digits.
+//! * Division: the largest scale of the operands plus 6, at most 38, with the final digit rounded.
+//!
+//! All rounding is half away from zero, e.g. 2.5 rounds to 3 and -2.5 rounds to -3.
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// The maximum number of digits in a decimal, and thus also its maximum scale.
+pub const MAX_PRECISION: u8 = 38;
+
+/// The number of additional fractional digits in a division result.
+pub const DIVISION_SCALE: u8 = 6;
+
+/// An exact decimal number. Equality, ordering, and hashing are numeric, such that e.g. 1.0 and
+/// 1.00 are equal even though they have different scales.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u8,
+}
+
+impl Decimal {
+    /// Creates a decimal from a mantissa and scale, or errors if it exceeds the maximum precision.
+    pub fn new(mantissa: i128, scale: u8) -> Result<Self> {
+        if scale > MAX_PRECISION {
+            return Err(Error::Value(format!("Decimal scale {} out of range", scale)));
+        }
+        if mantissa.unsigned_abs() >= 10_u128.pow(MAX_PRECISION as u32) {
+            return Err(Error::Value("Decimal overflow".into()));
+        }
+        Ok(Self { mantissa, scale })
+    }
+
+    /// Returns the mantissa, i.e. the value without its decimal point.
+    pub fn mantissa(self) -> i128 {
+        self.mantissa
+    }
+
+    /// Returns the scale, i.e. the number of fractional digits.
+    pub fn scale(self) -> u8 {
+        self.scale
+    }
+
+    /// Returns the precision, i.e. the total number of digits including the fractional digits.
+    /// This is at least 1, and never less than the scale.
+    pub fn precision(self) -> u8 {
+        let digits = self.mantissa.unsigned_abs().to_string().len() as u8;
+        digits.max(self.scale).max(1)
+    }
+
+    /// Rounds (or pads) the decimal to the given scale, rounding half away from zero.
+    pub fn round(self, scale: u8) -> Result<Self> {
+        if scale >= self.scale {
+            let mantissa = pow10(scale - self.scale)
+                .and_then(|p| self.mantissa.checked_mul(p))
+                .ok_or_else(|| Error::Value("Decimal overflow".into()))?;
+            return Self::new(mantissa, scale);
+        }
+        // Scales are at most 38, so the divisor always fits in an i128.
+        let p = 10_i128.pow((self.scale - scale) as u32);
+        let (mut mantissa, remainder) = (self.mantissa / p, self.mantissa % p);
+        if remainder.unsigned_abs() >= p.unsigned_abs() / 2 {
+            mantissa += self.mantissa.signum();
+        }
+        Self::new(mantissa, scale)
+    }
+
+    /// Returns the decimal with trailing fractional zeroes removed.
+    fn normalize(self) -> Self {
+        let mut decimal = self;
+        while decimal.scale > 0 && decimal.mantissa % 10 == 0 {
+            decimal.mantissa /= 10;
+            decimal.scale -= 1;
+        }
+        decimal
+    }
+
+    /// Returns the mantissas of two decimals rescaled to their largest common scale.
+    fn align(self, other: Self) -> Result<(i128, i128, u8)> {
+        let scale = self.scale.max(other.scale);
+        Ok((self.round(scale)?.mantissa, other.round(scale)?.mantissa, scale))
+    }
+
+    /// Adds two decimals, erroring on overflow.
+    pub fn checked_add(self, other: Self) -> Result<Self> {
+        let (lhs, rhs, scale) = self.align(other)?;
+        Self::new(
+            lhs.checked_add(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+            scale,
+        )
+    }
+
+    /// Subtracts two decimals, erroring on overflow.
+    pub fn checked_sub(self, other: Self) -> Result<Self> {
+        let (lhs, rhs, scale) = self.align(other)?;
+        Self::new(
+            lhs.checked_sub(rhs).ok_or_else(|| Error::Value("Decimal overflow".into()))?,
+            scale,
+        )
+    }
+
+    /// Multiplies two decimals, erroring on overflow.
+    pub fn checked_mul(self, other: Self) -> Result<Self> {
+        let mantissa = self
+            .mantissa
+            .checked_mul(other.mantissa)
+            .ok_or_else(|| Error::Value("Decimal overflow".into()))?;
+        let scale = self.scale + other.scale;
+        if scale > MAX_PRECISION {
+            // Round away the excess fractional digits, via an intermediate with just those as scale.
+            let excess = Self { mantissa, scale: scale - MAX_PRECISION };
+            return Self::new(excess.round(0)?.mantissa, MAX_PRECISION);
+        }
+        Self::new(mantissa, scale)
+    }
+
+    /// Divides two decimals, erroring on overflow or division by zero. Uses long division to
+    /// compute the quotient digit by digit, avoiding intermediate overflow.
+    pub fn checked_div(self, other: Self) -> Result<Self> {
+        if other.mantissa == 0 {
+            return Err(Error::Value("Can't divide by zero".into()));
+        }
+        let overflow = || Error::Value("Decimal overflow".into());
+        let scale = (self.scale.max(other.scale) + DIVISION_SCALE).min(MAX_PRECISION);
+        let divisor = other.mantissa.unsigned_abs();
+        let dividend = self.mantissa.unsigned_abs();
+        let mut quotient = dividend / divisor;
+        let mut remainder = dividend % divisor;
+        // The quotient of the mantissas has scale self.scale - other.scale, so we need this many
+        // additional digits to reach the target scale. This is never negative.
+        for _ in 0..(scale + other.scale - self.scale) {
+            let (digit, next) = divide_digit(remainder, divisor);
+            quotient =
+                quotient.checked_mul(10).and_then(|q| q.checked_add(digit)).ok_or_else(overflow)?;
+            remainder = next;
+        }
+        if divide_digit(remainder, divisor).0 >= 5 {
+            quotient = quotient.checked_add(1).ok_or_else(overflow)?;
+        }
+        let mantissa = i128::try_from(quotient).map_err(|_| overflow())?;
+        let negative = (self.mantissa < 0) != (other.mantissa < 0);
+        Self::new(if negative { -mantissa } else { mantissa }, scale)
+    }
+
+    /// Takes the remainder of two decimals, with the sign of the dividend (like integers).
+    pub fn checked_rem(self, other: Self) -> Result<Self> {
+        if other.mantissa == 0 {
+            return Err(Error::Value("Can't divide by zero".into()));
+        }
+        let (lhs, rhs, scale) = self.align(other)?;
+        Self::new(lhs % rhs, scale)
+    }
+
+    /// Converts a float to a decimal, using the shortest decimal representation that round-trips
+    /// to the same float, e.g. 0.1 converts to exactly 0.1. Errors for infinity and NaN.
+    pub fn from_f64(f: f64) -> Result<Self> {
+        if !f.is_finite() {
+            return Err(Error::Value(format!("Can't convert {} to decimal", f)));
+        }
+        f.to_string().parse()
+    }
+
+    /// Converts the decimal to the nearest float.
+    pub fn to_f64(self) -> f64 {
+        // Rust's float parsing is correctly rounded, unlike dividing by a power of 10.
+        self.to_string().parse().unwrap_or(std::f64::NAN)
+    }
+
+    /// Converts the decimal to an integer, truncating toward zero.
+    pub fn to_i64(self) -> Result<i64> {
+        // Scales are at most 38, so the divisor always fits in an i128.
+        i64::try_from(self.mantissa / 10_i128.pow(self.scale as u32))
+            .map_err(|_| Error::Value("Integer overflow".into()))
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(i: i64) -> Self {
+        Self { mantissa: i as i128, scale: 0 }
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = Error;
+
+    /// Parses a decimal, i.e. [+-]digits[.digits]. The scale is given by the number of
+    /// fractional digits, and fractional digits beyond the maximum scale are rounded.
+    fn from_str(s: &str) -> Result<Self> {
+        let err = || Error::Value(format!("Invalid decimal '{}'", s));
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (integer, fraction) = match unsigned.find('.') {
+            Some(i) => (&unsigned[..i], &unsigned[i + 1..]),
+            None => (unsigned, ""),
+        };
+        if integer.is_empty() && fraction.is_empty()
+            || !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit())
+        {
+            return Err(err());
+        }
+        let (fraction, round_up) = match fraction.len() {
+            l if l > MAX_PRECISION as usize => {
+                let (fraction, excess) = fraction.split_at(MAX_PRECISION as usize);
+                (fraction, excess.as_bytes()[0] >= b'5')
+            }
+            _ => (fraction, false),
+        };
+        let mut mantissa: i128 = 0;
+        for b in integer.trim_start_matches('0').bytes().chain(fraction.bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add((b - b'0') as i128))
+                .ok_or_else(|| Error::Value(format!("Decimal '{}' out of range", s)))?;
+        }
+        if round_up {
+            mantissa += 1;
+        }
+        Self::new(if negative { -mantissa } else { mantissa }, fraction.len() as u8)
+            .map_err(|_| Error::Value(format!("Decimal '{}' out of range", s)))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits =
+            format!("{:0>width$}", self.mantissa.unsigned_abs(), width = self.scale as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        if self.mantissa < 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", integer)?;
+        if !fraction.is_empty() {
+            write!(f, ".{}", fraction)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    /// Compares the integer parts first and then the fractional parts, since aligning the scales
+    /// of the full values may overflow.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        let split = |d: &Self| {
+            // Scales are at most 38, so 10^scale always fits in an i128.
+            let p = 10_i128.pow(d.scale as u32);
+            let fraction = d.mantissa.rem_euclid(p) * 10_i128.pow((scale - d.scale) as u32);
+            (d.mantissa.div_euclid(p), fraction)
+        };
+        split(self).cmp(&split(other))
+    }
+}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalize();
+        normalized.mantissa.hash(state);
+        normalized.scale.hash(state);
+    }
+}
+
+/// Returns 10^exp, or None if it overflows.
+fn pow10(exp: u8) -> Option<i128> {
+    10_i128.checked_pow(exp as u32)
+}
+
+/// Computes the next digit of a long division, given the current remainder and the divisor, and
+/// returns the digit and the new remainder. The remainder is always less than the divisor, but
+/// multiplying it by 10 may still overflow a u128 for very large divisors. In that case, we split
+/// the divisor into d = 10a + b, such that d * digit <= 10r iff r - a * digit >= b * digit / 10.
+fn divide_digit(remainder: u128, divisor: u128) -> (u128, u128) {
+    if let Some(r) = remainder.checked_mul(10) {
+        return (r / divisor, r % divisor);
+    }
+    let (a, b) = (divisor / 10, divisor % 10);
+    let digit = (0..10)
+        .rev()
+        .find(|&d| {
+            remainder >= a * d && (remainder - a * d).checked_mul(10).map_or(true, |r| r >= b * d)
+        })
+        .unwrap_or(0);
+    // 10r - d * digit = 10(r - a * digit) - b * digit, which is less than the divisor.
+    (digit, (remainder - a * digit) * 10 - b * digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse() -> Result<()> {
+        for (s, mantissa, scale, display) in &[
+            ("0", 0, 0, "0"),
+            ("12.34", 1234, 2, "12.34"),
+            ("-12.340", -12340, 3, "-12.340"),
+            ("+0.05", 5, 2, "0.05"),
+            (".5", 5, 1, "0.5"),
+            ("5.", 5, 0, "5"),
+            ("007", 7, 0, "7"),
+            ("-0.001", -1, 3, "-0.001"),
+        ] {
+            let d: Decimal = s.parse()?;
+            assert_eq!((d.mantissa(), d.scale()), (*mantissa, *scale), "{}", s);
+            assert_eq!(&d.to_string(), display);
+        }
+        for s in &["", "-", ".", "1.2.3", "1e3", "abc", " 1", "1,5", "--1"] {
+            assert!(s.parse::<Decimal>().is_err(), "{} should be invalid", s);
+        }
+        // 38 digits is the maximum.
+        assert!("99999999999999999999999999999999999999".parse::<Decimal>().is_ok());
+        assert!("100000000000000000000000000000000000000".parse::<Decimal>().is_err());
+        // Excess fractional digits are rounded.
+        let excess = dec(&format!("0.{}15", "0".repeat(37)));
+        assert_eq!(excess.to_string(), format!("0.{}2", "0".repeat(37)));
+        Ok(())
+    }
+
+    #[test]
+    fn round() -> Result<()> {
+        for (s, scale, expect) in &[
+            ("1.25", 1, "1.3"),
+            ("-1.25", 1, "-1.3"),
+            ("1.24", 1, "1.2"),
+            ("-1.24", 1, "-1.2"),
+            ("2.5", 0, "3"),
+            ("-2.5", 0, "-3"),
+            ("0.4", 0, "0"),
+            ("1.5", 3, "1.500"),
+            ("9.99", 1, "10.0"),
+        ] {
+            let d = dec(s).round(*scale)?;
+            assert_eq!(&d.to_string(), expect);
+            assert_eq!(d.scale(), *scale);
+        }
+        assert!(dec("99999999999999999999999999999999999999").round(1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn arithmetic() -> Result<()> {
+        assert_eq!(dec("1.5").checked_add(dec("2.25"))?.to_string(), "3.75");
+        assert_eq!(dec("1.50").checked_sub(dec("2.5"))?.to_string(), "-1.00");
+        assert_eq!(dec("1.5").checked_mul(dec("2.25"))?.to_string(), "3.375");
+        assert_eq!(dec("7.5").checked_rem(dec("2"))?.to_string(), "1.5");
+        assert_eq!(dec("-7.5").checked_rem(dec("2"))?.to_string(), "-1.5");
+        assert_eq!(dec("10").checked_div(dec("4"))?.to_string(), "2.500000");
+        assert_eq!(dec("1.00").checked_div(dec("3"))?.to_string(), "0.33333333");
+        assert_eq!(dec("2").checked_div(dec("3"))?.to_string(), "0.666667");
+        assert_eq!(dec("-2").checked_div(dec("3"))?.to_string(), "-0.666667");
+        assert_eq!(dec("1").checked_div(dec("0.003"))?.to_string(), "333.333333333");
+        assert_eq!(
+            dec("1").checked_div(dec("0")),
+            Err(Error::Value("Can't divide by zero".into()))
+        );
+
+        let max = dec("99999999999999999999999999999999999999");
+        let overflow = Err(Error::Value("Decimal overflow".into()));
+        assert_eq!(max.checked_add(dec("1")), overflow);
+        assert_eq!(max.checked_sub(dec("-1")), overflow);
+        assert_eq!(max.checked_mul(dec("10")), overflow);
+        assert_eq!(max.checked_div(dec("0.1")), overflow);
+        assert_eq!(max.checked_div(max)?.to_string(), "1.000000");
+        assert_eq!(dec("1").checked_div(max)?.to_string(), "0.000000");
+        // Divisors too large to multiply the remainder by 10 in a u128.
+        let large = dec("66666666666666666666666666666666666666");
+        assert_eq!(large.checked_div(max)?.to_string(), "0.666667");
+        assert_eq!(dec("1").checked_div(large)?.to_string(), "0.000000");
+
+        // Multiplying beyond the maximum scale rounds the result.
+        let tiny = dec("0.00000000000000000005");
+        assert_eq!(tiny.checked_mul(tiny)?.to_string(), format!("0.{}", "0".repeat(38)));
+        assert_eq!(tiny.checked_mul(dec("0.5"))?.to_string(), "0.000000000000000000025");
+        Ok(())
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(dec("1.0"), dec("1.00"));
+        assert!(dec("1.01") > dec("1.009"));
+        assert!(dec("-1.01") < dec("-1.009"));
+        assert!(dec("-0.5") < dec("0"));
+        assert!(
+            dec("99999999999999999999999999999999999999")
+                > dec("9999999999999999999999999999.9999999999")
+        );
+
+        use std::collections::hash_map::DefaultHasher;
+        let hash = |d: Decimal| {
+            let mut hasher = DefaultHasher::new();
+            d.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(dec("1.50")), hash(dec("1.5")));
+        assert_ne!(hash(dec("1.50")), hash(dec("1.05")));
+    }
+
+    #[test]
+    fn convert() -> Result<()> {
+        assert_eq!(Decimal::from_f64(0.1)?.to_string(), "0.1");
+        assert_eq!(Decimal::from_f64(-12.345)?.to_string(), "-12.345");
+        assert_eq!(Decimal::from_f64(1e20)?.to_string(), "100000000000000000000");
+        assert!(Decimal::from_f64(std::f64::NAN).is_err());
+        assert!(Decimal::from_f64(std::f64::INFINITY).is_err());
+        assert!(Decimal::from_f64(1e40).is_err());
+        assert_eq!(dec("0.1").to_f64(), 0.1);
+        assert_eq!(dec("-2.5").to_i64()?, -2);
+        assert_eq!(dec("2.99").to_i64()?, 2);
+        assert!(dec("99999999999999999999").to_i64().is_err());
+        Ok(())
+    }
+}
@@ -1,10 +1,16 @@
-use super::{Row, Value};
+use super::{self as types, Column, DataType, Function, Row, Value};
+use super::{DECIMAL_DIVISION_SCALE, DECIMAL_MAX_PRECISION};
 use crate::error::{Error, Result};
+use crate::sql::plan::Node;
+use crate::sql::schema::Catalog;
 
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt::{self, Display};
 use std::mem::replace;
+use std::ops::Bound;
 
 /// An expression, made up of constants and operations
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -12,6 +18,17 @@ pub enum Expression {
     // Values
     Constant(Value),
     Field(usize, Option<(Option<String>, String)>),
+    Function(Function, Vec<Expression>),
+    /// CASE with an optional operand, WHEN/THEN branches, and an optional ELSE result. With an
+    /// operand, the WHEN expressions are values to compare it with, otherwise boolean conditions.
+    Case(Option<Box<Expression>>, Vec<(Expression, Expression)>, Option<Box<Expression>>),
+    /// CAST of an expression to a datatype.
+    Cast(Box<Expression>, DataType),
+    /// TRY_CAST of an expression to a datatype, yielding NULL if the cast fails.
+    TryCast(Box<Expression>, DataType),
+    /// A scalar subquery, which must be replaced by its result value before the expression is
+    /// evaluated.
+    Subquery(Box<Node>),
 
     // Logical operations
     And(Box<Expression>, Box<Expression>),
@@ -20,7 +37,15 @@ pub enum Expression {
 
     // Comparisons operations (GTE, LTE, and NEQ are composite operations)
     Equal(Box<Expression>, Box<Expression>),
+    /// An EXISTS subquery, which must be replaced by its boolean result before the expression is
+    /// evaluated.
+    Exists(Box<Node>),
     GreaterThan(Box<Expression>, Box<Expression>),
+    In(Box<Expression>, Vec<Expression>),
+    /// An IN subquery, which must be replaced by an In list of its result values before the
+    /// expression is evaluated.
+    InSubquery(Box<Expression>, Box<Node>),
+    IsDistinctFrom(Box<Expression>, Box<Expression>),
     IsNull(Box<Expression>),
     LessThan(Box<Expression>, Box<Expression>),
 
@@ -36,7 +61,10 @@ pub enum Expression {
     Subtract(Box<Expression>, Box<Expression>),
 
     // String operations
-    Like(Box<Expression>, Box<Expression>),
+    ILike(Box<Expression>, Box<Expression>, Option<char>),
+    Like(Box<Expression>, Box<Expression>, Option<char>),
+    /// A LIKE or ILIKE operation with a constant pattern, which is only compiled once.
+    Matches(Box<Expression>, Pattern),
 }
 
 impl Expression {
@@ -47,6 +75,48 @@ impl Expression {
             // Constant values
             Self::Constant(c) => c.clone(),
             Self::Field(i, _) => row.and_then(|row| row.get(*i).cloned()).unwrap_or(Null),
+            // COALESCE arguments are evaluated lazily, up to the first non-NULL value.
+            Self::Function(Function::Coalesce, args) => {
+                for arg in args {
+                    match arg.evaluate(row)? {
+                        Null => {}
+                        value => return Ok(value),
+                    }
+                }
+                Null
+            }
+            Self::Function(function, args) => function
+                .evaluate(args.iter().map(|arg| arg.evaluate(row)).collect::<Result<_>>()?)?,
+            // Branches are evaluated lazily, such that untaken branches can't error.
+            Self::Case(operand, branches, r#else) => {
+                let operand = operand.as_ref().map(|o| o.evaluate(row)).transpose()?;
+                for (condition, result) in branches {
+                    let mut matched = condition.evaluate(row)?;
+                    if let Some(operand) = &operand {
+                        matched = Self::Equal(
+                            Box::new(Self::Constant(operand.clone())),
+                            Box::new(Self::Constant(matched)),
+                        )
+                        .evaluate(None)?;
+                    }
+                    match matched {
+                        Boolean(true) => return result.evaluate(row),
+                        Boolean(false) | Null => {}
+                        value => {
+                            return Err(Error::Value(format!(
+                                "CASE condition must be boolean, got {}",
+                                value
+                            )))
+                        }
+                    }
+                }
+                match r#else {
+                    Some(r#else) => r#else.evaluate(row)?,
+                    None => Null,
+                }
+            }
+            Self::Cast(expr, datatype) => expr.evaluate(row)?.cast(datatype)?,
+            Self::TryCast(expr, datatype) => expr.evaluate(row)?.cast(datatype).unwrap_or(Null),
 
             // Logical operations
             Self::And(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
@@ -78,10 +148,23 @@ impl Expression {
             Self::Equal(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
                 (Boolean(lhs), Boolean(rhs)) => Boolean(lhs == rhs),
                 (Integer(lhs), Integer(rhs)) => Boolean(lhs == rhs),
-                (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 == rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs == rhs as f64),
+                (Integer(lhs), Float(rhs)) => {
+                    Boolean(types::compare_integer_float(lhs, rhs) == Some(Ordering::Equal))
+                }
+                (Float(lhs), Integer(rhs)) => {
+                    Boolean(types::compare_integer_float(rhs, lhs) == Some(Ordering::Equal))
+                }
                 (Float(lhs), Float(rhs)) => Boolean(lhs == rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs == rhs),
+                (Date(lhs), Date(rhs)) => Boolean(lhs == rhs),
+                (Time(lhs), Time(rhs)) => Boolean(lhs == rhs),
+                (Timestamp(lhs), Timestamp(rhs)) => Boolean(lhs == rhs),
+                (Decimal(lhs), Decimal(rhs)) => Boolean(lhs == rhs),
+                (Decimal(lhs), Integer(rhs)) => Boolean(lhs == rhs.into()),
+                (Integer(lhs), Decimal(rhs)) => Boolean(types::Decimal::from(lhs) == rhs),
+                (Decimal(lhs), Float(rhs)) => Boolean(lhs.to_f64() == rhs),
+                (Float(lhs), Decimal(rhs)) => Boolean(lhs == rhs.to_f64()),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs == rhs),
                 (Null, _) | (_, Null) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
@@ -91,10 +174,23 @@ impl Expression {
                 #[allow(clippy::bool_comparison)]
                 (Boolean(lhs), Boolean(rhs)) => Boolean(lhs > rhs),
                 (Integer(lhs), Integer(rhs)) => Boolean(lhs > rhs),
-                (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 > rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs > rhs as f64),
+                (Integer(lhs), Float(rhs)) => {
+                    Boolean(types::compare_integer_float(lhs, rhs) == Some(Ordering::Greater))
+                }
+                (Float(lhs), Integer(rhs)) => {
+                    Boolean(types::compare_integer_float(rhs, lhs) == Some(Ordering::Less))
+                }
                 (Float(lhs), Float(rhs)) => Boolean(lhs > rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs > rhs),
+                (Date(lhs), Date(rhs)) => Boolean(lhs > rhs),
+                (Time(lhs), Time(rhs)) => Boolean(lhs > rhs),
+                (Timestamp(lhs), Timestamp(rhs)) => Boolean(lhs > rhs),
+                (Decimal(lhs), Decimal(rhs)) => Boolean(lhs > rhs),
+                (Decimal(lhs), Integer(rhs)) => Boolean(lhs > rhs.into()),
+                (Integer(lhs), Decimal(rhs)) => Boolean(types::Decimal::from(lhs) > rhs),
+                (Decimal(lhs), Float(rhs)) => Boolean(lhs.to_f64() > rhs),
+                (Float(lhs), Decimal(rhs)) => Boolean(lhs > rhs.to_f64()),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs > rhs),
                 (Null, _) | (_, Null) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
@@ -104,15 +200,65 @@ impl Expression {
                 #[allow(clippy::bool_comparison)]
                 (Boolean(lhs), Boolean(rhs)) => Boolean(lhs < rhs),
                 (Integer(lhs), Integer(rhs)) => Boolean(lhs < rhs),
-                (Integer(lhs), Float(rhs)) => Boolean((lhs as f64) < rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs < rhs as f64),
+                (Integer(lhs), Float(rhs)) => {
+                    Boolean(types::compare_integer_float(lhs, rhs) == Some(Ordering::Less))
+                }
+                (Float(lhs), Integer(rhs)) => {
+                    Boolean(types::compare_integer_float(rhs, lhs) == Some(Ordering::Greater))
+                }
                 (Float(lhs), Float(rhs)) => Boolean(lhs < rhs),
                 (String(lhs), String(rhs)) => Boolean(lhs < rhs),
+                (Date(lhs), Date(rhs)) => Boolean(lhs < rhs),
+                (Time(lhs), Time(rhs)) => Boolean(lhs < rhs),
+                (Timestamp(lhs), Timestamp(rhs)) => Boolean(lhs < rhs),
+                (Decimal(lhs), Decimal(rhs)) => Boolean(lhs < rhs),
+                (Decimal(lhs), Integer(rhs)) => Boolean(lhs < rhs.into()),
+                (Integer(lhs), Decimal(rhs)) => Boolean(types::Decimal::from(lhs) < rhs),
+                (Decimal(lhs), Float(rhs)) => Boolean(lhs.to_f64() < rhs),
+                (Float(lhs), Decimal(rhs)) => Boolean(lhs < rhs.to_f64()),
+                (Bytes(lhs), Bytes(rhs)) => Boolean(lhs < rhs),
                 (Null, _) | (_, Null) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
                 }
             },
+            // List items are evaluated lazily, stopping at the first match. If there is no match
+            // but the list contains a NULL, the result is NULL, like for a chain of OR = operations.
+            Self::In(lhs, list) => {
+                let lhs = lhs.evaluate(row)?;
+                let mut result = Boolean(false);
+                for item in list {
+                    let item = Self::Constant(item.evaluate(row)?);
+                    match Self::Equal(Self::Constant(lhs.clone()).into(), item.into())
+                        .evaluate(None)?
+                    {
+                        Boolean(true) => return Ok(Boolean(true)),
+                        Boolean(false) => {}
+                        Null => result = Null,
+                        value => {
+                            return Err(Error::Internal(format!("Unexpected comparison {}", value)))
+                        }
+                    }
+                }
+                result
+            }
+            Self::Exists(_) | Self::InSubquery(_, _) | Self::Subquery(_) => {
+                return Err(Error::Internal("Subquery has not been evaluated".into()))
+            }
+            Self::IsDistinctFrom(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (Null, Null) => Boolean(false),
+                (Null, _) | (_, Null) => Boolean(true),
+                (lhs, rhs) => {
+                    match Self::Equal(Self::Constant(lhs).into(), Self::Constant(rhs).into())
+                        .evaluate(None)?
+                    {
+                        Boolean(equal) => Boolean(!equal),
+                        value => {
+                            return Err(Error::Internal(format!("Unexpected comparison {}", value)))
+                        }
+                    }
+                }
+            },
             Self::IsNull(expr) => match expr.evaluate(row)? {
                 Null => Boolean(true),
                 _ => Boolean(false),
@@ -131,9 +277,18 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_add(rhs)?),
+                (Decimal(lhs), Integer(rhs)) => Decimal(lhs.checked_add(rhs.into())?),
+                (Integer(lhs), Decimal(rhs)) => {
+                    Decimal(types::Decimal::from(lhs).checked_add(rhs)?)
+                }
+                (Decimal(lhs), Float(rhs)) => Float(lhs.to_f64() + rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs + rhs.to_f64()),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => return Err(Error::Value(format!("Can't add {} and {}", lhs, rhs))),
             },
             Self::Assert(expr) => match expr.evaluate(row)? {
+                Decimal(d) => Decimal(d),
                 Float(f) => Float(f),
                 Integer(i) => Integer(i),
                 Null => Null,
@@ -143,7 +298,9 @@ impl Expression {
                 (Integer(_), Integer(rhs)) if rhs == 0 => {
                     return Err(Error::Value("Can't divide by zero".into()))
                 }
-                (Integer(lhs), Integer(rhs)) => Integer(lhs / rhs),
+                (Integer(lhs), Integer(rhs)) => Integer(
+                    lhs.checked_div(rhs).ok_or_else(|| Error::Value("Integer overflow".into()))?,
+                ),
                 (Integer(lhs), Float(rhs)) => Float(lhs as f64 / rhs),
                 (Integer(_), Null) => Null,
                 (Float(lhs), Integer(rhs)) => Float(lhs / rhs as f64),
@@ -152,24 +309,46 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_div(rhs)?),
+                (Decimal(lhs), Integer(rhs)) => Decimal(lhs.checked_div(rhs.into())?),
+                (Integer(lhs), Decimal(rhs)) => {
+                    Decimal(types::Decimal::from(lhs).checked_div(rhs)?)
+                }
+                (Decimal(lhs), Float(rhs)) => Float(lhs.to_f64() / rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs / rhs.to_f64()),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't divide {} and {}", lhs, rhs)))
                 }
             },
             Self::Exponentiate(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
                 (Integer(lhs), Integer(rhs)) if rhs >= 0 => Integer(
-                    lhs.checked_pow(rhs as u32)
+                    u32::try_from(rhs)
+                        .ok()
+                        .and_then(|rhs| lhs.checked_pow(rhs))
                         .ok_or_else(|| Error::Value("Integer overflow".into()))?,
                 ),
                 (Integer(lhs), Integer(rhs)) => Float((lhs as f64).powf(rhs as f64)),
                 (Integer(lhs), Float(rhs)) => Float((lhs as f64).powf(rhs)),
                 (Integer(_), Null) => Null,
-                (Float(lhs), Integer(rhs)) => Float((lhs).powi(rhs as i32)),
+                (Float(lhs), Integer(rhs)) => Float(match i32::try_from(rhs) {
+                    Ok(rhs) => lhs.powi(rhs),
+                    Err(_) => lhs.powf(rhs as f64),
+                }),
                 (Float(lhs), Float(rhs)) => Float((lhs).powf(rhs)),
                 (Float(_), Null) => Null,
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Integer(rhs)) => Float(match i32::try_from(rhs) {
+                    Ok(rhs) => lhs.to_f64().powi(rhs),
+                    Err(_) => lhs.to_f64().powf(rhs as f64),
+                }),
+                (Decimal(lhs), Float(rhs)) => Float(lhs.to_f64().powf(rhs)),
+                (Decimal(lhs), Decimal(rhs)) => Float(lhs.to_f64().powf(rhs.to_f64())),
+                (Integer(lhs), Decimal(rhs)) => Float((lhs as f64).powf(rhs.to_f64())),
+                (Float(lhs), Decimal(rhs)) => Float(lhs.powf(rhs.to_f64())),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't exponentiate {} and {}", lhs, rhs)))
                 }
@@ -178,7 +357,11 @@ impl Expression {
                 Integer(i) if i < 0 => {
                     return Err(Error::Value("Can't take factorial of negative number".into()))
                 }
-                Integer(i) => Integer((1..=i).fold(1, |a, b| a * b as i64)),
+                Integer(i) => Integer(
+                    (1..=i)
+                        .try_fold(1_i64, |a, b| a.checked_mul(b))
+                        .ok_or_else(|| Error::Value("Integer overflow".into()))?,
+                ),
                 Null => Null,
                 value => return Err(Error::Value(format!("Can't take factorial of {}", value))),
             },
@@ -187,7 +370,8 @@ impl Expression {
                 (Integer(_), Integer(rhs)) if rhs == 0 => {
                     return Err(Error::Value("Can't divide by zero".into()))
                 }
-                (Integer(lhs), Integer(rhs)) => Integer(lhs % rhs),
+                // i64::MIN % -1 overflows in Rust, but the remainder is always 0.
+                (Integer(lhs), Integer(rhs)) => Integer(lhs.wrapping_rem(rhs)),
                 (Integer(lhs), Float(rhs)) => Float(lhs as f64 % rhs),
                 (Integer(_), Null) => Null,
                 (Float(lhs), Integer(rhs)) => Float(lhs % rhs as f64),
@@ -196,6 +380,14 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_rem(rhs)?),
+                (Decimal(lhs), Integer(rhs)) => Decimal(lhs.checked_rem(rhs.into())?),
+                (Integer(lhs), Decimal(rhs)) => {
+                    Decimal(types::Decimal::from(lhs).checked_rem(rhs)?)
+                }
+                (Decimal(lhs), Float(rhs)) => Float(lhs.to_f64() % rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs % rhs.to_f64()),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't take modulo of {} and {}", lhs, rhs)))
                 }
@@ -212,12 +404,23 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_mul(rhs)?),
+                (Decimal(lhs), Integer(rhs)) => Decimal(lhs.checked_mul(rhs.into())?),
+                (Integer(lhs), Decimal(rhs)) => {
+                    Decimal(types::Decimal::from(lhs).checked_mul(rhs)?)
+                }
+                (Decimal(lhs), Float(rhs)) => Float(lhs.to_f64() * rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs * rhs.to_f64()),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't multiply {} and {}", lhs, rhs)))
                 }
             },
             Self::Negate(expr) => match expr.evaluate(row)? {
-                Integer(i) => Integer(-i),
+                Decimal(d) => Decimal(types::Decimal::new(-d.mantissa(), d.scale())?),
+                Integer(i) => {
+                    Integer(i.checked_neg().ok_or_else(|| Error::Value("Integer overflow".into()))?)
+                }
                 Float(f) => Float(-f),
                 Null => Null,
                 value => return Err(Error::Value(format!("Can't negate {}", value))),
@@ -234,31 +437,282 @@ impl Expression {
                 (Null, Float(_)) => Null,
                 (Null, Integer(_)) => Null,
                 (Null, Null) => Null,
+                (Decimal(lhs), Decimal(rhs)) => Decimal(lhs.checked_sub(rhs)?),
+                (Decimal(lhs), Integer(rhs)) => Decimal(lhs.checked_sub(rhs.into())?),
+                (Integer(lhs), Decimal(rhs)) => {
+                    Decimal(types::Decimal::from(lhs).checked_sub(rhs)?)
+                }
+                (Decimal(lhs), Float(rhs)) => Float(lhs.to_f64() - rhs),
+                (Float(lhs), Decimal(rhs)) => Float(lhs - rhs.to_f64()),
+                (Decimal(_), Null) | (Null, Decimal(_)) => Null,
                 (lhs, rhs) => {
                     return Err(Error::Value(format!("Can't subtract {} and {}", lhs, rhs)))
                 }
             },
 
             // String operations
-            Self::Like(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (String(lhs), String(rhs)) => Boolean(
-                    Regex::new(&format!(
-                        "^{}$",
-                        regex::escape(&rhs)
-                            .replace("%", ".*")
-                            .replace(".*.*", "%")
-                            .replace("_", ".")
-                            .replace("..", "_")
-                    ))?
-                    .is_match(&lhs),
-                ),
-                (String(_), Null) => Null,
-                (Null, String(_)) => Null,
-                (lhs, rhs) => return Err(Error::Value(format!("Can't LIKE {} and {}", lhs, rhs))),
+            Self::ILike(lhs, rhs, escape) | Self::Like(lhs, rhs, escape) => {
+                let case_insensitive = matches!(self, Self::ILike(_, _, _));
+                match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                    (String(lhs), String(rhs)) => {
+                        Boolean(Pattern::new(rhs, *escape, case_insensitive)?.is_match(&lhs))
+                    }
+                    (String(_), Null) => Null,
+                    (Null, String(_)) => Null,
+                    (Null, Null) => Null,
+                    (lhs, rhs) => {
+                        let op = if case_insensitive { "ILIKE" } else { "LIKE" };
+                        return Err(Error::Value(format!("Can't {} {} and {}", op, lhs, rhs)));
+                    }
+                }
+            }
+            Self::Matches(lhs, pattern) => match lhs.evaluate(row)? {
+                String(lhs) => Boolean(pattern.is_match(&lhs)),
+                Null => Null,
+                lhs => {
+                    let op = if pattern.case_insensitive { "ILIKE" } else { "LIKE" };
+                    return Err(Error::Value(format!(
+                        "Can't {} {} and {}",
+                        op, lhs, pattern.pattern
+                    )));
+                }
             },
         })
     }
 
+    /// Infers the datatype of the expression's values, given the columns of the rows it's
+    /// evaluated on, or returns None if it's unknown (e.g. for NULL constants). Only the schemas
+    /// of tables in subqueries are read from the catalog. Expressions that error during
+    /// evaluation due to incompatible types also have an unknown datatype.
+    pub fn datatype<C: Catalog>(
+        &self,
+        columns: &[Column],
+        catalog: &C,
+    ) -> Result<Option<DataType>> {
+        use DataType::*;
+        let infer = |expr: &Expression| expr.datatype(columns, catalog);
+        Ok(match self {
+            Self::Constant(value) => value.datatype(),
+            Self::Field(i, _) => columns.get(*i).and_then(|c| c.datatype.clone()),
+            Self::Function(function, args) => {
+                function.datatype(&args.iter().map(infer).collect::<Result<Vec<_>>>()?)
+            }
+            Self::Case(_, branches, r#else) => {
+                let mut datatype = None;
+                for result in branches.iter().map(|(_, r)| r).chain(r#else.as_deref()) {
+                    datatype = datatype.or(infer(result)?);
+                }
+                datatype
+            }
+            Self::Cast(_, datatype) | Self::TryCast(_, datatype) => Some(datatype.clone()),
+            Self::Subquery(node) => {
+                node.columns(catalog)?.into_iter().next().and_then(|c| c.datatype)
+            }
+
+            Self::And(..)
+            | Self::Not(_)
+            | Self::Or(..)
+            | Self::Equal(..)
+            | Self::Exists(_)
+            | Self::GreaterThan(..)
+            | Self::In(..)
+            | Self::InSubquery(..)
+            | Self::IsDistinctFrom(..)
+            | Self::IsNull(_)
+            | Self::LessThan(..)
+            | Self::ILike(..)
+            | Self::Like(..)
+            | Self::Matches(..) => Some(Boolean),
+
+            Self::Add(lhs, rhs) | Self::Subtract(lhs, rhs) | Self::Modulo(lhs, rhs) => {
+                Self::arithmetic_datatype(infer(lhs)?, infer(rhs)?, |l, r| l.max(r))
+            }
+            Self::Multiply(lhs, rhs) => {
+                Self::arithmetic_datatype(infer(lhs)?, infer(rhs)?, |l, r| l.saturating_add(r))
+            }
+            Self::Divide(lhs, rhs) => {
+                Self::arithmetic_datatype(infer(lhs)?, infer(rhs)?, |l, r| {
+                    l.max(r).saturating_add(DECIMAL_DIVISION_SCALE)
+                })
+            }
+            Self::Exponentiate(lhs, rhs) => Self::exponentiate_datatype(infer(lhs)?, infer(rhs)?),
+            Self::Assert(expr) | Self::Negate(expr) => infer(expr)?,
+            Self::Factorial(_) => Some(Integer),
+        })
+    }
+
+    /// Infers the datatype of an arithmetic operation on the given operand datatypes. Integers
+    /// yield integers, floats take precedence over other numbers, and decimals yield decimals of
+    /// the maximum precision, with a scale given by the operand scales. If one of the operands is
+    /// unknown (e.g. NULL), the other's datatype is used.
+    pub(super) fn arithmetic_datatype(
+        lhs: Option<DataType>,
+        rhs: Option<DataType>,
+        scale: impl Fn(u8, u8) -> u8,
+    ) -> Option<DataType> {
+        use DataType::*;
+        let decimal = |l, r| Decimal(DECIMAL_MAX_PRECISION, scale(l, r).min(DECIMAL_MAX_PRECISION));
+        Some(match (lhs, rhs) {
+            (Some(Integer), Some(Integer)) => Integer,
+            (Some(Float), Some(Integer | Float | Decimal(..)))
+            | (Some(Integer | Decimal(..)), Some(Float)) => Float,
+            (Some(Decimal(_, l)), Some(Decimal(_, r))) => decimal(l, r),
+            (Some(Decimal(_, l)), Some(Integer)) => decimal(l, 0),
+            (Some(Integer), Some(Decimal(_, r))) => decimal(0, r),
+            (Some(datatype), None) | (None, Some(datatype)) => datatype,
+            _ => return None,
+        })
+    }
+
+    /// Infers the datatype of an exponentiation on the given operand datatypes: integers yield
+    /// integers, and other numbers floats.
+    pub(super) fn exponentiate_datatype(
+        lhs: Option<DataType>,
+        rhs: Option<DataType>,
+    ) -> Option<DataType> {
+        use DataType::*;
+        match (lhs, rhs) {
+            (Some(Integer), Some(Integer) | None) | (None, Some(Integer)) => Some(Integer),
+            (Some(Integer | Float | Decimal(..)), _) | (_, Some(Float | Decimal(..))) => {
+                Some(Float)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks that the expression's operators are applied to compatible types, given the columns
+    /// of the rows it's evaluated on, without evaluating it. Errors with Error::Value naming the
+    /// offending expression and its operand types, e.g. "Can't compare STRING and INTEGER in
+    /// name = 1". Operands of unknown type (e.g. NULL) are assumed to be compatible, and function
+    /// arguments are only checked during evaluation. Subqueries are checked via Node::check().
+    pub fn check<C: Catalog>(&self, columns: &[Column], catalog: &C) -> Result<()> {
+        self.clone().transform(&|e| Ok(e), &|e| {
+            e.check_operands(columns, catalog)?;
+            Ok(e)
+        })?;
+        Ok(())
+    }
+
+    /// Checks the types of the expression's direct operands. Helper for check().
+    fn check_operands<C: Catalog>(&self, columns: &[Column], catalog: &C) -> Result<()> {
+        use DataType::*;
+        let infer = |expr: &Expression| expr.datatype(columns, catalog);
+        let numeric = |datatype: &DataType| matches!(datatype, Integer | Float | Decimal(..));
+        let comparable = |lhs: &DataType, rhs: &DataType| {
+            std::mem::discriminant(lhs) == std::mem::discriminant(rhs)
+                || numeric(lhs) && numeric(rhs)
+        };
+        let error = |op: &str, datatypes: &[&DataType]| {
+            let datatypes: Vec<std::string::String> =
+                datatypes.iter().map(|d| d.to_string()).collect();
+            Err(Error::Value(format!("Can't {} {} in {}", op, datatypes.join(" and "), self)))
+        };
+        let binary = match self {
+            Self::And(lhs, rhs) => Some(("and", lhs, rhs)),
+            Self::Or(lhs, rhs) => Some(("or", lhs, rhs)),
+            Self::Equal(lhs, rhs)
+            | Self::GreaterThan(lhs, rhs)
+            | Self::IsDistinctFrom(lhs, rhs)
+            | Self::LessThan(lhs, rhs) => Some(("compare", lhs, rhs)),
+            Self::Add(lhs, rhs) => Some(("add", lhs, rhs)),
+            Self::Divide(lhs, rhs) => Some(("divide", lhs, rhs)),
+            Self::Exponentiate(lhs, rhs) => Some(("exponentiate", lhs, rhs)),
+            Self::Modulo(lhs, rhs) => Some(("take modulo of", lhs, rhs)),
+            Self::Multiply(lhs, rhs) => Some(("multiply", lhs, rhs)),
+            Self::Subtract(lhs, rhs) => Some(("subtract", lhs, rhs)),
+            Self::Like(lhs, rhs, _) => Some(("LIKE", lhs, rhs)),
+            Self::ILike(lhs, rhs, _) => Some(("ILIKE", lhs, rhs)),
+            _ => None,
+        };
+        if let Some((op, lhs, rhs)) = binary {
+            if let (Some(lhs), Some(rhs)) = (infer(lhs)?, infer(rhs)?) {
+                let compatible = match op {
+                    "and" | "or" => lhs == Boolean && rhs == Boolean,
+                    "compare" => comparable(&lhs, &rhs),
+                    "exponentiate" => {
+                        Self::exponentiate_datatype(Some(lhs.clone()), Some(rhs.clone())).is_some()
+                    }
+                    "LIKE" | "ILIKE" => lhs == String && rhs == String,
+                    _ => numeric(&lhs) && numeric(&rhs),
+                };
+                if !compatible {
+                    return error(op, &[&lhs, &rhs]);
+                }
+            }
+            return Ok(());
+        }
+        match self {
+            Self::Not(expr) => match infer(expr)? {
+                Some(datatype) if datatype != Boolean => return error("negate", &[&datatype]),
+                _ => {}
+            },
+            Self::Assert(expr) | Self::Negate(expr) => match infer(expr)? {
+                Some(datatype) if !numeric(&datatype) => {
+                    let op =
+                        if let Self::Assert(_) = self { "take the positive of" } else { "negate" };
+                    return error(op, &[&datatype]);
+                }
+                _ => {}
+            },
+            Self::Factorial(expr) => match infer(expr)? {
+                Some(datatype) if datatype != Integer => {
+                    return error("take factorial of", &[&datatype])
+                }
+                _ => {}
+            },
+            Self::Matches(expr, pattern) => match infer(expr)? {
+                Some(datatype) if datatype != String => {
+                    let op = if pattern.case_insensitive { "ILIKE" } else { "LIKE" };
+                    return error(op, &[&datatype, &String]);
+                }
+                _ => {}
+            },
+            Self::In(lhs, list) => {
+                if let Some(lhs) = infer(lhs)? {
+                    for item in list {
+                        match infer(item)? {
+                            Some(item) if !comparable(&lhs, &item) => {
+                                return error("compare", &[&lhs, &item])
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Self::InSubquery(lhs, node) => {
+                node.check(catalog)?;
+                let rhs = node.columns(catalog)?.into_iter().next().and_then(|c| c.datatype);
+                if let (Some(lhs), Some(rhs)) = (infer(lhs)?, rhs) {
+                    if !comparable(&lhs, &rhs) {
+                        return error("compare", &[&lhs, &rhs]);
+                    }
+                }
+            }
+            Self::Exists(node) | Self::Subquery(node) => node.check(catalog)?,
+            Self::Case(operand, branches, _) => {
+                let operand = operand.as_deref().map(infer).transpose()?;
+                for (condition, _) in branches {
+                    match (&operand, infer(condition)?) {
+                        (Some(Some(operand)), Some(condition))
+                            if !comparable(operand, &condition) =>
+                        {
+                            return error("compare", &[operand, &condition]);
+                        }
+                        (None, Some(condition)) if condition != Boolean => {
+                            return Err(Error::Value(format!(
+                                "CASE condition must be boolean, got {} in {}",
+                                condition, self
+                            )));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     /// Walks the expression tree while calling a closure. Returns true as soon as the closure
     /// returns true. This is the inverse of walk().
     pub fn contains<F: Fn(&Expression) -> bool>(&self, visitor: &F) -> bool {
@@ -288,8 +742,10 @@ impl Expression {
             | Self::Equal(lhs, rhs)
             | Self::Exponentiate(lhs, rhs)
             | Self::GreaterThan(lhs, rhs)
+            | Self::ILike(lhs, rhs, _)
+            | Self::IsDistinctFrom(lhs, rhs)
             | Self::LessThan(lhs, rhs)
-            | Self::Like(lhs, rhs)
+            | Self::Like(lhs, rhs, _)
             | Self::Modulo(lhs, rhs)
             | Self::Multiply(lhs, rhs)
             | Self::Or(lhs, rhs)
@@ -299,12 +755,42 @@ impl Expression {
             }
 
             Self::Assert(expr)
+            | Self::Cast(expr, _)
             | Self::Factorial(expr)
+            | Self::InSubquery(expr, _)
             | Self::IsNull(expr)
+            | Self::Matches(expr, _)
             | Self::Negate(expr)
-            | Self::Not(expr) => Self::replace_with(expr, |e| e.transform(before, after))?,
+            | Self::Not(expr)
+            | Self::TryCast(expr, _) => Self::replace_with(expr, |e| e.transform(before, after))?,
+
+            Self::In(lhs, list) => {
+                Self::replace_with(lhs, |e| e.transform(before, after))?;
+                for item in list {
+                    Self::replace_with(item, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Function(_, args) => {
+                for arg in args {
+                    Self::replace_with(arg, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Case(operand, branches, r#else) => {
+                if let Some(operand) = operand {
+                    Self::replace_with(operand, |e| e.transform(before, after))?;
+                }
+                for (condition, result) in branches {
+                    Self::replace_with(condition, |e| e.transform(before, after))?;
+                    Self::replace_with(result, |e| e.transform(before, after))?;
+                }
+                if let Some(r#else) = r#else {
+                    Self::replace_with(r#else, |e| e.transform(before, after))?;
+                }
+            }
 
-            Self::Constant(_) | Self::Field(_, _) => {}
+            Self::Constant(_) | Self::Exists(_) | Self::Field(_, _) | Self::Subquery(_) => {}
         };
         after(self)
     }
@@ -319,20 +805,38 @@ impl Expression {
                 | Self::Equal(lhs, rhs)
                 | Self::Exponentiate(lhs, rhs)
                 | Self::GreaterThan(lhs, rhs)
+                | Self::ILike(lhs, rhs, _)
+                | Self::IsDistinctFrom(lhs, rhs)
                 | Self::LessThan(lhs, rhs)
-                | Self::Like(lhs, rhs)
+                | Self::Like(lhs, rhs, _)
                 | Self::Modulo(lhs, rhs)
                 | Self::Multiply(lhs, rhs)
                 | Self::Or(lhs, rhs)
                 | Self::Subtract(lhs, rhs) => lhs.walk(visitor) && rhs.walk(visitor),
 
                 Self::Assert(expr)
+                | Self::Cast(expr, _)
                 | Self::Factorial(expr)
+                | Self::InSubquery(expr, _)
                 | Self::IsNull(expr)
+                | Self::Matches(expr, _)
                 | Self::Negate(expr)
-                | Self::Not(expr) => expr.walk(visitor),
+                | Self::Not(expr)
+                | Self::TryCast(expr, _) => expr.walk(visitor),
+
+                Self::In(lhs, list) => lhs.walk(visitor) && list.iter().all(|e| e.walk(visitor)),
+
+                Self::Function(_, args) => args.iter().all(|arg| arg.walk(visitor)),
+
+                Self::Case(operand, branches, r#else) => {
+                    operand.iter().all(|operand| operand.walk(visitor))
+                        && branches.iter().all(|(condition, result)| {
+                            condition.walk(visitor) && result.walk(visitor)
+                        })
+                        && r#else.iter().all(|r#else| r#else.walk(visitor))
+                }
 
-                Self::Constant(_) | Self::Field(_, _) => true,
+                Self::Constant(_) | Self::Exists(_) | Self::Field(_, _) | Self::Subquery(_) => true,
             }
     }
 
@@ -464,21 +968,50 @@ impl Expression {
     }
 
     // Checks if the expression is a field lookup, and returns the list of values looked up.
-    // Expressions must be a combination of =, IS NULL, OR to be converted.
+    // Expressions must be a combination of =, IN, IS NULL, IS NOT DISTINCT FROM, OR to be
+    // converted.
     pub fn as_lookup(&self, field: usize) -> Option<Vec<Value>> {
         use Expression::*;
         // FIXME This should use a single match level, but since the child expressions are boxed
         // that would require box patterns, which are unstable.
         match &*self {
+            // NULL = NULL yields NULL rather than true, so it can't be used as a lookup - only
+            // IS NULL can look up NULL values.
             Equal(lhs, rhs) => match (&**lhs, &**rhs) {
-                (Field(i, _), Constant(v)) if i == &field => Some(vec![v.clone()]),
-                (Constant(v), Field(i, _)) if i == &field => Some(vec![v.clone()]),
+                (Field(i, _), Constant(v)) if i == &field && v != &Value::Null => {
+                    Some(vec![v.clone()])
+                }
+                (Constant(v), Field(i, _)) if i == &field && v != &Value::Null => {
+                    Some(vec![v.clone()])
+                }
                 (_, _) => None,
             },
+            // NULLs in an IN list never match, so they are skipped.
+            In(lhs, list) => match &**lhs {
+                Field(i, _) if i == &field => list
+                    .iter()
+                    .map(|e| match e {
+                        Constant(v) => Some(v.clone()),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .map(|values| values.into_iter().filter(|v| v != &Value::Null).collect()),
+                _ => None,
+            },
             IsNull(e) => match &**e {
                 Field(i, _) if i == &field => Some(vec![Value::Null]),
                 _ => None,
             },
+            // IS NOT DISTINCT FROM is null-safe equality, so it can also look up NULL values.
+            Not(e) => match &**e {
+                IsDistinctFrom(lhs, rhs) => match (&**lhs, &**rhs) {
+                    (Field(i, _), Constant(v)) | (Constant(v), Field(i, _)) if i == &field => {
+                        Some(vec![v.clone()])
+                    }
+                    (_, _) => None,
+                },
+                _ => None,
+            },
             Or(lhs, rhs) => match (lhs.as_lookup(field), rhs.as_lookup(field)) {
                 (Some(mut lvalues), Some(mut rvalues)) => {
                     lvalues.append(&mut rvalues);
@@ -490,6 +1023,57 @@ impl Expression {
         }
     }
 
+    // Checks if the expression is a range comparison (>, >=, < or <=) of a field with a non-NULL
+    // constant, and returns the lower and upper bounds of the range. >= and <= are built as an OR
+    // of > or < and = with the same constant, so these are converted to inclusive bounds.
+    pub fn as_range_lookup(&self, field: usize) -> Option<(Bound<Value>, Bound<Value>)> {
+        use Bound::*;
+        use Expression::*;
+        let range = |expr: &Expression| match expr {
+            GreaterThan(lhs, rhs) | LessThan(rhs, lhs) => match (&**lhs, &**rhs) {
+                (_, Constant(Value::Null)) | (Constant(Value::Null), _) => None,
+                (Field(i, _), Constant(v)) if i == &field => Some((Excluded(v.clone()), Unbounded)),
+                (Constant(v), Field(i, _)) if i == &field => Some((Unbounded, Excluded(v.clone()))),
+                (_, _) => None,
+            },
+            _ => None,
+        };
+        match self {
+            Or(lhs, rhs) => {
+                let (range, equal) = match (range(lhs), range(rhs)) {
+                    (Some(range), None) => (range, rhs),
+                    (None, Some(range)) => (range, lhs),
+                    (_, _) => return None,
+                };
+                let value = match &**equal {
+                    Equal(..) => match equal.as_lookup(field)?.as_slice() {
+                        [value] => value.clone(),
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                match range {
+                    (Excluded(v), Unbounded) if v == value => Some((Included(v), Unbounded)),
+                    (Unbounded, Excluded(v)) if v == value => Some((Unbounded, Included(v))),
+                    _ => None,
+                }
+            }
+            expr => range(expr),
+        }
+    }
+
+    // Checks if the expression is a LIKE pattern match on a field with a literal, case-sensitive
+    // prefix, and returns the prefix. Matching values must start with the prefix.
+    pub fn as_prefix_lookup(&self, field: usize) -> Option<String> {
+        match self {
+            Self::Matches(lhs, pattern) => match &**lhs {
+                Self::Field(i, _) if i == &field => pattern.prefix(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     // Creates an expression from a list of field lookup values.
     pub fn from_lookup(
         field: usize,
@@ -524,6 +1108,30 @@ impl Display for Expression {
             Self::Field(i, None) => format!("#{}", i),
             Self::Field(_, Some((None, name))) => name.to_string(),
             Self::Field(_, Some((Some(table), name))) => format!("{}.{}", table, name),
+            Self::Function(Function::Position, args) if args.len() == 2 => {
+                format!("position({} IN {})", args[0], args[1])
+            }
+            Self::Function(function, args) => format!(
+                "{}({})",
+                function,
+                args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::Case(operand, branches, r#else) => {
+                let mut s = "CASE".to_string();
+                if let Some(operand) = operand {
+                    s += &format!(" {}", operand);
+                }
+                for (condition, result) in branches {
+                    s += &format!(" WHEN {} THEN {}", condition, result);
+                }
+                if let Some(r#else) = r#else {
+                    s += &format!(" ELSE {}", r#else);
+                }
+                s + " END"
+            }
+            Self::Cast(expr, datatype) => format!("CAST({} AS {})", expr, datatype),
+            Self::TryCast(expr, datatype) => format!("TRY_CAST({} AS {})", expr, datatype),
+            Self::Subquery(_) => "(subquery)".to_string(),
 
             Self::And(lhs, rhs) => format!("{} AND {}", lhs, rhs),
             Self::Or(lhs, rhs) => format!("{} OR {}", lhs, rhs),
@@ -532,7 +1140,15 @@ impl Display for Expression {
             Self::Equal(lhs, rhs) => format!("{} = {}", lhs, rhs),
             Self::GreaterThan(lhs, rhs) => format!("{} > {}", lhs, rhs),
             Self::LessThan(lhs, rhs) => format!("{} < {}", lhs, rhs),
+            Self::IsDistinctFrom(lhs, rhs) => format!("{} IS DISTINCT FROM {}", lhs, rhs),
             Self::IsNull(expr) => format!("{} IS NULL", expr),
+            Self::In(lhs, list) => format!(
+                "{} IN ({})",
+                lhs,
+                list.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::Exists(_) => "EXISTS (subquery)".to_string(),
+            Self::InSubquery(lhs, _) => format!("{} IN (subquery)", lhs),
 
             Self::Add(lhs, rhs) => format!("{} + {}", lhs, rhs),
             Self::Assert(expr) => expr.to_string(),
@@ -544,8 +1160,143 @@ impl Display for Expression {
             Self::Negate(expr) => format!("-{}", expr),
             Self::Subtract(lhs, rhs) => format!("{} - {}", lhs, rhs),
 
-            Self::Like(lhs, rhs) => format!("{} LIKE {}", lhs, rhs),
+            Self::ILike(lhs, rhs, None) => format!("{} ILIKE {}", lhs, rhs),
+            Self::ILike(lhs, rhs, Some(escape)) => {
+                format!("{} ILIKE {} ESCAPE '{}'", lhs, rhs, escape)
+            }
+            Self::Like(lhs, rhs, None) => format!("{} LIKE {}", lhs, rhs),
+            Self::Like(lhs, rhs, Some(escape)) => {
+                format!("{} LIKE {} ESCAPE '{}'", lhs, rhs, escape)
+            }
+            Self::Matches(lhs, pattern) => format!("{} {}", lhs, pattern),
         };
         write!(f, "{}", s)
     }
 }
+
+/// A LIKE or ILIKE pattern, compiled to a regex. % matches any sequence of characters and _
+/// matches a single character. If an escape character is given, it makes the following character
+/// literal; otherwise, a doubled %% or __ matches a literal % or _. Patterns are serialized as
+/// their source, and recompiled when deserialized.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "(String, Option<char>, bool)", into = "(String, Option<char>, bool)")]
+pub struct Pattern {
+    pattern: String,
+    escape: Option<char>,
+    case_insensitive: bool,
+    regex: Regex,
+}
+
+impl Pattern {
+    /// Compiles a pattern, optionally with an escape character and case-insensitive matching.
+    pub fn new(pattern: String, escape: Option<char>, case_insensitive: bool) -> Result<Self> {
+        let mut regex = String::from(if case_insensitive { "^(?is:" } else { "^(?s:" });
+        for token in Self::tokenize(&pattern, escape)? {
+            match token {
+                PatternToken::Literal(c) => regex.push_str(&regex::escape(&c.to_string())),
+                PatternToken::Any => regex.push_str(".*"),
+                PatternToken::One => regex.push('.'),
+            }
+        }
+        regex.push_str(")$");
+        Ok(Self { regex: Regex::new(&regex)?, pattern, escape, case_insensitive })
+    }
+
+    /// Checks if a string matches the pattern.
+    pub fn is_match(&self, s: &str) -> bool {
+        self.regex.is_match(s)
+    }
+
+    /// Returns the literal prefix of a case-sensitive pattern, i.e. the characters before the
+    /// first wildcard, if any.
+    pub fn prefix(&self) -> Option<String> {
+        if self.case_insensitive {
+            return None;
+        }
+        let prefix = Self::tokenize(&self.pattern, self.escape)
+            .ok()?
+            .into_iter()
+            .map_while(|token| match token {
+                PatternToken::Literal(c) => Some(c),
+                PatternToken::Any | PatternToken::One => None,
+            })
+            .collect::<String>();
+        Some(prefix).filter(|p| !p.is_empty())
+    }
+
+    /// Splits a pattern into literal characters and wildcards.
+    fn tokenize(pattern: &str, escape: Option<char>) -> Result<Vec<PatternToken>> {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                c if Some(c) == escape => match chars.next() {
+                    Some(next) => PatternToken::Literal(next),
+                    None => {
+                        return Err(Error::Value(format!(
+                            "LIKE pattern '{}' can't end with escape character",
+                            pattern
+                        )))
+                    }
+                },
+                '%' | '_' if escape.is_none() && chars.peek() == Some(&c) => {
+                    chars.next();
+                    PatternToken::Literal(c)
+                }
+                '%' => PatternToken::Any,
+                '_' => PatternToken::One,
+                c => PatternToken::Literal(c),
+            })
+        }
+        Ok(tokens)
+    }
+}
+
+/// A token in a LIKE pattern.
+enum PatternToken {
+    Literal(char),
+    Any,
+    One,
+}
+
+impl TryFrom<(String, Option<char>, bool)> for Pattern {
+    type Error = Error;
+
+    fn try_from((pattern, escape, case_insensitive): (String, Option<char>, bool)) -> Result<Self> {
+        Self::new(pattern, escape, case_insensitive)
+    }
+}
+
+impl From<Pattern> for (String, Option<char>, bool) {
+    fn from(pattern: Pattern) -> Self {
+        (pattern.pattern, pattern.escape, pattern.case_insensitive)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+            && self.escape == other.escape
+            && self.case_insensitive == other.case_insensitive
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pattern")
+            .field("pattern", &self.pattern)
+            .field("escape", &self.escape)
+            .field("case_insensitive", &self.case_insensitive)
+            .finish()
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", if self.case_insensitive { "ILIKE" } else { "LIKE" }, self.pattern)?;
+        if let Some(escape) = self.escape {
+            write!(f, " ESCAPE '{}'", escape)?;
+        }
+        Ok(())
+    }
+}
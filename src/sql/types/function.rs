@@ -0,0 +1,333 @@
+//! Built-in scalar functions. String functions operate on characters rather than bytes, and
+//! character positions are 1-based, as in Postgres.
+//!
+//! Math functions return the type of their input, i.e. integers stay integers, except SQRT which
+//! always returns a float. POWER and MOD have the same semantics as the ^ and % operators. Integer
+//! overflow is an error, as for the arithmetic operators.
+//!
+//! COALESCE and NULLIF handle NULLs rather than propagating them. COALESCE only evaluates its
+//! arguments up to the first non-NULL value, see Expression::evaluate().
+use super::{self as types, DataType, Expression, Value};
+use crate::error::{Error, Result};
+
+use serde_derive::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+/// A built-in scalar function
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Function {
+    Abs,
+    Ceil,
+    Coalesce,
+    Concat,
+    Floor,
+    Length,
+    Lower,
+    LTrim,
+    Mod,
+    NullIf,
+    Position,
+    Power,
+    Round,
+    RTrim,
+    Sign,
+    Sqrt,
+    Substring,
+    Trim,
+    Upper,
+}
+
+impl Function {
+    /// Looks up a function by its (lowercase) name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Self::Abs,
+            "ceil" | "ceiling" => Self::Ceil,
+            "coalesce" => Self::Coalesce,
+            "concat" => Self::Concat,
+            "floor" => Self::Floor,
+            "length" => Self::Length,
+            "lower" => Self::Lower,
+            "ltrim" => Self::LTrim,
+            "mod" => Self::Mod,
+            "nullif" => Self::NullIf,
+            "position" => Self::Position,
+            "pow" | "power" => Self::Power,
+            "round" => Self::Round,
+            "rtrim" => Self::RTrim,
+            "sign" => Self::Sign,
+            "sqrt" => Self::Sqrt,
+            "substr" | "substring" => Self::Substring,
+            "trim" => Self::Trim,
+            "upper" => Self::Upper,
+            _ => return None,
+        })
+    }
+
+    /// Returns the minimum and maximum number of arguments, if bounded.
+    fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Coalesce | Self::Concat => (1, None),
+            Self::Abs | Self::Ceil | Self::Floor | Self::Sign | Self::Sqrt => (1, Some(1)),
+            Self::Length | Self::Lower | Self::Upper => (1, Some(1)),
+            Self::Mod | Self::NullIf | Self::Position | Self::Power => (2, Some(2)),
+            Self::Round => (1, Some(2)),
+            Self::Substring => (2, Some(3)),
+            Self::LTrim | Self::RTrim | Self::Trim => (1, Some(2)),
+        }
+    }
+
+    /// Checks that the function can be called with the given number of arguments.
+    pub fn check_args(&self, count: usize) -> Result<()> {
+        let expect = match self.arity() {
+            (min, Some(max)) if count < min || count > max => {
+                if min == max {
+                    format!("{}", min)
+                } else {
+                    format!("{} to {}", min, max)
+                }
+            }
+            (min, None) if count < min => format!("at least {}", min),
+            _ => return Ok(()),
+        };
+        let plural = if expect.ends_with(" 1") || expect == "1" { "" } else { "s" };
+        Err(Error::Value(format!(
+            "Function {} takes {} argument{}, got {}",
+            self, expect, plural, count
+        )))
+    }
+
+    /// Evaluates the function. Any NULL argument yields NULL, except for CONCAT which skips
+    /// NULL arguments, COALESCE which returns the first non-NULL argument, and NULLIF which
+    /// returns its first argument unless it equals the second.
+    pub fn evaluate(&self, args: Vec<Value>) -> Result<Value> {
+        use Value::*;
+        let null_aware = matches!(self, Self::Coalesce | Self::Concat | Self::NullIf);
+        if !null_aware && args.contains(&Null) {
+            return Ok(Null);
+        }
+        let overflow = || Error::Value("Integer overflow".into());
+        Ok(match (self, args.as_slice()) {
+            (Self::Abs, [Integer(i)]) => Integer(i.checked_abs().ok_or_else(overflow)?),
+            (Self::Abs, [Float(f)]) => Float(f.abs()),
+            (Self::Abs, [Decimal(d)]) => {
+                Decimal(types::Decimal::new(d.mantissa().abs(), d.scale())?)
+            }
+            (Self::Ceil, [Integer(i)]) => Integer(*i),
+            (Self::Ceil, [Float(f)]) => Float(f.ceil()),
+            (Self::Ceil, [Decimal(d)]) => {
+                let (quotient, remainder) = split_decimal(*d);
+                Decimal(types::Decimal::new(quotient + (remainder > 0) as i128, 0)?)
+            }
+            (Self::Coalesce, args) => args.iter().find(|v| **v != Null).cloned().unwrap_or(Null),
+            (Self::Concat, args) => {
+                String(args.iter().filter(|v| **v != Null).map(|v| v.to_string()).collect())
+            }
+            (Self::Floor, [Integer(i)]) => Integer(*i),
+            (Self::Floor, [Float(f)]) => Float(f.floor()),
+            (Self::Floor, [Decimal(d)]) => {
+                let (quotient, remainder) = split_decimal(*d);
+                Decimal(types::Decimal::new(quotient - (remainder < 0) as i128, 0)?)
+            }
+            (Self::Length, [String(s)]) => Integer(s.chars().count() as i64),
+            (Self::Length, [Bytes(b)]) => Integer(b.len() as i64),
+            (Self::Lower, [String(s)]) => String(s.to_lowercase()),
+            (Self::LTrim, [String(s)]) => String(s.trim_start_matches(' ').into()),
+            (Self::LTrim, [String(s), String(chars)]) => {
+                String(s.trim_start_matches(|c| chars.contains(c)).into())
+            }
+            (Self::Mod, [lhs, rhs]) => Expression::Modulo(
+                Box::new(Expression::Constant(lhs.clone())),
+                Box::new(Expression::Constant(rhs.clone())),
+            )
+            .evaluate(None)?,
+            (Self::NullIf, [lhs, rhs]) => match Expression::Equal(
+                Box::new(Expression::Constant(lhs.clone())),
+                Box::new(Expression::Constant(rhs.clone())),
+            )
+            .evaluate(None)?
+            {
+                Boolean(true) => Null,
+                _ => lhs.clone(),
+            },
+            (Self::Position, [String(substring), String(s)]) => Integer(match s.find(substring) {
+                Some(i) => s[..i].chars().count() as i64 + 1,
+                None => 0,
+            }),
+            (Self::Power, [lhs, rhs]) => Expression::Exponentiate(
+                Box::new(Expression::Constant(lhs.clone())),
+                Box::new(Expression::Constant(rhs.clone())),
+            )
+            .evaluate(None)?,
+            (Self::Round, [Integer(i)]) => Integer(*i),
+            (Self::Round, [Integer(i), Integer(digits)]) if *digits >= 0 => Integer(*i),
+            (Self::Round, [Integer(i), Integer(digits)]) => {
+                let n = u32::try_from(-digits).unwrap_or(u32::MAX);
+                let rounded = round_pow10(*i as i128, n)
+                    .checked_mul(pow10(n))
+                    .and_then(|i| i64::try_from(i).ok())
+                    .ok_or_else(overflow)?;
+                Integer(rounded)
+            }
+            (Self::Round, [Float(f)]) => Float(f.round()),
+            (Self::Round, [Float(f), Integer(digits)]) => {
+                let p = 10_f64.powi((*digits).max(-308).min(308) as i32);
+                let rounded = (f * p).round() / p;
+                Float(if rounded.is_finite() { rounded } else { *f })
+            }
+            (Self::Round, [Decimal(d)]) => Decimal(d.round(0)?),
+            (Self::Round, [Decimal(d), Integer(digits)]) if *digits >= 0 => {
+                Decimal(d.round((*digits).min(d.scale() as i64) as u8)?)
+            }
+            (Self::Round, [Decimal(d), Integer(digits)]) => {
+                let n = u32::try_from(-digits).unwrap_or(u32::MAX);
+                let quotient = round_pow10(d.mantissa(), n.saturating_add(d.scale() as u32));
+                let mantissa = quotient
+                    .checked_mul(pow10(n))
+                    .ok_or_else(|| Error::Value("Decimal overflow".into()))?;
+                Decimal(types::Decimal::new(mantissa, 0)?)
+            }
+            (Self::RTrim, [String(s)]) => String(s.trim_end_matches(' ').into()),
+            (Self::RTrim, [String(s), String(chars)]) => {
+                String(s.trim_end_matches(|c| chars.contains(c)).into())
+            }
+            (Self::Sign, [Integer(i)]) => Integer(i.signum()),
+            (Self::Sign, [Float(f)]) if *f == 0.0 || f.is_nan() => Float(*f),
+            (Self::Sign, [Float(f)]) => Float(f.signum()),
+            (Self::Sign, [Decimal(d)]) => {
+                Decimal(types::Decimal::from(d.mantissa().signum() as i64))
+            }
+            (Self::Sqrt, [Integer(i)]) => sqrt(*i as f64)?,
+            (Self::Sqrt, [Float(f)]) => sqrt(*f)?,
+            (Self::Sqrt, [Decimal(d)]) => sqrt(d.to_f64())?,
+            (Self::Substring, [String(s), Integer(start)]) => String(substring(s, *start, None)?),
+            (Self::Substring, [String(s), Integer(start), Integer(count)]) => {
+                String(substring(s, *start, Some(*count))?)
+            }
+            (Self::Trim, [String(s)]) => String(s.trim_matches(' ').into()),
+            (Self::Trim, [String(s), String(chars)]) => {
+                String(s.trim_matches(|c| chars.contains(c)).into())
+            }
+            (Self::Upper, [String(s)]) => String(s.to_uppercase()),
+            (_, args) => {
+                return Err(Error::Value(format!(
+                    "Can't take {} of {}",
+                    self,
+                    args.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+                )))
+            }
+        })
+    }
+
+    /// Infers the datatype of the function's result, given the datatypes of its arguments (None
+    /// if unknown, e.g. NULL). Returns None if it can't be inferred.
+    pub fn datatype(&self, args: &[Option<DataType>]) -> Option<DataType> {
+        use DataType::*;
+        let first = args.first().cloned().flatten();
+        match self {
+            Self::Abs => first,
+            Self::Ceil | Self::Floor => match first {
+                Some(Decimal(precision, _)) => Some(Decimal(precision, 0)),
+                datatype => datatype,
+            },
+            Self::Coalesce => args.iter().find_map(|datatype| datatype.clone()),
+            Self::Concat | Self::Lower | Self::LTrim | Self::RTrim | Self::Trim | Self::Upper => {
+                Some(String)
+            }
+            Self::Substring => Some(String),
+            Self::Length | Self::Position => Some(Integer),
+            Self::Mod => {
+                Expression::arithmetic_datatype(first, args.get(1).cloned().flatten(), |l, r| {
+                    l.max(r)
+                })
+            }
+            Self::NullIf => first,
+            Self::Power => Expression::exponentiate_datatype(first, args.get(1).cloned().flatten()),
+            Self::Round => match first {
+                Some(Decimal(precision, _)) if args.len() == 1 => Some(Decimal(precision, 0)),
+                datatype => datatype,
+            },
+            Self::Sign => match first {
+                Some(Decimal(..)) => Some(Decimal(1, 0)),
+                datatype => datatype,
+            },
+            Self::Sqrt => Some(Float),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Abs => "abs",
+            Self::Ceil => "ceil",
+            Self::Coalesce => "coalesce",
+            Self::Concat => "concat",
+            Self::Floor => "floor",
+            Self::Length => "length",
+            Self::Lower => "lower",
+            Self::LTrim => "ltrim",
+            Self::Mod => "mod",
+            Self::NullIf => "nullif",
+            Self::Position => "position",
+            Self::Power => "power",
+            Self::Round => "round",
+            Self::RTrim => "rtrim",
+            Self::Sign => "sign",
+            Self::Sqrt => "sqrt",
+            Self::Substring => "substring",
+            Self::Trim => "trim",
+            Self::Upper => "upper",
+        })
+    }
+}
+
+/// Returns the characters of a string starting at the 1-based position start, up to count
+/// characters if given. Positions outside of the string are ignored, such that a start before
+/// the string shortens the result and a start past the end yields an empty string.
+fn substring(s: &str, start: i64, count: Option<i64>) -> Result<String> {
+    let end = match count {
+        Some(count) if count < 0 => {
+            return Err(Error::Value(format!("Substring length can't be negative, got {}", count)))
+        }
+        Some(count) => start.saturating_add(count),
+        None => i64::MAX,
+    };
+    let start = start.max(1);
+    if end <= start {
+        return Ok(String::new());
+    }
+    Ok(s.chars().skip((start - 1) as usize).take((end - start) as usize).collect())
+}
+
+/// Returns 10^n, saturating at i128::MAX. Callers only multiply it by a value rounded via
+/// round_pow10(), which is 0 whenever 10^n doesn't fit.
+fn pow10(n: u32) -> i128 {
+    10_i128.checked_pow(n).unwrap_or(i128::MAX)
+}
+
+/// Divides an integer by 10^n, rounding half away from zero.
+fn round_pow10(i: i128, n: u32) -> i128 {
+    match 10_i128.checked_pow(n) {
+        Some(p) if (i % p).unsigned_abs() * 2 >= p as u128 => i / p + i.signum(),
+        Some(p) => i / p,
+        None => 0,
+    }
+}
+
+/// Splits a decimal into its integral part and the mantissa of its fractional part, both
+/// truncated towards zero.
+fn split_decimal(d: types::Decimal) -> (i128, i128) {
+    // Scales are at most 38, so the divisor always fits in an i128.
+    let p = 10_i128.pow(d.scale() as u32);
+    (d.mantissa() / p, d.mantissa() % p)
+}
+
+/// Takes the square root of a number, erroring if it is negative.
+fn sqrt(f: f64) -> Result<Value> {
+    if f < 0.0 {
+        return Err(Error::Value("Can't take square root of negative number".into()));
+    }
+    Ok(Value::Float(f.sqrt()))
+}
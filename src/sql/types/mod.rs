@@ -1,5 +1,13 @@
+mod datetime;
+mod decimal;
 mod expression;
-pub use expression::Expression;
+mod function;
+pub use datetime::{Date, Time, Timestamp};
+pub use decimal::{
+    Decimal, DIVISION_SCALE as DECIMAL_DIVISION_SCALE, MAX_PRECISION as DECIMAL_MAX_PRECISION,
+};
+pub use expression::{Expression, Pattern};
+pub use function::Function;
 
 use crate::error::{Error, Result};
 
@@ -15,16 +23,27 @@ pub enum DataType {
     Integer,
     Float,
     String,
+    Date,
+    Time,
+    Timestamp,
+    /// A fixed-point decimal with the given precision (total digits) and scale (fractional digits)
+    Decimal(u8, u8),
+    Bytes,
 }
 
 impl std::fmt::Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.write_str(match self {
-            Self::Boolean => "BOOLEAN",
-            Self::Integer => "INTEGER",
-            Self::Float => "FLOAT",
-            Self::String => "STRING",
-        })
+        match self {
+            Self::Boolean => f.write_str("BOOLEAN"),
+            Self::Integer => f.write_str("INTEGER"),
+            Self::Float => f.write_str("FLOAT"),
+            Self::String => f.write_str("STRING"),
+            Self::Date => f.write_str("DATE"),
+            Self::Time => f.write_str("TIME"),
+            Self::Timestamp => f.write_str("TIMESTAMP"),
+            Self::Decimal(precision, scale) => write!(f, "DECIMAL({},{})", precision, scale),
+            Self::Bytes => f.write_str("BYTEA"),
+        }
     }
 }
 
@@ -36,6 +55,11 @@ pub enum Value {
     Integer(i64),
     Float(f64),
     String(String),
+    Date(Date),
+    Time(Time),
+    Timestamp(Timestamp),
+    Decimal(Decimal),
+    Bytes(Vec<u8>),
 }
 
 impl std::cmp::Eq for Value {}
@@ -43,13 +67,19 @@ impl std::cmp::Eq for Value {}
 #[allow(clippy::derive_hash_xor_eq)]
 impl Hash for Value {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.datatype().hash(state);
+        // Decimal datatypes depend on the value's scale, but equal decimals must hash equally.
+        std::mem::discriminant(self).hash(state);
         match self {
-            Value::Null => self.hash(state),
+            Value::Null => {}
             Value::Boolean(v) => v.hash(state),
             Value::Integer(v) => v.hash(state),
             Value::Float(v) => v.to_be_bytes().hash(state),
             Value::String(v) => v.hash(state),
+            Value::Date(v) => v.hash(state),
+            Value::Time(v) => v.hash(state),
+            Value::Timestamp(v) => v.hash(state),
+            Value::Decimal(v) => v.hash(state),
+            Value::Bytes(v) => v.hash(state),
         }
     }
 }
@@ -75,9 +105,91 @@ impl Value {
             Self::Integer(_) => Some(DataType::Integer),
             Self::Float(_) => Some(DataType::Float),
             Self::String(_) => Some(DataType::String),
+            Self::Date(_) => Some(DataType::Date),
+            Self::Time(_) => Some(DataType::Time),
+            Self::Timestamp(_) => Some(DataType::Timestamp),
+            Self::Decimal(d) => Some(DataType::Decimal(d.precision(), d.scale())),
+            Self::Bytes(_) => Some(DataType::Bytes),
         }
     }
 
+    /// Casts the value to the given datatype, or errors if the cast is not possible. NULL casts to
+    /// NULL. All values cast to STRING using their canonical format, as output by Display, and
+    /// date, time, and binary values can be cast back from it. Numbers cast to DECIMAL are rounded
+    /// half away from zero to its scale, and error if they exceed its precision. Floats use their
+    /// shortest exact decimal representation, e.g. 0.1 is exactly 0.1. Decimals and floats cast to
+    /// INTEGER are truncated toward zero, and error if out of range, and decimals cast to FLOAT
+    /// are rounded to the nearest float. Strings cast to numbers and booleans are parsed, ignoring
+    /// surrounding whitespace. Booleans cast to INTEGER as 1 or 0, and integers cast to BOOLEAN
+    /// are true unless 0.
+    pub fn cast(self, datatype: &DataType) -> Result<Self> {
+        Ok(match (self, datatype) {
+            (Self::Null, _) => Self::Null,
+            (Self::Integer(i), DataType::Decimal(..)) => Self::Decimal(i.into()).cast(datatype)?,
+            (Self::Float(f), DataType::Decimal(..)) => {
+                Self::Decimal(Decimal::from_f64(f)?).cast(datatype)?
+            }
+            (Self::String(s), DataType::Decimal(..)) => {
+                Self::Decimal(s.trim().parse()?).cast(datatype)?
+            }
+            (Self::Decimal(d), DataType::Decimal(precision, scale)) => match d.round(*scale) {
+                Ok(rounded) if rounded.precision() <= *precision => Self::Decimal(rounded),
+                _ => {
+                    return Err(Error::Value(format!("Value {} out of range for {}", d, datatype)))
+                }
+            },
+            (Self::Decimal(d), DataType::Integer) => match d.to_i64() {
+                Ok(i) => Self::Integer(i),
+                Err(_) => {
+                    return Err(Error::Value(format!("Value {} out of range for {}", d, datatype)))
+                }
+            },
+            (Self::Decimal(d), DataType::Float) => Self::Float(d.to_f64()),
+            (Self::Decimal(d), DataType::String) => Self::String(d.to_string()),
+            (v, dt) if v.datatype().as_ref() == Some(dt) => v,
+            (Self::String(s), DataType::Date) => Self::Date(s.parse()?),
+            (Self::String(s), DataType::Time) => Self::Time(s.parse()?),
+            (Self::String(s), DataType::Timestamp) => Self::Timestamp(s.parse()?),
+            (Self::Date(d), DataType::Timestamp) => Self::Timestamp(d.into()),
+            (Self::Timestamp(t), DataType::Date) => Self::Date(t.date()),
+            (Self::Timestamp(t), DataType::Time) => Self::Time(t.time()),
+            (Self::Boolean(b), DataType::Integer) => Self::Integer(b as i64),
+            (Self::Integer(i), DataType::Boolean) => Self::Boolean(i != 0),
+            (Self::Integer(i), DataType::Float) => Self::Float(i as f64),
+            (Self::Float(f), DataType::Integer) => match f.trunc() {
+                r if r >= i64::MIN as f64 && r < i64::MAX as f64 => Self::Integer(r as i64),
+                _ => {
+                    return Err(Error::Value(format!("Value {} out of range for {}", f, datatype)))
+                }
+            },
+            (Self::String(s), DataType::Boolean) => match s.trim().to_lowercase().as_ref() {
+                "true" | "t" | "yes" | "y" | "on" | "1" => Self::Boolean(true),
+                "false" | "f" | "no" | "n" | "off" | "0" => Self::Boolean(false),
+                _ => return Err(Error::Value(format!("Can't cast {} to {}", s, datatype))),
+            },
+            (Self::String(s), DataType::Integer) => match s.trim().parse() {
+                Ok(i) => Self::Integer(i),
+                Err(_) => return Err(Error::Value(format!("Can't cast {} to {}", s, datatype))),
+            },
+            (Self::String(s), DataType::Float) => match s.trim().parse() {
+                Ok(f) => Self::Float(f),
+                Err(_) => return Err(Error::Value(format!("Can't cast {} to {}", s, datatype))),
+            },
+            (Self::String(s), DataType::Bytes) => match parse_bytes(s.trim()) {
+                Some(b) => Self::Bytes(b),
+                None => return Err(Error::Value(format!("Can't cast {} to {}", s, datatype))),
+            },
+            (v @ Self::Boolean(_), DataType::String)
+            | (v @ Self::Integer(_), DataType::String)
+            | (v @ Self::Float(_), DataType::String)
+            | (v @ Self::Date(_), DataType::String)
+            | (v @ Self::Time(_), DataType::String)
+            | (v @ Self::Timestamp(_), DataType::String)
+            | (v @ Self::Bytes(_), DataType::String) => Self::String(v.to_string()),
+            (v, dt) => return Err(Error::Value(format!("Can't cast {} to {}", v, dt))),
+        })
+    }
+
     /// Returns the inner boolean, or an error if not a boolean
     pub fn boolean(self) -> Result<bool> {
         match self {
@@ -121,6 +233,13 @@ impl std::fmt::Display for Value {
                 Self::Integer(i) => i.to_string(),
                 Self::Float(f) => f.to_string(),
                 Self::String(s) => s.clone(),
+                Self::Date(d) => d.to_string(),
+                Self::Time(t) => t.to_string(),
+                Self::Timestamp(t) => t.to_string(),
+                Self::Decimal(d) => d.to_string(),
+                Self::Bytes(b) => {
+                    format!("x'{}'", b.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+                }
             }
             .as_ref(),
         )
@@ -135,21 +254,99 @@ impl PartialOrd for Value {
             (_, Self::Null) => Some(Ordering::Greater),
             (Self::Boolean(a), Self::Boolean(b)) => a.partial_cmp(b),
             (Self::Float(a), Self::Float(b)) => a.partial_cmp(b),
-            (Self::Float(a), Self::Integer(b)) => a.partial_cmp(&(*b as f64)),
-            (Self::Integer(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
+            (Self::Float(a), Self::Integer(b)) => {
+                compare_integer_float(*b, *a).map(Ordering::reverse)
+            }
+            (Self::Integer(a), Self::Float(b)) => compare_integer_float(*a, *b),
             (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
             (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::Date(a), Self::Date(b)) => a.partial_cmp(b),
+            (Self::Time(a), Self::Time(b)) => a.partial_cmp(b),
+            (Self::Timestamp(a), Self::Timestamp(b)) => a.partial_cmp(b),
+            (Self::Decimal(a), Self::Decimal(b)) => a.partial_cmp(b),
+            (Self::Decimal(a), Self::Integer(b)) => a.partial_cmp(&Decimal::from(*b)),
+            (Self::Integer(a), Self::Decimal(b)) => Decimal::from(*a).partial_cmp(b),
+            (Self::Decimal(a), Self::Float(b)) => a.to_f64().partial_cmp(b),
+            (Self::Float(a), Self::Decimal(b)) => a.partial_cmp(&b.to_f64()),
+            (Self::Bytes(a), Self::Bytes(b)) => a.partial_cmp(b),
             (_, _) => None,
         }
     }
 }
 
+// Values of different numeric datatypes can be compared and combined as follows, while numbers
+// and strings are never implicitly converted to each other (an explicit CAST is required):
+//
+//           | INTEGER            | FLOAT              | DECIMAL
+// ----------+--------------------+--------------------+-------------------
+//  INTEGER  | INTEGER            | exact, FLOAT       | DECIMAL
+//  FLOAT    | exact, FLOAT       | FLOAT              | FLOAT
+//  DECIMAL  | DECIMAL            | FLOAT              | DECIMAL
+//
+// Each cell gives how the operands are compared (exact or as the result type), and the result
+// type of arithmetic. INTEGER and FLOAT compare exactly (see compare_integer_float), but
+// arithmetic converts the integer to the nearest float, which is exact up to ±2^53.
+
+/// Compares an integer and a float exactly, rather than converting the integer to a float, which
+/// rounds integers beyond ±2^53 (e.g. 2^53 + 1 would equal 2^53 as a float). Returns None if the
+/// float is NaN.
+pub fn compare_integer_float(i: i64, f: f64) -> Option<Ordering> {
+    if f.is_nan() {
+        return None;
+    }
+    // Floats outside of [-2^63, 2^63) are beyond any integer, otherwise their integral part
+    // converts exactly to an integer.
+    if f >= -(i64::MIN as f64) {
+        return Some(Ordering::Less);
+    } else if f < i64::MIN as f64 {
+        return Some(Ordering::Greater);
+    }
+    let trunc = f.trunc();
+    match i.cmp(&(trunc as i64)) {
+        Ordering::Equal => 0.0.partial_cmp(&(f - trunc)),
+        ordering => Some(ordering),
+    }
+}
+
+/// Parses a binary value from its canonical string format, i.e. a hex string literal like
+/// x'00ff'. Returns None if the string isn't a valid hex string literal.
+fn parse_bytes(s: &str) -> Option<Vec<u8>> {
+    let hex = s.strip_prefix(&['x', 'X'][..])?.strip_prefix('\'')?.strip_suffix('\'')?;
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+/// Converts a float to an integer if it is integral and within range, i.e. without any loss.
+pub fn float_to_integer(f: f64) -> Option<i64> {
+    if f.fract() == 0.0 && f >= i64::MIN as f64 && f < -(i64::MIN as f64) {
+        Some(f as i64)
+    } else {
+        None
+    }
+}
+
+/// Converts an integer to a float if it is exactly representable as one, i.e. without rounding.
+pub fn integer_to_float(i: i64) -> Option<f64> {
+    match compare_integer_float(i, i as f64) {
+        Some(Ordering::Equal) => Some(i as f64),
+        _ => None,
+    }
+}
+
 impl From<bool> for Value {
     fn from(v: bool) -> Self {
         Value::Boolean(v)
     }
 }
 
+impl From<Decimal> for Value {
+    fn from(v: Decimal) -> Self {
+        Value::Decimal(v)
+    }
+}
+
 impl From<f64> for Value {
     fn from(v: f64) -> Self {
         Value::Float(v)
@@ -162,6 +359,12 @@ impl From<i64> for Value {
     }
 }
 
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        Value::Bytes(v)
+    }
+}
+
 impl From<String> for Value {
     fn from(v: String) -> Self {
         Value::String(v)
@@ -177,13 +380,121 @@ impl From<&str> for Value {
 /// A row of values
 pub type Row = Vec<Value>;
 
-/// A row iterator
-pub type Rows = Box<dyn Iterator<Item = Result<Row>> + Send>;
+/// A batch of rows
+pub type Batch = Vec<Row>;
+
+/// The default number of rows per batch, see Rows::chunked().
+pub const BATCH_SIZE: usize = 1024;
+
+/// A row iterator. Executors pass rows to each other in batches, which amortizes the cost of the
+/// dynamic dispatch and allocations per call, and process them in tight loops via next_batch().
+/// Rows can also be iterated one at a time, e.g. to stream them to a client.
+pub struct Rows {
+    source: RowSource,
+    /// The remaining rows of the current batch, when iterating rows one at a time
+    batch: std::vec::IntoIter<Row>,
+}
+
+/// The source of a row iterator
+enum RowSource {
+    Rows(Box<dyn Iterator<Item = Result<Row>> + Send>),
+    Batches(Box<dyn Iterator<Item = Result<Batch>> + Send>),
+}
+
+impl Rows {
+    /// Creates a row iterator from individual rows. Batches contain a single row, such that rows
+    /// are only fetched from the source as they are needed.
+    pub fn new<I: Iterator<Item = Result<Row>> + Send + 'static>(rows: I) -> Self {
+        Self { source: RowSource::Rows(Box::new(rows)), batch: Vec::new().into_iter() }
+    }
+
+    /// Creates a row iterator from batches of rows. Empty batches are skipped.
+    pub fn from_batches<I: Iterator<Item = Result<Batch>> + Send + 'static>(batches: I) -> Self {
+        Self { source: RowSource::Batches(Box::new(batches)), batch: Vec::new().into_iter() }
+    }
+
+    /// Creates a row iterator that fetches batches of up to size rows at a time from the given
+    /// rows, e.g. from a table scan.
+    pub fn chunked<I: Iterator<Item = Result<Row>> + Send + 'static>(rows: I, size: usize) -> Self {
+        let mut rows = rows.fuse();
+        Self::from_batches(std::iter::from_fn(move || {
+            let mut batch = Vec::with_capacity(size.min(BATCH_SIZE));
+            for row in rows.by_ref().take(size.max(1)) {
+                batch.push(match row {
+                    Ok(row) => row,
+                    Err(err) => return Some(Err(err)),
+                });
+            }
+            if batch.is_empty() {
+                None
+            } else {
+                Some(Ok(batch))
+            }
+        }))
+    }
+
+    /// Creates an empty row iterator.
+    pub fn empty() -> Self {
+        Self::from_batches(std::iter::empty())
+    }
+
+    /// Returns the next non-empty batch of rows, if any.
+    pub fn next_batch(&mut self) -> Option<Result<Batch>> {
+        if !self.batch.as_slice().is_empty() {
+            return Some(Ok(std::mem::take(&mut self.batch).collect()));
+        }
+        match &mut self.source {
+            RowSource::Rows(rows) => rows.next().map(|r| r.map(|row| vec![row])),
+            RowSource::Batches(batches) => loop {
+                match batches.next()? {
+                    Ok(batch) if batch.is_empty() => continue,
+                    result => return Some(result),
+                }
+            },
+        }
+    }
+
+    /// Transforms each batch of rows with the given function, e.g. to filter or project them.
+    /// The function is called as batches are fetched.
+    pub fn map_batches<F>(mut self, mut f: F) -> Self
+    where
+        F: FnMut(Batch) -> Result<Batch> + Send + 'static,
+    {
+        Self::from_batches(std::iter::from_fn(move || Some(self.next_batch()?.and_then(&mut f))))
+    }
+}
+
+impl Iterator for Rows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(row) = self.batch.next() {
+            return Some(Ok(row));
+        }
+        match &mut self.source {
+            RowSource::Rows(rows) => rows.next(),
+            RowSource::Batches(batches) => loop {
+                match batches.next()? {
+                    Ok(batch) => {
+                        self.batch = batch.into_iter();
+                        if let Some(row) = self.batch.next() {
+                            return Some(Ok(row));
+                        }
+                    }
+                    Err(err) => return Some(Err(err)),
+                }
+            },
+        }
+    }
+}
 
 /// A column (in a result set, see schema::Column for table columns)
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     pub name: Option<String>,
+    /// The datatype of the column's values, as inferred from the query plan, or None if unknown
+    /// (e.g. for NULL constants)
+    pub datatype: Option<DataType>,
 }
 
 /// A set of columns
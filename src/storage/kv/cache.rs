@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// A least-recently-used cache of keys to values, ordered by a monotonically increasing access
+/// sequence number. Entries beyond the given capacity (in number of keys) are evicted, and a zero
+/// capacity disables the cache. Used by the MVCC store to cache recently read keys, see
+/// MVCC::with_cache().
+pub(super) struct Lru<V: Clone> {
+    capacity: usize,
+    entries: HashMap<Vec<u8>, (V, u64)>,
+    order: BTreeMap<u64, Vec<u8>>,
+    next_seq: u64,
+}
+
+impl<V: Clone> Lru<V> {
+    /// Creates a new LRU cache with the given capacity.
+    pub(super) fn new(capacity: usize) -> Self {
+        Self { capacity, entries: HashMap::new(), order: BTreeMap::new(), next_seq: 0 }
+    }
+
+    /// Fetches a cached value, marking it as recently used. Returns None if the key isn't cached.
+    pub(super) fn get(&mut self, key: &[u8]) -> Option<V> {
+        let seq = self.next_seq;
+        let (value, entry_seq) = self.entries.get_mut(key)?;
+        let key = self.order.remove(entry_seq)?;
+        *entry_seq = seq;
+        let value = value.clone();
+        self.order.insert(seq, key);
+        self.next_seq += 1;
+        Some(value)
+    }
+
+    /// Caches a value as recently used, evicting the least recently used entries beyond the
+    /// capacity.
+    pub(super) fn insert(&mut self, key: Vec<u8>, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some((_, old_seq)) = self.entries.insert(key.clone(), (value, seq)) {
+            self.order.remove(&old_seq);
+        }
+        self.order.insert(seq, key);
+        while self.entries.len() > self.capacity {
+            let (_, key) = match self.order.iter().next() {
+                Some((&seq, _)) => self.order.remove_entry(&seq).unwrap(),
+                None => break,
+            };
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Updates the value of a cached key after a write, if it's cached.
+    pub(super) fn update(&mut self, key: &[u8], value: V) {
+        if let Some((cached, _)) = self.entries.get_mut(key) {
+            *cached = value;
+        }
+    }
+
+    /// Removes all cached entries.
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // Cached values are returned until updated, and writes don't cache uncached keys.
+    fn get_update() {
+        let mut lru = Lru::new(10);
+        assert_eq!(lru.get(b"a"), None);
+        lru.insert(b"a".to_vec(), Some(vec![0x01]));
+        lru.insert(b"b".to_vec(), None);
+        assert_eq!(lru.get(b"a"), Some(Some(vec![0x01])));
+        assert_eq!(lru.get(b"b"), Some(None));
+
+        lru.update(b"a", Some(vec![0x02]));
+        lru.update(b"c", Some(vec![0x03]));
+        assert_eq!(lru.get(b"a"), Some(Some(vec![0x02])));
+        assert_eq!(lru.get(b"c"), None);
+
+        lru.clear();
+        assert_eq!(lru.get(b"a"), None);
+        assert_eq!(lru.get(b"b"), None);
+    }
+
+    #[test]
+    // The least recently used keys are evicted beyond the capacity, and a zero capacity disables
+    // the cache.
+    fn evict() {
+        let mut lru = Lru::new(2);
+        lru.insert(b"a".to_vec(), 1);
+        lru.insert(b"b".to_vec(), 2);
+        lru.get(b"a");
+        lru.insert(b"c".to_vec(), 3); // evicts b
+        assert_eq!(lru.get(b"a"), Some(1));
+        assert_eq!(lru.get(b"b"), None);
+        assert_eq!(lru.get(b"c"), Some(3));
+        lru.insert(b"b".to_vec(), 2); // evicts a
+        assert_eq!(lru.get(b"a"), None);
+        assert_eq!(lru.get(b"b"), Some(2));
+
+        let mut lru = Lru::new(0);
+        lru.insert(b"a".to_vec(), 1);
+        assert_eq!(lru.get(b"a"), None);
+    }
+}
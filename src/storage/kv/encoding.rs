@@ -7,9 +7,16 @@
 //! i64:     Big-endian binary representation, with sign bit flipped.
 //! f64:     Big-endian binary representation, with sign bit flipped if +, all flipped if -.
 //! Value:   Like above, with type prefix 0x00=Null 0x01=Boolean 0x02=Float 0x03=Integer 0x04=String
+//!          0x05=Date 0x06=Time 0x07=Timestamp 0x08=Decimal 0x09=Bytes, where dates and times
+//!          are encoded as i64.
+//! Decimal: Sign byte 0x00=negative 0x01=zero 0x02=positive. Non-zero decimals continue with the
+//!          exponent e as a byte biased by 0x80, then the significant digits as bytes 0x01-0x0a
+//!          terminated by 0x00, such that value = 0.digits * 10^e. Trailing zeroes are removed, so
+//!          numerically equal decimals encode identically. All bytes after the sign are inverted
+//!          for negative decimals.
 
 use crate::error::{Error, Result};
-use crate::sql::types::Value;
+use crate::sql::types::{Date, Decimal, Time, Timestamp, Value};
 
 use std::convert::TryInto;
 
@@ -142,6 +149,64 @@ pub fn take_i64(bytes: &mut &[u8]) -> Result<i64> {
     Ok(n)
 }
 
+/// Encodes a decimal, in an order-preserving variable-length format. See module docs for details.
+pub fn encode_decimal(decimal: Decimal) -> Vec<u8> {
+    let mantissa = decimal.mantissa();
+    if mantissa == 0 {
+        return vec![0x01];
+    }
+    let digits = mantissa.unsigned_abs().to_string();
+    let significant = digits.trim_end_matches('0');
+    let exponent = digits.len() as i16 - decimal.scale() as i16;
+    let mut bytes = Vec::with_capacity(significant.len() + 3);
+    bytes.push((0x80 + exponent) as u8);
+    bytes.extend(significant.bytes().map(|b| b - b'0' + 1));
+    bytes.push(0x00);
+    if mantissa < 0 {
+        bytes.iter_mut().for_each(|b| *b = !*b);
+        bytes.insert(0, 0x00);
+    } else {
+        bytes.insert(0, 0x02);
+    }
+    bytes
+}
+
+/// Decodes a decimal from a slice and shrinks the slice. The decoded decimal has no trailing
+/// fractional zeroes, and may thus have a smaller scale than the encoded decimal.
+pub fn take_decimal(bytes: &mut &[u8]) -> Result<Decimal> {
+    let invert = match take_byte(bytes)? {
+        0x00 => true,
+        0x01 => return Decimal::new(0, 0),
+        0x02 => false,
+        b => return Err(Error::Internal(format!("Invalid decimal sign {:x?}", b))),
+    };
+    let mut take = || take_byte(bytes).map(|b| if invert { !b } else { b });
+    let exponent = take()? as i16 - 0x80;
+    let mut mantissa: i128 = 0;
+    let mut digits: i16 = 0;
+    loop {
+        match take()? {
+            0x00 => break,
+            b @ 0x01..=0x0a => {
+                mantissa = mantissa
+                    .checked_mul(10)
+                    .ok_or_else(|| Error::Internal("Decimal overflow".into()))?
+                    + (b - 1) as i128;
+                digits += 1;
+            }
+            b => return Err(Error::Internal(format!("Invalid decimal digit {:x?}", b))),
+        }
+    }
+    let scale = digits - exponent;
+    if scale < 0 {
+        mantissa = 10_i128
+            .checked_pow(-scale as u32)
+            .and_then(|p| mantissa.checked_mul(p))
+            .ok_or_else(|| Error::Internal("Decimal overflow".into()))?;
+    }
+    Decimal::new(if invert { -mantissa } else { mantissa }, scale.max(0) as u8)
+}
+
 /// Encodes a string. Simply converts to a byte vector and encodes that.
 pub fn encode_string(string: &str) -> Vec<u8> {
     encode_bytes(string.as_bytes())
@@ -181,9 +246,22 @@ pub fn encode_value(value: &Value) -> Vec<u8> {
         Value::Float(f) => [&[0x02][..], &encode_f64(*f)].concat(),
         Value::Integer(i) => [&[0x03][..], &encode_i64(*i)].concat(),
         Value::String(s) => [&[0x04][..], &encode_string(s)].concat(),
+        Value::Date(d) => [&[0x05][..], &encode_i64(d.0)].concat(),
+        Value::Time(t) => [&[0x06][..], &encode_i64(t.0)].concat(),
+        Value::Timestamp(t) => [&[0x07][..], &encode_i64(t.0)].concat(),
+        Value::Decimal(d) => [&[0x08][..], &encode_decimal(*d)].concat(),
+        Value::Bytes(b) => [&[0x09][..], &encode_bytes(b)].concat(),
     }
 }
 
+/// Encodes a string prefix as a key prefix of all encoded string values starting with it, i.e.
+/// the encoded string value without its terminator.
+pub fn encode_string_value_prefix(prefix: &str) -> Vec<u8> {
+    let mut encoded = encode_value(&Value::String(prefix.into()));
+    encoded.truncate(encoded.len() - 2);
+    encoded
+}
+
 /// Decodes a value from a slice and shrinks the slice.
 pub fn take_value(bytes: &mut &[u8]) -> Result<Value> {
     match take_byte(bytes)? {
@@ -192,6 +270,11 @@ pub fn take_value(bytes: &mut &[u8]) -> Result<Value> {
         0x02 => Ok(Value::Float(take_f64(bytes)?)),
         0x03 => Ok(Value::Integer(take_i64(bytes)?)),
         0x04 => Ok(Value::String(take_string(bytes)?)),
+        0x05 => Ok(Value::Date(Date(take_i64(bytes)?))),
+        0x06 => Ok(Value::Time(Time(take_i64(bytes)?))),
+        0x07 => Ok(Value::Timestamp(Timestamp(take_i64(bytes)?))),
+        0x08 => Ok(Value::Decimal(take_decimal(bytes)?)),
+        0x09 => Ok(Value::Bytes(take_bytes(bytes)?)),
         n => Err(Error::Internal(format!("Invalid value prefix {:x?}", n))),
     }
 }
@@ -367,6 +450,77 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encode_decimal() -> Result<()> {
+        use super::encode_decimal;
+        let dec = |s: &str| s.parse::<Decimal>().unwrap();
+        assert_eq!(encode_decimal(dec("0")), vec![0x01]);
+        assert_eq!(encode_decimal(dec("0.000")), vec![0x01]);
+        assert_eq!(encode_decimal(dec("12.30")), vec![0x02, 0x82, 0x02, 0x03, 0x04, 0x00]);
+        assert_eq!(encode_decimal(dec("12.3")), vec![0x02, 0x82, 0x02, 0x03, 0x04, 0x00]);
+        assert_eq!(encode_decimal(dec("0.05")), vec![0x02, 0x7f, 0x06, 0x00]);
+        assert_eq!(encode_decimal(dec("-12.3")), vec![0x00, 0x7d, 0xfd, 0xfc, 0xfb, 0xff]);
+
+        // Encoded decimals must sort in numerical order.
+        let sorted: Vec<Decimal> = [
+            "-99999999999999999999999999999999999999",
+            "-100",
+            "-12.3",
+            "-12.25",
+            "-12.2",
+            "-1",
+            "-0.5",
+            "-0.00000000000000000000000000000000000001",
+            "0",
+            "0.00000000000000000000000000000000000001",
+            "0.05",
+            "0.5",
+            "1",
+            "1.05",
+            "1.5",
+            "9.99",
+            "10",
+            "12.2",
+            "12.25",
+            "12.3",
+            "100",
+            "99999999999999999999999999999999999999",
+        ]
+        .iter()
+        .map(|s| dec(s))
+        .collect();
+        for pair in sorted.windows(2) {
+            assert!(
+                encode_decimal(pair[0]) < encode_decimal(pair[1]),
+                "{} should sort before {}",
+                pair[0],
+                pair[1]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn take_decimal() -> Result<()> {
+        use super::{encode_decimal, take_decimal};
+        let dec = |s: &str| s.parse::<Decimal>().unwrap();
+        for s in &["0", "12.30", "-12.3", "0.05", "-0.05", "1200", "-99999999999999999999.999999"] {
+            let mut bytes: &[u8] = &[encode_decimal(dec(s)), vec![0xaf]].concat();
+            let decoded = take_decimal(&mut bytes)?;
+            assert_eq!(decoded, dec(s));
+            assert_eq!(bytes, &[0xaf]);
+        }
+        // Trailing zeroes are not retained, but integer zeroes are.
+        assert_eq!(take_decimal(&mut &encode_decimal(dec("12.30"))[..])?.to_string(), "12.3");
+        assert_eq!(take_decimal(&mut &encode_decimal(dec("1200"))[..])?.to_string(), "1200");
+
+        assert!(take_decimal(&mut &[][..]).is_err());
+        assert!(take_decimal(&mut &[0x03][..]).is_err());
+        assert!(take_decimal(&mut &[0x02, 0x81, 0x02][..]).is_err());
+        assert!(take_decimal(&mut &[0x02, 0x81, 0x0b, 0x00][..]).is_err());
+        Ok(())
+    }
+
     #[test]
     fn encode_string() -> Result<()> {
         use super::encode_string;
@@ -466,6 +620,52 @@ mod tests {
             encode_value(&Value::String("abc".into())),
             vec![0x04, 0x61, 0x62, 0x63, 0x00, 0x00]
         );
+        assert_eq!(
+            encode_value(&Value::Date(Date(-1))),
+            vec![0x05, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(
+            encode_value(&Value::Time(Time(1024))),
+            vec![0x06, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00]
+        );
+        assert_eq!(
+            encode_value(&Value::Timestamp(Timestamp(1024))),
+            vec![0x07, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00]
+        );
+        assert!(
+            encode_value(&Value::Timestamp(Timestamp(-1)))
+                < encode_value(&Value::Timestamp(Timestamp(0)))
+        );
+        assert_eq!(
+            encode_value(&Value::Decimal("1.5".parse()?)),
+            vec![0x08, 0x02, 0x81, 0x02, 0x06, 0x00]
+        );
+        assert_eq!(
+            encode_value(&Value::Bytes(vec![0x00, 0xff])),
+            vec![0x09, 0x00, 0xff, 0xff, 0x00, 0x00]
+        );
+        assert!(encode_value(&Value::Bytes(vec![])) < encode_value(&Value::Bytes(vec![0x00])));
+        assert!(
+            encode_value(&Value::Bytes(vec![0x00, 0xff])) < encode_value(&Value::Bytes(vec![0x01]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encode_string_value_prefix() -> Result<()> {
+        use super::{encode_string_value_prefix, encode_value};
+
+        assert_eq!(encode_string_value_prefix("ab"), vec![0x04, 0x61, 0x62]);
+        assert_eq!(encode_string_value_prefix("a\0"), vec![0x04, 0x61, 0x00, 0xff]);
+        assert_eq!(encode_string_value_prefix(""), vec![0x04]);
+        for s in &["ab", "abc", "ab\0", "ab\u{ff}"] {
+            assert!(encode_value(&Value::String(s.to_string()))
+                .starts_with(&encode_string_value_prefix("ab")));
+        }
+        for s in &["a", "aa", "ac", "b"] {
+            assert!(!encode_value(&Value::String(s.to_string()))
+                .starts_with(&encode_string_value_prefix("ab")));
+        }
         Ok(())
     }
 
@@ -499,6 +699,26 @@ mod tests {
         assert_eq!(take_value(&mut bytes)?, Value::String("abc".into()));
         assert_eq!(bytes, &[0xaf]);
 
+        let mut bytes: &[u8] = &[0x05, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xaf];
+        assert_eq!(take_value(&mut bytes)?, Value::Date(Date(-1)));
+        assert_eq!(bytes, &[0xaf]);
+
+        let mut bytes: &[u8] = &[0x06, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0xaf];
+        assert_eq!(take_value(&mut bytes)?, Value::Time(Time(1024)));
+        assert_eq!(bytes, &[0xaf]);
+
+        let mut bytes: &[u8] = &[0x07, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0xaf];
+        assert_eq!(take_value(&mut bytes)?, Value::Timestamp(Timestamp(1024)));
+        assert_eq!(bytes, &[0xaf]);
+
+        let mut bytes: &[u8] = &[0x08, 0x02, 0x81, 0x02, 0x06, 0x00, 0xaf];
+        assert_eq!(take_value(&mut bytes)?, Value::Decimal("1.5".parse()?));
+        assert_eq!(bytes, &[0xaf]);
+
+        let mut bytes: &[u8] = &[0x09, 0x00, 0xff, 0xff, 0x00, 0x00, 0xaf];
+        assert_eq!(take_value(&mut bytes)?, Value::Bytes(vec![0x00, 0xff]));
+        assert_eq!(bytes, &[0xaf]);
+
         Ok(())
     }
 }
@@ -1,3 +1,4 @@
+mod cache;
 pub mod encoding;
 mod memory;
 pub mod mvcc;
@@ -34,6 +35,7 @@ pub trait Store: Display + Send + Sync {
 }
 
 /// A scan range.
+#[derive(Clone)]
 pub struct Range {
     start: Bound<Vec<u8>>,
     end: Bound<Vec<u8>>,
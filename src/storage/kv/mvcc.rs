@@ -1,54 +1,193 @@
+use super::cache::Lru;
 use super::{encoding, Range, Store};
 use crate::error::{Error, Result};
 
 use serde::{Deserialize, Serialize};
 use serde_derive::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::iter::Peekable;
 use std::ops::{Bound, RangeBounds};
-use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 /// MVCC status
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Status {
     pub txns: u64,
     pub txns_active: u64,
+    /// The ID of the oldest active transaction, if any.
+    pub txns_active_oldest: Option<u64>,
     pub storage: String,
 }
 
+/// The result of an MVCC vacuum, see MVCC::vacuum().
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vacuum {
+    /// The number of record versions removed.
+    pub versions: u64,
+    /// The number of bytes removed, counting both keys and values.
+    pub bytes: u64,
+}
+
+/// Uncommitted writes of active transactions, keyed by transaction ID.
+type Buffers = Arc<Mutex<HashMap<u64, Buffer>>>;
+
+/// The uncommitted writes of a transaction.
+#[derive(Clone, Default)]
+struct Buffer {
+    /// Writes keyed by user key. A None value is used for deletion.
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// Range deletions, as key prefixes, see Transaction::delete_prefixes(). Writes buffered
+    /// after a range deletion are kept in writes, and take precedence.
+    tombstones: BTreeSet<Vec<u8>>,
+}
+
+/// The key ranges read by active serializable transactions, keyed by transaction ID.
+type Reads = Arc<Mutex<HashMap<u64, Vec<Range>>>>;
+
+/// A cache of the latest stored version of recently read keys, as a version/value pair, see
+/// MVCC::with_cache(). Missing keys are cached as version 0.
+type Cache = Arc<Mutex<Lru<(u64, Option<Vec<u8>>)>>>;
+
+/// The in-memory transaction state (buffered writes and reads) when a write batch began.
+type TxnState = (HashMap<u64, Buffer>, HashMap<u64, Vec<Range>>);
+
 /// An MVCC-based transactional key-value store.
 pub struct MVCC {
     /// The underlying KV store. It is protected by a mutex so it can be shared between txns.
-    store: Arc<RwLock<Box<dyn Store>>>,
+    store: Arc<RwLock<BatchStore>>,
+    /// Buffered writes of active transactions. They are kept here rather than in the transaction
+    /// itself so that they survive the transaction being resumed.
+    buffers: Buffers,
+    /// Key ranges read by active serializable transactions, kept here for the same reason.
+    reads: Reads,
+    /// The maximum size of values written by transactions, in bytes, if limited.
+    max_value_size: Option<usize>,
+    /// A cache of recently read keys, if enabled.
+    cache: Option<Cache>,
 }
 
 impl Clone for MVCC {
     fn clone(&self) -> Self {
-        MVCC { store: self.store.clone() }
+        MVCC {
+            store: self.store.clone(),
+            buffers: self.buffers.clone(),
+            reads: self.reads.clone(),
+            max_value_size: self.max_value_size,
+            cache: self.cache.clone(),
+        }
     }
 }
 
 impl MVCC {
     /// Creates a new MVCC key-value store with the given key-value store for storage.
     pub fn new(store: Box<dyn Store>) -> Self {
-        Self { store: Arc::new(RwLock::new(store)) }
+        Self {
+            store: Arc::new(RwLock::new(BatchStore { store, batch: None, txn_state: None })),
+            buffers: Arc::new(Mutex::new(HashMap::new())),
+            reads: Arc::new(Mutex::new(HashMap::new())),
+            max_value_size: None,
+            cache: None,
+        }
+    }
+
+    /// Caches the latest stored version of up to capacity recently read keys in memory, evicting
+    /// the least recently used keys, or disables the cache if 0. Transaction reads of a key are
+    /// served from the cache when the cached version is visible to them, which is usually the
+    /// case. Commits update cached keys, so reads never see stale values. Scans bypass the cache.
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = match capacity {
+            0 => None,
+            capacity => Some(Arc::new(Mutex::new(Lru::new(capacity)))),
+        };
+        self
+    }
+
+    /// Limits the size of values written by transactions. Larger values are rejected with
+    /// Error::Value before they are written.
+    pub fn with_max_value_size(mut self, max_value_size: Option<usize>) -> Self {
+        self.max_value_size = max_value_size;
+        self
     }
 
     /// Begins a new transaction in read-write mode.
     #[allow(dead_code)]
     pub fn begin(&self) -> Result<Transaction> {
-        Transaction::begin(self.store.clone(), Mode::ReadWrite)
+        self.begin_with_mode(Mode::ReadWrite)
     }
 
     /// Begins a new transaction in the given mode.
     pub fn begin_with_mode(&self, mode: Mode) -> Result<Transaction> {
-        Transaction::begin(self.store.clone(), mode)
+        Transaction::begin(
+            self.store.clone(),
+            self.buffers.clone(),
+            self.reads.clone(),
+            self.cache.clone(),
+            mode,
+            self.max_value_size,
+        )
     }
 
     /// Resumes a transaction with the given ID.
     pub fn resume(&self, id: u64) -> Result<Transaction> {
-        Transaction::resume(self.store.clone(), id)
+        Transaction::resume(
+            self.store.clone(),
+            self.buffers.clone(),
+            self.reads.clone(),
+            self.cache.clone(),
+            id,
+            self.max_value_size,
+        )
+    }
+
+    /// Begins a write batch. Until the batch is committed or aborted, all writes to the underlying
+    /// store (by transactions, metadata changes, and vacuums) are buffered in memory, and flushes
+    /// are deferred. Reads see the buffered writes. This allows a sequence of operations to be
+    /// written atomically with a single flush. Errors if a batch is already active.
+    pub fn begin_batch(&self) -> Result<()> {
+        let txn_state = (self.buffers.lock()?.clone(), self.reads.lock()?.clone());
+        let mut session = self.store.write()?;
+        if session.batch.is_some() {
+            return Err(Error::Internal("Write batch already active".into()));
+        }
+        session.batch = Some(BTreeMap::new());
+        session.txn_state = Some(txn_state);
+        Ok(())
+    }
+
+    /// Commits the active write batch, writing its buffered writes to the underlying store and
+    /// flushing it.
+    pub fn commit_batch(&self) -> Result<()> {
+        let mut session = self.store.write()?;
+        let batch =
+            session.batch.take().ok_or_else(|| Error::Internal("No active write batch".into()))?;
+        session.txn_state = None;
+        for (key, value) in batch {
+            match value {
+                Some(value) => session.store.set(&key, value)?,
+                None => session.store.delete(&key)?,
+            }
+        }
+        session.store.flush()
+    }
+
+    /// Aborts the active write batch, discarding its buffered writes and reverting the in-memory
+    /// state of active transactions (their buffered writes and reads) to when the batch began.
+    /// The cache is cleared, since it may contain writes from the batch.
+    pub fn abort_batch(&self) -> Result<()> {
+        let txn_state = {
+            let mut session = self.store.write()?;
+            session.batch.take().ok_or_else(|| Error::Internal("No active write batch".into()))?;
+            if let Some(cache) = &self.cache {
+                cache.lock()?.clear();
+            }
+            session.txn_state.take()
+        };
+        if let Some((buffers, reads)) = txn_state {
+            *self.buffers.lock()? = buffers;
+            *self.reads.lock()? = reads;
+        }
+        Ok(())
     }
 
     /// Fetches an unversioned metadata value
@@ -63,6 +202,128 @@ impl MVCC {
         session.set(&Key::Metadata(key.into()).encode(), value)
     }
 
+    /// Deletes an unversioned metadata value
+    pub fn delete_metadata(&self, key: &[u8]) -> Result<()> {
+        let mut session = self.store.write()?;
+        session.delete(&Key::Metadata(key.into()).encode())
+    }
+
+    /// Garbage collects record versions that are no longer visible to any active or future
+    /// transaction, returning the number of versions and bytes removed. All transactions see the
+    /// versions below the watermark, i.e. the lowest version that may be invisible to an active
+    /// transaction, so only the latest of these is kept for each key, and only if it's not a
+    /// deletion. Range tombstones below the watermark are removed along with the versions they
+    /// hide. Snapshots that could see removed versions are also removed, such that snapshot
+    /// transactions can no longer begin at them. The store is locked while vacuuming, but it is
+    /// safe to vacuum while transactions are active.
+    pub fn vacuum(&self) -> Result<Vacuum> {
+        let mut session = self.store.write()?;
+
+        // Find the watermark, from the snapshots of active transactions. Snapshot transactions
+        // use the snapshot of their version, and other transactions their own.
+        let mut watermark = match session.get(&Key::TxnNext.encode())? {
+            Some(ref v) => deserialize(v)?,
+            None => 1,
+        };
+        let mut versions = Vec::new();
+        let mut scan = session
+            .scan(Range::from(Key::TxnActive(0).encode()..Key::TxnActive(std::u64::MAX).encode()));
+        while let Some((key, value)) = scan.next().transpose()? {
+            match (Key::decode(&key)?, deserialize(&value)?) {
+                (Key::TxnActive(_), Mode::Snapshot { version }) => versions.push(version),
+                (Key::TxnActive(id), _) => versions.push(id),
+                (k, _) => return Err(Error::Internal(format!("Expected TxnActive, got {:?}", k))),
+            }
+        }
+        std::mem::drop(scan);
+        for version in versions {
+            let invisible: HashSet<u64> = match session.get(&Key::TxnSnapshot(version).encode())? {
+                Some(ref v) => deserialize(v)?,
+                None => {
+                    return Err(Error::Internal(format!(
+                        "Snapshot not found for version {}",
+                        version
+                    )))
+                }
+            };
+            watermark = invisible.into_iter().fold(watermark.min(version), u64::min);
+        }
+
+        // Collect the garbage keys and their sizes, starting with snapshots that could see
+        // removed versions.
+        let mut garbage = Vec::new();
+        let mut scan = session.scan(Range::from(
+            Key::TxnSnapshot(0).encode()..Key::TxnSnapshot(std::u64::MAX).encode(),
+        ));
+        while let Some((key, value)) = scan.next().transpose()? {
+            let version = match Key::decode(&key)? {
+                Key::TxnSnapshot(version) => version,
+                k => return Err(Error::Internal(format!("Expected TxnSnapshot, got {:?}", k))),
+            };
+            let invisible: HashSet<u64> = deserialize(&value)?;
+            if version < watermark || invisible.iter().any(|id| *id < watermark) {
+                garbage.push((key.len() + value.len(), key, false));
+            }
+        }
+        std::mem::drop(scan);
+
+        // Range tombstones below the watermark hide their versions from all transactions, so both
+        // the tombstones and the versions they hide are garbage. The tombstones are removed last.
+        let tombstones: Vec<(u64, Vec<u8>)> = Transaction::tombstones(&session, 0)?
+            .into_iter()
+            .filter(|(version, _)| *version < watermark)
+            .collect();
+
+        // Records are ordered by key and then version, so we track the latest version below the
+        // watermark for the current key, which is garbage if a later one is found, or if it's a
+        // deletion.
+        let mut latest: Option<(Vec<u8>, Vec<u8>, usize, bool)> = None;
+        let mut scan = session.scan(Range::from(Key::Record(vec![].into(), 0).encode()..));
+        while let Some((key, value)) = scan.next().transpose()? {
+            let (record, version) = match Key::decode(&key)? {
+                Key::Record(record, version) => (record.into_owned(), version),
+                k => return Err(Error::Internal(format!("Expected Txn::Record, got {:?}", k))),
+            };
+            if is_deleted(&tombstones, &record, version) {
+                garbage.push((key.len() + value.len(), key, true));
+                continue;
+            }
+            let same = latest.as_ref().map(|(r, ..)| r == &record).unwrap_or(false);
+            if let Some((_, key, size, deleted)) = latest.take() {
+                if same && version < watermark || deleted {
+                    garbage.push((size, key, true));
+                }
+            }
+            if version < watermark {
+                let deleted = deserialize::<Option<Vec<u8>>>(&value)?.is_none();
+                latest = Some((record, key.clone(), key.len() + value.len(), deleted));
+            }
+        }
+        std::mem::drop(scan);
+        if let Some((_, key, size, true)) = latest {
+            garbage.push((size, key, true));
+        }
+        for (version, prefix) in tombstones {
+            let key = Key::Tombstone(version, prefix.into()).encode();
+            garbage.push((key.len(), key, false));
+        }
+
+        // The cache may refer to removed versions, so it's cleared.
+        if let Some(cache) = &self.cache {
+            cache.lock()?.clear();
+        }
+        let mut vacuum = Vacuum { versions: 0, bytes: 0 };
+        for (size, key, record) in garbage {
+            session.delete(&key)?;
+            vacuum.bytes += size as u64;
+            if record {
+                vacuum.versions += 1;
+            }
+        }
+        session.flush()?;
+        Ok(vacuum)
+    }
+
     /// Returns engine status
     //
     // Bizarrely, the return statement is in fact necessary - see:
@@ -70,16 +331,27 @@ impl MVCC {
     #[allow(clippy::needless_return)]
     pub fn status(&self) -> Result<Status> {
         let store = self.store.read()?;
+        let mut txns_active = 0;
+        let mut txns_active_oldest = None;
+        let mut scan = store
+            .scan(Range::from(Key::TxnActive(0).encode()..Key::TxnActive(std::u64::MAX).encode()));
+        while let Some((key, _)) = scan.next().transpose()? {
+            if txns_active_oldest.is_none() {
+                match Key::decode(&key)? {
+                    Key::TxnActive(id) => txns_active_oldest = Some(id),
+                    k => return Err(Error::Internal(format!("Expected TxnActive, got {:?}", k))),
+                }
+            }
+            txns_active += 1;
+        }
+        std::mem::drop(scan);
         return Ok(Status {
             txns: match store.get(&Key::TxnNext.encode())? {
                 Some(ref v) => deserialize(v)?,
                 None => 1,
             } - 1,
-            txns_active: store
-                .scan(Range::from(
-                    Key::TxnActive(0).encode()..Key::TxnActive(std::u64::MAX).encode(),
-                ))
-                .try_fold(0, |count, r| r.map(|_| count + 1))?,
+            txns_active,
+            txns_active_oldest,
             storage: store.to_string(),
         });
     }
@@ -95,21 +367,94 @@ fn deserialize<'a, V: Deserialize<'a>>(bytes: &'a [u8]) -> Result<V> {
     Ok(bincode::deserialize(bytes)?)
 }
 
-/// An MVCC transaction.
+/// Returns the key range of keys under a given prefix. Errors if the prefix is empty.
+fn prefix_range(prefix: &[u8]) -> Result<Range> {
+    if prefix.is_empty() {
+        return Err(Error::Internal("Scan prefix cannot be empty".into()));
+    }
+    let start = prefix.to_vec();
+    let mut end = start.clone();
+    for i in (0..end.len()).rev() {
+        match end[i] {
+            // If all 0xff we could in principle use Range::Unbounded, but it won't happen
+            0xff if i == 0 => return Err(Error::Internal("Invalid prefix scan range".into())),
+            0xff => {
+                end[i] = 0x00;
+                continue;
+            }
+            v => {
+                end[i] = v + 1;
+                break;
+            }
+        }
+    }
+    Ok(Range::from(start..end))
+}
+
+/// Checks whether a key range may contain keys under a given prefix.
+fn overlaps_prefix(range: &Range, prefix: &[u8]) -> Result<bool> {
+    let prefix_range = prefix_range(prefix)?;
+    let end = match prefix_range.end_bound() {
+        Bound::Excluded(end) => end,
+        _ => return Err(Error::Internal("Unexpected prefix range end".into())),
+    };
+    Ok((match range.start_bound() {
+        Bound::Included(start) | Bound::Excluded(start) => start < end,
+        Bound::Unbounded => true,
+    }) && (match range.end_bound() {
+        Bound::Included(end) => prefix <= &end[..],
+        Bound::Excluded(end) => prefix < &end[..],
+        Bound::Unbounded => true,
+    }))
+}
+
+/// Checks whether a version of a key is deleted by any of the given range tombstones, as
+/// version/prefix pairs. A tombstone deletes all versions of keys under its prefix that are
+/// older than it.
+fn is_deleted(tombstones: &[(u64, Vec<u8>)], key: &[u8], version: u64) -> bool {
+    tombstones.iter().any(|(t, prefix)| version < *t && key.starts_with(prefix))
+}
+
+/// An MVCC transaction. Writes are buffered in memory until the transaction commits, at which
+/// point they are written to the underlying store in a single batch.
+///
+/// Transactions run under snapshot isolation, which prevents most anomalies but permits write
+/// skew. Serializable transactions (Mode::Serializable) additionally record the key ranges they
+/// read, and when committing they abort with Error::Serialization if any of them has been written
+/// by a concurrent transaction that has since committed. Each serializable transaction thus
+/// appears to execute atomically at its commit. This is conservative, in that it aborts on any
+/// such read/write conflict rather than only on dependency cycles, but it is sound. Only
+/// serializable transactions are serializable with respect to each other: snapshot isolation
+/// transactions may still cause anomalies.
 pub struct Transaction {
     /// The underlying store for the transaction. Shared between transactions using a mutex.
-    store: Arc<RwLock<Box<dyn Store>>>,
+    store: Arc<RwLock<BatchStore>>,
+    /// The buffered writes of all active transactions.
+    buffers: Buffers,
+    /// The read key ranges of all active serializable transactions.
+    reads: Reads,
+    /// The store's cache of recently read keys, if enabled.
+    cache: Option<Cache>,
     /// The unique transaction ID.
     id: u64,
     /// The transaction mode.
     mode: Mode,
     /// The snapshot that the transaction is running in.
     snapshot: Snapshot,
+    /// The maximum size of written values, if limited.
+    max_value_size: Option<usize>,
 }
 
 impl Transaction {
     /// Begins a new transaction in the given mode.
-    fn begin(store: Arc<RwLock<Box<dyn Store>>>, mode: Mode) -> Result<Self> {
+    fn begin(
+        store: Arc<RwLock<BatchStore>>,
+        buffers: Buffers,
+        reads: Reads,
+        cache: Option<Cache>,
+        mode: Mode,
+        max_value_size: Option<usize>,
+    ) -> Result<Self> {
         let mut session = store.write()?;
 
         let id = match session.get(&Key::TxnNext.encode())? {
@@ -125,14 +470,29 @@ impl Transaction {
         let mut snapshot = Snapshot::take(&mut session, id)?;
         std::mem::drop(session);
         if let Mode::Snapshot { version } = &mode {
-            snapshot = Snapshot::restore(&store.read()?, *version)?
+            // If the snapshot doesn't exist, the transaction must not be left active.
+            let restored = Snapshot::restore(&store.read()?, *version);
+            snapshot = match restored {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    store.write()?.delete(&Key::TxnActive(id).encode())?;
+                    return Err(err);
+                }
+            }
         }
 
-        Ok(Self { store, id, mode, snapshot })
+        Ok(Self { store, buffers, reads, cache, id, mode, snapshot, max_value_size })
     }
 
     /// Resumes an active transaction with the given ID. Errors if the transaction is not active.
-    fn resume(store: Arc<RwLock<Box<dyn Store>>>, id: u64) -> Result<Self> {
+    fn resume(
+        store: Arc<RwLock<BatchStore>>,
+        buffers: Buffers,
+        reads: Reads,
+        cache: Option<Cache>,
+        id: u64,
+        max_value_size: Option<usize>,
+    ) -> Result<Self> {
         let session = store.read()?;
         let mode = match session.get(&Key::TxnActive(id).encode())? {
             Some(v) => deserialize(&v)?,
@@ -143,7 +503,7 @@ impl Transaction {
             _ => Snapshot::restore(&session, id)?,
         };
         std::mem::drop(session);
-        Ok(Self { store, id, mode, snapshot })
+        Ok(Self { store, buffers, reads, cache, id, mode, snapshot, max_value_size })
     }
 
     /// Returns the transaction ID.
@@ -156,35 +516,75 @@ impl Transaction {
         self.mode
     }
 
-    /// Commits the transaction, by removing the txn from the active set.
+    /// Commits the transaction, by writing its buffered writes to the store and removing the txn
+    /// from the active set. The buffers are held locked until the writes are in the store, such
+    /// that concurrent writers will always see either the buffered or the stored versions.
+    ///
+    /// Serializable transactions first check that none of the key ranges they read have been
+    /// written (or range deleted) by concurrent transactions, erroring with Error::Serialization
+    /// otherwise. The transaction then remains active, and must be rolled back.
     pub fn commit(self) -> Result<()> {
+        let mut buffers = self.buffers.lock()?;
         let mut session = self.store.write()?;
+        if let Some(ranges) = self.reads.lock()?.get(&self.id) {
+            for (version, prefix) in Self::tombstones(&session, self.snapshot.min_invisible())? {
+                if self.snapshot.is_visible(version) {
+                    continue;
+                }
+                for range in ranges {
+                    if overlaps_prefix(range, &prefix)? {
+                        return Err(Error::Serialization);
+                    }
+                }
+            }
+            for range in ranges {
+                let mut scan = session.scan(Self::record_range(range));
+                while let Some((key, _)) = scan.next().transpose()? {
+                    match Key::decode(&key)? {
+                        Key::Record(_, version) if !self.snapshot.is_visible(version) => {
+                            return Err(Error::Serialization)
+                        }
+                        Key::Record(..) => {}
+                        k => {
+                            return Err(Error::Internal(format!(
+                                "Expected Txn::Record, got {:?}",
+                                k
+                            )))
+                        }
+                    }
+                }
+            }
+        }
+        self.reads.lock()?.remove(&self.id);
+        let buffer = buffers.remove(&self.id).unwrap_or_default();
+        for prefix in buffer.tombstones {
+            session.set(&Key::Tombstone(self.id, prefix.into()).encode(), vec![])?;
+        }
+        for (key, value) in &buffer.writes {
+            session.set(&Key::Record(key.into(), self.id).encode(), serialize(value)?)?;
+        }
         session.delete(&Key::TxnActive(self.id).encode())?;
-        session.flush()
+        let result = session.flush();
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock()?;
+            match result {
+                Ok(()) => {
+                    for (key, value) in buffer.writes {
+                        cache.update(&key, (self.id, value));
+                    }
+                }
+                Err(_) => cache.clear(),
+            }
+        }
+        result
     }
 
-    /// Rolls back the transaction, by removing all updated entries.
+    /// Rolls back the transaction, by discarding its buffered writes and removing the txn from
+    /// the active set.
     pub fn rollback(self) -> Result<()> {
-        let mut session = self.store.write()?;
-        if self.mode.mutable() {
-            let mut rollback = Vec::new();
-            let mut scan = session.scan(Range::from(
-                Key::TxnUpdate(self.id, vec![].into()).encode()
-                    ..Key::TxnUpdate(self.id + 1, vec![].into()).encode(),
-            ));
-            while let Some((key, _)) = scan.next().transpose()? {
-                match Key::decode(&key)? {
-                    Key::TxnUpdate(_, updated_key) => rollback.push(updated_key.into_owned()),
-                    k => return Err(Error::Internal(format!("Expected TxnUpdate, got {:?}", k))),
-                };
-                rollback.push(key);
-            }
-            std::mem::drop(scan);
-            for key in rollback.into_iter() {
-                session.delete(&key)?;
-            }
-        }
-        session.delete(&Key::TxnActive(self.id).encode())
+        self.buffers.lock()?.remove(&self.id);
+        self.reads.lock()?.remove(&self.id);
+        self.store.write()?.delete(&Key::TxnActive(self.id).encode())
     }
 
     /// Deletes a key.
@@ -192,29 +592,167 @@ impl Transaction {
         self.write(key, None)
     }
 
-    /// Fetches a key.
+    /// Deletes all keys under a given prefix that are visible to the transaction, including its
+    /// own buffered writes, returning the number of deleted keys. See delete_prefixes().
+    pub fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64> {
+        Ok(self.delete_prefixes(&[prefix])?[0])
+    }
+
+    /// Deletes all keys under the given prefixes that are visible to the transaction, including
+    /// its own buffered writes, returning the number of deleted keys for each prefix. Rather than
+    /// writing a deletion for each key, this buffers a single range tombstone per prefix, which
+    /// hides all earlier versions of the keys under it once committed. Conflicts are checked for
+    /// all prefixes before anything is buffered, so on error (e.g. Error::Serialization) none of
+    /// the prefixes are deleted.
+    pub fn delete_prefixes(&mut self, prefixes: &[&[u8]]) -> Result<Vec<u64>> {
+        if !self.mode.mutable() {
+            return Err(Error::ReadOnly);
+        }
+        let mut counts = Vec::new();
+        for prefix in prefixes {
+            counts.push(self.scan_prefix(prefix)?.try_fold(0, |count, r| r.map(|_| count + 1))?);
+        }
+        let mut buffers = self.buffers.lock()?;
+
+        // Check if any of the prefixes are dirty, i.e. if they contain any uncommitted changes
+        // buffered by other txns, or any versions or tombstones in the store that aren't visible
+        // to us.
+        for (_, buffer) in buffers.iter().filter(|(id, _)| **id != self.id) {
+            for prefix in prefixes {
+                if matches!(buffer.writes.range(prefix.to_vec()..).next(), Some((k, _)) if k.starts_with(prefix))
+                    || buffer
+                        .tombstones
+                        .iter()
+                        .any(|t| t.starts_with(prefix) || prefix.starts_with(t))
+                {
+                    return Err(Error::Serialization);
+                }
+            }
+        }
+        let session = self.store.read()?;
+        for (version, tombstone) in Self::tombstones(&session, self.snapshot.min_invisible())? {
+            if !self.snapshot.is_visible(version)
+                && prefixes.iter().any(|p| tombstone.starts_with(p) || p.starts_with(&tombstone))
+            {
+                return Err(Error::Serialization);
+            }
+        }
+        for prefix in prefixes {
+            let mut scan = session.scan(Self::record_range(&prefix_range(prefix)?));
+            while let Some((k, _)) = scan.next().transpose()? {
+                match Key::decode(&k)? {
+                    Key::Record(key, version) => {
+                        if key.starts_with(prefix) && !self.snapshot.is_visible(version) {
+                            return Err(Error::Serialization);
+                        }
+                    }
+                    k => return Err(Error::Internal(format!("Expected Txn::Record, got {:?}", k))),
+                };
+            }
+        }
+        std::mem::drop(session);
+
+        let buffer = buffers.entry(self.id).or_default();
+        for prefix in prefixes {
+            buffer.writes.retain(|k, _| !k.starts_with(prefix));
+            buffer.tombstones.insert(prefix.to_vec());
+        }
+        Ok(counts)
+    }
+
+    /// Fetches a key, including any buffered writes by the transaction itself.
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(buffer) = self.buffers.lock()?.get(&self.id) {
+            if let Some(value) = buffer.writes.get(key) {
+                return Ok(value.clone());
+            }
+            if buffer.tombstones.iter().any(|prefix| key.starts_with(prefix)) {
+                return Ok(None);
+            }
+        }
+        self.record_read(Range::from(key.to_vec()..=key.to_vec()))?;
         let session = self.store.read()?;
+        let tombstones = self.visible_tombstones(&session)?;
+
+        // The cache holds the latest stored version of the key (or version 0 if none), which is
+        // the version we want if it's visible to us. If the key isn't cached, we scan all of its
+        // versions to cache the latest one.
+        let mut cache = match &self.cache {
+            Some(cache) => Some(cache.lock()?),
+            None => None,
+        };
+        let mut uncached = false;
+        if let Some(cache) = cache.as_mut() {
+            match cache.get(key) {
+                Some((version, _))
+                    if self.snapshot.is_visible(version)
+                        && is_deleted(&tombstones, key, version) =>
+                {
+                    return Ok(None)
+                }
+                Some((version, value)) if self.snapshot.is_visible(version) => return Ok(value),
+                Some(_) => {}
+                None => uncached = true,
+            }
+        }
+        let end = if uncached { u64::MAX } else { self.id };
         let mut scan = session
             .scan(Range::from(
-                Key::Record(key.into(), 0).encode()..=Key::Record(key.into(), self.id).encode(),
+                Key::Record(key.into(), 0).encode()..=Key::Record(key.into(), end).encode(),
             ))
             .rev();
         while let Some((k, v)) = scan.next().transpose()? {
             match Key::decode(&k)? {
                 Key::Record(_, version) => {
+                    if let Some(cache) = cache.as_mut().filter(|_| uncached) {
+                        cache.insert(key.to_vec(), (version, deserialize(&v)?));
+                        uncached = false;
+                    }
                     if self.snapshot.is_visible(version) {
+                        if is_deleted(&tombstones, key, version) {
+                            return Ok(None);
+                        }
                         return deserialize(&v);
                     }
                 }
                 k => return Err(Error::Internal(format!("Expected Txn::Record, got {:?}", k))),
             };
         }
+        if let Some(cache) = cache.as_mut().filter(|_| uncached) {
+            cache.insert(key.to_vec(), (0, None));
+        }
         Ok(None)
     }
 
-    /// Scans a key range.
+    /// Scans a key range, including any buffered writes by the transaction itself.
     pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<super::Scan> {
+        let (buffered, own_tombstones) = match self.buffers.lock()?.get(&self.id) {
+            Some(buffer) => {
+                let range = Range::from((range.start_bound(), range.end_bound()));
+                let buffered = buffer
+                    .writes
+                    .iter()
+                    .filter(|(k, _)| range.contains(k))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                (buffered, buffer.tombstones.clone())
+            }
+            None => (VecDeque::new(), BTreeSet::new()),
+        };
+        let range = Range::from(range);
+        let session = self.store.read()?;
+        let mut tombstones = self.visible_tombstones(&session)?;
+        // Our own buffered tombstones hide all stored versions visible to us.
+        tombstones.extend(own_tombstones.into_iter().map(|prefix| (self.id + 1, prefix)));
+        let scan = session.scan(Self::record_range(&range));
+        std::mem::drop(session);
+        self.record_read(range)?;
+        let scan = Box::new(Scan::new(scan, self.snapshot.clone(), tombstones));
+        Ok(Box::new(BufferedScan::new(scan, buffered)))
+    }
+
+    /// Returns the range of stored records (i.e. all versions) for a key range.
+    fn record_range(range: &Range) -> Range {
         let start = match range.start_bound() {
             Bound::Excluded(k) => Bound::Excluded(Key::Record(k.into(), std::u64::MAX).encode()),
             Bound::Included(k) => Bound::Included(Key::Record(k.into(), 0).encode()),
@@ -225,49 +763,92 @@ impl Transaction {
             Bound::Included(k) => Bound::Included(Key::Record(k.into(), std::u64::MAX).encode()),
             Bound::Unbounded => Bound::Unbounded,
         };
-        let scan = self.store.read()?.scan(Range::from((start, end)));
-        Ok(Box::new(Scan::new(scan, self.snapshot.clone())))
+        Range::from((start, end))
+    }
+
+    /// Returns the committed range tombstones with versions from the given version onwards, as
+    /// version/prefix pairs.
+    fn tombstones(session: &BatchStore, from: u64) -> Result<Vec<(u64, Vec<u8>)>> {
+        // Tombstone keys are prefixed by 0x04, so they all sort before 0x05.
+        let mut scan =
+            session.scan(Range::from(Key::Tombstone(from, vec![].into()).encode()..vec![0x05]));
+        let mut tombstones = Vec::new();
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::Tombstone(version, prefix) => tombstones.push((version, prefix.into_owned())),
+                k => return Err(Error::Internal(format!("Expected Tombstone, got {:?}", k))),
+            }
+        }
+        Ok(tombstones)
+    }
+
+    /// Returns the committed range tombstones that are visible to the transaction.
+    fn visible_tombstones(&self, session: &BatchStore) -> Result<Vec<(u64, Vec<u8>)>> {
+        Ok(Self::tombstones(session, 0)?
+            .into_iter()
+            .filter(|(version, _)| self.snapshot.is_visible(*version))
+            .collect())
+    }
+
+    /// Records a key range read by a serializable transaction, to check for conflicting writes
+    /// when committing.
+    fn record_read(&self, range: Range) -> Result<()> {
+        if self.mode == Mode::Serializable {
+            self.reads.lock()?.entry(self.id).or_default().push(range);
+        }
+        Ok(())
     }
 
     /// Scans keys under a given prefix.
     pub fn scan_prefix(&self, prefix: &[u8]) -> Result<super::Scan> {
-        if prefix.is_empty() {
-            return Err(Error::Internal("Scan prefix cannot be empty".into()));
-        }
-        let start = prefix.to_vec();
-        let mut end = start.clone();
-        for i in (0..end.len()).rev() {
-            match end[i] {
-                // If all 0xff we could in principle use Range::Unbounded, but it won't happen
-                0xff if i == 0 => return Err(Error::Internal("Invalid prefix scan range".into())),
-                0xff => {
-                    end[i] = 0x00;
-                    continue;
-                }
-                v => {
-                    end[i] = v + 1;
-                    break;
-                }
-            }
+        self.scan_prefix_after(prefix, None)
+    }
+
+    /// Scans keys under a given prefix, starting after the given key if any. This allows resuming
+    /// a scan in a new transaction handle, e.g. when fetching it in batches.
+    pub fn scan_prefix_after(&self, prefix: &[u8], after: Option<&[u8]>) -> Result<super::Scan> {
+        let range = prefix_range(prefix)?;
+        match after {
+            Some(after) => self.scan((Bound::Excluded(after.to_vec()), range.end)),
+            None => self.scan(range),
         }
-        self.scan(start..end)
     }
 
-    /// Sets a key.
+    /// Sets a key. Errors if the value exceeds the maximum value size.
     pub fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
-        self.write(key, Some(value))
+        match self.max_value_size {
+            Some(max) if value.len() > max => Err(Error::Value(format!(
+                "Value of {} bytes exceeds maximum size of {} bytes",
+                value.len(),
+                max
+            ))),
+            _ => self.write(key, Some(value)),
+        }
     }
 
-    /// Writes a value for a key. None is used for deletion.
+    /// Buffers a write of a value for a key. None is used for deletion.
     fn write(&self, key: &[u8], value: Option<Vec<u8>>) -> Result<()> {
         if !self.mode.mutable() {
             return Err(Error::ReadOnly);
         }
-        let mut session = self.store.write()?;
-
-        // Check if the key is dirty, i.e. if it has any uncommitted changes, by scanning for any
-        // versions that aren't visible to us.
-        let min = self.snapshot.invisible.iter().min().cloned().unwrap_or(self.id + 1);
+        let mut buffers = self.buffers.lock()?;
+
+        // Check if the key is dirty, i.e. if it has any uncommitted changes buffered by other
+        // txns, or any versions in the store that aren't visible to us.
+        if buffers.iter().any(|(id, buffer)| {
+            *id != self.id
+                && (buffer.writes.contains_key(key)
+                    || buffer.tombstones.iter().any(|prefix| key.starts_with(prefix)))
+        }) {
+            return Err(Error::Serialization);
+        }
+        let session = self.store.read()?;
+        let min = self.snapshot.min_invisible();
+        for (version, prefix) in Self::tombstones(&session, min)? {
+            if !self.snapshot.is_visible(version) && key.starts_with(&prefix) {
+                return Err(Error::Serialization);
+            }
+        }
         let mut scan = session
             .scan(Range::from(
                 Key::Record(key.into(), min).encode()
@@ -285,12 +866,10 @@ impl Transaction {
             };
         }
         std::mem::drop(scan);
+        std::mem::drop(session);
 
-        // Write the key and its update record.
-        let key = Key::Record(key.into(), self.id).encode();
-        let update = Key::TxnUpdate(self.id, (&key).into()).encode();
-        session.set(&update, vec![])?;
-        session.set(&key, serialize(&value)?)
+        buffers.entry(self.id).or_default().writes.insert(key.to_vec(), value);
+        Ok(())
     }
 }
 
@@ -307,6 +886,8 @@ pub enum Mode {
     /// transaction will be visible in the snapshot (i.e. transactions that had not committed before
     /// the snapshot transaction started will not be visible, even though they have a lower version).
     Snapshot { version: u64 },
+    /// A read-write transaction with serializable isolation, see Transaction.
+    Serializable,
 }
 
 impl Mode {
@@ -316,6 +897,7 @@ impl Mode {
             Self::ReadWrite => true,
             Self::ReadOnly => false,
             Self::Snapshot { .. } => false,
+            Self::Serializable => true,
         }
     }
 
@@ -323,6 +905,8 @@ impl Mode {
     pub fn satisfies(&self, other: &Mode) -> bool {
         match (self, other) {
             (Mode::ReadWrite, Mode::ReadOnly) => true,
+            (Mode::Serializable, Mode::ReadOnly) => true,
+            (Mode::Serializable, Mode::ReadWrite) => true,
             (Mode::Snapshot { .. }, Mode::ReadOnly) => true,
             (_, _) if self == other => true,
             (_, _) => false,
@@ -342,7 +926,7 @@ struct Snapshot {
 
 impl Snapshot {
     /// Takes a new snapshot, persisting it as `Key::TxnSnapshot(version)`.
-    fn take(session: &mut RwLockWriteGuard<Box<dyn Store>>, version: u64) -> Result<Self> {
+    fn take(session: &mut RwLockWriteGuard<BatchStore>, version: u64) -> Result<Self> {
         let mut snapshot = Self { version, invisible: HashSet::new() };
         let mut scan =
             session.scan(Range::from(Key::TxnActive(0).encode()..Key::TxnActive(version).encode()));
@@ -358,13 +942,18 @@ impl Snapshot {
     }
 
     /// Restores an existing snapshot from `Key::TxnSnapshot(version)`, or errors if not found.
-    fn restore(session: &RwLockReadGuard<Box<dyn Store>>, version: u64) -> Result<Self> {
+    fn restore(session: &RwLockReadGuard<BatchStore>, version: u64) -> Result<Self> {
         match session.get(&Key::TxnSnapshot(version).encode())? {
             Some(ref v) => Ok(Self { version, invisible: deserialize(v)? }),
             None => Err(Error::Value(format!("Snapshot not found for version {}", version))),
         }
     }
 
+    /// Returns the lowest version that is invisible in this snapshot.
+    fn min_invisible(&self) -> u64 {
+        self.invisible.iter().min().cloned().unwrap_or(self.version + 1)
+    }
+
     /// Checks whether the given version is visible in this snapshot.
     fn is_visible(&self, version: u64) -> bool {
         version <= self.version && self.invisible.get(&version).is_none()
@@ -381,8 +970,9 @@ enum Key<'a> {
     TxnActive(u64),
     /// Txn snapshot, containing concurrent active txns at start of txn.
     TxnSnapshot(u64),
-    /// Update marker for a txn ID and key, used for rollback.
-    TxnUpdate(u64, Cow<'a, [u8]>),
+    /// A range tombstone written by a txn version, deleting all older versions of keys under
+    /// the key prefix. Ordered by version first, so conflict checks can skip visible versions.
+    Tombstone(u64, Cow<'a, [u8]>),
     /// A record for a key/version pair.
     Record(Cow<'a, [u8]>, u64),
     /// Arbitrary unversioned metadata.
@@ -397,8 +987,8 @@ impl<'a> Key<'a> {
             Self::TxnNext => vec![0x01],
             Self::TxnActive(id) => [&[0x02][..], &encode_u64(id)].concat(),
             Self::TxnSnapshot(version) => [&[0x03][..], &encode_u64(version)].concat(),
-            Self::TxnUpdate(id, key) => {
-                [&[0x04][..], &encode_u64(id), &encode_bytes(&key)].concat()
+            Self::Tombstone(version, prefix) => {
+                [&[0x04][..], &encode_u64(version), &encode_bytes(&prefix)].concat()
             }
             Self::Metadata(key) => [&[0x05][..], &encode_bytes(&key)].concat(),
             Self::Record(key, version) => {
@@ -415,7 +1005,7 @@ impl<'a> Key<'a> {
             0x01 => Self::TxnNext,
             0x02 => Self::TxnActive(take_u64(bytes)?),
             0x03 => Self::TxnSnapshot(take_u64(bytes)?),
-            0x04 => Self::TxnUpdate(take_u64(bytes)?, take_bytes(bytes)?.into()),
+            0x04 => Self::Tombstone(take_u64(bytes)?, take_bytes(bytes)?.into()),
             0x05 => Self::Metadata(take_bytes(bytes)?.into()),
             0xff => Self::Record(take_bytes(bytes)?.into(), take_u64(bytes)?),
             b => return Err(Error::Internal(format!("Unknown MVCC key prefix {:x?}", b))),
@@ -437,9 +1027,10 @@ pub struct Scan {
 }
 
 impl Scan {
-    /// Creates a new scan.
-    fn new(mut scan: super::Scan, snapshot: Snapshot) -> Self {
-        // Augment the underlying scan to decode the key and filter invisible versions. We don't
+    /// Creates a new scan, hiding versions deleted by the given range tombstones.
+    fn new(mut scan: super::Scan, snapshot: Snapshot, tombstones: Vec<(u64, Vec<u8>)>) -> Self {
+        // Augment the underlying scan to decode the key and filter invisible or deleted versions,
+        // such that the last remaining version is the latest visible one. We don't
         // return the version, since we don't need it, but beware that all versions of the key
         // will still be returned - we usually only need the last, which is what the next() and
         // next_back() methods need to handle. We also don't decode the value, since we only need
@@ -447,6 +1038,7 @@ impl Scan {
         scan = Box::new(scan.filter_map(move |r| {
             r.and_then(|(k, v)| match Key::decode(&k)? {
                 Key::Record(_, version) if !snapshot.is_visible(version) => Ok(None),
+                Key::Record(key, version) if is_deleted(&tombstones, &key, version) => Ok(None),
                 Key::Record(key, _) => Ok(Some((key.into_owned(), v))),
                 k => Err(Error::Internal(format!("Expected Record, got {:?}", k))),
             })
@@ -507,6 +1099,156 @@ impl DoubleEndedIterator for Scan {
     }
 }
 
+/// A scan which merges buffered writes, i.e. of a transaction or write batch, into a scan of the
+/// store. Buffered writes replace any stored version of the same key, and buffered deletes hide it.
+struct BufferedScan {
+    /// The scan of the store.
+    scan: super::Scan,
+    /// Items taken from the front and back of the store scan, but not yet returned.
+    front: Option<(Vec<u8>, Vec<u8>)>,
+    back: Option<(Vec<u8>, Vec<u8>)>,
+    /// The buffered writes within the scan range, in key order.
+    buffered: VecDeque<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl BufferedScan {
+    /// Creates a new buffered scan.
+    fn new(scan: super::Scan, buffered: VecDeque<(Vec<u8>, Option<Vec<u8>>)>) -> Self {
+        Self { scan, front: None, back: None, buffered }
+    }
+
+    // next() with error handling.
+    fn try_next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            if self.front.is_none() {
+                self.front = self.scan.next().transpose()?.or_else(|| self.back.take());
+            }
+            let buffered = match (&self.front, self.buffered.front()) {
+                (None, None) => return Ok(None),
+                (Some(_), None) => false,
+                (None, Some(_)) => true,
+                (Some((key, _)), Some((buffered_key, _))) if key == buffered_key => {
+                    self.front = None;
+                    true
+                }
+                (Some((key, _)), Some((buffered_key, _))) => key > buffered_key,
+            };
+            if !buffered {
+                return Ok(self.front.take());
+            }
+            if let Some((key, Some(value))) = self.buffered.pop_front() {
+                return Ok(Some((key, value)));
+            }
+        }
+    }
+
+    /// next_back() with error handling.
+    fn try_next_back(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            if self.back.is_none() {
+                self.back = self.scan.next_back().transpose()?.or_else(|| self.front.take());
+            }
+            let buffered = match (&self.back, self.buffered.back()) {
+                (None, None) => return Ok(None),
+                (Some(_), None) => false,
+                (None, Some(_)) => true,
+                (Some((key, _)), Some((buffered_key, _))) if key == buffered_key => {
+                    self.back = None;
+                    true
+                }
+                (Some((key, _)), Some((buffered_key, _))) => key < buffered_key,
+            };
+            if !buffered {
+                return Ok(self.back.take());
+            }
+            if let Some((key, Some(value))) = self.buffered.pop_back() {
+                return Ok(Some((key, value)));
+            }
+        }
+    }
+}
+
+impl Iterator for BufferedScan {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+impl DoubleEndedIterator for BufferedScan {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.try_next_back().transpose()
+    }
+}
+
+/// Wraps the underlying store of an MVCC store, buffering writes while a write batch is active,
+/// see MVCC::begin_batch().
+struct BatchStore {
+    /// The underlying store.
+    store: Box<dyn Store>,
+    /// The buffered writes of the active batch, if any, keyed by key. A None value is used for
+    /// deletion.
+    batch: Option<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+    /// The in-memory transaction state when the active batch began, restored if it's aborted.
+    txn_state: Option<TxnState>,
+}
+
+impl std::fmt::Display for BatchStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.store.fmt(f)
+    }
+}
+
+impl Store for BatchStore {
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        match &mut self.batch {
+            Some(batch) => {
+                batch.insert(key.to_vec(), None);
+                Ok(())
+            }
+            None => self.store.delete(key),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &self.batch {
+            Some(_) => Ok(()),
+            None => self.store.flush(),
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.batch.as_ref().and_then(|batch| batch.get(key)) {
+            Some(value) => Ok(value.clone()),
+            None => self.store.get(key),
+        }
+    }
+
+    fn scan(&self, range: Range) -> super::Scan {
+        match &self.batch {
+            Some(batch) => {
+                let buffered = batch
+                    .iter()
+                    .filter(|(k, _)| range.contains(k))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                Box::new(BufferedScan::new(self.store.scan(range), buffered))
+            }
+            None => self.store.scan(range),
+        }
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        match &mut self.batch {
+            Some(batch) => {
+                batch.insert(key.to_vec(), Some(value));
+                Ok(())
+            }
+            None => self.store.set(key, value),
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::super::Test;
@@ -536,6 +1278,28 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_set_max_value_size() -> Result<()> {
+        let mvcc = setup().with_max_value_size(Some(4));
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01; 4])?;
+        assert_eq!(
+            txn.set(b"b", vec![0x01; 5]),
+            Err(Error::Value("Value of 5 bytes exceeds maximum size of 4 bytes".into()))
+        );
+        txn.commit()?;
+
+        // The limit also applies to resumed transactions, and rejected values aren't written.
+        let txn = mvcc.begin()?;
+        let mut txn = mvcc.resume(txn.id())?;
+        assert_eq!(txn.get(b"a")?, Some(vec![0x01; 4]));
+        assert_eq!(txn.get(b"b")?, None);
+        assert!(txn.set(b"a", vec![0x02; 5]).is_err());
+        assert_eq!(txn.get(b"a")?, Some(vec![0x01; 4]));
+        txn.rollback()?;
+        Ok(())
+    }
+
     #[test]
     fn test_begin_with_mode_readonly() -> Result<()> {
         let mvcc = setup();
@@ -707,14 +1471,152 @@ pub mod tests {
     }
 
     #[test]
-    fn test_txn_delete_idempotent() -> Result<()> {
+    fn test_txn_delete_prefix() -> Result<()> {
         let mvcc = setup();
 
         let mut txn = mvcc.begin()?;
-        txn.delete(b"key")?;
+        txn.set(b"a", vec![0x01])?;
+        txn.set(b"b", vec![0x02])?;
+        txn.set(b"ba", vec![0x02, 0x01])?;
+        txn.set(b"c", vec![0x03])?;
         txn.commit()?;
 
-        Ok(())
+        // Buffered writes are deleted too, and other txns still see the keys until commit.
+        let mut t1 = mvcc.begin()?;
+        let t2 = mvcc.begin()?;
+        t1.set(b"bb", vec![0x02, 0x02])?;
+        assert_eq!(3, t1.delete_prefix(b"b")?);
+        assert_eq!(
+            vec![(b"a".to_vec(), vec![0x01]), (b"c".to_vec(), vec![0x03])],
+            t1.scan(..)?.collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(4, t2.scan(..)?.count());
+        assert_eq!(Err(Error::Serialization), mvcc.begin()?.delete(b"ba"));
+
+        // A rollback restores the keys.
+        t1.rollback()?;
+        let mut txn = mvcc.begin()?;
+        assert_eq!(4, txn.scan(..)?.count());
+        assert_eq!(2, txn.delete_prefix(b"b")?);
+        txn.commit()?;
+
+        let txn = mvcc.begin()?;
+        assert_eq!(None, txn.get(b"b")?);
+        assert_eq!(None, txn.get(b"ba")?);
+        assert_eq!(Some(vec![0x01]), txn.get(b"a")?);
+        assert_eq!(0, txn.scan_prefix(b"b")?.count());
+
+        Ok(())
+    }
+
+    #[test]
+    // A committed prefix deletion writes a single range tombstone, which hides the older versions
+    // under the prefix from later txns, but not from concurrent ones nor newer writes.
+    fn test_txn_delete_prefix_tombstone() -> Result<()> {
+        let store = Test::new();
+        let mvcc = MVCC::new(Box::new(store.clone()));
+
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01])?;
+        txn.set(b"ba", vec![0x02, 0x01])?;
+        txn.set(b"bb", vec![0x02, 0x02])?;
+        txn.commit()?;
+
+        let t1 = mvcc.begin()?;
+        let mut t2 = mvcc.begin()?;
+        assert_eq!(2, t2.delete_prefix(b"b")?);
+        t2.set(b"bc", vec![0x02, 0x03])?;
+        assert_eq!(Some(vec![0x02, 0x03]), t2.get(b"bc")?);
+        t2.commit()?;
+        assert_eq!(1, store.scan(Range::from(vec![0x04]..vec![0x05])).count());
+        assert_eq!(4, store.scan(Range::from(vec![0xff]..)).count());
+
+        assert_eq!(3, t1.scan(..)?.count());
+        assert_eq!(Some(vec![0x02, 0x01]), t1.get(b"ba")?);
+
+        let txn = mvcc.begin()?;
+        assert_eq!(
+            vec![(b"a".to_vec(), vec![0x01]), (b"bc".to_vec(), vec![0x02, 0x03])],
+            txn.scan(..)?.collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            vec![(b"bc".to_vec(), vec![0x02, 0x03]), (b"a".to_vec(), vec![0x01])],
+            txn.scan(..)?.rev().collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(None, txn.get(b"ba")?);
+        assert_eq!(Some(vec![0x02, 0x03]), txn.get(b"bc")?);
+
+        Ok(())
+    }
+
+    #[test]
+    // Prefix deletions conflict with concurrent writes and prefix deletions under the prefix, and
+    // delete nothing on conflict.
+    fn test_txn_delete_prefix_conflict() -> Result<()> {
+        let mvcc = setup();
+
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01])?;
+        txn.set(b"ba", vec![0x02])?;
+        txn.set(b"c", vec![0x03])?;
+        txn.commit()?;
+
+        // Committed concurrent writes.
+        let mut t1 = mvcc.begin()?;
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"bb", vec![0x02])?;
+        t2.commit()?;
+        assert_eq!(Err(Error::Serialization), t1.delete_prefix(b"b"));
+        assert_eq!(1, t1.delete_prefix(b"c")?);
+        t1.rollback()?;
+
+        // Committed concurrent prefix deletions.
+        let mut t3 = mvcc.begin()?;
+        let mut t4 = mvcc.begin()?;
+        t3.delete_prefix(b"b")?;
+        t3.commit()?;
+        assert_eq!(Err(Error::Serialization), t4.set(b"bc", vec![0x02]));
+        assert_eq!(Err(Error::Serialization), t4.delete_prefix(b"bc"));
+        t4.set(b"d", vec![0x04])?;
+        t4.commit()?;
+
+        // Uncommitted concurrent writes, for any of the prefixes.
+        let mut t5 = mvcc.begin()?;
+        let mut t6 = mvcc.begin()?;
+        t6.set(b"cc", vec![0x03])?;
+        assert_eq!(Err(Error::Serialization), t5.delete_prefixes(&[b"a", b"c"]));
+        assert_eq!(Some(vec![0x01]), t5.get(b"a")?);
+        assert_eq!(1, t6.delete_prefix(b"a")?);
+        t6.rollback()?;
+        t5.rollback()?;
+
+        // Serializable txns conflict with prefix deletions of keys they read.
+        let mut t7 = mvcc.begin_with_mode(Mode::Serializable)?;
+        let mut t8 = mvcc.begin()?;
+        assert_eq!(Some(vec![0x01]), t7.get(b"a")?);
+        t8.delete_prefix(b"a")?;
+        t8.commit()?;
+        t7.set(b"e", vec![0x05])?;
+        assert_eq!(Err(Error::Serialization), t7.commit());
+
+        assert_eq!(
+            Err(Error::Internal("Scan prefix cannot be empty".into())),
+            mvcc.begin()?.delete_prefix(b"")
+        );
+        assert_eq!(Err(Error::ReadOnly), mvcc.begin_with_mode(Mode::ReadOnly)?.delete_prefix(b"a"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_txn_delete_idempotent() -> Result<()> {
+        let mvcc = setup();
+
+        let mut txn = mvcc.begin()?;
+        txn.delete(b"key")?;
+        txn.commit()?;
+
+        Ok(())
     }
 
     #[test]
@@ -957,6 +1859,16 @@ pub mod tests {
         assert_eq!(None, scan.next_back().transpose()?);
         std::mem::drop(scan);
 
+        // Scan after a key
+        assert_eq!(
+            vec![(b"bb".to_vec(), vec![0x02, 0x02]), (b"bc".to_vec(), vec![0x02, 0x03])],
+            txn.scan_prefix_after(b"b", Some(b"ba"))?.collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            Vec::<(Vec<u8>, Vec<u8>)>::new(),
+            txn.scan_prefix_after(b"b", Some(b"bc"))?.collect::<Result<Vec<_>>>()?
+        );
+
         txn.commit()?;
         Ok(())
     }
@@ -1015,6 +1927,104 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_txn_read_your_writes() -> Result<()> {
+        let mvcc = setup();
+
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01])?;
+        txn.set(b"c", vec![0x03])?;
+        txn.set(b"e", vec![0x05])?;
+        txn.commit()?;
+
+        // The transaction sees its own writes, replacing and deleting committed keys, while other
+        // transactions don't.
+        let mut txn = mvcc.begin()?;
+        let other = mvcc.begin()?;
+        txn.set(b"a", vec![0x0a])?;
+        txn.set(b"b", vec![0x02])?;
+        txn.delete(b"c")?;
+        txn.set(b"f", vec![0x06])?;
+        assert_eq!(Some(vec![0x0a]), txn.get(b"a")?);
+        assert_eq!(Some(vec![0x02]), txn.get(b"b")?);
+        assert_eq!(None, txn.get(b"c")?);
+        assert_eq!(Some(vec![0x01]), other.get(b"a")?);
+        assert_eq!(None, other.get(b"b")?);
+        assert_eq!(Some(vec![0x03]), other.get(b"c")?);
+
+        let expect = vec![
+            (b"a".to_vec(), vec![0x0a]),
+            (b"b".to_vec(), vec![0x02]),
+            (b"e".to_vec(), vec![0x05]),
+            (b"f".to_vec(), vec![0x06]),
+        ];
+        assert_eq!(expect, txn.scan(..)?.collect::<Result<Vec<_>>>()?);
+        assert_eq!(
+            expect.iter().rev().cloned().collect::<Vec<_>>(),
+            txn.scan(..)?.rev().collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(
+            expect[1..3].to_vec(),
+            txn.scan(b"b".to_vec()..b"f".to_vec())?.collect::<Result<Vec<_>>>()?
+        );
+
+        // Alternate forward/backward scan
+        let mut scan = txn.scan(..)?;
+        assert_eq!(Some((b"a".to_vec(), vec![0x0a])), scan.next().transpose()?);
+        assert_eq!(Some((b"f".to_vec(), vec![0x06])), scan.next_back().transpose()?);
+        assert_eq!(Some((b"e".to_vec(), vec![0x05])), scan.next_back().transpose()?);
+        assert_eq!(Some((b"b".to_vec(), vec![0x02])), scan.next().transpose()?);
+        assert_eq!(None, scan.next().transpose()?);
+        assert_eq!(None, scan.next_back().transpose()?);
+        std::mem::drop(scan);
+
+        // The writes survive resuming the transaction, and are visible to others once committed.
+        let txn = mvcc.resume(txn.id())?;
+        assert_eq!(Some(vec![0x0a]), txn.get(b"a")?);
+        txn.commit()?;
+        let txn = mvcc.begin()?;
+        assert_eq!(expect, txn.scan(..)?.collect::<Result<Vec<_>>>()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_txn_rollback_no_writes() -> Result<()> {
+        let store = Test::new();
+        let mvcc = MVCC::new(Box::new(store.clone()));
+        let dump = || -> Result<Vec<(Vec<u8>, Vec<u8>)>> { store.scan(Range::from(..)).collect() };
+
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01])?;
+        txn.commit()?;
+
+        // Buffered writes don't touch the store, and rolling back only removes the active marker.
+        let mut txn = mvcc.begin()?;
+        let id = txn.id();
+        let begun = dump()?;
+        txn.set(b"a", vec![0x02])?;
+        txn.set(b"b", vec![0x02])?;
+        txn.delete(b"a")?;
+        assert_eq!(begun, dump()?);
+        txn.rollback()?;
+        assert_eq!(
+            begun
+                .into_iter()
+                .filter(|(k, _)| k != &Key::TxnActive(id).encode())
+                .collect::<Vec<_>>(),
+            dump()?
+        );
+
+        // The writes are gone, and don't conflict with other transactions.
+        let mut txn = mvcc.begin()?;
+        assert_eq!(Some(vec![0x01]), txn.get(b"a")?);
+        assert_eq!(None, txn.get(b"b")?);
+        txn.set(b"b", vec![0x03])?;
+        txn.commit()?;
+
+        Ok(())
+    }
+
     #[test]
     // A dirty write is when t2 overwrites an uncommitted value written by t1.
     fn test_txn_anomaly_dirty_write() -> Result<()> {
@@ -1132,32 +2142,252 @@ pub mod tests {
         Ok(())
     }
 
-    /* FIXME To avoid write skew we need to implement serializable snapshot isolation.
     #[test]
-    // Write skew is when t1 reads b and writes it to a while t2 reads a and writes it to b.¨
+    // Write skew is when t1 reads a and b and writes a, while t2 reads a and b and writes b.
+    // Snapshot isolation allows this, serializable isolation does not.
     fn test_txn_anomaly_write_skew() -> Result<()> {
         let mvcc = setup();
 
         let mut t0 = mvcc.begin()?;
         t0.set(b"a", b"1".to_vec())?;
-        t0.set(b"b", b"2".to_vec())?;
+        t0.set(b"b", b"1".to_vec())?;
         t0.commit()?;
 
         let mut t1 = mvcc.begin()?;
         let mut t2 = mvcc.begin()?;
-
         assert_eq!(Some(b"1".to_vec()), t1.get(b"a")?);
-        assert_eq!(Some(b"2".to_vec()), t2.get(b"b")?);
+        assert_eq!(Some(b"1".to_vec()), t1.get(b"b")?);
+        assert_eq!(Some(b"1".to_vec()), t2.get(b"a")?);
+        assert_eq!(Some(b"1".to_vec()), t2.get(b"b")?);
+        t1.set(b"a", b"0".to_vec())?;
+        t2.set(b"b", b"0".to_vec())?;
+        t1.commit()?;
+        t2.commit()?;
+
+        let mut t3 = mvcc.begin_with_mode(Mode::Serializable)?;
+        let mut t4 = mvcc.begin_with_mode(Mode::Serializable)?;
+        assert_eq!(Some(b"0".to_vec()), t3.get(b"a")?);
+        assert_eq!(Some(b"0".to_vec()), t3.get(b"b")?);
+        assert_eq!(Some(b"0".to_vec()), t4.get(b"a")?);
+        assert_eq!(Some(b"0".to_vec()), t4.get(b"b")?);
+        t3.set(b"a", b"1".to_vec())?;
+        t4.set(b"b", b"1".to_vec())?;
+        let id = t4.id();
+        t3.commit()?;
+        assert_eq!(Err(Error::Serialization), t4.commit());
 
-        // Some of the following operations should error
-        t1.set(b"a", b"2".to_vec())?;
+        // The failed transaction remains active until rolled back.
+        let t4 = mvcc.resume(id)?;
+        assert_eq!(Some(b"1".to_vec()), t4.get(b"b")?);
+        t4.rollback()?;
+
+        let t5 = mvcc.begin_with_mode(Mode::ReadOnly)?;
+        assert_eq!(Some(b"1".to_vec()), t5.get(b"a")?);
+        assert_eq!(Some(b"0".to_vec()), t5.get(b"b")?);
+
+        Ok(())
+    }
+
+    #[test]
+    // A serializable transaction errors if a concurrent transaction inserts into a range it scanned.
+    fn test_txn_serializable_phantom() -> Result<()> {
+        let mvcc = setup();
+
+        let mut t0 = mvcc.begin()?;
+        t0.set(b"a", b"1".to_vec())?;
+        t0.set(b"c", b"1".to_vec())?;
+        t0.commit()?;
+
+        let mut t1 = mvcc.begin_with_mode(Mode::Serializable)?;
+        let mut t2 = mvcc.begin()?;
+        assert_eq!(1, t1.scan(b"a".to_vec()..b"c".to_vec())?.count());
         t2.set(b"b", b"1".to_vec())?;
+        t2.commit()?;
+        t1.set(b"d", b"1".to_vec())?;
+        let id = t1.id();
+        assert_eq!(Err(Error::Serialization), t1.commit());
+        mvcc.resume(id)?.rollback()?;
+
+        // Writes outside of the scanned range don't conflict, nor do reads of own writes.
+        let mut t3 = mvcc.begin_with_mode(Mode::Serializable)?;
+        let mut t4 = mvcc.begin()?;
+        t3.set(b"e", b"1".to_vec())?;
+        assert_eq!(Some(b"1".to_vec()), t3.get(b"e")?);
+        assert_eq!(2, t3.scan(b"a".to_vec()..b"c".to_vec())?.count());
+        t4.set(b"c", b"2".to_vec())?;
+        t4.commit()?;
+        t3.commit()?;
 
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum() -> Result<()> {
+        let mvcc = setup();
+
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+        t1.set(b"b", vec![0x01])?;
+        t1.set(b"c", vec![0x01])?;
         t1.commit()?;
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"a", vec![0x02])?;
+        t2.delete(b"b")?;
         t2.commit()?;
+        let t3 = mvcc.begin_with_mode(Mode::ReadOnly)?;
+        let mut t4 = mvcc.begin()?;
+        t4.set(b"a", vec![0x04])?;
+        t4.commit()?;
+
+        // The active t3 holds back the watermark, so only the versions that t3 can't see are
+        // removed, i.e. a@1, b@1, and the deletion b@2. Old snapshots are removed too.
+        let vacuum = mvcc.vacuum()?;
+        assert_eq!(3, vacuum.versions);
+        assert!(vacuum.bytes > 0);
+        assert_eq!(Some(vec![0x02]), t3.get(b"a")?);
+        assert_eq!(None, t3.get(b"b")?);
+        assert_eq!(Some(vec![0x01]), t3.get(b"c")?);
+        assert_eq!(
+            mvcc.begin_with_mode(Mode::Snapshot { version: 1 }).err(),
+            Some(Error::Value("Snapshot not found for version 1".into()))
+        );
+        assert_eq!(Vacuum { versions: 0, bytes: 0 }, mvcc.vacuum()?);
 
+        // Once t3 completes, a@2 can be removed as well.
+        t3.commit()?;
+        assert_eq!(1, mvcc.vacuum()?.versions);
+        let txn = mvcc.begin_with_mode(Mode::ReadOnly)?;
+        assert_eq!(Some(vec![0x04]), txn.get(b"a")?);
+        assert_eq!(None, txn.get(b"b")?);
+        assert_eq!(Some(vec![0x01]), txn.get(b"c")?);
+        assert_eq!(
+            vec![(b"a".to_vec(), vec![0x04]), (b"c".to_vec(), vec![0x01])],
+            txn.scan(..)?.collect::<Result<Vec<_>>>()?
+        );
+        txn.commit()?;
         Ok(())
-    }*/
+    }
+
+    #[test]
+    // Range tombstones below the watermark are removed along with the versions they hide.
+    fn test_vacuum_tombstone() -> Result<()> {
+        let store = Test::new();
+        let mvcc = MVCC::new(Box::new(store.clone()));
+
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+        t1.set(b"ba", vec![0x01])?;
+        t1.set(b"bb", vec![0x01])?;
+        t1.commit()?;
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"ba", vec![0x02])?;
+        t2.commit()?;
+        let t3 = mvcc.begin_with_mode(Mode::ReadOnly)?;
+        let mut t4 = mvcc.begin()?;
+        t4.delete_prefix(b"b")?;
+        t4.set(b"bc", vec![0x04])?;
+        t4.commit()?;
+
+        // The active t3 can still see the deleted keys, so only ba@1 is removed.
+        assert_eq!(1, mvcc.vacuum()?.versions);
+        assert_eq!(Some(vec![0x02]), t3.get(b"ba")?);
+        assert_eq!(Some(vec![0x01]), t3.get(b"bb")?);
+        t3.commit()?;
+
+        // Once t3 completes, ba@2, bb@1 and the tombstone are removed.
+        assert_eq!(2, mvcc.vacuum()?.versions);
+        assert_eq!(0, store.scan(Range::from(vec![0x04]..vec![0x05])).count());
+        let txn = mvcc.begin_with_mode(Mode::ReadOnly)?;
+        assert_eq!(
+            vec![(b"a".to_vec(), vec![0x01]), (b"bc".to_vec(), vec![0x04])],
+            txn.scan(..)?.collect::<Result<Vec<_>>>()?
+        );
+        assert_eq!(None, txn.get(b"ba")?);
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum_snapshot() -> Result<()> {
+        let mvcc = setup();
+
+        let mut t1 = mvcc.begin()?;
+        t1.set(b"a", vec![0x01])?;
+        t1.commit()?;
+        let mut t2 = mvcc.begin()?;
+        let t3 = mvcc.begin()?;
+        t2.set(b"a", vec![0x02])?;
+        t2.commit()?;
+        t3.commit()?;
+
+        // A snapshot transaction at version 3 can't see t2's write, since t2 was active when t3
+        // began. It therefore holds back the watermark, and a@1 is retained.
+        let snapshot = mvcc.begin_with_mode(Mode::Snapshot { version: 3 })?;
+        assert_eq!(0, mvcc.vacuum()?.versions);
+        assert_eq!(Some(vec![0x01]), snapshot.get(b"a")?);
+        snapshot.commit()?;
+
+        // Once it completes, a@1 is removed, and so is the snapshot for version 3.
+        assert_eq!(1, mvcc.vacuum()?.versions);
+        assert_eq!(
+            mvcc.begin_with_mode(Mode::Snapshot { version: 3 }).err(),
+            Some(Error::Value("Snapshot not found for version 3".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    // Reads are served from the cache when the cached version is visible, and commits update it.
+    fn test_cache() -> Result<()> {
+        let store = Test::new();
+        let mvcc = MVCC::new(Box::new(store.clone())).with_cache(10);
+
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01])?;
+        txn.commit()?;
+
+        // Returns the value of a key, and the number of key/value pairs read from the store.
+        let get = |txn: &Transaction, key: &[u8]| -> Result<(Option<Vec<u8>>, u64)> {
+            let reads = store.reads();
+            let value = txn.get(key)?;
+            Ok((value, store.reads() - reads))
+        };
+
+        let t1 = mvcc.begin()?;
+        assert_eq!((Some(vec![0x01]), 1), get(&t1, b"a")?);
+        assert_eq!((Some(vec![0x01]), 0), get(&t1, b"a")?);
+        assert_eq!(None, t1.get(b"b")?);
+
+        // A commit updates the cache. The concurrent t1 can't see the new version, so it reads
+        // from the store, while new txns read from the cache.
+        let mut t2 = mvcc.begin()?;
+        t2.set(b"a", vec![0x02])?;
+        t2.set(b"b", vec![0x02])?;
+        t2.commit()?;
+        assert_eq!((Some(vec![0x01]), 1), get(&t1, b"a")?);
+        assert_eq!(None, t1.get(b"b")?);
+        let t3 = mvcc.begin()?;
+        assert_eq!((Some(vec![0x02]), 0), get(&t3, b"a")?);
+        assert_eq!((Some(vec![0x02]), 0), get(&t3, b"b")?);
+
+        // Range deletions hide cached versions, and vacuums clear the cache.
+        let mut t4 = mvcc.begin()?;
+        t4.delete_prefix(b"a")?;
+        t4.commit()?;
+        let t5 = mvcc.begin()?;
+        assert_eq!(None, t5.get(b"a")?);
+        assert_eq!(Some(vec![0x02]), t3.get(b"a")?);
+        t5.commit()?;
+        t1.commit()?;
+        t3.commit()?;
+        mvcc.vacuum()?;
+        let txn = mvcc.begin()?;
+        assert_eq!((Some(vec![0x02]), 1), get(&txn, b"b")?);
+        assert_eq!((Some(vec![0x02]), 0), get(&txn, b"b")?);
+        assert_eq!(None, txn.get(b"a")?);
+
+        Ok(())
+    }
 
     #[test]
     fn test_metadata() -> Result<()> {
@@ -1170,6 +2400,76 @@ pub mod tests {
 
         mvcc.set_metadata(b"foo", b"baz".to_vec())?;
         assert_eq!(Some(b"baz".to_vec()), mvcc.get_metadata(b"foo")?);
+
+        mvcc.delete_metadata(b"foo")?;
+        assert_eq!(None, mvcc.get_metadata(b"foo")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch() -> Result<()> {
+        let store = Test::new();
+        let mvcc = MVCC::new(Box::new(store.clone()));
+        let dump = || -> Result<Vec<(Vec<u8>, Vec<u8>)>> { store.scan(Range::from(..)).collect() };
+
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x01])?;
+        txn.set(b"b", vec![0x01])?;
+        txn.commit()?;
+        let mut active = mvcc.begin()?;
+        active.set(b"x", vec![0x01])?;
+        let before = dump()?;
+
+        // Writes in a batch don't touch the store, but are visible to reads and scans.
+        mvcc.begin_batch()?;
+        active.set(b"y", vec![0x01])?;
+        assert_eq!(mvcc.begin_batch(), Err(Error::Internal("Write batch already active".into())));
+        let mut txn = mvcc.begin()?;
+        txn.set(b"a", vec![0x02])?;
+        txn.delete(b"b")?;
+        txn.set(b"c", vec![0x02])?;
+        txn.commit()?;
+        mvcc.set_metadata(b"foo", b"bar".to_vec())?;
+        assert_eq!(before, dump()?);
+        let txn = mvcc.begin()?;
+        assert_eq!(
+            txn.scan(..)?.collect::<Result<Vec<_>>>()?,
+            vec![(b"a".to_vec(), vec![0x02]), (b"c".to_vec(), vec![0x02])]
+        );
+        assert_eq!(
+            txn.scan(..)?.rev().collect::<Result<Vec<_>>>()?,
+            vec![(b"c".to_vec(), vec![0x02]), (b"a".to_vec(), vec![0x02])]
+        );
+        txn.rollback()?;
+        assert_eq!(Some(b"bar".to_vec()), mvcc.get_metadata(b"foo")?);
+
+        // Aborting the batch discards all of its writes.
+        mvcc.abort_batch()?;
+        assert_eq!(mvcc.abort_batch(), Err(Error::Internal("No active write batch".into())));
+        assert_eq!(before, dump()?);
+        assert_eq!(None, mvcc.get_metadata(b"foo")?);
+        let txn = mvcc.begin()?;
+        assert_eq!(Some(vec![0x01]), txn.get(b"a")?);
+        assert_eq!(Some(vec![0x01]), txn.get(b"b")?);
+        txn.rollback()?;
+
+        // It also reverts the buffered writes of active transactions.
+        assert_eq!(Some(vec![0x01]), active.get(b"x")?);
+        assert_eq!(None, active.get(b"y")?);
+        active.rollback()?;
+
+        // Committing the batch writes them to the store.
+        mvcc.begin_batch()?;
+        let mut txn = mvcc.begin()?;
+        txn.delete(b"b")?;
+        txn.commit()?;
+        mvcc.set_metadata(b"foo", b"bar".to_vec())?;
+        mvcc.commit_batch()?;
+        assert_ne!(before, dump()?);
+        assert_eq!(Some(b"bar".to_vec()), mvcc.get_metadata(b"foo")?);
+        let txn = mvcc.begin()?;
+        assert_eq!(Some(vec![0x01]), txn.get(b"a")?);
+        assert_eq!(None, txn.get(b"b")?);
         Ok(())
     }
 }
@@ -2,6 +2,7 @@ use super::{Memory, Range, Scan, Store};
 use crate::error::Result;
 
 use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 /// Key-value storage backend for testing. Protects an inner Memory backend using a mutex, so it can
@@ -9,12 +10,18 @@ use std::sync::{Arc, RwLock};
 #[derive(Clone)]
 pub struct Test {
     kv: Arc<RwLock<Memory>>,
+    reads: Arc<AtomicU64>,
 }
 
 impl Test {
     /// Creates a new Test key-value storage engine.
     pub fn new() -> Self {
-        Self { kv: Arc::new(RwLock::new(Memory::new())) }
+        Self { kv: Arc::new(RwLock::new(Memory::new())), reads: Arc::new(AtomicU64::new(0)) }
+    }
+
+    /// Returns the number of key/value pairs read so far, by gets and scans.
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::SeqCst)
     }
 }
 
@@ -34,12 +41,15 @@ impl Store for Test {
     }
 
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
         self.kv.read()?.get(key)
     }
 
     fn scan(&self, range: Range) -> Scan {
         // Since the mutex guard is scoped to this method, we simply buffer the result.
-        Box::new(self.kv.read().unwrap().scan(range).collect::<Vec<Result<_>>>().into_iter())
+        let items = self.kv.read().unwrap().scan(range).collect::<Vec<Result<_>>>();
+        self.reads.fetch_add(items.len() as u64, Ordering::SeqCst);
+        Box::new(items.into_iter())
     }
 
     fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
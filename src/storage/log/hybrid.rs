@@ -8,7 +8,28 @@ use std::fs::{create_dir_all, File, OpenOptions};
 use std::io::{BufReader, BufWriter, Read, Seek as _, SeekFrom, Write};
 use std::ops::Bound;
 use std::path::Path;
-use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// The durability mode of a hybrid log, i.e. when writes are fsynced to disk. This trades off
+/// write latency against the risk of losing writes. All modes fsync on clean shutdown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Durability {
+    /// Fsyncs every write before returning. Committed entries and metadata survive process
+    /// crashes, machine crashes, and power loss, but every write pays the fsync latency.
+    Sync,
+    /// Fsyncs writes in the background at the given interval, and also after the given number of
+    /// writes if non-zero. Writes survive process crashes, since they are buffered by the
+    /// operating system, but the writes since the last fsync can be lost on machine crashes or
+    /// power loss.
+    Periodic { interval: Duration, writes: u64 },
+    /// Never fsyncs writes, leaving it to the operating system. Writes survive process crashes,
+    /// but an unbounded number of writes can be lost on machine crashes or power loss.
+    None,
+}
 
 /// A hybrid log store, storing committed entries in an append-only file, uncommitted entries
 /// in memory, and metadata in a separate file (should be an on-disk key-value store).
@@ -21,6 +42,11 @@ use std::sync::{Mutex, MutexGuard};
 /// scanning the file, since maintaining the index in a separate file requires additional fsyncing
 /// which is expensive. Since datasets are expected to be small, scanning the file on startup is
 /// reasonably cheap.
+///
+/// Writes are fsynced according to the durability mode, or when explicitly synced. Losing committed
+/// entries can violate Raft safety guarantees, so anything but Durability::Sync relies on not
+/// losing a majority of nodes at the same time. Raft always syncs term and vote metadata, which
+/// is rarely written.
 pub struct Hybrid {
     /// The append-only log file. Protected by a mutex for interior mutability (i.e. read seeks).
     file: Mutex<File>,
@@ -33,8 +59,12 @@ pub struct Hybrid {
     /// The file used to store metadata.
     /// FIXME Should be an on-disk B-tree key-value store.
     metadata_file: File,
-    /// If true, fsync writes.
-    sync: bool,
+    /// The durability mode.
+    durability: Durability,
+    /// The number of writes since the last fsync, shared with the flusher.
+    unsynced: Arc<AtomicU64>,
+    /// The background flusher, for Durability::Periodic.
+    flusher: Option<Flusher>,
 }
 
 impl Display for Hybrid {
@@ -45,7 +75,7 @@ impl Display for Hybrid {
 
 impl Hybrid {
     /// Creates or opens a new hybrid log, with files in the given directory.
-    pub fn new(dir: &Path, sync: bool) -> Result<Self> {
+    pub fn new(dir: &Path, durability: Durability) -> Result<Self> {
         create_dir_all(dir)?;
 
         let file =
@@ -57,16 +87,47 @@ impl Hybrid {
             .create(true)
             .open(dir.join("raft-metadata"))?;
 
+        let unsynced = Arc::new(AtomicU64::new(0));
+        let flusher = match durability {
+            Durability::Periodic { interval, .. } => Some(Flusher::start(
+                interval,
+                vec![file.try_clone()?, metadata_file.try_clone()?],
+                unsynced.clone(),
+            )?),
+            Durability::Sync | Durability::None => None,
+        };
+
         Ok(Self {
             index: Self::build_index(&file)?,
             file: Mutex::new(file),
             uncommitted: VecDeque::new(),
             metadata: Self::load_metadata(&metadata_file)?,
             metadata_file,
-            sync,
+            durability,
+            unsynced,
+            flusher,
         })
     }
 
+    /// Handles durability after a write to the log file or metadata file, fsyncing as needed.
+    /// The log file must not be locked by the caller.
+    fn written(&self, log: bool) -> Result<()> {
+        match self.durability {
+            Durability::Sync if log => self.file.lock()?.sync_data()?,
+            Durability::Sync => self.metadata_file.sync_data()?,
+            Durability::Periodic { writes, .. } => {
+                let unsynced = self.unsynced.fetch_add(1, Ordering::SeqCst) + 1;
+                if writes > 0 && unsynced >= writes {
+                    self.unsynced.store(0, Ordering::SeqCst);
+                    self.metadata_file.sync_data()?;
+                    self.file.lock()?.sync_data()?;
+                }
+            }
+            Durability::None => {}
+        }
+        Ok(())
+    }
+
     /// Builds the index by scanning the log file.
     fn build_index(file: &File) -> Result<BTreeMap<u64, (u64, u32)>> {
         let filesize = file.metadata()?.len();
@@ -140,10 +201,8 @@ impl Store for Hybrid {
         }
         bufwriter.flush()?;
         drop(bufwriter);
-        if self.sync {
-            file.sync_data()?;
-        }
-        Ok(())
+        drop(file);
+        self.written(true)
     }
 
     fn committed(&self) -> u64 {
@@ -246,8 +305,14 @@ impl Store for Hybrid {
         self.metadata_file.set_len(0)?;
         self.metadata_file.seek(SeekFrom::Start(0))?;
         bincode::serialize_into(&mut self.metadata_file, &self.metadata)?;
-        if self.sync {
+        self.written(false)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if self.durability != Durability::Sync {
             self.metadata_file.sync_data()?;
+            self.file.lock()?.sync_data()?;
+            self.unsynced.store(0, Ordering::SeqCst);
         }
         Ok(())
     }
@@ -256,8 +321,49 @@ impl Store for Hybrid {
 impl Drop for Hybrid {
     /// Attempt to fsync data on drop, in case we're running without sync.
     fn drop(&mut self) {
+        self.flusher.take();
         self.metadata_file.sync_all().ok();
         self.file.lock().map(|f| f.sync_all()).ok();
+        self.unsynced.store(0, Ordering::SeqCst);
+    }
+}
+
+/// A background thread which periodically fsyncs files with unsynced writes. It is stopped when
+/// dropped.
+struct Flusher {
+    shutdown: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Flusher {
+    /// Starts a flusher for the given files, fsyncing them at the given interval if unsynced is
+    /// non-zero, and then resetting it.
+    fn start(interval: Duration, files: Vec<File>, unsynced: Arc<AtomicU64>) -> Result<Self> {
+        let (shutdown, rx) = channel();
+        let thread =
+            std::thread::Builder::new().name("toydb-log-flusher".into()).spawn(move || loop {
+                match rx.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+                }
+                if unsynced.swap(0, Ordering::SeqCst) > 0 {
+                    for file in &files {
+                        if let Err(err) = file.sync_data() {
+                            log::error!("Failed to fsync log: {}", err);
+                        }
+                    }
+                }
+            })?;
+        Ok(Self { shutdown: Some(shutdown), thread: Some(thread) })
+    }
+}
+
+impl Drop for Flusher {
+    fn drop(&mut self) {
+        self.shutdown.take();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
     }
 }
 
@@ -273,7 +379,7 @@ impl<'a> Read for MutexReader<'a> {
 impl super::TestSuite<Hybrid> for Hybrid {
     fn setup() -> Result<Self> {
         let dir = tempdir::TempDir::new("toydb")?;
-        Hybrid::new(dir.as_ref(), false)
+        Hybrid::new(dir.as_ref(), Durability::None)
     }
 }
 
@@ -286,7 +392,7 @@ fn tests() -> Result<()> {
 #[test]
 fn test_persistent() -> Result<()> {
     let dir = tempdir::TempDir::new("toydb")?;
-    let mut l = Hybrid::new(dir.as_ref(), true)?;
+    let mut l = Hybrid::new(dir.as_ref(), Durability::Sync)?;
 
     l.append(vec![0x01])?;
     l.append(vec![0x02])?;
@@ -295,7 +401,7 @@ fn test_persistent() -> Result<()> {
     l.append(vec![0x05])?;
     l.commit(3)?;
 
-    let l = Hybrid::new(dir.as_ref(), true)?;
+    let l = Hybrid::new(dir.as_ref(), Durability::Sync)?;
 
     assert_eq!(
         vec![vec![1], vec![2], vec![3]],
@@ -304,3 +410,66 @@ fn test_persistent() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_durability_sync() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+    let mut l = Hybrid::new(dir.as_ref(), Durability::Sync)?;
+    l.append(vec![0x01])?;
+    l.append(vec![0x02])?;
+    l.commit(2)?;
+    l.set_metadata(b"a", vec![0x01])?;
+    assert_eq!(l.unsynced.load(Ordering::SeqCst), 0);
+
+    // Skip the fsync on drop, since writes should already have been fsynced.
+    std::mem::forget(l);
+
+    let l = Hybrid::new(dir.as_ref(), Durability::Sync)?;
+    assert_eq!(vec![vec![1], vec![2]], l.scan(Range::from(..)).collect::<Result<Vec<_>>>()?);
+    assert_eq!(Some(vec![0x01]), l.get_metadata(b"a")?);
+    Ok(())
+}
+
+#[test]
+fn test_durability_periodic() -> Result<()> {
+    let dir = tempdir::TempDir::new("toydb")?;
+
+    // The flusher fsyncs writes in the background.
+    let durability = Durability::Periodic { interval: Duration::from_millis(10), writes: 0 };
+    let mut l = Hybrid::new(dir.as_ref(), durability)?;
+    l.append(vec![0x01])?;
+    l.commit(1)?;
+    l.set_metadata(b"a", vec![0x01])?;
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while l.unsynced.load(Ordering::SeqCst) > 0 {
+        assert!(std::time::Instant::now() < deadline, "writes not fsynced by flusher");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    drop(l);
+
+    // Writes are also fsynced after the given number of writes.
+    let durability = Durability::Periodic { interval: Duration::from_secs(3600), writes: 2 };
+    let mut l = Hybrid::new(dir.as_ref(), durability)?;
+    l.append(vec![0x02])?;
+    l.commit(2)?;
+    assert_eq!(l.unsynced.load(Ordering::SeqCst), 1);
+    l.set_metadata(b"b", vec![0x02])?;
+    assert_eq!(l.unsynced.load(Ordering::SeqCst), 0);
+
+    // Unsynced writes are fsynced on shutdown, without waiting for the interval.
+    l.append(vec![0x03])?;
+    l.commit(3)?;
+    let unsynced = l.unsynced.clone();
+    assert_eq!(unsynced.load(Ordering::SeqCst), 1);
+    drop(l);
+    assert_eq!(unsynced.load(Ordering::SeqCst), 0);
+
+    let l = Hybrid::new(dir.as_ref(), Durability::Sync)?;
+    assert_eq!(
+        vec![vec![1], vec![2], vec![3]],
+        l.scan(Range::from(..)).collect::<Result<Vec<_>>>()?
+    );
+    assert_eq!(Some(vec![0x01]), l.get_metadata(b"a")?);
+    assert_eq!(Some(vec![0x02]), l.get_metadata(b"b")?);
+    Ok(())
+}
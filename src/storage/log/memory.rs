@@ -104,6 +104,10 @@ impl Store for Memory {
         self.metadata.insert(key.to_vec(), value);
         Ok(())
     }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
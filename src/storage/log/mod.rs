@@ -3,7 +3,7 @@ mod memory;
 #[cfg(test)]
 mod test;
 
-pub use hybrid::Hybrid;
+pub use hybrid::{Durability, Hybrid};
 pub use memory::Memory;
 #[cfg(test)]
 pub use test::Test;
@@ -46,6 +46,10 @@ pub trait Store: Display + Sync + Send {
     /// Sets a metadata value.
     fn set_metadata(&mut self, key: &[u8], value: Vec<u8>) -> Result<()>;
 
+    /// Fsyncs all previous writes to durable storage, regardless of the store's durability
+    /// settings. This is a durability barrier for writes that must not be lost, e.g. Raft votes.
+    fn sync(&mut self) -> Result<()>;
+
     /// Returns true if the log has no entries.
     fn is_empty(&self) -> bool {
         self.len() == 0
@@ -160,6 +164,8 @@ trait TestSuite<S: Store> {
         s.set_metadata(b"a", vec![0x01])?;
         assert_eq!(Some(vec![0x01]), s.get_metadata(b"a")?);
         assert_eq!(None, s.get_metadata(b"b")?);
+        s.sync()?;
+        assert_eq!(Some(vec![0x01]), s.get_metadata(b"a")?);
         Ok(())
     }
 
@@ -65,6 +65,10 @@ impl Store for Test {
     fn set_metadata(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
         self.store.write()?.set_metadata(key, value)
     }
+
+    fn sync(&mut self) -> Result<()> {
+        self.store.write()?.sync()
+    }
 }
 
 #[cfg(test)]
@@ -4,15 +4,16 @@ use super::{assert_row, assert_rows, setup};
 
 use toydb::error::{Error, Result};
 use toydb::raft;
-use toydb::sql::engine::{Mode, Status};
+use toydb::sql::engine::{Change, Mode, Status};
 use toydb::sql::execution::ResultSet;
 use toydb::sql::schema;
-use toydb::sql::types::{Column, DataType, Value};
+use toydb::sql::types::{Column, DataType, Rows, Value};
 use toydb::storage::kv;
 use toydb::Client;
 
 use pretty_assertions::assert_eq;
 use serial_test::serial;
+use std::collections::HashMap;
 
 #[tokio::test(core_threads = 2)]
 #[serial]
@@ -34,6 +35,7 @@ async fn get_table() -> Result<()> {
                     primary_key: true,
                     nullable: false,
                     default: None,
+                    generated: None,
                     unique: true,
                     index: false,
                     references: None,
@@ -44,6 +46,7 @@ async fn get_table() -> Result<()> {
                     primary_key: false,
                     nullable: false,
                     default: None,
+                    generated: None,
                     unique: false,
                     index: false,
                     references: None,
@@ -54,6 +57,7 @@ async fn get_table() -> Result<()> {
                     primary_key: false,
                     nullable: false,
                     default: None,
+                    generated: None,
                     unique: false,
                     index: false,
                     references: Some("studios".into()),
@@ -64,6 +68,7 @@ async fn get_table() -> Result<()> {
                     primary_key: false,
                     nullable: false,
                     default: None,
+                    generated: None,
                     unique: false,
                     index: false,
                     references: Some("genres".into()),
@@ -74,6 +79,7 @@ async fn get_table() -> Result<()> {
                     primary_key: false,
                     nullable: false,
                     default: None,
+                    generated: None,
                     unique: false,
                     index: false,
                     references: None,
@@ -84,6 +90,7 @@ async fn get_table() -> Result<()> {
                     primary_key: false,
                     nullable: true,
                     default: Some(Value::Null),
+                    generated: None,
                     unique: false,
                     index: false,
                     references: None,
@@ -94,11 +101,14 @@ async fn get_table() -> Result<()> {
                     primary_key: false,
                     nullable: true,
                     default: Some(Value::Null),
+                    generated: None,
                     unique: false,
                     index: false,
                     references: None,
                 },
-            ]
+            ],
+            version: 0,
+            layouts: vec![],
         }
     );
     Ok(())
@@ -130,8 +140,14 @@ async fn status() -> Result<()> {
                 apply_index: 26,
                 storage: "hybrid".into(),
                 storage_size: 3239,
+                dropped_messages: HashMap::new(),
+            },
+            mvcc: kv::mvcc::Status {
+                txns: 1,
+                txns_active: 0,
+                txns_active_oldest: None,
+                storage: "memory".into()
             },
-            mvcc: kv::mvcc::Status { txns: 1, txns_active: 0, storage: "memory".into() },
         }
     );
     Ok(())
@@ -147,8 +163,11 @@ async fn execute() -> Result<()> {
     assert_eq!(
         result,
         ResultSet::Query {
-            columns: vec![Column { name: Some("id".into()) }, Column { name: Some("name".into()) }],
-            rows: Box::new(std::iter::empty()),
+            columns: vec![
+                Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+                Column { name: Some("name".into()), datatype: Some(DataType::String) }
+            ],
+            rows: Rows::empty(),
         }
     );
     assert_rows(
@@ -164,8 +183,11 @@ async fn execute() -> Result<()> {
     assert_eq!(
         result,
         ResultSet::Query {
-            columns: vec![Column { name: Some("id".into()) }, Column { name: Some("name".into()) }],
-            rows: Box::new(std::iter::empty()),
+            columns: vec![
+                Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+                Column { name: Some("name".into()), datatype: Some(DataType::String) }
+            ],
+            rows: Rows::empty(),
         }
     );
     assert_rows(result, Vec::new());
@@ -217,6 +239,284 @@ async fn execute() -> Result<()> {
         Err(Error::Value("Unknown field x".into()))
     );
 
+    // RETURNING
+    assert_eq!(
+        c.execute("INSERT INTO genres VALUES (9, 'Western') RETURNING id, name").await,
+        Ok(ResultSet::Returning {
+            count: 1,
+            columns: vec![
+                Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+                Column { name: Some("name".into()), datatype: Some(DataType::String) }
+            ],
+            rows: vec![vec![Value::Integer(9), Value::String("Western".into())]],
+        }),
+    );
+    assert_eq!(
+        c.execute("DELETE FROM genres WHERE id > 8 RETURNING id * 2").await,
+        Ok(ResultSet::Returning {
+            count: 1,
+            columns: vec![Column { name: None, datatype: Some(DataType::Integer) }],
+            rows: vec![vec![Value::Integer(18)]],
+        }),
+    );
+    assert_eq!(
+        c.execute("UPDATE genres SET name = 'Horror' WHERE FALSE RETURNING *").await,
+        Ok(ResultSet::Returning {
+            count: 0,
+            columns: vec![
+                Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+                Column { name: Some("name".into()), datatype: Some(DataType::String) }
+            ],
+            rows: vec![],
+        }),
+    );
+
+    Ok(())
+}
+
+#[tokio::test(core_threads = 2)]
+#[serial]
+async fn execute_prepared() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    let select = c.prepare("SELECT * FROM genres WHERE id >= $1 ORDER BY id LIMIT $2").await?;
+    assert_rows(
+        c.execute_prepared(select, vec![Value::Integer(2), Value::Integer(1)]).await?,
+        vec![vec![Value::Integer(2), Value::String("Action".into())]],
+    );
+    assert_rows(
+        c.execute_prepared(select, vec![Value::Float(2.5), Value::Integer(5)]).await?,
+        vec![vec![Value::Integer(3), Value::String("Comedy".into())]],
+    );
+    assert_eq!(
+        c.execute_prepared(select, vec![Value::Integer(1)]).await,
+        Err(Error::Value("Expected 2 parameters, got 1".into()))
+    );
+
+    let insert = c.prepare("INSERT INTO genres VALUES (?, ?)").await?;
+    assert_eq!(
+        c.execute_prepared(insert, vec![Value::Integer(9), Value::String("'); DROP".into())]).await,
+        Ok(ResultSet::Create { count: 1 }),
+    );
+    assert_row(
+        c.execute("SELECT name FROM genres WHERE id = 9").await?,
+        vec![Value::String("'); DROP".into())],
+    );
+
+    // Prepared statements are specific to the connection.
+    let other = Client::new("127.0.0.1:9605").await?;
+    assert_eq!(
+        other.execute_prepared(select, vec![Value::Integer(1), Value::Integer(1)]).await,
+        Err(Error::Value(format!("Prepared statement {} does not exist", select)))
+    );
+
+    Ok(())
+}
+
+#[tokio::test(core_threads = 2)]
+#[serial]
+async fn validate() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    assert_eq!(
+        c.validate("SELECT m.id, m.title AS name, m.rating * 2 FROM movies m").await?,
+        vec![
+            Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+            Column { name: Some("name".into()), datatype: Some(DataType::String) },
+            Column { name: None, datatype: Some(DataType::Float) },
+        ]
+    );
+    assert_eq!(
+        c.validate("SELECT * FROM genres g JOIN studios s ON g.id = s.id").await?,
+        vec![
+            Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+            Column { name: Some("name".into()), datatype: Some(DataType::String) },
+            Column { name: Some("id".into()), datatype: Some(DataType::Integer) },
+            Column { name: Some("name".into()), datatype: Some(DataType::String) },
+            Column { name: Some("country_id".into()), datatype: Some(DataType::String) },
+        ]
+    );
+    assert_eq!(
+        c.validate("SELECT genre_id, COUNT(*) FROM movies GROUP BY genre_id").await?,
+        vec![
+            Column { name: Some("genre_id".into()), datatype: Some(DataType::Integer) },
+            Column { name: Some("count".into()), datatype: Some(DataType::Integer) }
+        ]
+    );
+    assert_eq!(
+        c.validate("DELETE FROM genres RETURNING id").await?,
+        vec![Column { name: Some("id".into()), datatype: Some(DataType::Integer) }]
+    );
+    assert_eq!(c.validate("UPDATE genres SET name = 'x'").await?, vec![]);
+
+    assert_eq!(
+        c.validate("SELECT unknown FROM movies").await,
+        Err(Error::Value("Unknown field unknown".into()))
+    );
+    assert_eq!(
+        c.validate("SELECT * FROM unknown").await,
+        Err(Error::Value("Table unknown does not exist".into()))
+    );
+    assert_eq!(c.validate("SELECT 1 + 'a'").await, Err(Error::Value("Can't add 1 and a".into())));
+    assert_eq!(
+        c.validate("INSERT INTO genres VALUES (9, 'x'").await,
+        Err(Error::Parse("Unexpected end of input".into()))
+    );
+
+    // Validated statements are not executed.
+    assert_eq!(c.validate("INSERT INTO genres VALUES (9, 'x')").await?, vec![]);
+    c.validate("DROP TABLE genres").await?;
+    assert_rows(
+        c.execute("SELECT COUNT(*) FROM genres WHERE id = 9 OR name = 'x'").await?,
+        vec![vec![Value::Integer(0)]],
+    );
+
+    Ok(())
+}
+
+#[tokio::test(core_threads = 2)]
+#[serial]
+async fn export_import() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    // Export the table schemas (key prefix 0x01) and rows (key prefix 0x03).
+    let schemas = c.export(&[0x01]).await?;
+    assert_eq!(schemas.len(), 4);
+    let rows = c.export(&[0x03]).await?;
+    assert_eq!(rows.len(), 3 + 3 + 4 + 10);
+    assert!(rows.iter().all(|(key, _)| key[0] == 0x03));
+
+    // Import them into a fresh server, which yields the same rows.
+    let _fresh_teardown =
+        setup::server("fresh", "127.0.0.1:9606", "127.0.0.1:9706", HashMap::new()).await?;
+    let fresh = Client::new("127.0.0.1:9606").await?;
+    fresh.authenticate(setup::PASSWORD).await?;
+    assert_eq!(fresh.import(schemas).await?, 4);
+    assert_eq!(fresh.import(rows.clone()).await?, 20);
+    assert_eq!(fresh.list_tables().await?, vec!["countries", "genres", "movies", "studios"]);
+    assert_eq!(fresh.export(&[0x03]).await?, rows);
+    let query = "SELECT m.title, g.name FROM movies m JOIN genres g ON m.genre_id = g.id";
+    let expect = match c.execute(query).await? {
+        ResultSet::Query { rows, .. } => rows.collect::<Result<Vec<_>>>()?,
+        r => panic!("Unexpected result {:?}", r),
+    };
+    assert_rows(fresh.execute(query).await?, expect);
+
+    // Importing the same keys again is idempotent.
+    assert_eq!(fresh.import(rows.clone()).await?, 20);
+    assert_eq!(fresh.export(&[0x03]).await?, rows);
+
+    Ok(())
+}
+
+#[tokio::test(core_threads = 2)]
+#[serial]
+async fn authenticate() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    // New sessions execute statements as the unprivileged anonymous user.
+    let anon = Client::new("127.0.0.1:9605").await?;
+    let denied = |user: &str| {
+        Err(Error::Unauthorized(format!(
+            "User {} does not have SELECT privilege on table genres",
+            user
+        )))
+    };
+    assert_eq!(anon.execute("SELECT * FROM genres").await, denied("anonymous"));
+    assert_eq!(
+        anon.execute("CREATE TABLE other (id INTEGER PRIMARY KEY)").await,
+        Err(Error::Unauthorized("User anonymous must be a superuser to change the schema".into()))
+    );
+    assert_eq!(
+        anon.set_user(None).await,
+        Err(Error::Unauthorized("User anonymous must be a superuser to change user".into()))
+    );
+    assert_eq!(
+        anon.export(&[]).await,
+        Err(Error::Unauthorized("User anonymous must be a superuser to export data".into()))
+    );
+
+    // Privileges can be granted to anonymous sessions by creating the user.
+    c.execute("CREATE USER anonymous").await?;
+    c.execute("GRANT SELECT ON genres TO anonymous").await?;
+    assert_row(
+        anon.execute("SELECT name FROM genres WHERE id = 1").await?,
+        vec![Value::String("Science Fiction".into())],
+    );
+    c.execute("DROP USER anonymous").await?;
+    assert_eq!(anon.execute("SELECT * FROM genres").await, denied("anonymous"));
+
+    // Authenticating as the superuser requires the password.
+    assert_eq!(
+        anon.authenticate("wrong").await,
+        Err(Error::Unauthorized("Invalid superuser password".into()))
+    );
+    anon.authenticate(setup::PASSWORD).await?;
+    assert_row(
+        anon.execute("SELECT name FROM genres WHERE id = 1").await?,
+        vec![Value::String("Science Fiction".into())],
+    );
+
+    // A superuser can switch to other users, which can't switch back without the password.
+    anon.execute("CREATE USER alice").await?;
+    anon.set_user(Some("alice")).await?;
+    assert_eq!(anon.execute("SELECT * FROM genres").await, denied("alice"));
+    assert_eq!(
+        anon.set_user(None).await,
+        Err(Error::Unauthorized("User alice must be a superuser to change user".into()))
+    );
+    anon.authenticate(setup::PASSWORD).await?;
+    anon.execute("SELECT * FROM genres").await?;
+
+    Ok(())
+}
+
+#[tokio::test(core_threads = 2)]
+#[serial]
+async fn watch() -> Result<()> {
+    let (c, _teardown) = setup::server_with_client(setup::movies()).await?;
+
+    let watcher = Client::new("127.0.0.1:9605").await?;
+    watcher.authenticate(setup::PASSWORD).await?;
+    assert_eq!(
+        watcher.watch("unknown").await.err(),
+        Some(Error::Value("Table unknown does not exist".into()))
+    );
+    let mut watch = watcher.watch("genres").await?;
+
+    // Changes to other tables and rolled back changes are not emitted.
+    c.execute("INSERT INTO countries VALUES ('no', 'Norway')").await?;
+    c.execute("BEGIN").await?;
+    c.execute("DELETE FROM genres").await?;
+    c.execute("ROLLBACK").await?;
+
+    c.execute("INSERT INTO genres VALUES (4, 'Drama')").await?;
+    c.execute("UPDATE genres SET name = 'Sci-Fi' WHERE id = 1").await?;
+    c.execute("DELETE FROM genres WHERE id = 2").await?;
+
+    assert_eq!(
+        watch.next().await?,
+        Change::Insert {
+            table: "genres".into(),
+            row: vec![Value::Integer(4), Value::String("Drama".into())],
+        }
+    );
+    assert_eq!(
+        watch.next().await?,
+        Change::Update {
+            table: "genres".into(),
+            before: vec![Value::Integer(1), Value::String("Science Fiction".into())],
+            after: vec![Value::Integer(1), Value::String("Sci-Fi".into())],
+        }
+    );
+    assert_eq!(
+        watch.next().await?,
+        Change::Delete {
+            table: "genres".into(),
+            row: vec![Value::Integer(2), Value::String("Action".into())],
+        }
+    );
+
     Ok(())
 }
 
@@ -315,6 +615,7 @@ async fn execute_txn() -> Result<()> {
 async fn execute_txn_concurrent() -> Result<()> {
     let (a, _teardown) = setup::server_with_client(setup::movies()).await?;
     let b = Client::new("127.0.0.1:9605").await?;
+    b.authenticate(setup::PASSWORD).await?;
 
     // Concurrent updates should throw a serialization failure on conflict.
     assert_eq!(a.execute("BEGIN").await?, ResultSet::Begin { id: 2, mode: Mode::ReadWrite });
@@ -348,3 +649,89 @@ async fn execute_txn_concurrent() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(core_threads = 2)]
+#[serial]
+async fn cancel() -> Result<()> {
+    let (a, _teardown) = setup::server_with_client(setup::movies()).await?;
+    let b = Client::new("127.0.0.1:9605").await?;
+    b.authenticate(setup::PASSWORD).await?;
+    let id = a.session_id().await?;
+    assert_ne!(b.session_id().await?, id);
+
+    // A long cross join is cancelled promptly by another client, rolling back the transaction.
+    a.execute("BEGIN").await?;
+    a.execute("UPDATE genres SET name = 'x' WHERE id = 1").await?;
+    let start = std::time::Instant::now();
+    let (result, cancelled) = tokio::join!(
+        a.execute(
+            "SELECT COUNT(*) FROM movies a, movies b, movies c, movies d, movies e, movies f"
+        ),
+        async {
+            tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+            b.cancel(id).await
+        },
+    );
+    cancelled?;
+    assert_eq!(result, Err(Error::Cancelled));
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(a.txn(), None);
+
+    // The session survives, and the update was rolled back.
+    assert_row(
+        a.execute("SELECT * FROM genres WHERE id = 1").await?,
+        vec![Value::Integer(1), Value::String("Science Fiction".into())],
+    );
+
+    // Cancelling an unknown session errors.
+    assert_eq!(b.cancel(999).await, Err(Error::Value("Session 999 does not exist".into())));
+
+    Ok(())
+}
+
+#[tokio::test(core_threads = 2)]
+#[serial]
+async fn execute_txn_serializable() -> Result<()> {
+    let (a, _teardown) = setup::server_with_client(setup::movies()).await?;
+    let b = Client::new("127.0.0.1:9605").await?;
+    b.authenticate(setup::PASSWORD).await?;
+
+    // Serializable transactions should fail to commit on write skew, i.e. when a concurrent
+    // transaction has committed a write to a row they read.
+    let begin = "BEGIN ISOLATION LEVEL SERIALIZABLE";
+    assert_eq!(a.execute(begin).await?, ResultSet::Begin { id: 2, mode: Mode::Serializable });
+    assert_eq!(b.execute(begin).await?, ResultSet::Begin { id: 3, mode: Mode::Serializable });
+    assert_eq!(a.txn(), Some((2, Mode::Serializable)));
+
+    assert_row(
+        a.execute("SELECT * FROM genres WHERE id = 2").await?,
+        vec![Value::Integer(2), Value::String("Action".into())],
+    );
+    assert_row(
+        b.execute("SELECT * FROM genres WHERE id = 1").await?,
+        vec![Value::Integer(1), Value::String("Science Fiction".into())],
+    );
+    assert_eq!(
+        a.execute("UPDATE genres SET name = 'x' WHERE id = 1").await,
+        Ok(ResultSet::Update { count: 1 })
+    );
+    assert_eq!(
+        b.execute("UPDATE genres SET name = 'y' WHERE id = 2").await,
+        Ok(ResultSet::Update { count: 1 })
+    );
+
+    assert_eq!(a.execute("COMMIT").await, Ok(ResultSet::Commit { id: 2 }));
+    assert_eq!(b.execute("COMMIT").await, Err(Error::Serialization));
+    assert_eq!(b.txn(), None);
+    assert_eq!(b.execute("ROLLBACK").await, Err(Error::Value("Not in a transaction".into())));
+
+    assert_rows(
+        a.execute("SELECT * FROM genres WHERE id <= 2").await?,
+        vec![
+            vec![Value::Integer(1), Value::String("x".into())],
+            vec![Value::Integer(2), Value::String("Action".into())],
+        ],
+    );
+
+    Ok(())
+}
@@ -67,6 +67,9 @@ pub fn simple() -> Vec<&'static str> {
     vec!["CREATE TABLE test (id INTEGER PRIMARY KEY, value STRING)"]
 }
 
+/// The superuser password of test servers
+pub const PASSWORD: &str = "secret";
+
 /// Sets up a test server
 pub async fn server(
     id: &str,
@@ -78,10 +81,14 @@ pub async fn server(
     let mut srv = Server::new(
         id,
         peers,
-        Box::new(storage::log::Hybrid::new(&dir.path(), false)?),
+        Box::new(storage::log::Hybrid::new(&dir.path(), storage::log::Durability::None)?),
         Box::new(storage::kv::Memory::new()),
+        0,
+        toydb::sql::engine::MAX_VALUE_SIZE,
+        None,
     )
-    .await?;
+    .await?
+    .with_superuser_password(Some(PASSWORD.into()));
 
     srv = srv.listen(addr_sql, addr_raft).await?;
     let (task, abort) = srv.serve().remote_handle();
@@ -97,6 +104,7 @@ pub async fn server(
 pub async fn server_with_client(queries: Vec<&str>) -> Result<(Client, Teardown)> {
     let teardown = server("test", "127.0.0.1:9605", "127.0.0.1:9705", HashMap::new()).await?;
     let client = Client::new("127.0.0.1:9605").await?;
+    client.authenticate(PASSWORD).await?;
     if !queries.is_empty() {
         client.execute("BEGIN").await?;
         for query in queries {
@@ -138,6 +146,7 @@ pub async fn cluster_with_clients(
     let mut clients = Vec::<Client>::new();
     for (id, (addr_sql, _)) in nodes {
         let client = Client::new(addr_sql).await?;
+        client.authenticate(PASSWORD).await?;
         assert_eq!(id, client.status().await?.raft.server);
         clients.push(client);
     }
@@ -170,6 +179,7 @@ pub async fn cluster_with_pool(
     let teardown = cluster(nodes.clone()).await?;
 
     let pool = Pool::new(nodes.into_iter().map(|(_, (addr, _))| addr).collect(), pool_size).await?;
+    pool.authenticate(PASSWORD).await?;
     pool.get().await.status().await?;
 
     if !queries.is_empty() {
@@ -1,13 +1,21 @@
 ///! Evaluates SQL expressions and compares with expectations.
 use toydb::error::{Error, Result};
 use toydb::sql::engine::Engine;
-use toydb::sql::types::Value;
+use toydb::sql::execution::ResultSet;
+use toydb::sql::types::{self, Value};
+
+use std::mem::discriminant;
 
 fn eval_expr(expr: &str) -> Result<Value> {
     let engine = super::setup(Vec::new())?;
     engine.session()?.execute(&format!("SELECT {}", expr))?.into_value()
 }
 
+/// Parses a decimal value.
+fn dec(s: &str) -> Value {
+    Value::Decimal(s.parse().unwrap())
+}
+
 macro_rules! test_expr {
     ( $( $name:ident: $expr:expr => $expect:expr, )* ) => {
     $(
@@ -67,7 +75,275 @@ test_expr! {
     lit_string_whitespace: "' Has \n newlines and \t tabs  '" => Ok(String(" Has \n newlines and \t tabs  ".into())),
     lit_string_long: &format!("'{}'", "a".repeat(4096)) => Ok("a".repeat(4096).into()),
 
+    lit_date: "DATE '2024-01-31'" => Ok(Date(types::Date(19753))),
+    lit_date_case: "date '1969-12-31'" => Ok(Date(types::Date(-1))),
+    lit_date_invalid: "DATE '2023-02-29'" => Err(Error::Value("Invalid date '2023-02-29': Day 29 out of range".into())),
+    lit_date_format: "DATE '2024-1-31'" => Err(Error::Value("Invalid date '2024-1-31'".into())),
+    lit_date_nostring: "DATE 3" => Err(Error::Parse("Expected string, got 3".into())),
+    lit_time: "TIME '12:34:56'" => Ok(Time(types::Time(45_296_000_000))),
+    lit_time_fraction: "TIME '12:34:56.789'" => Ok(Time(types::Time(45_296_789_000))),
+    lit_time_minutes: "TIME '12:34'" => Ok(Time(types::Time(45_240_000_000))),
+    lit_time_invalid: "TIME '24:00:00'" => Err(Error::Value("Invalid time '24:00:00'".into())),
+    lit_timestamp: "TIMESTAMP '2024-01-31 12:30:00'" => Ok(Timestamp(types::Timestamp(1_706_704_200_000_000))),
+    lit_timestamp_iso: "TIMESTAMP '2024-01-31T12:30:00Z'" => Ok(Timestamp(types::Timestamp(1_706_704_200_000_000))),
+    lit_timestamp_date: "TIMESTAMP '2024-01-31'" => Ok(Timestamp(types::Timestamp(1_706_659_200_000_000))),
+    lit_timestamp_invalid: "TIMESTAMP '2024-01-31 12:30:00+01:00'" => Err(Error::Value("Invalid timestamp '2024-01-31 12:30:00+01:00': Invalid time '12:30:00+01:00'".into())),
+
+    lit_decimal: "DECIMAL '12.34'" => Ok(dec("12.34")),
+    lit_decimal_numeric: "NUMERIC '-0.05'" => Ok(dec("-0.05")),
+    lit_decimal_integer: "DECIMAL '1200'" => Ok(dec("1200")),
+    lit_decimal_max: "DECIMAL '9999999999999999999999999999.9999999999'" => Ok(dec("9999999999999999999999999999.9999999999")),
+    lit_decimal_overflow: "DECIMAL '100000000000000000000000000000000000000'" => Err(Error::Value("Decimal '100000000000000000000000000000000000000' out of range".into())),
+    lit_decimal_invalid: "DECIMAL '1.2.3'" => Err(Error::Value("Invalid decimal '1.2.3'".into())),
+    lit_decimal_exponent: "DECIMAL '1e3'" => Err(Error::Value("Invalid decimal '1e3'".into())),
+    lit_decimal_nostring: "DECIMAL 3" => Err(Error::Parse("Expected string, got 3".into())),
+
+    lit_bytes: "x'DEADbeef'" => Ok(Bytes(vec![0xde, 0xad, 0xbe, 0xef])),
+    lit_bytes_upper: "X'00FF'" => Ok(Bytes(vec![0x00, 0xff])),
+    lit_bytes_empty: "x''" => Ok(Bytes(vec![])),
+    lit_bytes_odd: "x'abc'" => Err(Error::Parse("Invalid hexadecimal string x'abc'".into())),
+    lit_bytes_invalid: "x'zz'" => Err(Error::Parse("Invalid hexadecimal string x'zz'".into())),
+    lit_bytes_unicode: "x'é0'" => Err(Error::Parse("Invalid hexadecimal string x'é0'".into())),
+    lit_bytes_space: "x 'ab'" => Err(Error::Parse("Unexpected token ab".into())),
+
     // Functions
+    func_now: "NOW() = CURRENT_TIMESTAMP" => Ok(Boolean(true)),
+    func_now_case: "now() > TIMESTAMP '2024-01-01'" => Ok(Boolean(true)),
+    func_length_bytes: "LENGTH(x'00ff00')" => Ok(Integer(3)),
+    func_length_bytes_empty: "length(x'')" => Ok(Integer(0)),
+    func_length_string: "LENGTH('Hi! 👋')" => Ok(Integer(5)),
+    func_length_null: "LENGTH(NULL)" => Ok(Null),
+    func_length_integer: "LENGTH(1)" => Err(Error::Value("Can't take length of 1".into())),
+    func_length_args: "LENGTH('a', 'b')" => Err(Error::Value("Function length takes 1 argument, got 2".into())),
+    func_upper: "UPPER('Hello, wörld!')" => Ok(String("HELLO, WÖRLD!".into())),
+    func_upper_null: "UPPER(NULL)" => Ok(Null),
+    func_upper_integer: "UPPER(1)" => Err(Error::Value("Can't take upper of 1".into())),
+    func_upper_args: "UPPER()" => Err(Error::Value("Function upper takes 1 argument, got 0".into())),
+    func_lower: "LOWER('Hello, WÖRLD!')" => Ok(String("hello, wörld!".into())),
+    func_lower_null: "LOWER(NULL)" => Ok(Null),
+    func_lower_bytes: "LOWER(x'00')" => Err(Error::Value("Can't take lower of x'00'".into())),
+    func_substring: "SUBSTRING('Hello 👋 world' FROM 7 FOR 3)" => Ok(String("👋 w".into())),
+    func_substring_from: "SUBSTRING('Hello 👋 world' FROM 7)" => Ok(String("👋 world".into())),
+    func_substring_args: "SUBSTRING('Hello 👋 world', 7, 3)" => Ok(String("👋 w".into())),
+    func_substring_args_from: "substring('Hello 👋 world', 7)" => Ok(String("👋 world".into())),
+    func_substring_substr: "SUBSTR('日本語テキスト', 2, 2)" => Ok(String("本語".into())),
+    func_substring_start_zero: "SUBSTR('abcde', 0, 3)" => Ok(String("ab".into())),
+    func_substring_start_negative: "SUBSTR('abcde', -2, 5)" => Ok(String("ab".into())),
+    func_substring_start_negative_all: "SUBSTR('abcde', -2, 3)" => Ok(String("".into())),
+    func_substring_start_past_end: "SUBSTR('abcde', 10)" => Ok(String("".into())),
+    func_substring_count_past_end: "SUBSTR('abcde', 4, 10)" => Ok(String("de".into())),
+    func_substring_count_zero: "SUBSTR('abcde', 2, 0)" => Ok(String("".into())),
+    func_substring_count_negative: "SUBSTR('abcde', 2, -1)" => Err(Error::Value("Substring length can't be negative, got -1".into())),
+    func_substring_count_overflow: "SUBSTR('abcde', 2, 9223372036854775807)" => Ok(String("bcde".into())),
+    func_substring_null: "SUBSTRING(NULL FROM 1)" => Ok(Null),
+    func_substring_null_start: "SUBSTRING('abc' FROM NULL)" => Ok(Null),
+    func_substring_null_count: "SUBSTRING('abc' FROM 1 FOR NULL)" => Ok(Null),
+    func_substring_float: "SUBSTR('abc', 1.0)" => Err(Error::Value("Can't take substring of abc, 1".into())),
+    func_substring_args_few: "SUBSTR('abc')" => Err(Error::Value("Function substring takes 2 to 3 arguments, got 1".into())),
+    func_substring_for_only: "SUBSTRING('abc' FOR 1)" => Err(Error::Parse("Expected token ,, found FOR".into())),
+    func_trim: "TRIM('  a b  ')" => Ok(String("a b".into())),
+    func_trim_chars: "TRIM('xyaxbyx', 'xy')" => Ok(String("axb".into())),
+    func_trim_unicode: "TRIM('👋👋hi👋', '👋')" => Ok(String("hi".into())),
+    func_trim_tabs: "TRIM('	a ')" => Ok(String("	a".into())),
+    func_trim_null: "TRIM(NULL)" => Ok(Null),
+    func_trim_null_chars: "TRIM('a', NULL)" => Ok(Null),
+    func_ltrim: "LTRIM('  a  ')" => Ok(String("a  ".into())),
+    func_ltrim_chars: "LTRIM('xxaxx', 'x')" => Ok(String("axx".into())),
+    func_rtrim: "RTRIM('  a  ')" => Ok(String("  a".into())),
+    func_rtrim_chars: "RTRIM('xxaxx', 'x')" => Ok(String("xxa".into())),
+    func_coalesce: "COALESCE(NULL, 2, 3)" => Ok(Integer(2)),
+    func_coalesce_first: "COALESCE(1, NULL, 3)" => Ok(Integer(1)),
+    func_coalesce_one: "COALESCE(NULL)" => Ok(Null),
+    func_coalesce_all_null: "COALESCE(NULL, NULL, NULL)" => Ok(Null),
+    func_coalesce_lazy: "COALESCE(1, 1 / 0)" => Ok(Integer(1)),
+    func_coalesce_lazy_taken: "COALESCE(NULL, 1 / 0)" => Err(Error::Value("Can't divide by zero".into())),
+    func_coalesce_numeric: "COALESCE(NULL, 1, 2.5, DECIMAL '1.5')" => Ok(Integer(1)),
+    func_coalesce_types: "COALESCE(NULL, 1, 'a')" => Err(Error::Value("COALESCE arguments have incompatible types INTEGER and STRING".into())),
+    func_coalesce_types_expr: "COALESCE(1 + 1, 'a')" => Ok(Integer(2)),
+    func_coalesce_none: "COALESCE()" => Err(Error::Value("Function coalesce takes at least 1 argument, got 0".into())),
+    func_concat: "CONCAT('a', 'b', 'c')" => Ok(String("abc".into())),
+    func_concat_one: "CONCAT('a')" => Ok(String("a".into())),
+    func_concat_null: "CONCAT('a', NULL, 'c')" => Ok(String("ac".into())),
+    func_concat_all_null: "CONCAT(NULL, NULL)" => Ok(String("".into())),
+    func_concat_types: "CONCAT('a', 1, 2.5, TRUE, DATE '2024-01-31')" => Ok(String("a12.5TRUE2024-01-31".into())),
+    func_concat_none: "CONCAT()" => Err(Error::Value("Function concat takes at least 1 argument, got 0".into())),
+    func_nullif_equal: "NULLIF(1, 1)" => Ok(Null),
+    func_nullif_unequal: "NULLIF(1, 2)" => Ok(Integer(1)),
+    func_nullif_numeric: "NULLIF(1, 1.0)" => Ok(Null),
+    func_nullif_string: "NULLIF('a', 'b')" => Ok(String("a".into())),
+    func_nullif_null_lhs: "NULLIF(NULL, 1)" => Ok(Null),
+    func_nullif_null_rhs: "NULLIF(1, NULL)" => Ok(Integer(1)),
+    func_nullif_incomparable: "NULLIF(1, 'a')" => Err(Error::Value("Can't compare 1 and a".into())),
+    func_nullif_arity: "NULLIF(1)" => Err(Error::Value("Function nullif takes 2 arguments, got 1".into())),
+    func_position: "POSITION('lo' IN 'hello')" => Ok(Integer(4)),
+    func_position_unicode: "POSITION('🌍' IN 'hi 👋🌍')" => Ok(Integer(5)),
+    func_position_missing: "POSITION('x' IN 'hello')" => Ok(Integer(0)),
+    func_position_empty: "POSITION('' IN 'hello')" => Ok(Integer(1)),
+    func_position_first: "POSITION('l' IN 'hello')" => Ok(Integer(3)),
+    func_position_args: "POSITION('l', 'hello')" => Ok(Integer(3)),
+    func_position_null: "POSITION(NULL IN 'hello')" => Ok(Null),
+    func_position_integer: "POSITION(1 IN 'hello')" => Err(Error::Value("Can't take position of 1, hello".into())),
+    func_position_noin: "POSITION('l')" => Err(Error::Value("Function position takes 2 arguments, got 1".into())),
+    func_nested: "UPPER(SUBSTR(TRIM('  hello  '), 2, LENGTH('abc')))" => Ok(String("ELL".into())),
+    case_searched: "CASE WHEN 1 > 2 THEN 'a' WHEN 2 > 1 THEN 'b' ELSE 'c' END" => Ok(String("b".into())),
+    case_searched_first: "CASE WHEN TRUE THEN 'a' WHEN TRUE THEN 'b' END" => Ok(String("a".into())),
+    case_searched_else: "CASE WHEN 1 > 2 THEN 'a' ELSE 'c' END" => Ok(String("c".into())),
+    case_searched_no_else: "CASE WHEN 1 > 2 THEN 'a' END" => Ok(Null),
+    case_searched_null: "CASE WHEN NULL THEN 1 ELSE 2 END" => Ok(Integer(2)),
+    case_searched_integer: "CASE WHEN 1 THEN 1 END" => Err(Error::Value("CASE condition must be boolean, got INTEGER in CASE WHEN 1 THEN 1 END".into())),
+    case_simple: "CASE 2 WHEN 1 THEN 'one' WHEN 2 THEN 'two' END" => Ok(String("two".into())),
+    case_simple_else: "CASE 3 WHEN 1 THEN 'one' WHEN 2 THEN 'two' ELSE 'many' END" => Ok(String("many".into())),
+    case_simple_no_else: "CASE 3 WHEN 1 THEN 'one' END" => Ok(Null),
+    case_simple_null: "CASE NULL WHEN NULL THEN 1 ELSE 2 END" => Ok(Integer(2)),
+    case_simple_null_when: "CASE 1 WHEN NULL THEN 1 ELSE 2 END" => Ok(Integer(2)),
+    case_simple_numeric: "CASE 1.0 WHEN 1 THEN 'yes' END" => Ok(String("yes".into())),
+    case_simple_expr: "CASE 1 + 1 WHEN 3 - 1 THEN 'two' END" => Ok(String("two".into())),
+    case_simple_incomparable: "CASE 1 WHEN 'a' THEN 1 END" => Err(Error::Value("Can't compare INTEGER and STRING in CASE 1 WHEN a THEN 1 END".into())),
+    case_lazy_then: "CASE WHEN FALSE THEN 1 / 0 ELSE 1 END" => Ok(Integer(1)),
+    case_lazy_else: "CASE WHEN TRUE THEN 1 ELSE 1 / 0 END" => Ok(Integer(1)),
+    case_lazy_when: "CASE WHEN TRUE THEN 1 WHEN 1 / 0 = 1 THEN 2 END" => Ok(Integer(1)),
+    case_lazy_taken: "CASE WHEN FALSE THEN 1 ELSE 1 / 0 END" => Err(Error::Value("Can't divide by zero".into())),
+    case_types: "CASE WHEN TRUE THEN 1 ELSE 'a' END" => Err(Error::Value("CASE branches have incompatible types INTEGER and STRING".into())),
+    case_types_numeric: "CASE WHEN FALSE THEN 1 WHEN FALSE THEN DECIMAL '1.5' ELSE 2.5 END" => Ok(Float(2.5)),
+    case_types_null: "CASE WHEN FALSE THEN NULL WHEN FALSE THEN 'a' ELSE 'b' END" => Ok(String("b".into())),
+    case_types_expr: "CASE WHEN FALSE THEN 1 + 1 ELSE 'b' END" => Ok(String("b".into())),
+    case_nested: "CASE WHEN TRUE THEN CASE 1 WHEN 1 THEN 'inner' END ELSE 'outer' END" => Ok(String("inner".into())),
+    case_operator: "CASE WHEN TRUE THEN 1 END + 1" => Ok(Integer(2)),
+    case_lowercase: "case when true then 1 end" => Ok(Integer(1)),
+    case_bare: "CASE END" => Err(Error::Parse("Expected expression atom, found END".into())),
+    case_no_when: "CASE 1 END" => Err(Error::Parse("Expected token WHEN, found END".into())),
+    case_no_then: "CASE WHEN TRUE 1 END" => Err(Error::Parse("Expected token THEN, found 1".into())),
+    case_no_end: "CASE WHEN TRUE THEN 1" => Err(Error::Parse("Unexpected end of input".into())),
+    case_else_only: "CASE ELSE 1 END" => Err(Error::Parse("Expected expression atom, found ELSE".into())),
+    cast_string_integer: "CAST('42' AS INTEGER)" => Ok(Integer(42)),
+    cast_string_integer_space: "CAST(' -7 ' AS INT)" => Ok(Integer(-7)),
+    cast_string_integer_invalid: "CAST('abc' AS INTEGER)" => Err(Error::Value("Can't cast abc to INTEGER".into())),
+    cast_string_integer_float: "CAST('1.5' AS INTEGER)" => Err(Error::Value("Can't cast 1.5 to INTEGER".into())),
+    cast_string_integer_overflow: "CAST('9223372036854775808' AS INTEGER)" => Err(Error::Value("Can't cast 9223372036854775808 to INTEGER".into())),
+    cast_string_float: "CAST('2.75' AS FLOAT)" => Ok(Float(2.75)),
+    cast_string_float_exp: "CAST('1e3' AS DOUBLE)" => Ok(Float(1000.0)),
+    cast_string_float_invalid: "CAST('abc' AS FLOAT)" => Err(Error::Value("Can't cast abc to FLOAT".into())),
+    cast_string_decimal: "CAST('1.25' AS DECIMAL(3,1))" => Ok(dec("1.3")),
+    cast_string_boolean: "CAST('true' AS BOOLEAN)" => Ok(Boolean(true)),
+    cast_string_boolean_short: "CAST('F' AS BOOL)" => Ok(Boolean(false)),
+    cast_string_boolean_invalid: "CAST('maybe' AS BOOLEAN)" => Err(Error::Value("Can't cast maybe to BOOLEAN".into())),
+    cast_string_date: "CAST('2020-01-02' AS DATE)" => Ok(Date("2020-01-02".parse()?)),
+    cast_string_string: "CAST('abc' AS TEXT)" => Ok(String("abc".into())),
+    cast_integer_string: "CAST(42 AS STRING)" => Ok(String("42".into())),
+    cast_integer_float: "CAST(3 AS FLOAT)" => Ok(Float(3.0)),
+    cast_integer_decimal: "CAST(3 AS DECIMAL(3,1))" => Ok(dec("3.0")),
+    cast_integer_boolean: "CAST(0 AS BOOLEAN)" => Ok(Boolean(false)),
+    cast_integer_boolean_true: "CAST(-2 AS BOOLEAN)" => Ok(Boolean(true)),
+    cast_float_integer: "CAST(2.7 AS INTEGER)" => Ok(Integer(2)),
+    cast_float_integer_negative: "CAST(-2.7 AS INTEGER)" => Ok(Integer(-2)),
+    cast_float_integer_overflow: "CAST(1e19 AS INTEGER)" => Err(Error::Value("Value 10000000000000000000 out of range for INTEGER".into())),
+    cast_float_integer_nan: "CAST(NAN AS INTEGER)" => Err(Error::Value("Value NaN out of range for INTEGER".into())),
+    cast_float_string: "CAST(1.5 AS VARCHAR)" => Ok(String("1.5".into())),
+    cast_float_decimal: "CAST(1.55 AS DECIMAL(4,1))" => Ok(dec("1.6")),
+    cast_decimal_integer: "CAST(DECIMAL '2.5' AS INTEGER)" => Ok(Integer(2)),
+    cast_decimal_integer_overflow: "CAST(DECIMAL '9223372036854775808' AS INTEGER)" => Err(Error::Value("Value 9223372036854775808 out of range for INTEGER".into())),
+    cast_decimal_float: "CAST(DECIMAL '2.5' AS FLOAT)" => Ok(Float(2.5)),
+    cast_boolean_integer: "CAST(TRUE AS INTEGER)" => Ok(Integer(1)),
+    cast_boolean_string: "CAST(FALSE AS STRING)" => Ok(String("FALSE".into())),
+    cast_boolean_float: "CAST(TRUE AS FLOAT)" => Err(Error::Value("Can't cast TRUE to FLOAT".into())),
+    cast_null: "CAST(NULL AS INTEGER)" => Ok(Null),
+    cast_expr: "CAST(CONCAT('1', '2') AS INTEGER) + 1" => Ok(Integer(13)),
+    cast_nested: "CAST(CAST(3.7 AS INTEGER) AS STRING)" => Ok(String("3".into())),
+    cast_case_types: "CASE WHEN TRUE THEN CAST(1 AS STRING) ELSE 1 END" => Err(Error::Value("CASE branches have incompatible types STRING and INTEGER".into())),
+    cast_lowercase: "cast(1 as string)" => Ok(String("1".into())),
+    cast_no_as: "CAST(1 INTEGER)" => Err(Error::Parse("Expected token AS, found INTEGER".into())),
+    cast_no_type: "CAST(1 AS)" => Err(Error::Parse("Unexpected token )".into())),
+    cast_unknown_type: "CAST(1 AS foo)" => Err(Error::Parse("Unexpected token foo".into())),
+    try_cast: "TRY_CAST(' 42 ' AS INTEGER)" => Ok(Integer(42)),
+    try_cast_invalid: "TRY_CAST('abc' AS INTEGER)" => Ok(Null),
+    try_cast_overflow: "TRY_CAST(1e19 AS INTEGER)" => Ok(Null),
+    try_cast_expr: "COALESCE(TRY_CAST(CONCAT('1', 'x') AS INTEGER), -1)" => Ok(Integer(-1)),
+    try_cast_expr_error: "TRY_CAST(1 / 0 AS STRING)" => Err(Error::Value("Can't divide by zero".into())),
+    try_cast_lowercase: "try_cast(1 as string)" => Ok(String("1".into())),
+    try_cast_no_as: "TRY_CAST(1 INTEGER)" => Err(Error::Parse("Expected token AS, found INTEGER".into())),
+    func_abs: "ABS(-3)" => Ok(Integer(3)),
+    func_abs_positive: "ABS(3)" => Ok(Integer(3)),
+    func_abs_float: "ABS(-3.5)" => Ok(Float(3.5)),
+    func_abs_float_infinity: "ABS(-INFINITY)" => Ok(Float(std::f64::INFINITY)),
+    func_abs_decimal: "ABS(DECIMAL '-1.50')" => Ok(dec("1.50")),
+    func_abs_min: "ABS(-9223372036854775807 - 1)" => Err(Error::Value("Integer overflow".into())),
+    func_abs_null: "ABS(NULL)" => Ok(Null),
+    func_abs_string: "ABS('a')" => Err(Error::Value("Can't take abs of a".into())),
+    func_abs_args: "ABS(1, 2)" => Err(Error::Value("Function abs takes 1 argument, got 2".into())),
+    func_ceil: "CEIL(1)" => Ok(Integer(1)),
+    func_ceil_float: "CEIL(1.2)" => Ok(Float(2.0)),
+    func_ceil_float_negative: "CEIL(-1.8)" => Ok(Float(-1.0)),
+    func_ceil_decimal: "CEIL(DECIMAL '1.01')" => Ok(dec("2")),
+    func_ceil_decimal_negative: "CEIL(DECIMAL '-1.99')" => Ok(dec("-1")),
+    func_ceil_decimal_integral: "CEIL(DECIMAL '3.00')" => Ok(dec("3")),
+    func_ceil_ceiling: "CEILING(0.5)" => Ok(Float(1.0)),
+    func_ceil_null: "CEIL(NULL)" => Ok(Null),
+    func_floor: "FLOOR(-1)" => Ok(Integer(-1)),
+    func_floor_float: "FLOOR(1.8)" => Ok(Float(1.0)),
+    func_floor_float_negative: "FLOOR(-1.2)" => Ok(Float(-2.0)),
+    func_floor_float_nan: "FLOOR(NAN)" => Ok(Float(std::f64::NAN)),
+    func_floor_decimal: "FLOOR(DECIMAL '1.99')" => Ok(dec("1")),
+    func_floor_decimal_negative: "FLOOR(DECIMAL '-1.01')" => Ok(dec("-2")),
+    func_floor_bool: "FLOOR(TRUE)" => Err(Error::Value("Can't take floor of TRUE".into())),
+    func_mod: "MOD(7, 3)" => Ok(Integer(1)),
+    func_mod_negative: "MOD(-7, 3)" => Ok(Integer(-1)),
+    func_mod_float: "MOD(7.5, 2)" => Ok(Float(1.5)),
+    func_mod_decimal: "MOD(DECIMAL '7.5', 2)" => Ok(dec("1.5")),
+    func_mod_zero: "MOD(7, 0)" => Err(Error::Value("Can't divide by zero".into())),
+    func_mod_min: "MOD(-9223372036854775807 - 1, -1)" => Ok(Integer(0)),
+    func_mod_null: "MOD(7, NULL)" => Ok(Null),
+    func_mod_string: "MOD('a', 2)" => Err(Error::Value("Can't take modulo of a and 2".into())),
+    func_power: "POWER(2, 10)" => Ok(Integer(1024)),
+    func_power_pow: "POW(2, 0.5)" => Ok(Float(std::f64::consts::SQRT_2)),
+    func_power_negative: "POWER(2, -1)" => Ok(Float(0.5)),
+    func_power_overflow: "POWER(2, 63)" => Err(Error::Value("Integer overflow".into())),
+    func_power_overflow_exponent: "POWER(2, 4294967296)" => Err(Error::Value("Integer overflow".into())),
+    func_power_float_large_exponent: "POWER(1.0, 4294967296)" => Ok(Float(1.0)),
+    func_power_null: "POWER(NULL, 2)" => Ok(Null),
+    func_power_args: "POWER(2)" => Err(Error::Value("Function power takes 2 arguments, got 1".into())),
+    func_round: "ROUND(7)" => Ok(Integer(7)),
+    func_round_digits: "ROUND(1234, 2)" => Ok(Integer(1234)),
+    func_round_digits_negative: "ROUND(1250, -2)" => Ok(Integer(1300)),
+    func_round_digits_negative_neg: "ROUND(-1250, -2)" => Ok(Integer(-1300)),
+    func_round_digits_negative_down: "ROUND(1249, -2)" => Ok(Integer(1200)),
+    func_round_digits_negative_large: "ROUND(1249, -100)" => Ok(Integer(0)),
+    func_round_digits_negative_overflow: "ROUND(9223372036854775807, -1)" => Err(Error::Value("Integer overflow".into())),
+    func_round_float: "ROUND(2.5)" => Ok(Float(3.0)),
+    func_round_float_negative: "ROUND(-2.5)" => Ok(Float(-3.0)),
+    func_round_float_digits: "ROUND(3.14159, 2)" => Ok(Float(3.14)),
+    func_round_float_digits_negative: "ROUND(1250.0, -2)" => Ok(Float(1300.0)),
+    func_round_float_digits_large: "ROUND(1.5e300, 100)" => Ok(Float(1.5e300)),
+    func_round_float_digits_huge: "ROUND(1.5, 9223372036854775807)" => Ok(Float(1.5)),
+    func_round_float_digits_tiny: "ROUND(1.5e300, -9223372036854775807)" => Ok(Float(0.0)),
+    func_round_float_infinity: "ROUND(INFINITY, 2)" => Ok(Float(std::f64::INFINITY)),
+    func_round_decimal: "ROUND(DECIMAL '2.5')" => Ok(dec("3")),
+    func_round_decimal_digits: "ROUND(DECIMAL '-2.345', 2)" => Ok(dec("-2.35")),
+    func_round_decimal_digits_more: "ROUND(DECIMAL '2.5', 40)" => Ok(dec("2.5")),
+    func_round_decimal_digits_negative: "ROUND(DECIMAL '1450.5', -2)" => Ok(dec("1500")),
+    func_round_decimal_digits_negative_half: "ROUND(DECIMAL '14.5', -1)" => Ok(dec("10")),
+    func_round_decimal_digits_negative_large: "ROUND(DECIMAL '14.5', -40)" => Ok(dec("0")),
+    func_round_decimal_overflow: "ROUND(DECIMAL '99999999999999999999999999999999999999', -1)" => Err(Error::Value("Decimal overflow".into())),
+    func_round_null: "ROUND(NULL)" => Ok(Null),
+    func_round_null_digits: "ROUND(1.5, NULL)" => Ok(Null),
+    func_round_float_digits_float: "ROUND(1.5, 1.0)" => Err(Error::Value("Can't take round of 1.5, 1".into())),
+    func_sign: "SIGN(-7)" => Ok(Integer(-1)),
+    func_sign_zero: "SIGN(0)" => Ok(Integer(0)),
+    func_sign_float: "SIGN(2.5)" => Ok(Float(1.0)),
+    func_sign_float_zero: "SIGN(0.0)" => Ok(Float(0.0)),
+    func_sign_float_nan: "SIGN(NAN)" => Ok(Float(std::f64::NAN)),
+    func_sign_decimal: "SIGN(DECIMAL '-0.01')" => Ok(dec("-1")),
+    func_sign_null: "SIGN(NULL)" => Ok(Null),
+    func_sqrt: "SQRT(16)" => Ok(Float(4.0)),
+    func_sqrt_float: "SQRT(2.25)" => Ok(Float(1.5)),
+    func_sqrt_decimal: "SQRT(DECIMAL '0.25')" => Ok(Float(0.5)),
+    func_sqrt_zero: "SQRT(-0.0)" => Ok(Float(-0.0)),
+    func_sqrt_infinity: "SQRT(INFINITY)" => Ok(Float(std::f64::INFINITY)),
+    func_sqrt_negative: "SQRT(-1)" => Err(Error::Value("Can't take square root of negative number".into())),
+    func_sqrt_negative_float: "SQRT(-0.5)" => Err(Error::Value("Can't take square root of negative number".into())),
+    func_sqrt_negative_decimal: "SQRT(DECIMAL '-0.5')" => Err(Error::Value("Can't take square root of negative number".into())),
+    func_sqrt_null: "SQRT(NULL)" => Ok(Null),
+    func_sqrt_string: "SQRT('4')" => Err(Error::Value("Can't take sqrt of 4".into())),
+    func_now_args: "NOW(1)" => Err(Error::Value("Unknown function now".into())),
     func_unknown: "unknown()" => Err(Error::Value("Unknown function unknown".into())),
     func_unknown_case: "UnKnown ( )" => Err(Error::Value("Unknown function unknown".into())),
     func_unknown_space: "unknown ( )" => Err(Error::Value("Unknown function unknown".into())),
@@ -85,16 +361,16 @@ test_expr! {
     op_and_null_true: "NULL AND TRUE" => Ok(Null),
     op_and_null_false: "NULL AND FALSE" => Ok(Boolean(false)),
     op_and_null_null: "NULL AND NULL" => Ok(Null),
-    op_and_error_float: "3.14 AND 3.14" => Err(Error::Value("Can't and 3.14 and 3.14".into())),
-    op_and_error_integer: "3 AND 3" => Err(Error::Value("Can't and 3 and 3".into())),
-    op_and_error_string: "'a' AND 'b'" => Err(Error::Value("Can't and a and b".into())),
+    op_and_error_float: "3.14 AND 3.14" => Err(Error::Value("Can't and FLOAT and FLOAT in 3.14 AND 3.14".into())),
+    op_and_error_integer: "3 AND 3" => Err(Error::Value("Can't and INTEGER and INTEGER in 3 AND 3".into())),
+    op_and_error_string: "'a' AND 'b'" => Err(Error::Value("Can't and STRING and STRING in a AND b".into())),
 
     op_not_true: "NOT TRUE" => Ok(Boolean(false)),
     op_not_false: "NOT FALSE" => Ok(Boolean(true)),
     op_not_null: "NOT NULL" => Ok(Null),
-    op_not_error_float: "NOT 3.14" => Err(Error::Value("Can't negate 3.14".into())),
-    op_not_error_integer: "NOT 3" => Err(Error::Value("Can't negate 3".into())),
-    op_not_error_string: "NOT 'abc'" => Err(Error::Value("Can't negate abc".into())),
+    op_not_error_float: "NOT 3.14" => Err(Error::Value("Can't negate FLOAT in NOT 3.14".into())),
+    op_not_error_integer: "NOT 3" => Err(Error::Value("Can't negate INTEGER in NOT 3".into())),
+    op_not_error_string: "NOT 'abc'" => Err(Error::Value("Can't negate STRING in NOT abc".into())),
 
     op_or_true_true: "TRUE OR TRUE" => Ok(Boolean(true)),
     op_or_true_false: "TRUE OR FALSE" => Ok(Boolean(true)),
@@ -105,12 +381,19 @@ test_expr! {
     op_or_null_true: "NULL OR TRUE" => Ok(Boolean(true)),
     op_or_null_false: "NULL OR FALSE" => Ok(Null),
     op_or_null_null: "NULL OR NULL" => Ok(Null),
-    op_or_error_float: "3.14 OR 3.14" => Err(Error::Value("Can't or 3.14 and 3.14".into())),
-    op_or_error_integer: "3 OR 3" => Err(Error::Value("Can't or 3 and 3".into())),
-    op_or_error_string: "'a' OR 'b'" => Err(Error::Value("Can't or a and b".into())),
+    op_not_and_null_false: "NOT (NULL AND FALSE)" => Ok(Boolean(true)),
+    op_not_and_null_true: "NOT (NULL AND TRUE)" => Ok(Null),
+    op_not_or_null_false: "NOT (NULL OR FALSE)" => Ok(Null),
+    op_not_or_null_true: "NOT (NULL OR TRUE)" => Ok(Boolean(false)),
+    op_or_error_float: "3.14 OR 3.14" => Err(Error::Value("Can't or FLOAT and FLOAT in 3.14 OR 3.14".into())),
+    op_or_error_integer: "3 OR 3" => Err(Error::Value("Can't or INTEGER and INTEGER in 3 OR 3".into())),
+    op_or_error_string: "'a' OR 'b'" => Err(Error::Value("Can't or STRING and STRING in a OR b".into())),
 
     // Comparison operators
     op_eq_bool: "TRUE = TRUE" => Ok(Boolean(true)),
+    op_eq_bytes: "x'00ff' = X'00FF'" => Ok(Boolean(true)),
+    op_eq_bytes_not: "x'00ff' = x'00'" => Ok(Boolean(false)),
+    op_eq_bytes_string: "x'61' = 'a'" => Err(Error::Value("Can't compare BYTEA and STRING in x'61' = a".into())),
     op_eq_bool_not: "TRUE = FALSE" => Ok(Boolean(false)),
     op_eq_float: "3.14 = 3.14" => Ok(Boolean(true)),
     op_eq_float_not: "3.14 = 2.718" => Ok(Boolean(false)),
@@ -130,7 +413,13 @@ test_expr! {
     op_eq_string_case: "'abc' = 'ABC'" => Ok(Boolean(false)),
     op_eq_string_unicode: "'😀' = '😀'" => Ok(Boolean(true)),
     op_eq_string_unicode_not: "'😀' = '🙁'" => Ok(Boolean(false)),
-    op_eq_conflict: "1 = 'a'" => Err(Error::Value("Can't compare 1 and a".into())),
+    op_eq_conflict: "1 = 'a'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 = a".into())),
+    op_eq_date: "DATE '2024-01-31' = DATE '2024-01-31'" => Ok(Boolean(true)),
+    op_eq_date_not: "DATE '2024-01-31' = DATE '2024-02-01'" => Ok(Boolean(false)),
+    op_eq_time: "TIME '12:00' = TIME '12:00:00.000'" => Ok(Boolean(true)),
+    op_eq_timestamp: "TIMESTAMP '2024-01-31T12:00:00Z' = TIMESTAMP '2024-01-31 12:00:00'" => Ok(Boolean(true)),
+    op_eq_temporal_conflict: "DATE '2024-01-31' = TIMESTAMP '2024-01-31'" => Err(Error::Value("Can't compare DATE and TIMESTAMP in 2024-01-31 = 2024-01-31 00:00:00".into())),
+    op_eq_temporal_string: "DATE '2024-01-31' = '2024-01-31'" => Err(Error::Value("Can't compare DATE and STRING in 2024-01-31 = 2024-01-31".into())),
 
     op_neq_bool: "TRUE != FALSE" => Ok(Boolean(true)),
     op_neq_bool_not: "TRUE != TRUE" => Ok(Boolean(false)),
@@ -152,9 +441,14 @@ test_expr! {
     op_neq_string_case: "'abc' != 'ABC'" => Ok(Boolean(true)),
     op_neq_string_unicode: "'😀' != '🙁'" => Ok(Boolean(true)),
     op_neq_string_unicode_not: "'😀' != '😀'" => Ok(Boolean(false)),
-    op_neq_conflict: "1 != 'a'" => Err(Error::Value("Can't compare 1 and a".into())),
+    op_neq_conflict: "1 != 'a'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 = a".into())),
 
     op_gt_bool: "TRUE > FALSE" => Ok(Boolean(true)),
+    op_gt_bytes: "x'0100' > x'00ff'" => Ok(Boolean(true)),
+    op_gt_bytes_prefix: "x'00' > x''" => Ok(Boolean(true)),
+    op_gt_date: "DATE '2024-02-01' > DATE '2024-01-31'" => Ok(Boolean(true)),
+    op_gt_time: "TIME '12:00:00.000001' > TIME '12:00'" => Ok(Boolean(true)),
+    op_gt_timestamp: "TIMESTAMP '1970-01-01' > TIMESTAMP '1969-12-31 23:59:59.999999'" => Ok(Boolean(true)),
     op_gt_bool_eq: "TRUE > TRUE" => Ok(Boolean(false)),
     op_gt_bool_not: "FALSE > TRUE" => Ok(Boolean(false)),
     op_gt_float: "3.14 > 3.13" => Ok(Boolean(true)),
@@ -185,7 +479,7 @@ test_expr! {
     op_gt_string_unicode: "'🙁' > '😀'" => Ok(Boolean(true)),
     op_gt_string_unicode_eq: "'😀' > '😀'" => Ok(Boolean(false)),
     op_gt_string_unicode_not: "'😀' > '🙁'" => Ok(Boolean(false)),
-    op_gt_conflict: "1 > 'a'" => Err(Error::Value("Can't compare 1 and a".into())),
+    op_gt_conflict: "1 > 'a'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 > a".into())),
 
     op_gte_bool: "TRUE >= TRUE" => Ok(Boolean(true)),
     op_gte_bool_gt: "TRUE >= FALSE" => Ok(Boolean(true)),
@@ -217,9 +511,13 @@ test_expr! {
     op_gte_string_unicode: "'😀' >= '😀'" => Ok(Boolean(true)),
     op_gte_string_unicode_gt: "'🙁' >= '😀'" => Ok(Boolean(true)),
     op_gte_string_unicode_not: "'😀' >= '🙁'" => Ok(Boolean(false)),
-    op_gte_conflict: "1 >= 'a'" => Err(Error::Value("Can't compare 1 and a".into())),
+    op_gte_conflict: "1 >= 'a'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 > a".into())),
 
     op_lt_bool: "FALSE < TRUE" => Ok(Boolean(true)),
+    op_lt_bytes: "x'00ff' < x'01'" => Ok(Boolean(true)),
+    op_lt_date: "DATE '2024-01-31' < DATE '2024-02-01'" => Ok(Boolean(true)),
+    op_lt_time: "TIME '23:59:59' < TIME '00:00'" => Ok(Boolean(false)),
+    op_lt_timestamp: "TIMESTAMP '2024-01-31 12:00:00' < TIMESTAMP '2024-01-31 12:00:01'" => Ok(Boolean(true)),
     op_lt_bool_eq: "TRUE < TRUE" => Ok(Boolean(false)),
     op_lt_bool_not: "TRUE < FALSE" => Ok(Boolean(false)),
     op_lt_float: "3.13 < 3.14" => Ok(Boolean(true)),
@@ -250,7 +548,7 @@ test_expr! {
     op_lt_string_unicode: "'😀' < '🙁'" => Ok(Boolean(true)),
     op_lt_string_unicode_eq: "'😀' < '😀'" => Ok(Boolean(false)),
     op_lt_string_unicode_not: "'🙁' < '😀'" => Ok(Boolean(false)),
-    op_lt_conflict: "1 < 'a'" => Err(Error::Value("Can't compare 1 and a".into())),
+    op_lt_conflict: "1 < 'a'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 < a".into())),
 
     op_lte_bool: "TRUE <= TRUE" => Ok(Boolean(true)),
     op_lte_bool_lt: "FALSE <= TRUE" => Ok(Boolean(true)),
@@ -282,7 +580,7 @@ test_expr! {
     op_lte_string_unicode: "'😀' <= '😀'" => Ok(Boolean(true)),
     op_lte_string_unicode_lt: "'😀' <= '🙁'" => Ok(Boolean(true)),
     op_lte_string_unicode_not: "'🙁' <= '😀'" => Ok(Boolean(false)),
-    op_lte_conflict: "1 <= 'a'" => Err(Error::Value("Can't compare 1 and a".into())),
+    op_lte_conflict: "1 <= 'a'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 < a".into())),
 
     op_null: "NULL IS NULL" => Ok(Boolean(true)),
     op_null_not: "NULL IS NOT NULL" => Ok(Boolean(false)),
@@ -290,6 +588,22 @@ test_expr! {
     op_null_bool_not: "TRUE IS NOT NULL" => Ok(Boolean(true)),
     op_null_rhs_bool: "NULL IS TRUE" => Err(Error::Parse("Expected token NULL, found TRUE".into())),
 
+    op_distinct: "1 IS DISTINCT FROM 2" => Ok(Boolean(true)),
+    op_distinct_equal: "1 IS DISTINCT FROM 1" => Ok(Boolean(false)),
+    op_distinct_float: "1 IS DISTINCT FROM 1.0" => Ok(Boolean(false)),
+    op_distinct_decimal: "DECIMAL '1.50' IS DISTINCT FROM 1.5" => Ok(Boolean(false)),
+    op_distinct_string: "'a' IS DISTINCT FROM 'A'" => Ok(Boolean(true)),
+    op_distinct_lhs_null: "NULL IS DISTINCT FROM 1" => Ok(Boolean(true)),
+    op_distinct_rhs_null: "1 IS DISTINCT FROM NULL" => Ok(Boolean(true)),
+    op_distinct_null: "NULL IS DISTINCT FROM NULL" => Ok(Boolean(false)),
+    op_distinct_conflict: "1 IS DISTINCT FROM 'a'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 IS DISTINCT FROM a".into())),
+    op_distinct_conflict_null: "NULL IS DISTINCT FROM 'a'" => Ok(Boolean(true)),
+    op_distinct_not: "1 IS NOT DISTINCT FROM 2" => Ok(Boolean(false)),
+    op_distinct_not_equal: "1 IS NOT DISTINCT FROM 1" => Ok(Boolean(true)),
+    op_distinct_not_null: "NULL IS NOT DISTINCT FROM NULL" => Ok(Boolean(true)),
+    op_distinct_not_lhs_null: "NULL IS NOT DISTINCT FROM 1" => Ok(Boolean(false)),
+    op_distinct_nofrom: "1 IS DISTINCT 2" => Err(Error::Parse("Expected token FROM, found 2".into())),
+
     // Math operators
     op_add_float_float: "3.1 + 2.71" => Ok(Float(3.1 + 2.71)),
     op_add_float_int: "3.72 + 1" => Ok(Float(3.72 + 1.0)),
@@ -307,8 +621,8 @@ test_expr! {
     op_add_underflow_int: "-9223372036854775807 + -2" => Err(Error::Value("Integer overflow".into())),
     op_add_overflow_float: "2e308 + 2e308" => Ok(Float(std::f64::INFINITY)),
     op_add_round_int_float: "9223372036854775807 + 10.0" => Ok(Float(9_223_372_036_854_776_000.0)),
-    op_add_error_bool: "TRUE + FALSE" => Err(Error::Value("Can't add TRUE and FALSE".into())),
-    op_add_error_strings: "'a' + 'b'" => Err(Error::Value("Can't add a and b".into())),
+    op_add_error_bool: "TRUE + FALSE" => Err(Error::Value("Can't add BOOLEAN and BOOLEAN in TRUE + FALSE".into())),
+    op_add_error_strings: "'a' + 'b'" => Err(Error::Value("Can't add STRING and STRING in a + b".into())),
 
     op_assert_float: "+3.72" => Ok(Float(3.72)),
     op_assert_int: "+1" => Ok(Integer(1)),
@@ -316,8 +630,8 @@ test_expr! {
     op_assert_infinity: "+INFINITY" => Ok(Float(std::f64::INFINITY)),
     op_assert_nan: "+NAN" => Ok(Float(std::f64::NAN)),
     op_assert_multi: "+++1" => Ok(Integer(1)),
-    op_assert_error_bool: "+TRUE" => Err(Error::Value("Can't take the positive of TRUE".into())),
-    op_assert_error_string: "+'abc'" => Err(Error::Value("Can't take the positive of abc".into())),
+    op_assert_error_bool: "+TRUE" => Err(Error::Value("Can't take the positive of BOOLEAN in TRUE".into())),
+    op_assert_error_string: "+'abc'" => Err(Error::Value("Can't take the positive of STRING in abc".into())),
 
     op_divide_float_float: "4.16 / 3.2" => Ok(Float(1.3)),
     op_divide_float_float_zero: "4.16 / 0.0" => Ok(Float(std::f64::INFINITY)),
@@ -330,6 +644,8 @@ test_expr! {
     op_divide_integer_integer: "8 / 3" => Ok(Integer(2)),
     op_divide_integer_integer_negative: "8 / -3" => Ok(Integer(-2)),
     op_divide_integer_integer_zero: "1 / 0" => Err(Error::Value("Can't divide by zero".into())),
+    op_divide_integer_integer_min: "(-9223372036854775807 - 1) / -1" => Err(Error::Value("Integer overflow".into())),
+    op_divide_integer_integer_min_one: "(-9223372036854775807 - 1) / 1" => Ok(Integer(std::i64::MIN)),
     op_divide_integer_null: "1 / NULL" => Ok(Null),
     op_divide_infinity: "1 / INFINITY" => Ok(Float(0.0)),
     op_divide_infinity_divisor: "INFINITY / 10" => Ok(Float(std::f64::INFINITY)),
@@ -338,8 +654,8 @@ test_expr! {
     op_divide_null_float: "NULL / 3.14" => Ok(Null),
     op_divide_null_integer: "NULL / 1" => Ok(Null),
     op_divide_null_null: "NULL / NULL" => Ok(Null),
-    op_divide_error_bool: "TRUE / FALSE" => Err(Error::Value("Can't divide TRUE and FALSE".into())),
-    op_divide_error_strings: "'a' / 'b'" => Err(Error::Value("Can't divide a and b".into())),
+    op_divide_error_bool: "TRUE / FALSE" => Err(Error::Value("Can't divide BOOLEAN and BOOLEAN in TRUE / FALSE".into())),
+    op_divide_error_strings: "'a' / 'b'" => Err(Error::Value("Can't divide STRING and STRING in a / b".into())),
 
     op_exp_float_float: "6.25 ^ 0.5" => Ok(Float(2.5)),
     op_exp_float_int: "6.25 ^ 2" => Ok(Float(39.0625)),
@@ -347,6 +663,7 @@ test_expr! {
     op_exp_int_float: "9 ^ 0.5" => Ok(Float(3.0)),
     op_exp_int_int: "2 ^ 3" => Ok(Integer(8)),
     op_exp_int_int_large: "2 ^ 10000000000" => Err(Error::Value("Integer overflow".into())),
+    op_exp_float_int_large: "1.0 ^ 10000000000" => Ok(Float(1.0)),
     op_exp_int_null: "1 ^ NULL" => Ok(Null),
     op_exp_null_float: "NULL ^ 3.14" => Ok(Null),
     op_exp_null_int: "NULL ^ 1" => Ok(Null),
@@ -359,16 +676,18 @@ test_expr! {
     op_exp_overflow_float: "10e200 ^ 2" => Ok(Float(std::f64::INFINITY)),
     op_exp_overflow_int: "9223372036854775807 ^ 2" => Err(Error::Value("Integer overflow".into())),
     op_exp_negative: "2 ^ -3" => Ok(Float(0.125)),
-    op_exp_error_bool: "TRUE ^ FALSE" => Err(Error::Value("Can't exponentiate TRUE and FALSE".into())),
-    op_exp_error_strings: "'a' ^ 'b'" => Err(Error::Value("Can't exponentiate a and b".into())),
+    op_exp_error_bool: "TRUE ^ FALSE" => Err(Error::Value("Can't exponentiate BOOLEAN and BOOLEAN in TRUE ^ FALSE".into())),
+    op_exp_error_strings: "'a' ^ 'b'" => Err(Error::Value("Can't exponentiate STRING and STRING in a ^ b".into())),
 
     op_factorial: "3!" => Ok(Integer(6)),
     op_factorial_zero: "0!" => Ok(Integer(1)),
+    op_factorial_max: "20!" => Ok(Integer(2_432_902_008_176_640_000)),
+    op_factorial_overflow: "21!" => Err(Error::Value("Integer overflow".into())),
     op_factorial_null: "NULL!" => Ok(Null),
-    op_factorial_error_bool: "TRUE!" => Err(Error::Value("Can't take factorial of TRUE".into())),
-    op_factorial_error_float: "3.14!" => Err(Error::Value("Can't take factorial of 3.14".into())),
+    op_factorial_error_bool: "TRUE!" => Err(Error::Value("Can't take factorial of BOOLEAN in !TRUE".into())),
+    op_factorial_error_float: "3.14!" => Err(Error::Value("Can't take factorial of FLOAT in !3.14".into())),
     op_factorial_error_negative: "-3!" => Err(Error::Value("Can't take factorial of negative number".into())),
-    op_factorial_error_string: "'abc'!" => Err(Error::Value("Can't take factorial of abc".into())),
+    op_factorial_error_string: "'abc'!" => Err(Error::Value("Can't take factorial of STRING in !abc".into())),
 
     op_modulo_float_float: "6.28 % 2.2" => Ok(Float(1.88)),
     op_modulo_float_float_zero: "6.28 % 0.0" => Ok(Float(std::f64::NAN)),
@@ -383,11 +702,12 @@ test_expr! {
     op_modulo_null_null: "NULL % NULL" => Ok(Null),
     op_modulo_negative: "-5 % 3" => Ok(Integer(-2)),
     op_modulo_negative_rhs: "5 % -3" => Ok(Integer(2)),
+    op_modulo_min: "(-9223372036854775807 - 1) % -1" => Ok(Integer(0)),
     op_modulo_infinity: "INFINITY % 7" => Ok(Float(std::f64::NAN)),
     op_modulo_infinity_divisor: "7 % INFINITY" => Ok(Float(7.0)),
     op_modulo_nan: "7 % NAN" => Ok(Float(std::f64::NAN)),
-    op_modulo_error_bool: "TRUE % FALSE" => Err(Error::Value("Can't take modulo of TRUE and FALSE".into())),
-    op_modulo_error_strings: "'a' % 'b'" => Err(Error::Value("Can't take modulo of a and b".into())),
+    op_modulo_error_bool: "TRUE % FALSE" => Err(Error::Value("Can't take modulo of BOOLEAN and BOOLEAN in TRUE % FALSE".into())),
+    op_modulo_error_strings: "'a' % 'b'" => Err(Error::Value("Can't take modulo of STRING and STRING in a % b".into())),
 
     op_multiply_float_float: "3.1 * 2.71" => Ok(Float(3.1 * 2.71)),
     op_multiply_float_int: "3.72 * 1" => Ok(Float(3.72 * 1.0)),
@@ -405,8 +725,8 @@ test_expr! {
     op_multiply_underflow_int: "9223372036854775807 * -2" => Err(Error::Value("Integer overflow".into())),
     op_multiply_overflow_float: "2e308 * 2" => Ok(Float(std::f64::INFINITY)),
     op_multiply_round_int_float: "9223372036854775807 * 2.0" => Ok(Float(18_446_744_073_709_552_000.0)),
-    op_multiply_error_bool: "TRUE * FALSE" => Err(Error::Value("Can't multiply TRUE and FALSE".into())),
-    op_multiply_error_strings: "'a' * 'b'" => Err(Error::Value("Can't multiply a and b".into())),
+    op_multiply_error_bool: "TRUE * FALSE" => Err(Error::Value("Can't multiply BOOLEAN and BOOLEAN in TRUE * FALSE".into())),
+    op_multiply_error_strings: "'a' * 'b'" => Err(Error::Value("Can't multiply STRING and STRING in a * b".into())),
 
     op_negate: "-1" => Ok(Integer(-1)),
     op_negate_double: "--1" => Ok(Integer(1)),
@@ -416,8 +736,9 @@ test_expr! {
     op_negate_null: "-NULL" => Ok(Null),
     op_negate_infinity: "-INFINITY" => Ok(Float(-std::f64::INFINITY)),
     op_negate_nan: "-NAN" => Ok(Float(std::f64::NAN)),
-    op_negate_error_bool: "-TRUE" => Err(Error::Value("Can't negate TRUE".into())),
-    op_negate_error_string: "-'abc'" => Err(Error::Value("Can't negate abc".into())),
+    op_negate_error_bool: "-TRUE" => Err(Error::Value("Can't negate BOOLEAN in -TRUE".into())),
+    op_negate_error_string: "-'abc'" => Err(Error::Value("Can't negate STRING in -abc".into())),
+    op_negate_min: "-(-9223372036854775807 - 1)" => Err(Error::Value("Integer overflow".into())),
 
     op_subtract_float_float: "3.1 - 2.71" => Ok(Float(3.1 - 2.71)),
     op_subtract_float_int: "3.72 - 1" => Ok(Float(3.72 - 1.0)),
@@ -435,8 +756,8 @@ test_expr! {
     op_subtract_underflow_int: "-9223372036854775807 - 2" => Err(Error::Value("Integer overflow".into())),
     op_subtract_overflow_float: "2e308 - -2e308" => Ok(Float(std::f64::INFINITY)),
     op_subtract_round_int_float: "9223372036854775807 - -10.0" => Ok(Float(9_223_372_036_854_776_000.0)),
-    op_subtract_error_bool: "TRUE - FALSE" => Err(Error::Value("Can't subtract TRUE and FALSE".into())),
-    op_subtract_error_strings: "'a' - 'b'" => Err(Error::Value("Can't subtract a and b".into())),
+    op_subtract_error_bool: "TRUE - FALSE" => Err(Error::Value("Can't subtract BOOLEAN and BOOLEAN in TRUE - FALSE".into())),
+    op_subtract_error_strings: "'a' - 'b'" => Err(Error::Value("Can't subtract STRING and STRING in a - b".into())),
 
     // String operators
     op_like_percent: "'abcde' LIKE 'a%e'" => Ok(Boolean(true)),
@@ -458,13 +779,149 @@ test_expr! {
     op_like_neq: "'xyz' LIKE 'abc'" => Ok(Boolean(false)),
     op_like_null: "'abc' LIKE NULL" => Ok(Null),
     op_like_null_lhs: "NULL LIKE 'abc'" => Ok(Null),
+    op_like_null_both: "NULL LIKE NULL" => Ok(Null),
+    op_like_error_integer: "1 LIKE '1'" => Err(Error::Value("Can't LIKE INTEGER and STRING in 1 LIKE 1".into())),
+    op_like_dot: "'abc' LIKE 'a.c'" => Ok(Boolean(false)),
+    op_like_regex_chars: "'a.b(c)[d]+$' LIKE 'a.b(c)[d]+$'" => Ok(Boolean(true)),
+    op_like_newline: "'a
+b' LIKE 'a%b'" => Ok(Boolean(true)),
+    op_like_newline_underscore: "'a
+b' LIKE 'a_b'" => Ok(Boolean(true)),
+    op_like_escape_percent: r"'100%' LIKE '100\%' ESCAPE '\'" => Ok(Boolean(true)),
+    op_like_escape_percent_not: r"'1000' LIKE '100\%' ESCAPE '\'" => Ok(Boolean(false)),
+    op_like_escape_underscore: "'a_c' LIKE 'a!_c' ESCAPE '!'" => Ok(Boolean(true)),
+    op_like_escape_underscore_not: "'abc' LIKE 'a!_c' ESCAPE '!'" => Ok(Boolean(false)),
+    op_like_escape_escape: "'a!c' LIKE 'a!!c' ESCAPE '!'" => Ok(Boolean(true)),
+    op_like_escape_letter: "'abc' LIKE 'a!bc' ESCAPE '!'" => Ok(Boolean(true)),
+    op_like_escape_double: "'a%%c' LIKE 'a%%c' ESCAPE '!'" => Ok(Boolean(true)),
+    op_like_escape_double_wildcard: "'abc' LIKE 'a%%c' ESCAPE '!'" => Ok(Boolean(true)),
+    op_like_escape_mixed: "'a_bxyz%' LIKE 'a!__%!%' ESCAPE '!'" => Ok(Boolean(true)),
+    op_like_escape_null: "NULL LIKE 'a!%' ESCAPE '!'" => Ok(Null),
+    op_like_escape_trailing: "'a!' LIKE 'a!' ESCAPE '!'" => Err(Error::Value(
+        "LIKE pattern 'a!' can't end with escape character".into()
+    )),
+    op_like_escape_empty: "'a' LIKE 'a' ESCAPE ''" => Err(Error::Parse(
+        "ESCAPE must be a single character, got ''".into()
+    )),
+    op_like_escape_long: "'a' LIKE 'a' ESCAPE '!!'" => Err(Error::Parse(
+        "ESCAPE must be a single character, got '!!'".into()
+    )),
+    op_not_like: "'abcde' NOT LIKE 'a%e'" => Ok(Boolean(false)),
+    op_not_like_not: "'abcde' NOT LIKE 'a_e'" => Ok(Boolean(true)),
+    op_not_like_null: "'abc' NOT LIKE NULL" => Ok(Null),
+    op_not_like_escape: "'a%c' NOT LIKE 'a!%c' ESCAPE '!'" => Ok(Boolean(false)),
+    op_not_like_and: "'abc' NOT LIKE 'x%' AND 'abc' LIKE 'a%'" => Ok(Boolean(true)),
+    op_not_like_not_like: "NOT ('abc' NOT LIKE 'a%')" => Ok(Boolean(true)),
+    op_ilike: "'ABCDE' ILIKE 'a%e'" => Ok(Boolean(true)),
+    op_ilike_not: "'abcdef' ILIKE 'A%E'" => Ok(Boolean(false)),
+    op_ilike_underscore: "'aBc' ILIKE 'A_C'" => Ok(Boolean(true)),
+    op_ilike_unicode: "'ÅRE' ILIKE 'åre'" => Ok(Boolean(true)),
+    op_ilike_regex_chars: "'A.B(C)' ILIKE 'a.b(c)'" => Ok(Boolean(true)),
+    op_ilike_dot: "'ABC' ILIKE 'a.c'" => Ok(Boolean(false)),
+    op_ilike_escape: "'A%C' ILIKE 'a!%c' ESCAPE '!'" => Ok(Boolean(true)),
+    op_ilike_escape_not: "'ABC' ILIKE 'a!%c' ESCAPE '!'" => Ok(Boolean(false)),
+    op_ilike_null: "'abc' ILIKE NULL" => Ok(Null),
+    op_ilike_error_integer: "1 ILIKE '1'" => Err(Error::Value("Can't ILIKE INTEGER and STRING in 1 ILIKE 1".into())),
+    op_not_ilike: "'ABC' NOT ILIKE 'a%'" => Ok(Boolean(false)),
+    op_not_ilike_not: "'ABC' NOT ILIKE 'b%'" => Ok(Boolean(true)),
+
+    op_between: "2 BETWEEN 1 AND 3" => Ok(Boolean(true)),
+    op_between_low: "1 BETWEEN 1 AND 3" => Ok(Boolean(true)),
+    op_between_high: "3 BETWEEN 1 AND 3" => Ok(Boolean(true)),
+    op_between_below: "0 BETWEEN 1 AND 3" => Ok(Boolean(false)),
+    op_between_above: "4 BETWEEN 1 AND 3" => Ok(Boolean(false)),
+    op_between_reversed: "2 BETWEEN 3 AND 1" => Ok(Boolean(false)),
+    op_between_numeric: "2.5 BETWEEN 2 AND DECIMAL '3.5'" => Ok(Boolean(true)),
+    op_between_string: "'b' BETWEEN 'a' AND 'c'" => Ok(Boolean(true)),
+    op_between_expr: "1 + 2 BETWEEN 1 + 1 AND 2 * 2" => Ok(Boolean(true)),
+    op_between_null: "NULL BETWEEN 1 AND 3" => Ok(Null),
+    op_between_null_low: "2 BETWEEN NULL AND 3" => Ok(Null),
+    op_between_null_low_false: "4 BETWEEN NULL AND 3" => Ok(Boolean(false)),
+    op_between_and: "2 BETWEEN 1 AND 3 AND FALSE" => Ok(Boolean(false)),
+    op_between_or: "0 BETWEEN 1 AND 3 OR TRUE" => Ok(Boolean(true)),
+    op_between_incomparable: "1 BETWEEN 'a' AND 'b'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 > a".into())),
+    op_between_no_and: "1 BETWEEN 0" => Err(Error::Parse("Unexpected end of input".into())),
+    op_between_no_and_or: "1 BETWEEN 0 OR 2" => Err(Error::Parse("Expected token AND, found OR".into())),
+    op_not_between: "4 NOT BETWEEN 1 AND 3" => Ok(Boolean(true)),
+    op_not_between_not: "2 NOT BETWEEN 1 AND 3" => Ok(Boolean(false)),
+    op_not_between_null: "2 NOT BETWEEN NULL AND 3" => Ok(Null),
+
+    op_in: "2 IN (1, 2, 3)" => Ok(Boolean(true)),
+    op_in_not: "4 IN (1, 2, 3)" => Ok(Boolean(false)),
+    op_in_single: "1 IN (1)" => Ok(Boolean(true)),
+    op_in_expr: "1 + 1 IN (3 - 1, 5)" => Ok(Boolean(true)),
+    op_in_numeric: "1 IN (2.0, 1.0)" => Ok(Boolean(true)),
+    op_in_string: "'b' IN ('a', 'b')" => Ok(Boolean(true)),
+    op_in_null: "NULL IN (1, 2)" => Ok(Null),
+    op_in_null_list: "NULL IN (NULL)" => Ok(Null),
+    op_in_null_match: "1 IN (NULL, 1)" => Ok(Boolean(true)),
+    op_in_null_nomatch: "3 IN (1, NULL, 2)" => Ok(Null),
+    op_in_lazy: "1 IN (1, 1 / 0)" => Ok(Boolean(true)),
+    op_in_incomparable: "1 IN ('a')" => Err(Error::Value("Can't compare INTEGER and STRING in 1 IN (a)".into())),
+    op_in_empty: "1 IN ()" => Err(Error::Parse("Expected expression atom, found )".into())),
+    op_in_bare: "1 IN 1" => Err(Error::Parse("Expected token (, found 1".into())),
+    op_in_and: "1 IN (1) AND 2 IN (2)" => Ok(Boolean(true)),
+    op_not_in: "4 NOT IN (1, 2, 3)" => Ok(Boolean(true)),
+    op_not_in_not: "2 NOT IN (1, 2, 3)" => Ok(Boolean(false)),
+    op_not_in_null: "NULL NOT IN (1, 2)" => Ok(Null),
+    op_not_in_null_match: "1 NOT IN (NULL, 1)" => Ok(Boolean(false)),
+    op_not_in_null_nomatch: "3 NOT IN (1, NULL, 2)" => Ok(Null),
+    op_not_in_not_in: "NOT (1 NOT IN (1))" => Ok(Boolean(true)),
+    op_in_position: "POSITION('b' IN 'abc') IN (2)" => Ok(Boolean(true)),
+
+    // Decimal operators. Equality is numeric, so these don't check the result scale.
+    op_add_decimal: "DECIMAL '1.5' + DECIMAL '2.25'" => Ok(dec("3.75")),
+    op_add_decimal_integer: "DECIMAL '1.5' + 2" => Ok(dec("3.5")),
+    op_add_integer_decimal: "2 + DECIMAL '1.5'" => Ok(dec("3.5")),
+    op_add_decimal_float: "DECIMAL '1.5' + 0.25" => Ok(Float(1.75)),
+    op_add_decimal_null: "DECIMAL '1.5' + NULL" => Ok(Null),
+    op_add_decimal_exact: "DECIMAL '0.1' + DECIMAL '0.2' = DECIMAL '0.3'" => Ok(Boolean(true)),
+    op_add_decimal_overflow: "DECIMAL '99999999999999999999999999999999999999' + 1" => Err(Error::Value("Decimal overflow".into())),
+    op_add_decimal_string: "DECIMAL '1.5' + 'a'" => Err(Error::Value("Can't add DECIMAL(2,1) and STRING in 1.5 + a".into())),
+    op_subtract_decimal: "DECIMAL '1.5' - DECIMAL '2.25'" => Ok(dec("-0.75")),
+    op_subtract_decimal_integer: "DECIMAL '1.5' - 2" => Ok(dec("-0.5")),
+    op_subtract_decimal_overflow: "DECIMAL '-99999999999999999999999999999999999999' - 1" => Err(Error::Value("Decimal overflow".into())),
+    op_multiply_decimal: "DECIMAL '1.5' * DECIMAL '2.25'" => Ok(dec("3.375")),
+    op_multiply_decimal_integer: "DECIMAL '19.99' * 3" => Ok(dec("59.97")),
+    op_multiply_decimal_float: "DECIMAL '1.5' * 2.0" => Ok(Float(3.0)),
+    op_multiply_decimal_overflow: "DECIMAL '9999999999999999999999999999999999999' * 100" => Err(Error::Value("Decimal overflow".into())),
+    op_divide_decimal: "DECIMAL '10' / DECIMAL '4'" => Ok(dec("2.5")),
+    op_divide_decimal_repeating: "DECIMAL '1.00' / DECIMAL '3'" => Ok(dec("0.33333333")),
+    op_divide_decimal_repeating_round: "DECIMAL '2' / DECIMAL '3'" => Ok(dec("0.666667")),
+    op_divide_decimal_repeating_negative: "DECIMAL '-2' / 3" => Ok(dec("-0.666667")),
+    op_divide_decimal_integer: "1 / DECIMAL '8'" => Ok(dec("0.125")),
+    op_divide_decimal_small: "DECIMAL '1' / DECIMAL '3000000'" => Ok(dec("0")),
+    op_divide_decimal_zero: "DECIMAL '1' / DECIMAL '0.00'" => Err(Error::Value("Can't divide by zero".into())),
+    op_divide_decimal_overflow: "DECIMAL '99999999999999999999999999999999999999' / DECIMAL '0.1'" => Err(Error::Value("Decimal overflow".into())),
+    op_modulo_decimal: "DECIMAL '7.5' % DECIMAL '2'" => Ok(dec("1.5")),
+    op_modulo_decimal_negative: "DECIMAL '-7.5' % 2" => Ok(dec("-1.5")),
+    op_modulo_decimal_zero: "DECIMAL '7.5' % 0" => Err(Error::Value("Can't divide by zero".into())),
+    op_exponentiate_decimal: "DECIMAL '1.5' ^ 2" => Ok(Float(2.25)),
+    op_negate_decimal: "-DECIMAL '1.5'" => Ok(dec("-1.5")),
+    op_assert_decimal: "+DECIMAL '1.5'" => Ok(dec("1.5")),
+    op_factorial_decimal: "DECIMAL '3'!" => Err(Error::Value("Can't take factorial of DECIMAL(1,0) in !3".into())),
+    op_eq_decimal: "DECIMAL '1.5' = DECIMAL '1.5'" => Ok(Boolean(true)),
+    op_eq_decimal_scale: "DECIMAL '1.5' = DECIMAL '1.500'" => Ok(Boolean(true)),
+    op_eq_decimal_scale_not: "DECIMAL '1.5' = DECIMAL '1.501'" => Ok(Boolean(false)),
+    op_eq_decimal_integer: "DECIMAL '3.00' = 3" => Ok(Boolean(true)),
+    op_eq_decimal_integer_not: "DECIMAL '3.01' = 3" => Ok(Boolean(false)),
+    op_eq_decimal_float: "DECIMAL '0.1' = 0.1" => Ok(Boolean(true)),
+    op_eq_decimal_null: "DECIMAL '0.1' = NULL" => Ok(Null),
+    op_eq_decimal_string: "DECIMAL '0.1' = '0.1'" => Err(Error::Value("Can't compare DECIMAL(1,1) and STRING in 0.1 = 0.1".into())),
+    op_gt_decimal: "DECIMAL '1.01' > DECIMAL '1.009'" => Ok(Boolean(true)),
+    op_gt_decimal_negative: "DECIMAL '-1.01' > DECIMAL '-1.009'" => Ok(Boolean(false)),
+    op_gt_decimal_integer: "DECIMAL '3.01' > 3" => Ok(Boolean(true)),
+    op_gt_decimal_float: "DECIMAL '3.01' > 3.1" => Ok(Boolean(false)),
+    op_lt_decimal: "DECIMAL '1.009' < DECIMAL '1.01'" => Ok(Boolean(true)),
+    op_lt_decimal_scale: "DECIMAL '1.10' < DECIMAL '1.1'" => Ok(Boolean(false)),
+    op_lt_integer_decimal: "2 < DECIMAL '2.5'" => Ok(Boolean(true)),
 
     // Operator precedence, testing each operator against the ones at the same level and immediately
     // below it in order.
     op_prec_negate_factorial: "-3!" => Err(Error::Value("Can't take factorial of negative number".into())),
     op_prec_negate_factorial_paren: "-(3!)" => Ok(Integer(-6)),
     op_prec_negate_is: "-NULL IS NULL" => Ok(Boolean(true)),
-    op_prec_negate_is_paren: "-(NULL IS NULL)" => Err(Error::Value("Can't negate TRUE".into())),
+    op_prec_negate_is_paren: "-(NULL IS NULL)" => Err(Error::Value("Can't negate BOOLEAN in -NULL IS NULL".into())),
 
     op_prec_not_factorial: "NOT NULL IS NULL" => Ok(Boolean(true)),
     op_prec_not_factorial_paren: "NOT (NULL IS NULL)" => Ok(Boolean(false)),
@@ -476,6 +933,10 @@ test_expr! {
 
     op_prec_is_exp: "2^NULL IS NULL" => Err(Error::Value("Can't exponentiate 2 and TRUE".into())),
     op_prec_is_exp_paren: "(2^NULL) IS NULL" => Ok(Boolean(true)),
+    op_prec_is_distinct_add: "1 IS DISTINCT FROM 1 + 1" => Err(Error::Value("Can't add BOOLEAN and INTEGER in 1 IS DISTINCT FROM 1 + 1".into())),
+    op_prec_is_distinct_add_paren: "1 IS DISTINCT FROM (1 + 1)" => Ok(Boolean(true)),
+    op_prec_is_distinct_and: "1 IS DISTINCT FROM NULL AND TRUE" => Ok(Boolean(true)),
+    op_prec_is_distinct_negate: "-1 IS DISTINCT FROM -1" => Ok(Boolean(false)),
 
     op_assoc_exp: "2^3^2" => Ok(Integer(512)),
     op_assoc_exp_paren: "(2^3)^2" => Ok(Integer(64)),
@@ -509,79 +970,317 @@ test_expr! {
 
     op_prec_add_subtract: "1 + 2 - 3" => Ok(Integer(0)),
     op_prec_add_gt: "1 + 2 > 2" => Ok(Boolean(true)),
-    op_prec_add_gt_paren: "1 + (2 > 2)" => Err(Error::Value("Can't add 1 and FALSE".into())),
+    op_prec_add_gt_paren: "1 + (2 > 2)" => Err(Error::Value("Can't add INTEGER and BOOLEAN in 1 + 2 > 2".into())),
     op_prec_add_gte: "1 + 2 >= 2" => Ok(Boolean(true)),
-    op_prec_add_gte_paren: "1 + (2 >= 2)" => Err(Error::Value("Can't add 1 and TRUE".into())),
+    op_prec_add_gte_paren: "1 + (2 >= 2)" => Err(Error::Value("Can't add INTEGER and BOOLEAN in 1 + 2 > 2 OR 2 = 2".into())),
     op_prec_add_lt: "1 + 2 < 2" => Ok(Boolean(false)),
-    op_prec_add_lt_paren: "1 + (2 < 2)" => Err(Error::Value("Can't add 1 and FALSE".into())),
+    op_prec_add_lt_paren: "1 + (2 < 2)" => Err(Error::Value("Can't add INTEGER and BOOLEAN in 1 + 2 < 2".into())),
     op_prec_add_lte: "1 + 2 <= 2" => Ok(Boolean(false)),
-    op_prec_add_lte_paren: "1 + (2 <= 2)" => Err(Error::Value("Can't add 1 and TRUE".into())),
+    op_prec_add_lte_paren: "1 + (2 <= 2)" => Err(Error::Value("Can't add INTEGER and BOOLEAN in 1 + 2 < 2 OR 2 = 2".into())),
 
     op_prec_subtract_add: "3 - 2 + 1" => Ok(Integer(2)),
     op_prec_subtract_gt: "5 - 2 > 2" => Ok(Boolean(true)),
-    op_prec_subtract_gt_paren: "5 - (2 > 2)" => Err(Error::Value("Can't subtract 5 and FALSE".into())),
+    op_prec_subtract_gt_paren: "5 - (2 > 2)" => Err(Error::Value("Can't subtract INTEGER and BOOLEAN in 5 - 2 > 2".into())),
     op_prec_subtract_gte: "5 - 2 >= 2" => Ok(Boolean(true)),
-    op_prec_subtract_gte_paren: "5 - (2 >= 2)" => Err(Error::Value("Can't subtract 5 and TRUE".into())),
+    op_prec_subtract_gte_paren: "5 - (2 >= 2)" => Err(Error::Value("Can't subtract INTEGER and BOOLEAN in 5 - 2 > 2 OR 2 = 2".into())),
     op_prec_subtract_lt: "5 - 2 < 2" => Ok(Boolean(false)),
-    op_prec_subtract_lt_paren: "5 - (2 < 2)" => Err(Error::Value("Can't subtract 5 and FALSE".into())),
+    op_prec_subtract_lt_paren: "5 - (2 < 2)" => Err(Error::Value("Can't subtract INTEGER and BOOLEAN in 5 - 2 < 2".into())),
     op_prec_subtract_lte: "5 - 2 <= 2" => Ok(Boolean(false)),
-    op_prec_subtract_lte_paren: "5 - (2 <= 2)" => Err(Error::Value("Can't subtract 5 and TRUE".into())),
+    op_prec_subtract_lte_paren: "5 - (2 <= 2)" => Err(Error::Value("Can't subtract INTEGER and BOOLEAN in 5 - 2 < 2 OR 2 = 2".into())),
 
     op_prec_gt_gte: "5 > 3 >= TRUE" => Ok(Boolean(true)),
     op_prec_gt_lt: "5 > 3 < TRUE" => Ok(Boolean(false)),
     op_prec_gt_lte: "5 > 3 <= TRUE" => Ok(Boolean(true)),
     op_prec_gt_eq: "5 > 3 = TRUE" => Ok(Boolean(true)),
-    op_prec_gt_eq_paren: "5 > (3 = TRUE)" => Err(Error::Value("Can't compare 3 and TRUE".into())),
+    op_prec_gt_eq_paren: "5 > (3 = TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 3 = TRUE".into())),
     op_prec_gt_neq: "5 > 3 != TRUE" => Ok(Boolean(false)),
-    op_prec_gt_neq_paren: "5 > (3 != TRUE)" => Err(Error::Value("Can't compare 3 and TRUE".into())),
-    op_prec_gt_like: "5 > 3 LIKE 'abc'" => Err(Error::Value("Can't LIKE TRUE and abc".into())),
-    op_prec_gt_like_paren: "5 > (3 LIKE 'abc')" => Err(Error::Value("Can't LIKE 3 and abc".into())),
+    op_prec_gt_neq_paren: "5 > (3 != TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 3 = TRUE".into())),
+    op_prec_gt_like: "5 > 3 LIKE 'abc'" => Err(Error::Value("Can't LIKE BOOLEAN and STRING in 5 > 3 LIKE abc".into())),
+    op_prec_gt_like_paren: "5 > (3 LIKE 'abc')" => Err(Error::Value("Can't LIKE INTEGER and STRING in 3 LIKE abc".into())),
 
     op_prec_gte_gt: "5 >= 3 > TRUE" => Ok(Boolean(false)),
     op_prec_gte_lt: "5 >= 3 < TRUE" => Ok(Boolean(false)),
     op_prec_gte_lte: "5 >= 3 <= TRUE" => Ok(Boolean(true)),
     op_prec_gte_eq: "5 >= 3 = TRUE" => Ok(Boolean(true)),
-    op_prec_gte_eq_paren: "5 >= (3 = TRUE)" => Err(Error::Value("Can't compare 3 and TRUE".into())),
+    op_prec_gte_eq_paren: "5 >= (3 = TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 3 = TRUE".into())),
     op_prec_gte_neq: "5 >= 3 != TRUE" => Ok(Boolean(false)),
-    op_prec_gte_neq_paren: "5 >= (3 != TRUE)" => Err(Error::Value("Can't compare 3 and TRUE".into())),
-    op_prec_gte_like: "5 >= 3 LIKE 'abc'" => Err(Error::Value("Can't LIKE TRUE and abc".into())),
-    op_prec_gte_like_paren: "5 >= (3 LIKE 'abc')" => Err(Error::Value("Can't LIKE 3 and abc".into())),
+    op_prec_gte_neq_paren: "5 >= (3 != TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 3 = TRUE".into())),
+    op_prec_gte_like: "5 >= 3 LIKE 'abc'" => Err(Error::Value("Can't LIKE BOOLEAN and STRING in 5 > 3 OR 5 = 3 LIKE abc".into())),
+    op_prec_gte_like_paren: "5 >= (3 LIKE 'abc')" => Err(Error::Value("Can't LIKE INTEGER and STRING in 3 LIKE abc".into())),
 
     op_prec_lt_gt: "3 < 5 > TRUE" => Ok(Boolean(false)),
     op_prec_lt_gte: "3 < 5 >= TRUE" => Ok(Boolean(true)),
     op_prec_lt_lte: "3 < 5 <= TRUE" => Ok(Boolean(true)),
     op_prec_lt_eq: "3 < 5 = TRUE" => Ok(Boolean(true)),
-    op_prec_lt_eq_paren: "3 < (5 = TRUE)" => Err(Error::Value("Can't compare 5 and TRUE".into())),
+    op_prec_lt_eq_paren: "3 < (5 = TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 5 = TRUE".into())),
     op_prec_lt_neq: "3 < 5 != TRUE" => Ok(Boolean(false)),
-    op_prec_lt_neq_paren: "3 < (5 != TRUE)" => Err(Error::Value("Can't compare 5 and TRUE".into())),
-    op_prec_lt_like: "3 < 5 LIKE 'abc'" => Err(Error::Value("Can't LIKE TRUE and abc".into())),
-    op_prec_lt_like_paren: "3 < (5 LIKE 'abc')" => Err(Error::Value("Can't LIKE 5 and abc".into())),
+    op_prec_lt_neq_paren: "3 < (5 != TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 5 = TRUE".into())),
+    op_prec_lt_like: "3 < 5 LIKE 'abc'" => Err(Error::Value("Can't LIKE BOOLEAN and STRING in 3 < 5 LIKE abc".into())),
+    op_prec_lt_like_paren: "3 < (5 LIKE 'abc')" => Err(Error::Value("Can't LIKE INTEGER and STRING in 5 LIKE abc".into())),
 
     op_prec_lte_gt: "3 <= 5 > TRUE" => Ok(Boolean(false)),
     op_prec_lte_gte: "3 <= 5 >= TRUE" => Ok(Boolean(true)),
     op_prec_lte_lte: "3 <= 5 <= TRUE" => Ok(Boolean(true)),
     op_prec_lte_eq: "3 <= 5 = TRUE" => Ok(Boolean(true)),
-    op_prec_lte_eq_paren: "3 <= (5 = TRUE)" => Err(Error::Value("Can't compare 5 and TRUE".into())),
+    op_prec_lte_eq_paren: "3 <= (5 = TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 5 = TRUE".into())),
     op_prec_lte_neq: "3 <= 5 != TRUE" => Ok(Boolean(false)),
-    op_prec_lte_neq_paren: "3 <= (5 != TRUE)" => Err(Error::Value("Can't compare 5 and TRUE".into())),
-    op_prec_lte_like: "3 <= 5 LIKE 'abc'" => Err(Error::Value("Can't LIKE TRUE and abc".into())),
-    op_prec_lte_like_paren: "3 <= (5 LIKE 'abc')" => Err(Error::Value("Can't LIKE 5 and abc".into())),
+    op_prec_lte_neq_paren: "3 <= (5 != TRUE)" => Err(Error::Value("Can't compare INTEGER and BOOLEAN in 5 = TRUE".into())),
+    op_prec_lte_like: "3 <= 5 LIKE 'abc'" => Err(Error::Value("Can't LIKE BOOLEAN and STRING in 3 < 5 OR 3 = 5 LIKE abc".into())),
+    op_prec_lte_like_paren: "3 <= (5 LIKE 'abc')" => Err(Error::Value("Can't LIKE INTEGER and STRING in 5 LIKE abc".into())),
 
     op_prec_eq_neq: "1 = 1 != FALSE" => Ok(Boolean(true)),
-    op_prec_eq_like: "1 = 1 LIKE 'abc'" => Err(Error::Value("Can't LIKE TRUE and abc".into())),
+    op_prec_eq_like: "1 = 1 LIKE 'abc'" => Err(Error::Value("Can't LIKE BOOLEAN and STRING in 1 = 1 LIKE abc".into())),
     op_prec_eq_and: "1 = 1 AND TRUE" => Ok(Boolean(true)),
-    op_prec_eq_and_paren: "1 = (1 AND TRUE)" => Err(Error::Value("Can't and 1 and TRUE".into())),
+    op_prec_eq_and_paren: "1 = (1 AND TRUE)" => Err(Error::Value("Can't and INTEGER and BOOLEAN in 1 AND TRUE".into())),
 
     op_prec_neq_eq: "1 != 2 = TRUE" => Ok(Boolean(true)),
-    op_prec_neq_like: "1 != 2 LIKE 'abc'" => Err(Error::Value("Can't LIKE TRUE and abc".into())),
+    op_prec_neq_like: "1 != 2 LIKE 'abc'" => Err(Error::Value("Can't LIKE BOOLEAN and STRING in NOT 1 = 2 LIKE abc".into())),
     op_prec_neq_and: "2 != 1 AND TRUE" => Ok(Boolean(true)),
-    op_prec_neq_and_paren: "2 != (1 AND TRUE)" => Err(Error::Value("Can't and 1 and TRUE".into())),
+    op_prec_neq_and_paren: "2 != (1 AND TRUE)" => Err(Error::Value("Can't and INTEGER and BOOLEAN in 1 AND TRUE".into())),
 
     op_prec_like_eq: "'abc' LIKE 'abc' = TRUE" => Ok(Boolean(true)),
     op_prec_like_neq: "'abc' LIKE 'abc' != FALSE" => Ok(Boolean(true)),
     op_prec_like_and: "'abc' LIKE 'abc' AND TRUE" => Ok(Boolean(true)),
-    op_prec_like_and_paren: "'abc' LIKE ('abc' AND TRUE)" => Err(Error::Value("Can't and abc and TRUE".into())),
+    op_prec_like_and_paren: "'abc' LIKE ('abc' AND TRUE)" => Err(Error::Value("Can't and STRING and BOOLEAN in abc AND TRUE".into())),
 
     op_prec_and_or: "FALSE AND TRUE OR TRUE" => Ok(Boolean(true)),
     op_prec_and_or_paren: "FALSE AND (TRUE OR TRUE)" => Ok(Boolean(false)),
+
+    // Integers and floats compare exactly, even beyond 2^53 where integers round as floats.
+    numeric_eq_int_float_large: "9007199254740993 = 9007199254740992.0" => Ok(Boolean(false)),
+    numeric_eq_int_float_large_exact: "9007199254740992 = 9007199254740992.0" => Ok(Boolean(true)),
+    numeric_eq_float_int_large: "9007199254740992.0 = 9007199254740993" => Ok(Boolean(false)),
+    numeric_eq_int_float_max: "9223372036854775807 = 9223372036854775807.0" => Ok(Boolean(false)),
+    numeric_eq_int_float_min: "-9223372036854775807 - 1 = -9223372036854775808.0" => Ok(Boolean(true)),
+    numeric_eq_int_float_nan: "1 = NAN" => Ok(Boolean(false)),
+    numeric_neq_int_float_large: "9007199254740993 != 9007199254740992.0" => Ok(Boolean(true)),
+    numeric_gt_int_float_large: "9007199254740993 > 9007199254740992.0" => Ok(Boolean(true)),
+    numeric_gt_float_int_large: "9007199254740992.0 > 9007199254740993" => Ok(Boolean(false)),
+    numeric_gt_int_float_negative: "-2 > -2.5" => Ok(Boolean(true)),
+    numeric_gt_int_float_infinity: "-9223372036854775807 > -INFINITY" => Ok(Boolean(true)),
+    numeric_gt_int_float_nan: "1 > NAN" => Ok(Boolean(false)),
+    numeric_lt_int_float_large: "9007199254740992 < 9007199254740993.0" => Ok(Boolean(false)),
+    numeric_lt_float_int_large: "9007199254740992.0 < 9007199254740993" => Ok(Boolean(true)),
+    numeric_lt_int_float_max: "9223372036854775807 < 9223372036854775807.0" => Ok(Boolean(true)),
+    numeric_lt_int_float_negative: "-3 < -2.5" => Ok(Boolean(true)),
+    numeric_lt_int_float_infinity: "9223372036854775807 < INFINITY" => Ok(Boolean(true)),
+    numeric_lt_int_float_nan: "1 < NAN" => Ok(Boolean(false)),
+    numeric_lte_int_float_large: "9007199254740993 <= 9007199254740992.0" => Ok(Boolean(false)),
+    numeric_gte_float_int_large: "9007199254740992.0 >= 9007199254740993" => Ok(Boolean(false)),
+    numeric_between_int_float_large: "9007199254740993 BETWEEN 9007199254740992.0 AND 9007199254740994.0" => Ok(Boolean(true)),
+    numeric_in_int_float_large: "9007199254740993 IN (9007199254740992.0, 9007199254740994.0)" => Ok(Boolean(false)),
+    // Arithmetic converts integers to the nearest float, which rounds beyond 2^53.
+    numeric_add_int_float_large: "9007199254740993 + 0.0" => Ok(Float(9007199254740992.0)),
+    numeric_subtract_int_float_large: "9007199254740993 - 9007199254740992.0" => Ok(Float(0.0)),
+    // Strings are not implicitly converted to numbers, nor numbers to strings.
+    numeric_eq_int_string: "1 = '1'" => Err(Error::Value("Can't compare INTEGER and STRING in 1 = 1".into())),
+    numeric_eq_float_string: "1.5 = '1.5'" => Err(Error::Value("Can't compare FLOAT and STRING in 1.5 = 1.5".into())),
+    numeric_eq_decimal_string: "DECIMAL '1.5' = '1.5'" => Err(Error::Value("Can't compare DECIMAL(2,1) and STRING in 1.5 = 1.5".into())),
+    numeric_lt_string_int: "'1' < 2" => Err(Error::Value("Can't compare STRING and INTEGER in 1 < 2".into())),
+    numeric_add_int_string: "1 + '1'" => Err(Error::Value("Can't add INTEGER and STRING in 1 + 1".into())),
+    numeric_add_string_float: "'1' + 1.5" => Err(Error::Value("Can't add STRING and FLOAT in 1 + 1.5".into())),
+    numeric_multiply_string_decimal: "'2' * DECIMAL '1.5'" => Err(Error::Value("Can't multiply STRING and DECIMAL(2,1) in 2 * 1.5".into())),
+    numeric_cast_string_int: "CAST('1' AS INTEGER) = 1" => Ok(Boolean(true)),
+}
+
+/// Checks the numeric coercion matrix (see sql::types) for every pair of INTEGER, FLOAT, and
+/// DECIMAL operands: equal and unequal values compare as expected, arithmetic yields the
+/// matrix' result type, and strings are rejected.
+#[test]
+fn numeric_coercion() -> Result<()> {
+    // Each datatype with expressions for 2, 3, and 2.5 (except for INTEGER), and an example value
+    // of the datatype.
+    let values = [
+        ("2", "3", None, Integer(0)),
+        ("2.0", "3.0", Some("2.5"), Float(0.0)),
+        ("DECIMAL '2.0'", "DECIMAL '3'", Some("DECIMAL '2.5'"), dec("0")),
+    ];
+    // Arithmetic result datatypes, indexed by [lhs][rhs].
+    let results = [[0, 1, 2], [1, 1, 1], [2, 1, 2]];
+
+    let compare = |lhs: &str, op: &str, rhs: &str, expect: bool| -> Result<()> {
+        let expr = format!("{} {} {}", lhs, op, rhs);
+        assert_eq!(eval_expr(&expr)?, Boolean(expect), "{}", expr);
+        Ok(())
+    };
+    for (l, (two, three, half, _)) in values.iter().enumerate() {
+        for (r, (rtwo, rthree, rhalf, _)) in values.iter().enumerate() {
+            for (op, equal, less) in
+                &[("=", true, false), ("!=", false, true), ("<", false, true), ("<=", true, true)]
+            {
+                compare(two, op, rtwo, *equal)?;
+                compare(two, op, rthree, *less)?;
+                let greater = matches!(*op, "!=");
+                compare(three, op, rtwo, greater)?;
+                if let Some(rhalf) = rhalf {
+                    compare(two, op, rhalf, *less)?;
+                    compare(three, op, rhalf, greater)?;
+                }
+                if let Some(half) = half {
+                    compare(half, op, rtwo, greater)?;
+                    compare(half, op, rthree, *less)?;
+                }
+            }
+            for op in &["+", "-", "*", "/"] {
+                let expr = format!("{} {} {}", three, op, rtwo);
+                let result = eval_expr(&expr)?;
+                let expect = &values[results[l][r]].3;
+                assert_eq!(discriminant(&result), discriminant(expect), "{} = {}", expr, result);
+            }
+        }
+        for op in &["=", "<", "+", "-"] {
+            for expr in &[format!("{} {} '2'", two, op), format!("'2' {} {}", op, two)] {
+                assert!(matches!(eval_expr(expr), Err(Error::Value(_))), "{}", expr);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the three-valued logic truth tables of logical and comparison operators, for all
+/// combinations of TRUE, FALSE, and NULL.
+#[test]
+fn truth_table() -> Result<()> {
+    const T: Value = Boolean(true);
+    const F: Value = Boolean(false);
+    const N: Value = Null;
+    let values = ["TRUE", "FALSE", "NULL"];
+
+    for (op, expect) in &[("NOT", [F, T, N]), ("IS NULL", [F, F, T]), ("IS NOT NULL", [T, T, F])] {
+        for (value, expect) in values.iter().zip(expect.iter()) {
+            let expr = match *op {
+                "NOT" => format!("NOT {}", value),
+                op => format!("{} {}", value, op),
+            };
+            assert_eq!(eval_expr(&expr)?, *expect, "{}", expr);
+        }
+    }
+
+    // Each table is indexed by [lhs][rhs], in the order TRUE, FALSE, NULL.
+    for (op, table) in &[
+        ("AND", [[T, F, N], [F, F, F], [N, F, N]]),
+        ("OR", [[T, T, T], [T, F, N], [T, N, N]]),
+        ("=", [[T, F, N], [F, T, N], [N, N, N]]),
+        ("!=", [[F, T, N], [T, F, N], [N, N, N]]),
+        ("<", [[F, F, N], [T, F, N], [N, N, N]]),
+        ("<=", [[T, F, N], [T, T, N], [N, N, N]]),
+        (">", [[F, T, N], [F, F, N], [N, N, N]]),
+        (">=", [[T, T, N], [F, T, N], [N, N, N]]),
+        ("IS DISTINCT FROM", [[F, T, T], [T, F, T], [T, T, F]]),
+        ("IS NOT DISTINCT FROM", [[T, F, F], [F, T, F], [F, F, T]]),
+    ] {
+        for (lhs, row) in values.iter().zip(table.iter()) {
+            for (rhs, expect) in values.iter().zip(row.iter()) {
+                let expr = format!("{} {} {}", lhs, op, rhs);
+                assert_eq!(eval_expr(&expr)?, *expect, "{}", expr);
+                // Negating the result must follow the NOT truth table.
+                let expr = format!("NOT ({} {} {})", lhs, op, rhs);
+                let negated = match expect {
+                    Boolean(b) => Boolean(!b),
+                    _ => Null,
+                };
+                assert_eq!(eval_expr(&expr)?, negated, "{}", expr);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn in_large_list() -> Result<()> {
+    let list = (1..=1000).map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+    assert_eq!(eval_expr(&format!("1000 IN ({})", list))?, Boolean(true));
+    assert_eq!(eval_expr(&format!("1001 IN ({})", list))?, Boolean(false));
+    assert_eq!(eval_expr(&format!("1001 NOT IN ({}, NULL)", list))?, Null);
+    Ok(())
+}
+
+/// Checks CAST and TRY_CAST for every pair of source and target datatypes, including overflow.
+/// Failed casts error, in which case TRY_CAST yields NULL instead. Both yield NULL for NULL, and
+/// NULL result columns have the target datatype.
+#[test]
+fn cast_matrix() -> Result<()> {
+    use types::DataType;
+    let targets = [
+        ("BOOLEAN", DataType::Boolean),
+        ("INTEGER", DataType::Integer),
+        ("FLOAT", DataType::Float),
+        ("STRING", DataType::String),
+        ("DATE", DataType::Date),
+        ("TIME", DataType::Time),
+        ("TIMESTAMP", DataType::Timestamp),
+        ("DECIMAL(4,1)", DataType::Decimal(4, 1)),
+        ("BYTEA", DataType::Bytes),
+    ];
+    let s = |s: &str| Some(String(s.into()));
+    let date = || -> Result<Value> { Ok(Date("2020-01-02".parse()?)) };
+    let time = || -> Result<Value> { Ok(Time("12:34:56".parse()?)) };
+    let timestamp = || -> Result<Value> { Ok(Timestamp("2020-01-02 12:34:56".parse()?)) };
+    let midnight = || -> Result<Value> { Ok(Timestamp("2020-01-02 00:00:00".parse()?)) };
+
+    // Each source expression with the expected result for each target, in order, or None if the
+    // cast fails.
+    #[rustfmt::skip]
+    let casts: Vec<(&str, [Option<Value>; 9])> = vec![
+        ("NULL", [Some(Null), Some(Null), Some(Null), Some(Null), Some(Null), Some(Null), Some(Null), Some(Null), Some(Null)]),
+
+        ("TRUE", [Some(Boolean(true)), Some(Integer(1)), None, s("TRUE"), None, None, None, None, None]),
+        ("FALSE", [Some(Boolean(false)), Some(Integer(0)), None, s("FALSE"), None, None, None, None, None]),
+
+        ("42", [Some(Boolean(true)), Some(Integer(42)), Some(Float(42.0)), s("42"), None, None, None, Some(dec("42.0")), None]),
+        ("0", [Some(Boolean(false)), Some(Integer(0)), Some(Float(0.0)), s("0"), None, None, None, Some(dec("0.0")), None]),
+        ("9223372036854775807", [Some(Boolean(true)), Some(Integer(i64::MAX)), Some(Float(9.223_372_036_854_776e18)), s("9223372036854775807"), None, None, None, None, None]),
+
+        ("-2.7", [None, Some(Integer(-2)), Some(Float(-2.7)), s("-2.7"), None, None, None, Some(dec("-2.7")), None]),
+        ("2.99", [None, Some(Integer(2)), Some(Float(2.99)), s("2.99"), None, None, None, Some(dec("3.0")), None]),
+        ("1e19", [None, None, Some(Float(1e19)), s("10000000000000000000"), None, None, None, None, None]),
+        ("-9.2e18", [None, Some(Integer(-9_200_000_000_000_000_000)), Some(Float(-9.2e18)), s("-9200000000000000000"), None, None, None, None, None]),
+        ("INFINITY", [None, None, Some(Float(f64::INFINITY)), s("inf"), None, None, None, None, None]),
+
+        ("' 1 '", [Some(Boolean(true)), Some(Integer(1)), Some(Float(1.0)), s(" 1 "), None, None, None, Some(dec("1.0")), None]),
+        ("' -2.75 '", [None, None, Some(Float(-2.75)), s(" -2.75 "), None, None, None, Some(dec("-2.8")), None]),
+        ("'off'", [Some(Boolean(false)), None, None, s("off"), None, None, None, None, None]),
+        ("'9223372036854775808'", [None, None, Some(Float(9.223_372_036_854_776e18)), s("9223372036854775808"), None, None, None, None, None]),
+        ("'2020-01-02'", [None, None, None, s("2020-01-02"), Some(date()?), None, Some(midnight()?), None, None]),
+        ("'12:34:56'", [None, None, None, s("12:34:56"), None, Some(time()?), None, None, None]),
+        ("'2020-01-02 12:34:56'", [None, None, None, s("2020-01-02 12:34:56"), None, None, Some(timestamp()?), None, None]),
+        ("'x''00ff'''", [None, None, None, s("x'00ff'"), None, None, None, None, Some(Bytes(vec![0x00, 0xff]))]),
+        ("'x''0'''", [None, None, None, s("x'0'"), None, None, None, None, None]),
+        ("'abc'", [None, None, None, s("abc"), None, None, None, None, None]),
+
+        ("DATE '2020-01-02'", [None, None, None, s("2020-01-02"), Some(date()?), None, Some(midnight()?), None, None]),
+        ("TIME '12:34:56'", [None, None, None, s("12:34:56"), None, Some(time()?), None, None, None]),
+        ("TIMESTAMP '2020-01-02 12:34:56'", [None, None, None, s("2020-01-02 12:34:56"), Some(date()?), Some(time()?), Some(timestamp()?), None, None]),
+
+        ("DECIMAL '-2.75'", [None, Some(Integer(-2)), Some(Float(-2.75)), s("-2.75"), None, None, None, Some(dec("-2.8")), None]),
+        ("DECIMAL '999.99'", [None, Some(Integer(999)), Some(Float(999.99)), s("999.99"), None, None, None, None, None]),
+        ("DECIMAL '99999999999999999999'", [None, None, Some(Float(1e20)), s("99999999999999999999"), None, None, None, None, None]),
+
+        ("x'00ff'", [None, None, None, s("x'00ff'"), None, None, None, None, Some(Bytes(vec![0x00, 0xff]))]),
+    ];
+
+    let engine = super::setup(Vec::new())?;
+    let mut session = engine.session()?;
+    for (source, expects) in casts {
+        for ((target, datatype), expect) in targets.iter().zip(expects.iter()) {
+            let cast = format!("CAST({} AS {})", source, target);
+            let try_cast = format!("TRY_CAST({} AS {})", source, target);
+            match expect {
+                Some(value) => assert_eq!(eval_expr(&cast)?, *value, "{}", cast),
+                None => assert!(matches!(eval_expr(&cast), Err(Error::Value(_))), "{}", cast),
+            }
+            match session.execute(&format!("SELECT {}", try_cast))? {
+                ResultSet::Query { columns, rows } => {
+                    let expect = expect.clone().unwrap_or(Null);
+                    if expect == Null {
+                        assert_eq!(columns[0].datatype.as_ref(), Some(datatype), "{}", try_cast);
+                    }
+                    let rows = rows.collect::<Result<Vec<_>>>()?;
+                    assert_eq!(rows, vec![vec![expect]], "{}", try_cast);
+                }
+                result => panic!("Unexpected result {:?}", result),
+            }
+        }
+    }
+    Ok(())
 }
@@ -3,18 +3,54 @@ mod mutation;
 mod query;
 mod schema;
 
-use toydb::error::Result;
-use toydb::sql::engine::{Engine, KV};
+use toydb::error::{Error, Result};
+use toydb::raft::{self, State as _};
+use toydb::sql::engine::{Engine, Raft, KV, MAX_VALUE_SIZE};
 use toydb::storage::kv;
 
 /// Sets up a basic in-memory SQL engine with an initial dataset.
 fn setup(queries: Vec<&str>) -> Result<KV> {
-    let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())));
+    let engine = KV::new(kv::MVCC::new(Box::new(kv::Memory::new())))?;
+    load(&engine, queries)?;
+    Ok(engine)
+}
+
+/// Sets up a Raft SQL engine with an initial dataset, backed by a single in-memory state machine
+/// which applies requests directly, i.e. without a Raft cluster. Used to check that the Raft
+/// engine gives the same results as the local engine.
+fn setup_raft(queries: Vec<&str>) -> Result<Raft> {
+    let mut state = Raft::new_state(kv::MVCC::new(Box::new(kv::Memory::new())), MAX_VALUE_SIZE)?;
+    let (request_tx, mut request_rx) =
+        tokio::sync::mpsc::unbounded_channel::<raft::ClientRequest>();
+    std::thread::spawn(move || {
+        let mut index = 0;
+        while let Some((request, _, response_tx)) = futures::executor::block_on(request_rx.recv()) {
+            let response = match request {
+                raft::Request::Mutate(command) => {
+                    index += 1;
+                    state.mutate(index, command).map(raft::Response::State)
+                }
+                raft::Request::Query(command) => state.query(command).map(raft::Response::State),
+                request => Err(Error::Internal(format!("Unexpected request {:?}", request))),
+            };
+            response_tx.send(response).ok();
+        }
+    });
+    let engine = Raft::new(raft::Client::new(request_tx));
+    load(&engine, queries)?;
+    Ok(engine)
+}
+
+/// Loads an initial dataset into an engine, in a single transaction.
+fn load<E: Engine + 'static>(engine: &E, queries: Vec<&str>) -> Result<()>
+where
+    E::Transaction: Send,
+{
     let mut session = engine.session()?;
     session.execute("BEGIN")?;
     for query in queries {
         session.execute(query)?;
     }
     session.execute("COMMIT")?;
-    Ok(engine)
+    Ok(())
 }
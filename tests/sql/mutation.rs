@@ -1,6 +1,6 @@
 ///! Mutation tests, using an in-memory database against golden files in tests/sql/mutation/
 use toydb::error::Result;
-use toydb::sql::engine::{Engine as _, Mode, Transaction as _};
+use toydb::sql::engine::{Engine, Mode, Transaction as _};
 use toydb::sql::schema::Catalog as _;
 
 use goldenfile::Mint;
@@ -17,44 +17,59 @@ macro_rules! test_mutation {
             #[test]
             fn $name() -> Result<()> {
                 let setup: &[&str] = &$setup;
-                let engine = super::setup(setup.into())?;
-                let mut mint = Mint::new("tests/sql/mutation");
-                let mut f = mint.new_goldenfile(stringify!($name))?;
-
-                write!(f, "Query: {}\n", $query.trim())?;
-                match engine.session()?.execute($query) {
-                    Ok(resultset) => {
-                        write!(f, "Result: {:?}\n\n", resultset)?;
-                    },
-                    Err(err) => write!(f, "Error: {:?}\n\n", err)?,
-                };
-
-                write!(f, "Storage:")?;
-                let txn = engine.begin(Mode::ReadWrite)?;
-                for table in txn.scan_tables()? {
-                    write!(f, "\n{}\n", table)?;
-                    for row in txn.scan(&table.name, None)? {
-                        write!(f, "{:?}\n", row?)?;
-                    }
-
-                    for column in table.columns.iter().filter(|c| c.index) {
-                        write!(f, "\nIndex {}.{}\n", table.name, column.name)?;
-                        let mut scan = txn.scan_index(&table.name, &column.name)?;
-                        while let Some((value, pks)) = scan.next().transpose()? {
-                            let mut pks = pks.into_iter().collect::<Vec<_>>();
-                            pks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-                            write!(f, "{:?} => {:?}\n", value, pks)?;
-                        }
-                    }
-                }
-                txn.rollback()?;
+                let mut output = Vec::new();
+                run(super::setup(setup.into())?, $query, &mut output)?;
+                let mut raft_output = Vec::new();
+                run(super::setup_raft(setup.into())?, $query, &mut raft_output)?;
+                assert_eq!(
+                    String::from_utf8_lossy(&raft_output),
+                    String::from_utf8_lossy(&output),
+                    "Raft engine results differ from local engine"
+                );
 
+                let mut mint = Mint::new("tests/sql/mutation");
+                mint.new_goldenfile(stringify!($name))?.write_all(&output)?;
                 Ok(())
             }
         )*
     }
 }
 
+/// Runs a mutation against the engine, writing its result and the resulting storage contents to f.
+fn run<E: Engine + 'static>(engine: E, query: &str, f: &mut Vec<u8>) -> Result<()>
+where
+    E::Transaction: Send + 'static,
+{
+    write!(f, "Query: {}\n", query.trim())?;
+    match engine.session()?.execute(query) {
+        Ok(resultset) => {
+            write!(f, "Result: {:?}\n\n", resultset)?;
+        }
+        Err(err) => write!(f, "Error: {:?}\n\n", err)?,
+    };
+
+    write!(f, "Storage:")?;
+    let txn = engine.begin(Mode::ReadWrite)?;
+    for table in txn.scan_tables()? {
+        write!(f, "\n{}\n", table)?;
+        for row in txn.scan(&table.name, None)? {
+            write!(f, "{:?}\n", row?)?;
+        }
+
+        for column in table.columns.iter().filter(|c| c.index) {
+            write!(f, "\nIndex {}.{}\n", table.name, column.name)?;
+            let mut scan = txn.scan_index(&table.name, &column.name)?;
+            while let Some((value, pks)) = scan.next().transpose()? {
+                let mut pks = pks.into_iter().collect::<Vec<_>>();
+                pks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                write!(f, "{:?} => {:?}\n", value, pks)?;
+            }
+        }
+    }
+    txn.rollback()?;
+    Ok(())
+}
+
 test_mutation! { with [
         "CREATE TABLE test (
             id INTEGER PRIMARY KEY DEFAULT 0,
@@ -76,6 +91,14 @@ test_mutation! { with [
     delete_where_float: "DELETE FROM test WHERE 3.14",
     delete_where_integer: "DELETE FROM test WHERE 1",
     delete_where_string: "DELETE FROM test WHERE 'a'",
+    delete_where_in: "DELETE FROM test WHERE name IN ('a', 'c')",
+    delete_where_in_subquery: "DELETE FROM test WHERE id IN (SELECT id FROM other WHERE id > 1)",
+    delete_where_in_subquery_not: "DELETE FROM test WHERE id NOT IN (SELECT id FROM other WHERE id > 1)",
+    delete_where_in_subquery_self: "DELETE FROM test WHERE id - 1 IN (SELECT id FROM test)",
+    delete_where_exists_correlated: "DELETE FROM test WHERE EXISTS (SELECT * FROM other WHERE other.id = test.id + 1)",
+    delete_where_exists_self: "DELETE FROM test WHERE EXISTS (SELECT * FROM test t WHERE t.id = test.id - 1)",
+    delete_where_exists_self_range: "DELETE FROM test WHERE EXISTS (SELECT * FROM test t WHERE t.id < test.id AND t.id + 1 >= test.id)",
+    delete_where_subquery_scalar_self: "DELETE FROM test WHERE value >= (SELECT AVG(value) FROM test)",
     delete_case: "DELETE FROM TeSt WHERE ID = 1",
     delete_missing_column_where: "DELETE FROM test WHERE missing = TRUE",
     delete_missing_table: "DELETE FROM missing",
@@ -83,6 +106,21 @@ test_mutation! { with [
     delete_bare: "DELETE",
     delete_bare_from: "DELETE FROM",
     delete_bare_where: "DELETE FROM test WHERE",
+    delete_returning: "DELETE FROM test WHERE id > 1 RETURNING id, name AS label, value * 2",
+    delete_returning_all: "DELETE FROM test WHERE id = 1 RETURNING *",
+    delete_returning_none: "DELETE FROM test WHERE FALSE RETURNING id",
+    delete_returning_type: "DELETE FROM test WHERE id > 1 RETURNING id * 'a'",
+    truncate: "TRUNCATE test",
+    truncate_table: "TRUNCATE TABLE test",
+    truncate_missing: "TRUNCATE missing",
+    truncate_bare: "TRUNCATE",
+    copy_from_missing: "COPY missing FROM '/nonexistent.csv'",
+    copy_from_file_missing: "COPY test FROM '/nonexistent/file.csv'",
+    copy_to_missing: "COPY missing TO '/nonexistent.csv'",
+    copy_bare: "COPY test",
+    copy_option_unknown: "COPY test FROM 'file.csv' WITH (QUOTE '\"')",
+    copy_delimiter_invalid: "COPY test FROM 'file.csv' WITH (DELIMITER '\"')",
+    copy_delimiter_long: "COPY test FROM 'file.csv' WITH (DELIMITER ';;')",
 }
 
 test_mutation! { with [
@@ -116,6 +154,51 @@ test_mutation! { with [
     insert_bare: "INSERT INTO test",
     insert_bare_no_table: "INSERT INTO",
     insert_bare_values: "INSERT INTO test VALUES",
+    insert_returning: "INSERT INTO test VALUES (1, 'a', 101), (2, 'b') RETURNING id, name, value * 2 AS doubled",
+    insert_returning_all: "INSERT INTO test (name) VALUES ('a') RETURNING *",
+    insert_returning_aggregate: "INSERT INTO test VALUES (1, 'a', 101) RETURNING COUNT(id)",
+    insert_returning_missing: "INSERT INTO test VALUES (1, 'a', 101) RETURNING missing",
+    insert_returning_bare: "INSERT INTO test VALUES (1, 'a', 101) RETURNING",
+}
+
+test_mutation! { with [
+        "CREATE TABLE test (
+            id INTEGER PRIMARY KEY DEFAULT 0,
+            name STRING INDEX,
+            value INTEGER
+        )",
+        "INSERT INTO test VALUES (1, 'a', 101), (2, 'b', 102), (3, 'c', 103)",
+        "CREATE TABLE other (id INTEGER PRIMARY KEY)",
+        "INSERT INTO other VALUES (1), (2), (3)",
+    ];
+
+    insert_select: "INSERT INTO test SELECT id + 10, name, value * 2 FROM test",
+    insert_select_columns: "INSERT INTO test (value, id) SELECT id, id + 10 FROM other",
+    insert_select_partial: "INSERT INTO test SELECT id + 10 FROM other",
+    insert_select_mismatch: "INSERT INTO test (id) SELECT id, name FROM test",
+    insert_select_empty: "INSERT INTO test SELECT * FROM test WHERE FALSE",
+    insert_select_with: "WITH t AS (SELECT id + 10 AS id FROM other WHERE id > 1) INSERT INTO test (id) SELECT id FROM t",
+    insert_select_with_inner: "INSERT INTO test (id, name) WITH t (x) AS (SELECT id * 100 FROM other) SELECT x, 'cte' FROM t",
+    insert_select_with_shadow: "WITH test AS (SELECT id + 10 AS id FROM test) INSERT INTO test (id) SELECT id FROM test",
+    insert_select_with_values: "WITH t AS (SELECT 1) INSERT INTO test VALUES (9, 'x', 9)",
+    insert_conflict_nothing: "INSERT INTO test VALUES (1, 'x', 1), (4, 'd', 104) ON CONFLICT DO NOTHING",
+    insert_conflict_nothing_target: "INSERT INTO test VALUES (1, 'x', 1), (4, 'd', 104) ON CONFLICT (id) DO NOTHING",
+    insert_conflict_update: "INSERT INTO test VALUES (1, 'x', 1), (4, 'd', 104) ON CONFLICT (id) DO UPDATE SET name = excluded.name, value = test.value + excluded.value",
+    insert_conflict_update_key: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT DO UPDATE SET id = excluded.id + 10",
+    insert_conflict_update_repeat: "INSERT INTO test VALUES (4, 'd', 1), (4, 'e', 2) ON CONFLICT DO UPDATE SET value = test.value + excluded.value",
+    insert_conflict_update_ambiguous: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT DO UPDATE SET value = value + 1",
+    insert_conflict_update_missing: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT DO UPDATE SET missing = 1",
+    insert_conflict_select: "INSERT INTO test SELECT id + 2, 'x', id FROM other ON CONFLICT DO UPDATE SET name = excluded.name",
+    insert_conflict_select_self: "INSERT INTO test SELECT * FROM test ON CONFLICT DO UPDATE SET value = excluded.value * 2",
+    insert_conflict_target_invalid: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT (name) DO NOTHING",
+    insert_conflict_target_missing: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT (missing) DO NOTHING",
+    insert_conflict_bare: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT (id)",
+    insert_conflict_bare_update: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT DO UPDATE",
+    insert_conflict_returning: "INSERT INTO test VALUES (1, 'x', 1), (4, 'd', 104), (2, 'y', 2) ON CONFLICT (id) DO UPDATE SET value = test.value + excluded.value RETURNING id, name, value",
+    insert_conflict_returning_nothing: "INSERT INTO test VALUES (1, 'x', 1) ON CONFLICT DO NOTHING RETURNING id",
+    insert_select_returning: "INSERT INTO test SELECT id + 10, 'x', id FROM other WHERE id > 1 RETURNING id, value",
+    delete_with: "WITH t AS (SELECT id FROM other WHERE id > 1) DELETE FROM test WHERE id IN (SELECT id FROM t)",
+    update_with: "WITH t AS (SELECT MAX(id) AS id FROM other) UPDATE test SET value = 0 WHERE id = (SELECT id FROM t)",
 }
 
 test_mutation! { with [
@@ -139,6 +222,7 @@ test_mutation! { with [
     update_where_float: "UPDATE test SET name = 'x' WHERE 3.14",
     update_where_integer: "UPDATE test SET name = 'x' WHERE 1",
     update_where_string: "UPDATE test SET name = 'x' WHERE 'a'",
+    update_where_exists_correlated: "UPDATE test SET name = 'x' WHERE NOT EXISTS (SELECT * FROM other WHERE other.id = test.id + 1)",
     update_where_full: "UPDATE test SET id = 9, name = 'x', value = 999 WHERE id = 1",
     update_case: "UPDATE TeSt SET Name = 'x' WHERE ID = 1",
     update_missing_column_set: "UPDATE test SET missing = 0",
@@ -149,4 +233,140 @@ test_mutation! { with [
     update_bare_set: "UPDATE test SET",
     update_bare_where: "UPDATE test SET name = 'x' WHERE",
     update_bare_no_table: "UPDATE",
+    update_returning: "UPDATE test SET value = value + 1 WHERE id > 1 RETURNING id, value",
+    update_returning_all: "UPDATE test SET id = id * 10, name = 'x' WHERE id = 1 RETURNING *",
+    update_returning_none: "UPDATE test SET value = 0 WHERE FALSE RETURNING *",
+}
+
+test_mutation! { with [
+        "CREATE TABLE events (
+            at TIMESTAMP PRIMARY KEY,
+            day DATE INDEX,
+            starts TIME INDEX
+        )",
+    ];
+
+    insert_temporal: "INSERT INTO events VALUES
+        (TIMESTAMP '2024-01-31 12:00:00', DATE '2024-01-31', TIME '12:00'),
+        (TIMESTAMP '1969-12-31 23:59:59.5', DATE '1969-12-31', TIME '23:59:59.5'),
+        (TIMESTAMP '2024-01-31T08:15:00Z', DATE '2024-01-31', TIME '08:15'),
+        (TIMESTAMP '2000-02-29', DATE '2000-02-29', TIME '00:00')",
+    insert_temporal_invalid: "INSERT INTO events VALUES (TIMESTAMP '2023-02-29 12:00:00', NULL, NULL)",
+    insert_temporal_string: "INSERT INTO events VALUES ('2024-01-31 12:00:00', NULL, NULL)",
+}
+
+test_mutation! { with [
+        "CREATE TABLE prices (
+            id DECIMAL(6, 2) PRIMARY KEY,
+            price DECIMAL(6, 2) INDEX,
+            total DECIMAL DEFAULT 0
+        )",
+        "INSERT INTO prices VALUES (1, DECIMAL '9.99', 10), (2.5, DECIMAL '0.5', NULL)",
+    ];
+
+    insert_decimal: "INSERT INTO prices VALUES (DECIMAL '3.00', DECIMAL '1234.5', DECIMAL '12345678901234567890')",
+    insert_decimal_round: "INSERT INTO prices VALUES (DECIMAL '3.005', DECIMAL '-0.125', DECIMAL '0.5')",
+    insert_decimal_float: "INSERT INTO prices VALUES (3, 19.99, 1.5)",
+    insert_decimal_range: "INSERT INTO prices VALUES (3, DECIMAL '10000', 0)",
+    insert_decimal_range_round: "INSERT INTO prices VALUES (3, DECIMAL '9999.995', 0)",
+    insert_decimal_pk_duplicate: "INSERT INTO prices VALUES (DECIMAL '2.500', 1, 0)",
+    insert_decimal_nan: "INSERT INTO prices VALUES (3, NAN, 0)",
+    insert_decimal_string: "INSERT INTO prices VALUES (3, '1.5', 0)",
+    insert_decimal_select: "INSERT INTO prices (id, price) SELECT id + 10, 1.005 FROM prices",
+    insert_decimal_returning: "INSERT INTO prices VALUES (3, 1.005, 0) RETURNING price, total",
+    insert_decimal_conflict: "INSERT INTO prices VALUES (DECIMAL '2.500', 7, 0) ON CONFLICT DO UPDATE SET price = excluded.price",
+    update_decimal: "UPDATE prices SET price = price * DECIMAL '1.125' WHERE id = 1",
+    update_decimal_divide: "UPDATE prices SET price = price / 3",
+    update_decimal_range: "UPDATE prices SET price = price * 10000",
+    update_decimal_pk: "UPDATE prices SET id = id + DECIMAL '0.001' WHERE id = 2.5",
+}
+
+test_mutation! { with [
+        "CREATE TABLE blobs (id BYTEA PRIMARY KEY, data BLOB INDEX)",
+        "INSERT INTO blobs VALUES (x'', x'00'), (x'00', x''), (x'00ff', x'ff00'), (x'ff', x'00')",
+    ];
+
+    insert_bytea_pk: "INSERT INTO blobs VALUES (x'0000', x'00'), (x'ffff', NULL)",
+    insert_bytea_pk_duplicate: "INSERT INTO blobs VALUES (x'00FF', x'')",
+    insert_bytea_pk_null: "INSERT INTO blobs VALUES (NULL, x'')",
+    update_bytea: "UPDATE blobs SET data = x'ffff' WHERE data = x'00'",
+    update_bytea_pk: "UPDATE blobs SET id = x'0001' WHERE id = x'00'",
+    delete_bytea: "DELETE FROM blobs WHERE id = x''",
+}
+
+test_mutation! { with [
+        "CREATE TABLE stock (
+            warehouse INTEGER,
+            item STRING,
+            quantity INTEGER NOT NULL,
+            PRIMARY KEY (warehouse, item)
+        )",
+        "INSERT INTO stock VALUES (2, 'b', 1), (1, 'b', 2), (1, 'a', 3), (2, 'a', 4), (-1, 'c', 5)",
+    ];
+
+    insert_pk_composite: "INSERT INTO stock VALUES (1, 'c', 6), (3, 'a', 7), (0, 'z', 8)",
+    insert_pk_composite_duplicate: "INSERT INTO stock VALUES (1, 'a', 6)",
+    insert_pk_composite_null: "INSERT INTO stock VALUES (1, NULL, 6)",
+    insert_pk_composite_conflict: "INSERT INTO stock VALUES (1, 'a', 10), (3, 'a', 1) ON CONFLICT (item, warehouse) DO UPDATE SET quantity = stock.quantity + excluded.quantity",
+    insert_pk_composite_conflict_partial: "INSERT INTO stock VALUES (1, 'a', 10) ON CONFLICT (warehouse) DO NOTHING",
+    update_pk_composite: "UPDATE stock SET item = 'c' WHERE warehouse = 2 AND item = 'a'",
+    update_pk_composite_duplicate: "UPDATE stock SET item = 'b' WHERE warehouse = 2 AND item = 'a'",
+    delete_pk_composite: "DELETE FROM stock WHERE warehouse = 1 AND item = 'b'",
+}
+
+test_mutation! { with [
+        "CREATE TABLE users (
+            id INTEGER PRIMARY KEY,
+            email STRING UNIQUE,
+            visits INTEGER DEFAULT 0
+        )",
+        "INSERT INTO users VALUES (1, 'a@example.com', 1), (2, 'b@example.com', 2), (3, NULL, 3)",
+    ];
+
+    insert_unique_conflict: "INSERT INTO users (id, email) VALUES (9, 'a@example.com'), (4, 'd@example.com') ON CONFLICT (email) DO UPDATE SET visits = users.visits + 1",
+    insert_unique_conflict_null: "INSERT INTO users VALUES (4, NULL, 0) ON CONFLICT (email) DO NOTHING",
+    insert_unique_conflict_key: "INSERT INTO users VALUES (1, 'c@example.com', 0) ON CONFLICT (email) DO NOTHING",
+    insert_unique_conflict_other: "INSERT INTO users VALUES (4, 'a@example.com', 0) ON CONFLICT (id) DO NOTHING",
+}
+
+test_mutation! { with [
+        "CREATE TABLE accounts (id INTEGER PRIMARY KEY, name STRING INDEX, balance INTEGER)",
+        "CREATE TABLE payments (id INTEGER PRIMARY KEY, account_id INTEGER, amount INTEGER)",
+        "INSERT INTO accounts VALUES (1, 'a', 10), (2, 'b', 20), (3, 'c', 30), (4, 'd', 40)",
+        "INSERT INTO payments VALUES (1, 1, 5), (2, 2, 7), (3, 2, 1), (4, 9, 100)",
+    ];
+
+    update_from: "UPDATE accounts SET balance = balance + amount FROM payments WHERE accounts.id = payments.account_id AND payments.id != 3",
+    update_from_alias: "UPDATE accounts SET name = 'x', balance = p.amount FROM payments p WHERE p.account_id = accounts.id AND p.id = 1",
+    update_from_multiple: "UPDATE accounts SET balance = balance + amount FROM payments WHERE accounts.id = payments.account_id",
+    update_from_none: "UPDATE accounts SET balance = 0 FROM payments WHERE payments.amount > 1000",
+    update_from_pk: "UPDATE accounts SET id = payments.id + 10, name = 'x' FROM payments WHERE accounts.id = payments.account_id AND payments.id < 3",
+    update_from_self: "UPDATE accounts SET balance = 0 FROM accounts",
+    update_from_set_qualified: "UPDATE accounts SET payments.amount = 0 FROM payments",
+    delete_using: "DELETE FROM accounts USING payments WHERE accounts.id = payments.account_id",
+    delete_using_join: "DELETE FROM accounts USING payments p JOIN accounts a ON a.id = p.account_id WHERE accounts.id = a.id + 1",
+    delete_using_none: "DELETE FROM accounts USING payments WHERE payments.amount > 1000",
+    delete_using_returning: "DELETE FROM accounts USING payments WHERE accounts.id = payments.account_id RETURNING accounts.id",
+    update_from_returning: "UPDATE accounts SET balance = balance + amount FROM payments WHERE accounts.id = payments.account_id AND payments.id != 3 RETURNING *",
+}
+
+test_mutation! { with [
+        "CREATE TABLE parent (id INTEGER PRIMARY KEY, parent_id INTEGER INDEX REFERENCES parent)",
+        "CREATE TABLE child (id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES parent)",
+        "INSERT INTO parent VALUES (1, NULL), (2, 1), (3, 1)",
+        "INSERT INTO child VALUES (1, NULL), (2, NULL)",
+    ];
+
+    truncate_ref_null: "TRUNCATE parent",
+    truncate_ref_child: "TRUNCATE child",
+}
+
+test_mutation! { with [
+        "CREATE TABLE parent (id INTEGER PRIMARY KEY)",
+        "CREATE TABLE child (id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES parent)",
+        "INSERT INTO parent VALUES (1), (2)",
+        "INSERT INTO child VALUES (1, NULL), (2, 2)",
+    ];
+
+    truncate_ref: "TRUNCATE parent",
 }
@@ -1,11 +1,12 @@
-///! Tests for the SQL query engine. Runs SQL queries against an in-memory database,
-///! and compares the results with golden files stored under tests/sql/query/
+///! Tests for the SQL query engine. Runs SQL queries against an in-memory database, using both
+///! the local and Raft engines, and compares the results with golden files stored under
+///! tests/sql/query/
 use toydb::error::{Error, Result};
 use toydb::sql::engine::{Engine, Mode, Transaction};
 use toydb::sql::execution::ResultSet;
 use toydb::sql::parser::Parser;
 use toydb::sql::plan::Plan;
-use toydb::sql::types::Row;
+use toydb::sql::types::{Row, Rows};
 
 use goldenfile::Mint;
 use std::io::Write;
@@ -69,89 +70,111 @@ macro_rules! test_query {
                     (9, 'Birdman', 4, 3, 2014, 7.7, TRUE),
                     (10, 'Inception', 4, 1, 2010, 8.8, TRUE)",
             ]);
-            let engine = super::setup(setup)?;
+            let mut output = Vec::new();
+            run(super::setup(setup.clone())?, $query, &mut output)?;
+            let mut raft_output = Vec::new();
+            run(super::setup_raft(setup)?, $query, &mut raft_output)?;
+            assert_eq!(
+                String::from_utf8_lossy(&raft_output),
+                String::from_utf8_lossy(&output),
+                "Raft engine results differ from local engine"
+            );
 
             let mut mint = Mint::new("tests/sql/query");
-            let mut f = mint.new_goldenfile(format!("{}", stringify!($name)))?;
-
-            write!(f, "Query: {}\n\n", $query)?;
+            mint.new_goldenfile(stringify!($name))?.write_all(&output)?;
+            Ok(())
+        }
+    )*
+    }
+}
 
-            let mut txn = engine.begin(Mode::ReadWrite)?;
+/// Runs a query against the engine, writing its plan, results, and parse and plan trees to f.
+fn run<E: Engine + 'static>(engine: E, query: &str, f: &mut Vec<u8>) -> Result<()>
+where
+    E::Transaction: Send + 'static,
+{
+    write!(f, "Query: {}\n\n", query)?;
 
-            // First, just try to generate a plan and execute it
-            let result = Parser::new($query).parse()
-                .and_then(|ast| Plan::build(ast, &mut txn))
-                .and_then(|plan| plan.optimize(&mut txn))
-                .and_then(|plan| {
-                    write!(f, "Explain:\n{}\n\n", plan)?;
-                    plan.execute(&mut txn)
-                });
+    let mut txn = engine.begin(Mode::ReadWrite)?;
 
-            match result {
-                Ok(ResultSet::Query{columns, rows}) => {
-                    let rows: Vec<Row> = match rows.collect() {
-                        Ok(rows) => rows,
-                        Err(err) => {
-                            write!(f, " {:?}", err)?;
-                            return Ok(())
-                        }
-                    };
-                    write!(f, "Result:")?;
-                    if !columns.is_empty() || !rows.is_empty() {
-                        write!(f, " {:?}\n", columns
-                            .into_iter()
-                            .map(|c| c.name.unwrap_or_else(|| "?".to_string()))
-                            .collect::<Vec<_>>())?;
-                        for row in rows {
-                            write!(f, "{:?}\n", row)?;
-                        }
-                    } else {
-                        write!(f, " <none>\n")?;
-                    }
-                }
-                Ok(r) => return Err(Error::Internal(format!("Unexpected result {:?}\n", r))),
-                Err(err) => {
-                    write!(f, "Error: {}\n", err)?;
-                }
+    // First, just try to generate a plan and execute it. Rows are collected here, since engines
+    // may report evaluation errors either when executing the plan or when streaming its rows.
+    let result = Parser::new(query)
+        .parse()
+        .and_then(|ast| Plan::build(ast, &mut txn))
+        .and_then(|plan| plan.optimize(&mut txn))
+        .and_then(|plan| {
+            write!(f, "Explain:\n{}\n\n", plan)?;
+            plan.execute(&mut txn)
+        })
+        .and_then(|result| match result {
+            ResultSet::Query { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<Row>>>()?;
+                Ok(ResultSet::Query { columns, rows: Rows::new(rows.into_iter().map(Ok)) })
             }
-            write!(f, "\n")?;
+            r => Ok(r),
+        });
 
-            // Then output some parse and plan trees, for debugging.
-            write!(f, "AST: ")?;
-            let ast = match Parser::new($query).parse() {
-                Ok(ast) => ast,
-                Err(err) => {
-                    write!(f, "{:?}", err)?;
-                    return Ok(())
+    match result {
+        Ok(ResultSet::Query { columns, rows }) => {
+            let rows: Vec<Row> = rows.collect::<Result<_>>()?;
+            write!(f, "Result:")?;
+            if !columns.is_empty() || !rows.is_empty() {
+                write!(
+                    f,
+                    " {:?}\n",
+                    columns
+                        .into_iter()
+                        .map(|c| c.name.unwrap_or_else(|| "?".to_string()))
+                        .collect::<Vec<_>>()
+                )?;
+                for row in rows {
+                    write!(f, "{:?}\n", row)?;
                 }
-            };
-            write!(f, "{:#?}\n\n", ast)?;
+            } else {
+                write!(f, " <none>\n")?;
+            }
+        }
+        Ok(r) => return Err(Error::Internal(format!("Unexpected result {:?}\n", r))),
+        Err(err) => {
+            write!(f, "Error: {}\n", err)?;
+        }
+    }
+    write!(f, "\n")?;
 
-            write!(f, "Plan: ")?;
-            let plan = match Plan::build(ast, &mut txn) {
-                Ok(plan) => plan,
-                Err(err) => {
-                    write!(f, "{:?}", err)?;
-                    return Ok(())
-                }
-            };
-            write!(f, "{:#?}\n\n", plan)?;
+    // Then output some parse and plan trees, for debugging.
+    write!(f, "AST: ")?;
+    let ast = match Parser::new(query).parse() {
+        Ok(ast) => ast,
+        Err(err) => {
+            write!(f, "{:?}", err)?;
+            return Ok(());
+        }
+    };
+    write!(f, "{:#?}\n\n", ast)?;
 
-            write!(f, "Optimized plan: ")?;
-            let plan = match plan.optimize(&mut txn) {
-                Ok(plan) => plan,
-                Err(err) => {
-                    write!(f, "{:?}", err)?;
-                    return Ok(())
-                }
-            };
-            write!(f, "{:#?}\n\n", plan)?;
+    write!(f, "Plan: ")?;
+    let plan = match Plan::build(ast, &mut txn) {
+        Ok(plan) => plan,
+        Err(err) => {
+            write!(f, "{:?}", err)?;
+            return Ok(());
+        }
+    };
+    write!(f, "{:#?}\n\n", plan)?;
 
-            txn.commit()?;
-            Ok(())
+    write!(f, "Optimized plan: ")?;
+    let plan = match plan.optimize(&mut txn) {
+        Ok(plan) => plan,
+        Err(err) => {
+            write!(f, "{:?}", err)?;
+            return Ok(());
         }
-    )*
-    }
+    };
+    write!(f, "{:#?}\n\n", plan)?;
+
+    txn.commit()?;
+    Ok(())
 }
 
 test_query! {
@@ -174,7 +197,40 @@ test_query! {
 
     expr_dynamic: "SELECT 2020 - year AS age FROM movies",
     expr_static: "SELECT 1 + 2 * 3, 'abc' LIKE 'x%' AS nope",
+    nofrom_arithmetic: "SELECT 1 + 1",
+    nofrom_function: "SELECT UPPER('hi')",
+    nofrom_function_const: "SELECT ABS(-1), UPPER('a'), ROUND(2.5) AS r, 1 + ABS(-2)",
+    nofrom_string: "SELECT 'hello' AS greeting",
+    nofrom_field: "SELECT id",
+    nofrom_count_star: "SELECT COUNT(*)",
+    nofrom_aggregate_field: "SELECT COUNT(id)",
+    nofrom_where: "SELECT 1 WHERE FALSE",
+    nofrom_subquery: "SELECT (SELECT MAX(id) FROM movies) + 1",
     expr_mixed: "SELECT 1 + 2 * 3, 2020 - released AS age FROM movies",
+    expr_null_logic: "SELECT id, ultrahd AND NULL, ultrahd OR NULL, NOT ultrahd FROM movies",
+    expr_case: "SELECT id, CASE WHEN rating >= 8 THEN 'great' WHEN rating >= 7 THEN 'good' ELSE 'meh' END AS verdict FROM movies ORDER BY id",
+    expr_case_simple: "SELECT id, CASE genre_id WHEN 1 THEN 'Science Fiction' WHEN 2 THEN 'Action' END FROM movies ORDER BY id",
+    expr_case_where: "SELECT id, title FROM movies WHERE CASE WHEN ultrahd THEN rating > 8 ELSE released < 2000 END ORDER BY id",
+    expr_case_order: "SELECT id, genre_id FROM movies ORDER BY CASE genre_id WHEN 2 THEN 0 ELSE genre_id END, id DESC",
+    expr_case_aggregate: "SELECT genre_id, SUM(CASE WHEN ultrahd THEN 1 ELSE 0 END) AS uhd, COUNT(*) FROM movies GROUP BY genre_id ORDER BY genre_id",
+    expr_case_aggregate_result: "SELECT genre_id, CASE WHEN COUNT(*) > 3 THEN 'many' ELSE 'few' END FROM movies GROUP BY genre_id ORDER BY genre_id",
+    expr_cast: "SELECT id, CAST(released AS STRING) AS year FROM movies WHERE CAST(rating AS INTEGER) = 8 ORDER BY id",
+    expr_cast_error: "SELECT CAST(title AS INTEGER) FROM movies",
+    expr_try_cast: "SELECT id, TRY_CAST(title AS INTEGER) AS title FROM movies WHERE id <= 3",
+    expr_case_lazy: "SELECT id, CASE WHEN id > 100 THEN 1 / 0 ELSE id END FROM movies ORDER BY id",
+    expr_case_fold: "SELECT id, CASE WHEN 1 = 2 THEN 'no' WHEN 2 > 1 THEN title ELSE 'else' END FROM movies ORDER BY id",
+    expr_case_fold_prune: "SELECT id, CASE WHEN 1 = 2 THEN 'x' WHEN rating > 8 THEN 'great' WHEN TRUE THEN 'ok' ELSE 'never' END FROM movies ORDER BY id",
+    expr_case_fold_constant: "SELECT id, title FROM movies WHERE id = CASE 2 WHEN 1 THEN 10 WHEN 2 THEN 3 END",
+    expr_case_fold_none: "SELECT id, CASE WHEN FALSE THEN title END FROM movies ORDER BY id",
+    expr_case_fold_simple: "SELECT id, CASE 1 WHEN 2 THEN 'no' WHEN genre_id THEN 'maybe' END FROM movies ORDER BY id",
+    expr_case_types: "SELECT CASE WHEN ultrahd THEN 'yes' ELSE 0 END FROM movies",
+    expr_func_string: "SELECT id, UPPER(title), SUBSTRING(title FROM 1 FOR 4), POSITION('e' IN title) FROM movies ORDER BY id",
+    expr_func_where: "SELECT id, title FROM movies WHERE LOWER(title) LIKE '%the%' AND LENGTH(title) > 8 ORDER BY id",
+    expr_func_unknown: "SELECT UNKNOWN(title) FROM movies",
+    expr_func_math: "SELECT id, rating, ROUND(rating), FLOOR(rating), SIGN(rating - 8), MOD(id, 3) FROM movies ORDER BY id",
+    expr_func_const: "SELECT id, ABS(-1), LOWER('A'), MOD(7, 3) FROM movies WHERE id < 3 ORDER BY id",
+    expr_func_fold: "SELECT id, rating FROM movies WHERE id <= POWER(2, 2) + MOD(7, 3) AND rating > SQRT(ABS(-49)) ORDER BY id",
+    expr_func_fold_error: "SELECT id FROM movies WHERE id = SQRT(-1)",
 
     as_: r#"SELECT 1, 2 b, 3 AS c, 4 AS "👋", id AS "some id" FROM movies"#,
     as_bare: "SELECT 1 AS",
@@ -198,12 +254,72 @@ test_query! {
     where_integer: "SELECT * FROM movies WHERE 7",
     where_string: "SELECT * FROM movies WHERE 'abc'",
     where_multi: "SELECT * FROM movies WHERE TRUE, TRUE",
+    where_null_is: "SELECT id, title, ultrahd FROM movies WHERE ultrahd IS NULL",
+    where_null_is_not: "SELECT id, title, ultrahd FROM movies WHERE ultrahd IS NOT NULL",
+    where_null_is_or: "SELECT id, title, ultrahd FROM movies WHERE ultrahd IS NULL OR ultrahd = FALSE",
+    where_null_is_and: "SELECT id, title, ultrahd FROM movies WHERE ultrahd IS NOT NULL AND released > 2010",
+    where_null_equal: "SELECT id, title, ultrahd FROM movies WHERE ultrahd = NULL",
+    where_null_not: "SELECT id, title, ultrahd FROM movies WHERE NOT ultrahd",
+    where_null_not_and: "SELECT id, title, ultrahd FROM movies WHERE NOT (ultrahd AND NULL)",
+    where_null_not_or: "SELECT id, title, ultrahd FROM movies WHERE NOT (ultrahd OR NULL)",
+    where_null_compare: "SELECT id, title, ultrahd FROM movies WHERE ultrahd = TRUE OR ultrahd != TRUE",
+    where_like: "SELECT id, title FROM movies WHERE title LIKE 'S%' ORDER BY id",
+    where_like_not: "SELECT id, title FROM movies WHERE title NOT LIKE '%i_%' ORDER BY id",
+    where_like_escape: "SELECT id, title FROM movies WHERE title LIKE '!S_c%' ESCAPE '!' ORDER BY id",
+    where_like_column: "SELECT id, title FROM movies WHERE title LIKE CONCAT(SUBSTRING(title FROM 1 FOR 1), '%a%') ORDER BY id",
+    where_ilike: "SELECT id, title FROM movies WHERE title ILIKE '%the%' ORDER BY id",
+    where_ilike_not: "SELECT id, title FROM movies WHERE title NOT ILIKE '%the%' ORDER BY id",
+    where_between: "SELECT id, title, released FROM movies WHERE released BETWEEN 2000 AND 2010 ORDER BY id",
+    where_between_not: "SELECT id, title, released FROM movies WHERE released NOT BETWEEN 2000 AND 2010 ORDER BY id",
+    where_between_pk: "SELECT id, title FROM movies WHERE id BETWEEN 3 AND 5 ORDER BY id",
+    where_pk_keyset: "SELECT id, title FROM movies WHERE id > 4 ORDER BY id LIMIT 3",
+    where_pk_keyset_desc: "SELECT id, title FROM movies WHERE id < 7 ORDER BY id DESC LIMIT 3",
+    where_pk_range_float: "SELECT id, title FROM movies WHERE id > 2.5 AND id <= 5 ORDER BY id",
+    where_in: "SELECT id, title, released FROM movies WHERE released IN (1997, 2015, 2020) ORDER BY id",
+    where_in_not: "SELECT id, title, released FROM movies WHERE released NOT IN (1997, 2015, 2020) ORDER BY id",
+    where_in_pk: "SELECT * FROM movies WHERE id IN (3, 5, 7)",
+    where_in_index: "SELECT * FROM movies WHERE genre_id IN (2, 3, 4) ORDER BY id",
+    where_in_index_partial: "SELECT * FROM movies WHERE genre_id IN (2, 3) AND studio_id = 2 ORDER BY id",
+    where_in_subquery: "SELECT id, title FROM movies WHERE studio_id IN (SELECT id FROM studios WHERE country_id = 'us') ORDER BY id",
+    where_in_subquery_not: "SELECT id, title FROM movies WHERE studio_id NOT IN (SELECT id FROM studios WHERE country_id = 'us') ORDER BY id",
+    where_in_subquery_empty: "SELECT id, title FROM movies WHERE studio_id IN (SELECT id FROM studios WHERE FALSE)",
+    where_in_subquery_nested: "SELECT id, title FROM movies WHERE studio_id IN (SELECT id FROM studios WHERE country_id IN (SELECT id FROM countries WHERE name LIKE 'U%')) ORDER BY id",
+    where_in_subquery_columns: "SELECT id, title FROM movies WHERE studio_id IN (SELECT id, name FROM studios)",
+    where_in_subquery_outer: "SELECT id, title FROM movies WHERE studio_id IN (SELECT id FROM studios WHERE id = movies.genre_id)",
+    where_in_subquery_correlated_not: "SELECT id, title FROM movies m WHERE studio_id NOT IN (SELECT s.id FROM studios s WHERE s.country_id = 'us' AND s.id != m.genre_id) ORDER BY id",
+    where_in_subquery_correlated_star: "SELECT id, title FROM movies m WHERE studio_id IN (SELECT * FROM studios s WHERE s.id = m.genre_id)",
+    where_in_subquery_select: "SELECT id, genre_id IN (SELECT id FROM genres WHERE name = 'Action') AS action FROM movies ORDER BY id",
+    where_subquery_scalar: "SELECT id, title, rating FROM movies WHERE rating > (SELECT rating FROM movies WHERE title = 'Heat') ORDER BY id",
+    where_subquery_scalar_empty: "SELECT id, title FROM movies WHERE rating > (SELECT rating FROM movies WHERE FALSE)",
+    where_subquery_scalar_rows: "SELECT id, title FROM movies WHERE rating > (SELECT rating FROM movies)",
+    where_subquery_scalar_columns: "SELECT id, title FROM movies WHERE rating > (SELECT id, rating FROM movies WHERE id = 1)",
+    where_subquery_scalar_correlated: "SELECT id, title FROM movies m WHERE rating = (SELECT rating FROM movies WHERE id = m.id)",
+    where_subquery_select: "SELECT id, (SELECT name FROM genres WHERE id = 2) AS genre FROM movies WHERE id < 3 ORDER BY id",
+    where_exists: "SELECT * FROM genres WHERE EXISTS (SELECT * FROM movies WHERE rating > 8.5)",
+    where_exists_empty: "SELECT * FROM genres WHERE EXISTS (SELECT * FROM movies WHERE rating > 9)",
+    where_exists_not: "SELECT * FROM genres WHERE NOT EXISTS (SELECT * FROM movies WHERE rating > 9)",
+    where_exists_correlated: "SELECT * FROM genres g WHERE EXISTS (SELECT 1 FROM movies m WHERE m.genre_id = g.id AND m.rating > 8) ORDER BY id",
+    where_exists_correlated_join: "SELECT g.id, g.name FROM genres g JOIN movies m ON m.genre_id = g.id WHERE m.rating > 8 GROUP BY g.id, g.name ORDER BY g.id",
+    where_exists_correlated_not: "SELECT * FROM genres g WHERE NOT EXISTS (SELECT 1 FROM movies m WHERE m.genre_id = g.id AND m.rating > 8) ORDER BY id",
+    where_exists_correlated_and: "SELECT * FROM studios s WHERE country_id = 'us' AND EXISTS (SELECT * FROM movies WHERE studio_id = s.id AND genre_id = 3) ORDER BY id",
+    where_exists_correlated_shadow: "SELECT * FROM genres g WHERE EXISTS (SELECT * FROM movies WHERE id = 8 AND genre_id = g.id)",
+    where_exists_correlated_or: "SELECT * FROM genres g WHERE id = 1 OR EXISTS (SELECT * FROM movies WHERE genre_id = g.id)",
+    where_exists_correlated_aggregate: "SELECT * FROM genres g WHERE EXISTS (SELECT COUNT(*) FROM movies WHERE genre_id = g.id)",
+    where_exists_correlated_limit: "SELECT * FROM genres g WHERE EXISTS (SELECT * FROM movies WHERE genre_id = g.id LIMIT 1)",
+    where_exists_select: "SELECT id, EXISTS (SELECT * FROM movies WHERE rating > 8.5) AS any, NOT EXISTS (SELECT * FROM movies WHERE rating > 9) AS none FROM genres ORDER BY id",
     where_pk: "SELECT * FROM movies WHERE id = 3",
     where_pk_or: "SELECT * FROM movies WHERE id = 3 OR id = 5 OR id = 7",
     where_pk_or_partial: "SELECT * FROM movies WHERE (id = 2 OR id = 3 OR id = 4 OR id = 5) AND genre_id = 1",
     where_index: "SELECT * FROM movies WHERE genre_id = 2 ORDER BY id",
     where_index_or: "SELECT * FROM movies WHERE genre_id = 2 OR genre_id = 3 OR genre_id = 4 OR genre_id = 5 ORDER BY id",
     where_index_or_partial: "SELECT * FROM movies WHERE (genre_id = 2 OR genre_id = 3) AND studio_id = 2 ORDER BY id",
+    where_index_range: "SELECT * FROM movies WHERE genre_id > 1 AND genre_id < 3",
+    where_index_range_between: "SELECT * FROM movies WHERE studio_id BETWEEN 2 AND 3",
+    where_index_range_lower: "SELECT * FROM movies WHERE studio_id >= 3",
+    where_index_range_upper: "SELECT * FROM movies WHERE 2 > studio_id",
+    where_index_range_narrow: "SELECT * FROM movies WHERE genre_id > 1 AND genre_id >= 2 AND genre_id < 4 AND genre_id <= 3",
+    where_index_range_empty: "SELECT * FROM movies WHERE genre_id > 2 AND genre_id < 2",
+    where_index_range_float: "SELECT * FROM movies WHERE genre_id > 1.5 ORDER BY id",
     where_field_unknown: "SELECT * FROM movies WHERE unknown",
     where_field_qualified: "SELECT movies.id, genres.id FROM movies, genres WHERE movies.id >= 3 AND genres.id = 1",
     where_field_ambiguous: "SELECT movies.id, genres.id FROM movies, genres WHERE id >= 3",
@@ -228,6 +344,124 @@ test_query! {
     order_aggregate: "SELECT studio_id, MAX(rating) FROM movies GROUP BY studio_id ORDER BY MAX(rating)",
     order_aggregate_noselect: "SELECT studio_id, MAX(rating) FROM movies GROUP BY studio_id ORDER BY MIN(rating)",
     order_group_by_noselect: "SELECT MAX(rating) FROM movies GROUP BY studio_id ORDER BY studio_id",
+    order_ordinal: "SELECT title AS name, released FROM movies ORDER BY 2, 1",
+    order_ordinal_desc: "SELECT id, title FROM movies ORDER BY 2 DESC",
+    order_ordinal_star: "SELECT * FROM movies ORDER BY 6, 1 DESC",
+    order_ordinal_aggregate: "SELECT studio_id, COUNT(*) FROM movies GROUP BY studio_id ORDER BY 2 DESC, 1",
+    order_ordinal_union: "SELECT id FROM movies UNION SELECT id FROM genres ORDER BY 1 DESC",
+    order_ordinal_expr: "SELECT id, title FROM movies ORDER BY 1 + 1, id",
+    order_ordinal_zero: "SELECT id, title FROM movies ORDER BY 0",
+    order_ordinal_out_of_range: "SELECT id, title FROM movies ORDER BY 3",
+    order_ordinal_hidden: "SELECT title FROM movies ORDER BY released, 2",
+    order_alias: "SELECT title AS name, released AS year FROM movies ORDER BY year DESC, name",
+    order_alias_shadow: "SELECT id AS rating, rating AS id FROM movies ORDER BY id, rating",
+}
+test_query! { with [
+        "CREATE TABLE patterns (id INTEGER PRIMARY KEY, value STRING INDEX, other STRING)",
+        "INSERT INTO patterns VALUES
+            (1, 'abc', 'abc'),
+            (2, 'a.c', 'a.c'),
+            (3, 'A.C', 'A.C'),
+            (4, 'a(b)c', 'a(b)c'),
+            (5, 'a[b]c', 'a[b]c'),
+            (6, 'a+b*c?', 'a+b*c?'),
+            (7, '^a$', '^a$'),
+            (8, '100%', '100%'),
+            (9, '1000', '1000'),
+            (10, 'b', 'b'),
+            (11, NULL, NULL)",
+    ];
+    like_regex_dot: "SELECT * FROM patterns WHERE other LIKE 'a.c' ORDER BY id",
+    like_regex_chars: "SELECT * FROM patterns WHERE other LIKE 'a(%' OR other LIKE 'a[b]_' OR other LIKE '%*c?' OR other LIKE '^_$' ORDER BY id",
+    like_ilike: "SELECT * FROM patterns WHERE other ILIKE 'a.c' ORDER BY id",
+    like_escape: "SELECT * FROM patterns WHERE other LIKE '100!%' ESCAPE '!' ORDER BY id",
+    like_prefix_index: "SELECT * FROM patterns WHERE value LIKE 'a%' ORDER BY id",
+    like_prefix_index_exact: "SELECT * FROM patterns WHERE value LIKE 'a.c' ORDER BY id",
+    like_prefix_index_regex: "SELECT * FROM patterns WHERE value LIKE 'a(%)_' ORDER BY id",
+    like_prefix_index_escape: "SELECT * FROM patterns WHERE value LIKE '100!%%' ESCAPE '!' ORDER BY id",
+    like_prefix_index_double: "SELECT * FROM patterns WHERE value LIKE '100%%' ORDER BY id",
+    like_prefix_index_and: "SELECT * FROM patterns WHERE value LIKE 'a%' AND id > 3 ORDER BY id",
+    like_prefix_index_lookup: "SELECT * FROM patterns WHERE value LIKE 'a%' AND value = 'abc'",
+    like_prefix_none: "SELECT * FROM patterns WHERE value LIKE '%c' ORDER BY id",
+    like_prefix_ilike: "SELECT * FROM patterns WHERE value ILIKE 'a%' ORDER BY id",
+    like_prefix_not: "SELECT * FROM patterns WHERE value NOT LIKE 'a%' ORDER BY id",
+    like_prefix_unindexed: "SELECT * FROM patterns WHERE other LIKE 'a%' ORDER BY id",
+}
+test_query! { with [
+        "CREATE TABLE nullable (id INTEGER PRIMARY KEY, value INTEGER INDEX)",
+        "INSERT INTO nullable VALUES (1, 1), (2, NULL), (3, 3), (4, NULL), (5, 1)",
+        "CREATE TABLE other (id INTEGER PRIMARY KEY, value INTEGER)",
+        "INSERT INTO other VALUES (1, NULL), (2, 3)",
+        "CREATE TABLE floats (id INTEGER PRIMARY KEY, value FLOAT)",
+        "INSERT INTO floats VALUES (1, 1.0), (2, 3.5), (3, NULL)",
+    ];
+    order_index_null_limit: "SELECT * FROM nullable ORDER BY value LIMIT 3",
+    order_index_null_limit_desc: "SELECT * FROM nullable ORDER BY value DESC LIMIT 4",
+    order_nulls_default: "SELECT * FROM nullable ORDER BY value DESC, id",
+    order_nulls_first: "SELECT * FROM nullable ORDER BY value DESC NULLS FIRST, id",
+    order_nulls_last: "SELECT * FROM nullable ORDER BY value NULLS LAST, id DESC",
+    order_nulls_lowercase: "SELECT * FROM nullable ORDER BY value nulls last, id",
+    order_nulls_expr: "SELECT id, value * 2 AS twice FROM nullable ORDER BY value * 2 DESC NULLS FIRST, id DESC",
+    order_nulls_alias: "SELECT id, value AS v FROM nullable ORDER BY v NULLS LAST, 1 DESC",
+    order_nulls_stable: "SELECT * FROM nullable ORDER BY value NULLS LAST",
+    order_nulls_index_limit: "SELECT * FROM nullable ORDER BY value NULLS LAST LIMIT 3",
+    order_nulls_index_limit_desc: "SELECT * FROM nullable ORDER BY value DESC NULLS LAST LIMIT 3",
+    order_nulls_union: "SELECT value FROM nullable UNION ALL SELECT value FROM other ORDER BY 1 NULLS LAST",
+    order_nulls_missing: "SELECT * FROM nullable ORDER BY value NULLS",
+    order_nulls_unknown: "SELECT * FROM nullable ORDER BY value NULLS MIDDLE",
+    order_nulls_no_direction: "SELECT * FROM nullable ORDER BY value FIRST",
+    where_index_null: "SELECT * FROM nullable WHERE value IS NULL ORDER BY id",
+    agg_null_mixed: "SELECT COUNT(value), SUM(value), AVG(value), MIN(value), MAX(value) FROM nullable",
+    agg_null_mixed_distinct: "SELECT COUNT(DISTINCT value), SUM(DISTINCT value), AVG(DISTINCT value), MIN(DISTINCT value), MAX(DISTINCT value) FROM nullable",
+    agg_null_mixed_distinct_rewrite: "WITH d AS (SELECT value FROM nullable GROUP BY value) SELECT COUNT(value), SUM(value), AVG(value), MIN(value), MAX(value) FROM d",
+    agg_null_mixed_window: "SELECT id, value, SUM(value) OVER (ORDER BY id), AVG(value) OVER (ORDER BY id) FROM nullable ORDER BY id",
+    where_index_null_distinct: "SELECT * FROM nullable WHERE value IS NOT DISTINCT FROM NULL ORDER BY id",
+    where_index_null_distinct_value: "SELECT * FROM nullable WHERE 1 IS NOT DISTINCT FROM value ORDER BY id",
+    where_index_null_distinct_or: "SELECT * FROM nullable WHERE value IS NOT DISTINCT FROM NULL OR value IS NOT DISTINCT FROM 3 ORDER BY id",
+    where_index_null_distinct_not: "SELECT * FROM nullable WHERE value IS DISTINCT FROM 1 ORDER BY id",
+    where_3vl_and: "SELECT * FROM nullable WHERE value = 1 AND NULL",
+    where_3vl_or: "SELECT * FROM nullable WHERE value = 1 OR NULL ORDER BY id",
+    where_3vl_not: "SELECT * FROM nullable WHERE NOT (value = 1) ORDER BY id",
+    group_null: "SELECT value, COUNT(*) FROM nullable GROUP BY value ORDER BY value",
+    join_null: "SELECT * FROM nullable n JOIN other o ON n.value = o.value ORDER BY n.id",
+    join_null_left: "SELECT * FROM nullable n LEFT JOIN other o ON n.value = o.value ORDER BY n.id",
+    join_null_distinct: "SELECT * FROM nullable n JOIN other o ON n.value IS NOT DISTINCT FROM o.value ORDER BY n.id",
+    join_null_nested: "SELECT * FROM nullable n JOIN other o ON n.value >= o.value AND n.value <= o.value ORDER BY n.id",
+    join_null_left_nested: "SELECT * FROM nullable n LEFT JOIN other o ON n.value >= o.value AND n.value <= o.value ORDER BY n.id",
+    join_null_full: "SELECT * FROM nullable n FULL JOIN other o ON n.value = o.value ORDER BY n.id, o.id",
+    join_null_full_nested: "SELECT * FROM nullable n FULL JOIN other o ON n.value >= o.value AND n.value <= o.value ORDER BY n.id, o.id",
+    join_null_full_where: "SELECT * FROM nullable n FULL JOIN other o ON n.value = o.value WHERE n.id IS NULL OR o.id IS NULL ORDER BY n.id, o.id",
+    join_null_full_on_left: "SELECT * FROM nullable n FULL JOIN other o ON n.value = o.value AND n.id > 3 ORDER BY n.id, o.id",
+    join_null_numeric: "SELECT * FROM nullable n JOIN floats f ON n.value = f.value ORDER BY n.id",
+    join_null_numeric_nested: "SELECT * FROM nullable n JOIN floats f ON n.value >= f.value AND n.value <= f.value ORDER BY n.id",
+    where_index_null_not: "SELECT * FROM nullable WHERE value IS NOT NULL",
+    where_index_null_or: "SELECT * FROM nullable WHERE value IS NULL OR value = 3 ORDER BY id",
+    where_index_null_equal: "SELECT * FROM nullable WHERE value = NULL",
+    where_index_null_pk: "SELECT * FROM nullable WHERE id = NULL",
+    where_index_null_range: "SELECT * FROM nullable WHERE value < 3",
+    where_index_null_in: "SELECT * FROM nullable WHERE value IN (3, NULL) ORDER BY id",
+    where_index_null_not_in: "SELECT * FROM nullable WHERE value NOT IN (3, NULL) ORDER BY id",
+    where_in_subquery_null: "SELECT * FROM nullable WHERE id IN (SELECT value FROM other) ORDER BY id",
+    where_in_subquery_null_not: "SELECT * FROM nullable WHERE id NOT IN (SELECT value FROM other) ORDER BY id",
+    where_in_subquery_null_not_lhs: "SELECT * FROM nullable WHERE value NOT IN (SELECT value FROM other WHERE value IS NOT NULL) ORDER BY id",
+    where_in_subquery_null_not_empty: "SELECT * FROM nullable WHERE value NOT IN (SELECT value FROM other WHERE FALSE) ORDER BY id",
+    where_in_subquery_null_not_correlated: "SELECT * FROM nullable n WHERE value NOT IN (SELECT value FROM other o WHERE o.id >= n.id) ORDER BY id",
+}
+test_query! { with [
+        "CREATE TABLE stock (
+            warehouse INTEGER,
+            item STRING,
+            quantity INTEGER NOT NULL,
+            PRIMARY KEY (warehouse, item)
+        )",
+        "INSERT INTO stock VALUES (2, 'b', 1), (1, 'b', 2), (1, 'a', 3), (2, 'a', 4), (-1, 'c', 5), (10, 'a', 6)",
+    ];
+    where_pk_composite: "SELECT * FROM stock WHERE warehouse = 1 AND item = 'b'",
+    where_pk_composite_reversed: "SELECT * FROM stock WHERE item = 'b' AND 1 = warehouse AND quantity > 0",
+    where_pk_composite_in: "SELECT * FROM stock WHERE warehouse IN (1, 2) AND item = 'a' ORDER BY warehouse",
+    where_pk_composite_missing: "SELECT * FROM stock WHERE warehouse = 1 AND item = 'c'",
+    where_pk_composite_partial: "SELECT * FROM stock WHERE warehouse = 1",
+    where_pk_composite_scan: "SELECT * FROM stock",
 }
 test_query! { with [
         "CREATE TABLE booleans (id INTEGER PRIMARY KEY, value BOOLEAN)",
@@ -243,6 +477,29 @@ test_query! { with [
     order_float_asc: "SELECT * FROM floats ORDER BY value ASC",
     order_float_desc: "SELECT * FROM floats ORDER BY value DESC",
 }
+test_query! { with [
+        "CREATE TABLE numbers (id INTEGER PRIMARY KEY, i INTEGER INDEX, f FLOAT INDEX)",
+        "INSERT INTO numbers VALUES
+            (1, 1, 1.0),
+            (2, 2, 2.5),
+            (3, 9007199254740993, 9007199254740992.0),
+            (4, 9223372036854775807, 9223372036854775807.0)",
+    ];
+    numeric_pk_float: "SELECT * FROM numbers WHERE id = 1.0",
+    numeric_pk_float_fraction: "SELECT * FROM numbers WHERE id = 1.5",
+    numeric_pk_float_in: "SELECT * FROM numbers WHERE id IN (1.0, 2.5, 3) ORDER BY id",
+    numeric_pk_float_range: "SELECT * FROM numbers WHERE id >= 2.0 AND id < 4.0 ORDER BY id",
+    numeric_index_float: "SELECT * FROM numbers WHERE i = 2.0",
+    numeric_index_float_large: "SELECT * FROM numbers WHERE i = 9007199254740992.0",
+    numeric_index_integer: "SELECT * FROM numbers WHERE f = 1",
+    numeric_index_integer_large: "SELECT * FROM numbers WHERE f = 9007199254740993",
+    numeric_index_integer_large_exact: "SELECT * FROM numbers WHERE f = 9007199254740992",
+    numeric_compare: "SELECT id, i = f, i < f, i > f FROM numbers ORDER BY id",
+    numeric_order: "SELECT id, i, f FROM numbers ORDER BY i - f, id",
+    numeric_join: "SELECT a.id, b.id FROM numbers a JOIN numbers b ON a.i = b.f ORDER BY a.id",
+    numeric_join_range: "SELECT a.id, b.id FROM numbers a JOIN numbers b ON a.i >= b.f AND a.i <= b.f ORDER BY a.id",
+    numeric_string: "SELECT * FROM numbers WHERE i = '1'",
+}
 test_query! { with [
         "CREATE TABLE integers (id INTEGER PRIMARY KEY, value INTEGER)",
         "INSERT INTO integers VALUES (1, 7), (2, NULL), (3, -3), (4, 3), (5, 0)",
@@ -266,6 +523,91 @@ test_query! { with [
     order_string_asc: "SELECT * FROM strings ORDER BY value ASC",
     order_string_desc: "SELECT * FROM strings ORDER BY value DESC",
 }
+test_query! { with [
+        "CREATE TABLE events (id INTEGER PRIMARY KEY, at TIMESTAMP INDEX, day DATE, starts TIME)",
+        "INSERT INTO events VALUES
+            (1, TIMESTAMP '2024-01-31 12:00:00', DATE '2024-01-31', TIME '12:00'),
+            (2, TIMESTAMP '2024-01-31 08:15:00', DATE '2024-01-31', TIME '08:15'),
+            (3, NULL, NULL, NULL),
+            (4, TIMESTAMP '2024-02-01 00:00:00', DATE '2024-02-01', TIME '00:00'),
+            (5, TIMESTAMP '1969-12-31 23:59:59.5', DATE '1969-12-31', TIME '23:59:59.5'),
+            (6, TIMESTAMP '2024-01-31 12:00:00', DATE '2024-01-31', TIME '12:00')
+        ",
+    ];
+    order_temporal_asc: "SELECT * FROM events ORDER BY at ASC, id ASC",
+    order_temporal_desc: "SELECT * FROM events ORDER BY day DESC, starts DESC, id ASC",
+    where_temporal_equal: "SELECT * FROM events WHERE day = DATE '2024-01-31' ORDER BY id",
+    where_temporal_index: "SELECT * FROM events WHERE at = TIMESTAMP '2024-01-31 12:00:00' ORDER BY id",
+    where_temporal_range: "SELECT * FROM events WHERE at >= TIMESTAMP '2024-01-31' AND at < TIMESTAMP '2024-02-01' ORDER BY at, id",
+    where_temporal_range_open: "SELECT * FROM events WHERE at > TIMESTAMP '2024-01-31 08:15:00' ORDER BY id",
+    where_temporal_time: "SELECT id, starts FROM events WHERE starts < TIME '12:00' ORDER BY id",
+    where_temporal_string: "SELECT * FROM events WHERE at = '2024-01-31 12:00:00'",
+    where_temporal_mismatch: "SELECT * FROM events WHERE at = day",
+    agg_temporal: "SELECT MIN(at), MAX(at), MIN(day), MAX(starts), COUNT(at) FROM events WHERE at IS NOT NULL",
+}
+test_query! { with [
+        "CREATE TABLE prices (id INTEGER PRIMARY KEY, price DECIMAL(10, 2) INDEX, rate DECIMAL(5, 4))",
+        "INSERT INTO prices VALUES
+            (1, DECIMAL '19.99', DECIMAL '0.0825'),
+            (2, DECIMAL '5', DECIMAL '0.1'),
+            (3, NULL, NULL),
+            (4, DECIMAL '-3.5', DECIMAL '1'),
+            (5, DECIMAL '19.990', DECIMAL '0.0825'),
+            (6, DECIMAL '0.01', DECIMAL '0.3333')
+        ",
+    ];
+    order_decimal_asc: "SELECT * FROM prices ORDER BY price ASC, id ASC",
+    order_decimal_desc: "SELECT * FROM prices ORDER BY rate DESC, id ASC",
+    where_decimal_equal: "SELECT * FROM prices WHERE rate = DECIMAL '0.08250' ORDER BY id",
+    where_decimal_index: "SELECT * FROM prices WHERE price = DECIMAL '19.9900' ORDER BY id",
+    where_decimal_integer: "SELECT * FROM prices WHERE price = 5 ORDER BY id",
+    where_decimal_float: "SELECT * FROM prices WHERE price > 4.99 ORDER BY id",
+    where_decimal_range: "SELECT * FROM prices WHERE price >= DECIMAL '0.01' AND price < 10 ORDER BY id",
+    expr_decimal: "SELECT id, price * rate, price + rate, price / rate, price - 1, price * 1.5 FROM prices WHERE id != 3 ORDER BY id",
+    agg_decimal: "SELECT SUM(price), AVG(price), MIN(price), MAX(price), COUNT(price) FROM prices WHERE price IS NOT NULL",
+    agg_decimal_group: "SELECT rate, SUM(price), AVG(price) FROM prices WHERE price IS NOT NULL GROUP BY rate ORDER BY rate",
+}
+test_query! { with [
+        "CREATE TABLE blobs (id INTEGER PRIMARY KEY, data BYTEA INDEX)",
+        "INSERT INTO blobs VALUES (1, x'00ff'), (2, x''), (3, NULL), (4, x'ff'), (5, x'00'), (6, x'00ff')",
+    ];
+    order_bytea: "SELECT * FROM blobs ORDER BY data ASC, id ASC",
+    where_bytea: "SELECT * FROM blobs WHERE data > x'00' ORDER BY id",
+    where_bytea_index: "SELECT * FROM blobs WHERE data = X'00FF' ORDER BY id",
+    expr_bytea: "SELECT id, data, LENGTH(data) FROM blobs ORDER BY id",
+}
+test_query! { with [
+        "CREATE TABLE pairs (id INTEGER PRIMARY KEY, a INTEGER, b STRING)",
+        "INSERT INTO pairs VALUES (1, NULL, 'x'), (2, 1, 'x'), (3, NULL, 'x'), (4, 1, NULL), (5, NULL, NULL), (6, 1, 'x'), (7, NULL, NULL)",
+    ];
+    distinct: "SELECT DISTINCT genre_id FROM movies",
+    distinct_multi: "SELECT DISTINCT genre_id, studio_id FROM movies ORDER BY genre_id, studio_id",
+    distinct_null: "SELECT DISTINCT a, b FROM pairs",
+    distinct_expr: "SELECT DISTINCT released / 10 * 10 AS decade FROM movies ORDER BY decade",
+    distinct_star: "SELECT DISTINCT * FROM pairs",
+    distinct_pk: "SELECT DISTINCT m.id AS movie, title FROM movies m WHERE released > 2000",
+    distinct_limit: "SELECT DISTINCT genre_id FROM movies LIMIT 2",
+    distinct_offset: "SELECT DISTINCT genre_id FROM movies ORDER BY genre_id OFFSET 1",
+    distinct_order_hidden: "SELECT DISTINCT genre_id FROM movies ORDER BY released",
+    distinct_group: "SELECT DISTINCT genre_id FROM movies GROUP BY genre_id ORDER BY genre_id",
+    distinct_group_having: "SELECT DISTINCT genre_id FROM movies GROUP BY genre_id, studio_id HAVING MAX(rating) > 7.5 ORDER BY genre_id",
+    distinct_aggregate: "SELECT DISTINCT COUNT(*) FROM movies GROUP BY studio_id ORDER BY 1",
+    distinct_join: "SELECT DISTINCT g.name FROM movies m JOIN genres g ON m.genre_id = g.id ORDER BY g.name",
+    distinct_subquery: "SELECT * FROM genres WHERE id IN (SELECT DISTINCT genre_id FROM movies WHERE rating > 8)",
+    distinct_union: "SELECT DISTINCT genre_id FROM movies UNION ALL SELECT DISTINCT a FROM pairs",
+    distinct_on: "SELECT DISTINCT ON (genre_id) genre_id, title, rating FROM movies ORDER BY genre_id, rating DESC",
+    distinct_on_hidden: "SELECT DISTINCT ON (studio_id) title FROM movies ORDER BY studio_id, released",
+    distinct_on_multi: "SELECT DISTINCT ON (genre_id, ultrahd) genre_id, ultrahd, title FROM movies ORDER BY genre_id, ultrahd, id",
+    distinct_on_expr: "SELECT DISTINCT ON (released / 10) released, title FROM movies ORDER BY released / 10, released",
+    distinct_on_position: "SELECT DISTINCT ON (1) genre_id, title FROM movies ORDER BY 1, title",
+    distinct_on_position_invalid: "SELECT DISTINCT ON (3) genre_id, title FROM movies",
+    distinct_on_null: "SELECT DISTINCT ON (a) a, id FROM pairs ORDER BY a, id DESC",
+    distinct_on_pk: "SELECT DISTINCT ON (id) * FROM movies",
+    distinct_on_limit: "SELECT DISTINCT ON (genre_id) genre_id, title FROM movies ORDER BY genre_id, id LIMIT 2 OFFSET 1",
+    distinct_on_aggregate: "SELECT DISTINCT ON (genre_id) genre_id, studio_id, COUNT(*) FROM movies GROUP BY genre_id, studio_id ORDER BY genre_id, COUNT(*) DESC, studio_id",
+    distinct_on_no_parens: "SELECT DISTINCT ON genre_id, title FROM movies",
+    distinct_on_empty: "SELECT DISTINCT ON () genre_id FROM movies",
+}
 test_query! {
     limit: "SELECT * FROM movies LIMIT 3",
     limit_zero: "SELECT * FROM movies LIMIT 0",
@@ -292,6 +634,14 @@ test_query! {
     offset_float: "SELECT * FROM movies OFFSET 3.14",
     offset_string: "SELECT * FROM movies OFFSET 'abc'",
 
+    limit_order_topk: "SELECT * FROM movies ORDER BY rating DESC, id LIMIT 3",
+    limit_order_topk_offset: "SELECT id, title FROM movies ORDER BY title LIMIT 2 OFFSET 3",
+    limit_order_index: "SELECT id, title, genre_id FROM movies ORDER BY genre_id, id LIMIT 4",
+    limit_order_index_desc: "SELECT id, title, genre_id FROM movies ORDER BY genre_id DESC LIMIT 4",
+    limit_order_index_filter: "SELECT id, title, genre_id FROM movies WHERE released > 2000 ORDER BY genre_id DESC LIMIT 2 OFFSET 1",
+    limit_order_index_zero: "SELECT id, genre_id FROM movies ORDER BY genre_id LIMIT 0",
+    limit_order_index_unsorted: "SELECT id, genre_id FROM movies ORDER BY genre_id, title LIMIT 3",
+
     join_cross: "SELECT * FROM movies CROSS JOIN genres",
     join_cross_alias: r#"
         SELECT m.id, m.title, g.id, g.name, c.id, c.name
@@ -334,11 +684,32 @@ test_query! {
     join_left_all: "SELECT * FROM movies m LEFT JOIN genres g ON m.id = g.id",
     join_left_outer: "SELECT m.id AS movie_id, g.id AS genre_id FROM movies m LEFT OUTER JOIN genres g ON m.id = g.id",
     join_left_truncate: "SELECT g.id AS genre_id, m.id AS movie_id FROM genres g LEFT JOIN movies m ON m.id = g.id",
+    join_left_on_left: "SELECT g.id, m.id FROM genres g LEFT JOIN movies m ON m.genre_id = g.id AND g.id = 1 ORDER BY g.id, m.id",
+    join_left_on_right: "SELECT g.id, m.id FROM genres g LEFT JOIN movies m ON m.genre_id = g.id AND m.rating > 8 ORDER BY g.id, m.id",
+    join_left_where_left: "SELECT g.id, m.id FROM genres g LEFT JOIN movies m ON m.genre_id = g.id WHERE g.id = 3 ORDER BY g.id, m.id",
+    join_left_where_right: "SELECT g.id, m.id FROM genres g LEFT JOIN movies m ON m.genre_id = g.id AND m.rating > 8 WHERE m.id IS NULL ORDER BY g.id",
+    join_left_where_both: "SELECT g.id, m.id FROM genres g LEFT JOIN movies m ON m.genre_id = g.id WHERE g.id > 1 AND m.rating > 8 ORDER BY g.id, m.id",
 
     join_right: "SELECT g.id AS genre_id, m.id AS movie_id FROM genres g RIGHT JOIN movies m ON m.id = g.id",
     join_right_all: "SELECT * FROM genres g RIGHT JOIN movies m ON m.id = g.id",
     join_right_outer: "SELECT g.id AS genre_id, m.id AS movie_id FROM genres g RIGHT OUTER JOIN movies m ON m.id = g.id",
     join_right_truncate: "SELECT m.id AS movie_id, g.id AS genre_id FROM movies m RIGHT JOIN genres g ON m.id = g.id",
+    join_right_swapped: "SELECT g.id AS genre_id, m.id AS movie_id FROM movies m LEFT JOIN genres g ON m.id = g.id",
+    join_right_where: "SELECT g.id, m.id FROM movies m RIGHT JOIN genres g ON m.genre_id = g.id AND m.rating > 8 WHERE g.id > 1 AND m.id IS NULL ORDER BY g.id",
+
+    join_full: "SELECT s.id AS studio_id, g.id AS genre_id FROM studios s FULL JOIN genres g ON s.id = g.id ORDER BY s.id",
+    join_full_all: "SELECT * FROM genres g FULL JOIN studios s ON g.id = s.id ORDER BY s.id",
+    join_full_outer: "SELECT s.id AS studio_id, g.id AS genre_id FROM studios s FULL OUTER JOIN genres g ON s.id = g.id ORDER BY s.id",
+    join_full_nested: "SELECT s.id AS studio_id, g.id AS genre_id FROM studios s FULL JOIN genres g ON s.id >= g.id AND s.id <= g.id ORDER BY s.id",
+    join_full_multi: "SELECT c.id, s.id, m.id FROM countries c FULL JOIN studios s ON c.id = s.country_id FULL JOIN movies m ON s.id = m.studio_id AND m.rating > 8.0 ORDER BY c.id, s.id, m.id",
+
+    join_hash_duplicate: "SELECT a.id, b.id FROM movies a JOIN movies b ON a.genre_id = b.genre_id ORDER BY a.id, b.id",
+    join_hash_duplicate_nested: "SELECT a.id, b.id FROM movies a JOIN movies b ON a.genre_id >= b.genre_id AND a.genre_id <= b.genre_id ORDER BY a.id, b.id",
+    join_hash_build_left: "SELECT g.id, m.id FROM genres g JOIN movies m ON m.genre_id = g.id ORDER BY g.id, m.id",
+    join_hash_build_left_nested: "SELECT g.id, m.id FROM genres g JOIN movies m ON m.genre_id >= g.id AND m.genre_id <= g.id ORDER BY g.id, m.id",
+    join_hash_left_duplicate: "SELECT g.id, m.id FROM genres g LEFT JOIN movies m ON m.genre_id = g.id ORDER BY g.id, m.id",
+    join_hash_left_duplicate_nested: "SELECT g.id, m.id FROM genres g LEFT JOIN movies m ON m.genre_id >= g.id AND m.genre_id <= g.id ORDER BY g.id, m.id",
+    join_hash_small: "SELECT * FROM movies m JOIN genres g ON m.genre_id = g.id WHERE m.id = 1 AND g.id = 1",
 
     agg_count_star: "SELECT COUNT(*) FROM movies",
     agg_expr: "SELECT SUM(rating * 10) / COUNT(*) FROM movies",
@@ -386,6 +757,166 @@ test_query! { with [
     agg_string: "SELECT MIN(s), MAX(s), SUM(s), COUNT(s), AVG(s) FROM strings WHERE s IS NOT NULL",
     agg_string_null: "SELECT MIN(s), MAX(s), SUM(s), COUNT(s), AVG(s) FROM strings",
 }
+test_query! { with [
+        "CREATE TABLE mixed (id INTEGER PRIMARY KEY, s STRING, b BOOLEAN)",
+        "INSERT INTO mixed VALUES (1, 'a', NULL), (2, 'b', TRUE), (3, NULL, NULL), (4, 'd', FALSE), (5, NULL, NULL)",
+    ];
+    agg_minmax_null_mixed: "SELECT MIN(s), MAX(s), MIN(b), MAX(b) FROM mixed",
+    agg_minmax_null_mixed_group: "SELECT id % 2 AS odd, MIN(s), MAX(s), MIN(b), MAX(b) FROM mixed GROUP BY odd ORDER BY odd",
+    agg_minmax_null_all: "SELECT MIN(s), MAX(s), MIN(b), MAX(b) FROM mixed WHERE id IN (3, 5)",
+}
+test_query! {
+    agg_distinct: "SELECT COUNT(DISTINCT studio_id), SUM(DISTINCT studio_id), AVG(DISTINCT studio_id), COUNT(studio_id), SUM(studio_id) FROM movies",
+    agg_distinct_expr: "SELECT COUNT(DISTINCT rating * 10), MAX(DISTINCT rating) FROM movies",
+    agg_distinct_group: "SELECT genre_id, COUNT(DISTINCT studio_id), COUNT(DISTINCT ultrahd), COUNT(*) FROM movies GROUP BY genre_id ORDER BY genre_id",
+    agg_distinct_group_null: "SELECT genre_id, COUNT(DISTINCT ultrahd), SUM(DISTINCT released), AVG(DISTINCT studio_id), COUNT(DISTINCT NULL), SUM(DISTINCT NULL) FROM movies GROUP BY genre_id ORDER BY genre_id",
+    agg_distinct_group_rewrite: "SELECT genre_id, studio_id FROM movies GROUP BY genre_id, studio_id ORDER BY genre_id, studio_id",
+    agg_distinct_having: "SELECT genre_id FROM movies GROUP BY genre_id HAVING COUNT(DISTINCT studio_id) > 2 ORDER BY genre_id",
+    agg_distinct_null: "SELECT COUNT(DISTINCT NULL), SUM(DISTINCT NULL), AVG(DISTINCT NULL) FROM movies",
+    agg_distinct_star: "SELECT COUNT(DISTINCT *) FROM movies",
+    agg_distinct_nonaggregate: "SELECT UPPER(DISTINCT title) FROM movies",
+    agg_string_agg: "SELECT STRING_AGG(title, ', ' ORDER BY title) FROM movies",
+    agg_string_agg_unordered: "SELECT STRING_AGG(title, ', ') FROM movies WHERE id < 4",
+    agg_string_agg_group: "SELECT genre_id, STRING_AGG(title, ',' ORDER BY released DESC, title), COUNT(*) FROM movies GROUP BY genre_id ORDER BY genre_id",
+    agg_string_agg_null: "SELECT studio_id, STRING_AGG(CASE WHEN ultrahd THEN title END, ';' ORDER BY id) FROM movies GROUP BY studio_id ORDER BY studio_id",
+    agg_string_agg_distinct: "SELECT STRING_AGG(DISTINCT country_id, ',' ORDER BY country_id DESC) FROM studios",
+    agg_string_agg_nonstring: "SELECT STRING_AGG(rating, ' ' ORDER BY rating, id) FROM movies",
+    agg_string_agg_empty: "SELECT STRING_AGG(title, ',') FROM movies WHERE FALSE",
+    agg_string_agg_args: "SELECT STRING_AGG(title) FROM movies",
+    agg_string_agg_separator: "SELECT STRING_AGG(title, 1) FROM movies",
+    agg_string_agg_separator_field: "SELECT STRING_AGG(title, title) FROM movies",
+    agg_order_nonaggregate: "SELECT UPPER(title ORDER BY id) FROM movies",
+    agg_order_sum: "SELECT SUM(id ORDER BY id DESC) FROM movies",
+    agg_order_nulls: "SELECT STRING_AGG(title, ', ' ORDER BY ultrahd NULLS LAST, id) FROM movies",
+}
+test_query! { with [
+        "CREATE TABLE tags (id INTEGER PRIMARY KEY, post INTEGER, tag STRING)",
+        "INSERT INTO tags VALUES
+            (1, 1, 'a,b'),
+            (2, 1, 'c'),
+            (3, 1, NULL),
+            (4, 1, 'it''s'),
+            (5, 2, ','),
+            (6, 2, ''),
+            (7, 3, NULL)",
+    ];
+    agg_string_agg_delimiter: "SELECT post, STRING_AGG(tag, ',' ORDER BY id), STRING_AGG(tag, ''',''' ORDER BY id DESC), COUNT(tag) FROM tags GROUP BY post ORDER BY post",
+}
+test_query! {
+    window_row_number: "SELECT title, released, ROW_NUMBER() OVER (ORDER BY released) AS n FROM movies ORDER BY n",
+    window_row_number_empty: "SELECT id, ROW_NUMBER() OVER () FROM movies WHERE id > 6",
+    window_rank: "SELECT title, rating, RANK() OVER (ORDER BY rating DESC), DENSE_RANK() OVER (ORDER BY rating DESC), ROW_NUMBER() OVER (ORDER BY rating DESC, id) FROM movies ORDER BY rating DESC, id",
+    window_partition: "SELECT genre_id, title, rating, RANK() OVER (PARTITION BY genre_id ORDER BY rating DESC) AS rank FROM movies ORDER BY genre_id, rank, id",
+    window_partition_null: "SELECT ultrahd, id, ROW_NUMBER() OVER (PARTITION BY ultrahd ORDER BY id) FROM movies ORDER BY ultrahd, id",
+    window_order_nulls: "SELECT id, ultrahd, ROW_NUMBER() OVER (ORDER BY ultrahd DESC NULLS FIRST, id), RANK() OVER (ORDER BY ultrahd NULLS LAST) FROM movies ORDER BY id",
+    window_aggregate: "SELECT genre_id, id, COUNT(*) OVER (PARTITION BY genre_id), SUM(id) OVER (PARTITION BY genre_id), AVG(rating) OVER (PARTITION BY genre_id), MIN(released) OVER (PARTITION BY genre_id), MAX(title) OVER (PARTITION BY genre_id) FROM movies ORDER BY genre_id, id",
+    window_aggregate_running: "SELECT studio_id, id, COUNT(*) OVER (PARTITION BY studio_id ORDER BY id), SUM(id) OVER (PARTITION BY studio_id ORDER BY id), MAX(rating) OVER (PARTITION BY studio_id ORDER BY id) FROM movies ORDER BY studio_id, id",
+    window_aggregate_peers: "SELECT id, rating, SUM(id) OVER (ORDER BY rating DESC), COUNT(*) OVER (ORDER BY rating DESC) FROM movies ORDER BY rating DESC, id",
+    window_aggregate_empty: "SELECT id, COUNT(*) OVER (), SUM(id) OVER (), MAX(rating) OVER () FROM movies ORDER BY id",
+    window_multiple: "SELECT id, ROW_NUMBER() OVER (ORDER BY id DESC), RANK() OVER (PARTITION BY studio_id ORDER BY released), COUNT(*) OVER (PARTITION BY studio_id), ROW_NUMBER() OVER (ORDER BY id DESC) FROM movies ORDER BY id",
+    window_expr: "SELECT id, ROW_NUMBER() OVER (ORDER BY id) * 10 + RANK() OVER (ORDER BY released / 10) AS expr FROM movies ORDER BY id",
+    window_order: "SELECT title FROM movies ORDER BY RANK() OVER (ORDER BY rating DESC), id",
+    window_order_alias: "SELECT title, ROW_NUMBER() OVER (ORDER BY title DESC) AS n FROM movies ORDER BY n LIMIT 3",
+    window_group: "SELECT genre_id, COUNT(*), RANK() OVER (ORDER BY COUNT(*) DESC), SUM(COUNT(*)) OVER (ORDER BY genre_id) FROM movies GROUP BY genre_id ORDER BY genre_id",
+    window_group_ungrouped: "SELECT genre_id, RANK() OVER (ORDER BY rating) FROM movies GROUP BY genre_id",
+    window_having: "SELECT genre_id, COUNT(*) AS count, ROW_NUMBER() OVER (ORDER BY genre_id) FROM movies GROUP BY genre_id HAVING count < 5 ORDER BY genre_id",
+    window_having_window: "SELECT genre_id, COUNT(*) FROM movies GROUP BY genre_id HAVING RANK() OVER (ORDER BY genre_id) = 1",
+    window_where: "SELECT id FROM movies WHERE ROW_NUMBER() OVER (ORDER BY id) > 1",
+    window_nested: "SELECT SUM(ROW_NUMBER() OVER ()) OVER () FROM movies",
+    window_no_over: "SELECT ROW_NUMBER() FROM movies",
+    window_args: "SELECT RANK(id) OVER (ORDER BY id) FROM movies",
+    window_unknown: "SELECT UPPER(title) OVER () FROM movies",
+    window_distinct: "SELECT COUNT(DISTINCT studio_id) OVER () FROM movies",
+    window_nofrom: "SELECT ROW_NUMBER() OVER (), COUNT(*) OVER ()",
+    window_none: "SELECT id, ROW_NUMBER() OVER (ORDER BY id) FROM movies WHERE FALSE",
+}
+
+test_query! {
+    cte: "WITH t AS (SELECT id, title FROM movies WHERE rating > 8) SELECT * FROM t",
+    cte_columns: "WITH t (a, b) AS (SELECT id, title FROM movies) SELECT * FROM t WHERE a < 3",
+    cte_columns_partial: "WITH t (a) AS (SELECT id, title FROM movies) SELECT a, t.title FROM t WHERE a < 3",
+    cte_columns_original: "WITH t (a) AS (SELECT id FROM movies) SELECT id FROM t",
+    cte_columns_excess: "WITH t (a, b, c) AS (SELECT id, title FROM movies) SELECT * FROM t",
+    cte_unnamed: "WITH t AS (SELECT 1 + 1, 'a') SELECT * FROM t",
+    cte_hidden: "WITH t AS (SELECT title FROM movies ORDER BY rating DESC, id LIMIT 3) SELECT * FROM t",
+    cte_aggregate: "WITH best AS (SELECT genre_id, MAX(rating) AS rating FROM movies GROUP BY genre_id) SELECT m.title, best.rating FROM movies m JOIN best ON m.genre_id = best.genre_id AND m.rating = best.rating ORDER BY m.title",
+    cte_multiple: "WITH a AS (SELECT id, title, released FROM movies WHERE genre_id = 1), b AS (SELECT title FROM a WHERE released > 2000) SELECT * FROM b ORDER BY title",
+    cte_multiple_references: "WITH t AS (SELECT id, genre_id FROM movies WHERE released > 2010) SELECT a.id, b.id FROM t a JOIN t b ON a.genre_id = b.genre_id WHERE a.id < b.id ORDER BY a.id, b.id",
+    cte_multiple_references_unaliased: "WITH t AS (SELECT id FROM movies) SELECT * FROM t JOIN t ON t.id = t.id",
+    cte_shadow: "WITH movies AS (SELECT * FROM movies WHERE genre_id = 1) SELECT id, title FROM movies ORDER BY id",
+    cte_shadow_subquery: "WITH genres AS (SELECT id FROM genres WHERE name = 'Action') SELECT title FROM movies WHERE genre_id IN (SELECT id FROM genres)",
+    cte_shadow_previous: "WITH t AS (SELECT id FROM movies), u AS (WITH t AS (SELECT id FROM genres) SELECT * FROM t) SELECT * FROM u",
+    cte_nested: "WITH t AS (WITH u AS (SELECT id, title FROM movies) SELECT * FROM u WHERE id < 3) SELECT * FROM t",
+    cte_where_projection: "WITH t AS (SELECT id, title, rating * 2 AS r FROM movies) SELECT * FROM t WHERE r > 16 AND id > 2 ORDER BY id",
+    cte_where_join: "WITH t AS (SELECT m.title, g.name AS genre FROM movies m JOIN genres g ON m.genre_id = g.id) SELECT * FROM t WHERE genre = 'Action' ORDER BY title",
+    cte_where_aggregate: "WITH t AS (SELECT genre_id, MAX(rating) AS rating FROM movies GROUP BY genre_id) SELECT * FROM t WHERE rating > 8 AND genre_id > 1",
+    cte_nested_scope: "WITH t AS (WITH u AS (SELECT 1) SELECT 2) SELECT * FROM u",
+    cte_forward_reference: "WITH a AS (SELECT * FROM b), b AS (SELECT 1) SELECT * FROM a",
+    cte_duplicate: "WITH t AS (SELECT 1), t AS (SELECT 2) SELECT * FROM t",
+    cte_unused: "WITH t AS (SELECT * FROM missing) SELECT 1",
+    cte_bare: "WITH t AS (SELECT 1)",
+    cte_no_parens: "WITH t AS SELECT 1 SELECT * FROM t",
+}
+test_query! {
+    union_all: "SELECT id, title FROM movies WHERE id < 3 UNION ALL SELECT id, name FROM genres",
+    union_all_duplicates: "SELECT genre_id FROM movies UNION ALL SELECT id FROM genres ORDER BY genre_id",
+    union: "SELECT genre_id FROM movies UNION SELECT id FROM genres ORDER BY genre_id",
+    union_chained: "SELECT 1 AS n UNION SELECT 2 UNION ALL SELECT 1 ORDER BY n",
+    union_chained_all: "SELECT 1 AS n UNION ALL SELECT 2 UNION SELECT 1 ORDER BY n",
+    union_columns: "SELECT id AS a, title FROM movies WHERE id = 1 UNION SELECT id AS b, name FROM genres WHERE id = 1 ORDER BY a, title",
+    union_order_right: "SELECT id AS a FROM movies UNION SELECT id AS b FROM genres ORDER BY b",
+    union_order_limit: "SELECT id, title FROM movies UNION ALL SELECT id, name FROM genres ORDER BY id DESC, title LIMIT 4 OFFSET 2",
+    union_order_left: "SELECT id FROM movies ORDER BY id UNION SELECT id FROM genres",
+    union_limit_left: "SELECT id FROM movies LIMIT 1 UNION SELECT id FROM genres",
+    union_nulls: "SELECT NULL AS n UNION SELECT NULL UNION ALL SELECT 1 ORDER BY n",
+    union_numeric: "SELECT 1 UNION ALL SELECT 2.5",
+    union_types: "SELECT id, title FROM movies UNION ALL SELECT name, id FROM genres",
+    union_types_null: "SELECT rating FROM movies WHERE rating IS NULL UNION ALL SELECT 'a'",
+    union_types_null_typed: "SELECT CAST(NULL AS INTEGER) + 1 AS n UNION ALL SELECT id FROM genres",
+    union_column_count: "SELECT id, title FROM movies UNION SELECT id FROM genres",
+    union_aggregate: "SELECT COUNT(*) FROM movies UNION ALL SELECT COUNT(*) FROM genres",
+    union_subquery: "SELECT title FROM movies WHERE genre_id IN (SELECT 2 UNION SELECT 3) ORDER BY title",
+    union_cte: "WITH t AS (SELECT id FROM genres UNION ALL SELECT id FROM studios) SELECT COUNT(*) FROM t",
+    union_with: "WITH t AS (SELECT id FROM genres) SELECT id FROM t UNION SELECT 4 ORDER BY id",
+    union_missing: "SELECT 1 UNION",
+    union_missing_select: "SELECT 1 UNION ALL 2",
+    union_star: "SELECT * FROM genres UNION ALL SELECT * FROM genres WHERE id = 1",
+    intersect: "SELECT genre_id FROM movies INTERSECT SELECT id FROM genres WHERE id > 1 ORDER BY genre_id",
+    intersect_all: "SELECT genre_id AS g FROM movies INTERSECT ALL SELECT genre_id FROM movies WHERE id > 5 ORDER BY g",
+    intersect_nulls: "WITH t AS (SELECT NULL AS n UNION ALL SELECT NULL UNION ALL SELECT 1) SELECT n FROM t INTERSECT SELECT NULL",
+    intersect_numeric: "SELECT 1 INTERSECT SELECT 1.0",
+    intersect_types: "SELECT id FROM genres INTERSECT SELECT name FROM genres",
+    intersect_order_left: "SELECT id FROM genres ORDER BY id INTERSECT SELECT id FROM genres",
+    except: "SELECT id FROM genres EXCEPT SELECT genre_id FROM movies WHERE genre_id > 1 ORDER BY id",
+    except_all: "SELECT genre_id AS g FROM movies EXCEPT ALL SELECT id FROM genres ORDER BY g",
+    except_nulls: "SELECT NULL AS n UNION ALL SELECT NULL UNION ALL SELECT 1 EXCEPT SELECT NULL",
+    except_column_count: "SELECT id, title FROM movies EXCEPT SELECT id FROM genres",
+    set_precedence: "SELECT 1 AS n UNION SELECT 2 INTERSECT SELECT 3 ORDER BY n",
+    set_precedence_except: "SELECT 1 AS n UNION SELECT 2 EXCEPT SELECT 1 ORDER BY n",
+    set_chained: "SELECT 1 AS n UNION ALL SELECT 2 UNION ALL SELECT 3 EXCEPT SELECT 2 INTERSECT SELECT 2 ORDER BY n DESC LIMIT 1",
+}
+test_query! { with [
+        "CREATE TABLE edges (id INTEGER PRIMARY KEY, src INTEGER, dst INTEGER)",
+        "INSERT INTO edges VALUES (1, 1, 2), (2, 2, 3), (3, 3, 4), (4, 2, 5), (5, 6, 7), (6, 4, 2)",
+    ];
+    cte_recursive: "WITH RECURSIVE t (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 5) SELECT * FROM t",
+    cte_recursive_closure: "WITH RECURSIVE reach (src, dst) AS (SELECT src, dst FROM edges UNION SELECT reach.src, edges.dst FROM reach JOIN edges ON reach.dst = edges.src) SELECT * FROM reach ORDER BY src, dst",
+    cte_recursive_cycle: "WITH RECURSIVE t (node) AS (SELECT 1 UNION SELECT dst FROM t JOIN edges ON node = src) SELECT * FROM t ORDER BY node",
+    cte_recursive_depth: "WITH RECURSIVE t (node, depth) AS (SELECT 1, 0 UNION ALL SELECT dst, depth + 1 FROM t JOIN edges ON node = src WHERE depth < 4) SELECT * FROM t ORDER BY depth, node",
+    cte_recursive_empty_seed: "WITH RECURSIVE t (n) AS (SELECT 1 WHERE FALSE UNION ALL SELECT n + 1 FROM t) SELECT * FROM t",
+    cte_recursive_nonrecursive: "WITH RECURSIVE t AS (SELECT id FROM edges WHERE id < 3) SELECT * FROM t",
+    cte_recursive_multiple: "WITH RECURSIVE a (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM a WHERE n < 3), b (n) AS (SELECT n FROM a UNION SELECT n * 10 FROM b WHERE n < 100) SELECT * FROM b ORDER BY n",
+    cte_recursive_self_seed: "WITH RECURSIVE t (n) AS (SELECT * FROM t UNION SELECT n + 1 FROM t) SELECT * FROM t",
+    cte_recursive_self_nonunion: "WITH RECURSIVE t (n) AS (SELECT n FROM t) SELECT * FROM t",
+    cte_recursive_no_reference: "WITH RECURSIVE t (n) AS (SELECT 1 UNION SELECT 2) SELECT * FROM t ORDER BY n",
+    cte_recursive_aggregate: "WITH RECURSIVE t (n) AS (SELECT 1 UNION SELECT MAX(n) + 1 FROM t) SELECT * FROM t",
+    cte_recursive_group_by: "WITH RECURSIVE t (n) AS (SELECT 1 UNION SELECT n + 1 FROM t GROUP BY n) SELECT * FROM t",
+    cte_recursive_window: "WITH RECURSIVE t (n) AS (SELECT 1 UNION SELECT ROW_NUMBER() OVER () FROM t) SELECT * FROM t",
+    cte_recursive_twice: "WITH RECURSIVE t (n) AS (SELECT 1 UNION SELECT a.n + 1 FROM t a JOIN t b ON a.n = b.n) SELECT * FROM t",
+    cte_recursive_subquery: "WITH RECURSIVE t (n) AS (SELECT 1 UNION SELECT id FROM edges WHERE id IN (SELECT n + 1 FROM t)) SELECT * FROM t",
+    cte_recursive_columns: "WITH RECURSIVE t (n) AS (SELECT 1 UNION SELECT n, n FROM t) SELECT * FROM t",
+    cte_recursive_no_union: "WITH RECURSIVE t (n) AS (SELECT 1 UNION) SELECT * FROM t",
+}
 test_query! {
     group_simple: "SELECT studio_id, MAX(rating) FROM movies GROUP BY studio_id ORDER BY studio_id",
     group_noselect: "SELECT MAX(rating) AS best FROM movies GROUP BY studio_id ORDER BY best DESC",
@@ -402,11 +933,108 @@ test_query! {
     group_expr_aggr_nogroupref: "SELECT studio_id, SUM(rating * 10) / COUNT(*) + id FROM movies GROUP BY studio_id ORDER BY studio_id",
     group_expr_multigroup: "SELECT studio_id + genre_id AS multi, MAX(rating) AS rating FROM movies GROUP BY studio_id, genre_id ORDER BY rating, multi",
 
+    group_expr_function: "SELECT SUBSTR(title, 1, 1) AS initial, COUNT(*) FROM movies GROUP BY SUBSTR(title, 1, 1) ORDER BY initial",
+    group_expr_function_ungrouped: "SELECT SUBSTR(title, 1, 1), title FROM movies GROUP BY SUBSTR(title, 1, 1)",
+    group_ungrouped: "SELECT studio_id, title, COUNT(*) FROM movies GROUP BY studio_id",
+    group_ungrouped_qualified: "SELECT m.studio_id, m.title FROM movies m GROUP BY m.studio_id",
+    group_ungrouped_aggregate: "SELECT title, COUNT(*) FROM movies",
+    group_ungrouped_order: "SELECT studio_id, COUNT(*) FROM movies GROUP BY studio_id ORDER BY title",
+    group_null_ultrahd: "SELECT ultrahd, COUNT(*) FROM movies GROUP BY ultrahd ORDER BY ultrahd",
+    group_empty: "SELECT studio_id, COUNT(*) FROM movies WHERE FALSE GROUP BY studio_id",
+    group_empty_global: "SELECT COUNT(*), MAX(rating) FROM movies WHERE FALSE",
+    group_star: "SELECT * FROM movies GROUP BY id",
     having: "SELECT studio_id, MAX(rating) AS rating FROM movies GROUP BY studio_id HAVING rating > 8 ORDER BY studio_id",
     having_aggr: "SELECT studio_id, MAX(rating) FROM movies GROUP BY studio_id HAVING MIN(rating) > 7 ORDER BY studio_id",
     having_aggr_expr: "SELECT studio_id, MAX(rating) FROM movies GROUP BY studio_id HAVING MAX(rating) - MIN(rating) < 1 ORDER BY studio_id",
     having_aggr_nested: "SELECT studio_id, MAX(rating) AS best FROM movies GROUP BY studio_id HAVING MIN(best) > 7 ORDER BY studio_id",
     having_nogroup: "SELECT id, rating FROM movies HAVING rating > 8 ORDER BY id",
     having_noselect: "SELECT studio_id FROM movies GROUP BY studio_id HAVING MAX(rating) > 8 ORDER BY studio_id",
+    having_alias_aggr: "SELECT genre_id, COUNT(*) AS count FROM movies GROUP BY genre_id HAVING count > 2 AND COUNT(*) < 7 ORDER BY genre_id",
+    having_global: "SELECT COUNT(*) FROM movies HAVING COUNT(*) > 5",
+    having_global_empty: "SELECT COUNT(*) FROM movies HAVING COUNT(*) > 50",
+    having_ungrouped: "SELECT studio_id, COUNT(*) FROM movies GROUP BY studio_id HAVING rating > 8",
+    having_star: "SELECT * FROM movies HAVING TRUE",
     having_noaggr: "SELECT studio_id, MAX(rating) AS rating FROM movies GROUP BY studio_id HAVING studio_id >= 3 ORDER BY studio_id",
 }
+
+test_query! { with [
+        "CREATE TABLE departments (id INTEGER PRIMARY KEY, name STRING NOT NULL)",
+        "INSERT INTO departments VALUES (1, 'Engineering'), (2, 'Sales')",
+        "CREATE TABLE employees (
+            id INTEGER PRIMARY KEY,
+            name STRING NOT NULL,
+            department_id INTEGER REFERENCES departments,
+            salary INTEGER
+        )",
+        "INSERT INTO employees VALUES (1, 'Alice', 1, 100), (2, 'Bob', 1, 80), (3, 'Carol', 2, 90), (4, 'Dave', NULL, 70)",
+        "CREATE VIEW staff AS
+            SELECT e.id, e.name, d.name AS department, e.salary
+            FROM employees e JOIN departments d ON e.department_id = d.id",
+        "CREATE VIEW well_paid AS SELECT * FROM staff WHERE salary >= 90",
+        "CREATE VIEW well_paid_names AS SELECT name FROM well_paid ORDER BY name",
+        "CREATE TABLE scratch (id INTEGER PRIMARY KEY)",
+        "CREATE VIEW orphan AS SELECT * FROM scratch",
+        "DROP TABLE scratch",
+    ];
+    view_join: "SELECT * FROM staff",
+    view_nested: "SELECT * FROM well_paid_names",
+    view_alias: "SELECT s.name, s.department FROM staff AS s WHERE s.salary < 90",
+    view_aggregate: "SELECT department, COUNT(*) FROM staff GROUP BY department ORDER BY department",
+    view_join_table: "SELECT w.name, d.id FROM well_paid w JOIN departments d ON w.department = d.name",
+    view_subquery: "SELECT name FROM employees WHERE id IN (SELECT id FROM well_paid)",
+    view_cte_shadow: "WITH employees AS (SELECT 1 AS id) SELECT name FROM staff, employees WHERE staff.id = employees.id",
+    view_dropped_table: "SELECT * FROM orphan",
+}
+test_query! { with [
+        "CREATE TABLE people (id INTEGER PRIMARY KEY, name STRING NOT NULL, manager_id INTEGER REFERENCES people)",
+        "INSERT INTO people VALUES (1, 'Alice', NULL), (2, 'Bob', 1), (3, 'Carol', 1), (4, 'Dave', 3), (5, 'Eve', 4)",
+    ];
+    join_self: "SELECT e.name, m.name FROM people e JOIN people m ON e.manager_id = m.id ORDER BY e.id",
+    join_self_as: "SELECT e.name AS employee, m.name AS manager FROM people AS e JOIN people AS m ON e.manager_id = m.id WHERE m.name = 'Alice' ORDER BY employee",
+    join_self_left: "SELECT e.id, e.name, m.id, m.name FROM people e LEFT JOIN people m ON e.manager_id = m.id ORDER BY e.id",
+    join_self_chain: "SELECT e.name, m.name, g.name FROM people e JOIN people m ON e.manager_id = m.id JOIN people g ON m.manager_id = g.id ORDER BY e.id",
+    join_self_where: "SELECT e.name FROM people e, people m WHERE e.manager_id = m.id AND m.manager_id IS NULL ORDER BY e.name",
+    join_self_ambiguous: "SELECT name FROM people e JOIN people m ON e.manager_id = m.id",
+    join_self_ambiguous_on: "SELECT e.name FROM people e JOIN people m ON manager_id = m.id",
+    join_self_table_name: "SELECT people.name FROM people e JOIN people m ON e.manager_id = m.id",
+    join_self_unaliased: "SELECT * FROM people JOIN people ON people.manager_id = people.id",
+}
+test_query! { with [
+        r#"CREATE TABLE features (
+            id INTEGER PRIMARY KEY,
+            name STRING NOT NULL UNIQUE INDEX,
+            "quoted name" STRING DEFAULT 'it''s',
+            price DECIMAL(10, 2) NOT NULL DEFAULT 1.5,
+            total DECIMAL(12, 2) GENERATED ALWAYS AS (price * 2) STORED,
+            ratio FLOAT DEFAULT 0.5,
+            active BOOLEAN DEFAULT TRUE,
+            created DATE DEFAULT DATE '2024-01-31',
+            data BYTEA DEFAULT x'00ff',
+            parent_id INTEGER INDEX REFERENCES features
+        )"#,
+        "CREATE TABLE pairs (a INTEGER, b STRING, PRIMARY KEY (a, b))",
+    ];
+    show_create_table: "SHOW CREATE TABLE features",
+    show_create_table_composite: "SHOW CREATE TABLE pairs",
+    show_create_table_missing: "SHOW CREATE TABLE missing",
+    show_create_table_bare: "SHOW CREATE TABLE",
+    show_tables: "SHOW TABLES",
+    system_tables: "SELECT name FROM system.tables WHERE name < 'm'",
+    system_columns: "SELECT * FROM system.columns WHERE table_name = 'features'",
+    system_columns_alias: "SELECT c.column_name, c.data_type FROM system.columns AS c WHERE c.table_name = 'pairs' AND c.is_primary_key",
+    system_indexes: "SELECT * FROM system.indexes",
+    system_join: "SELECT i.table_name, i.column_name, c.data_type FROM system.indexes i JOIN system.columns c ON i.table_name = c.table_name AND i.column_name = c.column_name",
+    system_join_table: "SELECT g.name, c.column_name FROM genres g JOIN system.columns c ON c.table_name = 'genres' WHERE g.id = 1",
+    system_aggregate: "SELECT table_name, COUNT(*) FROM system.columns GROUP BY table_name ORDER BY table_name",
+    system_missing: "SELECT * FROM system.missing",
+    system_schema_missing: "SELECT * FROM other.tables",
+}
+test_query! {
+    check_join_ambiguous: "SELECT id FROM movies a JOIN movies b ON a.id = b.id",
+    check_order_like: "SELECT id FROM movies ORDER BY released LIKE 'a'",
+    check_where_compare: "SELECT * FROM movies WHERE released = 'a'",
+    check_where_null: "SELECT id FROM movies WHERE released = NULL + 1",
+    check_join_predicate: "SELECT * FROM movies m JOIN genres g ON m.id",
+    check_subquery_compare: "SELECT id FROM movies WHERE released = (SELECT 'a')",
+    check_subquery_inner: "SELECT (SELECT id + TRUE FROM movies LIMIT 1)",
+}
@@ -1,7 +1,10 @@
 ///! Schema-related tests, using an in-memory database against golden files in tests/sql/chema/
-use toydb::error::Result;
-use toydb::sql::engine::{Engine as _, Mode, Transaction as _};
-use toydb::sql::schema::Catalog as _;
+use super::super::assert_rows;
+use toydb::error::{Error, Result};
+use toydb::sql::engine::{Engine, Mode, Transaction as _, ANONYMOUS_USER};
+use toydb::sql::execution::ResultSet;
+use toydb::sql::schema::{Catalog as _, View};
+use toydb::sql::types::Value;
 
 use goldenfile::Mint;
 use std::io::Write;
@@ -17,42 +20,64 @@ macro_rules! test_schema {
             #[test]
             fn $name() -> Result<()> {
                 let setup: &[&str] = &$setup;
-                let engine = super::setup(setup.into())?;
-                let mut mint = Mint::new("tests/sql/schema");
-                let mut f = mint.new_goldenfile(stringify!($name))?;
-
-                write!(f, "Query: {}\n", $query.trim())?;
-                match engine.session()?.execute($query) {
-                    Ok(result) => write!(f, "Result: {:?}\n\n", result)?,
-                    Err(err) => write!(f, "Error: {:?}\n\n", err)?,
-                };
-
-                write!(f, "Storage:")?;
-                let txn = engine.begin(Mode::ReadWrite)?;
-                for table in txn.scan_tables()? {
-                    write!(f, "\n{}\n", table)?;
-                    for row in txn.scan(&table.name, None)? {
-                        write!(f, "{:?}\n", row?)?;
-                    }
-
-                    for column in table.columns.iter().filter(|c| c.index) {
-                        write!(f, "\nIndex {}.{}\n", table.name, column.name)?;
-                        let mut scan = txn.scan_index(&table.name, &column.name)?;
-                        while let Some((value, pks)) = scan.next().transpose()? {
-                            let mut pks = pks.into_iter().collect::<Vec<_>>();
-                            pks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-                            write!(f, "{:?} => {:?}\n", value, pks)?;
-                        }
-                    }
-                }
-                txn.rollback()?;
+                let mut output = Vec::new();
+                run(super::setup(setup.into())?, $query, &mut output)?;
+                let mut raft_output = Vec::new();
+                run(super::setup_raft(setup.into())?, $query, &mut raft_output)?;
+                assert_eq!(
+                    String::from_utf8_lossy(&raft_output),
+                    String::from_utf8_lossy(&output),
+                    "Raft engine results differ from local engine"
+                );
 
+                let mut mint = Mint::new("tests/sql/schema");
+                mint.new_goldenfile(stringify!($name))?.write_all(&output)?;
                 Ok(())
             }
         )*
     }
 }
 
+/// Runs a schema statement against the engine, writing its result and the resulting storage
+/// contents to f.
+fn run<E: Engine + 'static>(engine: E, query: &str, f: &mut Vec<u8>) -> Result<()>
+where
+    E::Transaction: Send + 'static,
+{
+    write!(f, "Query: {}\n", query.trim())?;
+    match engine.session()?.execute(query) {
+        Ok(result) => write!(f, "Result: {:?}\n\n", result)?,
+        Err(err) => write!(f, "Error: {:?}\n\n", err)?,
+    };
+
+    write!(f, "Storage:")?;
+    let txn = engine.begin(Mode::ReadWrite)?;
+    for table in txn.scan_tables()? {
+        write!(f, "\n{}\n", table)?;
+        for row in txn.scan(&table.name, None)? {
+            write!(f, "{:?}\n", row?)?;
+        }
+
+        for column in table.columns.iter().filter(|c| c.index) {
+            write!(f, "\nIndex {}.{}\n", table.name, column.name)?;
+            let mut scan = txn.scan_index(&table.name, &column.name)?;
+            while let Some((value, pks)) = scan.next().transpose()? {
+                let mut pks = pks.into_iter().collect::<Vec<_>>();
+                pks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                write!(f, "{:?} => {:?}\n", value, pks)?;
+            }
+        }
+    }
+    for view in txn.scan_views()? {
+        write!(f, "\n{}\n", view)?;
+    }
+    for user in txn.scan_users()? {
+        write!(f, "\n{:?}\n", user)?;
+    }
+    txn.rollback()?;
+    Ok(())
+}
+
 test_schema! {
     create_table_bare: "CREATE TABLE",
     create_table_datatype: r#"
@@ -70,6 +95,15 @@ test_schema! {
             "varchar" VARCHAR
         )
     "#,
+    create_table_datatype_temporal: "CREATE TABLE name (id INTEGER PRIMARY KEY, d DATE, t TIME, ts TIMESTAMP)",
+    create_table_datatype_decimal: "CREATE TABLE name (id INTEGER PRIMARY KEY, a DECIMAL(10, 2), b NUMERIC(5), c DECIMAL, d DECIMAL(38, 38))",
+    create_table_datatype_decimal_precision_zero: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(0, 0))",
+    create_table_datatype_decimal_precision_large: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(39, 2))",
+    create_table_datatype_decimal_precision_overflow: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(256, 2))",
+    create_table_datatype_decimal_scale_large: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(5, 6))",
+    create_table_datatype_decimal_empty: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL())",
+    create_table_datatype_decimal_pk: "CREATE TABLE name (id DECIMAL(10, 2) PRIMARY KEY)",
+    create_table_datatype_bytea: "CREATE TABLE name (id INTEGER PRIMARY KEY, a BYTEA, b BLOB)",
     create_table_datatype_missing: "CREATE TABLE name (id)",
     create_table_datatype_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value NULL)",
 
@@ -96,6 +130,15 @@ test_schema! {
     create_table_pk_nullable: "CREATE TABLE name (id INTEGER PRIMARY KEY NULL)",
     create_table_pk_default: "CREATE TABLE name (id INTEGER PRIMARY KEY DEFAULT 1)",
     create_table_pk_unique: "CREATE TABLE name (id INTEGER PRIMARY KEY UNIQUE)",
+    create_table_pk_composite: "CREATE TABLE name (a INTEGER, b STRING INDEX, c FLOAT, PRIMARY KEY (a, b))",
+    create_table_pk_composite_single: "CREATE TABLE name (id INTEGER, value STRING, PRIMARY KEY (id))",
+    create_table_pk_composite_order: "CREATE TABLE name (a INTEGER, b STRING, PRIMARY KEY (b, a))",
+    create_table_pk_composite_missing: "CREATE TABLE name (a INTEGER, b STRING, PRIMARY KEY (a, c))",
+    create_table_pk_composite_duplicate: "CREATE TABLE name (a INTEGER, b STRING, PRIMARY KEY (a, a))",
+    create_table_pk_composite_inline: "CREATE TABLE name (a INTEGER PRIMARY KEY, b STRING, PRIMARY KEY (a, b))",
+    create_table_pk_composite_multiple: "CREATE TABLE name (a INTEGER, b STRING, PRIMARY KEY (a), PRIMARY KEY (b))",
+    create_table_pk_composite_nullable: "CREATE TABLE name (a INTEGER NULL, b STRING, PRIMARY KEY (a, b))",
+    create_table_pk_composite_empty: "CREATE TABLE name (a INTEGER, b STRING, PRIMARY KEY ())",
 
     create_table_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING NULL)",
     create_table_null_not: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING NOT NULL)",
@@ -108,10 +151,32 @@ test_schema! {
     create_table_default_string: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING DEFAULT 'foo')",
     create_table_default_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING DEFAULT NULL)",
     create_table_default_null_not: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING NOT NULL DEFAULT NULL)",
+    create_table_default_not_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING DEFAULT 'foo' NOT NULL)",
+    create_table_default_date: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DATE DEFAULT DATE '2024-01-31')",
+    create_table_default_timestamp: "CREATE TABLE name (id INTEGER PRIMARY KEY, value TIMESTAMP DEFAULT TIMESTAMP '2024-01-31 12:30:00')",
     create_table_default_expr: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER DEFAULT 1 + 2 * 3)",
     create_table_default_conflict: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING DEFAULT 7)",
     create_table_default_conflict_float_integer: "CREATE TABLE name (id INTEGER PRIMARY KEY, value FLOAT DEFAULT 7)",
+    create_table_default_conflict_date_string: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DATE DEFAULT '2024-01-31')",
+    create_table_default_decimal: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(10, 2) DEFAULT DECIMAL '1.5')",
+    create_table_default_decimal_integer: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(10, 2) DEFAULT 7)",
+    create_table_default_decimal_float: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(10, 2) DEFAULT 3.145)",
+    create_table_default_decimal_range: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(3, 2) DEFAULT 10)",
+    create_table_default_conflict_decimal_string: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(10, 2) DEFAULT '1.5')",
+    create_table_default_bytea: "CREATE TABLE name (id INTEGER PRIMARY KEY, value BYTEA DEFAULT x'00ff')",
+    create_table_default_conflict_bytea_string: "CREATE TABLE name (id INTEGER PRIMARY KEY, value BYTEA DEFAULT '00ff')",
     create_table_default_conflict_integer_float: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER DEFAULT 3.14)",
+    create_table_generated: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER, doubled INTEGER NOT NULL GENERATED ALWAYS AS ((value + 1) * 2) STORED INDEX)",
+    create_table_generated_bare: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER, doubled INTEGER GENERATED ALWAYS AS value * 2 STORED)",
+    create_table_generated_virtual: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER, doubled INTEGER GENERATED ALWAYS AS (value * 2))",
+    create_table_generated_default: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER, doubled INTEGER DEFAULT 0 GENERATED ALWAYS AS (value * 2) STORED)",
+    create_table_generated_pk: "CREATE TABLE name (id INTEGER PRIMARY KEY GENERATED ALWAYS AS (1) STORED)",
+    create_table_generated_missing: "CREATE TABLE name (id INTEGER PRIMARY KEY, doubled INTEGER GENERATED ALWAYS AS (missing * 2) STORED)",
+    create_table_generated_now: "CREATE TABLE name (id INTEGER PRIMARY KEY, created TIMESTAMP GENERATED ALWAYS AS (NOW()) STORED)",
+    create_table_generated_subquery: "CREATE TABLE name (id INTEGER PRIMARY KEY, value INTEGER GENERATED ALWAYS AS ((SELECT 1)) STORED)",
+    create_table_generated_generated: "CREATE TABLE name (id INTEGER PRIMARY KEY, a INTEGER GENERATED ALWAYS AS (id * 2) STORED, b INTEGER GENERATED ALWAYS AS (a * 2) STORED)",
+    create_table_generated_conflict: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING GENERATED ALWAYS AS (id * 2) STORED)",
+    create_table_generated_decimal: "CREATE TABLE name (id INTEGER PRIMARY KEY, value DECIMAL(10, 2) GENERATED ALWAYS AS (id / 3.0) STORED)",
 
     create_table_index: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING INDEX)",
     create_table_index_pk: "CREATE TABLE name (id INTEGER PRIMARY KEY INDEX, value STRING)",
@@ -121,6 +186,18 @@ test_schema! {
     create_table_unique_not_null: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING NOT NULL UNIQUE)",
     create_table_unique_default: "CREATE TABLE name (id INTEGER PRIMARY KEY, value STRING DEFAULT 'foo' UNIQUE)",
 }
+test_schema! { with [
+        "CREATE TABLE IF NOT EXISTS test (id INTEGER PRIMARY KEY, value STRING)",
+        "INSERT INTO test VALUES (1, 'a')",
+        "CREATE TABLE IF NOT EXISTS test (id INTEGER PRIMARY KEY, value STRING)",
+    ];
+    create_table_if_not_exists: "CREATE TABLE IF NOT EXISTS test (id INTEGER PRIMARY KEY, value STRING)",
+    create_table_if_not_exists_mismatch: "CREATE TABLE IF NOT EXISTS test (id STRING PRIMARY KEY)",
+    create_table_if_not_exists_invalid: "CREATE TABLE IF NOT EXISTS test (id STRING)",
+    create_table_if_not_exists_new: "CREATE TABLE IF NOT EXISTS other (id INTEGER PRIMARY KEY)",
+    create_table_if_not: "CREATE TABLE IF NOT test (id INTEGER PRIMARY KEY)",
+    create_table_if_exists: "CREATE TABLE IF EXISTS test (id INTEGER PRIMARY KEY)",
+}
 test_schema! { with ["CREATE TABLE test (id INTEGER PRIMARY KEY)"];
     create_table_exists: "CREATE TABLE test (id INTEGER PRIMARY KEY)",
 
@@ -134,9 +211,47 @@ test_schema! { with ["CREATE TABLE test (id INTEGER PRIMARY KEY)"];
     create_table_ref_missing: "CREATE TABLE other (id INTEGER PRIMARY KEY, missing_id INTEGER REFERENCES missing)",
     create_table_ref_type: "CREATE TABLE other (id INTEGER PRIMARY KEY, test_id STRING REFERENCES test)",
     create_table_ref_self: "CREATE TABLE other (id INTEGER PRIMARY KEY, self_id INTEGER REFERENCES other)",
+    create_table_ref_composite: "CREATE TABLE other (a INTEGER, b INTEGER REFERENCES other, PRIMARY KEY (a, b))",
     create_table_ref_self_type: "CREATE TABLE other (id INTEGER PRIMARY KEY, self_id STRING REFERENCES other)",
 }
 
+test_schema! { with [
+        "CREATE TABLE target (id INTEGER PRIMARY KEY, value STRING INDEX)",
+        "INSERT INTO target VALUES (1, 'a'), (2, 'b')",
+        "CREATE TABLE source (id INTEGER PRIMARY KEY, target_id INTEGER INDEX REFERENCES target)",
+        "INSERT INTO source VALUES (1, 1), (2, 2), (3, NULL)",
+        "CREATE TABLE self (id INTEGER PRIMARY KEY, self_id INTEGER REFERENCES self)",
+        "INSERT INTO self VALUES (1, 1), (2, 1)",
+    ];
+    alter_table_add_column: "ALTER TABLE target ADD COLUMN flag BOOLEAN NOT NULL DEFAULT FALSE",
+    alter_table_add_column_exists: "ALTER TABLE target ADD COLUMN value STRING",
+    alter_table_add_column_implicit: "ALTER TABLE target ADD flag BOOLEAN",
+    alter_table_add_column_index: "ALTER TABLE target ADD COLUMN code INTEGER INDEX DEFAULT 7",
+    alter_table_add_column_not_null: "ALTER TABLE target ADD COLUMN flag BOOLEAN NOT NULL",
+    alter_table_add_column_pk: "ALTER TABLE target ADD COLUMN pk INTEGER PRIMARY KEY",
+    alter_table_add_column_ref: "ALTER TABLE source ADD COLUMN other_id INTEGER REFERENCES target DEFAULT 2",
+    alter_table_add_column_ref_missing: "ALTER TABLE source ADD COLUMN other_id INTEGER REFERENCES target DEFAULT 9",
+    alter_table_add_column_unique: "ALTER TABLE target ADD COLUMN code INTEGER UNIQUE DEFAULT 7",
+    alter_table_bad_action: "ALTER TABLE target MODIFY value",
+    alter_table_bare: "ALTER TABLE",
+    alter_table_drop_column: "ALTER TABLE source DROP COLUMN target_id",
+    alter_table_drop_column_implicit: "ALTER TABLE target DROP value",
+    alter_table_drop_column_missing: "ALTER TABLE target DROP COLUMN missing",
+    alter_table_drop_column_pk: "ALTER TABLE target DROP COLUMN id",
+    alter_table_missing: "ALTER TABLE missing RENAME TO name",
+    alter_table_rename: "ALTER TABLE target RENAME TO renamed",
+    alter_table_rename_bare: "ALTER TABLE target RENAME TO",
+    alter_table_rename_exists: "ALTER TABLE target RENAME TO source",
+    alter_table_rename_self: "ALTER TABLE self RENAME TO renamed",
+    alter_table_rename_column: "ALTER TABLE target RENAME COLUMN value TO renamed",
+    alter_table_rename_column_bare: "ALTER TABLE target RENAME COLUMN value",
+    alter_table_rename_column_exists: "ALTER TABLE target RENAME COLUMN value TO id",
+    alter_table_rename_column_implicit: "ALTER TABLE target RENAME value TO renamed",
+    alter_table_rename_column_missing: "ALTER TABLE target RENAME COLUMN missing TO renamed",
+    alter_table_rename_column_pk: "ALTER TABLE target RENAME COLUMN id TO pk",
+    alter_table_rename_column_ref: "ALTER TABLE source RENAME COLUMN target_id TO ref_id",
+}
+
 test_schema! { with [
         "CREATE TABLE a (id INTEGER PRIMARY KEY)",
         "INSERT INTO a VALUES (11), (12), (13)",
@@ -149,6 +264,10 @@ test_schema! { with [
     drop_table_bare: "DROP TABLE",
     drop_table_missing: "DROP TABLE name",
     drop_table_multiple: "DROP TABLE a, c",
+    drop_table_if_exists: "DROP TABLE IF EXISTS a",
+    drop_table_if_exists_missing: "DROP TABLE IF EXISTS name",
+    drop_table_if_exists_bare: "DROP TABLE IF EXISTS",
+    drop_table_if_not_exists: "DROP TABLE IF NOT EXISTS a",
 }
 test_schema! { with [
         "CREATE TABLE target (id INTEGER PRIMARY KEY)",
@@ -215,6 +334,17 @@ test_schema! { with [
     insert_string_float: r#"INSERT INTO types (id, "string") VALUES (0, 3.14)"#,
     insert_string_integer: r#"INSERT INTO types (id, "string") VALUES (0, 1)"#,
 }
+test_schema! { with [
+        "CREATE TABLE blobs (id INTEGER PRIMARY KEY, value BYTEA)",
+    ];
+    insert_bytea: "INSERT INTO blobs VALUES (0, x'DEADBEEF')",
+    insert_bytea_empty: "INSERT INTO blobs VALUES (0, x'')",
+    insert_bytea_null: "INSERT INTO blobs VALUES (0, NULL)",
+    insert_bytea_1024: &format!("INSERT INTO blobs VALUES (0, x'{}')", "ff".repeat(1024)),
+    insert_bytea_1025: &format!("INSERT INTO blobs VALUES (0, x'{}')", "ff".repeat(1025)),
+    insert_bytea_string: "INSERT INTO blobs VALUES (0, 'DEADBEEF')",
+    insert_bytea_integer: "INSERT INTO blobs VALUES (0, 1)",
+}
 test_schema! { with [
         "CREATE TABLE test (id INTEGER PRIMARY KEY, value INTEGER)",
         "INSERT INTO test VALUES (1, 7)",
@@ -300,6 +430,88 @@ test_schema! { with [
     insert_nulls_required: r#"INSERT INTO nulls (id, "null", not_null, "default") VALUES (1, NULL, NULL, NULL)"#,
 }
 
+test_schema! { with [
+    r#"CREATE TABLE nulls (
+        id INTEGER PRIMARY KEY,
+        "null" BOOLEAN NULL,
+        not_null BOOLEAN NOT NULL INDEX,
+        not_null_default BOOLEAN NOT NULL DEFAULT FALSE
+    )"#,
+    r#"INSERT INTO nulls VALUES (1, TRUE, TRUE, TRUE), (2, NULL, FALSE, FALSE)"#];
+    insert_nulls_required_missing: r#"INSERT INTO nulls (id, "null") VALUES (3, NULL)"#,
+    insert_nulls_required_default: r#"INSERT INTO nulls (id, not_null) VALUES (3, TRUE)"#,
+    insert_nulls_required_default_null: r#"INSERT INTO nulls VALUES (3, NULL, TRUE, NULL)"#,
+    insert_nulls_required_select: r#"INSERT INTO nulls SELECT id + 10, "null", "null", TRUE FROM nulls"#,
+    insert_nulls_required_conflict: r#"INSERT INTO nulls VALUES (1, NULL, FALSE, FALSE) ON CONFLICT DO UPDATE SET not_null = excluded."null""#,
+    update_nulls: r#"UPDATE nulls SET "null" = NULL WHERE id = 1"#,
+    update_nulls_required: r#"UPDATE nulls SET not_null = NULL WHERE id = 1"#,
+    update_nulls_required_expr: r#"UPDATE nulls SET not_null = "null""#,
+    update_nulls_required_pk: r#"UPDATE nulls SET id = 3, not_null_default = NULL WHERE id = 1"#,
+}
+
+test_schema! { with [
+    r#"CREATE TABLE nulls (id INTEGER PRIMARY KEY, not_null BOOLEAN NOT NULL)"#,
+    r#"INSERT INTO nulls VALUES (1, TRUE)"#,
+    r#"ALTER TABLE nulls RENAME COLUMN not_null TO required"#,
+    r#"ALTER TABLE nulls RENAME TO renamed"#];
+    insert_nulls_required_renamed: r#"INSERT INTO renamed VALUES (2, NULL)"#,
+    update_nulls_required_renamed: r#"UPDATE renamed SET required = NULL"#,
+}
+
+test_schema! { with [
+    "CREATE TABLE items (id INTEGER PRIMARY KEY, name STRING, price INTEGER)",
+    "INSERT INTO items VALUES (1, 'a', 10), (2, 'b', 20)",
+    "CREATE VIEW cheap AS SELECT id, name FROM items WHERE price < 15"];
+    create_view: "CREATE VIEW expensive AS SELECT * FROM items WHERE price >= 15",
+    create_view_bare: "CREATE VIEW",
+    create_view_exists: "CREATE VIEW cheap AS SELECT 1",
+    create_view_invalid: "CREATE VIEW invalid AS SELECT * FROM missing",
+    create_view_nested: "CREATE VIEW cheap_names AS SELECT name FROM cheap",
+    create_view_not_query: "CREATE VIEW invalid AS INSERT INTO items VALUES (3, 'c', 30)",
+    create_view_quoted: r#"CREATE VIEW "my view" AS SELECT 'it''s'"#,
+    create_view_table_exists: "CREATE VIEW items AS SELECT 1",
+    create_table_view_exists: "CREATE TABLE cheap (id INTEGER PRIMARY KEY)",
+    alter_table_rename_view_exists: "ALTER TABLE items RENAME TO cheap",
+    drop_view: "DROP VIEW cheap",
+    drop_view_bare: "DROP VIEW",
+    drop_view_if_exists: "DROP VIEW IF EXISTS missing",
+    drop_view_missing: "DROP VIEW missing",
+    drop_view_table: "DROP VIEW items",
+    drop_table_view: "DROP TABLE cheap",
+    delete_view: "DELETE FROM cheap",
+    insert_view: "INSERT INTO cheap VALUES (3, 'c')",
+    update_view: "UPDATE cheap SET name = 'x'",
+}
+
+test_schema! { with [
+    "CREATE TABLE items (id INTEGER PRIMARY KEY, name STRING)",
+    "CREATE TABLE other (id INTEGER PRIMARY KEY)",
+    "CREATE USER admin SUPERUSER",
+    "CREATE USER alice",
+    "GRANT SELECT ON items TO alice"];
+    create_user: "CREATE USER bob",
+    create_user_bare: "CREATE USER",
+    create_user_exists: "CREATE USER alice",
+    create_user_superuser: "CREATE USER bob SUPERUSER",
+    drop_user: "DROP USER alice",
+    drop_user_bare: "DROP USER",
+    drop_user_if_exists: "DROP USER IF EXISTS missing",
+    drop_user_missing: "DROP USER missing",
+    grant: "GRANT INSERT, UPDATE ON TABLE items TO alice",
+    grant_all: "GRANT ALL PRIVILEGES ON items TO alice",
+    grant_existing: "GRANT SELECT ON items TO alice",
+    grant_invalid: "GRANT DROP ON items TO alice",
+    grant_missing_table: "GRANT SELECT ON missing TO alice",
+    grant_missing_user: "GRANT SELECT ON items TO missing",
+    grant_other: "GRANT DELETE ON other TO alice",
+    revoke: "REVOKE SELECT ON items FROM alice",
+    revoke_all: "REVOKE ALL ON items FROM alice",
+    revoke_missing: "REVOKE DELETE ON other FROM alice",
+    revoke_to: "REVOKE SELECT ON items TO alice",
+    drop_table_privileges: "DROP TABLE items",
+    alter_table_rename_privileges: "ALTER TABLE items RENAME TO things",
+}
+
 test_schema! { with [
     r#"CREATE TABLE defaults (
         id INTEGER PRIMARY KEY,
@@ -313,10 +525,59 @@ test_schema! { with [
     insert_default: "INSERT INTO defaults (id, required) VALUES (1, TRUE)",
     insert_default_unnamed: "INSERT INTO defaults VALUES (1, TRUE)",
     insert_default_missing: "INSERT INTO defaults (id) VALUES (1)",
+    insert_default_missing_unnamed: "INSERT INTO defaults VALUES (1)",
     insert_default_override: "INSERT INTO defaults VALUES (1, TRUE, TRUE, FALSE, 2.718, 3, 'bar')",
     insert_default_override_null: "INSERT INTO defaults VALUES (1, TRUE, NULL, NULL, NULL, NULL, NULL)",
 }
 
+test_schema! { with [
+    r#"CREATE TABLE defaults_expr (
+        id INTEGER PRIMARY KEY,
+        "integer" INTEGER DEFAULT 1 + 2 * 3,
+        "string" STRING DEFAULT CONCAT(UPPER('foo'), 'bar'),
+        required BOOLEAN NOT NULL DEFAULT NOT FALSE
+    )"#];
+    insert_default_expr: "INSERT INTO defaults_expr (id) VALUES (1)",
+    insert_default_expr_unnamed: "INSERT INTO defaults_expr VALUES (1)",
+    insert_default_expr_override: "INSERT INTO defaults_expr VALUES (1, 2, 'baz', FALSE)",
+}
+
+test_schema! { with [
+        r#"CREATE TABLE products (
+            id INTEGER PRIMARY KEY,
+            note STRING,
+            name STRING DEFAULT UPPER('x'),
+            price FLOAT NOT NULL DEFAULT 100.0 * 2,
+            price_with_tax FLOAT GENERATED ALWAYS AS (price * 1.25) STORED,
+            label STRING GENERATED ALWAYS AS (CONCAT(LOWER(name), '!')) STORED INDEX
+        )"#,
+        "INSERT INTO products VALUES (1, NULL, 'A', 10.0)",
+    ];
+    insert_generated_partial: "INSERT INTO products (id) VALUES (2)",
+    insert_generated_unnamed: "INSERT INTO products VALUES (2, 'note', 'B', 20.0)",
+    insert_generated_direct: "INSERT INTO products (id, price_with_tax) VALUES (2, 1.0)",
+    insert_generated_direct_unnamed: "INSERT INTO products VALUES (2, NULL, 'B', 20.0, 25.0)",
+    insert_generated_conflict: "INSERT INTO products VALUES (1, NULL, 'B', 20.0) ON CONFLICT DO UPDATE SET price = excluded.price_with_tax",
+    insert_generated_conflict_direct: "INSERT INTO products VALUES (1, NULL, 'B', 20.0) ON CONFLICT DO UPDATE SET label = 'x'",
+    insert_generated_returning: "INSERT INTO products (id, price) VALUES (2, 4.0) RETURNING price_with_tax, label",
+    update_generated: "UPDATE products SET price = 40.0 WHERE id = 1",
+    update_generated_other: "UPDATE products SET name = 'Z'",
+    update_generated_unreferenced: "UPDATE products SET note = 'note'",
+    update_generated_direct: "UPDATE products SET price_with_tax = 1.0",
+    alter_table_add_column_generated: "ALTER TABLE products ADD COLUMN doubled FLOAT GENERATED ALWAYS AS (price * 2.0) STORED",
+    alter_table_drop_column_generated: "ALTER TABLE products DROP COLUMN price_with_tax",
+    alter_table_drop_column_generated_ref: "ALTER TABLE products DROP COLUMN price",
+    alter_table_rename_column_generated_ref: "ALTER TABLE products RENAME COLUMN price TO cost",
+}
+
+test_schema! { with [
+        "CREATE TABLE products (id INTEGER PRIMARY KEY, note STRING, price FLOAT, price_with_tax FLOAT GENERATED ALWAYS AS (price * 1.25) STORED)",
+        "ALTER TABLE products DROP COLUMN note",
+        "ALTER TABLE products RENAME COLUMN price TO cost",
+    ];
+    insert_generated_altered: "INSERT INTO products VALUES (1, 8.0)",
+}
+
 test_schema! { with [
         r#"CREATE TABLE "unique" (
             id INTEGER PRIMARY KEY,
@@ -423,6 +684,22 @@ test_schema! { with [
     update_ref_self_self: "UPDATE self SET self_id = 2 WHERE id = 2",
 }
 
+test_schema! { with [
+        "CREATE TABLE target (id INTEGER PRIMARY KEY, value STRING)",
+        "INSERT INTO target VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+        "CREATE TABLE source (id INTEGER PRIMARY KEY, target_id INTEGER INDEX REFERENCES target)",
+        "INSERT INTO source VALUES (1, 1), (2, 2), (4, NULL)",
+        "CREATE TABLE self (id INTEGER PRIMARY KEY, self_id INTEGER INDEX REFERENCES self)",
+        "INSERT INTO self VALUES (1, 1), (2, 1), (3, 3)",
+    ];
+    delete_ref_index_conflict: "DELETE FROM target WHERE id = 1",
+    delete_ref_index_noref: "DELETE FROM target WHERE id = 3",
+    delete_ref_index_self: "DELETE FROM self WHERE id = 3",
+    delete_ref_index_self_conflict: "DELETE FROM self WHERE id = 1",
+    update_ref_index_pk: "UPDATE target SET id = 9 WHERE id = 2",
+    update_ref_index_source_missing: "UPDATE source SET target_id = 9 WHERE id = 4",
+}
+
 test_schema! { with [
         "CREATE TABLE test (id INTEGER PRIMARY KEY, name STRING INDEX, value INTEGER)",
         "INSERT INTO test VALUES (1, 'a', 101), (2, 'b', 102), (3, 'b', 103)",
@@ -444,3 +721,250 @@ test_schema! { with [
     update_index_pk: "UPDATE test SET id = 4 WHERE id = 1",
     update_index_null: "UPDATE test SET name = NULL WHERE id = 3",
 }
+
+/// Foreign key checks can't see uncommitted references from concurrent transactions, so writes
+/// that add a reference must conflict with concurrent writes to the referenced row.
+#[test]
+fn ref_concurrent() -> Result<()> {
+    let engine = super::setup(vec![
+        "CREATE TABLE target (id INTEGER PRIMARY KEY)",
+        "INSERT INTO target VALUES (1), (2), (3)",
+        "CREATE TABLE source (id INTEGER PRIMARY KEY, target_id INTEGER REFERENCES target)",
+    ])?;
+    let mut a = engine.session()?;
+    let mut b = engine.session()?;
+
+    // An uncommitted reference blocks deletion of the target.
+    a.execute("BEGIN")?;
+    a.execute("INSERT INTO source VALUES (1, 1)")?;
+    assert_eq!(b.execute("DELETE FROM target WHERE id = 1"), Err(Error::Serialization));
+    a.execute("COMMIT")?;
+    assert_eq!(
+        b.execute("DELETE FROM target WHERE id = 1"),
+        Err(Error::Value("Primary key 1 is referenced by table source column target_id".into()))
+    );
+
+    // An uncommitted deletion blocks references to the target.
+    a.execute("BEGIN")?;
+    a.execute("DELETE FROM target WHERE id = 2")?;
+    assert_eq!(b.execute("INSERT INTO source VALUES (2, 2)"), Err(Error::Serialization));
+    assert_eq!(
+        b.execute("UPDATE source SET target_id = 2 WHERE id = 1"),
+        Err(Error::Serialization)
+    );
+    a.execute("COMMIT")?;
+
+    // A deletion committed after the transaction began blocks references to the target, even
+    // though the target is still visible in the transaction's snapshot.
+    b.execute("BEGIN")?;
+    a.execute("DELETE FROM target WHERE id = 3")?;
+    assert_eq!(b.execute("INSERT INTO source VALUES (3, 3)"), Err(Error::Serialization));
+    b.execute("ROLLBACK")?;
+
+    // Concurrent transactions that don't change references don't conflict with the target.
+    a.execute("BEGIN")?;
+    b.execute("BEGIN")?;
+    a.execute("UPDATE source SET id = 9 WHERE id = 1")?;
+    b.execute("INSERT INTO target VALUES (4)")?;
+    a.execute("COMMIT")?;
+    b.execute("COMMIT")?;
+
+    Ok(())
+}
+
+/// Renames must be visible to later statements in the same transaction and to later transactions
+/// in other sessions, while concurrent transactions keep seeing the old schema.
+#[test]
+fn alter_table_rename_sessions() -> Result<()> {
+    let engine = super::setup(vec![
+        "CREATE TABLE target (id INTEGER PRIMARY KEY, value STRING INDEX)",
+        "INSERT INTO target VALUES (1, 'a'), (2, 'b')",
+    ])?;
+    let mut a = engine.session()?;
+    let mut b = engine.session()?;
+
+    b.execute("BEGIN")?;
+    a.execute("BEGIN")?;
+    a.execute("ALTER TABLE target RENAME TO renamed")?;
+    a.execute("ALTER TABLE renamed RENAME COLUMN value TO name")?;
+    assert_rows(
+        a.execute("SELECT id, name FROM renamed WHERE name = 'b'")?,
+        vec![vec![Value::Integer(2), Value::String("b".into())]],
+    );
+    a.execute("INSERT INTO renamed VALUES (3, 'c')")?;
+    a.execute("COMMIT")?;
+
+    assert_rows(
+        b.execute("SELECT * FROM target WHERE value = 'a'")?,
+        vec![vec![Value::Integer(1), Value::String("a".into())]],
+    );
+    assert_eq!(
+        b.execute("SELECT * FROM renamed"),
+        Err(Error::Value("Table renamed does not exist".into()))
+    );
+    assert_eq!(b.execute("UPDATE target SET value = 'x' WHERE id = 1"), Err(Error::Serialization));
+    b.execute("ROLLBACK")?;
+
+    assert_rows(
+        b.execute("SELECT * FROM renamed WHERE name = 'c'")?,
+        vec![vec![Value::Integer(3), Value::String("c".into())]],
+    );
+    assert_eq!(
+        b.execute("SELECT * FROM target"),
+        Err(Error::Value("Table target does not exist".into()))
+    );
+
+    Ok(())
+}
+
+#[test]
+/// Cyclic view references can't be created via SQL, since views are checked when created, but
+/// are detected when expanding views.
+fn view_cycle() -> Result<()> {
+    let engine = super::setup(vec![])?;
+    let mut txn = engine.begin(Mode::ReadWrite)?;
+    txn.create_view(View { name: "a".into(), query: "SELECT * FROM b".into() })?;
+    txn.create_view(View { name: "b".into(), query: "SELECT * FROM c".into() })?;
+    txn.create_view(View { name: "c".into(), query: "SELECT * FROM a".into() })?;
+    txn.commit()?;
+
+    let mut session = engine.session()?;
+    assert_eq!(
+        session.execute("SELECT * FROM a"),
+        Err(Error::Value("Cyclic view reference a -> b -> c -> a".into()))
+    );
+    assert_eq!(
+        session.execute("SELECT * FROM c"),
+        Err(Error::Value("Cyclic view reference c -> a -> b -> c".into()))
+    );
+    Ok(())
+}
+
+#[test]
+/// Users can only execute statements that they have the privileges for. Queries require SELECT on
+/// all tables they read, mutations require the mutation's privilege, and schema changes require a
+/// superuser.
+fn privileges() -> Result<()> {
+    let engine = super::setup(vec![
+        "CREATE TABLE movies (id INTEGER PRIMARY KEY, title STRING, genre_id INTEGER)",
+        "CREATE TABLE genres (id INTEGER PRIMARY KEY, name STRING)",
+        "INSERT INTO genres VALUES (1, 'Drama')",
+        "INSERT INTO movies VALUES (1, 'Sicario', 1)",
+        "CREATE VIEW dramas AS SELECT m.id, m.title FROM movies m JOIN genres g ON m.genre_id = g.id",
+        "CREATE USER admin SUPERUSER",
+        "CREATE USER alice",
+        "CREATE USER bob",
+        "GRANT SELECT, INSERT ON movies TO alice",
+    ])?;
+    let denied = |user: &str, privilege: &str, table: &str| {
+        Err(Error::Unauthorized(format!(
+            "User {} does not have {} privilege on table {}",
+            user, privilege, table
+        )))
+    };
+
+    // A user with SELECT can read the table, but a user without it can't.
+    let mut alice = engine.session()?;
+    alice.set_user(Some("alice"))?;
+    assert_eq!(alice.user(), Some("alice"));
+    assert_rows(
+        alice.execute("SELECT title FROM movies")?,
+        vec![vec![Value::String("Sicario".into())]],
+    );
+    let mut bob = engine.session()?;
+    bob.set_user(Some("bob"))?;
+    assert_eq!(bob.execute("SELECT title FROM movies"), denied("bob", "SELECT", "movies"));
+    assert_eq!(bob.execute("SELECT * FROM movies WHERE id = 1"), denied("bob", "SELECT", "movies"));
+    assert_eq!(bob.execute("EXPLAIN SELECT * FROM movies"), denied("bob", "SELECT", "movies"));
+
+    // All tables read by a query are checked, including via joins, subqueries, and views.
+    assert_eq!(
+        alice.execute("SELECT * FROM movies m JOIN genres g ON m.genre_id = g.id"),
+        denied("alice", "SELECT", "genres")
+    );
+    assert_eq!(
+        alice.execute("SELECT * FROM movies WHERE genre_id IN (SELECT id FROM genres)"),
+        denied("alice", "SELECT", "genres")
+    );
+    assert_eq!(alice.execute("SELECT * FROM dramas"), denied("alice", "SELECT", "genres"));
+    assert_eq!(
+        alice.execute("INSERT INTO movies SELECT id + 1, name, id FROM genres"),
+        denied("alice", "SELECT", "genres")
+    );
+
+    // Mutations require their own privilege.
+    assert_eq!(
+        alice.execute("INSERT INTO movies VALUES (2, 'Heat', 1)"),
+        Ok(ResultSet::Create { count: 1 })
+    );
+    assert_eq!(
+        alice.execute("UPDATE movies SET title = 'x' WHERE id = 2"),
+        denied("alice", "UPDATE", "movies")
+    );
+    assert_eq!(
+        alice.execute("DELETE FROM movies WHERE id = 2"),
+        denied("alice", "DELETE", "movies")
+    );
+    assert_eq!(alice.execute("TRUNCATE movies"), denied("alice", "DELETE", "movies"));
+    assert_eq!(
+        alice.execute("INSERT INTO genres VALUES (2, 'Crime')"),
+        denied("alice", "INSERT", "genres")
+    );
+
+    // Schema changes, user management, and vacuuming require a superuser.
+    let superuser = |operation: &str| {
+        Error::Unauthorized(format!("User alice must be a superuser to {}", operation))
+    };
+    for query in &[
+        "CREATE TABLE other (id INTEGER PRIMARY KEY)",
+        "DROP TABLE movies",
+        "ALTER TABLE movies ADD COLUMN year INTEGER",
+        "CREATE VIEW other AS SELECT * FROM movies",
+    ] {
+        assert_eq!(alice.execute(query), Err(superuser("change the schema")));
+    }
+    assert_eq!(alice.execute("GRANT SELECT ON genres TO alice"), Err(superuser("manage users")));
+    assert_eq!(alice.execute("CREATE USER eve SUPERUSER"), Err(superuser("manage users")));
+    assert_eq!(alice.execute("ANALYZE"), Err(superuser("analyze tables")));
+    assert_eq!(alice.execute("VACUUM"), Err(superuser("vacuum")));
+    assert_eq!(alice.set_user(None), Err(superuser("change user")));
+
+    // Grants and revocations take effect for subsequent statements of existing sessions.
+    let mut admin = engine.session()?;
+    admin.set_user(Some("admin"))?;
+    admin.execute("GRANT SELECT ON movies TO bob")?;
+    assert_rows(
+        bob.execute("SELECT id FROM movies ORDER BY id")?,
+        vec![vec![Value::Integer(1)], vec![Value::Integer(2)]],
+    );
+    admin.execute("REVOKE ALL ON movies FROM bob")?;
+    assert_eq!(bob.execute("SELECT * FROM movies"), denied("bob", "SELECT", "movies"));
+
+    // Superusers have all privileges, and can change the session user.
+    admin.execute("CREATE TABLE other (id INTEGER PRIMARY KEY)")?;
+    assert_rows(admin.execute("SELECT COUNT(*) FROM dramas")?, vec![vec![Value::Integer(2)]]);
+    admin.set_user(Some("bob"))?;
+    assert_eq!(admin.execute("SELECT * FROM other"), denied("bob", "SELECT", "other"));
+
+    // Users must exist, except the built-in anonymous user, which has no privileges unless it's
+    // created and granted some.
+    assert_eq!(
+        engine.session()?.set_user(Some("missing")),
+        Err(Error::Value("User missing does not exist".into()))
+    );
+    let mut anon = engine.session()?;
+    anon.set_user(Some(ANONYMOUS_USER))?;
+    assert_eq!(anon.execute("SELECT * FROM movies"), denied("anonymous", "SELECT", "movies"));
+    engine.session()?.execute("CREATE USER anonymous")?;
+    engine.session()?.execute("GRANT SELECT ON movies TO anonymous")?;
+    assert_rows(
+        anon.execute("SELECT id FROM movies ORDER BY id")?,
+        vec![vec![Value::Integer(1)], vec![Value::Integer(2)]],
+    );
+
+    // Switching to the superuser is not checked by the engine, callers must authenticate.
+    anon.set_superuser()?;
+    assert_eq!(anon.user(), None);
+    anon.execute("DROP USER anonymous")?;
+    Ok(())
+}